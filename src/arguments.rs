@@ -1,6 +1,201 @@
+use json::{object, JsonValue};
 use std::path::Path;
 
 const PROGRAM_NAME: &str = "qemu-launcher";
+const SELF_TEST_QEMU_BINARY: &str = "qemu-system-x86_64";
+
+struct OptionSpec {
+    long: &'static str,
+    short: Option<char>,
+    value: Option<&'static str>,
+    description: &'static str,
+}
+
+const HELP: OptionSpec = OptionSpec {
+    long: "help",
+    short: Some('h'),
+    value: None,
+    description: "display this help message.",
+};
+
+const VERBOSE: OptionSpec = OptionSpec {
+    long: "verbose",
+    short: Some('v'),
+    value: None,
+    description: "enable verbose mode. In this mode additional information about program execution flow will \
+        be printed.",
+};
+
+const DEBUG: OptionSpec = OptionSpec {
+    long: "debug",
+    short: Some('d'),
+    value: None,
+    description: "enable debugging mode. In this mode a lot of information about pretty much every step taken \
+        by the application will be printed.",
+};
+
+const ADOPT: OptionSpec = OptionSpec {
+    long: "adopt",
+    short: None,
+    value: None,
+    description: "resume supervision of the already running virtual machine, e.g. after the launcher crashed, \
+        instead of starting a new one. Requires `launcher.qmp_socket` to be configured.",
+};
+
+const MLOCK_SELF: OptionSpec = OptionSpec {
+    long: "mlock-self",
+    short: None,
+    value: None,
+    description: "lock the launcher memory until vCPU pinning is completed, so that the launcher itself does \
+        not page-fault during the latency-critical startup.",
+};
+
+const EVENTS_FD: OptionSpec = OptionSpec {
+    long: "events-fd",
+    short: None,
+    value: Some("<fd>"),
+    description: "write newline-delimited JSON launch events, such as `spawned`, `ready` or `exited`, into the \
+        already open file descriptor, e.g. a pipe set up by an orchestration agent.",
+};
+
+const GLOBAL_OPTIONS: &[&OptionSpec] = &[&HELP, &VERBOSE, &DEBUG];
+
+struct SettingSpec {
+    long: &'static str,
+    variable: &'static str,
+}
+
+// Settings given on the command line take precedence over the matching environment variables
+const SETTINGS: &[SettingSpec] = &[
+    SettingSpec {
+        long: "config-dir",
+        variable: "QEMU_LAUNCHER_CONFIG_DIR",
+    },
+    SettingSpec {
+        long: "log-dir",
+        variable: "QEMU_LAUNCHER_LOG_DIR",
+    },
+    SettingSpec {
+        long: "runtime-dir",
+        variable: "QEMU_LAUNCHER_RUNTIME_DIR",
+    },
+    SettingSpec {
+        long: "state-dir",
+        variable: "QEMU_LAUNCHER_STATE_DIR",
+    },
+];
+
+struct CommandSpec {
+    name: &'static str,
+    operands: &'static str,
+    options: &'static [&'static OptionSpec],
+    passthrough: Option<&'static str>,
+    description: &'static str,
+}
+
+const RUN: &str = "run";
+const CHECK_ISOLATION: &str = "check-isolation";
+const CLEANUP: &str = "cleanup";
+const CONFIG: &str = "config";
+const CONFIG_DUMP: &str = "dump";
+const DOCTOR: &str = "doctor";
+const IMPORT_LIBVIRT: &str = "import-libvirt";
+const INTROSPECT: &str = "introspect";
+const SCHEMA: &str = "schema";
+const SELF_TEST: &str = "self-test";
+const STATUS: &str = "status";
+const THROTTLE: &str = "throttle";
+
+const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: RUN,
+        operands: "<vm-name>",
+        options: &[&ADOPT, &EVENTS_FD, &MLOCK_SELF],
+        passthrough: Some("<qemu-argument>..."),
+        description: "launch the virtual machine and supervise it until qemu exits, arguments after `--` are \
+            passed to qemu as is. This is the default command, so its name can be omitted.",
+    },
+    CommandSpec {
+        name: CHECK_ISOLATION,
+        operands: "<vm-name>",
+        options: &[],
+        passthrough: None,
+        description: "measure, for every pinned host CPU of the running virtual machine, the number of foreign \
+            tasks in its cpuset, interrupts and involuntary context switches of the vCPU threads over 5 seconds.",
+    },
+    CommandSpec {
+        name: CLEANUP,
+        operands: "<vm-name>",
+        options: &[],
+        passthrough: None,
+        description: "remove host resources, such as firewall rules, left behind by a launcher which did not exit \
+            cleanly. The virtual machine must not be running.",
+    },
+    CommandSpec {
+        name: CONFIG,
+        operands: "dump <vm-name>",
+        options: &[],
+        passthrough: None,
+        description: "print the effective virtual machine configuration as YAML, merged with its profile and \
+            `env_file`, with passwords and secret-looking environment variables masked.",
+    },
+    CommandSpec {
+        name: DOCTOR,
+        operands: "",
+        options: &[],
+        passthrough: None,
+        description: "load every virtual machine configuration and report host resources claimed by more than \
+            one machine: pinned host CPUs, MAC addresses, written disk images and socket paths.",
+    },
+    CommandSpec {
+        name: IMPORT_LIBVIRT,
+        operands: "<domain.xml>",
+        options: &[],
+        passthrough: None,
+        description: "convert the libvirt domain XML definition into the launcher configuration and print it to \
+            the standard output.",
+    },
+    CommandSpec {
+        name: INTROSPECT,
+        operands: "",
+        options: &[],
+        passthrough: None,
+        description: "print the supported command line options, environment variables with their defaults and \
+            the configuration schema version as JSON, for provisioning tools.",
+    },
+    CommandSpec {
+        name: SCHEMA,
+        operands: "",
+        options: &[],
+        passthrough: None,
+        description: "print the JSON Schema describing the virtual machine configuration file format.",
+    },
+    CommandSpec {
+        name: STATUS,
+        operands: "<vm-name>",
+        options: &[],
+        passthrough: None,
+        description: "tell whether the virtual machine is running and print the record of its last launch, with \
+            the qemu command line, environment, user, resource limits and vCPU pinning it was started with.",
+    },
+    CommandSpec {
+        name: THROTTLE,
+        operands: "<vm-name> <percent>",
+        options: &[],
+        passthrough: None,
+        description: "throttle the vCPUs of the running virtual machine, by stopping it for the given share of \
+            every 100 milliseconds over QMP, until `0` is requested. Requires `launcher.qmp_socket` or \
+            `launcher.qmp_proxy` to be configured.",
+    },
+    CommandSpec {
+        name: SELF_TEST,
+        operands: "[qemu-binary]",
+        options: &[],
+        passthrough: None,
+        description: "launch a minimal qemu machine without KVM and check the QMP communication with it, \
+            `qemu-system-x86_64` is used unless another qemu binary is given.",
+    },
+];
 
 pub struct UsageArgs {
     program_name: String,
@@ -14,7 +209,7 @@ impl UsageArgs {
 
 pub struct ErrorArgs {
     program_name: String,
-    error: &'static str,
+    error: String,
 }
 
 impl ErrorArgs {
@@ -22,19 +217,27 @@ impl ErrorArgs {
         &self.program_name
     }
 
-    pub fn get_error(&self) -> &'static str {
-        self.error
+    pub fn get_error(&self) -> &str {
+        &self.error
     }
 }
 
 pub struct ValidArgs {
     program_name: String,
+    adopt: bool,
     debug: bool,
+    events_fd: Option<i32>,
     machine_name: String,
+    mlock_self: bool,
+    qemu_arguments: Vec<String>,
     verbose: bool,
 }
 
 impl ValidArgs {
+    pub fn is_adopt_mode(&self) -> bool {
+        self.adopt
+    }
+
     pub fn is_debug_enabled(&self) -> bool {
         self.debug
     }
@@ -43,6 +246,10 @@ impl ValidArgs {
         self.verbose || self.is_debug_enabled()
     }
 
+    pub fn get_events_fd(&self) -> Option<i32> {
+        self.events_fd
+    }
+
     pub fn get_machine_name(&self) -> &str {
         &self.machine_name
     }
@@ -50,11 +257,104 @@ impl ValidArgs {
     pub fn get_program_name(&self) -> &str {
         &self.program_name
     }
+
+    pub fn get_qemu_arguments(&self) -> &Vec<String> {
+        &self.qemu_arguments
+    }
+
+    pub fn should_mlock_self(&self) -> bool {
+        self.mlock_self
+    }
+}
+
+pub struct CheckIsolationArgs {
+    machine_name: String,
+}
+
+impl CheckIsolationArgs {
+    pub fn get_machine_name(&self) -> &str {
+        &self.machine_name
+    }
+}
+
+pub struct CleanupArgs {
+    machine_name: String,
+}
+
+impl CleanupArgs {
+    pub fn get_machine_name(&self) -> &str {
+        &self.machine_name
+    }
+}
+
+pub struct ConfigDumpArgs {
+    machine_name: String,
+}
+
+impl ConfigDumpArgs {
+    pub fn get_machine_name(&self) -> &str {
+        &self.machine_name
+    }
+}
+
+pub struct ImportArgs {
+    domain_path: String,
+}
+
+impl ImportArgs {
+    pub fn get_domain_path(&self) -> &str {
+        &self.domain_path
+    }
+}
+
+pub struct SelfTestArgs {
+    qemu_binary: String,
+}
+
+impl SelfTestArgs {
+    pub fn get_qemu_binary(&self) -> &str {
+        &self.qemu_binary
+    }
+}
+
+pub struct StatusArgs {
+    machine_name: String,
+}
+
+impl StatusArgs {
+    pub fn get_machine_name(&self) -> &str {
+        &self.machine_name
+    }
+}
+
+pub struct ThrottleArgs {
+    machine_name: String,
+    percent: u8,
+}
+
+impl ThrottleArgs {
+    pub fn get_machine_name(&self) -> &str {
+        &self.machine_name
+    }
+
+    pub fn get_percent(&self) -> u8 {
+        self.percent
+    }
 }
 
 pub enum Arguments {
+    CheckIsolation(CheckIsolationArgs),
+    Cleanup(CleanupArgs),
+    ConfigDump(ConfigDumpArgs),
+    Doctor,
     Empty,
+    Import(ImportArgs),
+    Introspect,
     Invalid(ErrorArgs),
+    Schema,
+    SelfTest(SelfTestArgs),
+    Status(StatusArgs),
+    Throttle(ThrottleArgs),
     Valid(ValidArgs),
     Usage(UsageArgs),
 }
@@ -74,57 +374,361 @@ impl Arguments {
         }
         .to_owned();
 
-        let mut verbose = false;
-        let mut debug = false;
-        let mut machine_name = String::new();
-        let mut has_machine_name = false;
+        let invalid = |error: String| {
+            Arguments::Invalid(ErrorArgs {
+                program_name: program_name.clone(),
+                error,
+            })
+        };
+
+        let mut options: Vec<&'static str> = vec![];
+        let mut values: Vec<(&'static str, String)> = vec![];
+        let mut pending_value = None;
+        let mut positional = vec![];
+        let mut passthrough = None;
+
+        for (index, argument) in arguments.iter().enumerate().skip(1) {
+            if let Some(long) = pending_value.take() {
+                values.push((long, argument.to_owned()));
+                continue;
+            }
 
-        for argument in &arguments[1..] {
-            match argument.as_str() {
-                "-v" => {
-                    verbose = true;
+            if argument == "--" {
+                passthrough = Some(arguments[index + 1..].to_vec());
+                break;
+            }
+
+            if argument == "--self-test" {
+                // Kept for compatibility, the self-test used to be requested with a flag
+                positional.insert(0, SELF_TEST.to_owned());
+                continue;
+            }
+
+            if find_setting(argument).is_some() {
+                continue;
+            }
+
+            if let Some(long) = argument.strip_prefix("--") {
+                if SETTINGS.iter().any(|setting| setting.long == long) {
+                    return invalid(format!(
+                        "The `{}` option requires a value, e.g. `{}=<path>`.",
+                        argument, argument
+                    ));
                 }
-                "-d" => {
-                    debug = true;
+
+                let (long, value) = match long.split_once('=') {
+                    Some((long, value)) => (long, Some(value)),
+                    None => (long, None),
+                };
+
+                match (find_option(|option| option.long == long), value) {
+                    (Some(option), Some(value)) if option.value.is_some() => {
+                        options.push(option.long);
+                        values.push((option.long, value.to_owned()));
+                    }
+                    (Some(option), None) if option.value.is_some() => {
+                        options.push(option.long);
+                        pending_value = Some(option.long);
+                    }
+                    (Some(option), None) => options.push(option.long),
+                    (Some(option), Some(_)) => {
+                        return invalid(format!(
+                            "The `--{}` option does not accept a value.",
+                            option.long
+                        ))
+                    }
+                    (None, _) => return invalid(format!("Unknown option `{}`.", argument)),
                 }
-                "-h" => return Arguments::Usage(UsageArgs { program_name }),
-                _ => {
-                    if has_machine_name {
-                        return Arguments::Invalid(ErrorArgs {
-                            program_name,
-                            error: "Too many parameters.",
-                        });
+                continue;
+            }
+
+            match argument.strip_prefix('-') {
+                Some(shorts) if shorts.len() > 0 => {
+                    for short in shorts.chars() {
+                        match find_option(|option| option.short == Some(short)) {
+                            Some(option) => options.push(option.long),
+                            None => return invalid(format!("Unknown option `-{}`.", short)),
+                        }
                     }
+                }
+                _ => positional.push(argument.to_owned()),
+            }
+        }
+
+        if options.contains(&HELP.long) {
+            return Arguments::Usage(UsageArgs { program_name });
+        }
+
+        if let Some(long) = pending_value {
+            return invalid(format!("The `--{}` option requires a value.", long));
+        }
 
-                    machine_name = argument.to_owned();
-                    has_machine_name = true;
+        let events_fd = match values.iter().find(|(long, _)| *long == EVENTS_FD.long) {
+            Some((_, fd)) => match fd.parse::<i32>() {
+                Ok(fd) if fd >= 0 => Some(fd),
+                _ => {
+                    return invalid(format!(
+                        "The `--{}` option expects a file descriptor number.",
+                        EVENTS_FD.long
+                    ))
                 }
+            },
+            None => None,
+        };
+
+        let command = match COMMANDS
+            .iter()
+            .find(|command| positional.first() == Some(&command.name.to_owned()))
+        {
+            Some(command) => {
+                positional.remove(0);
+                command
+            }
+            None => &COMMANDS[0],
+        };
+
+        for option in &options {
+            let supported = GLOBAL_OPTIONS
+                .iter()
+                .chain(command.options.iter())
+                .any(|supported| &supported.long == option);
+
+            if !supported {
+                return invalid(format!(
+                    "The `--{}` option is not supported by the `{}` command.",
+                    option, command.name
+                ));
             }
         }
 
-        if !has_machine_name {
-            return Arguments::Invalid(ErrorArgs {
-                program_name,
-                error: "Missing the guest machine name",
-            });
+        if passthrough.is_some() && command.passthrough.is_none() {
+            return invalid(format!(
+                "The `{}` command does not accept arguments after `--`.",
+                command.name
+            ));
+        }
+
+        let percent = match command.name {
+            THROTTLE if positional.len() > 1 => positional.pop(),
+            _ => None,
+        };
+
+        let action = match command.name {
+            CONFIG if positional.len() > 0 => Some(positional.remove(0)),
+            _ => None,
+        };
+
+        match (command.name, action.as_deref()) {
+            (CONFIG, None) => {
+                return invalid(String::from("Missing the `config` action, e.g. `dump`."))
+            }
+            (CONFIG, Some(action)) if action != CONFIG_DUMP => {
+                return invalid(format!(
+                    "Unknown `config` action `{}`, `{}` expected.",
+                    action, CONFIG_DUMP
+                ))
+            }
+            _ => {}
         }
 
-        if !is_valid_machine_name(&machine_name) {
-            return Arguments::Invalid(ErrorArgs {
+        let operand = match command.name {
+            DOCTOR | INTROSPECT | SCHEMA => None,
+            _ => positional.pop(),
+        };
+
+        if positional.len() > 0 {
+            return invalid(String::from("Too many parameters."));
+        }
+
+        match (command.name, operand) {
+            (DOCTOR, _) => Arguments::Doctor,
+            (INTROSPECT, _) => Arguments::Introspect,
+            (SCHEMA, _) => Arguments::Schema,
+            (SELF_TEST, qemu_binary) => Arguments::SelfTest(SelfTestArgs {
+                qemu_binary: qemu_binary.unwrap_or_else(|| SELF_TEST_QEMU_BINARY.to_owned()),
+            }),
+            (IMPORT_LIBVIRT, Some(domain_path)) => Arguments::Import(ImportArgs { domain_path }),
+            (IMPORT_LIBVIRT, None) => {
+                invalid(String::from("Missing the libvirt domain definition path."))
+            }
+            (_, None) => invalid(String::from("Missing the guest machine name")),
+            (_, Some(machine_name)) if !is_valid_machine_name(&machine_name) => invalid(
+                String::from("The machine name contains invalid characters."),
+            ),
+            (CHECK_ISOLATION, Some(machine_name)) => {
+                Arguments::CheckIsolation(CheckIsolationArgs { machine_name })
+            }
+            (CLEANUP, Some(machine_name)) => Arguments::Cleanup(CleanupArgs { machine_name }),
+            (CONFIG, Some(machine_name)) => Arguments::ConfigDump(ConfigDumpArgs { machine_name }),
+            (STATUS, Some(machine_name)) => Arguments::Status(StatusArgs { machine_name }),
+            (THROTTLE, Some(machine_name)) => match percent.map(|percent| percent.parse::<u8>()) {
+                Some(Ok(percent)) if percent <= 100 => Arguments::Throttle(ThrottleArgs {
+                    machine_name,
+                    percent,
+                }),
+                Some(_) => invalid(String::from(
+                    "The throttle percentage must be a number from 0 to 100.",
+                )),
+                None => invalid(String::from("Missing the throttle percentage.")),
+            },
+            (_, Some(machine_name)) => Arguments::Valid(ValidArgs {
                 program_name,
-                error: "The machine name contains invalid characters.",
-            });
+                adopt: options.contains(&ADOPT.long),
+                verbose: options.contains(&VERBOSE.long),
+                debug: options.contains(&DEBUG.long),
+                events_fd,
+                machine_name,
+                mlock_self: options.contains(&MLOCK_SELF.long),
+                qemu_arguments: passthrough.unwrap_or_default(),
+            }),
         }
+    }
+}
+
+pub fn usage(program_name: &str) -> String {
+    let mut usage = String::new();
 
-        Arguments::Valid(ValidArgs {
+    for (index, command) in COMMANDS.iter().enumerate() {
+        let mut synopsis = vec![String::from("[options]")];
+        if command.name == RUN {
+            synopsis.push(format!("[{}]", command.name));
+        } else {
+            synopsis.push(command.name.to_owned());
+        }
+        if command.operands.len() > 0 {
+            synopsis.push(command.operands.to_owned());
+        }
+        if let Some(passthrough) = command.passthrough {
+            synopsis.push(format!("[-- {}]", passthrough));
+        }
+
+        let prefix = if index == 0 { "Usage:" } else { "      " };
+        usage.push_str(&format!(
+            "{} {} {}\n",
+            prefix,
             program_name,
-            verbose,
-            debug,
-            machine_name,
+            synopsis.join(" ")
+        ));
+    }
+
+    usage.push_str("\nCommands:\n");
+    for command in COMMANDS {
+        usage.push_str(&format!("{}  {}\n", command.name, command.description));
+    }
+
+    usage.push_str("\nOptions:\n");
+    for option in GLOBAL_OPTIONS {
+        usage.push_str(&format_option(option, None));
+    }
+    for setting in SETTINGS {
+        usage.push_str(&format!(
+            "--{}=<path>  override the `{}` environment variable.\n",
+            setting.long, setting.variable
+        ));
+    }
+    for command in COMMANDS {
+        for option in command.options {
+            usage.push_str(&format_option(option, Some(command.name)));
+        }
+    }
+
+    usage
+}
+
+fn format_option(option: &OptionSpec, command: Option<&str>) -> String {
+    let name = match option.short {
+        Some(short) => format!("-{}, --{}", short, option.long),
+        None => format!("--{}", option.long),
+    };
+    let name = match option.value {
+        Some(value) => format!("{} {}", name, value),
+        None => name,
+    };
+
+    match command {
+        Some(command) => format!("{}  ({} only) {}\n", name, command, option.description),
+        None => format!("{}  {}\n", name, option.description),
+    }
+}
+
+pub fn describe() -> JsonValue {
+    let options: Vec<JsonValue> = GLOBAL_OPTIONS
+        .iter()
+        .map(|option| describe_option(option))
+        .collect();
+    let settings: Vec<JsonValue> = SETTINGS
+        .iter()
+        .map(|setting| {
+            object! {
+                "long": setting.long,
+                "value": "<path>",
+                "variable": setting.variable,
+            }
+        })
+        .collect();
+    let commands: Vec<JsonValue> = COMMANDS
+        .iter()
+        .map(|command| {
+            let options: Vec<JsonValue> = command
+                .options
+                .iter()
+                .map(|option| describe_option(option))
+                .collect();
+
+            object! {
+                "name": command.name,
+                "default": command.name == RUN,
+                "operands": command.operands,
+                "passthrough": command.passthrough,
+                "options": options,
+                "description": command.description,
+            }
         })
+        .collect();
+
+    object! {
+        "options": options,
+        "settings": settings,
+        "commands": commands,
+    }
+}
+
+fn describe_option(option: &OptionSpec) -> JsonValue {
+    object! {
+        "long": option.long,
+        "short": option.short.map(|short| short.to_string()),
+        "value": option.value,
+        "description": option.description,
     }
 }
 
+pub fn environment_overrides(arguments: &Vec<String>) -> Vec<(String, String)> {
+    arguments
+        .iter()
+        .skip(1)
+        .take_while(|argument| *argument != "--")
+        .filter_map(|argument| find_setting(argument))
+        .map(|(setting, value)| (setting.variable.to_owned(), value.to_owned()))
+        .collect()
+}
+
+fn find_setting(argument: &str) -> Option<(&'static SettingSpec, &str)> {
+    let (long, value) = argument.strip_prefix("--")?.split_once('=')?;
+
+    SETTINGS
+        .iter()
+        .find(|setting| setting.long == long)
+        .map(|setting| (setting, value))
+}
+
+fn find_option<P: Fn(&OptionSpec) -> bool>(predicate: P) -> Option<&'static OptionSpec> {
+    GLOBAL_OPTIONS
+        .iter()
+        .chain(COMMANDS.iter().flat_map(|command| command.options.iter()))
+        .find(|option| predicate(option))
+        .cloned()
+}
+
 fn is_valid_machine_name(machine_name: &str) -> bool {
     if machine_name.contains("\0") || machine_name.contains("/") {
         return false;
@@ -135,7 +739,7 @@ fn is_valid_machine_name(machine_name: &str) -> bool {
 
 #[cfg(test)]
 mod test {
-    use super::Arguments;
+    use super::{environment_overrides, usage, Arguments};
 
     #[test]
     fn arguments_accepts_machine_name() {
@@ -153,10 +757,54 @@ mod test {
             !arguments.is_debug_enabled(),
             "Debug mode is enabled without `-d` flag"
         );
+        assert!(
+            !arguments.is_adopt_mode(),
+            "Adopt mode is enabled without `--adopt` flag"
+        );
+        assert!(
+            !arguments.should_mlock_self(),
+            "Launcher memory locking is enabled without `--mlock-self` flag"
+        );
         assert_eq!("my-vm", arguments.get_machine_name());
         assert_eq!("launcher", arguments.get_program_name());
     }
 
+    #[test]
+    fn arguments_accepts_mlock_self_flag() {
+        let arguments = match Arguments::new(&vec![
+            String::from("launcher"),
+            String::from("--mlock-self"),
+            String::from("my-vm"),
+        ]) {
+            Arguments::Valid(v) => v,
+            _ => panic!("Expected arguments to be valid"),
+        };
+
+        assert!(
+            arguments.should_mlock_self(),
+            "Launcher memory locking is not enabled with `--mlock-self` flag"
+        );
+        assert_eq!("my-vm", arguments.get_machine_name());
+    }
+
+    #[test]
+    fn arguments_accepts_adopt_flag() {
+        let arguments = match Arguments::new(&vec![
+            String::from("launcher"),
+            String::from("--adopt"),
+            String::from("my-vm"),
+        ]) {
+            Arguments::Valid(v) => v,
+            _ => panic!("Expected arguments to be valid"),
+        };
+
+        assert!(
+            arguments.is_adopt_mode(),
+            "Adopt mode is not enabled with `--adopt` flag"
+        );
+        assert_eq!("my-vm", arguments.get_machine_name());
+    }
+
     #[test]
     fn arguments_accepts_verbose_flag() {
         let arguments = match Arguments::new(&vec![
@@ -294,4 +942,414 @@ mod test {
         assert_eq!(super::PROGRAM_NAME, arguments.get_program_name());
         assert_eq!("myvm", arguments.get_machine_name());
     }
+
+    #[test]
+    fn arguments_accepts_import_libvirt_command() {
+        let arguments = match Arguments::new(&vec![
+            String::from("launcher"),
+            String::from("import-libvirt"),
+            String::from("/tmp/domain.xml"),
+        ]) {
+            Arguments::Import(i) => i,
+            _ => panic!("Expected arguments to be an import instance"),
+        };
+
+        assert_eq!("/tmp/domain.xml", arguments.get_domain_path());
+    }
+
+    #[test]
+    fn arguments_reports_missing_libvirt_domain_path() {
+        let result = match Arguments::new(&vec![
+            String::from("launcher"),
+            String::from("import-libvirt"),
+        ]) {
+            Arguments::Invalid(e) => e,
+            _ => panic!("Expected arguments to be invalid"),
+        };
+
+        assert_eq!(
+            "Missing the libvirt domain definition path.",
+            result.get_error()
+        );
+    }
+
+    #[test]
+    fn arguments_accepts_check_isolation_command() {
+        let arguments = match Arguments::new(&vec![
+            String::from("launcher"),
+            String::from("check-isolation"),
+            String::from("my-vm"),
+        ]) {
+            Arguments::CheckIsolation(c) => c,
+            _ => panic!("Expected arguments to be a check isolation instance"),
+        };
+
+        assert_eq!("my-vm", arguments.get_machine_name());
+    }
+
+    #[test]
+    fn arguments_accepts_cleanup_command() {
+        let arguments = match Arguments::new(&vec![
+            String::from("launcher"),
+            String::from("cleanup"),
+            String::from("my-vm"),
+        ]) {
+            Arguments::Cleanup(c) => c,
+            _ => panic!("Expected arguments to be a cleanup instance"),
+        };
+
+        assert_eq!("my-vm", arguments.get_machine_name());
+    }
+
+    #[test]
+    fn arguments_accepts_status_command() {
+        let arguments = match Arguments::new(&vec![
+            String::from("launcher"),
+            String::from("status"),
+            String::from("my-vm"),
+        ]) {
+            Arguments::Status(s) => s,
+            _ => panic!("Expected arguments to be a status instance"),
+        };
+
+        assert_eq!("my-vm", arguments.get_machine_name());
+    }
+
+    #[test]
+    fn arguments_accepts_throttle_command() {
+        let arguments = match Arguments::new(&vec![
+            String::from("launcher"),
+            String::from("throttle"),
+            String::from("my-vm"),
+            String::from("40"),
+        ]) {
+            Arguments::Throttle(t) => t,
+            _ => panic!("Expected arguments to be a throttle instance"),
+        };
+
+        assert_eq!("my-vm", arguments.get_machine_name());
+        assert_eq!(40, arguments.get_percent());
+
+        for (arguments, error) in &[
+            (
+                vec!["launcher", "throttle", "my-vm"],
+                "Missing the throttle percentage.",
+            ),
+            (
+                vec!["launcher", "throttle", "my-vm", "101"],
+                "The throttle percentage must be a number from 0 to 100.",
+            ),
+        ] {
+            let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+
+            match Arguments::new(&arguments) {
+                Arguments::Invalid(e) => assert_eq!(*error, e.get_error()),
+                _ => panic!("Expected arguments to be invalid"),
+            };
+        }
+    }
+
+    #[test]
+    fn arguments_accepts_schema_command() {
+        match Arguments::new(&vec![String::from("launcher"), String::from("schema")]) {
+            Arguments::Schema => {}
+            _ => panic!("Expected arguments to be a schema instance"),
+        }
+    }
+
+    #[test]
+    fn arguments_accepts_config_dump_command() {
+        let arguments = match Arguments::new(&vec![
+            String::from("launcher"),
+            String::from("config"),
+            String::from("dump"),
+            String::from("my-vm"),
+        ]) {
+            Arguments::ConfigDump(c) => c,
+            _ => panic!("Expected arguments to be a config dump instance"),
+        };
+
+        assert_eq!("my-vm", arguments.get_machine_name());
+
+        for (arguments, error) in &[
+            (
+                vec!["launcher", "config"],
+                "Missing the `config` action, e.g. `dump`.",
+            ),
+            (
+                vec!["launcher", "config", "edit", "my-vm"],
+                "Unknown `config` action `edit`, `dump` expected.",
+            ),
+            (
+                vec!["launcher", "config", "dump"],
+                "Missing the guest machine name",
+            ),
+        ] {
+            let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+
+            match Arguments::new(&arguments) {
+                Arguments::Invalid(e) => assert_eq!(*error, e.get_error()),
+                _ => panic!("Expected arguments to be invalid"),
+            };
+        }
+    }
+
+    #[test]
+    fn arguments_accepts_doctor_command() {
+        match Arguments::new(&vec![String::from("launcher"), String::from("doctor")]) {
+            Arguments::Doctor => {}
+            _ => panic!("Expected arguments to be a doctor instance"),
+        }
+    }
+
+    #[test]
+    fn arguments_accepts_introspect_command() {
+        match Arguments::new(&vec![String::from("launcher"), String::from("introspect")]) {
+            Arguments::Introspect => {}
+            _ => panic!("Expected arguments to be an introspect instance"),
+        }
+    }
+
+    #[test]
+    fn describe_lists_commands_with_their_options() {
+        let description = super::describe();
+
+        assert_eq!("help", description["options"][0]["long"]);
+        assert_eq!("h", description["options"][0]["short"]);
+        assert_eq!(
+            "QEMU_LAUNCHER_CONFIG_DIR",
+            description["settings"][0]["variable"]
+        );
+        assert_eq!("run", description["commands"][0]["name"]);
+        assert_eq!(true, description["commands"][0]["default"]);
+        assert_eq!(
+            "events-fd",
+            description["commands"][0]["options"][1]["long"]
+        );
+        assert_eq!("<fd>", description["commands"][0]["options"][1]["value"]);
+        assert!(description["commands"][0]["options"][1]["short"].is_null());
+    }
+
+    #[test]
+    fn arguments_accepts_self_test_flag_with_optional_qemu_binary() {
+        let arguments =
+            match Arguments::new(&vec![String::from("launcher"), String::from("--self-test")]) {
+                Arguments::SelfTest(t) => t,
+                _ => panic!("Expected arguments to be a self-test instance"),
+            };
+
+        assert_eq!("qemu-system-x86_64", arguments.get_qemu_binary());
+
+        let arguments = match Arguments::new(&vec![
+            String::from("launcher"),
+            String::from("--self-test"),
+            String::from("/usr/bin/qemu-kvm"),
+        ]) {
+            Arguments::SelfTest(t) => t,
+            _ => panic!("Expected arguments to be a self-test instance"),
+        };
+
+        assert_eq!("/usr/bin/qemu-kvm", arguments.get_qemu_binary());
+    }
+
+    #[test]
+    fn arguments_accepts_long_and_combined_short_options() {
+        let arguments = match Arguments::new(&vec![
+            String::from("launcher"),
+            String::from("--verbose"),
+            String::from("-vd"),
+            String::from("my-vm"),
+        ]) {
+            Arguments::Valid(v) => v,
+            _ => panic!("Expected arguments to be valid"),
+        };
+
+        assert!(arguments.is_verbose_mode());
+        assert!(arguments.is_debug_enabled());
+    }
+
+    #[test]
+    fn arguments_accepts_run_command_with_qemu_arguments_after_separator() {
+        let arguments = match Arguments::new(&vec![
+            String::from("launcher"),
+            String::from("run"),
+            String::from("--adopt"),
+            String::from("my-vm"),
+            String::from("--"),
+            String::from("-S"),
+            String::from("--help"),
+        ]) {
+            Arguments::Valid(v) => v,
+            _ => panic!("Expected arguments to be valid"),
+        };
+
+        assert!(arguments.is_adopt_mode());
+        assert_eq!("my-vm", arguments.get_machine_name());
+        assert_eq!(
+            &vec![String::from("-S"), String::from("--help")],
+            arguments.get_qemu_arguments()
+        );
+    }
+
+    #[test]
+    fn arguments_reports_unknown_option() {
+        let result = match Arguments::new(&vec![
+            String::from("launcher"),
+            String::from("--foo"),
+            String::from("my-vm"),
+        ]) {
+            Arguments::Invalid(e) => e,
+            _ => panic!("Expected arguments to be invalid"),
+        };
+
+        assert_eq!("Unknown option `--foo`.", result.get_error());
+    }
+
+    #[test]
+    fn arguments_reports_option_not_supported_by_command() {
+        let result = match Arguments::new(&vec![
+            String::from("launcher"),
+            String::from("schema"),
+            String::from("--adopt"),
+        ]) {
+            Arguments::Invalid(e) => e,
+            _ => panic!("Expected arguments to be invalid"),
+        };
+
+        assert_eq!(
+            "The `--adopt` option is not supported by the `schema` command.",
+            result.get_error()
+        );
+    }
+
+    #[test]
+    fn arguments_reports_separator_for_command_without_passthrough() {
+        let result = match Arguments::new(&vec![
+            String::from("launcher"),
+            String::from("check-isolation"),
+            String::from("my-vm"),
+            String::from("--"),
+        ]) {
+            Arguments::Invalid(e) => e,
+            _ => panic!("Expected arguments to be invalid"),
+        };
+
+        assert_eq!(
+            "The `check-isolation` command does not accept arguments after `--`.",
+            result.get_error()
+        );
+    }
+
+    #[test]
+    fn arguments_accepts_settings_overriding_environment_variables() {
+        let arguments = vec![
+            String::from("launcher"),
+            String::from("--log-dir=/tmp/logs"),
+            String::from("my-vm"),
+            String::from("--state-dir=/tmp/state"),
+            String::from("--"),
+            String::from("--runtime-dir=/tmp/run"),
+        ];
+
+        match Arguments::new(&arguments) {
+            Arguments::Valid(v) => {
+                assert_eq!("my-vm", v.get_machine_name());
+                assert_eq!(
+                    &vec![String::from("--runtime-dir=/tmp/run")],
+                    v.get_qemu_arguments()
+                );
+            }
+            _ => panic!("Expected arguments to be valid"),
+        };
+
+        assert_eq!(
+            vec![
+                ("QEMU_LAUNCHER_LOG_DIR".to_owned(), "/tmp/logs".to_owned()),
+                (
+                    "QEMU_LAUNCHER_STATE_DIR".to_owned(),
+                    "/tmp/state".to_owned()
+                ),
+            ],
+            environment_overrides(&arguments)
+        );
+    }
+
+    #[test]
+    fn arguments_accepts_events_fd_option() {
+        for arguments in &[
+            vec!["launcher", "--events-fd", "3", "my-vm"],
+            vec!["launcher", "my-vm", "--events-fd=3"],
+        ] {
+            let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+
+            match Arguments::new(&arguments) {
+                Arguments::Valid(v) => {
+                    assert_eq!(Some(3), v.get_events_fd());
+                    assert_eq!("my-vm", v.get_machine_name());
+                }
+                _ => panic!("Expected arguments to be valid"),
+            };
+        }
+    }
+
+    #[test]
+    fn arguments_reports_invalid_events_fd() {
+        for (arguments, error) in &[
+            (
+                vec!["launcher", "my-vm", "--events-fd"],
+                "The `--events-fd` option requires a value.",
+            ),
+            (
+                vec!["launcher", "my-vm", "--events-fd=stdout"],
+                "The `--events-fd` option expects a file descriptor number.",
+            ),
+            (
+                vec!["launcher", "my-vm", "--adopt=yes"],
+                "The `--adopt` option does not accept a value.",
+            ),
+        ] {
+            let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+
+            match Arguments::new(&arguments) {
+                Arguments::Invalid(e) => assert_eq!(*error, e.get_error()),
+                _ => panic!("Expected arguments to be invalid"),
+            };
+        }
+    }
+
+    #[test]
+    fn arguments_reports_setting_without_value() {
+        let result = match Arguments::new(&vec![
+            String::from("launcher"),
+            String::from("--config-dir"),
+            String::from("my-vm"),
+        ]) {
+            Arguments::Invalid(e) => e,
+            _ => panic!("Expected arguments to be invalid"),
+        };
+
+        assert_eq!(
+            "The `--config-dir` option requires a value, e.g. `--config-dir=<path>`.",
+            result.get_error()
+        );
+    }
+
+    #[test]
+    fn usage_lists_all_commands_and_options() {
+        let usage = usage("launcher");
+
+        assert!(usage.starts_with(
+            "Usage: launcher [options] [run] <vm-name> [-- <qemu-argument>...]\n       launcher [options] check-isolation <vm-name>\n"
+        ));
+        for text in &[
+            "\nself-test  ",
+            "\nschema  ",
+            "\n-h, --help  ",
+            "\n--adopt  (run only) ",
+            "\n--events-fd <fd>  (run only) ",
+            "\n--state-dir=<path>  ",
+        ] {
+            assert!(usage.contains(text), "Usage does not contain `{}`", text);
+        }
+    }
 }
@@ -0,0 +1,134 @@
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+// The source of time for waits and timeouts, so tests can run them against a fake clock
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    fn sleep(&self, duration: Duration);
+}
+
+pub struct SystemClock {}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock {})
+}
+
+// Checks the condition every interval until it holds, or gives up once the deadline has passed
+pub fn wait_until<F: FnMut() -> bool>(
+    clock: &dyn Clock,
+    deadline: Instant,
+    interval: Duration,
+    mut condition: F,
+) -> bool {
+    loop {
+        if condition() {
+            return true;
+        }
+
+        if clock.now() >= deadline {
+            return false;
+        }
+
+        clock.sleep(interval);
+    }
+}
+
+#[cfg(test)]
+pub mod fake {
+    use super::Clock;
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    // Only moves forward when slept on, which returns immediately and is recorded for assertions
+    pub struct FakeClock {
+        now: Mutex<Instant>,
+        sleeps: Mutex<Vec<Duration>>,
+    }
+
+    impl FakeClock {
+        pub fn new() -> Arc<Self> {
+            Arc::new(FakeClock {
+                now: Mutex::new(Instant::now()),
+                sleeps: Mutex::new(vec![]),
+            })
+        }
+
+        pub fn get_sleeps(&self) -> Vec<Duration> {
+            self.sleeps.lock().unwrap().clone()
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Instant {
+            *self.now.lock().unwrap()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+            self.sleeps.lock().unwrap().push(duration);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fake::FakeClock, wait_until, Clock};
+    use std::time::Duration;
+
+    #[test]
+    fn fake_clock_advances_only_when_slept_on() {
+        let clock = FakeClock::new();
+        let started = clock.now();
+
+        assert_eq!(started, clock.now());
+
+        clock.sleep(Duration::from_secs(5));
+        clock.sleep(Duration::from_millis(100));
+
+        assert_eq!(Duration::from_millis(5100), clock.now() - started);
+        assert_eq!(
+            vec![Duration::from_secs(5), Duration::from_millis(100)],
+            clock.get_sleeps()
+        );
+    }
+
+    #[test]
+    fn wait_until_polls_the_condition_until_it_holds_or_the_deadline_passes() {
+        let clock = FakeClock::new();
+        let mut polls = 0;
+
+        let deadline = clock.now() + Duration::from_secs(5);
+        assert_eq!(
+            true,
+            wait_until(&*clock, deadline, Duration::from_secs(1), || {
+                polls += 1;
+                polls == 3
+            })
+        );
+        assert_eq!(vec![Duration::from_secs(1); 2], clock.get_sleeps());
+
+        let clock = FakeClock::new();
+        let deadline = clock.now() + Duration::from_millis(300);
+        assert_eq!(
+            false,
+            wait_until(&*clock, deadline, Duration::from_millis(100), || false)
+        );
+        assert_eq!(vec![Duration::from_millis(100); 3], clock.get_sleeps());
+    }
+}
@@ -1,1657 +1,10085 @@
+use json::JsonValue;
 #[cfg(not(test))]
 use std::fs::read_to_string;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
     convert::TryFrom,
     io::{Error, ErrorKind, Result},
+    net::Ipv4Addr,
     path::Path,
+    time::Duration,
 };
 #[cfg(test)]
 use test::std::fs::read_to_string;
 use yaml_rust::{
     yaml::{Array, Hash},
-    Yaml, YamlLoader,
+    Yaml, YamlEmitter, YamlLoader,
 };
 
-enum Argument {
-    Flag(String),
-    Parameter(String, String),
+const LAUNCHER_QMP_CHARDEV: &str = "launcher-qmp";
+const LAUNCHER_SERIAL_CHARDEV: &str = "launcher-serial";
+const LAUNCHER_IOTHREAD_PREFIX: &str = "launcher-iothread";
+const DEFAULT_PROFILE_DIRECTORY: &str = "/usr/local/etc/qemu-launcher/profiles";
+const HOSTNAME_PATH: &str = "/proc/sys/kernel/hostname";
+const HOSTNAME_PLACEHOLDER: &str = "$(hostname)";
+const MASKED_SECRET: &str = "********";
+// Halt polling parameters of the `kvm` module, which can be changed while the module is loaded
+const KVM_PARAMETERS: [&str; 4] = [
+    "halt_poll_ns",
+    "halt_poll_ns_grow",
+    "halt_poll_ns_grow_start",
+    "halt_poll_ns_shrink",
+];
+// Keys which can be applied to the running machine by re-pinning and re-scheduling vCPU threads
+const LIVE_KEYS: &[&str] = &[
+    "deadline",
+    "priority",
+    "scheduler",
+    "thermal_throttle",
+    "uclamp",
+    "vcpu_pinning",
+    "vcpu_pinning_by_index",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Deadline {
+    deadline_us: u64,
+    period_us: u64,
+    runtime_us: u64,
 }
 
-pub struct Config {
-    clear_env: bool,
-    command_line: Vec<Argument>,
-    cpu_pinning: Vec<(usize, usize, usize, usize)>,
-    env: HashMap<String, String>,
-    group: Option<u16>,
-    priority: Option<u8>,
-    qemu_binary: String,
-    rlimit_memlock: bool,
-    scheduler: Option<String>,
-    user: Option<u16>,
-}
+impl Deadline {
+    pub fn get_deadline_us(&self) -> u64 {
+        self.deadline_us
+    }
 
-impl Config {
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        Self::new(read_to_string(path)?)
+    pub fn get_period_us(&self) -> u64 {
+        self.period_us
     }
 
-    pub fn new<Y: AsRef<str>>(yaml: Y) -> Result<Self> {
-        let conf = match YamlLoader::load_from_str(yaml.as_ref()) {
-            Ok(mut data) => match data.pop() {
-                Some(conf) => conf,
-                None => {
-                    return Err(Error::new(
-                        ErrorKind::InvalidData,
-                        "Supplied configuration is empty.",
-                    ))
-                }
-            },
-            Err(e) => return Err(Error::new(ErrorKind::InvalidData, format!("{}", e))),
-        };
+    pub fn get_runtime_us(&self) -> u64 {
+        self.runtime_us
+    }
+}
 
-        Ok(Config {
-            clear_env: parse_clear_env(&conf)?,
-            command_line: parse_command_line(&conf)?,
-            cpu_pinning: parse_cpu_pinning(&conf)?,
-            env: parse_env(&conf)?,
-            group: parse_group(&conf)?,
-            priority: parse_priority(&conf)?,
-            qemu_binary: parse_qemu_binary(&conf)?,
-            rlimit_memlock: parse_rlimit_memlock(&conf)?,
-            scheduler: parse_scheduler(&conf)?,
-            user: parse_user(&conf)?,
-        })
+// A vCPU addressed by its place in the guest topology, dies and clusters are only known when `-smp`
+// splits the guest into more than one of them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VcpuPin {
+    cluster: Option<usize>,
+    core: usize,
+    die: Option<usize>,
+    host_cpu: usize,
+    socket: usize,
+    thread: usize,
+}
+
+impl VcpuPin {
+    pub fn get_socket(&self) -> usize {
+        self.socket
     }
 
-    pub fn get_user(&self) -> Option<u16> {
-        self.user
+    pub fn get_die(&self) -> Option<usize> {
+        self.die
     }
 
-    pub fn get_group(&self) -> Option<u16> {
-        self.group
+    pub fn get_cluster(&self) -> Option<usize> {
+        self.cluster
     }
 
-    pub fn get_cpu_pinning(&self) -> &Vec<(usize, usize, usize, usize)> {
-        &self.cpu_pinning
+    pub fn get_core(&self) -> usize {
+        self.core
     }
 
-    pub fn get_command_line_options(&self) -> Vec<String> {
-        let mut result = vec![];
+    pub fn get_thread(&self) -> usize {
+        self.thread
+    }
 
-        for option in &self.command_line {
-            match option {
-                Argument::Flag(flag) => result.push(format!("-{}", flag)),
-                Argument::Parameter(name, value) => {
-                    result.push(format!("-{}", name));
-                    result.push(value.clone());
-                }
-            }
-        }
+    pub fn get_host_cpu(&self) -> usize {
+        self.host_cpu
+    }
 
-        result
+    // The `launcher.vcpu_pinning` keys leading to the host CPU, e.g. `0.1.0`
+    pub fn get_path(&self) -> String {
+        self.levels()
+            .iter()
+            .map(|(_, id)| id.to_string())
+            .collect::<Vec<String>>()
+            .join(".")
     }
 
-    pub fn get_qemu_binary_path(&self) -> &String {
-        &self.qemu_binary
+    pub fn describe(&self) -> String {
+        self.levels()
+            .iter()
+            .map(|(level, id)| format!("{} {}", level, id))
+            .collect::<Vec<String>>()
+            .join(", ")
     }
 
-    pub fn has_cpu_pinning(&self) -> bool {
-        self.cpu_pinning.len() > 0
+    fn levels(&self) -> Vec<(&'static str, usize)> {
+        let mut levels = vec![("socket", self.socket)];
+        levels.extend(self.die.map(|die| ("die", die)));
+        levels.extend(self.cluster.map(|cluster| ("cluster", cluster)));
+        levels.push(("core", self.core));
+        levels.push(("thread", self.thread));
+
+        levels
     }
+}
 
-    pub fn should_clear_env(&self) -> bool {
-        self.clear_env
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UtilClamp {
+    max: Option<u8>,
+    min: Option<u8>,
+}
+
+impl UtilClamp {
+    pub fn get_max(&self) -> Option<u8> {
+        self.max
     }
 
-    pub fn get_env_vars(&self) -> &HashMap<String, String> {
-        &self.env
+    pub fn get_min(&self) -> Option<u8> {
+        self.min
     }
+}
 
-    pub fn has_scheduling(&self) -> bool {
-        if let None = self.scheduler {
-            return false;
-        }
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EnvSource {
+    Launcher,
+    Audio,
+    Display,
+    EnvFile,
+    Env,
+}
 
-        if let None = self.priority {
-            return false;
+impl EnvSource {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            EnvSource::Launcher => "the launcher environment",
+            EnvSource::Audio => "`launcher.audio`",
+            EnvSource::Display => "`launcher.display.env`",
+            EnvSource::EnvFile => "`launcher.env_file`",
+            EnvSource::Env => "`launcher.env`",
         }
-
-        true
     }
+}
 
-    pub fn get_priority(&self) -> Option<u8> {
-        self.priority
+#[derive(Debug, PartialEq)]
+pub struct EnvVar {
+    name: String,
+    value: String,
+    source: EnvSource,
+    overrides: Vec<EnvSource>,
+}
+
+impl EnvVar {
+    pub fn get_name(&self) -> &str {
+        &self.name
     }
 
-    pub fn get_scheduler(&self) -> &Option<String> {
-        &self.scheduler
+    pub fn get_value(&self) -> &str {
+        &self.value
     }
 
-    pub fn rlimit_memlock(&self) -> bool {
-        self.rlimit_memlock
+    pub fn get_source(&self) -> EnvSource {
+        self.source
     }
-}
 
-fn parse_bool_value(yaml: &Yaml, key: &str) -> Result<bool> {
-    match yaml[key] {
-        Yaml::Boolean(b) => Ok(b),
-        Yaml::BadValue => Ok(false),
-        _ => Err(Error::new(
-            ErrorKind::InvalidData,
-            format!(
-                "Invalid value for `launcher.{}` value: a boolean is expected.",
-                key
-            ),
-        )),
+    pub fn get_overrides(&self) -> &[EnvSource] {
+        &self.overrides
     }
 }
 
-fn parse_clear_env(config: &Yaml) -> Result<bool> {
-    parse_bool_value(&config["launcher"], "clear_env")
+pub struct Firewall {
+    allow_from: Vec<String>,
+    forwards: Vec<PortForward>,
+    guest_address: String,
 }
 
-fn parse_qemu_binary(config: &Yaml) -> Result<String> {
-    match config["launcher"]["binary"].as_str() {
-        Some(bin) => Ok(bin.to_string()),
-        None => Err(Error::new(
-            ErrorKind::InvalidData,
-            "qemu binary path is not specified, missing or \
-            the `launcher.binary` key has an invalid type.",
-        )),
+impl Firewall {
+    pub fn get_allow_from(&self) -> &[String] {
+        &self.allow_from
     }
-}
 
-fn parse_env(config: &Yaml) -> Result<HashMap<String, String>> {
-    match &config["launcher"]["env"] {
-        Yaml::Hash(h) => parse_env_hash(h),
-        Yaml::BadValue => Ok(HashMap::new()),
-        _ => Err(Error::new(
-            ErrorKind::InvalidData,
-            "Invalid value for the `launcher.env` key: a hash expected.",
-        )),
+    pub fn get_forwards(&self) -> &[PortForward] {
+        &self.forwards
+    }
+
+    pub fn get_guest_address(&self) -> &str {
+        &self.guest_address
     }
 }
 
-fn parse_env_hash(env: &Hash) -> Result<HashMap<String, String>> {
-    let mut env_vars = HashMap::new();
+pub struct PortForward {
+    guest_port: u16,
+    host_port: u16,
+    protocol: String,
+}
 
-    for (name, value) in env {
-        let name = name
-            .as_str()
-            .ok_or_else(|| {
-                Error::new(
-                    ErrorKind::InvalidData,
-                    "Environment variable name must be a string.",
-                )
-            })?
-            .to_string();
+impl PortForward {
+    pub fn get_guest_port(&self) -> u16 {
+        self.guest_port
+    }
 
-        let value = match value {
-            Yaml::Boolean(b) => b.to_string(),
-            Yaml::Integer(i) => i.to_string(),
-            Yaml::Real(r) => r.to_string(),
-            Yaml::String(s) => s.to_string(),
-            _ => {
-                return Err(Error::new(
-                    ErrorKind::InvalidData,
-                    format!("Invalid value for the `{}` environment variable.", name,),
-                ))
-            }
-        };
+    pub fn get_host_port(&self) -> u16 {
+        self.host_port
+    }
 
-        env_vars.insert(name, value);
+    pub fn get_protocol(&self) -> &str {
+        &self.protocol
     }
+}
 
-    Ok(env_vars)
+// Boot files served to the machine, by qemu itself over the `user` networking, or by the launcher on a
+// host address reachable over a tap or a bridge
+pub struct Netboot {
+    bootfile: Option<String>,
+    directory: String,
+    http_port: Option<u16>,
+    listen: Option<Ipv4Addr>,
 }
 
-fn parse_u16_value(config: &Yaml, key: &str) -> Result<Option<u16>> {
-    match config[key] {
-        Yaml::Integer(i) => match u16::try_from(i) {
-            Ok(i) => Ok(Some(i)),
-            Err(_) => Err(Error::new(
-                ErrorKind::InvalidData,
-                format!(
-                    "Invalid value for `launcher.{}` option: given value is \
-                    out of bounds, expected an unsigned 16-bit integer.",
-                    key
-                ),
-            )),
-        },
-        Yaml::BadValue => Ok(None),
-        _ => Err(Error::new(
-            ErrorKind::InvalidData,
-            format!(
-                "Invalid value for `launcher.{}` option: unsigned 16-bit integer expected.",
-                key
-            ),
-        )),
+impl Netboot {
+    pub fn get_directory(&self) -> &str {
+        &self.directory
     }
-}
 
-fn parse_user(config: &Yaml) -> Result<Option<u16>> {
-    parse_u16_value(&config["launcher"], "user")
+    pub fn get_http_port(&self) -> Option<u16> {
+        self.http_port
+    }
+
+    pub fn get_listen(&self) -> Option<Ipv4Addr> {
+        self.listen
+    }
 }
 
-fn parse_group(config: &Yaml) -> Result<Option<u16>> {
-    parse_u16_value(&config["launcher"], "group")
+pub struct CacheAllocation {
+    l3: BTreeMap<usize, String>,
+    mb: BTreeMap<usize, u8>,
 }
 
-fn parse_priority(config: &Yaml) -> Result<Option<u8>> {
-    match config["launcher"]["priority"] {
-        Yaml::Integer(i) => match u8::try_from(i) {
-            Ok(i) => Ok(Some(i)),
-            Err(_) => Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("Wrong value for `launcher.priority`: value out of bounds."),
-            )),
-        },
-        Yaml::BadValue => Ok(None),
-        _ => Err(Error::new(
-            ErrorKind::InvalidData,
-            format!("Failed to parse `launcher.priority`: an integer expected."),
-        )),
+impl CacheAllocation {
+    // Lines of the resctrl `schemata` file, cache domains which are not listed keep their defaults
+    pub fn get_schemata(&self) -> Vec<String> {
+        let mut schemata = vec![];
+
+        if self.l3.len() > 0 {
+            let domains: Vec<String> = self
+                .l3
+                .iter()
+                .map(|(id, mask)| format!("{}={}", id, mask))
+                .collect();
+            schemata.push(format!("L3:{}", domains.join(";")));
+        }
+
+        if self.mb.len() > 0 {
+            let domains: Vec<String> = self
+                .mb
+                .iter()
+                .map(|(id, percent)| format!("{}={}", id, percent))
+                .collect();
+            schemata.push(format!("MB:{}", domains.join(";")));
+        }
+
+        schemata
     }
 }
 
-fn parse_scheduler(config: &Yaml) -> Result<Option<String>> {
-    match &config["launcher"]["scheduler"] {
-        Yaml::String(s) => match s.as_str() {
-            "batch" | "deadline" | "fifo" | "idle" | "other" | "rr" => Ok(Some(s.to_string())),
-            _ => Err(Error::new(
-                ErrorKind::InvalidData,
-                format!(
-                    "Failed to parse `launcher.scheduler`: Expected one of \
-                    `batch`, `deadline`, `fifo`, `idle`, `other` or `rr`."
-                ),
-            )),
-        },
-        Yaml::BadValue => Ok(None),
-        _ => Err(Error::new(
-            ErrorKind::InvalidData,
-            format!("Failed to parse `launcher.scheduler`: string expected."),
-        )),
-    }
+pub struct SriovInterface {
+    interface: String,
+    vfs: Vec<VirtualFunction>,
 }
 
-fn parse_rlimit_memlock(config: &Yaml) -> Result<bool> {
-    match &config["launcher"]["rlimit_memlock"] {
-        Yaml::Boolean(b) => Ok(*b),
-        Yaml::BadValue => Ok(false),
-        _ => Err(Error::new(
-            ErrorKind::InvalidData,
-            format!("Failed to parse `launcher.rlimit_memlock`: boolean expected."),
-        )),
+impl SriovInterface {
+    pub fn get_interface(&self) -> &str {
+        &self.interface
     }
-}
 
-fn as_u64(id: &Yaml) -> Option<usize> {
-    match id.as_i64() {
-        Some(i) => match usize::try_from(i) {
-            Ok(i) => Some(i),
-            Err(_) => None,
-        },
-        None => None,
+    pub fn get_virtual_functions(&self) -> &[VirtualFunction] {
+        &self.vfs
     }
 }
 
-fn parse_cpu_pinning_sockets(sockets: &Hash) -> Result<Vec<(usize, usize, usize, usize)>> {
-    let mut cpu_pinning = vec![];
+pub struct VirtualFunction {
+    mac: Option<String>,
+    vlan: Option<u16>,
+}
 
-    for (socket, cores) in sockets {
-        let socket_id = match as_u64(socket) {
-            Some(id) => Ok(id),
-            None => Err(Error::new(
-                ErrorKind::InvalidData,
-                format!(
-                    "Failed to parse `launcher.vcpu_pinning`: \
-                        the socket ID must be an integer greater or equal to zero."
-                ),
-            )),
-        }?;
+impl VirtualFunction {
+    pub fn get_mac(&self) -> Option<&str> {
+        self.mac.as_deref()
+    }
 
-        match cores {
-            Yaml::Hash(cores) => {
-                cpu_pinning.append(&mut parse_cpu_pinning_cores(socket_id, cores)?)
-            }
-            _ => {
-                return Err(Error::new(
-                    ErrorKind::InvalidData,
-                    format!(
-                        "Failed to parse `launcher.vcpu_pinning.{}`: a hash expected.",
-                        socket_id
-                    ),
-                ))
-            }
-        }
+    pub fn get_vlan(&self) -> Option<u16> {
+        self.vlan
     }
+}
 
-    Ok(cpu_pinning)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathKind {
+    Directory,
+    File,
 }
 
-fn parse_cpu_pinning_cores(
-    socket_id: usize,
-    cores: &Hash,
-) -> Result<Vec<(usize, usize, usize, usize)>> {
-    let mut cpu_pinning = vec![];
+// A directory or a file which has to exist with the given ownership and mode before qemu starts
+pub struct ManagedPath {
+    group: Option<u16>,
+    kind: PathKind,
+    mode: Option<u32>,
+    path: String,
+    user: Option<u16>,
+}
 
-    for (core, threads) in cores {
-        let core_id = match as_u64(core) {
-            Some(id) => Ok(id),
-            None => Err(Error::new(
-                ErrorKind::InvalidData,
-                format!(
-                    "Failed to parse `launcher.vcpu_pinning.{}`: \
-                        the core ID must be an integer greater or equal to zero.",
-                    socket_id
-                ),
-            )),
-        }?;
+impl ManagedPath {
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
 
-        match threads {
-            Yaml::Hash(threads) => {
-                cpu_pinning.append(&mut parse_cpu_pinning_threads(socket_id, core_id, threads)?)
-            }
-            _ => {
-                return Err(Error::new(
-                    ErrorKind::InvalidData,
-                    format!(
-                        "Failed to parse `launcher.vcpu_pinning.{}.{}`: a hash expected.",
-                        socket_id, core_id
-                    ),
-                ))
-            }
-        }
+    pub fn get_kind(&self) -> PathKind {
+        self.kind
     }
 
-    Ok(cpu_pinning)
-}
-
-fn parse_cpu_pinning_threads(
-    socket_id: usize,
-    core_id: usize,
-    threads: &Hash,
-) -> Result<Vec<(usize, usize, usize, usize)>> {
-    let mut cpu_pinning = vec![];
-
-    for (thread, host) in threads {
-        let thread_id = match as_u64(thread) {
-            Some(id) => Ok(id),
-            None => Err(Error::new(
-                ErrorKind::InvalidData,
-                format!(
-                    "Failed to parse `launcher.vcpu_pinning.{}.{}`: \
-                        the thread ID must be a positive integer.",
-                    socket_id, core_id
-                ),
-            )),
-        }?;
+    pub fn get_mode(&self) -> Option<u32> {
+        self.mode
+    }
 
-        let host_id = match as_u64(host) {
-            Some(id) => Ok(id),
-            None => Err(Error::new(
-                ErrorKind::InvalidData,
-                format!(
-                    "Failed to parse `launcher.vcpu_pinning.{}.{}.{}`: \
-                        the host core ID must be an integer greater or equal to zero.",
-                    socket_id, core_id, thread_id
-                ),
-            )),
-        }?;
+    pub fn get_user(&self) -> Option<u16> {
+        self.user
+    }
 
-        cpu_pinning.push((socket_id, core_id, thread_id, host_id));
+    pub fn get_group(&self) -> Option<u16> {
+        self.group
     }
+}
 
-    Ok(cpu_pinning)
+// The log of the guest serial console, rotated by the launcher once it outgrows the maximum size
+pub struct SerialLog {
+    keep: u32,
+    max_size_mb: u64,
+    path: String,
+    socket: Option<String>,
 }
 
-fn parse_cpu_pinning(config: &Yaml) -> Result<Vec<(usize, usize, usize, usize)>> {
-    match &config["launcher"]["vcpu_pinning"] {
-        Yaml::Hash(sockets) => parse_cpu_pinning_sockets(sockets),
-        Yaml::BadValue => Ok(vec![]),
-        _ => {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!(
-                    "Failed to parse `launcher.vcpu_pinning` \
-                        configuration: a hash is expected."
-                ),
-            ))
-        }
+impl SerialLog {
+    pub fn get_keep(&self) -> u32 {
+        self.keep
     }
-}
 
-fn parse_command_line(config: &Yaml) -> Result<Vec<Argument>> {
-    match &config["qemu"] {
-        Yaml::Array(options) => parse_command_line_options(options),
-        _ => Err(Error::new(
-            ErrorKind::InvalidData,
-            format!(
-                "Failed to parse qemu command line options: \
-                    missing or invalid value, array expected."
-            ),
-        )),
+    pub fn get_max_size(&self) -> u64 {
+        self.max_size_mb * 1024 * 1024
     }
 }
 
-fn parse_command_line_options(options: &Array) -> Result<Vec<Argument>> {
-    let mut parsed_options = vec![];
-
-    for (position, option) in options.iter().enumerate() {
-        let position = position + 1;
+// A `qemu-storage-daemon` serving block nodes to the machine over NBD on a Unix socket
+pub struct StorageDaemon {
+    binary: String,
+    exports: Vec<StorageExport>,
+    host_cpus: Vec<usize>,
+    id: String,
+    priority: Option<u8>,
+    scheduler: Option<String>,
+    socket: String,
+}
 
-        match option {
-            Yaml::String(option) => parsed_options.push(Argument::Flag(option.to_owned())),
-            Yaml::Hash(option) => parsed_options.push(parse_parameter(option, position)?),
-            _ => {
-                return Err(Error::new(
-                    ErrorKind::InvalidData,
-                    format!(
-                        "Failed to parse qemu command line option {}. \
-                        Every option must be either a string or a hash.",
-                        position
-                    ),
-                ))
-            }
-        }
+impl StorageDaemon {
+    pub fn get_id(&self) -> &str {
+        &self.id
     }
 
-    parsed_options.push(Argument::Parameter(
-        String::from("qmp"),
-        String::from("stdio"),
-    ));
-
-    Ok(parsed_options)
-}
+    pub fn get_binary(&self) -> &str {
+        &self.binary
+    }
 
-fn parse_parameter(option: &Hash, position: usize) -> Result<Argument> {
-    if option.len() != 1 {
-        return Err(Error::new(
-            ErrorKind::InvalidData,
-            format!(
-                "Found a command line argument {} with {} pairs \
-                in the hash, but exactly one is expected.",
-                position,
-                option.len()
-            ),
-        ));
+    pub fn get_host_cpus(&self) -> &[usize] {
+        &self.host_cpus
     }
 
-    let entry = option.front().unwrap();
+    pub fn get_scheduler(&self) -> Option<&str> {
+        self.scheduler.as_deref()
+    }
 
-    let name = entry
-        .0
-        .as_str()
-        .ok_or_else(|| {
-            Error::new(
-                ErrorKind::InvalidData,
-                format!("Argument {} name must be a string.", position),
-            )
-        })?
-        .to_string();
+    pub fn get_priority(&self) -> Option<u8> {
+        self.priority
+    }
+}
 
-    let value = match entry.1 {
-        Yaml::Integer(i) => i.to_string(),
-        Yaml::String(s) => s.to_string(),
-        Yaml::Real(r) => r.to_string(),
-        Yaml::Array(v) => parse_parameter_value(&name, v)?,
-        _ => {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!(
-                    "Invalid value for `{}` qemu argument {}: expected a \
-                        string, number or a hash with a single pair.",
-                    name, position
-                ),
-            ))
-        }
-    };
+struct StorageExport {
+    blockdev: String,
+    node: String,
+    writable: bool,
+}
 
-    Ok(Argument::Parameter(name, value))
+// Powers the machine down once it stayed idle, i.e. paused or below the CPU usage threshold, for
+// the configured time
+pub struct IdleShutdown {
+    after: u64,
+    cpu_percent: u8,
 }
 
-fn parse_parameter_value(name: &str, values: &Array) -> Result<String> {
-    if values.len() == 0 {
-        return Err(Error::new(
-            ErrorKind::InvalidData,
-            format!(
-                "Empty value for `{}` argument, consider \
-                    using a string instead of an array.",
-                name
-            ),
-        ));
+impl IdleShutdown {
+    pub fn get_after(&self) -> Duration {
+        Duration::from_secs(self.after)
     }
 
-    let mut parts = vec![];
-
-    for value in values {
-        match value {
-            Yaml::String(s) => parts.push(s.to_string()),
-            Yaml::Integer(i) => parts.push(i.to_string()),
-            Yaml::Real(r) => parts.push(r.to_string()),
-            Yaml::Hash(h) => parts.push(parse_parameter_value_part(&name, h)?),
-            _ => {
-                return Err(Error::new(
-                    ErrorKind::InvalidData,
-                    format!(
-                        "Invalid value for `{}` option: must be a \
-                            hash with a single pair or a string",
-                        name
-                    ),
-                ))
-            }
-        }
+    // The share of a single host CPU the machine has to stay below to be considered idle
+    pub fn get_cpu_percent(&self) -> u8 {
+        self.cpu_percent
     }
+}
 
-    Ok(parts.join(","))
+// Throttles the vCPUs by the share of the highest threshold the host thermal zone has reached
+pub struct ThermalThrottle {
+    // Temperatures in degrees Celsius with the throttle percentage, in the declaration order
+    thresholds: Vec<(u32, u8)>,
+    zone: String,
 }
 
-fn parse_parameter_value_part(name: &str, part: &Hash) -> Result<String> {
-    if part.len() != 1 {
-        return Err(Error::new(
-            ErrorKind::InvalidData,
-            format!(
-                "Failed to parse a value for `{}` argument: \
-                    a hash with multiple pairs found.",
-                name
-            ),
-        ));
+impl ThermalThrottle {
+    pub fn get_zone(&self) -> &str {
+        &self.zone
     }
 
-    let entry = part.front().unwrap();
+    // The temperature is in millidegrees Celsius, as reported by the kernel
+    pub fn get_throttle(&self, temperature: i64) -> u8 {
+        self.thresholds
+            .iter()
+            .filter(|(threshold, _)| temperature >= *threshold as i64 * 1000)
+            .map(|(_, throttle)| *throttle)
+            .max()
+            .unwrap_or(0)
+    }
+}
 
-    let part_name = entry.0.as_str().ok_or_else(|| {
-        Error::new(
-            ErrorKind::InvalidData,
-            format!(
-                "Failed to parse a value for `{}` argument: \
-                    a property name must be a string.",
-                name
-            ),
-        )
-    })?;
+struct Clock {
+    base: String,
+    tsc_frequency: Option<u64>,
+}
 
-    let part_value = match entry.1 {
-        Yaml::String(s) => s.to_string(),
-        Yaml::Integer(i) => i.to_string(),
-        Yaml::Real(r) => r.to_string(),
-        _ => {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!(
-                    "Failed to parse a value for `{}/{}` argument: \
-                    value must be either a string or a number.",
-                    name, part_name
-                ),
-            ))
-        }
-    };
+struct Io {
+    iothreads: bool,
+    // Estimated from the disks when not set explicitly
+    nofile: Option<u64>,
+}
 
-    Ok(format!("{}={}", part_name, part_value))
+struct Watchdog {
+    action: GuestShutdown,
+    model: String,
 }
 
-#[cfg(test)]
-mod test {
-    use super::Config;
-    use crate::{assert_error, expect, vec_deq, verify_expectations};
-    use ::std::{
-        cell::RefCell,
-        collections::{HashMap, VecDeque},
-        io::{Error, ErrorKind},
-    };
+struct Smbios {
+    manufacturer: Option<String>,
+    product: Option<String>,
+    serial: Option<String>,
+    uuid: Option<String>,
+    version: Option<String>,
+}
 
-    struct TestExpectations {
-        std_fs_read_to_string: VecDeque<(&'static str, Result<String, Error>)>,
-    }
+struct Audio {
+    backend: String,
+    id: String,
+    runtime_dir: Option<String>,
+    server: Option<String>,
+}
 
-    impl TestExpectations {
-        fn new() -> Self {
-            TestExpectations {
-                std_fs_read_to_string: vec_deq![],
-            }
+impl Audio {
+    fn get_pulse_server(&self) -> String {
+        match (&self.server, &self.runtime_dir) {
+            (Some(server), _) => server.clone(),
+            (None, Some(runtime_dir)) => format!("unix:{}/pulse/native", runtime_dir),
+            (None, None) => String::new(),
         }
     }
 
-    thread_local! { static TEST_EXPECTATIONS: RefCell<TestExpectations> = RefCell::new(TestExpectations::new()) }
-
-    fn verify_expectations() {
-        verify_expectations!(
-            std::fs::read_to_string => TEST_EXPECTATIONS::std_fs_read_to_string,
-        );
+    fn get_audiodev(&self) -> String {
+        match self.backend.as_str() {
+            "pulseaudio" => format!("pa,id={},server={}", self.id, self.get_pulse_server()),
+            backend => format!("{},id={}", backend, self.id),
+        }
     }
 
-    pub mod std {
-        pub mod fs {
-            use super::super::TEST_EXPECTATIONS;
-            use crate::verify_expectation;
-            use ::std::{io::Result, path::Path};
+    fn get_env_vars(&self) -> Vec<(String, String)> {
+        let mut vars = vec![];
 
-            pub fn read_to_string<P: AsRef<Path>>(path: P) -> Result<String> {
-                let path = path.as_ref().to_str().unwrap();
+        if let Some(runtime_dir) = &self.runtime_dir {
+            vars.push((String::from("XDG_RUNTIME_DIR"), runtime_dir.clone()));
+        }
 
-                verify_expectation!(TEST_EXPECTATIONS::std_fs_read_to_string => std::fs::read_to_string { path })
+        match self.backend.as_str() {
+            "pipewire" => {
+                if let Some(runtime_dir) = &self.runtime_dir {
+                    vars.push((String::from("PIPEWIRE_RUNTIME_DIR"), runtime_dir.clone()));
+                }
+                if let Some(server) = &self.server {
+                    vars.push((String::from("PIPEWIRE_REMOTE"), server.clone()));
+                }
             }
+            "pulseaudio" => {
+                vars.push((String::from("PULSE_SERVER"), self.get_pulse_server()));
+                vars.push((String::from("QEMU_AUDIO_DRV"), String::from("pa")));
+            }
+            _ => vars.push((String::from("QEMU_AUDIO_DRV"), String::from("alsa"))),
         }
-    }
 
-    #[test]
-    fn config_with_all_options_parsed_properly() {
-        let config = Config::new(
-            "
-            launcher:
-              user: 100
-              group: 200
-              vcpu_pinning:
-                0:
-                  0:
-                    0: 2
-                    1: 6
-                  1:
-                    0: 3
-                    1: 7
-              binary: /usr/bin/qemu-kvm
-              clear_env: true
-              env:
-                STRING: \"bar\"
-                INTEGER: 1
-                REAL: 1.0
-                BOOLEAN: true
-              priority: 1
-              scheduler: fifo
+        vars
+    }
+}
 
+enum Argument {
+    Flag(String),
+    Parameter(String, String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventSink {
+    Fifo(String),
+    File(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GuestShutdown {
+    Ignore,
+    Quit,
+    Restart,
+}
+
+struct NetworkInterface {
+    device: String,
+    id: String,
+    mac: Option<String>,
+    netdev: String,
+    pin_queues: bool,
+}
+
+enum QmpFormat {
+    Chardev,
+    Qmp,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum QmpLog {
+    File,
+    Main,
+}
+
+enum QmpPosition {
+    First,
+    Last,
+}
+
+// Machine types which lack devices and options the launcher generates for PC-style machines
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MachineFamily {
+    Microvm,
+    Pc,
+    S390Ccw,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Thp {
+    Always,
+    Madvise,
+    Never,
+}
+
+pub struct Config {
+    audio: Option<Audio>,
+    binary_sha256: Option<String>,
+    cache_allocation: Option<CacheAllocation>,
+    clear_env: bool,
+    clock: Option<Clock>,
+    command_line: Vec<Argument>,
+    cpu_pinning: Vec<VcpuPin>,
+    cpu_pinning_by_index: Vec<(usize, usize)>,
+    // The cpuset cgroup tree mount path and the prefix overriding the environment ones
+    cpuset: (Option<String>, Option<String>),
+    cwd_relative: bool,
+    deadline: Option<Deadline>,
+    defer_usb_host: bool,
+    depends_on: Vec<String>,
+    disable_timer_migration: bool,
+    display_env: Vec<(String, String)>,
+    env: HashMap<String, String>,
+    env_file: HashMap<String, String>,
+    env_keep: Vec<String>,
+    event_sink: Option<EventSink>,
+    firewall: Option<Firewall>,
+    fix_permissions: bool,
+    group: Option<u16>,
+    idle_shutdown: Option<IdleShutdown>,
+    io: Option<Io>,
+    isolate_workqueues: bool,
+    ksm: Option<bool>,
+    kvm: Vec<(String, u64)>,
+    lower_kernel_thread_priority: bool,
+    machine_family: MachineFamily,
+    machine_name: String,
+    max_runtime: Option<u64>,
+    merge_qemu_log: bool,
+    netboot: Option<Netboot>,
+    network: Vec<NetworkInterface>,
+    on_guest_shutdown: Option<GuestShutdown>,
+    paths: Vec<ManagedPath>,
+    post_start_hooks: Vec<String>,
+    priority: Option<u8>,
+    profile: Option<String>,
+    qemu_binary: String,
+    qemu_arguments: Vec<String>,
+    qmp_format: QmpFormat,
+    qmp_log: Option<QmpLog>,
+    qmp_position: QmpPosition,
+    qmp_proxy: Option<String>,
+    qmp_socket: Option<String>,
+    qmp_startup_commands: Vec<JsonValue>,
+    readconfig: bool,
+    rlimit_memlock: bool,
+    runtime_directory: String,
+    scheduler: Option<String>,
+    serial_log: Option<SerialLog>,
+    smbios: Option<Smbios>,
+    source: Yaml,
+    sriov: Vec<SriovInterface>,
+    storage_daemons: Vec<StorageDaemon>,
+    systemd_scope: Option<String>,
+    thermal_throttle: Option<ThermalThrottle>,
+    thp: Option<Thp>,
+    uclamp: HashMap<usize, UtilClamp>,
+    user: Option<u16>,
+    watchdog: Option<Watchdog>,
+}
+
+impl Config {
+    // Every file the configuration is read from, i.e. the machine one, its profile, the vCPU pinning
+    // file and the environment file, is handed to the check right before it is read
+    pub fn from_file<P: AsRef<Path>>(path: P, check: &dyn Fn(&str) -> Result<()>) -> Result<Self> {
+        let machine_name = match path.as_ref().file_stem() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => String::new(),
+        };
+
+        let profile_directory = match path.as_ref().parent() {
+            Some(directory) => directory.join("profiles"),
+            None => Path::new(DEFAULT_PROFILE_DIRECTORY).to_path_buf(),
+        };
+
+        check(&path.as_ref().to_string_lossy())?;
+        let contents = match read_to_string(path.as_ref()) {
+            Ok(contents) => contents,
+            Err(e) => {
+                return Err(Error::new(
+                    e.kind(),
+                    format!(
+                        "Failed to read configuration file `{}`: {}",
+                        path.as_ref().display(),
+                        e
+                    ),
+                ))
+            }
+        };
+        let config = match path.as_ref().extension() {
+            Some(extension) if extension == "json" => {
+                Self::load_json(contents, profile_directory, check)?
+            }
+            _ => Self::load(contents, profile_directory, check)?,
+        };
+
+        Ok(config.with_machine_name(machine_name))
+    }
+
+    #[cfg(test)]
+    pub fn new<Y: AsRef<str>>(yaml: Y) -> Result<Self> {
+        Self::load(yaml, DEFAULT_PROFILE_DIRECTORY, &|_| Ok({}))
+    }
+
+    // Stands in for a `launcher.env_file` outside of this module, inline variables keep precedence
+    #[cfg(test)]
+    pub fn with_env_file(mut self, vars: &[(&str, &str)]) -> Self {
+        for (name, value) in vars {
+            self.env_file.insert(name.to_string(), value.to_string());
+            self.env
+                .entry(name.to_string())
+                .or_insert_with(|| value.to_string());
+        }
+
+        self
+    }
+
+    fn load<Y: AsRef<str>, P: AsRef<Path>>(
+        yaml: Y,
+        profile_directory: P,
+        check: &dyn Fn(&str) -> Result<()>,
+    ) -> Result<Self> {
+        Self::from_document(load_yaml(yaml.as_ref())?, profile_directory.as_ref(), check)
+    }
+
+    // Machine generated configurations are accepted in JSON, which is loaded into the same document
+    // tree as YAML, so both formats go through the exact same validation. The format is picked by
+    // `from_file` only.
+    fn load_json<J: AsRef<str>, P: AsRef<Path>>(
+        json: J,
+        profile_directory: P,
+        check: &dyn Fn(&str) -> Result<()>,
+    ) -> Result<Self> {
+        Self::from_document(load_json(json.as_ref())?, profile_directory.as_ref(), check)
+    }
+
+    fn from_document(
+        mut conf: Yaml,
+        profile_directory: &Path,
+        check: &dyn Fn(&str) -> Result<()>,
+    ) -> Result<Self> {
+        let profile = parse_profile(&conf)?;
+
+        // Profile settings are merged beneath the machine ones, so the machine always wins
+        if let Some(name) = &profile {
+            let path = profile_directory.join(format!("{}.yml", name));
+            check(&path.to_string_lossy())?;
+            let profile_conf = match read_to_string(&path) {
+                Ok(contents) => load_yaml(&contents)?,
+                Err(e) => {
+                    return Err(Error::new(
+                        e.kind(),
+                        format!("Failed to read the `{}` profile: {}", path.display(), e),
+                    ))
+                }
+            };
+
+            conf = merge_profile(conf, profile_conf, name)?;
+        }
+
+        // Profiles live in the `profiles` directory next to the machine configurations
+        let config_directory = profile_directory.parent().unwrap_or(profile_directory);
+        let pinning_file = parse_vcpu_pinning_file(&conf, config_directory)?;
+
+        // Host specific settings are applied on top of both the profile and the machine
+        if let Some(path) = &pinning_file {
+            check(path)?;
+            let overlay = match read_to_string(path) {
+                Ok(contents) => load_yaml(&contents)?,
+                Err(e) => {
+                    return Err(Error::new(
+                        e.kind(),
+                        format!("Failed to read the `{}` vCPU pinning file: {}", path, e),
+                    ))
+                }
+            };
+
+            conf = merge_host_overlay(conf, overlay, path)?;
+        }
+
+        let env_file = match parse_env_file_path(&conf, config_directory)? {
+            Some(path) => {
+                check(&path)?;
+                read_env_file(&path)?
+            }
+            None => HashMap::new(),
+        };
+
+        let mut config = Self::from_yaml(conf, env_file)?;
+        config.profile = profile;
+
+        Ok(config)
+    }
+
+    fn from_yaml(conf: Yaml, env_file: HashMap<String, String>) -> Result<Self> {
+        Ok(Config {
+            audio: parse_audio(&conf)?,
+            binary_sha256: parse_binary_sha256(&conf)?,
+            cache_allocation: parse_cache_allocation(&conf)?,
+            clear_env: parse_clear_env(&conf)?,
+            clock: parse_clock(&conf)?,
+            command_line: parse_command_line(&conf)?,
+            cpu_pinning: parse_cpu_pinning(&conf)?,
+            cpu_pinning_by_index: parse_cpu_pinning_by_index(&conf)?,
+            cpuset: parse_cpuset(&conf)?,
+            cwd_relative: parse_cwd_relative(&conf)?,
+            deadline: parse_deadline(&conf)?,
+            defer_usb_host: parse_defer_usb_host(&conf)?,
+            depends_on: parse_depends_on(&conf)?,
+            disable_timer_migration: parse_disable_timer_migration(&conf)?,
+            display_env: parse_display_env(&conf)?,
+            env: parse_env(&conf, &env_file)?,
+            env_file,
+            env_keep: parse_env_keep(&conf)?,
+            event_sink: parse_event_sink(&conf)?,
+            firewall: parse_firewall(&conf)?,
+            fix_permissions: parse_fix_permissions(&conf)?,
+            group: parse_group(&conf)?,
+            idle_shutdown: parse_idle_shutdown(&conf)?,
+            io: parse_io(&conf)?,
+            isolate_workqueues: parse_isolate_workqueues(&conf)?,
+            ksm: parse_ksm(&conf)?,
+            kvm: parse_kvm(&conf)?,
+            lower_kernel_thread_priority: parse_lower_kernel_thread_priority(&conf)?,
+            machine_family: parse_machine_family(&conf)?,
+            machine_name: String::new(),
+            max_runtime: parse_max_runtime(&conf)?,
+            merge_qemu_log: parse_merge_qemu_log(&conf)?,
+            netboot: parse_netboot(&conf)?,
+            network: parse_network(&conf)?,
+            on_guest_shutdown: parse_on_guest_shutdown(&conf)?,
+            paths: parse_paths(&conf)?,
+            post_start_hooks: parse_post_start_hooks(&conf)?,
+            priority: parse_priority(&conf)?,
+            profile: None,
+            qemu_binary: parse_qemu_binary(&conf)?,
+            qemu_arguments: vec![],
+            qmp_format: parse_qmp_format(&conf)?,
+            qmp_log: parse_qmp_log(&conf)?,
+            qmp_position: parse_qmp_position(&conf)?,
+            qmp_proxy: parse_qmp_proxy(&conf)?,
+            qmp_socket: parse_qmp_socket(&conf)?,
+            qmp_startup_commands: parse_qmp_startup_commands(&conf)?,
+            readconfig: parse_readconfig(&conf)?,
+            rlimit_memlock: parse_rlimit_memlock(&conf)?,
+            runtime_directory: String::from("/run/qemu-launcher"),
+            scheduler: parse_scheduler(&conf)?,
+            serial_log: parse_serial_log(&conf)?,
+            smbios: parse_smbios(&conf)?,
+            sriov: parse_sriov(&conf)?,
+            storage_daemons: parse_storage_daemons(&conf)?,
+            systemd_scope: parse_systemd_scope(&conf)?,
+            thermal_throttle: parse_thermal_throttle(&conf)?,
+            thp: parse_thp(&conf)?,
+            uclamp: parse_uclamp(&conf)?,
+            user: parse_user(&conf)?,
+            watchdog: parse_watchdog(&conf)?,
+            source: conf,
+        })
+    }
+
+    // Returns the changed keys split into the ones applicable to the running machine and the ones
+    // requiring a restart. Pinning is only live-applicable while no host CPU is released from it.
+    pub fn get_changes(&self, other: &Config) -> (Vec<String>, Vec<String>) {
+        let mut keys = BTreeSet::new();
+        for config in &[self, other] {
+            if let Yaml::Hash(launcher) = &config.source["launcher"] {
+                keys.extend(launcher.keys().filter_map(|key| key.as_str()));
+            }
+        }
+
+        let pinned_cpus = other.get_pinned_host_cpus();
+        let pinning_is_live = self
+            .get_pinned_host_cpus()
+            .iter()
+            .all(|cpu| pinned_cpus.contains(cpu));
+
+        let (mut live, mut restart) = (vec![], vec![]);
+
+        for key in keys {
+            if self.source["launcher"][key] == other.source["launcher"][key] {
+                continue;
+            }
+
+            let is_pinning = key.starts_with("vcpu_pinning");
+            match LIVE_KEYS.contains(&key) && (pinning_is_live || !is_pinning) {
+                true => live.push(format!("launcher.{}", key)),
+                false => restart.push(format!("launcher.{}", key)),
+            }
+        }
+
+        if self.source["qemu"] != other.source["qemu"] {
+            restart.push(String::from("qemu"));
+        }
+
+        (live, restart)
+    }
+
+    // Builds the configuration the running machine ends up with, when only the live-applicable
+    // keys of the other configuration are taken over
+    pub fn with_live_changes(&self, other: &Config) -> Result<Config> {
+        let mut source = self.source.clone();
+
+        if let Yaml::Hash(root) = &mut source {
+            let launcher = root
+                .entry(Yaml::String(String::from("launcher")))
+                .or_insert_with(|| Yaml::Hash(Hash::new()));
+
+            if let Yaml::Hash(launcher) = launcher {
+                for key in LIVE_KEYS {
+                    let name = Yaml::String(key.to_string());
+                    match &other.source["launcher"][*key] {
+                        Yaml::BadValue => launcher.remove(&name),
+                        value => launcher.insert(name, value.clone()),
+                    };
+                }
+            }
+        }
+
+        let mut config = Self::from_yaml(source, self.env_file.clone())?;
+        config.machine_name = self.machine_name.clone();
+        config.qemu_arguments = self.qemu_arguments.clone();
+        config.runtime_directory = self.runtime_directory.clone();
+
+        Ok(config)
+    }
+
+    // Renders the configuration the way the launcher sees it: merged with the profile, with the
+    // `env_file` variables folded into `env` and raw qemu options split into names and values
+    pub fn dump(&self) -> Result<String> {
+        let mut launcher = match &self.source["launcher"] {
+            Yaml::Hash(launcher) => launcher.clone(),
+            _ => Hash::new(),
+        };
+
+        if let Some(profile) = &self.profile {
+            launcher.insert(
+                Yaml::String(String::from("profile")),
+                Yaml::String(profile.clone()),
+            );
+        }
+
+        if self.env.len() > 0 {
+            let mut env = Hash::new();
+            for (name, value) in self.get_masked_env_vars() {
+                env.insert(
+                    Yaml::String(name.to_owned()),
+                    Yaml::String(value.to_owned()),
+                );
+            }
+            launcher.insert(Yaml::String(String::from("env")), Yaml::Hash(env));
+        }
+
+        let qemu = self
+            .command_line
+            .iter()
+            .map(|option| match option {
+                Argument::Flag(flag) => Yaml::String(flag.clone()),
+                Argument::Parameter(name, value) => {
+                    let mut parameter = Hash::new();
+                    parameter.insert(
+                        Yaml::String(name.clone()),
+                        Yaml::String(mask_secret_properties(value)),
+                    );
+                    Yaml::Hash(parameter)
+                }
+            })
+            .collect();
+
+        let mut document = Hash::new();
+        document.insert(Yaml::String(String::from("launcher")), Yaml::Hash(launcher));
+        document.insert(Yaml::String(String::from("qemu")), Yaml::Array(qemu));
+
+        let mut yaml = String::new();
+        if let Err(e) = YamlEmitter::new(&mut yaml).dump(&Yaml::Hash(document)) {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("Failed to produce the YAML configuration: {:?}", e),
+            ));
+        }
+        yaml.push('\n');
+
+        Ok(yaml)
+    }
+
+    pub fn with_machine_name<N: AsRef<str>>(mut self, name: N) -> Self {
+        self.machine_name = name.as_ref().to_owned();
+
+        self
+    }
+
+    pub fn with_qemu_arguments(mut self, arguments: &[String]) -> Self {
+        self.qemu_arguments = arguments.to_vec();
+
+        self
+    }
+
+    pub fn with_runtime_directory<D: AsRef<str>>(mut self, directory: D) -> Self {
+        self.runtime_directory = directory.as_ref().to_owned();
+
+        self
+    }
+
+    pub fn get_profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    pub fn is_cwd_relative(&self) -> bool {
+        self.cwd_relative
+    }
+
+    pub fn get_machine_name(&self) -> &str {
+        &self.machine_name
+    }
+
+    pub fn get_user(&self) -> Option<u16> {
+        self.user
+    }
+
+    pub fn get_group(&self) -> Option<u16> {
+        self.group
+    }
+
+    pub fn get_cpu_pinning(&self) -> &Vec<VcpuPin> {
+        &self.cpu_pinning
+    }
+
+    pub fn get_cpu_pinning_by_index(&self) -> &Vec<(usize, usize)> {
+        &self.cpu_pinning_by_index
+    }
+
+    pub fn get_cpuset_mount_path(&self) -> Option<&str> {
+        self.cpuset.0.as_deref()
+    }
+
+    pub fn get_cpuset_prefix(&self) -> Option<&str> {
+        self.cpuset.1.as_deref()
+    }
+
+    pub fn get_pinned_host_cpus(&self) -> Vec<usize> {
+        pinned_host_cpus(&self.cpu_pinning, &self.cpu_pinning_by_index)
+    }
+
+    pub fn get_command_line_options(&self) -> Vec<String> {
+        let mut result = vec![];
+
+        if let QmpPosition::First = self.qmp_position {
+            result.append(&mut self.get_launcher_qmp_options());
+        }
+
+        let mut iothreads = 0;
+        for option in &self.command_line {
+            match option {
+                Argument::Flag(flag) => result.push(format!("-{}", flag)),
+                // Hot-added once the vCPUs are pinned
+                Argument::Parameter(_, _) if self.is_deferred_device(option) => {}
+                Argument::Parameter(name, value) => {
+                    let mut value = self.normalize_parameter(name, value);
+
+                    // Every disk controller gets an iothread of its own unless it picks one itself
+                    if let (Some(io), "device") = (&self.io, name.as_str()) {
+                        if io.iothreads
+                            && is_virtio_disk_controller(&value)
+                            && get_netdev_option(&value, "iothread").is_none()
+                        {
+                            let id = format!("{}{}", LAUNCHER_IOTHREAD_PREFIX, iothreads);
+                            iothreads += 1;
+
+                            result.push(String::from("-object"));
+                            result.push(format!("iothread,id={}", id));
+                            value.push_str(&format!(",iothread={}", id));
+                        }
+                    }
+
+                    result.push(format!("-{}", name));
+                    result.push(value);
+
+                    if let (Some(clock), "cpu") = (&self.clock, name.as_str()) {
+                        let last = result.len() - 1;
+                        result[last].push_str(",+kvmclock,+kvmclock-stable-bit");
+                        if let Some(frequency) = clock.tsc_frequency {
+                            result[last].push_str(&format!(",tsc-frequency={}", frequency));
+                        }
+                    }
+                }
+            }
+        }
+
+        result.extend(self.qemu_arguments.iter().cloned());
+
+        for nic in &self.network {
+            let mac = match &nic.mac {
+                Some(mac) => mac.clone(),
+                None => generate_mac_address(&self.machine_name, &nic.id),
+            };
+
+            // qemu serves the boot files itself over the `user` networking
+            let mut netdev = format!("{},id={}", nic.netdev, nic.id);
+            if let (Some(netboot), true) = (&self.netboot, is_user_netdev(&nic.netdev)) {
+                netdev.push_str(&format!(",tftp={}", netboot.directory));
+                if let Some(bootfile) = &netboot.bootfile {
+                    netdev.push_str(&format!(",bootfile={}", bootfile));
+                }
+            }
+
+            result.push(String::from("-netdev"));
+            result.push(netdev);
+            result.push(String::from("-device"));
+            result.push(format!("{},netdev={},mac={}", nic.device, nic.id, mac));
+        }
+
+        // Exported nodes keep their names, so devices refer to them as if they were local
+        for daemon in &self.storage_daemons {
+            for export in &daemon.exports {
+                result.push(String::from("-blockdev"));
+                result.push(format!(
+                    "driver=nbd,node-name={},server.type=unix,server.path={},export={}{}",
+                    export.node,
+                    self.get_storage_daemon_socket(daemon),
+                    export.node,
+                    if export.writable { "" } else { ",read-only=on" }
+                ));
+            }
+        }
+
+        if let Some(clock) = &self.clock {
+            result.push(String::from("-rtc"));
+            result.push(format!("base={},driftfix=slew", clock.base));
+            result.push(String::from("-global"));
+            result.push(String::from("kvm-pit.lost_tick_policy=delay"));
+            // microvm never has an HPET and refuses the property
+            if self.machine_family != MachineFamily::Microvm {
+                result.push(String::from("-machine"));
+                result.push(String::from("hpet=off"));
+            }
+        }
+
+        if let Some(audio) = &self.audio {
+            result.push(String::from("-audiodev"));
+            result.push(audio.get_audiodev());
+        }
+
+        if let Some(smbios) = &self.smbios {
+            result.push(String::from("-smbios"));
+            result.push(self.get_smbios_system(smbios));
+        }
+
+        if let Some(ksm) = self.ksm {
+            result.push(String::from("-machine"));
+            result.push(format!("mem-merge={}", if ksm { "on" } else { "off" }));
+        }
+
+        // qemu is kept around after the guest powers off, so the launcher decides what happens next
+        if self.on_guest_shutdown.is_some() {
+            result.push(String::from("-no-shutdown"));
+        }
+
+        // qemu only reports the expiry, so the launcher carries out the action and reports it
+        if let Some(watchdog) = &self.watchdog {
+            result.push(String::from("-device"));
+            result.push(watchdog.model.clone());
+            result.push(String::from("-watchdog-action"));
+            result.push(String::from("none"));
+        }
+
+        if self.merge_qemu_log {
+            result.push(String::from("-msg"));
+            result.push(String::from("timestamp=on"));
+        }
+
+        // The chardev log captures the console output whether or not anybody is attached to it
+        if let Some(log) = &self.serial_log {
+            let backend = match &log.socket {
+                Some(socket) => format!(
+                    "socket,path={},server=on,wait=off",
+                    self.normalize_path(socket)
+                ),
+                None => String::from("null"),
+            };
+
+            result.push(String::from("-chardev"));
+            result.push(format!(
+                "{},id={},logfile={},logappend=on",
+                backend,
+                LAUNCHER_SERIAL_CHARDEV,
+                self.get_serial_log_path(log)
+            ));
+            result.push(String::from("-serial"));
+            result.push(format!("chardev:{}", LAUNCHER_SERIAL_CHARDEV));
+        }
+
+        if let Some(socket) = &self.qmp_socket {
+            result.push(String::from("-qmp"));
+            result.push(format!(
+                "unix:{},server=on,wait=off",
+                self.normalize_path(socket)
+            ));
+        }
+
+        if let QmpPosition::Last = self.qmp_position {
+            result.append(&mut self.get_launcher_qmp_options());
+        }
+
+        result
+    }
+
+    pub fn get_serial_log_path(&self, log: &SerialLog) -> String {
+        self.normalize_path(&log.path)
+    }
+
+    pub fn get_storage_daemon_socket(&self, daemon: &StorageDaemon) -> String {
+        self.normalize_path(&daemon.socket)
+    }
+
+    pub fn get_storage_daemon_arguments(&self, daemon: &StorageDaemon) -> Vec<String> {
+        let mut result = vec![];
+
+        for export in &daemon.exports {
+            result.push(String::from("--blockdev"));
+            result.push(format!("{},node-name={}", export.blockdev, export.node));
+        }
+
+        result.push(String::from("--nbd-server"));
+        result.push(format!(
+            "addr.type=unix,addr.path={}",
+            self.get_storage_daemon_socket(daemon)
+        ));
+
+        for export in &daemon.exports {
+            result.push(String::from("--export"));
+            result.push(format!(
+                "type=nbd,id=export-{},node-name={},name={},writable={}",
+                export.node,
+                export.node,
+                export.node,
+                if export.writable { "on" } else { "off" }
+            ));
+        }
+
+        result
+    }
+
+    fn get_smbios_system(&self, smbios: &Smbios) -> String {
+        let uuid = match smbios.uuid.as_deref() {
+            Some("auto") => Some(generate_uuid(&self.machine_name)),
+            uuid => uuid.map(|uuid| uuid.to_owned()),
+        };
+
+        let fields = [
+            ("manufacturer", &smbios.manufacturer),
+            ("product", &smbios.product),
+            ("version", &smbios.version),
+            ("serial", &smbios.serial),
+            ("uuid", &uuid),
+        ];
+
+        let mut system = String::from("type=1");
+        for (name, value) in fields.iter() {
+            if let Some(value) = value {
+                // Commas are doubled to keep them within the qemu option value
+                system.push_str(&format!(",{}={}", name, value.replace(',', ",,")));
+            }
+        }
+
+        system
+    }
+
+    fn get_launcher_qmp_options(&self) -> Vec<String> {
+        match self.qmp_format {
+            QmpFormat::Chardev => vec![
+                String::from("-chardev"),
+                format!("stdio,id={}", LAUNCHER_QMP_CHARDEV),
+                String::from("-mon"),
+                format!("chardev={},mode=control", LAUNCHER_QMP_CHARDEV),
+            ],
+            QmpFormat::Qmp => vec![String::from("-qmp"), String::from("stdio")],
+        }
+    }
+
+    fn normalize_parameter(&self, name: &str, value: &str) -> String {
+        if !self.cwd_relative {
+            return value.to_owned();
+        }
+
+        match name {
+            "D" | "pidfile" => self.normalize_path(value),
+            _ => value
+                .split(',')
+                .map(|property| {
+                    for prefix in &["path=", "logfile=", "unix:"] {
+                        if property.starts_with(prefix) {
+                            return format!(
+                                "{}{}",
+                                prefix,
+                                self.normalize_path(&property[prefix.len()..])
+                            );
+                        }
+                    }
+
+                    property.to_owned()
+                })
+                .collect::<Vec<String>>()
+                .join(","),
+        }
+    }
+
+    fn normalize_path(&self, path: &str) -> String {
+        if !self.cwd_relative || path.starts_with('/') {
+            return path.to_owned();
+        }
+
+        format!("{}/{}/{}", self.runtime_directory, self.machine_name, path)
+    }
+
+    pub fn get_qemu_binary_path(&self) -> &String {
+        &self.qemu_binary
+    }
+
+    // Names of the options in the `qemu` section, without the leading dash
+    pub fn get_qemu_option_names(&self) -> Vec<&str> {
+        self.command_line
+            .iter()
+            .map(|option| match option {
+                Argument::Flag(name) | Argument::Parameter(name, _) => name.as_str(),
+            })
+            .collect()
+    }
+
+    pub fn get_binary_sha256(&self) -> Option<&str> {
+        self.binary_sha256.as_deref()
+    }
+
+    pub fn get_util_clamp(&self, cpu: usize) -> Option<UtilClamp> {
+        self.uclamp.get(&cpu).cloned()
+    }
+
+    pub fn get_netboot(&self) -> Option<&Netboot> {
+        self.netboot.as_ref()
+    }
+
+    pub fn get_firewall(&self) -> Option<&Firewall> {
+        self.firewall.as_ref()
+    }
+
+    pub fn get_sriov_interfaces(&self) -> &[SriovInterface] {
+        &self.sriov
+    }
+
+    pub fn get_paths(&self) -> &[ManagedPath] {
+        &self.paths
+    }
+
+    pub fn get_qmp_startup_commands(&self) -> &Vec<JsonValue> {
+        &self.qmp_startup_commands
+    }
+
+    pub fn has_cpu_pinning(&self) -> bool {
+        self.cpu_pinning.len() > 0 || self.cpu_pinning_by_index.len() > 0
+    }
+
+    pub fn has_queue_pinning(&self) -> bool {
+        self.network.iter().any(|nic| nic.pin_queues)
+    }
+
+    // Every vhost enabled interface in the order qemu creates its workers, along with the number of
+    // queues and whether they are pinned
+    pub fn get_vhost_queues(&self) -> Vec<(&str, usize, bool)> {
+        self.network
+            .iter()
+            .filter(|nic| get_netdev_option(&nic.netdev, "vhost") == Some("on"))
+            .map(|nic| {
+                let queues = get_netdev_option(&nic.netdev, "queues")
+                    .and_then(|queues| queues.parse::<usize>().ok())
+                    .unwrap_or(1);
+
+                (nic.id.as_str(), queues, nic.pin_queues)
+            })
+            .collect()
+    }
+
+    // Pinning to a CPU the host does not have only fails late with EINVAL from the kernel
+    pub fn validate_host_cpus(&self, present: &[usize]) -> Result<()> {
+        for pin in &self.cpu_pinning {
+            if !present.contains(&pin.host_cpu) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Wrong value for `launcher.vcpu_pinning`: the host CPU `{}` of {} \
+                        is not present on this host.",
+                        pin.host_cpu,
+                        pin.describe()
+                    ),
+                ));
+            }
+        }
+
+        for (index, host_cpu) in &self.cpu_pinning_by_index {
+            if !present.contains(host_cpu) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Wrong value for `launcher.vcpu_pinning_by_index`: the host CPU `{}` of vCPU {} \
+                        is not present on this host.",
+                        host_cpu, index
+                    ),
+                ));
+            }
+        }
+
+        Ok({})
+    }
+
+    pub fn should_clear_env(&self) -> bool {
+        self.clear_env
+    }
+
+    pub fn should_fix_permissions(&self) -> bool {
+        self.fix_permissions
+    }
+
+    #[cfg(test)]
+    pub fn get_env_vars(&self) -> &HashMap<String, String> {
+        &self.env
+    }
+
+    // The environment as it is written out: values taken from `launcher.env_file`, which is where
+    // secrets are kept out of the configuration, and values of secret looking names are masked
+    pub fn get_masked_env_vars(&self) -> BTreeMap<&str, &str> {
+        self.env
+            .iter()
+            .map(|(name, value)| {
+                let value = match self.env_file.get(name) == Some(value) || is_secret_variable(name)
+                {
+                    true => MASKED_SECRET,
+                    false => value.as_str(),
+                };
+
+                (name.as_str(), value)
+            })
+            .collect()
+    }
+
+    // Variables are layered from the lowest to the highest precedence: the launcher environment
+    // (only `launcher.env_keep` names when `launcher.clear_env` is set), `launcher.audio`,
+    // `launcher.display.env`, `launcher.env_file` and `launcher.env`. The result is sorted by name,
+    // so it does not depend on the hashing order.
+    pub fn resolve_env_vars<I: IntoIterator<Item = (String, String)>>(
+        &self,
+        launcher_env: I,
+    ) -> Vec<EnvVar> {
+        let mut resolved: BTreeMap<String, EnvVar> = BTreeMap::new();
+
+        let inherited = launcher_env
+            .into_iter()
+            .filter(|(name, _)| !self.clear_env || self.env_keep.contains(name))
+            .map(|(name, value)| (name, value, EnvSource::Launcher));
+        let audio = self
+            .audio
+            .iter()
+            .flat_map(Audio::get_env_vars)
+            .map(|(name, value)| (name, value, EnvSource::Audio));
+        let display = self
+            .display_env
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone(), EnvSource::Display));
+        let from_file = self
+            .env_file
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone(), EnvSource::EnvFile));
+        // Inline variables are merged over the file ones at parse time, identical values are
+        // attributed to the file
+        let inline = self
+            .env
+            .iter()
+            .filter(|(name, value)| self.env_file.get(*name) != Some(value))
+            .map(|(name, value)| (name.clone(), value.clone(), EnvSource::Env));
+
+        for (name, value, source) in inherited
+            .chain(audio)
+            .chain(display)
+            .chain(from_file)
+            .chain(inline)
+        {
+            let mut overrides = vec![];
+            if let Some(previous) = resolved.remove(&name) {
+                overrides = previous.overrides;
+                overrides.push(previous.source);
+            }
+
+            resolved.insert(
+                name.clone(),
+                EnvVar {
+                    name,
+                    value,
+                    source,
+                    overrides,
+                },
+            );
+        }
+
+        resolved.into_iter().map(|(_, var)| var).collect()
+    }
+
+    pub fn has_scheduling(&self) -> bool {
+        if let None = self.scheduler {
+            return false;
+        }
+
+        if let None = self.priority {
+            return false;
+        }
+
+        true
+    }
+
+    pub fn get_priority(&self) -> Option<u8> {
+        self.priority
+    }
+
+    pub fn get_rt_priority(&self) -> Option<u8> {
+        match self.scheduler.as_deref() {
+            Some("fifo") | Some("rr") => self.priority,
+            _ => None,
+        }
+    }
+
+    pub fn get_scheduler(&self) -> &Option<String> {
+        &self.scheduler
+    }
+
+    pub fn get_deadline(&self) -> Option<Deadline> {
+        self.deadline
+    }
+
+    pub fn rlimit_memlock(&self) -> bool {
+        self.rlimit_memlock
+    }
+
+    pub fn should_use_readconfig(&self) -> bool {
+        self.readconfig
+    }
+
+    pub fn get_open_files_limit(&self) -> Option<u64> {
+        let io = self.io.as_ref()?;
+
+        Some(io.nofile.unwrap_or_else(|| self.estimate_open_files()))
+    }
+
+    // Every disk holds its image, backing files and completion eventfds open, while every virtqueue
+    // of a disk controller needs a pair of eventfds on top of the default limit
+    fn estimate_open_files(&self) -> u64 {
+        let options = self.get_command_line_options();
+        let (mut disks, mut queues) = (0, 0);
+
+        for (name, value) in options.iter().zip(options.iter().skip(1)) {
+            match name.as_str() {
+                "-drive" | "-hda" | "-hdb" | "-hdc" | "-hdd" | "-cdrom" => disks += 1,
+                "-blockdev" if is_protocol_node(value) => disks += 1,
+                "-device" if is_virtio_disk_controller(value) => {
+                    queues += get_netdev_option(value, "num-queues")
+                        .and_then(|queues| queues.parse().ok())
+                        .unwrap_or(1)
+                }
+                _ => {}
+            }
+        }
+
+        1024 + 32 * disks + 4 * queues
+    }
+
+    pub fn uses_io_uring(&self) -> bool {
+        let options = self.get_command_line_options();
+
+        options
+            .iter()
+            .zip(options.iter().skip(1))
+            .filter(|(name, _)| *name == "-drive" || *name == "-blockdev")
+            .any(|(_, value)| {
+                get_netdev_option(value, "aio") == Some("io_uring")
+                    || get_netdev_option(value, "file.aio") == Some("io_uring")
+            })
+    }
+
+    pub fn get_cache_allocation(&self) -> Option<&CacheAllocation> {
+        self.cache_allocation.as_ref()
+    }
+
+    pub fn should_isolate_workqueues(&self) -> bool {
+        self.isolate_workqueues
+    }
+
+    pub fn should_disable_timer_migration(&self) -> bool {
+        self.disable_timer_migration
+    }
+
+    pub fn should_lower_kernel_thread_priority(&self) -> bool {
+        self.lower_kernel_thread_priority
+    }
+
+    pub fn get_ksm(&self) -> Option<bool> {
+        self.ksm
+    }
+
+    pub fn get_kvm_parameters(&self) -> &[(String, u64)] {
+        &self.kvm
+    }
+
+    pub fn get_idle_shutdown(&self) -> Option<&IdleShutdown> {
+        self.idle_shutdown.as_ref()
+    }
+
+    pub fn get_max_runtime(&self) -> Option<Duration> {
+        self.max_runtime.map(Duration::from_secs)
+    }
+
+    pub fn should_merge_qemu_log(&self) -> bool {
+        self.merge_qemu_log
+    }
+
+    pub fn get_dependencies(&self) -> &Vec<String> {
+        &self.depends_on
+    }
+
+    pub fn get_post_start_hooks(&self) -> &Vec<String> {
+        &self.post_start_hooks
+    }
+
+    pub fn get_qmp_proxy(&self) -> Option<String> {
+        self.qmp_proxy
+            .as_ref()
+            .map(|socket| self.normalize_path(socket))
+    }
+
+    pub fn get_qmp_socket(&self) -> Option<String> {
+        self.qmp_socket
+            .as_ref()
+            .map(|socket| self.normalize_path(socket))
+    }
+
+    pub fn get_thp(&self) -> Option<Thp> {
+        self.thp
+    }
+
+    pub fn get_systemd_scope(&self) -> Option<&str> {
+        self.systemd_scope.as_deref()
+    }
+
+    pub fn get_serial_log(&self) -> Option<&SerialLog> {
+        self.serial_log.as_ref()
+    }
+
+    pub fn get_storage_daemons(&self) -> &[StorageDaemon] {
+        &self.storage_daemons
+    }
+
+    pub fn get_thermal_throttle(&self) -> Option<&ThermalThrottle> {
+        self.thermal_throttle.as_ref()
+    }
+
+    pub fn get_qmp_log(&self) -> &Option<QmpLog> {
+        &self.qmp_log
+    }
+
+    pub fn get_on_guest_shutdown(&self) -> Option<GuestShutdown> {
+        self.on_guest_shutdown
+    }
+
+    pub fn get_watchdog_action(&self) -> Option<GuestShutdown> {
+        self.watchdog.as_ref().map(|watchdog| watchdog.action)
+    }
+
+    pub fn get_event_sink(&self) -> Option<EventSink> {
+        match &self.event_sink {
+            Some(EventSink::Fifo(path)) => Some(EventSink::Fifo(self.normalize_path(path))),
+            Some(EventSink::File(path)) => Some(EventSink::File(self.normalize_path(path))),
+            None => None,
+        }
+    }
+
+    // Host USB devices held back from the command line, which are hot-added once the vCPUs are pinned
+    pub fn get_deferred_devices(&self) -> Vec<String> {
+        self.command_line
+            .iter()
+            .filter(|option| self.is_deferred_device(option))
+            .filter_map(|option| match option {
+                Argument::Parameter(name, value) => Some(self.normalize_parameter(name, value)),
+                Argument::Flag(_) => None,
+            })
+            .collect()
+    }
+
+    fn is_deferred_device(&self, option: &Argument) -> bool {
+        match option {
+            Argument::Parameter(name, value) => {
+                self.defer_usb_host && is_usb_host_device(name, value)
+            }
+            Argument::Flag(_) => false,
+        }
+    }
+
+    pub fn should_watch_qmp_events(&self) -> bool {
+        self.on_guest_shutdown.is_some() || self.watchdog.is_some() || self.event_sink.is_some()
+    }
+
+    // Capabilities a launcher which is not running as root needs for the configured options, along
+    // with the option needing it. Writing into sysfs files owned by root needs `CAP_DAC_OVERRIDE`.
+    pub fn get_required_capabilities(&self) -> Vec<(&'static str, &'static str)> {
+        let mut required = vec![];
+        let mut require = |enabled: bool, option: &'static str, capability: &'static str| {
+            if enabled {
+                required.push((option, capability));
+            }
+        };
+
+        require(
+            self.cache_allocation.is_some(),
+            "launcher.cache_allocation",
+            "CAP_DAC_OVERRIDE",
+        );
+        require(self.deadline.is_some(), "launcher.deadline", "CAP_SYS_NICE");
+        require(
+            self.disable_timer_migration,
+            "launcher.disable_timer_migration",
+            "CAP_DAC_OVERRIDE",
+        );
+        require(
+            self.firewall.is_some(),
+            "launcher.firewall",
+            "CAP_NET_ADMIN",
+        );
+        require(self.group.is_some(), "launcher.group", "CAP_SETGID");
+        require(
+            self.isolate_workqueues,
+            "launcher.isolate_workqueues",
+            "CAP_DAC_OVERRIDE",
+        );
+        require(self.ksm.is_some(), "launcher.ksm", "CAP_DAC_OVERRIDE");
+        require(
+            self.netboot
+                .as_ref()
+                .map_or(false, |netboot| netboot.listen.is_some()),
+            "launcher.netboot",
+            "CAP_NET_BIND_SERVICE",
+        );
+        require(self.kvm.len() > 0, "launcher.kvm", "CAP_DAC_OVERRIDE");
+        require(
+            self.lower_kernel_thread_priority,
+            "launcher.lower_kernel_thread_priority",
+            "CAP_SYS_NICE",
+        );
+        require(
+            self.paths
+                .iter()
+                .any(|path| path.user.is_some() || path.group.is_some()),
+            "launcher.paths",
+            "CAP_CHOWN",
+        );
+        require(self.priority.is_some(), "launcher.priority", "CAP_SYS_NICE");
+        require(
+            self.rlimit_memlock,
+            "launcher.rlimit_memlock",
+            "CAP_SYS_RESOURCE",
+        );
+        require(self.sriov.len() > 0, "launcher.sriov", "CAP_NET_ADMIN");
+        require(
+            self.storage_daemons
+                .iter()
+                .any(|daemon| daemon.priority.is_some()),
+            "launcher.storage_daemons",
+            "CAP_SYS_NICE",
+        );
+        require(self.uclamp.len() > 0, "launcher.uclamp", "CAP_SYS_NICE");
+        require(self.user.is_some(), "launcher.user", "CAP_SETUID");
+        require(
+            self.fix_permissions,
+            "launcher.fix_permissions",
+            "CAP_CHOWN",
+        );
+        // The state directory is handed over to the qemu user
+        require(
+            self.cwd_relative && (self.user.is_some() || self.group.is_some()),
+            "launcher.cwd_relative",
+            "CAP_CHOWN",
+        );
+
+        required
+    }
+}
+
+fn load_yaml(yaml: &str) -> Result<Yaml> {
+    match YamlLoader::load_from_str(yaml) {
+        Ok(mut data) => match data.pop() {
+            Some(conf) => Ok(conf),
+            None => Err(Error::new(
+                ErrorKind::InvalidData,
+                "Supplied configuration is empty.",
+            )),
+        },
+        Err(e) => Err(Error::new(ErrorKind::InvalidData, format!("{}", e))),
+    }
+}
+
+fn load_json(json: &str) -> Result<Yaml> {
+    match json::parse(json) {
+        Ok(data) => Ok(json_to_yaml(&data)),
+        Err(e) => Err(Error::new(ErrorKind::InvalidData, format!("{}", e))),
+    }
+}
+
+fn json_to_yaml(value: &JsonValue) -> Yaml {
+    match value {
+        JsonValue::Object(object) => Yaml::Hash(
+            object
+                .iter()
+                .map(|(key, value)| {
+                    // Object keys are always strings in JSON, while hashes indexed by numbers use
+                    // integer keys, the way a plain YAML scalar would be loaded
+                    let key = match key.parse() {
+                        Ok(index) => Yaml::Integer(index),
+                        Err(_) => Yaml::String(key.to_owned()),
+                    };
+
+                    (key, json_to_yaml(value))
+                })
+                .collect(),
+        ),
+        JsonValue::Array(array) => Yaml::Array(array.iter().map(json_to_yaml).collect()),
+        JsonValue::Short(s) => Yaml::String(s.to_string()),
+        JsonValue::String(s) => Yaml::String(s.to_owned()),
+        // Integers beyond the `i64` range are kept as reals, so they are rejected as such by the
+        // parsers rather than silently truncated
+        JsonValue::Number(n) => match value.as_i64() {
+            Some(i) => Yaml::Integer(i),
+            None => Yaml::Real(n.to_string()),
+        },
+        JsonValue::Boolean(b) => Yaml::Boolean(*b),
+        JsonValue::Null => Yaml::Null,
+    }
+}
+
+fn parse_profile(config: &Yaml) -> Result<Option<String>> {
+    match &config["launcher"]["profile"] {
+        Yaml::String(name) if name.len() > 0 && !name.contains('/') => Ok(Some(name.to_owned())),
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.profile`: a profile name expected.",
+        )),
+    }
+}
+
+fn merge_profile(config: Yaml, profile: Yaml, name: &str) -> Result<Yaml> {
+    let mut launcher = match &profile["launcher"] {
+        Yaml::Hash(launcher) => launcher.clone(),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse the `{}` profile: a `launcher` hash expected.",
+                    name
+                ),
+            ))
+        }
+    };
+
+    if launcher.contains_key(&Yaml::String(String::from("profile"))) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse the `{}` profile: profiles can not reference other profiles.",
+                name
+            ),
+        ));
+    }
+
+    let mut config = match config {
+        Yaml::Hash(config) => config,
+        _ => return Ok(config),
+    };
+
+    if let Some(Yaml::Hash(machine)) = config.remove(&Yaml::String(String::from("launcher"))) {
+        for (key, value) in machine {
+            if key.as_str() == Some("profile") {
+                continue;
+            }
+
+            let merged = match (launcher.remove(&key), value) {
+                (Some(Yaml::Hash(defaults)), Yaml::Hash(values)) => {
+                    Yaml::Hash(merge_hash(defaults, values))
+                }
+                (_, value) => value,
+            };
+            launcher.insert(key, merged);
+        }
+    }
+
+    config.insert(Yaml::String(String::from("launcher")), Yaml::Hash(launcher));
+
+    Ok(Yaml::Hash(config))
+}
+
+fn parse_vcpu_pinning_file(config: &Yaml, config_directory: &Path) -> Result<Option<String>> {
+    let path = match &config["launcher"]["vcpu_pinning_file"] {
+        Yaml::String(path) if path.len() > 0 => path,
+        Yaml::BadValue => return Ok(None),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.vcpu_pinning_file`: a non-empty path expected.",
+            ))
+        }
+    };
+
+    let path = match path.contains(HOSTNAME_PLACEHOLDER) {
+        true => match read_to_string(HOSTNAME_PATH) {
+            Ok(hostname) => path.replace(HOSTNAME_PLACEHOLDER, hostname.trim()),
+            Err(e) => {
+                return Err(Error::new(
+                    e.kind(),
+                    format!(
+                        "Failed to read the host name from `{}`: {}",
+                        HOSTNAME_PATH, e
+                    ),
+                ))
+            }
+        },
+        false => path.to_owned(),
+    };
+
+    Ok(Some(
+        config_directory.join(path).to_string_lossy().to_string(),
+    ))
+}
+
+// Unlike profiles, values of the host overlay replace the machine ones as a whole, so host CPUs of
+// the portable definition never leak into the pinning of the host
+fn merge_host_overlay(config: Yaml, overlay: Yaml, path: &str) -> Result<Yaml> {
+    let overlay = match overlay["launcher"].clone() {
+        Yaml::Hash(overlay) => overlay,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse the `{}` vCPU pinning file: a `launcher` hash expected.",
+                    path
+                ),
+            ))
+        }
+    };
+
+    for key in &["profile", "vcpu_pinning_file"] {
+        if overlay.contains_key(&Yaml::String(key.to_string())) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse the `{}` vCPU pinning file: `{}` can not be set by it.",
+                    path, key
+                ),
+            ));
+        }
+    }
+
+    let mut config = match config {
+        Yaml::Hash(config) => config,
+        _ => return Ok(config),
+    };
+
+    let mut launcher = match config.remove(&Yaml::String(String::from("launcher"))) {
+        Some(Yaml::Hash(launcher)) => launcher,
+        _ => Hash::new(),
+    };
+
+    // Either way of pinning supplied by the host replaces any pinning of the machine
+    let replaces_pinning = ["vcpu_pinning", "vcpu_pinning_by_index"]
+        .iter()
+        .any(|key| overlay.contains_key(&Yaml::String(key.to_string())));
+    if replaces_pinning {
+        launcher.remove(&Yaml::String(String::from("vcpu_pinning")));
+        launcher.remove(&Yaml::String(String::from("vcpu_pinning_by_index")));
+    }
+
+    for (key, value) in overlay {
+        launcher.insert(key, value);
+    }
+
+    config.insert(Yaml::String(String::from("launcher")), Yaml::Hash(launcher));
+
+    Ok(Yaml::Hash(config))
+}
+
+fn is_secret_variable(name: &str) -> bool {
+    let name = name.to_uppercase();
+
+    ["PASSW", "SECRET", "TOKEN", "CREDENTIAL"]
+        .iter()
+        .any(|word| name.contains(word))
+        || name.ends_with("KEY")
+}
+
+// Passwords given inline, e.g. `-spice password=...`, and the data of `-object secret` are masked,
+// while references to secret objects, e.g. `password-secret=...`, are kept
+fn mask_secret_properties(value: &str) -> String {
+    let is_secret_object = value.split(',').next() == Some("secret");
+
+    value
+        .split(',')
+        .map(|property| match property.split_once('=') {
+            // `-vnc password=on` only asks for a password to be set over QMP
+            Some(("password", secret)) if secret != "on" && secret != "off" => {
+                format!("password={}", MASKED_SECRET)
+            }
+            Some(("data", _)) if is_secret_object => format!("data={}", MASKED_SECRET),
+            _ => property.to_owned(),
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn merge_hash(mut defaults: Hash, values: Hash) -> Hash {
+    for (key, value) in values {
+        let merged = match (defaults.remove(&key), value) {
+            (Some(Yaml::Hash(nested)), Yaml::Hash(values)) => {
+                Yaml::Hash(merge_hash(nested, values))
+            }
+            (_, value) => value,
+        };
+        defaults.insert(key, merged);
+    }
+
+    defaults
+}
+
+pub fn check_file_permissions(path: &str, mode: u32, owner: u32, euid: u32) -> Result<()> {
+    if owner != 0 && owner != euid {
+        return Err(Error::new(
+            ErrorKind::PermissionDenied,
+            format!(
+                "The `{}` configuration file is owned by the user with ID {}, \
+                expected to be owned by root or the user with ID {}.",
+                path, owner, euid
+            ),
+        ));
+    }
+
+    if mode & 0o022 != 0 {
+        return Err(Error::new(
+            ErrorKind::PermissionDenied,
+            format!(
+                "The `{}` configuration file is writable by the group or other users (mode {:04o}).",
+                path,
+                mode & 0o7777
+            ),
+        ));
+    }
+
+    Ok({})
+}
+
+fn parse_bool_value(yaml: &Yaml, key: &str) -> Result<bool> {
+    match yaml[key] {
+        Yaml::Boolean(b) => Ok(b),
+        Yaml::BadValue => Ok(false),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Invalid value for `launcher.{}` value: a boolean is expected.",
+                key
+            ),
+        )),
+    }
+}
+
+fn parse_clear_env(config: &Yaml) -> Result<bool> {
+    parse_bool_value(&config["launcher"], "clear_env")
+}
+
+fn parse_defer_usb_host(config: &Yaml) -> Result<bool> {
+    let defer_usb_host = parse_bool_value(&config["launcher"], "defer_usb_host")?;
+
+    let has_usb_host_device = parse_command_line(config)?
+        .iter()
+        .any(|option| match option {
+            Argument::Parameter(name, value) => is_usb_host_device(name, value),
+            Argument::Flag(_) => false,
+        });
+
+    if defer_usb_host && !has_usb_host_device {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.defer_usb_host`: there is no `usb-host` device in the `qemu` section.",
+        ));
+    }
+
+    Ok(defer_usb_host)
+}
+
+fn is_usb_host_device(name: &str, value: &str) -> bool {
+    name == "device"
+        && (value.split(',').next() == Some("usb-host")
+            || get_netdev_option(value, "driver") == Some("usb-host"))
+}
+
+fn parse_cwd_relative(config: &Yaml) -> Result<bool> {
+    parse_bool_value(&config["launcher"], "cwd_relative")
+}
+
+fn parse_readconfig(config: &Yaml) -> Result<bool> {
+    parse_bool_value(&config["launcher"], "readconfig")
+}
+
+fn parse_ksm(config: &Yaml) -> Result<Option<bool>> {
+    match &config["launcher"]["ksm"] {
+        Yaml::String(s) if s == "on" => Ok(Some(true)),
+        Yaml::String(s) if s == "off" => Ok(Some(false)),
+        Yaml::Boolean(b) => Ok(Some(*b)),
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.ksm`: Expected one of `on` or `off`.",
+        )),
+    }
+}
+
+fn parse_kvm(config: &Yaml) -> Result<Vec<(String, u64)>> {
+    let parameters = match &config["launcher"]["kvm"] {
+        Yaml::Hash(parameters) => parameters,
+        Yaml::BadValue => return Ok(vec![]),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.kvm`: a hash expected.",
+            ))
+        }
+    };
+
+    let mut kvm = vec![];
+    for (name, value) in parameters {
+        let name = match name.as_str() {
+            Some(name) if KVM_PARAMETERS.contains(&name) => name,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Failed to parse `launcher.kvm`: one of `{}` keys expected.",
+                        KVM_PARAMETERS.join("`, `")
+                    ),
+                ))
+            }
+        };
+
+        // The module parameters are unsigned 32 bit integers
+        match value {
+            Yaml::Integer(value) if *value >= 0 && *value <= u32::MAX as i64 => {
+                kvm.push((name.to_owned(), *value as u64))
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Failed to parse `launcher.kvm.{}`: a non-negative integer expected.",
+                        name
+                    ),
+                ))
+            }
+        }
+    }
+
+    Ok(kvm)
+}
+
+fn parse_max_runtime(config: &Yaml) -> Result<Option<u64>> {
+    match config["launcher"]["max_runtime"] {
+        Yaml::Integer(seconds) if seconds > 0 => Ok(Some(seconds as u64)),
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.max_runtime`: a positive number of seconds expected.",
+        )),
+    }
+}
+
+fn parse_idle_shutdown(config: &Yaml) -> Result<Option<IdleShutdown>> {
+    let policy = &config["launcher"]["idle_shutdown"];
+    match policy {
+        Yaml::Hash(keys) => {
+            for key in keys.keys() {
+                match key.as_str() {
+                    Some("after") | Some("cpu_percent") => {}
+                    _ => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!(
+                                "Failed to parse `launcher.idle_shutdown`: unknown key `{}`, \
+                                    `after` or `cpu_percent` expected.",
+                                key.as_str().unwrap_or("?")
+                            ),
+                        ))
+                    }
+                }
+            }
+        }
+        Yaml::BadValue => return Ok(None),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.idle_shutdown`: a hash expected.",
+            ))
+        }
+    }
+
+    let after = match policy["after"] {
+        Yaml::Integer(seconds) if seconds > 0 => seconds as u64,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.idle_shutdown.after`: a positive number of seconds expected.",
+            ))
+        }
+    };
+
+    let cpu_percent = match policy["cpu_percent"] {
+        Yaml::Integer(percent) if percent > 0 && percent <= 100 => percent as u8,
+        Yaml::BadValue => 5,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.idle_shutdown.cpu_percent`: \
+                    a percentage from 1 to 100 expected.",
+            ))
+        }
+    };
+
+    Ok(Some(IdleShutdown { after, cpu_percent }))
+}
+
+fn parse_merge_qemu_log(config: &Yaml) -> Result<bool> {
+    parse_bool_value(&config["launcher"], "merge_qemu_log")
+}
+
+fn parse_depends_on(config: &Yaml) -> Result<Vec<String>> {
+    let machines = match &config["launcher"]["depends_on"] {
+        Yaml::Array(a) => a,
+        Yaml::BadValue => return Ok(vec![]),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.depends_on`: an array of machine names expected.",
+            ))
+        }
+    };
+
+    let mut depends_on = vec![];
+
+    for (position, machine) in machines.iter().enumerate() {
+        match machine.as_str() {
+            Some(name) if name.len() > 0 && !name.contains('/') && !name.contains('\0') => {
+                depends_on.push(name.to_string())
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                    "Failed to parse `launcher.depends_on` item {}: a valid machine name expected.",
+                    position
+                ),
+                ))
+            }
+        }
+    }
+
+    Ok(depends_on)
+}
+
+fn parse_post_start_hooks(config: &Yaml) -> Result<Vec<String>> {
+    let hooks = match &config["launcher"]["post_start_hooks"] {
+        Yaml::Array(a) => a,
+        Yaml::BadValue => return Ok(vec![]),
+        _ => return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.post_start_hooks`: an array of executable paths expected.",
+        )),
+    };
+
+    let mut post_start_hooks = vec![];
+
+    for (position, hook) in hooks.iter().enumerate() {
+        match hook.as_str() {
+            Some(path) if path.starts_with('/') && !path.contains('\0') => {
+                post_start_hooks.push(path.to_string())
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Failed to parse `launcher.post_start_hooks` item {}: an absolute path expected.",
+                        position
+                    ),
+                ))
+            }
+        }
+    }
+
+    Ok(post_start_hooks)
+}
+
+fn parse_cpuset(config: &Yaml) -> Result<(Option<String>, Option<String>)> {
+    let cpuset = &config["launcher"]["cpuset"];
+
+    match cpuset {
+        Yaml::Hash(keys) => {
+            for key in keys.keys() {
+                match key.as_str() {
+                    Some("mount_path") | Some("prefix") => {}
+                    _ => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            "Failed to parse `launcher.cpuset`: a hash with the `mount_path` and `prefix` \
+                                keys expected.",
+                        ))
+                    }
+                }
+            }
+        }
+        Yaml::BadValue => return Ok((None, None)),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.cpuset`: a hash with the `mount_path` and `prefix` keys expected.",
+            ))
+        }
+    }
+
+    let mount_path = match &cpuset["mount_path"] {
+        Yaml::String(s) if s.starts_with('/') && s.trim_end_matches('/').len() > 0 => {
+            Some(s.trim_end_matches('/').to_string())
+        }
+        Yaml::BadValue => None,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.cpuset.mount_path`: an absolute path expected.",
+            ))
+        }
+    };
+
+    // Like `QEMU_LAUNCHER_CPUSET_PREFIX`, a single directory right under the mount path
+    let prefix = match &cpuset["prefix"] {
+        Yaml::String(s)
+            if s.len() > 0 && s != "." && s != ".." && !s.contains('/') && !s.contains('\0') =>
+        {
+            Some(s.to_string())
+        }
+        Yaml::BadValue => None,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.cpuset.prefix`: a directory name expected.",
+            ))
+        }
+    };
+
+    Ok((mount_path, prefix))
+}
+
+fn parse_io(config: &Yaml) -> Result<Option<Io>> {
+    let io = &config["launcher"]["io"];
+    match io {
+        Yaml::Hash(_) => {}
+        Yaml::BadValue => return Ok(None),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.io`: a hash expected.",
+            ))
+        }
+    }
+
+    let iothreads = match io["iothreads"] {
+        Yaml::Boolean(iothreads) => iothreads,
+        Yaml::BadValue => false,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.io.iothreads`: boolean expected.",
+            ))
+        }
+    };
+
+    let nofile = match &io["nofile"] {
+        Yaml::Integer(nofile) if *nofile > 0 => Some(*nofile as u64),
+        Yaml::String(nofile) if nofile == "auto" => None,
+        Yaml::BadValue => None,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.io.nofile`: `auto` or a positive integer expected.",
+            ))
+        }
+    };
+
+    Ok(Some(Io { iothreads, nofile }))
+}
+
+fn parse_isolate_workqueues(config: &Yaml) -> Result<bool> {
+    parse_bool_value(&config["launcher"], "isolate_workqueues")
+}
+
+fn parse_disable_timer_migration(config: &Yaml) -> Result<bool> {
+    parse_bool_value(&config["launcher"], "disable_timer_migration")
+}
+
+fn parse_lower_kernel_thread_priority(config: &Yaml) -> Result<bool> {
+    let lower = parse_bool_value(&config["launcher"], "lower_kernel_thread_priority")?;
+
+    // Only the kernel threads bound to the pinned host CPUs are touched
+    if lower
+        && config["launcher"]["vcpu_pinning"].is_badvalue()
+        && config["launcher"]["vcpu_pinning_by_index"].is_badvalue()
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.lower_kernel_thread_priority`: requires vCPU pinning to be configured.",
+        ));
+    }
+
+    Ok(lower)
+}
+
+fn parse_qemu_binary(config: &Yaml) -> Result<String> {
+    match config["launcher"]["binary"].as_str() {
+        Some(bin) => Ok(bin.to_string()),
+        None => Err(Error::new(
+            ErrorKind::InvalidData,
+            "qemu binary path is not specified, missing or \
+            the `launcher.binary` key has an invalid type.",
+        )),
+    }
+}
+
+fn parse_binary_sha256(config: &Yaml) -> Result<Option<String>> {
+    match &config["launcher"]["binary_sha256"] {
+        Yaml::String(s) if s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Ok(Some(s.to_ascii_lowercase()))
+        }
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.binary_sha256`: a hexadecimal SHA-256 digest of 64 characters expected.",
+        )),
+    }
+}
+
+fn parse_systemd_scope(config: &Yaml) -> Result<Option<String>> {
+    match &config["launcher"]["systemd_scope"] {
+        // systemd unit names are limited to 255 characters of the ASCII letters, digits, `:`, `-`,
+        // `_`, `.` and `\`
+        Yaml::String(s)
+            if s.len() > ".scope".len()
+                && s.len() <= 255
+                && s.ends_with(".scope")
+                && s.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || ":-_.\\".contains(c)) =>
+        {
+            Ok(Some(s.to_owned()))
+        }
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.systemd_scope`: a systemd scope unit name, e.g. `machine-foo.scope`, expected.",
+        )),
+    }
+}
+
+fn parse_env(config: &Yaml, env_file: &HashMap<String, String>) -> Result<HashMap<String, String>> {
+    let mut env_vars = env_file.clone();
+
+    let inline_vars = match &config["launcher"]["env"] {
+        Yaml::Hash(h) => parse_env_hash(h)?,
+        Yaml::BadValue => HashMap::new(),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Invalid value for the `launcher.env` key: a hash expected.",
+            ))
+        }
+    };
+
+    env_vars.extend(inline_vars);
+
+    Ok(env_vars)
+}
+
+fn parse_env_keep(config: &Yaml) -> Result<Vec<String>> {
+    let names = match &config["launcher"]["env_keep"] {
+        Yaml::Array(names) => names,
+        Yaml::BadValue => return Ok(vec![]),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.env_keep`: an array of variable names expected.",
+            ))
+        }
+    };
+
+    if !parse_clear_env(config)? {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.env_keep`: \
+                can only be used when `launcher.clear_env` is set to `true`.",
+        ));
+    }
+
+    let mut env_keep = vec![];
+
+    for (position, name) in names.iter().enumerate() {
+        match name.as_str() {
+            Some(name) if name.len() > 0 && !name.contains('=') => env_keep.push(name.to_string()),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Failed to parse `launcher.env_keep` item {}: \
+                            a valid variable name expected.",
+                        position
+                    ),
+                ))
+            }
+        }
+    }
+
+    Ok(env_keep)
+}
+
+// Relative paths are taken relative to the configuration directory, like the vCPU pinning file
+fn parse_env_file_path(config: &Yaml, config_directory: &Path) -> Result<Option<String>> {
+    match &config["launcher"]["env_file"] {
+        Yaml::String(path) if path.len() > 0 => Ok(Some(
+            config_directory.join(path).to_string_lossy().to_string(),
+        )),
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.env_file`: a non-empty path expected.",
+        )),
+    }
+}
+
+fn read_env_file(path: &str) -> Result<HashMap<String, String>> {
+    let contents = match read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(Error::new(
+                e.kind(),
+                format!("Failed to read the `{}` environment file: {}", path, e),
+            ))
+        }
+    };
+
+    let mut env_vars = HashMap::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.len() < 1 || line.starts_with('#') {
+            continue;
+        }
+
+        let (name, value) = match line.find('=') {
+            Some(position) if position > 0 => {
+                (line[..position].trim(), line[position + 1..].trim())
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Failed to parse the `{}` environment file: \
+                        line {} is not in the `KEY=VALUE` format.",
+                        path,
+                        number + 1
+                    ),
+                ))
+            }
+        };
+
+        env_vars.insert(name.to_string(), unquote_env_value(value).to_string());
+    }
+
+    Ok(env_vars)
+}
+
+fn unquote_env_value(value: &str) -> &str {
+    for quote in &["\"", "'"] {
+        if value.len() > 1 && value.starts_with(quote) && value.ends_with(quote) {
+            return &value[1..value.len() - 1];
+        }
+    }
+
+    value
+}
+
+fn parse_env_hash(env: &Hash) -> Result<HashMap<String, String>> {
+    let mut env_vars = HashMap::new();
+
+    for (name, value) in env {
+        let name = name
+            .as_str()
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    "Environment variable name must be a string.",
+                )
+            })?
+            .to_string();
+
+        let value = match value {
+            Yaml::Boolean(b) => b.to_string(),
+            Yaml::Integer(i) => i.to_string(),
+            Yaml::Real(r) => r.to_string(),
+            Yaml::String(s) => s.to_string(),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Invalid value for the `{}` environment variable.", name,),
+                ))
+            }
+        };
+
+        env_vars.insert(name, value);
+    }
+
+    Ok(env_vars)
+}
+
+fn parse_u16_value(config: &Yaml, key: &str) -> Result<Option<u16>> {
+    match config[key] {
+        Yaml::Integer(i) => match u16::try_from(i) {
+            Ok(i) => Ok(Some(i)),
+            Err(_) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Invalid value for `launcher.{}` option: given value is \
+                    out of bounds, expected an unsigned 16-bit integer.",
+                    key
+                ),
+            )),
+        },
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Invalid value for `launcher.{}` option: unsigned 16-bit integer expected.",
+                key
+            ),
+        )),
+    }
+}
+
+fn parse_user(config: &Yaml) -> Result<Option<u16>> {
+    parse_u16_value(&config["launcher"], "user")
+}
+
+fn parse_fix_permissions(config: &Yaml) -> Result<bool> {
+    let fix_permissions = match config["launcher"]["fix_permissions"] {
+        Yaml::Boolean(fix_permissions) => fix_permissions,
+        Yaml::BadValue => false,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.fix_permissions`: boolean expected.",
+            ))
+        }
+    };
+
+    // Without a user or a group to hand the files over to, qemu runs with the launcher credentials
+    if fix_permissions && parse_user(config)?.is_none() && parse_group(config)?.is_none() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.fix_permissions`: requires `launcher.user` or \
+                `launcher.group` to be set.",
+        ));
+    }
+
+    Ok(fix_permissions)
+}
+
+fn parse_group(config: &Yaml) -> Result<Option<u16>> {
+    parse_u16_value(&config["launcher"], "group")
+}
+
+fn parse_priority(config: &Yaml) -> Result<Option<u8>> {
+    let priority = match config["launcher"]["priority"] {
+        Yaml::Integer(i) => match u8::try_from(i) {
+            Ok(i) => i,
+            Err(_) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Wrong value for `launcher.priority`: value out of bounds."),
+                ))
+            }
+        },
+        Yaml::BadValue => return Ok(None),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Failed to parse `launcher.priority`: an integer expected."),
+            ))
+        }
+    };
+
+    // Mirrors the ranges accepted by sched_setscheduler(2) for each policy
+    let (min, max) = match config["launcher"]["scheduler"].as_str() {
+        Some("fifo") | Some("rr") => (1, 99),
+        Some("batch") | Some("idle") | Some("other") => (0, 0),
+        _ => return Ok(Some(priority)),
+    };
+
+    if priority < min || priority > max {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Wrong value for `launcher.priority`: the `{}` scheduler allows priorities from {} to {}.",
+                config["launcher"]["scheduler"].as_str().unwrap(),
+                min,
+                max
+            ),
+        ));
+    }
+
+    Ok(Some(priority))
+}
+
+fn parse_scheduler(config: &Yaml) -> Result<Option<String>> {
+    match &config["launcher"]["scheduler"] {
+        Yaml::String(s) => match s.as_str() {
+            "batch" | "deadline" | "fifo" | "idle" | "other" | "rr" => Ok(Some(s.to_string())),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.scheduler`: Expected one of \
+                    `batch`, `deadline`, `fifo`, `idle`, `other` or `rr`."
+                ),
+            )),
+        },
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Failed to parse `launcher.scheduler`: string expected."),
+        )),
+    }
+}
+
+fn parse_deadline(config: &Yaml) -> Result<Option<Deadline>> {
+    match &config["launcher"]["deadline"] {
+        Yaml::Hash(_) => {}
+        Yaml::BadValue => return Ok(None),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.deadline`: hash expected.",
+            ))
+        }
+    }
+
+    if let Yaml::Integer(_) = config["launcher"]["priority"] {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.deadline`: can not be combined with `launcher.priority`.",
+        ));
+    }
+
+    match &config["launcher"]["scheduler"] {
+        Yaml::String(s) if s != "deadline" => return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.deadline`: can only be used with the `deadline` scheduler.",
+        )),
+        _ => {}
+    }
+
+    let deadline = Deadline {
+        deadline_us: parse_deadline_value(config, "deadline_us")?,
+        period_us: parse_deadline_value(config, "period_us")?,
+        runtime_us: parse_deadline_value(config, "runtime_us")?,
+    };
+
+    // The kernel refuses deadline parameters not satisfying runtime <= deadline <= period
+    if deadline.runtime_us > deadline.deadline_us || deadline.deadline_us > deadline.period_us {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.deadline`: `runtime_us` must not exceed `deadline_us` \
+            and `deadline_us` must not exceed `period_us`.",
+        ));
+    }
+
+    Ok(Some(deadline))
+}
+
+fn parse_deadline_value(config: &Yaml, key: &str) -> Result<u64> {
+    match config["launcher"]["deadline"][key] {
+        Yaml::Integer(i) if i > 0 => Ok(i as u64),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse `launcher.deadline.{}`: a positive integer expected.",
+                key
+            ),
+        )),
+    }
+}
+
+fn parse_event_sink(config: &Yaml) -> Result<Option<EventSink>> {
+    match &config["launcher"]["event_sink"] {
+        Yaml::String(sink) => match sink.split_once(':') {
+            Some(("fifo", path)) if path.len() > 0 => Ok(Some(EventSink::Fifo(path.to_owned()))),
+            Some(("file", path)) if path.len() > 0 => Ok(Some(EventSink::File(path.to_owned()))),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.event_sink`: `fifo:<path>` or `file:<path>` expected.",
+            )),
+        },
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.event_sink`: string expected.",
+        )),
+    }
+}
+
+fn parse_qmp_proxy(config: &Yaml) -> Result<Option<String>> {
+    let path = match &config["launcher"]["qmp_proxy"] {
+        Yaml::String(s) if s.len() > 0 => s,
+        Yaml::BadValue => return Ok(None),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.qmp_proxy`: a non-empty path expected.",
+            ))
+        }
+    };
+
+    if config["launcher"]["qmp_socket"].as_str() == Some(path.as_str()) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.qmp_proxy`: the path is already used by `launcher.qmp_socket`.",
+        ));
+    }
+
+    Ok(Some(path.to_string()))
+}
+
+fn parse_qmp_socket(config: &Yaml) -> Result<Option<String>> {
+    match &config["launcher"]["qmp_socket"] {
+        Yaml::String(s) if s.len() > 0 && !s.contains(',') => Ok(Some(s.to_string())),
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.qmp_socket`: a non-empty path without commas expected.",
+        )),
+    }
+}
+
+fn parse_qmp_startup_commands(config: &Yaml) -> Result<Vec<JsonValue>> {
+    let commands = match &config["launcher"]["qmp_startup_commands"] {
+        Yaml::Array(commands) => commands,
+        Yaml::BadValue => return Ok(vec![]),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.qmp_startup_commands`: an array expected.",
+            ))
+        }
+    };
+
+    let mut result = vec![];
+    for (index, command) in commands.iter().enumerate() {
+        match (&command["execute"], yaml_to_json(command)) {
+            (Yaml::String(name), Some(json)) if name.len() > 0 => result.push(json),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Failed to parse `launcher.qmp_startup_commands`: the command #{} \
+                        must be a hash with the `execute` command name.",
+                        index
+                    ),
+                ))
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+fn yaml_to_json(value: &Yaml) -> Option<JsonValue> {
+    Some(match value {
+        Yaml::Hash(hash) => {
+            let mut object = JsonValue::new_object();
+            for (key, value) in hash {
+                object[key.as_str()?] = yaml_to_json(value)?;
+            }
+            object
+        }
+        Yaml::Array(array) => JsonValue::Array(
+            array
+                .iter()
+                .map(yaml_to_json)
+                .collect::<Option<Vec<JsonValue>>>()?,
+        ),
+        Yaml::String(s) => JsonValue::from(s.as_str()),
+        Yaml::Integer(i) => JsonValue::from(*i),
+        Yaml::Real(_) => JsonValue::from(value.as_f64()?),
+        Yaml::Boolean(b) => JsonValue::from(*b),
+        Yaml::Null => JsonValue::Null,
+        _ => return None,
+    })
+}
+
+fn parse_qmp_format(config: &Yaml) -> Result<QmpFormat> {
+    match &config["launcher"]["qmp_format"] {
+        Yaml::String(s) => match s.as_str() {
+            "chardev" => Ok(QmpFormat::Chardev),
+            "qmp" => Ok(QmpFormat::Qmp),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.qmp_format`: Expected one of `chardev` or `qmp`.",
+            )),
+        },
+        Yaml::BadValue => Ok(QmpFormat::Qmp),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.qmp_format`: string expected.",
+        )),
+    }
+}
+
+fn parse_qmp_position(config: &Yaml) -> Result<QmpPosition> {
+    match &config["launcher"]["qmp_position"] {
+        Yaml::String(s) => match s.as_str() {
+            "first" => Ok(QmpPosition::First),
+            "last" => Ok(QmpPosition::Last),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.qmp_position`: Expected one of `first` or `last`.",
+            )),
+        },
+        Yaml::BadValue => Ok(QmpPosition::Last),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.qmp_position`: string expected.",
+        )),
+    }
+}
+
+fn parse_qmp_log(config: &Yaml) -> Result<Option<QmpLog>> {
+    match &config["launcher"]["qmp_log"] {
+        Yaml::String(s) => match s.as_str() {
+            "file" => Ok(Some(QmpLog::File)),
+            "main" => Ok(Some(QmpLog::Main)),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.qmp_log`: Expected one of `file` or `main`.",
+            )),
+        },
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.qmp_log`: string expected.",
+        )),
+    }
+}
+
+fn as_guest_action(value: &str) -> Option<GuestShutdown> {
+    match value {
+        "ignore" => Some(GuestShutdown::Ignore),
+        "quit" => Some(GuestShutdown::Quit),
+        "restart" => Some(GuestShutdown::Restart),
+        _ => None,
+    }
+}
+
+fn parse_on_guest_shutdown(config: &Yaml) -> Result<Option<GuestShutdown>> {
+    match &config["launcher"]["on_guest_shutdown"] {
+        Yaml::String(s) => match as_guest_action(s) {
+            Some(action) => Ok(Some(action)),
+            None => Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.on_guest_shutdown`: Expected one of `quit`, `restart` or `ignore`.",
+            )),
+        },
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.on_guest_shutdown`: string expected.",
+        )),
+    }
+}
+
+fn parse_watchdog(config: &Yaml) -> Result<Option<Watchdog>> {
+    let watchdog = &config["launcher"]["watchdog"];
+    match watchdog {
+        Yaml::String(_) => {}
+        Yaml::Hash(keys) => {
+            for key in keys.keys() {
+                match key.as_str() {
+                    Some("action") | Some("model") => {}
+                    _ => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!(
+                                "Failed to parse `launcher.watchdog`: unknown key `{}`, \
+                                    `action` or `model` expected.",
+                                key.as_str().unwrap_or("?")
+                            ),
+                        ))
+                    }
+                }
+            }
+        }
+        Yaml::BadValue => return Ok(None),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.watchdog`: a string or a hash expected.",
+            ))
+        }
+    }
+
+    // microvm has no PCI bus by default and s390x only knows its diagnose based watchdog
+    let (default, models, expected): (_, &[&str], _) = match parse_machine_family(config)? {
+        MachineFamily::Microvm => (
+            "ib700",
+            &["i6300esb", "ib700"],
+            "one of `i6300esb` or `ib700`",
+        ),
+        MachineFamily::Pc => (
+            "i6300esb",
+            &["i6300esb", "ib700"],
+            "one of `i6300esb` or `ib700`",
+        ),
+        MachineFamily::S390Ccw => ("diag288", &["diag288"], "`diag288` on s390x machines"),
+    };
+
+    // The short form only names the model
+    let model = match (watchdog, &watchdog["model"]) {
+        (Yaml::String(model), _) | (_, Yaml::String(model)) => model.as_str(),
+        (_, Yaml::BadValue) => default,
+        _ => "",
+    };
+    if !models.contains(&model) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse `launcher.watchdog.model`: Expected {}.",
+                expected
+            ),
+        ));
+    }
+
+    let action = match &watchdog["action"] {
+        Yaml::String(action) => as_guest_action(action),
+        Yaml::BadValue => Some(GuestShutdown::Restart),
+        _ => None,
+    };
+    let action = match action {
+        Some(action) => action,
+        None => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.watchdog.action`: Expected one of `quit`, `restart` or `ignore`.",
+            ))
+        }
+    };
+
+    if has_qemu_option(config, "watchdog-action") {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.watchdog`: can not be combined with the `watchdog-action` \
+                option in the `qemu` section.",
+        ));
+    }
+
+    Ok(Some(Watchdog {
+        action,
+        model: model.to_owned(),
+    }))
+}
+
+fn parse_storage_daemons(config: &Yaml) -> Result<Vec<StorageDaemon>> {
+    let entries = match &config["launcher"]["storage_daemons"] {
+        Yaml::Array(entries) => entries,
+        Yaml::BadValue => return Ok(vec![]),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.storage_daemons`: an array expected.",
+            ))
+        }
+    };
+
+    // The host CPUs of vCPUs are reserved for them, the daemons run on the remaining ones
+    let pinned = pinned_host_cpus(
+        &parse_cpu_pinning(config)?,
+        &parse_cpu_pinning_by_index(config)?,
+    );
+
+    let mut daemons: Vec<StorageDaemon> = vec![];
+
+    for (position, entry) in entries.iter().enumerate() {
+        let daemon = parse_storage_daemon(entry, position)?;
+
+        if daemons.iter().any(|d| d.id == daemon.id) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Wrong value for `launcher.storage_daemons.{}.id`: \
+                        the `{}` storage daemon is already declared.",
+                    position, daemon.id
+                ),
+            ));
+        }
+
+        if daemons.iter().any(|d| d.socket == daemon.socket) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Wrong value for `launcher.storage_daemons.{}.socket`: \
+                        the `{}` socket is already used by another storage daemon.",
+                    position, daemon.socket
+                ),
+            ));
+        }
+
+        for export in &daemon.exports {
+            if daemons
+                .iter()
+                .flat_map(|d| d.exports.iter())
+                .any(|e| e.node == export.node)
+            {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Wrong value for `launcher.storage_daemons.{}.exports`: \
+                            the `{}` node is already exported by another storage daemon.",
+                        position, export.node
+                    ),
+                ));
+            }
+        }
+
+        if let Some(cpu) = daemon.host_cpus.iter().find(|cpu| pinned.contains(cpu)) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Wrong value for `launcher.storage_daemons.{}.host_cpus`: \
+                        the host CPU `{}` is reserved for vCPU pinning.",
+                    position, cpu
+                ),
+            ));
+        }
+
+        daemons.push(daemon);
+    }
+
+    Ok(daemons)
+}
+
+fn parse_storage_daemon(entry: &Yaml, position: usize) -> Result<StorageDaemon> {
+    match entry {
+        Yaml::Hash(_) => {}
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.storage_daemons.{}`: a hash expected.",
+                    position
+                ),
+            ))
+        }
+    }
+
+    let error = |key: &str, expected: &str| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse `launcher.storage_daemons.{}.{}`: {} expected.",
+                position, key, expected
+            ),
+        )
+    };
+
+    let id = match entry["id"].as_str() {
+        Some(id)
+            if id.len() > 0
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') =>
+        {
+            id.to_string()
+        }
+        _ => return Err(error("id", "a name of letters, digits, `-` and `_`")),
+    };
+
+    let binary = match &entry["binary"] {
+        Yaml::String(s) if s.len() > 0 => s.to_string(),
+        Yaml::BadValue => String::from("qemu-storage-daemon"),
+        _ => return Err(error("binary", "a non-empty path")),
+    };
+
+    let socket = match &entry["socket"] {
+        Yaml::String(s) if s.len() > 0 && !s.contains(',') => s.to_string(),
+        _ => return Err(error("socket", "a non-empty path without commas")),
+    };
+
+    let exports = match &entry["exports"] {
+        Yaml::Array(exports) if exports.len() > 0 => exports,
+        _ => return Err(error("exports", "a non-empty array")),
+    };
+
+    let mut parsed_exports: Vec<StorageExport> = vec![];
+    for (index, export) in exports.iter().enumerate() {
+        let key = |name: &str| format!("exports.{}.{}", index, name);
+
+        let node = match export["node"].as_str() {
+            Some(node) if node.len() > 0 && !node.contains(',') && !node.contains('=') => {
+                node.to_string()
+            }
+            _ => return Err(error(&key("node"), "a node name")),
+        };
+
+        let blockdev = match &export["blockdev"] {
+            Yaml::String(s) if s.len() > 0 => s.to_string(),
+            Yaml::Array(values) => parse_parameter_value("blockdev", values)?,
+            _ => return Err(error(&key("blockdev"), "a string or an array")),
+        };
+
+        let writable = match export["writable"] {
+            Yaml::Boolean(writable) => writable,
+            Yaml::BadValue => true,
+            _ => return Err(error(&key("writable"), "a boolean")),
+        };
+
+        if parsed_exports.iter().any(|e| e.node == node) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Wrong value for `launcher.storage_daemons.{}.{}`: \
+                        the `{}` node is already exported.",
+                    position,
+                    key("node"),
+                    node
+                ),
+            ));
+        }
+
+        parsed_exports.push(StorageExport {
+            blockdev,
+            node,
+            writable,
+        });
+    }
+
+    let host_cpus = match &entry["host_cpus"] {
+        Yaml::Array(cpus) if cpus.len() > 0 => {
+            let mut host_cpus = vec![];
+            for cpu in cpus {
+                match cpu {
+                    Yaml::Integer(cpu) if *cpu >= 0 => host_cpus.push(*cpu as usize),
+                    _ => return Err(error("host_cpus", "a non-empty array of host CPU IDs")),
+                }
+            }
+            host_cpus
+        }
+        Yaml::BadValue => vec![],
+        _ => return Err(error("host_cpus", "a non-empty array of host CPU IDs")),
+    };
+
+    // The policies `chrt` can apply to every thread of a running process
+    let scheduler = match entry["scheduler"].as_str() {
+        Some(s) if ["batch", "fifo", "idle", "other", "rr"].contains(&s) => Some(s.to_string()),
+        None if entry["scheduler"].is_badvalue() => None,
+        _ => {
+            return Err(error(
+                "scheduler",
+                "one of `batch`, `fifo`, `idle`, `other` or `rr`",
+            ))
+        }
+    };
+
+    let priority = match (&entry["priority"], scheduler.as_deref()) {
+        (Yaml::Integer(p), Some("fifo")) | (Yaml::Integer(p), Some("rr"))
+            if *p >= 1 && *p <= 99 =>
+        {
+            Some(*p as u8)
+        }
+        (_, Some("fifo")) | (_, Some("rr")) => {
+            return Err(error("priority", "a priority from 1 to 99"))
+        }
+        (Yaml::BadValue, _) => None,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Wrong value for `launcher.storage_daemons.{}.priority`: \
+                        only the `fifo` and `rr` schedulers take a priority.",
+                    position
+                ),
+            ))
+        }
+    };
+
+    Ok(StorageDaemon {
+        binary,
+        exports: parsed_exports,
+        host_cpus,
+        id,
+        priority,
+        scheduler,
+        socket,
+    })
+}
+
+fn parse_thermal_throttle(config: &Yaml) -> Result<Option<ThermalThrottle>> {
+    let policy = &config["launcher"]["thermal_throttle"];
+
+    match policy {
+        Yaml::Hash(_) => {}
+        Yaml::BadValue => return Ok(None),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.thermal_throttle`: a hash expected.",
+            ))
+        }
+    }
+
+    let zone = match policy["zone"].as_str() {
+        Some(zone) if zone.len() > 0 && !zone.contains('/') => zone.to_string(),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.thermal_throttle.zone`: \
+                    a thermal zone name, e.g. `thermal_zone0`, expected.",
+            ))
+        }
+    };
+
+    let entries = match &policy["thresholds"] {
+        Yaml::Array(entries) if entries.len() > 0 => entries,
+        _ => return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.thermal_throttle.thresholds`: a non-empty array expected.",
+        )),
+    };
+
+    let mut thresholds: Vec<(u32, u8)> = vec![];
+
+    for (position, entry) in entries.iter().enumerate() {
+        let temperature = match entry["temperature"] {
+            Yaml::Integer(t) if t > 0 && t <= 150 => t as u32,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Failed to parse `launcher.thermal_throttle.thresholds.{}.temperature`: \
+                            degrees Celsius from 1 to 150 expected.",
+                        position
+                    ),
+                ))
+            }
+        };
+
+        let throttle = match entry["throttle"] {
+            Yaml::Integer(p) if p > 0 && p <= 100 => p as u8,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Failed to parse `launcher.thermal_throttle.thresholds.{}.throttle`: \
+                            a percentage from 1 to 100 expected.",
+                        position
+                    ),
+                ))
+            }
+        };
+
+        if thresholds.iter().any(|(t, _)| *t == temperature) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Wrong value for `launcher.thermal_throttle.thresholds.{}.temperature`: \
+                        the {} degrees threshold is already declared.",
+                    position, temperature
+                ),
+            ));
+        }
+
+        thresholds.push((temperature, throttle));
+    }
+
+    // The vCPUs are throttled over a QMP connection of its own, the private channel is taken
+    if config["launcher"]["qmp_socket"].is_badvalue()
+        && config["launcher"]["qmp_proxy"].is_badvalue()
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.thermal_throttle`: either `launcher.qmp_socket` \
+                or `launcher.qmp_proxy` is required to throttle the vCPUs.",
+        ));
+    }
+
+    Ok(Some(ThermalThrottle { thresholds, zone }))
+}
+
+fn parse_thp(config: &Yaml) -> Result<Option<Thp>> {
+    match &config["launcher"]["thp"] {
+        Yaml::String(s) => match s.as_str() {
+            "always" => Ok(Some(Thp::Always)),
+            "madvise" => Ok(Some(Thp::Madvise)),
+            "never" => Ok(Some(Thp::Never)),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.thp`: Expected one of `always`, `madvise` or `never`.",
+            )),
+        },
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.thp`: string expected.",
+        )),
+    }
+}
+
+fn parse_rlimit_memlock(config: &Yaml) -> Result<bool> {
+    match &config["launcher"]["rlimit_memlock"] {
+        Yaml::Boolean(b) => Ok(*b),
+        Yaml::BadValue => Ok(false),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Failed to parse `launcher.rlimit_memlock`: boolean expected."),
+        )),
+    }
+}
+
+fn as_u64(id: &Yaml) -> Option<usize> {
+    match id.as_i64() {
+        Some(i) => match usize::try_from(i) {
+            Ok(i) => Some(i),
+            Err(_) => None,
+        },
+        None => None,
+    }
+}
+
+// Dies and clusters only add a level to `launcher.vcpu_pinning` when `-smp` asks for more than one
+// of them, otherwise qemu reports every vCPU in die and cluster 0
+fn vcpu_pinning_levels(config: &Yaml) -> Result<Vec<&'static str>> {
+    let smp = parse_command_line(config)?
+        .into_iter()
+        .filter_map(|argument| match argument {
+            Argument::Parameter(name, value) if name == "smp" => Some(value),
+            _ => None,
+        })
+        .last()
+        .unwrap_or_default();
+
+    let mut levels = vec!["socket"];
+    for level in &["die", "cluster"] {
+        let count = get_netdev_option(&smp, &format!("{}s", level))
+            .and_then(|count| count.parse::<usize>().ok())
+            .unwrap_or(1);
+        if count > 1 {
+            levels.push(level);
+        }
+    }
+    levels.extend(&["core", "thread"]);
+
+    Ok(levels)
+}
+
+fn vcpu_pinning_key(ids: &[usize]) -> String {
+    let mut key = String::from("launcher.vcpu_pinning");
+    for id in ids {
+        key.push_str(&format!(".{}", id));
+    }
+
+    key
+}
+
+fn parse_cpu_pinning_level(levels: &[&str], ids: &[usize], entries: &Hash) -> Result<Vec<VcpuPin>> {
+    let level = levels[ids.len()];
+    let mut cpu_pinning = vec![];
+
+    for (key, value) in entries {
+        let mut ids = ids.to_vec();
+        match as_u64(key) {
+            Some(id) => ids.push(id),
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Failed to parse `{}`: the {} ID must be {}.",
+                        vcpu_pinning_key(&ids),
+                        level,
+                        match level {
+                            "thread" => "a positive integer",
+                            _ => "an integer greater or equal to zero",
+                        }
+                    ),
+                ))
+            }
+        }
+
+        if ids.len() < levels.len() {
+            match value {
+                Yaml::Hash(entries) => {
+                    cpu_pinning.append(&mut parse_cpu_pinning_level(levels, &ids, entries)?)
+                }
+                _ => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "Failed to parse `{}`: a hash expected.",
+                            vcpu_pinning_key(&ids)
+                        ),
+                    ))
+                }
+            }
+            continue;
+        }
+
+        let mut pin = VcpuPin {
+            cluster: None,
+            core: 0,
+            die: None,
+            host_cpu: match as_u64(value) {
+                Some(id) => id,
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "Failed to parse `{}`: \
+                                the host core ID must be an integer greater or equal to zero.",
+                            vcpu_pinning_key(&ids)
+                        ),
+                    ))
+                }
+            },
+            socket: 0,
+            thread: 0,
+        };
+        for (level, id) in levels.iter().zip(ids) {
+            match *level {
+                "socket" => pin.socket = id,
+                "die" => pin.die = Some(id),
+                "cluster" => pin.cluster = Some(id),
+                "core" => pin.core = id,
+                _ => pin.thread = id,
+            }
+        }
+        cpu_pinning.push(pin);
+    }
+
+    Ok(cpu_pinning)
+}
+
+fn parse_cpu_pinning(config: &Yaml) -> Result<Vec<VcpuPin>> {
+    match &config["launcher"]["vcpu_pinning"] {
+        Yaml::Hash(sockets) => parse_cpu_pinning_level(&vcpu_pinning_levels(config)?, &[], sockets),
+        Yaml::BadValue => Ok(vec![]),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.vcpu_pinning` \
+                        configuration: a hash is expected."
+                ),
+            ))
+        }
+    }
+}
+
+fn parse_uclamp(config: &Yaml) -> Result<HashMap<usize, UtilClamp>> {
+    let cpus = match &config["launcher"]["uclamp"] {
+        Yaml::Hash(cpus) => cpus,
+        Yaml::BadValue => return Ok(HashMap::new()),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.uclamp`: a hash expected.",
+            ))
+        }
+    };
+
+    let pinned_cpus = pinned_host_cpus(
+        &parse_cpu_pinning(config)?,
+        &parse_cpu_pinning_by_index(config)?,
+    );
+    let mut uclamp = HashMap::new();
+
+    for (cpu, clamp) in cpus {
+        let cpu = match as_u64(cpu) {
+            Some(cpu) => cpu,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Failed to parse `launcher.uclamp`: \
+                        the host CPU ID must be an integer greater or equal to zero.",
+                ))
+            }
+        };
+
+        if !pinned_cpus.contains(&cpu) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Wrong value for `launcher.uclamp.{}`: \
+                        the host CPU is not used in vCPU pinning.",
+                    cpu
+                ),
+            ));
+        }
+
+        match clamp {
+            Yaml::Hash(_) => {}
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Failed to parse `launcher.uclamp.{}`: a hash expected.",
+                        cpu
+                    ),
+                ))
+            }
+        }
+
+        let clamp = UtilClamp {
+            max: parse_uclamp_value(clamp, cpu, "max")?,
+            min: parse_uclamp_value(clamp, cpu, "min")?,
+        };
+
+        if let (Some(min), Some(max)) = (clamp.min, clamp.max) {
+            if min > max {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Wrong value for `launcher.uclamp.{}`: `min` must not exceed `max`.",
+                        cpu
+                    ),
+                ));
+            }
+        }
+
+        uclamp.insert(cpu, clamp);
+    }
+
+    Ok(uclamp)
+}
+
+fn parse_cache_allocation(config: &Yaml) -> Result<Option<CacheAllocation>> {
+    let allocation = match &config["launcher"]["cache_allocation"] {
+        Yaml::Hash(allocation) => allocation,
+        Yaml::BadValue => return Ok(None),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.cache_allocation`: a hash expected.",
+            ))
+        }
+    };
+
+    for key in allocation.keys() {
+        match key.as_str() {
+            Some("l3") | Some("mb") => {}
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Failed to parse `launcher.cache_allocation`: only the `l3` and `mb` keys are allowed.",
+                ))
+            }
+        }
+    }
+
+    // The resctrl group is assigned the pinned host CPUs, so nothing else would use the allocation
+    if config["launcher"]["vcpu_pinning"].is_badvalue()
+        && config["launcher"]["vcpu_pinning_by_index"].is_badvalue()
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.cache_allocation`: requires vCPU pinning to be configured.",
+        ));
+    }
+
+    let l3 = parse_cache_domains(config, "l3", |mask| match mask {
+        // Cache allocation requires a contiguous set of cache ways
+        Yaml::String(mask) => match u64::from_str_radix(mask, 16) {
+            Ok(bits) if is_contiguous_mask(bits) => Some(mask.to_lowercase()),
+            _ => None,
+        },
+        _ => None,
+    })?;
+    let mb = parse_cache_domains(config, "mb", |percent| match percent {
+        Yaml::Integer(percent) if *percent > 0 && *percent <= 100 => Some(*percent as u8),
+        _ => None,
+    })?;
+
+    if l3.len() < 1 && mb.len() < 1 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.cache_allocation`: at least one of `l3` or `mb` expected.",
+        ));
+    }
+
+    Ok(Some(CacheAllocation { l3, mb }))
+}
+
+fn is_contiguous_mask(bits: u64) -> bool {
+    if bits == 0 {
+        return false;
+    }
+
+    let shifted = bits >> bits.trailing_zeros();
+
+    shifted & shifted.wrapping_add(1) == 0
+}
+
+fn parse_cache_domains<T>(
+    config: &Yaml,
+    key: &str,
+    parse: impl Fn(&Yaml) -> Option<T>,
+) -> Result<BTreeMap<usize, T>> {
+    let expected = match key {
+        "l3" => "a hexadecimal mask of contiguous cache ways, e.g. `ff0`",
+        _ => "a memory bandwidth percentage from 1 to 100",
+    };
+
+    let domains = match &config["launcher"]["cache_allocation"][key] {
+        Yaml::Hash(domains) => domains,
+        Yaml::BadValue => return Ok(BTreeMap::new()),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.cache_allocation.{}`: \
+                        a hash of cache IDs expected.",
+                    key
+                ),
+            ))
+        }
+    };
+
+    let mut parsed = BTreeMap::new();
+
+    for (id, value) in domains {
+        let id = match as_u64(id) {
+            Some(id) => id,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Failed to parse `launcher.cache_allocation.{}`: \
+                            the cache ID must be an integer greater or equal to zero.",
+                        key
+                    ),
+                ))
+            }
+        };
+
+        match parse(value) {
+            Some(value) => parsed.insert(id, value),
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Wrong value for `launcher.cache_allocation.{}.{}`: {} expected.",
+                        key, id, expected
+                    ),
+                ))
+            }
+        };
+    }
+
+    Ok(parsed)
+}
+
+fn parse_uclamp_value(clamp: &Yaml, cpu: usize, key: &str) -> Result<Option<u8>> {
+    match clamp[key] {
+        Yaml::Integer(i) if i >= 0 && i <= 100 => Ok(Some(i as u8)),
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse `launcher.uclamp.{}.{}`: a percentage from 0 to 100 expected.",
+                cpu, key
+            ),
+        )),
+    }
+}
+
+fn parse_cpu_pinning_by_index(config: &Yaml) -> Result<Vec<(usize, usize)>> {
+    let indices = match &config["launcher"]["vcpu_pinning_by_index"] {
+        Yaml::Hash(indices) => indices,
+        Yaml::BadValue => return Ok(vec![]),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.vcpu_pinning_by_index`: a hash expected.",
+            ))
+        }
+    };
+
+    if let Yaml::Hash(_) = config["launcher"]["vcpu_pinning"] {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.vcpu_pinning_by_index`: \
+                can not be combined with `launcher.vcpu_pinning`.",
+        ));
+    }
+
+    let mut cpu_pinning = vec![];
+
+    for (index, host) in indices {
+        let index = match as_u64(index) {
+            Some(index) => index,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Failed to parse `launcher.vcpu_pinning_by_index`: \
+                        the vCPU index must be an integer greater or equal to zero.",
+                ))
+            }
+        };
+
+        match as_u64(host) {
+            Some(host_id) => cpu_pinning.push((index, host_id)),
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Failed to parse `launcher.vcpu_pinning_by_index.{}`: \
+                            the host core ID must be an integer greater or equal to zero.",
+                        index
+                    ),
+                ))
+            }
+        }
+    }
+
+    Ok(cpu_pinning)
+}
+
+fn pinned_host_cpus(
+    cpu_pinning: &[VcpuPin],
+    cpu_pinning_by_index: &[(usize, usize)],
+) -> Vec<usize> {
+    cpu_pinning
+        .iter()
+        .map(|pin| pin.host_cpu)
+        .chain(cpu_pinning_by_index.iter().map(|pin| pin.1))
+        .collect()
+}
+
+fn parse_clock(config: &Yaml) -> Result<Option<Clock>> {
+    let clock = &config["launcher"]["clock"];
+    let (base, tsc_frequency) = match clock {
+        Yaml::String(base) => (base.as_str(), None),
+        Yaml::Hash(_) => {
+            let base = match &clock["base"] {
+                Yaml::String(base) => base.as_str(),
+                Yaml::BadValue => "utc",
+                _ => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "Failed to parse `launcher.clock.base`: string expected.",
+                    ))
+                }
+            };
+
+            let tsc_frequency = match clock["tsc_frequency"] {
+                Yaml::Integer(frequency) if frequency > 0 => Some(frequency as u64),
+                Yaml::BadValue => None,
+                _ => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "Failed to parse `launcher.clock.tsc_frequency`: \
+                            a positive integer in Hz expected.",
+                    ))
+                }
+            };
+
+            (base, tsc_frequency)
+        }
+        Yaml::BadValue => return Ok(None),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.clock`: a string or a hash expected.",
+            ))
+        }
+    };
+
+    if base != "utc" && base != "localtime" {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.clock`: the clock base must be either `utc` or `localtime`.",
+        ));
+    }
+
+    // The options are built around the x86 RTC, PIT and kvmclock
+    if parse_machine_family(config)? == MachineFamily::S390Ccw {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.clock`: not supported by s390x `s390-ccw-virtio` machines.",
+        ));
+    }
+
+    for option in &["rtc", "no-hpet"] {
+        if has_qemu_option(config, option) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.clock`: can not be combined with the `{}` option \
+                    in the `qemu` section.",
+                    option
+                ),
+            ));
+        }
+    }
+
+    // Clock related CPU features are appended to the existing `-cpu` option
+    if tsc_frequency.is_some() && !has_qemu_option(config, "cpu") {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.clock.tsc_frequency`: \
+                requires the `cpu` option in the `qemu` section.",
+        ));
+    }
+
+    Ok(Some(Clock {
+        base: base.to_owned(),
+        tsc_frequency,
+    }))
+}
+
+fn parse_audio(config: &Yaml) -> Result<Option<Audio>> {
+    let audio = &config["launcher"]["audio"];
+    let backend = match audio {
+        Yaml::String(backend) => backend.as_str(),
+        Yaml::Hash(_) => audio["backend"].as_str().unwrap_or(""),
+        Yaml::BadValue => return Ok(None),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.audio`: a string or a hash expected.",
+            ))
+        }
+    };
+
+    if !["alsa", "pipewire", "pulseaudio"].contains(&backend) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.audio.backend`: \
+                one of `alsa`, `pipewire` or `pulseaudio` expected.",
+        ));
+    }
+
+    let string = |key: &str| match &audio[key] {
+        Yaml::String(value) if value.len() > 0 => Ok(Some(value.clone())),
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse `launcher.audio.{}`: a non-empty string expected.",
+                key
+            ),
+        )),
+    };
+
+    let id = string("id")?.unwrap_or_else(|| String::from("audio0"));
+    let server = string("server")?;
+
+    // Sound servers listen in the runtime directory of the desktop user qemu runs as
+    let runtime_dir = match (string("runtime_dir")?, parse_user(config)?) {
+        (_, _) if backend == "alsa" => None,
+        (Some(runtime_dir), _) if runtime_dir.starts_with('/') => Some(runtime_dir),
+        (Some(_), _) => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.audio.runtime_dir`: an absolute path expected.",
+            ))
+        }
+        (None, Some(user)) => Some(format!("/run/user/{}", user)),
+        (None, None) => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.audio`: the `{}` backend requires either \
+                        `launcher.audio.runtime_dir` or `launcher.user` to be set.",
+                    backend
+                ),
+            ))
+        }
+    };
+
+    if has_qemu_option(config, "audiodev") {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.audio`: can not be combined with the `audiodev` option \
+                in the `qemu` section.",
+        ));
+    }
+
+    Ok(Some(Audio {
+        backend: backend.to_owned(),
+        id,
+        runtime_dir,
+        server,
+    }))
+}
+
+fn parse_serial_log(config: &Yaml) -> Result<Option<SerialLog>> {
+    let error = |key: &str, expected: &str| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse `launcher.serial_log{}`: {} expected.",
+                key, expected
+            ),
+        )
+    };
+    let path = |value: &Yaml, key: &str| match value {
+        Yaml::String(s) if s.len() > 0 && !s.contains(',') => Ok(s.to_string()),
+        _ => Err(error(key, "a non-empty path without commas")),
+    };
+
+    let log = match &config["launcher"]["serial_log"] {
+        Yaml::BadValue => return Ok(None),
+        Yaml::String(_) => SerialLog {
+            keep: 5,
+            max_size_mb: 10,
+            path: path(&config["launcher"]["serial_log"], "")?,
+            socket: None,
+        },
+        Yaml::Hash(log) => {
+            for key in log.keys() {
+                match key.as_str() {
+                    Some("keep") | Some("max_size_mb") | Some("path") | Some("socket") => {}
+                    _ => {
+                        return Err(error(
+                            "",
+                            "a hash with the `path`, `socket`, `max_size_mb` and `keep` keys",
+                        ))
+                    }
+                }
+            }
+
+            let log = &config["launcher"]["serial_log"];
+
+            SerialLog {
+                keep: match log["keep"] {
+                    Yaml::Integer(keep) if keep >= 0 && keep <= 100 => keep as u32,
+                    Yaml::BadValue => 5,
+                    _ => return Err(error(".keep", "a number of rotated logs from 0 to 100")),
+                },
+                max_size_mb: match log["max_size_mb"] {
+                    Yaml::Integer(size) if size >= 1 => size as u64,
+                    Yaml::BadValue => 10,
+                    _ => return Err(error(".max_size_mb", "a positive number of megabytes")),
+                },
+                path: path(&log["path"], ".path")?,
+                socket: match &log["socket"] {
+                    Yaml::BadValue => None,
+                    socket => Some(path(socket, ".socket")?),
+                },
+            }
+        }
+        _ => return Err(error("", "a path or a hash")),
+    };
+
+    // A second serial port would not be the console the guest kernel writes its panics to
+    if has_qemu_option(config, "serial") {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.serial_log`: can not be combined with the `serial` option \
+                in the `qemu` section.",
+        ));
+    }
+
+    Ok(Some(log))
+}
+
+fn parse_smbios(config: &Yaml) -> Result<Option<Smbios>> {
+    let smbios = match &config["launcher"]["smbios"] {
+        Yaml::Hash(smbios) => smbios,
+        Yaml::BadValue => return Ok(None),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.smbios`: a hash expected.",
+            ))
+        }
+    };
+
+    if smbios.len() < 1 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.smbios`: at least one field expected.",
+        ));
+    }
+
+    if parse_machine_family(config)? == MachineFamily::S390Ccw {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.smbios`: not supported by s390x `s390-ccw-virtio` machines.",
+        ));
+    }
+
+    for key in smbios.keys() {
+        match key.as_str() {
+            Some("manufacturer") | Some("product") | Some("serial") | Some("uuid")
+            | Some("version") => {}
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                    "Failed to parse `launcher.smbios`: unknown key `{}`, one of `manufacturer`, \
+                            `product`, `serial`, `uuid` or `version` expected.",
+                    key.as_str().unwrap_or("?")
+                ),
+                ))
+            }
+        }
+    }
+
+    // Firmware commonly truncates or rejects longer strings, so they are not silently cut by the guest
+    let string = |key: &str| match &config["launcher"]["smbios"][key] {
+        Yaml::String(value)
+            if value.len() > 0
+                && value.len() <= 64
+                && value.chars().all(|c| c.is_ascii() && !c.is_ascii_control()) =>
+        {
+            Ok(Some(value.clone()))
+        }
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse `launcher.smbios.{}`: a string of 1 to 64 printable ASCII \
+                    characters expected.",
+                key
+            ),
+        )),
+    };
+
+    let uuid = match &config["launcher"]["smbios"]["uuid"] {
+        Yaml::String(uuid) if uuid == "auto" || is_uuid(uuid) => Some(uuid.to_lowercase()),
+        Yaml::BadValue => None,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.smbios.uuid`: `auto` or a UUID, \
+                    e.g. `4c4c4544-0042-3510-8052-b4c04f384d32`, expected.",
+            ))
+        }
+    };
+
+    if uuid.is_some() && has_qemu_option(config, "uuid") {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.smbios.uuid`: can not be combined with the `uuid` option \
+                in the `qemu` section.",
+        ));
+    }
+
+    Ok(Some(Smbios {
+        manufacturer: string("manufacturer")?,
+        product: string("product")?,
+        serial: string("serial")?,
+        uuid,
+        version: string("version")?,
+    }))
+}
+
+fn is_uuid(value: &str) -> bool {
+    let groups: Vec<&str> = value.split('-').collect();
+
+    groups
+        .iter()
+        .map(|group| group.len())
+        .eq([8, 4, 4, 4, 12].iter().cloned())
+        && groups
+            .iter()
+            .all(|group| group.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn parse_display_env(config: &Yaml) -> Result<Vec<(String, String)>> {
+    let env = match &config["launcher"]["display"]["env"] {
+        Yaml::Hash(env) => env,
+        Yaml::BadValue => return Ok(vec![]),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.display.env`: a hash expected.",
+            ))
+        }
+    };
+
+    let names = [
+        ("display", "DISPLAY"),
+        ("wayland_display", "WAYLAND_DISPLAY"),
+        ("xauthority", "XAUTHORITY"),
+    ];
+
+    let mut vars = vec![];
+
+    for (key, value) in env {
+        let name = match names.iter().find(|(name, _)| key.as_str() == Some(*name)) {
+            Some((_, name)) => name,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Failed to parse `launcher.display.env`: only the `display`, \
+                        `wayland_display` and `xauthority` keys are supported.",
+                ))
+            }
+        };
+
+        match value.as_str() {
+            Some(value) if value.len() > 0 => vars.push((name.to_string(), value.to_owned())),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Failed to parse `launcher.display.env.{}`: a non-empty string expected.",
+                        key.as_str().unwrap_or_default()
+                    ),
+                ))
+            }
+        }
+    }
+
+    Ok(vars)
+}
+
+fn has_qemu_option(config: &Yaml, name: &str) -> bool {
+    match &config["qemu"] {
+        Yaml::Array(options) => options.iter().any(|option| match option {
+            Yaml::String(flag) if flag.starts_with('-') => split_raw_option(flag).0 == name,
+            Yaml::String(flag) => flag == name,
+            Yaml::Hash(parameter) => parameter.contains_key(&Yaml::String(name.to_owned())),
+            _ => false,
+        }),
+        _ => false,
+    }
+}
+
+fn parse_network(config: &Yaml) -> Result<Vec<NetworkInterface>> {
+    let interfaces = match &config["launcher"]["network"] {
+        Yaml::Array(interfaces) => interfaces,
+        Yaml::BadValue => return Ok(vec![]),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.network`: an array expected.",
+            ))
+        }
+    };
+
+    let mut network = vec![];
+    let family = parse_machine_family(config)?;
+
+    for (position, interface) in interfaces.iter().enumerate() {
+        let interface = parse_network_interface(interface, position, family)?;
+
+        // Queues are matched to vCPUs through the host CPUs the vCPUs are pinned to
+        if interface.pin_queues
+            && config["launcher"]["vcpu_pinning"].is_badvalue()
+            && config["launcher"]["vcpu_pinning_by_index"].is_badvalue()
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Wrong value for `launcher.network.{}.pin_queues`: vCPU pinning is not configured.",
+                    position
+                ),
+            ));
+        }
+
+        network.push(interface);
+    }
+
+    Ok(network)
+}
+
+fn parse_network_interface(
+    interface: &Yaml,
+    position: usize,
+    family: MachineFamily,
+) -> Result<NetworkInterface> {
+    match interface {
+        Yaml::Hash(_) => {}
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.network.{}`: a hash expected.",
+                    position
+                ),
+            ))
+        }
+    }
+
+    let netdev = match &interface["netdev"] {
+        Yaml::String(s) => s.to_string(),
+        Yaml::Array(values) => parse_parameter_value("netdev", values)?,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.network.{}.netdev`: \
+                        a string or an array expected.",
+                    position
+                ),
+            ))
+        }
+    };
+
+    let device = match &interface["device"] {
+        Yaml::String(s) => s.to_string(),
+        Yaml::Array(values) => parse_parameter_value("device", values)?,
+        // microvm only has virtio-mmio transports unless PCIe is enabled, s390x uses channel I/O
+        Yaml::BadValue => String::from(match family {
+            MachineFamily::Microvm => "virtio-net-device",
+            MachineFamily::Pc => "virtio-net-pci",
+            MachineFamily::S390Ccw => "virtio-net-ccw",
+        }),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.network.{}.device`: \
+                        a string or an array expected.",
+                    position
+                ),
+            ))
+        }
+    };
+
+    let id = match &interface["id"] {
+        Yaml::String(s) => s.to_string(),
+        Yaml::BadValue => format!("net{}", position),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.network.{}.id`: a string expected.",
+                    position
+                ),
+            ))
+        }
+    };
+
+    let mac = match &interface["mac"] {
+        Yaml::String(s) if is_valid_mac_address(s) => Some(s.to_lowercase()),
+        Yaml::BadValue => None,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.network.{}.mac`: a MAC \
+                        address in the `xx:xx:xx:xx:xx:xx` format expected.",
+                    position
+                ),
+            ))
+        }
+    };
+
+    let pin_queues = match &interface["pin_queues"] {
+        Yaml::Boolean(pin_queues) => *pin_queues,
+        Yaml::BadValue => false,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.network.{}.pin_queues`: a boolean expected.",
+                    position
+                ),
+            ))
+        }
+    };
+
+    // Only vhost spawns a host worker per queue which could be pinned
+    if pin_queues && get_netdev_option(&netdev, "vhost") != Some("on") {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Wrong value for `launcher.network.{}.pin_queues`: the netdev must enable vhost \
+                    with `vhost=on`.",
+                position
+            ),
+        ));
+    }
+
+    Ok(NetworkInterface {
+        device,
+        id,
+        mac,
+        netdev,
+        pin_queues,
+    })
+}
+
+// The machine type is taken from the last `-machine` (or `-M`) option naming it, either positionally
+// or with `type=`. PC-style behaviour is assumed when no machine type is given.
+fn parse_machine_family(config: &Yaml) -> Result<MachineFamily> {
+    let machine_type = parse_command_line(config)?
+        .into_iter()
+        .filter_map(|argument| match argument {
+            Argument::Parameter(name, value) if name == "machine" || name == "M" => {
+                match value.split(',').next() {
+                    Some(first) if first.len() > 0 && !first.contains('=') => {
+                        Some(first.to_owned())
+                    }
+                    _ => get_netdev_option(&value, "type").map(str::to_owned),
+                }
+            }
+            _ => None,
+        })
+        .last()
+        .unwrap_or_default();
+
+    Ok(match machine_type.as_str() {
+        "microvm" => MachineFamily::Microvm,
+        machine if machine.starts_with("s390-ccw-virtio") => MachineFamily::S390Ccw,
+        _ => MachineFamily::Pc,
+    })
+}
+
+fn is_virtio_disk_controller(device: &str) -> bool {
+    let driver = device.split(',').next().unwrap_or_default();
+
+    driver.starts_with("virtio-blk") || driver.starts_with("virtio-scsi")
+}
+
+// Format nodes refer to a protocol node, so only the latter are counted as disks
+fn is_protocol_node(blockdev: &str) -> bool {
+    match get_netdev_option(blockdev, "driver") {
+        Some("file") | Some("host_device") | Some("host_cdrom") | Some("nbd") => true,
+        _ => get_netdev_option(blockdev, "file.filename").is_some(),
+    }
+}
+
+fn get_netdev_option<'a>(netdev: &'a str, name: &str) -> Option<&'a str> {
+    netdev
+        .split(',')
+        .filter_map(|option| option.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+fn is_user_netdev(netdev: &str) -> bool {
+    netdev.split(',').next() == Some("user")
+}
+
+fn parse_netboot(config: &Yaml) -> Result<Option<Netboot>> {
+    let netboot = &config["launcher"]["netboot"];
+    match netboot {
+        Yaml::Hash(_) => {}
+        Yaml::BadValue => return Ok(None),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.netboot`: a hash expected.",
+            ))
+        }
+    }
+
+    // Both paths end up in the `-netdev` option value, where a comma starts the next property
+    let directory = match netboot["directory"].as_str() {
+        Some(directory) if directory.starts_with('/') && !directory.contains(',') => {
+            directory.to_owned()
+        }
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.netboot.directory`: an absolute path expected.",
+            ))
+        }
+    };
+
+    let bootfile = match &netboot["bootfile"] {
+        Yaml::String(bootfile)
+            if bootfile.len() > 0
+                && !bootfile.contains(',')
+                && !bootfile.split('/').any(|part| part == "..") =>
+        {
+            Some(bootfile.to_owned())
+        }
+        Yaml::BadValue => None,
+        _ => return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.netboot.bootfile`: a path inside of the directory expected.",
+        )),
+    };
+
+    let listen = match &netboot["listen"] {
+        Yaml::String(address) => match address.parse::<Ipv4Addr>() {
+            Ok(address) => Some(address),
+            Err(_) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Failed to parse `launcher.netboot.listen`: an IPv4 address expected.",
+                ))
+            }
+        },
+        Yaml::BadValue => None,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.netboot.listen`: an IPv4 address expected.",
+            ))
+        }
+    };
+
+    let http_port = match netboot["http_port"] {
+        Yaml::Integer(port) if port > 0 && port <= 65535 => Some(port as u16),
+        Yaml::BadValue => None,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.netboot.http_port`: a port number expected.",
+            ))
+        }
+    };
+
+    if http_port.is_some() && listen.is_none() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.netboot.http_port`: requires `launcher.netboot.listen`.",
+        ));
+    }
+
+    let mut has_user_netdev = false;
+    if let Yaml::Array(interfaces) = &config["launcher"]["network"] {
+        for (position, interface) in interfaces.iter().enumerate() {
+            let netdev = match &interface["netdev"] {
+                Yaml::String(s) => s.to_string(),
+                Yaml::Array(values) => parse_parameter_value("netdev", values)?,
+                _ => continue,
+            };
+
+            if !is_user_netdev(&netdev) {
+                continue;
+            }
+
+            if get_netdev_option(&netdev, "tftp").is_some()
+                || get_netdev_option(&netdev, "bootfile").is_some()
+            {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Wrong value for `launcher.network.{}.netdev`: `tftp` and `bootfile` are \
+                            generated from `launcher.netboot`.",
+                        position
+                    ),
+                ));
+            }
+
+            has_user_netdev = true;
+        }
+    }
+
+    if listen.is_none() && !has_user_netdev {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.netboot`: either `listen` or a `user` interface in \
+                `launcher.network` is required.",
+        ));
+    }
+
+    Ok(Some(Netboot {
+        bootfile,
+        directory,
+        http_port,
+        listen,
+    }))
+}
+
+fn parse_firewall(config: &Yaml) -> Result<Option<Firewall>> {
+    let firewall = &config["launcher"]["firewall"];
+    match firewall {
+        Yaml::Hash(_) => {}
+        Yaml::BadValue => return Ok(None),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.firewall`: a hash expected.",
+            ))
+        }
+    }
+
+    let guest_address = match firewall["guest_address"]
+        .as_str()
+        .map(str::parse::<Ipv4Addr>)
+    {
+        Some(Ok(address)) => address.to_string(),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.firewall.guest_address`: an IPv4 address expected.",
+            ))
+        }
+    };
+
+    let allow_from = match &firewall["allow_from"] {
+        Yaml::Array(ranges) => {
+            let mut allow_from = vec![];
+
+            for (position, range) in ranges.iter().enumerate() {
+                match range.as_str().filter(|range| is_valid_ipv4_range(range)) {
+                    Some(range) => allow_from.push(range.to_owned()),
+                    None => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!(
+                                "Failed to parse `launcher.firewall.allow_from` item {}: \
+                                    an IPv4 address or an `address/prefix` range expected.",
+                                position
+                            ),
+                        ))
+                    }
+                }
+            }
+
+            allow_from
+        }
+        Yaml::BadValue => vec![],
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.firewall.allow_from`: an array expected.",
+            ))
+        }
+    };
+
+    let forwards = match &firewall["forward"] {
+        Yaml::Array(forwards) if forwards.len() > 0 => {
+            let mut result = vec![];
+
+            for (position, forward) in forwards.iter().enumerate() {
+                result.push(parse_port_forward(forward, position)?);
+            }
+
+            result
+        }
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.firewall.forward`: a non-empty array expected.",
+            ))
+        }
+    };
+
+    Ok(Some(Firewall {
+        allow_from,
+        forwards,
+        guest_address,
+    }))
+}
+
+fn parse_port_forward(forward: &Yaml, position: usize) -> Result<PortForward> {
+    let port = |key: &str| match forward[key] {
+        Yaml::Integer(port) if port > 0 && port <= 65535 => Ok(Some(port as u16)),
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse `launcher.firewall.forward.{}.{}`: \
+                    a port number from 1 to 65535 expected.",
+                position, key
+            ),
+        )),
+    };
+
+    let host_port = match port("host_port")? {
+        Some(port) => port,
+        None => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.firewall.forward.{}`: `host_port` is mandatory.",
+                    position
+                ),
+            ))
+        }
+    };
+
+    let protocol = match &forward["protocol"] {
+        Yaml::String(protocol) if protocol == "tcp" || protocol == "udp" => protocol.to_owned(),
+        Yaml::BadValue => String::from("tcp"),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.firewall.forward.{}.protocol`: \
+                        either `tcp` or `udp` expected.",
+                    position
+                ),
+            ))
+        }
+    };
+
+    Ok(PortForward {
+        guest_port: port("guest_port")?.unwrap_or(host_port),
+        host_port,
+        protocol,
+    })
+}
+
+fn is_valid_ipv4_range(range: &str) -> bool {
+    let mut parts = range.splitn(2, '/');
+    let address_is_valid = parts.next().map(str::parse::<Ipv4Addr>).map(|a| a.is_ok());
+    let prefix_is_valid = match parts.next() {
+        Some(prefix) => prefix.parse::<u8>().map(|p| p <= 32).unwrap_or(false),
+        None => true,
+    };
+
+    address_is_valid == Some(true) && prefix_is_valid
+}
+
+fn parse_sriov(config: &Yaml) -> Result<Vec<SriovInterface>> {
+    let interfaces = match &config["launcher"]["sriov"] {
+        Yaml::Array(interfaces) => interfaces,
+        Yaml::BadValue => return Ok(vec![]),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.sriov`: an array expected.",
+            ))
+        }
+    };
+
+    let mut sriov: Vec<SriovInterface> = vec![];
+
+    for (position, interface) in interfaces.iter().enumerate() {
+        let interface = parse_sriov_interface(interface, position)?;
+
+        if sriov.iter().any(|i| i.interface == interface.interface) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Wrong value for `launcher.sriov.{}.interface`: \
+                        the `{}` interface is already configured.",
+                    position, interface.interface
+                ),
+            ));
+        }
+
+        sriov.push(interface);
+    }
+
+    Ok(sriov)
+}
+
+fn parse_sriov_interface(interface: &Yaml, position: usize) -> Result<SriovInterface> {
+    match interface {
+        Yaml::Hash(_) => {}
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.sriov.{}`: a hash expected.",
+                    position
+                ),
+            ))
+        }
+    }
+
+    let name = match &interface["interface"] {
+        Yaml::String(s) if s.len() > 0 && !s.contains('/') => s.to_string(),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.sriov.{}.interface`: \
+                        a physical function network interface name expected.",
+                    position
+                ),
+            ))
+        }
+    };
+
+    let vfs = match &interface["vfs"] {
+        Yaml::Integer(count) if *count > 0 => (0..*count)
+            .map(|_| VirtualFunction {
+                mac: None,
+                vlan: None,
+            })
+            .collect(),
+        Yaml::Array(vfs) if vfs.len() > 0 => {
+            let mut result = vec![];
+
+            for (index, vf) in vfs.iter().enumerate() {
+                result.push(parse_virtual_function(vf, position, index)?);
+            }
+
+            result
+        }
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.sriov.{}.vfs`: \
+                        a positive integer or a non-empty array expected.",
+                    position
+                ),
+            ))
+        }
+    };
+
+    Ok(SriovInterface {
+        interface: name,
+        vfs,
+    })
+}
+
+fn parse_virtual_function(vf: &Yaml, position: usize, index: usize) -> Result<VirtualFunction> {
+    match vf {
+        Yaml::Hash(_) => {}
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.sriov.{}.vfs.{}`: a hash expected.",
+                    position, index
+                ),
+            ))
+        }
+    }
+
+    let mac = match &vf["mac"] {
+        Yaml::String(s) if is_valid_mac_address(s) => Some(s.to_lowercase()),
+        Yaml::BadValue => None,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.sriov.{}.vfs.{}.mac`: a MAC \
+                        address in the `xx:xx:xx:xx:xx:xx` format expected.",
+                    position, index
+                ),
+            ))
+        }
+    };
+
+    let vlan = match vf["vlan"] {
+        Yaml::Integer(vlan) if vlan >= 1 && vlan <= 4094 => Some(vlan as u16),
+        Yaml::BadValue => None,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.sriov.{}.vfs.{}.vlan`: \
+                        a VLAN ID from 1 to 4094 expected.",
+                    position, index
+                ),
+            ))
+        }
+    };
+
+    Ok(VirtualFunction { mac, vlan })
+}
+
+fn parse_paths(config: &Yaml) -> Result<Vec<ManagedPath>> {
+    let entries = match &config["launcher"]["paths"] {
+        Yaml::Array(entries) => entries,
+        Yaml::BadValue => return Ok(vec![]),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.paths`: an array expected.",
+            ))
+        }
+    };
+
+    let mut paths: Vec<ManagedPath> = vec![];
+
+    for (position, entry) in entries.iter().enumerate() {
+        let path = parse_managed_path(entry, position)?;
+
+        if paths.iter().any(|p| p.path == path.path) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Wrong value for `launcher.paths.{}.path`: \
+                        the `{}` path is already declared.",
+                    position, path.path
+                ),
+            ));
+        }
+
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+fn parse_managed_path(entry: &Yaml, position: usize) -> Result<ManagedPath> {
+    match entry {
+        Yaml::Hash(_) => {}
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.paths.{}`: a hash expected.",
+                    position
+                ),
+            ))
+        }
+    }
+
+    let path = match &entry["path"] {
+        Yaml::String(s) if s.starts_with('/') && s.trim_end_matches('/').len() > 0 => {
+            s.trim_end_matches('/').to_string()
+        }
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.paths.{}.path`: an absolute path expected.",
+                    position
+                ),
+            ))
+        }
+    };
+
+    let kind = match entry["type"].as_str() {
+        Some("directory") => PathKind::Directory,
+        Some("file") => PathKind::File,
+        None if entry["type"].is_badvalue() => PathKind::Directory,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.paths.{}.type`: \
+                        either `directory` or `file` expected.",
+                    position
+                ),
+            ))
+        }
+    };
+
+    // A string, as YAML reads an unquoted `0750` as the decimal 750
+    let mode = match &entry["mode"] {
+        Yaml::BadValue => None,
+        mode => match mode
+            .as_str()
+            .filter(|s| s.len() <= 4)
+            .and_then(|s| u32::from_str_radix(s, 8).ok())
+        {
+            Some(mode) => Some(mode),
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Failed to parse `launcher.paths.{}.mode`: \
+                            an octal mode in a string, e.g. `\"0750\"`, expected.",
+                        position
+                    ),
+                ))
+            }
+        },
+    };
+
+    let id = |key: &str| match entry[key] {
+        Yaml::Integer(i) => match u16::try_from(i) {
+            Ok(i) => Ok(Some(i)),
+            Err(_) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse `launcher.paths.{}.{}`: an unsigned 16-bit integer expected.",
+                    position, key
+                ),
+            )),
+        },
+        Yaml::BadValue => Ok(None),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse `launcher.paths.{}.{}`: an unsigned 16-bit integer expected.",
+                position, key
+            ),
+        )),
+    };
+
+    Ok(ManagedPath {
+        group: id("group")?,
+        kind,
+        mode,
+        path,
+        user: id("user")?,
+    })
+}
+
+fn is_valid_mac_address(mac: &str) -> bool {
+    let octets: Vec<&str> = mac.split(':').collect();
+
+    octets.len() == 6
+        && octets
+            .iter()
+            .all(|octet| octet.len() == 2 && u8::from_str_radix(octet, 16).is_ok())
+}
+
+fn generate_mac_address(machine_name: &str, interface_id: &str) -> String {
+    // FNV-1a is used for its stability across releases and platforms, so the
+    // same machine always gets the same address and keeps its DHCP lease.
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in format!("{}/{}", machine_name, interface_id).bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    let bytes = hash.to_be_bytes();
+
+    format!(
+        "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+        (bytes[0] & 0xfc) | 0x02,
+        bytes[1],
+        bytes[2],
+        bytes[3],
+        bytes[4],
+        bytes[5]
+    )
+}
+
+fn generate_uuid(machine_name: &str) -> String {
+    // Derived the same way as MAC addresses, so the guest keeps its identity across launches
+    let mut bytes = vec![];
+    for half in &["smbios/0", "smbios/1"] {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in format!("{}/{}", machine_name, half).bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        bytes.extend_from_slice(&hash.to_be_bytes());
+    }
+
+    // RFC 9562 version 8 (custom) and variant bits
+    bytes[6] = (bytes[6] & 0x0f) | 0x80;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+fn parse_command_line(config: &Yaml) -> Result<Vec<Argument>> {
+    match &config["qemu"] {
+        Yaml::Array(options) => parse_command_line_options(options),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse qemu command line options: \
+                    missing or invalid value, array expected."
+            ),
+        )),
+    }
+}
+
+fn parse_command_line_options(options: &Array) -> Result<Vec<Argument>> {
+    let mut parsed_options = vec![];
+
+    for (position, option) in options.iter().enumerate() {
+        let position = position + 1;
+
+        let argument = match option {
+            Yaml::String(option) => parse_flag(option, position)?,
+            Yaml::Hash(option) => parse_parameter(option, position)?,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Failed to parse qemu command line option {}. \
+                        Every option must be either a string or a hash.",
+                        position
+                    ),
+                ))
+            }
+        };
+
+        match argument {
+            Argument::Parameter(name, value) if name == "qmp" && value == "stdio" => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "Failed to parse qemu command line option {}. The `-qmp stdio` \
+                            channel is reserved for the launcher, use `launcher.qmp_socket` instead.",
+                            position
+                        ),
+                    ))
+            }
+            argument => parsed_options.push(argument),
+        }
+    }
+
+    Ok(parsed_options)
+}
+
+// Strings starting with a dash are taken verbatim from a qemu command line, e.g.
+// `-device vfio-pci,host=01:00.0`, and are split into the option name and its value
+fn parse_flag(option: &str, position: usize) -> Result<Argument> {
+    if !option.starts_with('-') {
+        return Ok(Argument::Flag(option.to_owned()));
+    }
+
+    let (name, value) = split_raw_option(option);
+
+    if name.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse qemu command line option {}: an option name expected after the dash.",
+                position
+            ),
+        ));
+    }
+
+    Ok(match value {
+        Some(value) => Argument::Parameter(name.to_owned(), value.to_owned()),
+        None => Argument::Flag(name.to_owned()),
+    })
+}
+
+fn split_raw_option(option: &str) -> (&str, Option<&str>) {
+    let option = option.trim_start_matches('-').trim_end();
+
+    let (name, value) = match option.find(char::is_whitespace) {
+        Some(index) => (&option[..index], option[index..].trim_start()),
+        None => return (option, None),
+    };
+
+    // Quotes are only meaningful to a shell, so a single quoted value is unwrapped
+    let unquoted = ['"', '\''].iter().find_map(|quote| {
+        if value.len() > 1 && value.starts_with(*quote) && value.ends_with(*quote) {
+            Some(&value[1..value.len() - 1])
+        } else {
+            None
+        }
+    });
+
+    (name, Some(unquoted.unwrap_or(value)))
+}
+
+fn parse_parameter(option: &Hash, position: usize) -> Result<Argument> {
+    if option.len() != 1 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Found a command line argument {} with {} pairs \
+                in the hash, but exactly one is expected.",
+                position,
+                option.len()
+            ),
+        ));
+    }
+
+    let entry = option.front().unwrap();
+
+    let name = entry
+        .0
+        .as_str()
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Argument {} name must be a string.", position),
+            )
+        })?
+        .to_string();
+
+    let value = match entry.1 {
+        Yaml::Integer(i) => i.to_string(),
+        Yaml::String(s) => s.to_string(),
+        Yaml::Real(r) => r.to_string(),
+        Yaml::Array(v) => parse_parameter_value(&name, v)?,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Invalid value for `{}` qemu argument {}: expected a \
+                        string, number or a hash with a single pair.",
+                    name, position
+                ),
+            ))
+        }
+    };
+
+    Ok(Argument::Parameter(name, value))
+}
+
+fn parse_parameter_value(name: &str, values: &Array) -> Result<String> {
+    if values.len() == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Empty value for `{}` argument, consider \
+                    using a string instead of an array.",
+                name
+            ),
+        ));
+    }
+
+    let mut parts = vec![];
+
+    for value in values {
+        match value {
+            Yaml::String(s) => parts.push(s.to_string()),
+            Yaml::Integer(i) => parts.push(i.to_string()),
+            Yaml::Real(r) => parts.push(r.to_string()),
+            Yaml::Hash(h) => parts.push(parse_parameter_value_part(&name, h)?),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Invalid value for `{}` option: must be a \
+                            hash with a single pair or a string",
+                        name
+                    ),
+                ))
+            }
+        }
+    }
+
+    Ok(parts.join(","))
+}
+
+fn parse_parameter_value_part(name: &str, part: &Hash) -> Result<String> {
+    if part.len() != 1 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse a value for `{}` argument: \
+                    a hash with multiple pairs found.",
+                name
+            ),
+        ));
+    }
+
+    let entry = part.front().unwrap();
+
+    let part_name = entry.0.as_str().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse a value for `{}` argument: \
+                    a property name must be a string.",
+                name
+            ),
+        )
+    })?;
+
+    let part_value = match entry.1 {
+        Yaml::String(s) => s.to_string(),
+        Yaml::Integer(i) => i.to_string(),
+        Yaml::Real(r) => r.to_string(),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse a value for `{}/{}` argument: \
+                    value must be either a string or a number.",
+                    name, part_name
+                ),
+            ))
+        }
+    };
+
+    Ok(format!("{}={}", part_name, part_value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        check_file_permissions, Config, EnvSource, EventSink, GuestShutdown, PathKind, QmpLog, Thp,
+    };
+    use crate::{assert_error, expect, vec_deq, verify_expectations};
+    use ::std::{
+        cell::RefCell,
+        collections::{HashMap, VecDeque},
+        io::{Error, ErrorKind},
+        time::Duration,
+    };
+
+    struct TestExpectations {
+        std_fs_read_to_string: VecDeque<(&'static str, Result<String, Error>)>,
+    }
+
+    impl TestExpectations {
+        fn new() -> Self {
+            TestExpectations {
+                std_fs_read_to_string: vec_deq![],
+            }
+        }
+    }
+
+    thread_local! { static TEST_EXPECTATIONS: RefCell<TestExpectations> = RefCell::new(TestExpectations::new()) }
+
+    fn verify_expectations() {
+        verify_expectations!(
+            std::fs::read_to_string => TEST_EXPECTATIONS::std_fs_read_to_string,
+        );
+    }
+
+    pub mod std {
+        pub mod fs {
+            use super::super::TEST_EXPECTATIONS;
+            use crate::verify_expectation;
+            use ::std::{io::Result, path::Path};
+
+            pub fn read_to_string<P: AsRef<Path>>(path: P) -> Result<String> {
+                let path = path.as_ref().to_str().unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_read_to_string => std::fs::read_to_string { path })
+            }
+        }
+    }
+
+    #[test]
+    fn config_with_all_options_parsed_properly() {
+        let config = Config::new(
+            "
+            launcher:
+              user: 100
+              group: 200
+              vcpu_pinning:
+                0:
+                  0:
+                    0: 2
+                    1: 6
+                  1:
+                    0: 3
+                    1: 7
+              binary: /usr/bin/qemu-kvm
+              clear_env: true
+              env:
+                STRING: \"bar\"
+                INTEGER: 1
+                REAL: 1.0
+                BOOLEAN: true
+              priority: 1
+              scheduler: fifo
+              isolate_workqueues: true
+              disable_timer_migration: true
+              lower_kernel_thread_priority: true
+
+            qemu:
+            - realtime
+            - cpu: host
+            - smp: [ cpus: 1, cores: 2, threads: 2 ]
+            - m: [ 4096, slots: 2 ]
+            - numa: 1
+            - seed: 1.234
+            - device: [ vfio-pci, 3, 9.87 ]
+            - device: [ vfio-pci, multifunction: on, addr: 0.1 ]
+            - { device: [ usb-mouse ] }
+            - device:
+              - vfio-pci
+              - multifunction: on
+              - { addr: 0.2 }
+        ",
+        )
+        .unwrap();
+
+        assert_eq!(Some(100), config.get_user());
+        assert_eq!(Some(200), config.get_group());
+
+        let expected_cpu_pinnig: Vec<(usize, usize, usize, usize)> =
+            vec![(0, 0, 0, 2), (0, 0, 1, 6), (0, 1, 0, 3), (0, 1, 1, 7)];
+        let cpu_pinning: Vec<(usize, usize, usize, usize)> = config
+            .get_cpu_pinning()
+            .iter()
+            .map(|pin| {
+                (
+                    pin.get_socket(),
+                    pin.get_core(),
+                    pin.get_thread(),
+                    pin.get_host_cpu(),
+                )
+            })
+            .collect();
+        assert_eq!(expected_cpu_pinnig, cpu_pinning);
+
+        assert_eq!("/usr/bin/qemu-kvm", config.get_qemu_binary_path());
+        assert_eq!(true, config.should_clear_env());
+        assert_eq!(Some(1), config.get_priority());
+        assert_eq!(&Some(String::from("fifo")), config.get_scheduler());
+        assert_eq!(true, config.should_isolate_workqueues());
+        assert_eq!(true, config.should_disable_timer_migration());
+        assert_eq!(true, config.should_lower_kernel_thread_priority());
+        assert_eq!("bar", config.get_env_vars()["STRING"]);
+        assert_eq!("1", config.get_env_vars()["INTEGER"]);
+        assert_eq!("1.0", config.get_env_vars()["REAL"]);
+        assert_eq!("true", config.get_env_vars()["BOOLEAN"]);
+        assert_eq!(
+            vec![
+                "-realtime",
+                "-cpu",
+                "host",
+                "-smp",
+                "cpus=1,cores=2,threads=2",
+                "-m",
+                "4096,slots=2",
+                "-numa",
+                "1",
+                "-seed",
+                "1.234",
+                "-device",
+                "vfio-pci,3,9.87",
+                "-device",
+                "vfio-pci,multifunction=on,addr=0.1",
+                "-device",
+                "usb-mouse",
+                "-device",
+                "vfio-pci,multifunction=on,addr=0.2",
+                "-qmp",
+                "stdio",
+            ],
+            config.get_command_line_options()
+        );
+    }
+
+    #[test]
+    fn config_with_absent_optional_fields_passed_properly() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+
+            qemu:
+            - sda: /dev/sdb
+        ",
+        )
+        .unwrap();
+
+        assert_eq!("/usr/bin/qemu-kvm", config.get_qemu_binary_path());
+        assert_eq!(None, config.get_user());
+        assert_eq!(None, config.get_group());
+        assert_eq!(0, config.get_cpu_pinning().len());
+        assert_eq!(false, config.should_clear_env());
+        assert_eq!(None, config.get_priority());
+        assert_eq!(&None, config.get_scheduler());
+        assert_eq!(false, config.should_isolate_workqueues());
+        assert_eq!(false, config.should_disable_timer_migration());
+        assert_eq!(false, config.should_lower_kernel_thread_priority());
+        assert_eq!(false, config.is_cwd_relative());
+        assert_eq!(&None, config.get_qmp_log());
+        assert_eq!(&HashMap::<String, String>::new(), config.get_env_vars());
+        assert_eq!(
+            vec!["-sda", "/dev/sdb", "-qmp", "stdio"],
+            config.get_command_line_options()
+        );
+    }
+
+    fn assert_error<T>(result: Result<T, Error>, kind: ErrorKind, message: &str) {
+        match result {
+            Ok(_) => panic!("Parser did not produce an error for invalid data."),
+            Err(e) => {
+                assert_eq!(kind, e.kind());
+                assert_eq!(message, format!("{}", e));
+            }
+        }
+    }
+
+    #[test]
+    fn empty_configuration_returns_error() {
+        assert_error(
+            Config::new(""),
+            ErrorKind::InvalidData,
+            "Supplied configuration is empty.",
+        );
+    }
+
+    #[test]
+    fn launcher_user_invalid_value_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  user: user
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Invalid value for `launcher.user` option: unsigned 16-bit integer expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_user_negative_value_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  user: -1
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Invalid value for `launcher.user` option: given value is \
+                out of bounds, expected an unsigned 16-bit integer.",
+        );
+    }
+
+    #[test]
+    fn launcher_user_too_high_value_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  user: 4294967295
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Invalid value for `launcher.user` option: given value is \
+                out of bounds, expected an unsigned 16-bit integer.",
+        );
+    }
+
+    #[test]
+    fn launcher_group_invalid_value_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  group: group
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Invalid value for `launcher.group` option: unsigned 16-bit integer expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_group_negative_value_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  group: -1
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Invalid value for `launcher.group` option: given value is \
+                out of bounds, expected an unsigned 16-bit integer.",
+        );
+    }
+
+    #[test]
+    fn launcher_group_too_high_value_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  group: 4294967295
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Invalid value for `launcher.group` option: given value is \
+                out of bounds, expected an unsigned 16-bit integer.",
+        );
+    }
+
+    #[test]
+    fn launcher_vcpu_pinning_by_index_maps_vcpu_indices_to_host_cpus() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              vcpu_pinning_by_index:
+                0: 4
+                1: 5
+
+            qemu:
+            - smp: 2
+        ",
+        )
+        .unwrap();
+
+        assert_eq!(&vec![(0, 4), (1, 5)], config.get_cpu_pinning_by_index());
+        assert_eq!(vec![4, 5], config.get_pinned_host_cpus());
+        assert!(config.has_cpu_pinning());
+    }
+
+    #[test]
+    fn validate_host_cpus_returns_error_for_cpus_not_present_on_host() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              vcpu_pinning:
+                0:
+                  0:
+                    0: 2
+                  1:
+                    0: 12
+            qemu: []
+            ",
+        )
+        .unwrap();
+
+        assert!(config.validate_host_cpus(&[0, 1, 2, 3, 12]).is_ok());
+        assert_error(
+            config.validate_host_cpus(&[0, 1, 2, 3]),
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.vcpu_pinning`: the host CPU `12` of socket 0, \
+                core 1, thread 0 is not present on this host.",
+        );
+
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              vcpu_pinning_by_index:
+                3: 9
+            qemu: []
+            ",
+        )
+        .unwrap();
+
+        assert_error(
+            config.validate_host_cpus(&[0, 1, 2, 3]),
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.vcpu_pinning_by_index`: the host CPU `9` of vCPU 3 \
+                is not present on this host.",
+        );
+    }
+
+    #[test]
+    fn launcher_vcpu_pinning_by_index_combined_with_vcpu_pinning_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning:
+                    0:
+                      0:
+                        0: 2
+                  vcpu_pinning_by_index:
+                    0: 4
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.vcpu_pinning_by_index`: \
+                can not be combined with `launcher.vcpu_pinning`.",
+        );
+    }
+
+    #[test]
+    fn launcher_vcpu_pinning_by_index_negative_host_core_id_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning_by_index:
+                    0: -4
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.vcpu_pinning_by_index.0`: \
+                            the host core ID must be an integer greater or equal to zero.",
+        );
+    }
+
+    #[test]
+    fn launcher_vcpu_pinning_not_a_hash_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning: []
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.vcpu_pinning` configuration: a hash is expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_vcpu_pinning_socket_id_not_an_integer_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning:
+                    a:
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.vcpu_pinning`: the socket \
+                ID must be an integer greater or equal to zero.",
+        );
+    }
+
+    #[test]
+    fn launcher_vcpu_pinning_negative_socket_id_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning:
+                    -1:
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.vcpu_pinning`: the socket \
+                ID must be an integer greater or equal to zero.",
+        );
+    }
+
+    #[test]
+    fn launcher_vcpu_pinning_socket_value_is_not_a_hash_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning:
+                    0: []
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.vcpu_pinning.0`: a hash expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_vcpu_pinning_core_id_not_an_integer_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning:
+                    0:
+                      a:
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.vcpu_pinning.0`: the core \
+                ID must be an integer greater or equal to zero.",
+        );
+    }
+
+    #[test]
+    fn launcher_vcpu_pinning_negative_core_id_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning:
+                    0:
+                      -1:
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.vcpu_pinning.0`: the core \
+                ID must be an integer greater or equal to zero.",
+        );
+    }
+
+    #[test]
+    fn launcher_vcpu_pinning_core_value_is_not_a_hash_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning:
+                    0:
+                      0: []
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.vcpu_pinning.0.0`: a hash expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_vcpu_pinning_thread_id_not_an_integer_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning:
+                    0:
+                      0:
+                        a:
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.vcpu_pinning.0.0`: \
+                the thread ID must be a positive integer.",
+        );
+    }
+
+    #[test]
+    fn launcher_vcpu_pinning_negative_thread_id_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning:
+                    0:
+                      0:
+                        -1:
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.vcpu_pinning.0.0`: \
+                the thread ID must be a positive integer.",
+        );
+    }
+
+    #[test]
+    fn launcher_vcpu_pinning_host_core_id_not_an_integer_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning:
+                    0:
+                      0:
+                        0: true
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.vcpu_pinning.0.0.0`: the host \
+                core ID must be an integer greater or equal to zero.",
+        );
+    }
+
+    #[test]
+    fn launcher_vcpu_pinning_negative_host_core_id_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning:
+                    0:
+                      0:
+                        0: -1
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.vcpu_pinning.0.0.0`: the host \
+                core ID must be an integer greater or equal to zero.",
+        );
+    }
+
+    #[test]
+    fn launcher_vcpu_pinning_adds_die_and_cluster_levels_from_smp() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              vcpu_pinning:
+                0:
+                  1:
+                    0:
+                      1:
+                        0: 5
+
+            qemu:
+            - smp: 8,sockets=1,dies=2,clusters=2,cores=1,threads=2
+        ",
+        )
+        .unwrap();
+
+        let pin = config.get_cpu_pinning()[0];
+
+        assert_eq!(1, config.get_cpu_pinning().len());
+        assert_eq!(
+            (0, Some(1), Some(0), 1, 0, 5),
+            (
+                pin.get_socket(),
+                pin.get_die(),
+                pin.get_cluster(),
+                pin.get_core(),
+                pin.get_thread(),
+                pin.get_host_cpu()
+            )
+        );
+        assert_eq!("0.1.0.1.0", pin.get_path());
+        assert_eq!(
+            "socket 0, die 1, cluster 0, core 1, thread 0",
+            pin.describe()
+        );
+    }
+
+    #[test]
+    fn launcher_vcpu_pinning_single_die_does_not_add_level() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              vcpu_pinning:
+                0:
+                  1:
+                    0: 3
+
+            qemu:
+            - smp: [ 2, sockets: 1, dies: 1, cores: 2, threads: 1 ]
+        ",
+        )
+        .unwrap();
+
+        let pin = config.get_cpu_pinning()[0];
+
+        assert_eq!(None, pin.get_die());
+        assert_eq!("0.1.0", pin.get_path());
+    }
+
+    #[test]
+    fn launcher_vcpu_pinning_negative_die_id_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning:
+                    0:
+                      -1:
+                        0:
+                          0: 2
+
+                qemu:
+                - smp: 4,dies=2,cores=2
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.vcpu_pinning.0`: the die \
+                ID must be an integer greater or equal to zero.",
+        );
+    }
+
+    #[test]
+    fn launcher_section_with_missing_qemu_binary_path_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "qemu binary path is not specified, missing or the \
+                `launcher.binary` key has an invalid type.",
+        );
+    }
+
+    #[test]
+    fn launcher_section_with_non_string_qemu_binary_path_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: 3
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "qemu binary path is not specified, missing or the \
+                `launcher.binary` key has an invalid type.",
+        );
+    }
+
+    #[test]
+    fn launcher_section_with_empty_qemu_binary_path_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary:
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "qemu binary path is not specified, missing or the \
+                `launcher.binary` key has an invalid type.",
+        );
+    }
+
+    #[test]
+    fn launcher_section_with_invalid_clear_env_option_value_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  clear_env: 'yes'
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Invalid value for `launcher.clear_env` value: a boolean is expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_section_with_empty_clear_env_option_value_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  clear_env:
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Invalid value for `launcher.clear_env` value: a boolean is expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_hash_with_invalid_env_value_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  env: []
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Invalid value for the `launcher.env` key: a hash expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_hash_with_empty_env_key_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  env:
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Invalid value for the `launcher.env` key: a hash expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_hash_with_env_hash_containing_non_string_keys_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  env:
+                    3: nope
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Environment variable name must be a string.",
+        );
+    }
+
+    #[test]
+    fn launcher_hash_with_env_hash_containing_invalid_values_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  env:
+                    NOPE: []
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Invalid value for the `NOPE` environment variable.",
+        );
+    }
+
+    #[test]
+    fn launcher_hash_with_empty_priority_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  priority:
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.priority`: an integer expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_hash_with_non_integer_priority_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  priority: a
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.priority`: an integer expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_hash_with_too_high_priority_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  priority: 1024
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.priority`: value out of bounds.",
+        );
+    }
+
+    #[test]
+    fn launcher_hash_with_negative_priority_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  priority: -2
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.priority`: value out of bounds.",
+        );
+    }
+
+    #[test]
+    fn launcher_priority_outside_of_scheduler_range_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  scheduler: fifo
+                  priority: 120
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.priority`: the `fifo` scheduler allows priorities from 1 to 99.",
+        );
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  scheduler: idle
+                  priority: 5
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.priority`: the `idle` scheduler allows priorities from 0 to 0.",
+        );
+    }
+
+    #[test]
+    fn launcher_hash_with_invalid_scheduler_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  scheduler: []
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.scheduler`: string expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_hash_with_empty_scheduler_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  scheduler:
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.scheduler`: string expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_hash_with_unsupported_scheduler_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  scheduler: foo
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.scheduler`: Expected one of \
+                `batch`, `deadline`, `fifo`, `idle`, `other` or `rr`.",
+        );
+    }
+
+    #[test]
+    fn launcher_deadline_is_parsed_into_scheduling_parameters() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              scheduler: deadline
+              deadline:
+                runtime_us: 500
+                deadline_us: 800
+                period_us: 1000
+
+            qemu:
+            - sda: /dev/sdb
+        ",
+        )
+        .unwrap();
+
+        let deadline = config.get_deadline().unwrap();
+        assert_eq!(500, deadline.get_runtime_us());
+        assert_eq!(800, deadline.get_deadline_us());
+        assert_eq!(1000, deadline.get_period_us());
+        assert_eq!(false, config.has_scheduling());
+    }
+
+    #[test]
+    fn launcher_deadline_combined_with_priority_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  priority: 1
+                  deadline:
+                    runtime_us: 500
+                    deadline_us: 800
+                    period_us: 1000
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.deadline`: can not be combined with `launcher.priority`.",
+        );
+    }
+
+    #[test]
+    fn launcher_deadline_with_other_scheduler_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  scheduler: fifo
+                  deadline:
+                    runtime_us: 500
+                    deadline_us: 800
+                    period_us: 1000
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.deadline`: can only be used with the `deadline` scheduler.",
+        );
+    }
+
+    #[test]
+    fn launcher_deadline_with_missing_period_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  deadline:
+                    runtime_us: 500
+                    deadline_us: 800
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.deadline.period_us`: a positive integer expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_deadline_with_runtime_exceeding_deadline_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  deadline:
+                    runtime_us: 900
+                    deadline_us: 800
+                    period_us: 1000
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.deadline`: `runtime_us` must not exceed `deadline_us` \
+            and `deadline_us` must not exceed `period_us`.",
+        );
+    }
+
+    #[test]
+    fn launcher_uclamp_is_parsed_per_pinned_host_cpu() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              vcpu_pinning:
+                0:
+                  0:
+                    0: 2
+                    1: 6
+              uclamp:
+                2:
+                  min: 60
+                6:
+                  min: 10
+                  max: 50
+
+            qemu:
+            - sda: /dev/sdb
+        ",
+        )
+        .unwrap();
+
+        let clamp = config.get_util_clamp(2).unwrap();
+        assert_eq!(Some(60), clamp.get_min());
+        assert_eq!(None, clamp.get_max());
+        let clamp = config.get_util_clamp(6).unwrap();
+        assert_eq!(Some(10), clamp.get_min());
+        assert_eq!(Some(50), clamp.get_max());
+        assert_eq!(None, config.get_util_clamp(3));
+    }
+
+    #[test]
+    fn launcher_uclamp_for_not_pinned_host_cpu_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning:
+                    0:
+                      0:
+                        0: 2
+                  uclamp:
+                    3:
+                      min: 60
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.uclamp.3`: \
+                        the host CPU is not used in vCPU pinning.",
+        );
+    }
+
+    #[test]
+    fn launcher_uclamp_with_out_of_range_value_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning:
+                    0:
+                      0:
+                        0: 2
+                  uclamp:
+                    2:
+                      max: 101
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.uclamp.2.max`: a percentage from 0 to 100 expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_uclamp_with_min_exceeding_max_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning:
+                    0:
+                      0:
+                        0: 2
+                  uclamp:
+                    2:
+                      min: 80
+                      max: 50
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.uclamp.2`: `min` must not exceed `max`.",
+        );
+    }
+
+    #[test]
+    fn launcher_qmp_log_accepts_file_and_main_values() {
+        for (value, expected) in vec![("file", QmpLog::File), ("main", QmpLog::Main)] {
+            let config = Config::new(format!(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  qmp_log: {}
+
+                qemu:
+                - sda: /dev/sdb
+                ",
+                value
+            ))
+            .unwrap();
+
+            assert_eq!(&Some(expected), config.get_qmp_log());
+        }
+    }
+
+    #[test]
+    fn launcher_on_guest_shutdown_keeps_qemu_running_after_guest_shutdown() {
+        for (value, expected) in vec![
+            ("quit", GuestShutdown::Quit),
+            ("restart", GuestShutdown::Restart),
+            ("ignore", GuestShutdown::Ignore),
+        ] {
+            let config = Config::new(format!(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  on_guest_shutdown: {}
+
+                qemu:
+                - nographic
+                ",
+                value
+            ))
+            .unwrap();
+
+            assert_eq!(Some(expected), config.get_on_guest_shutdown());
+            assert_eq!(
+                vec!["-nographic", "-no-shutdown", "-qmp", "stdio"],
+                config.get_command_line_options()
+            );
+        }
+    }
+
+    #[test]
+    fn launcher_on_guest_shutdown_with_unknown_value_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  on_guest_shutdown: poweroff
+                qemu:
+                - nographic
+                ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.on_guest_shutdown`: Expected one of `quit`, `restart` or `ignore`.",
+        );
+    }
+
+    #[test]
+    fn launcher_watchdog_adds_device_and_leaves_action_to_launcher() {
+        for (value, model, action) in vec![
+            ("i6300esb", "i6300esb", GuestShutdown::Restart),
+            (
+                "{ model: ib700, action: quit }",
+                "ib700",
+                GuestShutdown::Quit,
+            ),
+            ("{ action: ignore }", "i6300esb", GuestShutdown::Ignore),
+        ] {
+            let config = Config::new(format!(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  watchdog: {}
+
+                qemu:
+                - nographic
+                ",
+                value
+            ))
+            .unwrap();
+
+            assert_eq!(Some(action), config.get_watchdog_action());
+            assert!(config.should_watch_qmp_events());
+            assert_eq!(
+                vec![
+                    "-nographic",
+                    "-device",
+                    model,
+                    "-watchdog-action",
+                    "none",
+                    "-qmp",
+                    "stdio"
+                ],
+                config.get_command_line_options()
+            );
+        }
+    }
+
+    #[test]
+    fn launcher_watchdog_with_invalid_values_returns_error() {
+        for (value, message) in vec![
+            (
+                "[]",
+                "Failed to parse `launcher.watchdog`: a string or a hash expected.",
+            ),
+            (
+                "{ timeout: 30 }",
+                "Failed to parse `launcher.watchdog`: unknown key `timeout`, `action` or `model` expected.",
+            ),
+            (
+                "diag288",
+                "Failed to parse `launcher.watchdog.model`: Expected one of `i6300esb` or `ib700`.",
+            ),
+            (
+                "{ action: poweroff }",
+                "Failed to parse `launcher.watchdog.action`: Expected one of `quit`, `restart` or `ignore`.",
+            ),
+        ] {
+            assert_error(
+                Config::new(format!(
+                    "
+                    launcher:
+                      binary: /usr/bin/qemu-kvm
+                      watchdog: {}
+
+                    qemu:
+                    - nographic
+                    ",
+                    value
+                )),
+                ErrorKind::InvalidData,
+                message,
+            );
+        }
+    }
+
+    #[test]
+    fn launcher_watchdog_combined_with_qemu_watchdog_action_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  watchdog: i6300esb
+
+                qemu:
+                - watchdog-action: pause
+                ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.watchdog`: can not be combined with the `watchdog-action` \
+                option in the `qemu` section.",
+        );
+    }
+
+    #[test]
+    fn config_get_required_capabilities_lists_privileged_options() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              user: 1000
+              priority: 10
+              rlimit_memlock: true
+              cwd_relative: true
+              fix_permissions: true
+
+            qemu:
+            - nographic
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                ("launcher.priority", "CAP_SYS_NICE"),
+                ("launcher.rlimit_memlock", "CAP_SYS_RESOURCE"),
+                ("launcher.user", "CAP_SETUID"),
+                ("launcher.fix_permissions", "CAP_CHOWN"),
+                ("launcher.cwd_relative", "CAP_CHOWN"),
+            ],
+            config.get_required_capabilities()
+        );
+    }
+
+    #[test]
+    fn launcher_fix_permissions_without_user_or_group_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  fix_permissions: true
+                qemu: []
+                ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.fix_permissions`: requires `launcher.user` or \
+                `launcher.group` to be set.",
+        );
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  group: 107
+                  fix_permissions: yes
+                qemu: []
+                ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.fix_permissions`: boolean expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_thp_accepts_always_madvise_and_never_values() {
+        for (value, expected) in vec![
+            ("always", Thp::Always),
+            ("madvise", Thp::Madvise),
+            ("never", Thp::Never),
+        ] {
+            let config = Config::new(format!(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  thp: {}
+
+                qemu:
+                - sda: /dev/sdb
+                ",
+                value
+            ))
+            .unwrap();
+
+            assert_eq!(Some(expected), config.get_thp());
+        }
+    }
+
+    #[test]
+    fn launcher_hash_with_unsupported_thp_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  thp: sometimes
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.thp`: Expected one of `always`, `madvise` or `never`.",
+        );
+    }
+
+    #[test]
+    fn launcher_hash_with_unsupported_qmp_log_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  qmp_log: syslog
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.qmp_log`: Expected one of `file` or `main`.",
+        );
+    }
+
+    #[test]
+    fn launcher_qmp_socket_adds_user_facing_qmp_channel() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              qmp_socket: /run/qemu-launcher/my-vm/qmp.sock
+
+            qemu:
+            - sda: /dev/sdb
+        ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "-sda",
+                "/dev/sdb",
+                "-qmp",
+                "unix:/run/qemu-launcher/my-vm/qmp.sock,server=on,wait=off",
+                "-qmp",
+                "stdio"
+            ],
+            config.get_command_line_options()
+        );
+    }
+
+    #[test]
+    fn launcher_qmp_startup_commands_are_converted_into_json() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              qmp_startup_commands:
+              - execute: migrate-set-capabilities
+                arguments:
+                  capabilities:
+                  - { capability: postcopy-ram, state: true }
+              - execute: block_set_io_throttle
+                arguments: { id: disk0, iops: 100, bps: 0.5 }
+
+            qemu:
+            - sda: /dev/sdb
+        ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            &vec![
+                json::object! {
+                    "execute": "migrate-set-capabilities",
+                    "arguments": { "capabilities": [{ "capability": "postcopy-ram", "state": true }] }
+                },
+                json::object! {
+                    "execute": "block_set_io_throttle",
+                    "arguments": { "id": "disk0", "iops": 100, "bps": 0.5 }
+                },
+            ],
+            config.get_qmp_startup_commands()
+        );
+    }
+
+    #[test]
+    fn launcher_qmp_startup_commands_without_execute_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  qmp_startup_commands:
+                  - execute: stop
+                  - arguments: { id: disk0 }
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.qmp_startup_commands`: the command #1 \
+                must be a hash with the `execute` command name.",
+        );
+    }
+
+    #[test]
+    fn config_with_qemu_arguments_appends_them_to_the_command_line() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+
+            qemu:
+            - sda: /dev/sdb
+        ",
+        )
+        .unwrap()
+        .with_qemu_arguments(&[String::from("-S"), String::from("-no-reboot")]);
+
+        assert_eq!(
+            vec!["-sda", "/dev/sdb", "-S", "-no-reboot", "-qmp", "stdio"],
+            config.get_command_line_options()
+        );
+    }
+
+    #[test]
+    fn get_changes_splits_live_and_restart_requiring_changes() {
+        let current = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              scheduler: fifo
+              priority: 10
+              vcpu_pinning:
+                0:
+                  0:
+                    0: 2
+                    1: 3
+            qemu:
+            - m: 4G
+            ",
+        )
+        .unwrap();
+
+        let grown = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm-next
+              priority: 20
+              scheduler: fifo
+              vcpu_pinning:
+                0:
+                  0:
+                    0: 3
+                    1: 2
+                  1:
+                    0: 4
+            qemu:
+            - m: 8G
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            (
+                vec![
+                    String::from("launcher.priority"),
+                    String::from("launcher.vcpu_pinning")
+                ],
+                vec![String::from("launcher.binary"), String::from("qemu")]
+            ),
+            current.get_changes(&grown)
+        );
+
+        let shrunk = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              scheduler: fifo
+              priority: 10
+              vcpu_pinning:
+                0:
+                  0:
+                    0: 2
+            qemu:
+            - m: 4G
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            (vec![], vec![String::from("launcher.vcpu_pinning")]),
+            current.get_changes(&shrunk)
+        );
+    }
+
+    #[test]
+    fn with_live_changes_takes_over_only_live_keys() {
+        let current = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              scheduler: fifo
+              priority: 10
+            qemu:
+            - m: 4G
+            ",
+        )
+        .unwrap()
+        .with_machine_name("my-vm");
+
+        let reloaded = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm-next
+              deadline:
+                runtime_us: 500
+                deadline_us: 1000
+                period_us: 1000
+              scheduler: deadline
+            qemu:
+            - m: 8G
+            ",
+        )
+        .unwrap();
+
+        let config = current.with_live_changes(&reloaded).unwrap();
+
+        assert_eq!("my-vm", config.get_machine_name());
+        assert_eq!("/usr/bin/qemu-kvm", config.get_qemu_binary_path());
+        assert_eq!(&Some(String::from("deadline")), config.get_scheduler());
+        assert_eq!(None, config.get_priority());
+        assert!(config.get_deadline().is_some());
+        assert_eq!(
+            vec!["-m", "4G", "-qmp", "stdio"],
+            config.get_command_line_options()
+        );
+    }
+
+    #[test]
+    fn launcher_firewall_parses_guest_address_ranges_and_forwards() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              firewall:
+                guest_address: 192.168.122.10
+                allow_from: [ 10.0.0.0/8, 192.168.1.5 ]
+                forward:
+                - host_port: 2222
+                  guest_port: 22
+                - protocol: udp
+                  host_port: 5353
+
+            qemu:
+            - enable-kvm
+        ",
+        )
+        .unwrap();
+
+        let firewall = config.get_firewall().unwrap();
+        assert_eq!("192.168.122.10", firewall.get_guest_address());
+        assert_eq!(
+            &["10.0.0.0/8", "192.168.1.5"][..],
+            firewall.get_allow_from()
+        );
+        assert_eq!(2, firewall.get_forwards().len());
+        assert_eq!("tcp", firewall.get_forwards()[0].get_protocol());
+        assert_eq!(2222, firewall.get_forwards()[0].get_host_port());
+        assert_eq!(22, firewall.get_forwards()[0].get_guest_port());
+        assert_eq!("udp", firewall.get_forwards()[1].get_protocol());
+        assert_eq!(5353, firewall.get_forwards()[1].get_guest_port());
+    }
+
+    #[test]
+    fn launcher_firewall_with_invalid_source_range_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  firewall:
+                    guest_address: 192.168.122.10
+                    allow_from: [ 10.0.0.0/33 ]
+                    forward:
+                    - host_port: 2222
+
+                qemu:
+                - enable-kvm
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.firewall.allow_from` item 0: \
+                                    an IPv4 address or an `address/prefix` range expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_sriov_parses_interfaces_and_virtual_functions() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              sriov:
+              - interface: enp3s0f0
+                vfs:
+                - mac: 52:54:00:AB:CD:EF
+                  vlan: 100
+                - {}
+              - interface: enp3s0f1
+                vfs: 3
+
+            qemu:
+            - enable-kvm
+        ",
+        )
+        .unwrap();
+
+        let sriov = config.get_sriov_interfaces();
+        assert_eq!(2, sriov.len());
+        assert_eq!("enp3s0f0", sriov[0].get_interface());
+        assert_eq!(2, sriov[0].get_virtual_functions().len());
+        assert_eq!(
+            Some("52:54:00:ab:cd:ef"),
+            sriov[0].get_virtual_functions()[0].get_mac()
+        );
+        assert_eq!(Some(100), sriov[0].get_virtual_functions()[0].get_vlan());
+        assert_eq!(None, sriov[0].get_virtual_functions()[1].get_mac());
+        assert_eq!("enp3s0f1", sriov[1].get_interface());
+        assert_eq!(3, sriov[1].get_virtual_functions().len());
+    }
+
+    #[test]
+    fn launcher_sriov_with_invalid_vlan_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  sriov:
+                  - interface: enp3s0f0
+                    vfs:
+                    - vlan: 4095
+
+                qemu:
+                - enable-kvm
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.sriov.0.vfs.0.vlan`: a VLAN ID from 1 to 4094 expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_sriov_with_duplicate_interface_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  sriov:
+                  - interface: enp3s0f0
+                    vfs: 1
+                  - interface: enp3s0f0
+                    vfs: 2
+
+                qemu:
+                - enable-kvm
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.sriov.1.interface`: \
+                        the `enp3s0f0` interface is already configured.",
+        );
+    }
+
+    #[test]
+    fn launcher_paths_parses_declared_directories_and_files() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              paths:
+              - path: /var/lib/vm-foo/images/
+                user: 107
+                group: 36
+                mode: \"0750\"
+              - path: /var/lib/vm-foo/OVMF_VARS.fd
+                type: file
+
+            qemu:
+            - enable-kvm
+            ",
+        )
+        .unwrap();
+
+        let paths = config.get_paths();
+        assert_eq!(2, paths.len());
+        assert_eq!("/var/lib/vm-foo/images", paths[0].get_path());
+        assert_eq!(PathKind::Directory, paths[0].get_kind());
+        assert_eq!(Some(0o750), paths[0].get_mode());
+        assert_eq!(Some(107), paths[0].get_user());
+        assert_eq!(Some(36), paths[0].get_group());
+        assert_eq!("/var/lib/vm-foo/OVMF_VARS.fd", paths[1].get_path());
+        assert_eq!(PathKind::File, paths[1].get_kind());
+        assert_eq!(None, paths[1].get_mode());
+        assert_eq!(None, paths[1].get_user());
+        assert_eq!(
+            vec![("launcher.paths", "CAP_CHOWN")],
+            config.get_required_capabilities()
+        );
+    }
+
+    #[test]
+    fn launcher_paths_with_invalid_entry_returns_error() {
+        let config = |paths: &str| {
+            Config::new(format!(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  paths:
+{}
+
+                qemu:
+                - enable-kvm
+                ",
+                paths
+            ))
+        };
+
+        assert_error(
+            config("                  - path: images"),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.paths.0.path`: an absolute path expected.",
+        );
+        assert_error(
+            config("                  - path: /var/lib/vm-foo\n                    mode: 0750"),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.paths.0.mode`: \
+                            an octal mode in a string, e.g. `\"0750\"`, expected.",
+        );
+        assert_error(
+            config("                  - path: /var/lib/vm-foo\n                    type: socket"),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.paths.0.type`: \
+                        either `directory` or `file` expected.",
+        );
+        assert_error(
+            config("                  - path: /var/lib/vm-foo\n                  - path: /var/lib/vm-foo/"),
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.paths.1.path`: \
+                        the `/var/lib/vm-foo` path is already declared.",
+        );
+    }
+
+    #[test]
+    fn launcher_clock_generates_consistent_timekeeping_options() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              clock:
+                base: localtime
+                tsc_frequency: 2893000000
+
+            qemu:
+            - cpu: host
+        ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "-cpu",
+                "host,+kvmclock,+kvmclock-stable-bit,tsc-frequency=2893000000",
+                "-rtc",
+                "base=localtime,driftfix=slew",
+                "-global",
+                "kvm-pit.lost_tick_policy=delay",
+                "-machine",
+                "hpet=off",
+                "-qmp",
+                "stdio",
+            ],
+            config.get_command_line_options()
+        );
+    }
+
+    #[test]
+    fn microvm_and_s390_machines_get_machine_specific_devices() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              clock: utc
+              network:
+              - netdev: user
+              watchdog:
+                action: quit
+
+            qemu:
+            - machine: [ microvm, { accel: kvm } ]
+        ",
+        )
+        .unwrap()
+        .with_machine_name("router");
+
+        assert_eq!(
+            vec![
+                "-machine",
+                "microvm,accel=kvm",
+                "-netdev",
+                "user,id=net0",
+                "-device",
+                "virtio-net-device,netdev=net0,mac=aa:74:b3:d9:57:fb",
+                "-rtc",
+                "base=utc,driftfix=slew",
+                "-global",
+                "kvm-pit.lost_tick_policy=delay",
+                "-device",
+                "ib700",
+                "-watchdog-action",
+                "none",
+                "-qmp",
+                "stdio",
+            ],
+            config.get_command_line_options()
+        );
+
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-system-s390x
+              network:
+              - netdev: user
+              watchdog: {}
+
+            qemu:
+            - M: type=s390-ccw-virtio-9.0
+        ",
+        )
+        .unwrap()
+        .with_machine_name("router");
+
+        assert_eq!(
+            vec![
+                "-M",
+                "type=s390-ccw-virtio-9.0",
+                "-netdev",
+                "user,id=net0",
+                "-device",
+                "virtio-net-ccw,netdev=net0,mac=aa:74:b3:d9:57:fb",
+                "-device",
+                "diag288",
+                "-watchdog-action",
+                "none",
+                "-qmp",
+                "stdio",
+            ],
+            config.get_command_line_options()
+        );
+    }
+
+    #[test]
+    fn s390_machines_with_x86_only_options_return_error() {
+        let config = |launcher: &str| {
+            Config::new(format!(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-system-s390x
+{}
+
+                qemu:
+                - machine: s390-ccw-virtio
+                ",
+                launcher
+            ))
+        };
+
+        assert_error(
+            config("                  clock: utc"),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.clock`: not supported by s390x `s390-ccw-virtio` machines.",
+        );
+        assert_error(
+            config("                  smbios: { serial: ABC123 }"),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.smbios`: not supported by s390x `s390-ccw-virtio` machines.",
+        );
+        assert_error(
+            config("                  watchdog: i6300esb"),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.watchdog.model`: Expected `diag288` on s390x machines.",
+        );
+    }
+
+    #[test]
+    fn launcher_clock_combined_with_rtc_option_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  clock: utc
+
+                qemu:
+                - rtc: base=utc
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.clock`: can not be combined with the `rtc` option \
+                    in the `qemu` section.",
+        );
+    }
+
+    #[test]
+    fn launcher_clock_tsc_frequency_without_cpu_option_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  clock:
+                    tsc_frequency: 2893000000
+
+                qemu:
+                - m: 4G
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.clock.tsc_frequency`: \
+                requires the `cpu` option in the `qemu` section.",
+        );
+    }
+
+    #[test]
+    fn launcher_qmp_position_and_format_control_launcher_qmp_channel() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              qmp_position: first
+              qmp_format: chardev
+
+            qemu:
+            - readconfig: /etc/qemu/vm.cfg
+        ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "-chardev",
+                "stdio,id=launcher-qmp",
+                "-mon",
+                "chardev=launcher-qmp,mode=control",
+                "-readconfig",
+                "/etc/qemu/vm.cfg",
+            ],
+            config.get_command_line_options()
+        );
+    }
+
+    #[test]
+    fn launcher_hash_with_unsupported_qmp_position_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  qmp_position: middle
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.qmp_position`: Expected one of `first` or `last`.",
+        );
+    }
+
+    #[test]
+    fn launcher_hash_with_invalid_qmp_socket_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  qmp_socket: /tmp/qmp.sock,server=off
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.qmp_socket`: a non-empty path without commas expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_qmp_proxy_does_not_add_qemu_options() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              cwd_relative: true
+              qmp_proxy: qmp-proxy.sock
+
+            qemu:
+            - sda: /dev/sdb
+        ",
+        )
+        .unwrap()
+        .with_machine_name("my-vm")
+        .with_runtime_directory("/run/vms");
+
+        assert_eq!(
+            Some(String::from("/run/vms/my-vm/qmp-proxy.sock")),
+            config.get_qmp_proxy()
+        );
+        assert_eq!(
+            vec!["-sda", "/dev/sdb", "-qmp", "stdio"],
+            config.get_command_line_options()
+        );
+    }
+
+    #[test]
+    fn launcher_event_sink_accepts_fifo_and_file_paths() {
+        for (value, expected) in vec![
+            (
+                "fifo:/run/qemu-launcher/my-vm/events",
+                EventSink::Fifo(String::from("/run/qemu-launcher/my-vm/events")),
+            ),
+            (
+                "file:events.log",
+                EventSink::File(String::from("/run/vms/my-vm/events.log")),
+            ),
+        ] {
+            let config = Config::new(format!(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  cwd_relative: true
+                  event_sink: {}
+
+                qemu:
+                - sda: /dev/sdb
+                ",
+                value
+            ))
+            .unwrap()
+            .with_machine_name("my-vm")
+            .with_runtime_directory("/run/vms");
+
+            assert_eq!(Some(expected), config.get_event_sink());
+            assert!(config.should_watch_qmp_events());
+        }
+    }
+
+    #[test]
+    fn launcher_event_sink_without_kind_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  event_sink: /run/events
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.event_sink`: `fifo:<path>` or `file:<path>` expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_hash_with_qmp_proxy_sharing_qmp_socket_path_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  qmp_proxy: /tmp/qmp.sock
+                  qmp_socket: /tmp/qmp.sock
+
+                qemu:
+                - sda: /dev/sdb
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.qmp_proxy`: the path is already used by `launcher.qmp_socket`.",
+        );
+    }
+
+    #[test]
+    fn qemu_section_with_raw_command_line_strings_passed_verbatim() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+
+            qemu:
+            - \"-device vfio-pci,host=01:00.0\"
+            - \"--append 'console=ttyS0 root=/dev/vda'\"
+            - -nographic
+            - enable-kvm
+            - \"-watchdog-action   none\"
+        ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "-device",
+                "vfio-pci,host=01:00.0",
+                "-append",
+                "console=ttyS0 root=/dev/vda",
+                "-nographic",
+                "-enable-kvm",
+                "-watchdog-action",
+                "none",
+                "-qmp",
+                "stdio",
+            ],
+            config.get_command_line_options()
+        );
+
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - sda: /dev/sdb
+                - \"-qmp stdio\"
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse qemu command line option 2. The `-qmp stdio` \
+            channel is reserved for the launcher, use `launcher.qmp_socket` instead.",
+        );
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - \"-- foo\"
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse qemu command line option 1: an option name expected after the dash.",
+        );
+    }
+
+    #[test]
+    fn launcher_storage_daemons_export_nodes_over_nbd_to_the_machine() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              cwd_relative: true
+              storage_daemons:
+              - id: storage0
+                socket: storage0.sock
+                host_cpus: [0, 1]
+                scheduler: fifo
+                priority: 5
+                exports:
+                - node: disk0
+                  blockdev: driver=qcow2,file.driver=file,file.filename=/var/lib/vm-foo/disk0.qcow2
+                - node: iso0
+                  blockdev: [ driver=raw, file.driver=file, file.filename=/srv/install.iso ]
+                  writable: false
+
+            qemu:
+            - enable-kvm
+            ",
+        )
+        .unwrap()
+        .with_machine_name("vm-foo");
+
+        let daemon = &config.get_storage_daemons()[0];
+        assert_eq!("storage0", daemon.get_id());
+        assert_eq!("qemu-storage-daemon", daemon.get_binary());
+        assert_eq!(&[0, 1], daemon.get_host_cpus());
+        assert_eq!(Some("fifo"), daemon.get_scheduler());
+        assert_eq!(Some(5), daemon.get_priority());
+        assert_eq!(
+            vec![
+                "--blockdev",
+                "driver=qcow2,file.driver=file,file.filename=/var/lib/vm-foo/disk0.qcow2,node-name=disk0",
+                "--blockdev",
+                "driver=raw,file.driver=file,file.filename=/srv/install.iso,node-name=iso0",
+                "--nbd-server",
+                "addr.type=unix,addr.path=/run/qemu-launcher/vm-foo/storage0.sock",
+                "--export",
+                "type=nbd,id=export-disk0,node-name=disk0,name=disk0,writable=on",
+                "--export",
+                "type=nbd,id=export-iso0,node-name=iso0,name=iso0,writable=off",
+            ],
+            config.get_storage_daemon_arguments(daemon)
+        );
+        assert_eq!(
+            vec![
+                "-enable-kvm",
+                "-blockdev",
+                "driver=nbd,node-name=disk0,server.type=unix,\
+                server.path=/run/qemu-launcher/vm-foo/storage0.sock,export=disk0",
+                "-blockdev",
+                "driver=nbd,node-name=iso0,server.type=unix,\
+                server.path=/run/qemu-launcher/vm-foo/storage0.sock,export=iso0,read-only=on",
+                "-qmp",
+                "stdio",
+            ],
+            config.get_command_line_options()
+        );
+        assert!(config
+            .get_required_capabilities()
+            .contains(&("launcher.storage_daemons", "CAP_SYS_NICE")));
+    }
+
+    #[test]
+    fn launcher_storage_daemons_with_invalid_values_returns_error() {
+        let config = |daemons: &str| {
+            Config::new(format!(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning_by_index:
+                    0: 2
+                  storage_daemons:
+{}
+
+                qemu:
+                - enable-kvm
+                ",
+                daemons
+            ))
+        };
+
+        assert_error(
+            config("                  - { id: a, socket: a.sock, exports: [] }"),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.storage_daemons.0.exports`: a non-empty array expected.",
+        );
+        assert_error(
+            config(
+                "                  - { id: a, socket: a.sock, exports: [ { node: d0, blockdev: driver=null-co } ] }
+                  - { id: b, socket: b.sock, exports: [ { node: d0, blockdev: driver=null-co } ] }",
+            ),
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.storage_daemons.1.exports`: \
+            the `d0` node is already exported by another storage daemon.",
+        );
+        assert_error(
+            config(
+                "                  - { id: a, socket: a.sock, host_cpus: [1, 2], exports: [ { node: d0, blockdev: driver=null-co } ] }",
+            ),
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.storage_daemons.0.host_cpus`: \
+            the host CPU `2` is reserved for vCPU pinning.",
+        );
+        assert_error(
+            config(
+                "                  - { id: a, socket: a.sock, scheduler: fifo, exports: [ { node: d0, blockdev: driver=null-co } ] }",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.storage_daemons.0.priority`: a priority from 1 to 99 expected.",
+        );
+        assert_error(
+            config(
+                "                  - { id: a, socket: a.sock, scheduler: batch, priority: 3, exports: [ { node: d0, blockdev: driver=null-co } ] }",
+            ),
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.storage_daemons.0.priority`: \
+            only the `fifo` and `rr` schedulers take a priority.",
+        );
+    }
+
+    #[test]
+    fn launcher_thermal_throttle_picks_the_highest_reached_threshold() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              qmp_socket: /run/vm-foo/qmp.sock
+              thermal_throttle:
+                zone: thermal_zone1
+                thresholds:
+                - temperature: 95
+                  throttle: 80
+                - temperature: 85
+                  throttle: 30
+
+            qemu:
+            - enable-kvm
+            ",
+        )
+        .unwrap();
+
+        let policy = config.get_thermal_throttle().unwrap();
+        assert_eq!("thermal_zone1", policy.get_zone());
+        assert_eq!(0, policy.get_throttle(84999));
+        assert_eq!(30, policy.get_throttle(85000));
+        assert_eq!(80, policy.get_throttle(101000));
+    }
+
+    #[test]
+    fn launcher_thermal_throttle_with_invalid_values_returns_error() {
+        let config = |policy: &str, qmp_socket: &str| {
+            Config::new(format!(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  {}
+                  thermal_throttle:
+{}
+
+                qemu:
+                - enable-kvm
+                ",
+                qmp_socket, policy
+            ))
+        };
+        let qmp_socket = "qmp_socket: /run/vm-foo/qmp.sock";
+
+        assert_error(
+            config(
+                "                    zone: ../foo\n                    thresholds: []",
+                qmp_socket,
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.thermal_throttle.zone`: \
+            a thermal zone name, e.g. `thermal_zone0`, expected.",
+        );
+        assert_error(
+            config(
+                "                    zone: thermal_zone0\n                    thresholds: []",
+                qmp_socket,
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.thermal_throttle.thresholds`: a non-empty array expected.",
+        );
+        assert_error(
+            config(
+                "                    zone: thermal_zone0\n                    thresholds:\n                    - { temperature: 90, throttle: 101 }",
+                qmp_socket,
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.thermal_throttle.thresholds.0.throttle`: \
+            a percentage from 1 to 100 expected.",
+        );
+        assert_error(
+            config(
+                "                    zone: thermal_zone0\n                    thresholds:\n                    - { temperature: 90, throttle: 10 }\n                    - { temperature: 90, throttle: 20 }",
+                qmp_socket,
+            ),
+            ErrorKind::InvalidData,
+            "Wrong value for `launcher.thermal_throttle.thresholds.1.temperature`: \
+            the 90 degrees threshold is already declared.",
+        );
+        assert_error(
+            config(
+                "                    zone: thermal_zone0\n                    thresholds:\n                    - { temperature: 90, throttle: 10 }",
+                "",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.thermal_throttle`: either `launcher.qmp_socket` \
+            or `launcher.qmp_proxy` is required to throttle the vCPUs.",
+        );
+    }
+
+    #[test]
+    fn qemu_section_with_qmp_stdio_option_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - sda: /dev/sdb
+                - qmp: stdio
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse qemu command line option 2. The `-qmp stdio` \
+            channel is reserved for the launcher, use `launcher.qmp_socket` instead.",
+        );
+    }
+
+    #[test]
+    fn launcher_cwd_relative_rewrites_relative_paths_into_runtime_directory() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              cwd_relative: true
+              qmp_socket: qmp.sock
+
+            qemu:
+            - pidfile: qemu.pid
+            - chardev: [ socket, id: mon0, path: monitor.sock, server: on, logfile: /var/log/mon.log ]
+            - serial: unix:serial.sock,server=on
+            - drive: file=disk.qcow2
+        ",
+        )
+        .unwrap()
+        .with_machine_name("my-vm")
+        .with_runtime_directory("/run/vms");
+
+        assert!(config.is_cwd_relative());
+        assert_eq!(
+            vec![
+                "-pidfile",
+                "/run/vms/my-vm/qemu.pid",
+                "-chardev",
+                "socket,id=mon0,path=/run/vms/my-vm/monitor.sock,server=on,logfile=/var/log/mon.log",
+                "-serial",
+                "unix:/run/vms/my-vm/serial.sock,server=on",
+                "-drive",
+                "file=disk.qcow2",
+                "-qmp",
+                "unix:/run/vms/my-vm/qmp.sock,server=on,wait=off",
+                "-qmp",
+                "stdio"
+            ],
+            config.get_command_line_options()
+        );
+    }
+
+    #[test]
+    fn launcher_max_runtime_and_idle_shutdown_are_parsed() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              max_runtime: 7200
+              idle_shutdown:
+                after: 900
+
+            qemu:
+            - enable-kvm
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(Some(Duration::from_secs(7200)), config.get_max_runtime());
+        let policy = config.get_idle_shutdown().unwrap();
+        assert_eq!(Duration::from_secs(900), policy.get_after());
+        assert_eq!(5, policy.get_cpu_percent());
+
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+
+            qemu:
+            - enable-kvm
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(None, config.get_max_runtime());
+        assert_eq!(true, config.get_idle_shutdown().is_none());
+    }
+
+    #[test]
+    fn launcher_max_runtime_and_idle_shutdown_with_invalid_values_return_error() {
+        let config = |launcher: &str| {
+            Config::new(format!(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  {}
+
+                qemu:
+                - enable-kvm
+                ",
+                launcher
+            ))
+        };
+
+        assert_error(
+            config("max_runtime: 1h"),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.max_runtime`: a positive number of seconds expected.",
+        );
+        assert_error(
+            config("idle_shutdown: 900"),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.idle_shutdown`: a hash expected.",
+        );
+        assert_error(
+            config("idle_shutdown: { after: 900, paused: true }"),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.idle_shutdown`: unknown key `paused`, \
+            `after` or `cpu_percent` expected.",
+        );
+        assert_error(
+            config("idle_shutdown: { cpu_percent: 5 }"),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.idle_shutdown.after`: a positive number of seconds expected.",
+        );
+        assert_error(
+            config("idle_shutdown: { after: 900, cpu_percent: 0 }"),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.idle_shutdown.cpu_percent`: \
+            a percentage from 1 to 100 expected.",
+        );
+    }
+
+    #[test]
+    fn launcher_readconfig_keeps_the_full_command_line_in_the_configuration() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              readconfig: true
+
+            qemu:
+            - device: virtio-rng-pci
+        ",
+        )
+        .unwrap();
+
+        assert!(config.should_use_readconfig());
+        assert_eq!(
+            vec!["-device", "virtio-rng-pci", "-qmp", "stdio"],
+            config.get_command_line_options()
+        );
+
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  readconfig: yes
+                qemu: []
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Invalid value for `launcher.readconfig` value: a boolean is expected.",
+        );
+    }
+
+    #[test]
+    fn missing_qemu_section_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse qemu command line options: \
+                missing or invalid value, array expected.",
+        );
+    }
+
+    #[test]
+    fn non_array_qemu_section_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+
+                qemu: {}
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse qemu command line options: \
+                missing or invalid value, array expected.",
+        );
+    }
+
+    #[test]
+    fn qemu_section_with_non_hash_or_string_argument_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - true
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse qemu command line option 1. Every \
+                option must be either a string or a hash.",
+        );
+    }
+
+    #[test]
+    fn qemu_section_with_empty_argument_hash_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - {}
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Found a command line argument 1 with 0 pairs \
+                in the hash, but exactly one is expected.",
+        );
+    }
+
+    #[test]
+    fn qemu_section_with_argument_hash_containing_multiple_items_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - { a: 1, b: 2 }
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Found a command line argument 1 with 2 pairs \
+                in the hash, but exactly one is expected.",
+        );
+    }
+
+    #[test]
+    fn qemu_section_with_argument_hash_containing_non_string_keys_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - { 3: nope }
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Argument 1 name must be a string.",
+        );
+    }
+
+    #[test]
+    fn qemu_section_with_argument_hash_containing_invalid_values_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - still_no:
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Invalid value for `still_no` qemu argument 1: expected \
+                a string, number or a hash with a single pair.",
+        );
+    }
+
+    #[test]
+    fn qemu_section_with_argument_hash_containing_empty_array_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - foo: []
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Empty value for `foo` argument, consider \
+                using a string instead of an array.",
+        );
+    }
+
+    #[test]
+    fn qemu_section_with_argument_hash_containing_properties_hash_with_no_items_returns_error() {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - foo: [ bar: ]
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse a value for `foo/bar` argument: \
+                value must be either a string or a number.",
+        );
+    }
+
+    #[test]
+    fn qemu_section_with_argument_hash_containing_properties_hash_with_multiple_items_returns_error(
+    ) {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - foo: [ { bar: baz, this: wontwork } ]
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse a value for `foo` argument: \
+                a hash with multiple pairs found.",
+        );
+    }
+
+    #[test]
+    fn qemu_section_with_argument_hash_containing_properties_hash_with_non_string_keys_returns_error(
+    ) {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - foo: [ { 3: bad } ]
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse a value for `foo` argument: \
+                a property name must be a string.",
+        );
+    }
+
+    #[test]
+    fn qemu_section_with_argument_hash_containing_properties_hash_with_invalid_values_returns_error(
+    ) {
+        assert_error(
+            Config::new(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - foo: [ bad: [] ]
+            ",
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse a value for `foo/bad` argument: \
+                value must be either a string or a number.",
+        );
+    }
+
+    #[test]
+    fn check_file_permissions_accepts_files_owned_by_root_or_current_user() {
+        assert!(check_file_permissions("/etc/vm.yml", 0o100644, 0, 1000).is_ok());
+        assert!(check_file_permissions("/etc/vm.yml", 0o100600, 1000, 1000).is_ok());
+        assert!(check_file_permissions("/etc/vm.yml", 0o100444, 0, 0).is_ok());
+    }
+
+    #[test]
+    fn check_file_permissions_returns_error_for_wrong_owner() {
+        assert_error!(
+            ErrorKind::PermissionDenied,
+            "The `/etc/vm.yml` configuration file is owned by the user with ID 1000, \
+                expected to be owned by root or the user with ID 0.",
+            check_file_permissions("/etc/vm.yml", 0o100644, 1000, 0)
+        );
+    }
+
+    #[test]
+    fn check_file_permissions_returns_error_for_group_or_world_writable_file() {
+        assert_error!(
+            ErrorKind::PermissionDenied,
+            "The `/etc/vm.yml` configuration file is writable by the group or other users (mode 0664).",
+            check_file_permissions("/etc/vm.yml", 0o100664, 0, 0)
+        );
+        assert_error!(
+            ErrorKind::PermissionDenied,
+            "The `/etc/vm.yml` configuration file is writable by the group or other users (mode 0646).",
+            check_file_permissions("/etc/vm.yml", 0o100646, 0, 0)
+        );
+    }
+
+    #[test]
+    fn from_file_returns_error_if_fs_read_to_string_fails() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/etc/config/my_vm.yml" =>
+                Err(::std::io::Error::new(::std::io::ErrorKind::Other, "read_to_string()")) },
+        );
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to read configuration file `/etc/config/my_vm.yml`: read_to_string()",
+            Config::from_file("/etc/config/my_vm.yml", &|_| Ok({}))
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn from_file_returns_parsed_config_read_from_file() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/etc/config/my_vm.yml" => Ok(
+                "
+                launcher:
+                  binary: /bin/true
+                qemu:
+                - sda: /tmp/vm.qcow
+                "
+            .to_owned()) },
+        );
+
+        let config = Config::from_file("/etc/config/my_vm.yml", &|_| Ok({})).unwrap();
+
+        assert_eq!("my_vm", config.get_machine_name());
+        assert_eq!("/bin/true", config.get_qemu_binary_path());
+        assert_eq!(
+            vec!["-sda", "/tmp/vm.qcow", "-qmp", "stdio"],
+            config.get_command_line_options()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn from_file_parses_json_config_selected_by_extension() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/etc/config/my_vm.json" => Ok(
+                r#"{
+                    "launcher": { "binary": "/bin/true" },
+                    "qemu": [ { "sda": "/tmp/vm.qcow" } ]
+                }"#
+            .to_owned()) },
+        );
+
+        let config = Config::from_file("/etc/config/my_vm.json", &|_| Ok({})).unwrap();
+
+        assert_eq!("my_vm", config.get_machine_name());
+        assert_eq!("/bin/true", config.get_qemu_binary_path());
+        assert_eq!(
+            vec!["-sda", "/tmp/vm.qcow", "-qmp", "stdio"],
+            config.get_command_line_options()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn from_file_checks_every_file_before_reading_it() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/etc/config/my_vm.yml" => Ok(
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  profile: latency
+                  vcpu_pinning_file: pinning.yml
+                  env_file: my_vm.env
+                qemu: []
+                "
+            .to_owned()) },
+            { "/etc/config/profiles/latency.yml" => Ok("launcher:\n  priority: 5\n".to_owned()) },
+            { "/etc/config/pinning.yml" => Ok("launcher:\n  vcpu_pinning_by_index: { 0: 3 }\n".to_owned()) },
+            { "/etc/config/my_vm.env" => Ok("SPICE_DEBUG=1\n".to_owned()) },
+        );
+
+        let checked = RefCell::new(vec![]);
+        let config = Config::from_file("/etc/config/my_vm.yml", &|path| {
+            checked.borrow_mut().push(path.to_owned());
+            Ok({})
+        })
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "/etc/config/my_vm.yml",
+                "/etc/config/profiles/latency.yml",
+                "/etc/config/pinning.yml",
+                "/etc/config/my_vm.env",
+            ],
+            checked.into_inner()
+        );
+        assert_eq!(Some(5), config.get_priority());
+        assert_eq!(vec![3], config.get_pinned_host_cpus());
+        assert_eq!(
+            Some(&"1".to_owned()),
+            config.get_env_vars().get("SPICE_DEBUG")
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn from_file_refuses_env_file_writable_by_others_without_reading_it() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/etc/config/my_vm.yml" => Ok(
+                "launcher:\n  binary: /usr/bin/qemu-kvm\n  env_file: /etc/config/my_vm.env\nqemu: []\n"
+                    .to_owned()) },
+            { "/etc/config/my_vm.yml" => Ok(
+                "launcher:\n  binary: /usr/bin/qemu-kvm\n  env_file: /etc/config/my_vm.env\nqemu: []\n"
+                    .to_owned()) },
+        );
+
+        for mode in &[0o100664, 0o100646] {
+            assert_error!(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "The `/etc/config/my_vm.env` configuration file is writable by the group or other \
+                    users (mode {:04o}).",
+                    mode & 0o7777
+                ),
+                Config::from_file("/etc/config/my_vm.yml", &|path| match path {
+                    "/etc/config/my_vm.env" => check_file_permissions(path, *mode, 0, 0),
+                    _ => check_file_permissions(path, 0o100644, 0, 0),
+                })
+            );
+        }
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn load_json_produces_the_same_config_as_yaml() {
+        let json = Config::load_json(
+            r#"{
+                "launcher": {
+                    "binary": "/usr/bin/qemu-kvm",
+                    "priority": 5,
+                    "vcpu_pinning_by_index": { "0": 2, "1": 3 },
+                    "kvm": { "halt_poll_ns": 4294967295 }
+                },
+                "qemu": [
+                    "enable-kvm",
+                    { "machine": [ "q35", { "accel": "kvm" } ] },
+                    { "smp": "2" }
+                ]
+            }"#,
+            "/etc/config/profiles",
+            &|_| Ok({}),
+        )
+        .unwrap();
+        let yaml = Config::load(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              priority: 5
+              vcpu_pinning_by_index:
+                0: 2
+                1: 3
+              kvm:
+                halt_poll_ns: 4294967295
             qemu:
-            - realtime
-            - cpu: host
-            - smp: [ cpus: 1, cores: 2, threads: 2 ]
-            - m: [ 4096, slots: 2 ]
-            - numa: 1
-            - seed: 1.234
-            - device: [ vfio-pci, 3, 9.87 ]
-            - device: [ vfio-pci, multifunction: on, addr: 0.1 ]
-            - { device: [ usb-mouse ] }
-            - device:
-              - vfio-pci
-              - multifunction: on
-              - { addr: 0.2 }
-        ",
+            - enable-kvm
+            - machine: [ q35, { accel: kvm } ]
+            - smp: 2
+            ",
+            "/etc/config/profiles",
+            &|_| Ok({}),
         )
         .unwrap();
 
-        assert_eq!(Some(100), config.get_user());
-        assert_eq!(Some(200), config.get_group());
+        assert_eq!(
+            yaml.get_command_line_options(),
+            json.get_command_line_options()
+        );
+        assert_eq!(yaml.get_priority(), json.get_priority());
+        assert_eq!(yaml.get_pinned_host_cpus(), json.get_pinned_host_cpus());
+        assert_eq!(yaml.get_kvm_parameters(), json.get_kvm_parameters());
+    }
 
-        let expected_cpu_pinnig: &Vec<(usize, usize, usize, usize)> =
-            &vec![(0, 0, 0, 2), (0, 0, 1, 6), (0, 1, 0, 3), (0, 1, 1, 7)];
-        assert_eq!(expected_cpu_pinnig, config.get_cpu_pinning());
+    #[test]
+    fn load_json_reports_errors_like_yaml() {
+        assert_error(
+            Config::load_json(
+                r#"{ "launcher": { "binary": "#,
+                "/etc/config/profiles",
+                &|_| Ok({}),
+            ),
+            ErrorKind::InvalidData,
+            "Unexpected end of JSON",
+        );
+        assert_error(
+            Config::load_json(
+                r#"{
+                    "launcher": {
+                        "binary": "/usr/bin/qemu-kvm",
+                        "kvm": { "halt_poll_ns": 18446744073709551616 }
+                    },
+                    "qemu": []
+                }"#,
+                "/etc/config/profiles",
+                &|_| Ok({}),
+            ),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.kvm.halt_poll_ns`: a non-negative integer expected.",
+        );
+    }
 
-        assert_eq!("/usr/bin/qemu-kvm", config.get_qemu_binary_path());
-        assert_eq!(true, config.should_clear_env());
-        assert_eq!(Some(1), config.get_priority());
-        assert_eq!(&Some(String::from("fifo")), config.get_scheduler());
-        assert_eq!("bar", config.get_env_vars()["STRING"]);
-        assert_eq!("1", config.get_env_vars()["INTEGER"]);
-        assert_eq!("1.0", config.get_env_vars()["REAL"]);
-        assert_eq!("true", config.get_env_vars()["BOOLEAN"]);
+    #[test]
+    fn launcher_ksm_generates_machine_mem_merge_option() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              ksm: off
+            qemu:
+            - machine: [ q35, { accel: kvm } ]
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(Some(false), config.get_ksm());
         assert_eq!(
             vec![
-                "-realtime",
-                "-cpu",
-                "host",
-                "-smp",
-                "cpus=1,cores=2,threads=2",
-                "-m",
-                "4096,slots=2",
-                "-numa",
-                "1",
-                "-seed",
-                "1.234",
-                "-device",
-                "vfio-pci,3,9.87",
-                "-device",
-                "vfio-pci,multifunction=on,addr=0.1",
-                "-device",
-                "usb-mouse",
-                "-device",
-                "vfio-pci,multifunction=on,addr=0.2",
+                "-machine",
+                "q35,accel=kvm",
+                "-machine",
+                "mem-merge=off",
                 "-qmp",
-                "stdio",
+                "stdio"
             ],
             config.get_command_line_options()
         );
     }
 
     #[test]
-    fn config_with_absent_optional_fields_passed_properly() {
+    fn launcher_binary_sha256_is_normalized_to_lowercase() {
         let config = Config::new(
             "
             launcher:
               binary: /usr/bin/qemu-kvm
-
-            qemu:
-            - sda: /dev/sdb
-        ",
+              binary_sha256: E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855
+            qemu: []
+            ",
         )
         .unwrap();
 
-        assert_eq!("/usr/bin/qemu-kvm", config.get_qemu_binary_path());
-        assert_eq!(None, config.get_user());
-        assert_eq!(None, config.get_group());
-        assert_eq!(
-            &Vec::<(usize, usize, usize, usize)>::new(),
-            config.get_cpu_pinning()
-        );
-        assert_eq!(false, config.should_clear_env());
-        assert_eq!(None, config.get_priority());
-        assert_eq!(&None, config.get_scheduler());
-        assert_eq!(&HashMap::<String, String>::new(), config.get_env_vars());
         assert_eq!(
-            vec!["-sda", "/dev/sdb", "-qmp", "stdio"],
-            config.get_command_line_options()
+            Some("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+            config.get_binary_sha256()
         );
     }
 
-    fn assert_error(result: Result<Config, Error>, kind: ErrorKind, message: &str) {
-        match result {
-            Ok(_) => panic!("Parser did not produce an error for invalid data."),
-            Err(e) => {
-                assert_eq!(kind, e.kind());
-                assert_eq!(message, format!("{}", e));
-            }
-        }
-    }
-
     #[test]
-    fn empty_configuration_returns_error() {
-        assert_error(
-            Config::new(""),
-            ErrorKind::InvalidData,
-            "Supplied configuration is empty.",
+    fn launcher_systemd_scope_accepts_scope_unit_names() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              systemd_scope: machine-qemu\\x2dfoo.scope
+            qemu: []
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some("machine-qemu\\x2dfoo.scope"),
+            config.get_systemd_scope()
         );
+
+        for scope in &["machine-foo.service", ".scope", "machine/foo.scope", "true"] {
+            assert_error(
+                Config::new(&format!(
+                    "
+                    launcher:
+                      binary: /usr/bin/qemu-kvm
+                      systemd_scope: {}
+                    qemu: []
+                    ",
+                    scope
+                )),
+                ErrorKind::InvalidData,
+                "Failed to parse `launcher.systemd_scope`: a systemd scope unit name, e.g. `machine-foo.scope`, expected.",
+            );
+        }
     }
 
     #[test]
-    fn launcher_user_invalid_value_returns_error() {
+    fn launcher_binary_sha256_with_invalid_digest_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
-                  user: user
                   binary: /usr/bin/qemu-kvm
-
-                qemu:
-                - sda: /dev/sdb
-            ",
+                  binary_sha256: e3b0c44298fc1c149afbf4c8996fb924
+                qemu: []
+                ",
             ),
             ErrorKind::InvalidData,
-            "Invalid value for `launcher.user` option: unsigned 16-bit integer expected.",
+            "Failed to parse `launcher.binary_sha256`: a hexadecimal SHA-256 digest of 64 characters expected.",
         );
     }
 
     #[test]
-    fn launcher_user_negative_value_returns_error() {
+    fn launcher_ksm_with_invalid_value_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
-                  user: -1
                   binary: /usr/bin/qemu-kvm
-
-                qemu:
-                - sda: /dev/sdb
-            ",
+                  ksm: sometimes
+                qemu: []
+                ",
             ),
             ErrorKind::InvalidData,
-            "Invalid value for `launcher.user` option: given value is \
-                out of bounds, expected an unsigned 16-bit integer.",
+            "Failed to parse `launcher.ksm`: Expected one of `on` or `off`.",
         );
     }
 
     #[test]
-    fn launcher_user_too_high_value_returns_error() {
-        assert_error(
-            Config::new(
-                "
-                launcher:
-                  user: 4294967295
-                  binary: /usr/bin/qemu-kvm
-
-                qemu:
-                - sda: /dev/sdb
+    fn launcher_merge_qemu_log_enables_message_timestamps() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              merge_qemu_log: true
+            qemu:
+            - enable-kvm
             ",
-            ),
-            ErrorKind::InvalidData,
-            "Invalid value for `launcher.user` option: given value is \
-                out of bounds, expected an unsigned 16-bit integer.",
+        )
+        .unwrap();
+
+        assert!(config.should_merge_qemu_log());
+        assert_eq!(
+            vec!["-enable-kvm", "-msg", "timestamp=on", "-qmp", "stdio"],
+            config.get_command_line_options()
         );
     }
 
     #[test]
-    fn launcher_group_invalid_value_returns_error() {
-        assert_error(
-            Config::new(
-                "
-                launcher:
-                  group: group
-                  binary: /usr/bin/qemu-kvm
+    fn launcher_io_raises_the_open_files_limit_and_adds_iothreads() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              io:
+                iothreads: true
+            qemu:
+            - drive: file=/srv/images/a.qcow2,if=none,id=disk0,aio=io_uring,cache=none
+            - device: virtio-blk-pci,drive=disk0,num-queues=4
+            - blockdev: driver=file,node-name=proto1,filename=/srv/images/b.raw
+            - blockdev: driver=raw,node-name=disk1,file=proto1
+            - device: virtio-scsi-pci,id=scsi0,iothread=io-custom
+            - device: scsi-hd,drive=disk1,bus=scsi0.0
+            ",
+        )
+        .unwrap();
 
-                qemu:
-                - sda: /dev/sdb
+        assert_eq!(Some(1024 + 32 * 2 + 4 * 5), config.get_open_files_limit());
+        assert_eq!(true, config.uses_io_uring());
+        assert_eq!(
+            vec![
+                "-drive",
+                "file=/srv/images/a.qcow2,if=none,id=disk0,aio=io_uring,cache=none",
+                "-object",
+                "iothread,id=launcher-iothread0",
+                "-device",
+                "virtio-blk-pci,drive=disk0,num-queues=4,iothread=launcher-iothread0",
+                "-blockdev",
+                "driver=file,node-name=proto1,filename=/srv/images/b.raw",
+                "-blockdev",
+                "driver=raw,node-name=disk1,file=proto1",
+                "-device",
+                "virtio-scsi-pci,id=scsi0,iothread=io-custom",
+                "-device",
+                "scsi-hd,drive=disk1,bus=scsi0.0",
+                "-qmp",
+                "stdio"
+            ],
+            config.get_command_line_options()
+        );
+
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              io: { nofile: 65536 }
+            qemu:
+            - device: virtio-blk-pci,drive=disk0
             ",
-            ),
-            ErrorKind::InvalidData,
-            "Invalid value for `launcher.group` option: unsigned 16-bit integer expected.",
+        )
+        .unwrap();
+
+        assert_eq!(Some(65536), config.get_open_files_limit());
+        assert_eq!(false, config.uses_io_uring());
+        assert_eq!(
+            vec!["-device", "virtio-blk-pci,drive=disk0", "-qmp", "stdio"],
+            config.get_command_line_options()
         );
+
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+            qemu: []
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(None, config.get_open_files_limit());
     }
 
     #[test]
-    fn launcher_group_negative_value_returns_error() {
-        assert_error(
-            Config::new(
-                "
-                launcher:
-                  group: -1
-                  binary: /usr/bin/qemu-kvm
+    fn launcher_io_with_invalid_values_returns_error() {
+        for (io, message) in vec![
+            ("auto", "Failed to parse `launcher.io`: a hash expected."),
+            (
+                "{ iothreads: yes please }",
+                "Failed to parse `launcher.io.iothreads`: boolean expected.",
+            ),
+            (
+                "{ nofile: 0 }",
+                "Failed to parse `launcher.io.nofile`: `auto` or a positive integer expected.",
+            ),
+            (
+                "{ nofile: many }",
+                "Failed to parse `launcher.io.nofile`: `auto` or a positive integer expected.",
+            ),
+        ] {
+            assert_error(
+                Config::new(format!(
+                    "
+                    launcher:
+                      binary: /usr/bin/qemu-kvm
+                      io: {}
+                    qemu: []
+                    ",
+                    io
+                )),
+                ErrorKind::InvalidData,
+                message,
+            );
+        }
+    }
 
-                qemu:
-                - sda: /dev/sdb
+    #[test]
+    fn launcher_kvm_sets_halt_polling_parameters() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              kvm:
+                halt_poll_ns: 0
+                halt_poll_ns_shrink: 2
+            qemu: []
             ",
-            ),
-            ErrorKind::InvalidData,
-            "Invalid value for `launcher.group` option: given value is \
-                out of bounds, expected an unsigned 16-bit integer.",
+        )
+        .unwrap();
+
+        assert_eq!(
+            &[
+                (String::from("halt_poll_ns"), 0),
+                (String::from("halt_poll_ns_shrink"), 2)
+            ],
+            config.get_kvm_parameters()
+        );
+        assert_eq!(
+            vec![("launcher.kvm", "CAP_DAC_OVERRIDE")],
+            config.get_required_capabilities()
         );
+
+        for (kvm, message) in vec![
+            ("off", "Failed to parse `launcher.kvm`: a hash expected."),
+            (
+                "{ nx_huge_pages: 0 }",
+                "Failed to parse `launcher.kvm`: one of `halt_poll_ns`, `halt_poll_ns_grow`, \
+                `halt_poll_ns_grow_start`, `halt_poll_ns_shrink` keys expected.",
+            ),
+            (
+                "{ halt_poll_ns: -1 }",
+                "Failed to parse `launcher.kvm.halt_poll_ns`: a non-negative integer expected.",
+            ),
+            (
+                "{ halt_poll_ns_grow: 4294967296 }",
+                "Failed to parse `launcher.kvm.halt_poll_ns_grow`: a non-negative integer expected.",
+            ),
+        ] {
+            assert_error(
+                Config::new(format!(
+                    "
+                    launcher:
+                      binary: /usr/bin/qemu-kvm
+                      kvm: {}
+                    qemu: []
+                    ",
+                    kvm
+                )),
+                ErrorKind::InvalidData,
+                message,
+            );
+        }
     }
 
     #[test]
-    fn launcher_group_too_high_value_returns_error() {
-        assert_error(
-            Config::new(
-                "
-                launcher:
-                  group: 4294967295
-                  binary: /usr/bin/qemu-kvm
+    fn launcher_serial_log_adds_a_logging_serial_chardev() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              cwd_relative: true
+              serial_log:
+                path: /var/log/qemu-launcher/vm-foo/serial.log
+                socket: serial.sock
+                max_size_mb: 4
+                keep: 2
+            qemu:
+            - enable-kvm
+            ",
+        )
+        .unwrap()
+        .with_machine_name("vm-foo");
 
-                qemu:
-                - sda: /dev/sdb
+        let log = config.get_serial_log().unwrap();
+        assert_eq!(2, log.get_keep());
+        assert_eq!(4 * 1024 * 1024, log.get_max_size());
+        assert_eq!(
+            vec![
+                "-enable-kvm",
+                "-chardev",
+                "socket,path=/run/qemu-launcher/vm-foo/serial.sock,server=on,wait=off,\
+                id=launcher-serial,logfile=/var/log/qemu-launcher/vm-foo/serial.log,logappend=on",
+                "-serial",
+                "chardev:launcher-serial",
+                "-qmp",
+                "stdio"
+            ],
+            config.get_command_line_options()
+        );
+
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              serial_log: /var/log/vm-foo-serial.log
+            qemu: []
             ",
-            ),
-            ErrorKind::InvalidData,
-            "Invalid value for `launcher.group` option: given value is \
-                out of bounds, expected an unsigned 16-bit integer.",
+        )
+        .unwrap();
+
+        assert_eq!(5, config.get_serial_log().unwrap().get_keep());
+        assert_eq!(
+            vec![
+                "-chardev",
+                "null,id=launcher-serial,logfile=/var/log/vm-foo-serial.log,logappend=on",
+                "-serial",
+                "chardev:launcher-serial",
+                "-qmp",
+                "stdio"
+            ],
+            config.get_command_line_options()
         );
     }
 
     #[test]
-    fn launcher_vcpu_pinning_not_a_hash_returns_error() {
-        assert_error(
-            Config::new(
+    fn launcher_serial_log_with_invalid_values_returns_error() {
+        let config = |log: &str, qemu: &str| {
+            Config::new(format!(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
-                  vcpu_pinning: []
+                  serial_log: {}
+                qemu: {}
+                ",
+                log, qemu
+            ))
+        };
 
-                qemu:
-                - sda: /dev/sdb
-            ",
-            ),
+        assert_error(
+            config("/var/log/serial,log", "[]"),
             ErrorKind::InvalidData,
-            "Failed to parse `launcher.vcpu_pinning` configuration: a hash is expected.",
+            "Failed to parse `launcher.serial_log`: a non-empty path without commas expected.",
         );
-    }
-
-    #[test]
-    fn launcher_vcpu_pinning_socket_id_not_an_integer_returns_error() {
         assert_error(
-            Config::new(
-                "
-                launcher:
-                  binary: /usr/bin/qemu-kvm
-                  vcpu_pinning:
-                    a:
-
-                qemu:
-                - sda: /dev/sdb
-            ",
-            ),
+            config("{ path: serial.log, keep: 101 }", "[]"),
             ErrorKind::InvalidData,
-            "Failed to parse `launcher.vcpu_pinning`: the socket \
-                ID must be an integer greater or equal to zero.",
+            "Failed to parse `launcher.serial_log.keep`: a number of rotated logs from 0 to 100 expected.",
+        );
+        assert_error(
+            config("{ path: serial.log, rotate: true }", "[]"),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.serial_log`: a hash with the `path`, `socket`, `max_size_mb` \
+            and `keep` keys expected.",
+        );
+        assert_error(
+            config("serial.log", "[ serial: stdio ]"),
+            ErrorKind::InvalidData,
+            "Failed to parse `launcher.serial_log`: can not be combined with the `serial` option \
+            in the `qemu` section.",
         );
     }
 
     #[test]
-    fn launcher_vcpu_pinning_negative_socket_id_returns_error() {
-        assert_error(
-            Config::new(
-                "
-                launcher:
-                  binary: /usr/bin/qemu-kvm
-                  vcpu_pinning:
-                    -1:
-
-                qemu:
-                - sda: /dev/sdb
+    fn launcher_depends_on_is_parsed() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              depends_on: [ storage, router ]
+            qemu: []
             ",
-            ),
-            ErrorKind::InvalidData,
-            "Failed to parse `launcher.vcpu_pinning`: the socket \
-                ID must be an integer greater or equal to zero.",
+        )
+        .unwrap();
+
+        assert_eq!(
+            &vec!["storage".to_owned(), "router".to_owned()],
+            config.get_dependencies()
         );
     }
 
     #[test]
-    fn launcher_vcpu_pinning_socket_value_is_not_a_hash_returns_error() {
+    fn launcher_post_start_hooks_are_parsed() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              post_start_hooks: [ /usr/local/bin/tune-irqs, /usr/local/bin/notify ]
+            qemu: []
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            &vec![
+                "/usr/local/bin/tune-irqs".to_owned(),
+                "/usr/local/bin/notify".to_owned()
+            ],
+            config.get_post_start_hooks()
+        );
+
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
-                  vcpu_pinning:
-                    0: []
-
-                qemu:
-                - sda: /dev/sdb
-            ",
+                  post_start_hooks: [ /usr/local/bin/tune-irqs, notify ]
+                qemu: []
+                ",
             ),
             ErrorKind::InvalidData,
-            "Failed to parse `launcher.vcpu_pinning.0`: a hash expected.",
+            "Failed to parse `launcher.post_start_hooks` item 1: an absolute path expected.",
         );
     }
 
     #[test]
-    fn launcher_vcpu_pinning_core_id_not_an_integer_returns_error() {
+    fn launcher_defer_usb_host_holds_host_usb_devices_back() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              defer_usb_host: true
+            qemu:
+            - device: qemu-xhci,id=xhci
+            - device: usb-host,vendorid=0x046d,productid=0xc52b,id=kbd
+            - device: [ driver=usb-host, hostbus: 1, hostaddr: 5 ]
+            - device: usb-tablet
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "-device",
+                "qemu-xhci,id=xhci",
+                "-device",
+                "usb-tablet",
+                "-qmp",
+                "stdio"
+            ],
+            config.get_command_line_options()
+        );
+        assert_eq!(
+            vec![
+                "usb-host,vendorid=0x046d,productid=0xc52b,id=kbd",
+                "driver=usb-host,hostbus=1,hostaddr=5"
+            ],
+            config.get_deferred_devices()
+        );
+
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
-                  vcpu_pinning:
-                    0:
-                      a:
-
+                  defer_usb_host: true
                 qemu:
-                - sda: /dev/sdb
-            ",
+                - device: usb-tablet
+                ",
             ),
             ErrorKind::InvalidData,
-            "Failed to parse `launcher.vcpu_pinning.0`: the core \
-                ID must be an integer greater or equal to zero.",
+            "Wrong value for `launcher.defer_usb_host`: there is no `usb-host` device in the `qemu` section.",
         );
     }
 
     #[test]
-    fn launcher_vcpu_pinning_negative_core_id_returns_error() {
+    fn launcher_depends_on_with_invalid_machine_name_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
-                  vcpu_pinning:
-                    0:
-                      -1:
-
-                qemu:
-                - sda: /dev/sdb
-            ",
+                  depends_on: [ storage, ../router ]
+                qemu: []
+                ",
             ),
             ErrorKind::InvalidData,
-            "Failed to parse `launcher.vcpu_pinning.0`: the core \
-                ID must be an integer greater or equal to zero.",
+            "Failed to parse `launcher.depends_on` item 1: a valid machine name expected.",
         );
     }
 
     #[test]
-    fn launcher_vcpu_pinning_core_value_is_not_a_hash_returns_error() {
-        assert_error(
-            Config::new(
+    fn launcher_env_file_is_merged_with_inline_env_taking_precedence() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/etc/qemu-launcher/my_vm.env" => Ok(
                 "
-                launcher:
-                  binary: /usr/bin/qemu-kvm
-                  vcpu_pinning:
-                    0:
-                      0: []
+                # SPICE credentials
+                SPICE_PASSWORD='top secret'
+                QEMU_AUDIO_DRV = none
 
-                qemu:
-                - sda: /dev/sdb
+                EMPTY=
+                "
+            .to_owned()) },
+        );
+
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              env_file: /etc/qemu-launcher/my_vm.env
+              env:
+                QEMU_AUDIO_DRV: pa
+            qemu: []
             ",
-            ),
-            ErrorKind::InvalidData,
-            "Failed to parse `launcher.vcpu_pinning.0.0`: a hash expected.",
+        )
+        .unwrap();
+
+        let mut expected_env = HashMap::new();
+        expected_env.insert("SPICE_PASSWORD".to_owned(), "top secret".to_owned());
+        expected_env.insert("QEMU_AUDIO_DRV".to_owned(), "pa".to_owned());
+        expected_env.insert("EMPTY".to_owned(), "".to_owned());
+        assert_eq!(&expected_env, config.get_env_vars());
+        assert_eq!(
+            vec![
+                ("EMPTY", "********"),
+                ("QEMU_AUDIO_DRV", "pa"),
+                ("SPICE_PASSWORD", "********"),
+            ],
+            config.get_masked_env_vars().into_iter().collect::<Vec<_>>()
         );
+
+        verify_expectations();
     }
 
     #[test]
-    fn launcher_vcpu_pinning_thread_id_not_an_integer_returns_error() {
-        assert_error(
-            Config::new(
-                "
-                launcher:
-                  binary: /usr/bin/qemu-kvm
-                  vcpu_pinning:
-                    0:
-                      0:
-                        a:
+    fn resolve_env_vars_applies_sources_in_precedence_order_and_reports_overrides() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/etc/qemu-launcher/my_vm.env" => Ok("LANG=C\nSPICE_PASSWORD=secret\n".to_owned()) },
+        );
 
-                qemu:
-                - sda: /dev/sdb
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              clear_env: true
+              env_keep: [ HOME, LANG ]
+              env_file: /etc/qemu-launcher/my_vm.env
+              env:
+                LANG: en_US.UTF-8
+                QEMU_AUDIO_DRV: pa
+            qemu: []
             ",
-            ),
-            ErrorKind::InvalidData,
-            "Failed to parse `launcher.vcpu_pinning.0.0`: \
-                the thread ID must be a positive integer.",
+        )
+        .unwrap();
+
+        let resolved = config.resolve_env_vars(vec![
+            ("PATH".to_owned(), "/usr/bin".to_owned()),
+            ("LANG".to_owned(), "de_DE.UTF-8".to_owned()),
+            ("HOME".to_owned(), "/root".to_owned()),
+        ]);
+
+        let summary: Vec<(&str, &str, EnvSource, &[EnvSource])> = resolved
+            .iter()
+            .map(|v| {
+                (
+                    v.get_name(),
+                    v.get_value(),
+                    v.get_source(),
+                    v.get_overrides(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            vec![
+                ("HOME", "/root", EnvSource::Launcher, &[][..]),
+                (
+                    "LANG",
+                    "en_US.UTF-8",
+                    EnvSource::Env,
+                    &[EnvSource::Launcher, EnvSource::EnvFile][..]
+                ),
+                ("QEMU_AUDIO_DRV", "pa", EnvSource::Env, &[][..]),
+                ("SPICE_PASSWORD", "secret", EnvSource::EnvFile, &[][..]),
+            ],
+            summary
         );
+
+        verify_expectations();
     }
 
     #[test]
-    fn launcher_vcpu_pinning_negative_thread_id_returns_error() {
-        assert_error(
-            Config::new(
-                "
-                launcher:
-                  binary: /usr/bin/qemu-kvm
-                  vcpu_pinning:
-                    0:
-                      0:
-                        -1:
-
-                qemu:
-                - sda: /dev/sdb
+    fn launcher_audio_and_display_env_generate_audiodev_and_environment() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              user: 1000
+              audio: pulseaudio
+              display:
+                env:
+                  display: ':0'
+              env:
+                QEMU_AUDIO_DRV: none
+            qemu:
+            - device: ich9-intel-hda
             ",
-            ),
-            ErrorKind::InvalidData,
-            "Failed to parse `launcher.vcpu_pinning.0.0`: \
-                the thread ID must be a positive integer.",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "-device",
+                "ich9-intel-hda",
+                "-audiodev",
+                "pa,id=audio0,server=unix:/run/user/1000/pulse/native",
+                "-qmp",
+                "stdio"
+            ],
+            config.get_command_line_options()
+        );
+
+        let resolved = config.resolve_env_vars(vec![(
+            "XDG_RUNTIME_DIR".to_owned(),
+            "/run/user/0".to_owned(),
+        )]);
+        let summary: Vec<(&str, &str, EnvSource)> = resolved
+            .iter()
+            .map(|v| (v.get_name(), v.get_value(), v.get_source()))
+            .collect();
+
+        assert_eq!(
+            vec![
+                ("DISPLAY", ":0", EnvSource::Display),
+                (
+                    "PULSE_SERVER",
+                    "unix:/run/user/1000/pulse/native",
+                    EnvSource::Audio
+                ),
+                ("QEMU_AUDIO_DRV", "none", EnvSource::Env),
+                ("XDG_RUNTIME_DIR", "/run/user/1000", EnvSource::Audio),
+            ],
+            summary
         );
     }
 
     #[test]
-    fn launcher_vcpu_pinning_host_core_id_not_an_integer_returns_error() {
-        assert_error(
-            Config::new(
-                "
-                launcher:
-                  binary: /usr/bin/qemu-kvm
-                  vcpu_pinning:
-                    0:
-                      0:
-                        0: true
-
-                qemu:
-                - sda: /dev/sdb
+    fn launcher_audio_pipewire_exports_runtime_directory_and_remote() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              audio:
+                backend: pipewire
+                id: snd
+                runtime_dir: /run/user/1001
+                server: pipewire-0
+            qemu: []
             ",
-            ),
-            ErrorKind::InvalidData,
-            "Failed to parse `launcher.vcpu_pinning.0.0.0`: the host \
-                core ID must be an integer greater or equal to zero.",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec!["-audiodev", "pipewire,id=snd", "-qmp", "stdio"],
+            config.get_command_line_options()
+        );
+
+        let resolved = config.resolve_env_vars(vec![]);
+        let summary: Vec<(&str, &str)> = resolved
+            .iter()
+            .map(|v| (v.get_name(), v.get_value()))
+            .collect();
+
+        assert_eq!(
+            vec![
+                ("PIPEWIRE_REMOTE", "pipewire-0"),
+                ("PIPEWIRE_RUNTIME_DIR", "/run/user/1001"),
+                ("XDG_RUNTIME_DIR", "/run/user/1001"),
+            ],
+            summary
         );
     }
 
     #[test]
-    fn launcher_vcpu_pinning_negative_host_core_id_returns_error() {
+    fn launcher_audio_without_runtime_dir_or_user_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
-                  vcpu_pinning:
-                    0:
-                      0:
-                        0: -1
-
-                qemu:
-                - sda: /dev/sdb
-            ",
+                  audio: pipewire
+                qemu: []
+                ",
             ),
             ErrorKind::InvalidData,
-            "Failed to parse `launcher.vcpu_pinning.0.0.0`: the host \
-                core ID must be an integer greater or equal to zero.",
+            "Failed to parse `launcher.audio`: the `pipewire` backend requires either \
+                `launcher.audio.runtime_dir` or `launcher.user` to be set.",
         );
     }
 
     #[test]
-    fn launcher_section_with_missing_qemu_binary_path_returns_error() {
+    fn launcher_audio_combined_with_audiodev_option_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
-
+                  binary: /usr/bin/qemu-kvm
+                  audio: alsa
                 qemu:
-                - sda: /dev/sdb
-            ",
+                - audiodev: [ alsa, { id: snd } ]
+                ",
             ),
             ErrorKind::InvalidData,
-            "qemu binary path is not specified, missing or the \
-                `launcher.binary` key has an invalid type.",
+            "Failed to parse `launcher.audio`: can not be combined with the `audiodev` option \
+                in the `qemu` section.",
         );
     }
 
     #[test]
-    fn launcher_section_with_non_string_qemu_binary_path_returns_error() {
-        assert_error(
-            Config::new(
-                "
-                launcher:
-                  binary: 3
-
-                qemu:
-                - sda: /dev/sdb
+    fn launcher_smbios_generates_system_information_option() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              smbios:
+                manufacturer: Dell Inc.
+                product: PowerEdge R740
+                serial: ABC,123
+                uuid: 4C4C4544-0042-3510-8052-B4C04F384D32
+            qemu: []
             ",
-            ),
-            ErrorKind::InvalidData,
-            "qemu binary path is not specified, missing or the \
-                `launcher.binary` key has an invalid type.",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "-smbios",
+                "type=1,manufacturer=Dell Inc.,product=PowerEdge R740,serial=ABC,,123,\
+                    uuid=4c4c4544-0042-3510-8052-b4c04f384d32",
+                "-qmp",
+                "stdio"
+            ],
+            config.get_command_line_options()
         );
     }
 
     #[test]
-    fn launcher_section_with_empty_qemu_binary_path_returns_error() {
+    fn launcher_smbios_auto_uuid_is_stable_per_machine() {
+        let source = "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              smbios:
+                uuid: auto
+            qemu: []
+            ";
+        let first = Config::new(source)
+            .unwrap()
+            .with_machine_name(String::from("vm1"));
+        let second = Config::new(source)
+            .unwrap()
+            .with_machine_name(String::from("vm2"));
+
+        let options = first.get_command_line_options();
+        let uuid = options[1].trim_start_matches("type=1,uuid=");
+
+        assert!(super::is_uuid(uuid));
+        assert_eq!(Some('8'), uuid.chars().nth(14));
+        assert_eq!(options, first.get_command_line_options());
+        assert_ne!(options, second.get_command_line_options());
+    }
+
+    #[test]
+    fn launcher_smbios_with_invalid_values_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
-                  binary:
-
-                qemu:
-                - sda: /dev/sdb
-            ",
+                  binary: /usr/bin/qemu-kvm
+                  smbios:
+                    serial: \"tab\\tseparated\"
+                qemu: []
+                ",
             ),
             ErrorKind::InvalidData,
-            "qemu binary path is not specified, missing or the \
-                `launcher.binary` key has an invalid type.",
+            "Failed to parse `launcher.smbios.serial`: a string of 1 to 64 printable ASCII \
+                characters expected.",
         );
-    }
-
-    #[test]
-    fn launcher_section_with_invalid_clear_env_option_value_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
-                  clear_env: 'yes'
-
-                qemu:
-                - sda: /dev/sdb
-            ",
+                  smbios:
+                    uuid: 4c4c4544-0042-3510-8052
+                qemu: []
+                ",
             ),
             ErrorKind::InvalidData,
-            "Invalid value for `launcher.clear_env` value: a boolean is expected.",
+            "Failed to parse `launcher.smbios.uuid`: `auto` or a UUID, \
+                e.g. `4c4c4544-0042-3510-8052-b4c04f384d32`, expected.",
         );
-    }
-
-    #[test]
-    fn launcher_section_with_empty_clear_env_option_value_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
-                  clear_env:
-
+                  smbios:
+                    uuid: auto
                 qemu:
-                - sda: /dev/sdb
-            ",
+                - uuid: 4c4c4544-0042-3510-8052-b4c04f384d32
+                ",
             ),
             ErrorKind::InvalidData,
-            "Invalid value for `launcher.clear_env` value: a boolean is expected.",
+            "Failed to parse `launcher.smbios.uuid`: can not be combined with the `uuid` option \
+                in the `qemu` section.",
         );
     }
 
     #[test]
-    fn launcher_hash_with_invalid_env_value_returns_error() {
+    fn launcher_cache_allocation_renders_resctrl_schemata() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              vcpu_pinning_by_index:
+                0: 2
+              cache_allocation:
+                l3:
+                  1: 0FF0
+                  0: ff0
+                mb:
+                  0: 50
+            qemu: []
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec!["L3:0=ff0;1=0ff0", "MB:0=50"],
+            config.get_cache_allocation().unwrap().get_schemata()
+        );
+    }
+
+    #[test]
+    fn launcher_cache_allocation_with_invalid_values_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
-                  env: []
-
-                qemu:
-                - sda: /dev/sdb
-            ",
+                  vcpu_pinning_by_index:
+                    0: 2
+                  cache_allocation:
+                    l3:
+                      0: f0f
+                qemu: []
+                ",
             ),
             ErrorKind::InvalidData,
-            "Invalid value for the `launcher.env` key: a hash expected.",
+            "Wrong value for `launcher.cache_allocation.l3.0`: \
+                a hexadecimal mask of contiguous cache ways, e.g. `ff0` expected.",
         );
-    }
-
-    #[test]
-    fn launcher_hash_with_empty_env_key_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
-                  env:
-
-                qemu:
-                - sda: /dev/sdb
-            ",
+                  vcpu_pinning_by_index:
+                    0: 2
+                  cache_allocation:
+                    mb:
+                      0: 0
+                qemu: []
+                ",
             ),
             ErrorKind::InvalidData,
-            "Invalid value for the `launcher.env` key: a hash expected.",
+            "Wrong value for `launcher.cache_allocation.mb.0`: \
+                a memory bandwidth percentage from 1 to 100 expected.",
         );
-    }
-
-    #[test]
-    fn launcher_hash_with_env_hash_containing_non_string_keys_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
-                  env:
-                    3: nope
-
-                qemu:
-                - sda: /dev/sdb
-            ",
+                  cache_allocation:
+                    mb:
+                      0: 50
+                qemu: []
+                ",
             ),
             ErrorKind::InvalidData,
-            "Environment variable name must be a string.",
+            "Failed to parse `launcher.cache_allocation`: requires vCPU pinning to be configured.",
         );
     }
 
     #[test]
-    fn launcher_hash_with_env_hash_containing_invalid_values_returns_error() {
-        assert_error(
-            Config::new(
-                "
-                launcher:
-                  binary: /usr/bin/qemu-kvm
-                  env:
-                    NOPE: []
+    fn launcher_cpuset_overrides_the_mount_path_and_the_prefix() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              cpuset:
+                mount_path: /sys/fs/cgroup/tenants/
+                prefix: tenant-a
+            qemu: []
+            ",
+        )
+        .unwrap();
 
-                qemu:
-                - sda: /dev/sdb
+        assert_eq!(
+            Some("/sys/fs/cgroup/tenants"),
+            config.get_cpuset_mount_path()
+        );
+        assert_eq!(Some("tenant-a"), config.get_cpuset_prefix());
+
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              cpuset: { prefix: tenant-b }
+            qemu: []
             ",
+        )
+        .unwrap();
+
+        assert_eq!(None, config.get_cpuset_mount_path());
+        assert_eq!(Some("tenant-b"), config.get_cpuset_prefix());
+    }
+
+    #[test]
+    fn launcher_cpuset_with_invalid_values_returns_error() {
+        for (cpuset, message) in vec![
+            (
+                "{ mount: /sys/fs/cgroup }",
+                "Failed to parse `launcher.cpuset`: a hash with the `mount_path` and `prefix` keys expected.",
             ),
-            ErrorKind::InvalidData,
-            "Invalid value for the `NOPE` environment variable.",
-        );
+            (
+                "{ mount_path: cgroup/cpuset }",
+                "Failed to parse `launcher.cpuset.mount_path`: an absolute path expected.",
+            ),
+            (
+                "{ prefix: tenants/a }",
+                "Failed to parse `launcher.cpuset.prefix`: a directory name expected.",
+            ),
+            (
+                "{ prefix: .. }",
+                "Failed to parse `launcher.cpuset.prefix`: a directory name expected.",
+            ),
+        ] {
+            assert_error(
+                Config::new(format!(
+                    "
+                    launcher:
+                      binary: /usr/bin/qemu-kvm
+                      cpuset: {}
+                    qemu: []
+                    ",
+                    cpuset
+                )),
+                ErrorKind::InvalidData,
+                message,
+            );
+        }
     }
 
     #[test]
-    fn launcher_hash_with_empty_priority_returns_error() {
+    fn launcher_lower_kernel_thread_priority_without_vcpu_pinning_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
-                  priority:
-
-                qemu:
-                - sda: /dev/sdb
-            ",
+                  lower_kernel_thread_priority: true
+                qemu: []
+                ",
             ),
             ErrorKind::InvalidData,
-            "Failed to parse `launcher.priority`: an integer expected.",
+            "Failed to parse `launcher.lower_kernel_thread_priority`: requires vCPU pinning to be configured.",
         );
     }
 
     #[test]
-    fn launcher_hash_with_non_integer_priority_returns_error() {
+    fn launcher_display_env_with_unknown_key_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
-                  priority: a
-
-                qemu:
-                - sda: /dev/sdb
-            ",
+                  display:
+                    env:
+                      term: xterm
+                qemu: []
+                ",
             ),
             ErrorKind::InvalidData,
-            "Failed to parse `launcher.priority`: an integer expected.",
+            "Failed to parse `launcher.display.env`: only the `display`, \
+                `wayland_display` and `xauthority` keys are supported.",
         );
     }
 
     #[test]
-    fn launcher_hash_with_too_high_priority_returns_error() {
+    fn launcher_env_keep_without_clear_env_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
-                  priority: 1024
-
-                qemu:
-                - sda: /dev/sdb
-            ",
+                  env_keep: [ HOME ]
+                qemu: []
+                ",
             ),
             ErrorKind::InvalidData,
-            "Wrong value for `launcher.priority`: value out of bounds.",
+            "Failed to parse `launcher.env_keep`: \
+                can only be used when `launcher.clear_env` is set to `true`.",
         );
     }
 
     #[test]
-    fn launcher_hash_with_negative_priority_returns_error() {
+    fn launcher_env_file_with_malformed_line_returns_error() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/etc/qemu-launcher/my_vm.env" => Ok("FOO=bar\nBAZ\n".to_owned()) },
+        );
+
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
-                  priority: -2
-
-                qemu:
-                - sda: /dev/sdb
-            ",
+                  env_file: /etc/qemu-launcher/my_vm.env
+                qemu: []
+                ",
             ),
             ErrorKind::InvalidData,
-            "Wrong value for `launcher.priority`: value out of bounds.",
+            "Failed to parse the `/etc/qemu-launcher/my_vm.env` environment file: \
+                line 2 is not in the `KEY=VALUE` format.",
         );
+
+        verify_expectations();
     }
 
     #[test]
-    fn launcher_hash_with_invalid_scheduler_returns_error() {
+    fn launcher_env_file_returns_error_if_fs_read_to_string_fails() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/etc/qemu-launcher/my_vm.env" =>
+                Err(::std::io::Error::new(::std::io::ErrorKind::NotFound, "test error")) },
+        );
+
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
-                  scheduler: []
-
-                qemu:
-                - sda: /dev/sdb
-            ",
+                  env_file: /etc/qemu-launcher/my_vm.env
+                qemu: []
+                ",
             ),
-            ErrorKind::InvalidData,
-            "Failed to parse `launcher.scheduler`: string expected.",
+            ErrorKind::NotFound,
+            "Failed to read the `/etc/qemu-launcher/my_vm.env` environment file: test error",
         );
+
+        verify_expectations();
     }
 
     #[test]
-    fn launcher_hash_with_empty_scheduler_returns_error() {
-        assert_error(
-            Config::new(
+    fn launcher_profile_is_merged_beneath_machine_settings() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/usr/local/etc/qemu-launcher/profiles/latency.yml" => Ok(
                 "
                 launcher:
-                  binary: /usr/bin/qemu-kvm
-                  scheduler:
+                  scheduler: fifo
+                  priority: 10
+                  env:
+                    QEMU_AUDIO_DRV: none
+                    LANG: C
+                "
+            .to_owned()) },
+        );
 
-                qemu:
-                - sda: /dev/sdb
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              profile: latency
+              priority: 20
+              env:
+                LANG: en_US.UTF-8
+            qemu: []
             ",
-            ),
-            ErrorKind::InvalidData,
-            "Failed to parse `launcher.scheduler`: string expected.",
-        );
+        )
+        .unwrap();
+
+        let mut expected_env = HashMap::new();
+        expected_env.insert("QEMU_AUDIO_DRV".to_owned(), "none".to_owned());
+        expected_env.insert("LANG".to_owned(), "en_US.UTF-8".to_owned());
+        assert_eq!(&expected_env, config.get_env_vars());
+        assert_eq!(&Some("fifo".to_owned()), config.get_scheduler());
+        assert_eq!(Some(20), config.get_priority());
+        assert_eq!(Some("latency"), config.get_profile());
+
+        verify_expectations();
     }
 
     #[test]
-    fn launcher_hash_with_unsupported_scheduler_returns_error() {
-        assert_error(
-            Config::new(
+    fn dump_renders_the_merged_configuration_with_secrets_masked() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/usr/local/etc/qemu-launcher/profiles/desktop.yml" => Ok(
                 "
                 launcher:
-                  binary: /usr/bin/qemu-kvm
-                  scheduler: foo
+                  clear_env: true
+                  env:
+                    SPICE_PASSWORD: hunter2
+                "
+            .to_owned()) },
+        );
 
-                qemu:
-                - sda: /dev/sdb
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              profile: desktop
+              env:
+                LANG: C
+            qemu:
+            - enable-kvm
+            - -object secret,id=sec0,data=letmein
+            - spice: port=5900,password=hunter2
+            - vnc: :0,password=on
+            - drive: file=/srv/images/disk.luks,encrypt.key-secret=sec0
             ",
-            ),
-            ErrorKind::InvalidData,
-            "Failed to parse `launcher.scheduler`: Expected one of \
-                `batch`, `deadline`, `fifo`, `idle`, `other` or `rr`.",
+        )
+        .unwrap();
+
+        assert_eq!(
+            "---\n\
+            launcher:\n  \
+              clear_env: true\n  \
+              binary: /usr/bin/qemu-kvm\n  \
+              profile: desktop\n  \
+              env:\n    \
+                LANG: C\n    \
+                SPICE_PASSWORD: \"********\"\n\
+            qemu:\n  \
+              - enable-kvm\n  \
+              - object: \"secret,id=sec0,data=********\"\n  \
+              - spice: \"port=5900,password=********\"\n  \
+              - vnc: \":0,password=on\"\n  \
+              - drive: \"file=/srv/images/disk.luks,encrypt.key-secret=sec0\"\n",
+            config.dump().unwrap()
         );
+
+        verify_expectations();
     }
 
     #[test]
-    fn missing_qemu_section_returns_error() {
+    fn launcher_profile_referencing_another_profile_returns_error() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/usr/local/etc/qemu-launcher/profiles/latency.yml" => Ok(
+                "launcher:\n  profile: base\n".to_owned()) },
+        );
+
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
-            ",
+                  profile: latency
+                qemu: []
+                ",
             ),
             ErrorKind::InvalidData,
-            "Failed to parse qemu command line options: \
-                missing or invalid value, array expected.",
+            "Failed to parse the `latency` profile: profiles can not reference other profiles.",
         );
+
+        verify_expectations();
     }
 
     #[test]
-    fn non_array_qemu_section_returns_error() {
-        assert_error(
-            Config::new(
+    fn launcher_vcpu_pinning_file_replaces_machine_pinning_with_host_values() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/sys/kernel/hostname" => Ok("node7\n".to_owned()) },
+            { "/usr/local/etc/qemu-launcher/host-overrides/node7.yml" => Ok(
                 "
                 launcher:
-                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning_by_index:
+                    0: 10
+                    1: 11
+                  priority: 30
+                "
+            .to_owned()) },
+        );
 
-                qemu: {}
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              vcpu_pinning_file: host-overrides/$(hostname).yml
+              vcpu_pinning:
+                0:
+                  0:
+                    0: 2
+                    1: 3
+              scheduler: fifo
+              priority: 20
+            qemu: []
             ",
-            ),
-            ErrorKind::InvalidData,
-            "Failed to parse qemu command line options: \
-                missing or invalid value, array expected.",
-        );
+        )
+        .unwrap();
+
+        assert_eq!(vec![10, 11], config.get_pinned_host_cpus());
+        assert_eq!(Some(30), config.get_priority());
+        assert_eq!(&Some("fifo".to_owned()), config.get_scheduler());
+
+        verify_expectations();
     }
 
     #[test]
-    fn qemu_section_with_non_hash_or_string_argument_returns_error() {
-        assert_error(
-            Config::new(
-                "
-                launcher:
-                  binary: /usr/bin/qemu-kvm
+    fn launcher_vcpu_pinning_file_returns_error_if_it_is_invalid() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/etc/hosts.d/pinning.yml" =>
+                Err(::std::io::Error::new(::std::io::ErrorKind::NotFound, "test error")) },
+            { "/etc/hosts.d/pinning.yml" => Ok("launcher:\n  profile: latency\n".to_owned()) },
+        );
 
-                qemu:
-                - true
-            ",
-            ),
+        let yaml = "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              vcpu_pinning_file: /etc/hosts.d/pinning.yml
+            qemu: []
+            ";
+
+        assert_error(
+            Config::new(yaml),
+            ErrorKind::NotFound,
+            "Failed to read the `/etc/hosts.d/pinning.yml` vCPU pinning file: test error",
+        );
+        assert_error(
+            Config::new(yaml),
             ErrorKind::InvalidData,
-            "Failed to parse qemu command line option 1. Every \
-                option must be either a string or a hash.",
+            "Failed to parse the `/etc/hosts.d/pinning.yml` vCPU pinning file: \
+                `profile` can not be set by it.",
         );
+
+        verify_expectations();
     }
 
     #[test]
-    fn qemu_section_with_empty_argument_hash_returns_error() {
+    fn launcher_profile_returns_error_if_fs_read_to_string_fails() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/usr/local/etc/qemu-launcher/profiles/latency.yml" =>
+                Err(::std::io::Error::new(::std::io::ErrorKind::NotFound, "test error")) },
+        );
+
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
-
-                qemu:
-                - {}
-            ",
+                  profile: latency
+                qemu: []
+                ",
             ),
-            ErrorKind::InvalidData,
-            "Found a command line argument 1 with 0 pairs \
-                in the hash, but exactly one is expected.",
+            ErrorKind::NotFound,
+            "Failed to read the `/usr/local/etc/qemu-launcher/profiles/latency.yml` profile: test error",
         );
+
+        verify_expectations();
     }
 
     #[test]
-    fn qemu_section_with_argument_hash_containing_multiple_items_returns_error() {
-        assert_error(
-            Config::new(
-                "
-                launcher:
-                  binary: /usr/bin/qemu-kvm
+    fn launcher_network_generates_netdev_and_device_arguments() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              network:
+              - netdev: [ tap, ifname: tap0, script: no ]
+              - id: lan
+                netdev: bridge,br=br0
+                device: [ e1000, bus: pcie.0 ]
+                mac: 52:54:00:AB:CD:EF
 
-                qemu:
-                - { a: 1, b: 2 }
-            ",
-            ),
-            ErrorKind::InvalidData,
-            "Found a command line argument 1 with 2 pairs \
-                in the hash, but exactly one is expected.",
+            qemu:
+            - nographic
+        ",
+        )
+        .unwrap()
+        .with_machine_name("router");
+
+        assert_eq!(
+            vec![
+                "-nographic",
+                "-netdev",
+                "tap,ifname=tap0,script=no,id=net0",
+                "-device",
+                "virtio-net-pci,netdev=net0,mac=aa:74:b3:d9:57:fb",
+                "-netdev",
+                "bridge,br=br0,id=lan",
+                "-device",
+                "e1000,bus=pcie.0,netdev=lan,mac=52:54:00:ab:cd:ef",
+                "-qmp",
+                "stdio",
+            ],
+            config.get_command_line_options()
         );
     }
 
     #[test]
-    fn qemu_section_with_argument_hash_containing_non_string_keys_returns_error() {
-        assert_error(
-            Config::new(
-                "
-                launcher:
-                  binary: /usr/bin/qemu-kvm
+    fn launcher_netboot_generates_tftp_options_of_user_interfaces() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              netboot:
+                directory: /srv/netboot
+                bootfile: pxelinux.0
+                listen: 192.168.100.1
+                http_port: 8080
+              network:
+              - netdev: [ user, net: 10.0.2.0/24 ]
+              - netdev: [ tap, ifname: tap0, script: no ]
+
+            qemu: []
+        ",
+        )
+        .unwrap()
+        .with_machine_name("router");
 
-                qemu:
-                - { 3: nope }
-            ",
-            ),
-            ErrorKind::InvalidData,
-            "Argument 1 name must be a string.",
+        assert_eq!(
+            vec![
+                "-netdev",
+                "user,net=10.0.2.0/24,id=net0,tftp=/srv/netboot,bootfile=pxelinux.0",
+                "-device",
+                "virtio-net-pci,netdev=net0,mac=aa:74:b3:d9:57:fb",
+                "-netdev",
+                "tap,ifname=tap0,script=no,id=net1",
+                "-device",
+                "virtio-net-pci,netdev=net1,mac=aa:74:b4:d9:57:fb",
+                "-qmp",
+                "stdio",
+            ],
+            config.get_command_line_options()
+        );
+
+        let netboot = config.get_netboot().unwrap();
+        assert_eq!("/srv/netboot", netboot.get_directory());
+        assert_eq!(Some("192.168.100.1".parse().unwrap()), netboot.get_listen());
+        assert_eq!(Some(8080), netboot.get_http_port());
+        assert_eq!(
+            vec![("launcher.netboot", "CAP_NET_BIND_SERVICE")],
+            config.get_required_capabilities()
         );
     }
 
     #[test]
-    fn qemu_section_with_argument_hash_containing_invalid_values_returns_error() {
-        assert_error(
-            Config::new(
-                "
-                launcher:
-                  binary: /usr/bin/qemu-kvm
-
-                qemu:
-                - still_no:
-            ",
+    fn launcher_netboot_with_invalid_values_returns_error() {
+        let cases = [
+            (
+                "netboot: { directory: srv/netboot, listen: 192.168.100.1 }",
+                "Failed to parse `launcher.netboot.directory`: an absolute path expected.",
             ),
-            ErrorKind::InvalidData,
-            "Invalid value for `still_no` qemu argument 1: expected \
-                a string, number or a hash with a single pair.",
+            (
+                "netboot: { directory: /srv/netboot, bootfile: ../etc/shadow, listen: 192.168.100.1 }",
+                "Failed to parse `launcher.netboot.bootfile`: a path inside of the directory expected.",
+            ),
+            (
+                "netboot: { directory: /srv/netboot, listen: 192.168.100 }",
+                "Failed to parse `launcher.netboot.listen`: an IPv4 address expected.",
+            ),
+            (
+                "netboot: { directory: /srv/netboot, http_port: 80 }",
+                "Wrong value for `launcher.netboot.http_port`: requires `launcher.netboot.listen`.",
+            ),
+            (
+                "netboot: { directory: /srv/netboot }",
+                "Wrong value for `launcher.netboot`: either `listen` or a `user` interface in \
+                    `launcher.network` is required.",
+            ),
+            (
+                "netboot: { directory: /srv/netboot }\n  network: [ { netdev: \"user,tftp=/tmp\" } ]",
+                "Wrong value for `launcher.network.0.netdev`: `tftp` and `bootfile` are \
+                    generated from `launcher.netboot`.",
+            ),
+        ];
+
+        for (netboot, message) in &cases {
+            assert_error(
+                Config::new(&format!(
+                    "launcher:\n  binary: /usr/bin/qemu-kvm\n  {}\nqemu: []\n",
+                    netboot
+                )),
+                ErrorKind::InvalidData,
+                message,
+            );
+        }
+    }
+
+    #[test]
+    fn launcher_network_lists_vhost_queues_of_interfaces() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              vcpu_pinning_by_index:
+                0: 2
+              network:
+              - netdev: [ tap, ifname: tap0, vhost: on, queues: 4 ]
+                pin_queues: true
+              - netdev: user
+              - id: lan
+                netdev: tap,ifname=tap1,vhost=on
+
+            qemu:
+            - nographic
+        ",
+        )
+        .unwrap();
+
+        assert!(config.has_queue_pinning());
+        assert_eq!(
+            vec![("net0", 4, true), ("lan", 1, false)],
+            config.get_vhost_queues()
         );
     }
 
     #[test]
-    fn qemu_section_with_argument_hash_containing_empty_array_returns_error() {
+    fn launcher_network_pin_queues_without_vhost_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
-                  binary: /usr/bin/qemu-kvm
-
+                  vcpu_pinning_by_index:
+                    0: 2
+                  network:
+                  - netdev: tap,ifname=tap0,queues=4
+                    pin_queues: true
                 qemu:
-                - foo: []
-            ",
+                - nographic
+                ",
             ),
             ErrorKind::InvalidData,
-            "Empty value for `foo` argument, consider \
-                using a string instead of an array.",
+            "Wrong value for `launcher.network.0.pin_queues`: the netdev must enable vhost \
+                with `vhost=on`.",
         );
     }
 
     #[test]
-    fn qemu_section_with_argument_hash_containing_properties_hash_with_no_items_returns_error() {
+    fn launcher_network_pin_queues_without_vcpu_pinning_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
-                  binary: /usr/bin/qemu-kvm
-
+                  network:
+                  - netdev: tap,ifname=tap0,vhost=on,queues=4
+                    pin_queues: true
                 qemu:
-                - foo: [ bar: ]
-            ",
+                - nographic
+                ",
             ),
             ErrorKind::InvalidData,
-            "Failed to parse a value for `foo/bar` argument: \
-                value must be either a string or a number.",
+            "Wrong value for `launcher.network.0.pin_queues`: vCPU pinning is not configured.",
         );
     }
 
     #[test]
-    fn qemu_section_with_argument_hash_containing_properties_hash_with_multiple_items_returns_error(
-    ) {
+    fn generated_mac_address_is_stable_and_locally_administered() {
+        let mac = super::generate_mac_address("router", "net0");
+
+        assert_eq!(mac, super::generate_mac_address("router", "net0"));
+        assert_ne!(mac, super::generate_mac_address("router", "net1"));
+        assert_ne!(mac, super::generate_mac_address("switch", "net0"));
+        assert_eq!(0x02, u8::from_str_radix(&mac[0..2], 16).unwrap() & 0x03);
+    }
+
+    #[test]
+    fn launcher_network_with_non_array_value_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
+                  network: tap
 
                 qemu:
-                - foo: [ { bar: baz, this: wontwork } ]
+                - sda: /dev/sdb
             ",
             ),
             ErrorKind::InvalidData,
-            "Failed to parse a value for `foo` argument: \
-                a hash with multiple pairs found.",
+            "Failed to parse `launcher.network`: an array expected.",
         );
     }
 
     #[test]
-    fn qemu_section_with_argument_hash_containing_properties_hash_with_non_string_keys_returns_error(
-    ) {
+    fn launcher_network_without_netdev_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
+                  network:
+                  - device: virtio-net-pci
 
                 qemu:
-                - foo: [ { 3: bad } ]
+                - sda: /dev/sdb
             ",
             ),
             ErrorKind::InvalidData,
-            "Failed to parse a value for `foo` argument: \
-                a property name must be a string.",
+            "Failed to parse `launcher.network.0.netdev`: a string or an array expected.",
         );
     }
 
     #[test]
-    fn qemu_section_with_argument_hash_containing_properties_hash_with_invalid_values_returns_error(
-    ) {
+    fn launcher_network_with_invalid_mac_address_returns_error() {
         assert_error(
             Config::new(
                 "
                 launcher:
                   binary: /usr/bin/qemu-kvm
+                  network:
+                  - netdev: user
+                    mac: 52:54:00:12:34
 
                 qemu:
-                - foo: [ bad: [] ]
+                - sda: /dev/sdb
             ",
             ),
             ErrorKind::InvalidData,
-            "Failed to parse a value for `foo/bad` argument: \
-                value must be either a string or a number.",
-        );
-    }
-
-    #[test]
-    fn from_file_returns_error_if_fs_read_to_string_fails() {
-        expect!(
-            TEST_EXPECTATIONS::std_fs_read_to_string:
-            { "/etc/config/my_vm.yml" =>
-                Err(::std::io::Error::new(::std::io::ErrorKind::Other, "read_to_string()")) },
-        );
-
-        assert_error!(
-            ErrorKind::Other,
-            "read_to_string()",
-            Config::from_file("/etc/config/my_vm.yml")
-        );
-
-        verify_expectations();
-    }
-
-    #[test]
-    fn from_file_returns_parsed_config_read_from_file() {
-        expect!(
-            TEST_EXPECTATIONS::std_fs_read_to_string:
-            { "/etc/config/my_vm.yml" => Ok(
-                "
-                launcher:
-                  binary: /bin/true
-                qemu:
-                - sda: /tmp/vm.qcow
-                "
-            .to_owned()) },
-        );
-
-        let config = Config::from_file("/etc/config/my_vm.yml").unwrap();
-
-        assert_eq!("/bin/true", config.get_qemu_binary_path());
-        assert_eq!(
-            vec!["-sda", "/tmp/vm.qcow", "-qmp", "stdio"],
-            config.get_command_line_options()
+            "Failed to parse `launcher.network.0.mac`: a MAC address \
+                in the `xx:xx:xx:xx:xx:xx` format expected.",
         );
-
-        verify_expectations();
     }
 }
@@ -1,23 +1,38 @@
-use nix::{fcntl::FlockArg, mount::MsFlags};
+use crate::{
+    clock::{self, Clock},
+    pinning::PinningBackend,
+};
+use nix::{errno::Errno, fcntl::FlockArg, mount::MsFlags};
 use std::{
+    collections::HashMap,
     io::prelude::*,
     io::{BufRead, BufReader, Error, ErrorKind, SeekFrom},
     os::unix::io::AsRawFd,
     path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
 #[cfg(test)]
 use test::{
-    nix::{fcntl::flock, mount::mount},
-    proc_mounts::MountIter,
+    nix::{
+        fcntl::flock,
+        mount::{mount, umount},
+    },
     std::fs,
 };
 #[cfg(not(test))]
 use {
-    nix::{fcntl::flock, mount::mount},
-    proc_mounts::MountIter,
+    nix::{
+        fcntl::flock,
+        mount::{mount, umount},
+    },
     std::fs,
 };
 
+const DEFAULT_LOCK_DIRECTORY: &str = "/run/qemu-launcher";
+const PF_NO_SETAFFINITY: u64 = 0x04000000;
+const MOUNTINFO_PATH: &str = "/proc/self/mountinfo";
+
 macro_rules! path {
     ($path:expr) => (PathBuf::from(&$path));
     ($path:expr, $($part:expr), +) => {{
@@ -28,9 +43,20 @@ macro_rules! path {
 }
 
 pub struct CpuSet {
+    clock: Arc<dyn Clock>,
     mount_path: PathBuf,
     isolated_threads: Vec<usize>,
+    // Serializes the pool changes of all launchers sharing the prefix, cgroup files themselves are
+    // not reliably lockable and regular files can not be created in the cgroup tree
+    lock_path: PathBuf,
+    migrate_all: bool,
+    mount_options: Vec<String>,
+    mounted: bool,
     prefix: PathBuf,
+    release_delay: Duration,
+    release_retries: usize,
+    thread_name: String,
+    unmovable_tasks: Vec<String>,
 }
 
 impl CpuSet {
@@ -56,62 +82,137 @@ impl CpuSet {
         }
 
         Ok(CpuSet {
+            clock: clock::system(),
             mount_path: PathBuf::from(path.as_ref()),
             isolated_threads: vec![],
+            lock_path: lock_path(DEFAULT_LOCK_DIRECTORY, prefix.as_ref()),
+            migrate_all: false,
+            mount_options: vec![],
+            mounted: false,
             prefix: PathBuf::from(prefix.as_ref()),
+            release_delay: Duration::from_millis(0),
+            release_retries: 0,
+            thread_name: String::from("{cpu}"),
+            unmovable_tasks: vec![],
         })
     }
 
+    pub fn with_thread_name<S: AsRef<str>>(mut self, name: S) -> Self {
+        self.thread_name = name.as_ref().to_owned();
+
+        self
+    }
+
+    pub fn with_lock_directory<D: AsRef<Path>>(mut self, directory: D) -> Self {
+        self.lock_path = lock_path(directory, &self.prefix);
+
+        self
+    }
+
+    pub fn with_migrate_all(mut self, migrate_all: bool) -> Self {
+        self.migrate_all = migrate_all;
+
+        self
+    }
+
+    pub fn with_mount_options(mut self, options: &[String]) -> Self {
+        self.mount_options = options.to_vec();
+
+        self
+    }
+
+    pub fn get_unmovable_tasks(&self) -> &Vec<String> {
+        &self.unmovable_tasks
+    }
+
+    pub fn with_release_retries(mut self, retries: usize, delay: Duration) -> Self {
+        self.release_retries = retries;
+        self.release_delay = delay;
+
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+
+        self
+    }
+
     #[inline]
     fn cpuset_path(&self) -> PathBuf {
         path!(self.mount_path, self.prefix)
     }
 
-    pub fn pin_task(&mut self, host_id: usize, guest_id: usize) -> Result<(), Error> {
-        if let Err(e) = self.isolate_thread(host_id) {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!(
-                    "Failed to isolate the host cpu thread `{}` - {}",
-                    host_id, e
-                ),
-            ));
-        }
+    #[inline]
+    fn thread_path(&self, id: &usize) -> PathBuf {
+        path!(
+            self.cpuset_path(),
+            self.thread_name.replace("{cpu}", &id.to_string())
+        )
+    }
 
-        if let Err(e) = fs::write(
-            path!(self.cpuset_path(), host_id.to_string(), "tasks"),
-            guest_id.to_string(),
-        ) {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!(
-                    "Failed to pin the process id `{}` to the host cpu thread `{}` - {}",
-                    guest_id, host_id, e
-                ),
-            ));
-        }
+    pub fn get_thread_tasks(&self, id: usize) -> Result<Vec<String>, Error> {
+        let path = path!(self.thread_path(&id), "tasks");
 
-        Ok({})
+        match fs::read_to_string(&path) {
+            Ok(tasks) => Ok(tasks.lines().map(|task| task.trim().to_owned()).collect()),
+            Err(e) => Err(Error::new(
+                e.kind(),
+                format!("Failed to read `{}`: {}", path.display(), e),
+            )),
+        }
     }
 
-    fn isolate_thread(&mut self, id: usize) -> Result<(), Error> {
-        self.prepare_cpuset()?;
+    fn isolate_threads(
+        &mut self,
+        ids: &[usize],
+        failures: &mut HashMap<usize, String>,
+    ) -> Result<(), Error> {
+        self.ensure_mounted()?;
 
-        // TODO: issue a warning if thread already busy
-        if let Err(e) = self.is_thread_free(&id) {
-            match e.kind() {
-                ErrorKind::NotFound => {}
-                _ => return Err(e),
+        // Held until the pool is split, so no other launcher takes the same host CPUs meanwhile
+        let _lock = self.lock_pool()?;
+        self.configure_cpuset()?;
+        self.migrate_tasks()?;
+
+        let mut pending = vec![];
+        for id in ids {
+            // TODO: issue a warning if thread already busy
+            if let Err(e) = self.is_thread_free(id) {
+                if e.kind() != ErrorKind::NotFound {
+                    failures.insert(*id, e.to_string());
+                    continue;
+                }
             }
-        };
 
-        if self.isolated_threads.contains(&id) {
+            if !self.isolated_threads.contains(id) {
+                pending.push(*id);
+            }
+        }
+
+        if pending.len() < 1 {
             return Ok({});
         }
 
-        self.split_thread_from_pool(&id)?;
+        for (id, e) in self.split_threads_from_pool(&pending)? {
+            failures.insert(id, e.to_string());
+        }
+
+        for id in pending {
+            if failures.contains_key(&id) {
+                continue;
+            }
+
+            if let Err(e) = self.create_thread_cpuset(id) {
+                failures.insert(id, e.to_string());
+            }
+        }
+
+        Ok({})
+    }
 
-        let path = path!(self.cpuset_path(), id.to_string());
+    fn create_thread_cpuset(&mut self, id: usize) -> Result<(), Error> {
+        let path = self.thread_path(&id);
         fs::create_dir_all(&path)?;
 
         let mems = fs::read_to_string(path!(self.cpuset_path(), "cpuset.mems"))?;
@@ -124,16 +225,103 @@ impl CpuSet {
         Ok({})
     }
 
-    fn split_thread_from_pool(&self, id: &usize) -> Result<(), Error> {
+    fn split_threads_from_pool(&self, ids: &[usize]) -> Result<Vec<(usize, Error)>, Error> {
         let mut file = self.open_pool_cpus_file()?;
         let mut cpus = read_cpus_from_file(&mut file)?;
-        cpus.retain(|cpu| cpu != &id.to_string());
+
+        let mut missing = vec![];
+        for id in ids {
+            if !cpus.contains(&id.to_string()) {
+                missing.push((
+                    *id,
+                    Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "The host CPU `{}` is not in the CPU pool, it does not exist or is already pinned \
+                            by another virtual machine. The pool contains CPUs: {}.",
+                            id,
+                            cpus.join(",")
+                        ),
+                    ),
+                ));
+            }
+        }
+
+        if missing.len() == ids.len() {
+            return Ok(missing);
+        }
+
+        // All host CPUs are removed from the pool with a single write while holding the lock
+        cpus.retain(|cpu| !ids.iter().any(|id| &id.to_string() == cpu));
         write_cpus_to_file(&mut file, cpus)?;
 
-        Ok({})
+        Ok(missing)
+    }
+
+    pub fn collect_garbage<F: FnOnce() -> Vec<String>>(
+        &self,
+        tracked: F,
+    ) -> Result<Vec<String>, Error> {
+        let path = self.cpuset_path();
+        let entries = match fs::read_dir(&path) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => {
+                return Err(Error::new(
+                    e.kind(),
+                    format!("Failed to list `{}`: {}", path.display(), e),
+                ))
+            }
+        };
+
+        let mut candidates = vec![];
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+
+            if name != "pool" && entry.file_type()?.is_dir() {
+                candidates.push(name);
+            }
+        }
+
+        // Launchers record their state before pinning, so directories listed above are always tracked by now
+        let tracked = tracked();
+
+        let mut removed = vec![];
+        for name in candidates {
+            if tracked.contains(&name) {
+                continue;
+            }
+
+            let thread_path = path!(path, name);
+            if fs::read_to_string(path!(thread_path, "tasks"))?
+                .trim()
+                .len()
+                > 0
+            {
+                continue;
+            }
+
+            let cpus = fs::read_to_string(path!(thread_path, "cpuset.cpus"))?;
+            if let Err(e) = fs::remove_dir(&thread_path) {
+                return Err(Error::new(
+                    e.kind(),
+                    format!("Failed to remove `{}`: {}", thread_path.display(), e),
+                ));
+            }
+
+            for id in parse_cpus_list(cpus.trim()) {
+                self.return_thread_to_pool(&id)?;
+            }
+
+            removed.push(name);
+        }
+
+        Ok(removed)
     }
 
     fn return_thread_to_pool(&self, id: &usize) -> Result<(), Error> {
+        let _lock = self.lock_pool()?;
         let mut file = self.open_pool_cpus_file()?;
         let mut cpus = read_cpus_from_file(&mut file)?;
         cpus.push(id.to_string());
@@ -142,35 +330,132 @@ impl CpuSet {
         Ok({})
     }
 
+    // The kernel drops an offline CPU from every cpuset and does not add it back once the CPU is online
+    // again. Online CPUs neither pinned by a running machine nor in the pool are returned to it, and the
+    // isolated threads get their CPUs back. Returns the CPUs returned to the pool.
+    pub fn repair_pool(&self, online: &[usize], pinned: &[usize]) -> Result<Vec<usize>, Error> {
+        let cpus_path = path!(self.cpuset_path(), "cpuset.cpus");
+        let mut cpus = match fs::read_to_string(&cpus_path) {
+            Ok(cpus) => parse_cpus_list(cpus.trim()),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => {
+                return Err(Error::new(
+                    e.kind(),
+                    format!("Failed to read `{}`: {}", cpus_path.display(), e),
+                ))
+            }
+        };
+
+        let _lock = self.lock_pool()?;
+        let mut file = self.open_pool_cpus_file()?;
+        let mut pool = read_cpus_from_file(&mut file)?;
+
+        let mut restored = vec![];
+        for id in self
+            .isolated_threads
+            .iter()
+            .filter(|id| online.contains(id))
+        {
+            if fs::read_to_string(path!(self.thread_path(id), "cpuset.cpus"))?
+                .trim()
+                .len()
+                == 0
+            {
+                restored.push(*id);
+            }
+        }
+
+        let returned: Vec<usize> = online
+            .iter()
+            .filter(|id| {
+                !pinned.contains(id)
+                    && !self.isolated_threads.contains(id)
+                    && !pool.contains(&id.to_string())
+            })
+            .cloned()
+            .collect();
+
+        // Child cpusets can only be given the CPUs of their parent
+        let missing: Vec<usize> = returned
+            .iter()
+            .chain(&restored)
+            .filter(|id| !cpus.contains(id))
+            .cloned()
+            .collect();
+        if missing.len() > 0 {
+            cpus.extend(missing);
+            cpus.sort();
+
+            let cpus: Vec<String> = cpus.iter().map(|id| id.to_string()).collect();
+            fs::write(&cpus_path, cpus.join(","))?;
+        }
+
+        if returned.len() > 0 {
+            pool.extend(returned.iter().map(|id| id.to_string()));
+            write_cpus_to_file(&mut file, pool)?;
+        }
+
+        for id in restored {
+            fs::write(path!(self.thread_path(&id), "cpuset.cpus"), id.to_string())?;
+        }
+
+        Ok(returned)
+    }
+
     fn open_pool_cpus_file(&self) -> Result<fs::File, Error> {
         let path = path!(self.cpuset_path(), "pool", "cpuset.cpus");
-        let file = fs::OpenOptions::new().read(true).write(true).open(&path)?;
+
+        fs::OpenOptions::new().read(true).write(true).open(&path)
+    }
+
+    // The lock is released once the returned file is dropped
+    fn lock_pool(&self) -> Result<fs::File, Error> {
+        let file = match fs::File::create(&self.lock_path) {
+            Ok(file) => file,
+            Err(e) => {
+                return Err(Error::new(
+                    e.kind(),
+                    format!(
+                        "Failed to open the `{}` lock file: {}",
+                        self.lock_path.display(),
+                        e
+                    ),
+                ))
+            }
+        };
 
         // TODO: add debug on success
         if let Err(e) = flock(file.as_raw_fd(), FlockArg::LockExclusive) {
             return Err(Error::new(
                 ErrorKind::Other,
-                format!("Failed to lock `{}`: {}", path.display(), e),
+                format!("Failed to lock `{}`: {}", self.lock_path.display(), e),
             ));
         }
 
         Ok(file)
     }
 
-    fn prepare_cpuset(&self) -> Result<(), Error> {
-        self.ensure_mounted()?;
-        self.configure_cpuset()?;
-        self.migrate_tasks()?;
-        Ok({})
-    }
-
-    fn migrate_tasks(&self) -> Result<(), Error> {
+    fn migrate_tasks(&mut self) -> Result<(), Error> {
         let pool_cpus_path = path!(self.cpuset_path(), "pool", "cpuset.cpus");
         let pool_cpus = parse_cpus_list(fs::read_to_string(pool_cpus_path)?.trim());
 
         let file = fs::read_to_string(path!(self.mount_path, "tasks"))?;
         let path = path!(self.cpuset_path(), "pool", "tasks");
         for task in file.lines() {
+            if self.migrate_all {
+                match is_affinity_fixed(task) {
+                    Ok(false) => {
+                        if fs::write(&path, task).is_err() {
+                            self.unmovable_tasks.push(task.to_owned());
+                        }
+                    }
+                    // Per-CPU kernel threads can not be moved, tasks that have exited are gone
+                    Ok(true) | Err(_) => {}
+                }
+
+                continue;
+            }
+
             let task_cpus = match get_task_cpus(task) {
                 Ok(cpus) => cpus,
                 Err(_) => {
@@ -199,18 +484,14 @@ impl CpuSet {
         let mems_path = path!(path, "cpuset.mems");
         let mut mems = fs::read_to_string(&mems_path)?.trim().to_owned();
         if mems.len() == 0 {
-            mems = fs::read_to_string(path!(self.mount_path, "cpuset.mems"))?
-                .trim()
-                .to_owned();
+            mems = self.read_effective_value("mems")?;
             fs::write(&mems_path, &mems)?;
         }
 
         let cpus_path = path!(path, "cpuset.cpus");
         let mut cpus = fs::read_to_string(&cpus_path)?.trim().to_owned();
         if cpus.len() == 0 {
-            cpus = fs::read_to_string(path!(self.mount_path, "cpuset.cpus"))?
-                .trim()
-                .to_owned();
+            cpus = self.read_effective_value("cpus")?;
             fs::write(&cpus_path, &cpus)?;
         }
 
@@ -231,37 +512,90 @@ impl CpuSet {
         Ok({})
     }
 
-    pub fn release_threads(&mut self) -> Result<(), Error> {
-        let mut errors = false;
+    fn read_effective_value(&self, name: &str) -> Result<String, Error> {
+        // Effective values exclude offline CPUs and memory nodes, prefer them when the kernel provides them
+        match fs::read_to_string(path!(self.mount_path, format!("cpuset.effective_{}", name))) {
+            Ok(value) if value.trim().len() > 0 => return Ok(value.trim().to_owned()),
+            Ok(_) => {}
+            Err(e) => match e.kind() {
+                ErrorKind::NotFound => {}
+                _ => return Err(e),
+            },
+        }
+
+        Ok(
+            fs::read_to_string(path!(self.mount_path, format!("cpuset.{}", name)))?
+                .trim()
+                .to_owned(),
+        )
+    }
+
+    // Releases every isolated thread, reporting the outcome per host CPU. Threads whose cpuset could
+    // not be removed stay isolated, so releasing them again only retries the failed ones.
+    pub fn release_threads(&mut self) -> Vec<(usize, Result<(), Error>)> {
+        let mut results = vec![];
+        let mut remaining = vec![];
 
         for id in &self.isolated_threads {
-            match self.is_thread_free(id) {
-                Ok(None) => match fs::remove_dir(path!(self.cpuset_path(), id.to_string())) {
+            let path = self.thread_path(id);
+            let result = match self.wait_for_thread_to_free(id) {
+                Ok(None) => match fs::remove_dir(&path) {
                     Ok(_) => match self.return_thread_to_pool(id) {
-                        Ok(_) => {}
-                        Err(_) => errors = true, // TODO: emit warning
+                        Ok(_) => Ok({}),
+                        Err(e) => Err(Error::new(
+                            e.kind(),
+                            format!("Failed to return the CPU to the pool: {}", e),
+                        )),
                     },
-                    Err(_) => errors = true, // TODO: emit warning
+                    Err(e) => {
+                        remaining.push(*id);
+                        Err(Error::new(
+                            e.kind(),
+                            format!("Failed to remove `{}`: {}", path.display(), e),
+                        ))
+                    }
                 },
-                Ok(Some(_)) => errors = true, // TODO: emit warning
-                Err(_) => errors = true,      // TODO: emit warning
-            }
+                Ok(Some(task)) => {
+                    remaining.push(*id);
+                    Err(Error::new(
+                        ErrorKind::Other,
+                        format!("The task `{}` is still running on the CPU.", task),
+                    ))
+                }
+                Err(e) => {
+                    remaining.push(*id);
+                    Err(Error::new(
+                        e.kind(),
+                        format!("Failed to read the tasks of `{}`: {}", path.display(), e),
+                    ))
+                }
+            };
+
+            results.push((*id, result));
         }
 
-        self.isolated_threads = vec![];
+        self.isolated_threads = remaining;
 
-        if errors {
-            return Err(Error::new(
-                ErrorKind::Other,
-                "Failed to release some of the pinned threads.",
-            ));
-        }
+        results
+    }
 
-        Ok({})
+    fn wait_for_thread_to_free(&self, id: &usize) -> Result<Option<String>, Error> {
+        let mut attempt = 0;
+
+        loop {
+            // Exited tasks remain listed until the kernel reaps them, give it a moment
+            match self.is_thread_free(id)? {
+                Some(_) if attempt < self.release_retries => {
+                    attempt += 1;
+                    self.clock.sleep(self.release_delay);
+                }
+                task => return Ok(task),
+            }
+        }
     }
 
     fn is_thread_free(&self, id: &usize) -> Result<Option<String>, Error> {
-        let tasks_file_path = path!(self.cpuset_path(), id.to_string(), "tasks");
+        let tasks_file_path = path!(self.thread_path(id), "tasks");
         let mut reader = BufReader::new(fs::File::open(tasks_file_path)?);
         let mut data = String::new();
         reader.read_line(&mut data)?;
@@ -275,42 +609,209 @@ impl CpuSet {
         Ok(None)
     }
 
-    fn ensure_mounted(&self) -> Result<(), Error> {
+    fn ensure_mounted(&mut self) -> Result<(), Error> {
         fs::create_dir_all(&self.mount_path)?;
 
-        match MountIter::<BufReader<fs::File>>::source_mounted_at("cgroup", &self.mount_path) {
-            Ok(true) => Ok({}),
-            Ok(false) => self.mount_cpuset(),
-            Err(e) => Err(Error::new(
+        let mounts = match fs::read_to_string(MOUNTINFO_PATH) {
+            Ok(mountinfo) => parse_cgroup_mounts(&mountinfo),
+            Err(e) => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("An error occurred while reading mounts: {}", e),
+                ))
+            }
+        };
+
+        // Distributions co-mount cpuset with other controllers, so the source name can not be relied on
+        if let Some(mount) = mounts.iter().find(|mount| mount.path == self.mount_path) {
+            if mount.has_option("cpuset") {
+                return Ok({});
+            }
+
+            return Err(Error::new(
                 ErrorKind::Other,
-                format!("An error occurred while reading mounts: {}", e),
-            )),
+                format!(
+                    "`{}` is already mounted as a cgroup tree without the cpuset controller: {}",
+                    self.mount_path.display(),
+                    mount.options.join(",")
+                ),
+            ));
         }
+
+        // A cgroup v1 hierarchy can only be mounted again with the same set of controllers
+        let controllers = match mounts.iter().find(|mount| mount.has_option("cpuset")) {
+            Some(mount) => mount
+                .options
+                .iter()
+                .filter(|option| *option != "rw" && *option != "ro")
+                .cloned()
+                .collect(),
+            None => vec![String::from("cpuset")],
+        };
+
+        self.mount_cpuset(controllers)
     }
 
-    fn mount_cpuset(&self) -> Result<(), Error> {
+    fn mount_cpuset(&mut self, mut data: Vec<String>) -> Result<(), Error> {
+        let mut flags = MsFlags::empty();
+        for option in &self.mount_options {
+            match option.as_str() {
+                "nodev" => flags.insert(MsFlags::MS_NODEV),
+                "noexec" => flags.insert(MsFlags::MS_NOEXEC),
+                "nosuid" => flags.insert(MsFlags::MS_NOSUID),
+                "noatime" => flags.insert(MsFlags::MS_NOATIME),
+                "relatime" => flags.insert(MsFlags::MS_RELATIME),
+                option if !data.iter().any(|existing| existing == option) => {
+                    data.push(option.to_owned())
+                }
+                _ => {}
+            }
+        }
+
         if let Err(e) = mount(
             Some("cgroup"),
             &self.mount_path,
             Some("cgroup"),
-            MsFlags::empty(),
-            Some("cpuset"),
+            flags,
+            Some(data.join(",").as_str()),
         ) {
+            // A launcher running as a service account might only be granted some of the capabilities
+            let hint = match e {
+                nix::Error::Sys(Errno::EPERM) => {
+                    " (mounting needs the `CAP_SYS_ADMIN` capability, otherwise the cpuset tree has \
+                    to be mounted beforehand)"
+                }
+                _ => "",
+            };
+
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "Failed to mount cpuset to `{}`: {}{}",
+                    self.mount_path.display(),
+                    e,
+                    hint
+                ),
+            ));
+        }
+
+        self.mounted = true;
+
+        Ok({})
+    }
+
+    pub fn unmount(&mut self) -> Result<(), Error> {
+        if !self.mounted || self.isolated_threads.len() > 0 {
+            return Ok({});
+        }
+
+        // Launchers isolating threads at the same time would recreate the directories being removed
+        let _lock = self.lock_pool()?;
+
+        // The hierarchy can be shared with cgroups created by somebody else, those must outlive us
+        let entries = match fs::read_dir(&self.mount_path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                return Err(Error::new(
+                    e.kind(),
+                    format!("Failed to list `{}`: {}", self.mount_path.display(), e),
+                ))
+            }
+        };
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() && entry.file_name() != self.prefix.as_os_str() {
+                return Ok({});
+            }
+        }
+
+        // Other launcher instances still have isolated threads, the hierarchy is in use
+        let cpus = fs::read_to_string(path!(self.cpuset_path(), "cpuset.cpus"))?;
+        let pool_cpus = fs::read_to_string(path!(self.cpuset_path(), "pool", "cpuset.cpus"))?;
+        if parse_cpus_list(cpus.trim()) != parse_cpus_list(pool_cpus.trim()) {
+            return Ok({});
+        }
+
+        let tasks = fs::read_to_string(path!(self.cpuset_path(), "pool", "tasks"))?;
+        for task in tasks.lines() {
+            // The task might have already exited
+            let _ = fs::write(path!(self.mount_path, "tasks"), task);
+        }
+
+        fs::remove_dir(path!(self.cpuset_path(), "pool"))?;
+        fs::remove_dir(self.cpuset_path())?;
+
+        if let Err(e) = umount(&self.mount_path) {
             return Err(Error::new(
                 ErrorKind::Other,
                 format!(
-                    "Failed to mount cpuset to `{}`: {}",
+                    "Failed to unmount cpuset from `{}`: {}",
                     self.mount_path.display(),
                     e
                 ),
             ));
         }
 
+        self.mounted = false;
+
+        Ok({})
+    }
+}
+
+impl PinningBackend for CpuSet {
+    fn isolate(&mut self, host_ids: &[usize]) -> HashMap<usize, String> {
+        let mut failures = HashMap::new();
+        if let Err(e) = self.isolate_threads(host_ids, &mut failures) {
+            for host_id in host_ids {
+                failures.entry(*host_id).or_insert_with(|| e.to_string());
+            }
+        }
+
+        failures
+    }
+
+    fn pin(&mut self, host_id: usize, task_id: usize) -> Result<(), Error> {
+        fs::write(
+            path!(self.thread_path(&host_id), "tasks"),
+            task_id.to_string(),
+        )
+    }
+
+    fn release(&mut self) -> Result<(), Error> {
+        let failures: Vec<String> = self
+            .release_threads()
+            .into_iter()
+            .filter_map(|(id, result)| result.err().map(|e| format!("`{}` - {}", id, e)))
+            .collect();
+
+        if failures.len() > 0 {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "Failed to release the pinned host CPUs {}",
+                    failures.join(", ")
+                ),
+            ));
+        }
+
         Ok({})
     }
+
+    fn evict(&mut self) -> Result<(), Error> {
+        // Tasks still running on the pinned CPUs are moved into the pool, so their cpusets can be removed
+        let pool_tasks_path = path!(self.cpuset_path(), "pool", "tasks");
+        for id in &self.isolated_threads {
+            for task in self.get_thread_tasks(*id).unwrap_or_default() {
+                // The task might have already exited
+                let _ = fs::write(&pool_tasks_path, task);
+            }
+        }
+
+        self.release()
+    }
 }
 
-fn parse_cpus_list<S: AsRef<str>>(spec: S) -> Vec<usize> {
+pub fn parse_cpus_list<S: AsRef<str>>(spec: S) -> Vec<usize> {
     let spec = spec.as_ref();
 
     if spec.len() == 0 {
@@ -319,18 +820,116 @@ fn parse_cpus_list<S: AsRef<str>>(spec: S) -> Vec<usize> {
 
     let mut threads = vec![];
     for group in spec.split(',') {
-        let cores: Vec<&str> = group.split('-').collect();
-        match cores.len() {
-            1 => threads.push(cores[0].parse::<usize>().unwrap()),
-            2 => threads
-                .extend(cores[0].parse::<usize>().unwrap()..cores[1].parse::<usize>().unwrap() + 1),
+        let mut parts = group.splitn(2, ':');
+        let cores: Vec<&str> = parts.next().unwrap().split('-').collect();
+        let (first, last) = match cores.len() {
+            1 => (
+                cores[0].parse::<usize>().unwrap(),
+                cores[0].parse::<usize>().unwrap(),
+            ),
+            2 => (
+                cores[0].parse::<usize>().unwrap(),
+                cores[1].parse::<usize>().unwrap(),
+            ),
             _ => panic!("Malformed cpu core specification: {}", spec),
+        };
+
+        // The kernel `used/size` group format takes the first `used` CPUs out of every `size`
+        // CPUs of the range, a single number is a plain stride
+        let (used, size) = match parts.next() {
+            Some(_) if cores.len() == 1 => panic!("Malformed cpu core specification: {}", spec),
+            Some(stride) => match stride.find('/') {
+                Some(position) => (
+                    stride[..position].parse::<usize>().unwrap(),
+                    stride[position + 1..].parse::<usize>().unwrap(),
+                ),
+                None => (1, stride.parse::<usize>().unwrap()),
+            },
+            None => (1, 1),
+        };
+
+        if used < 1 || used > size {
+            panic!("Malformed cpu core specification: {}", spec);
         }
+
+        threads.extend((first..last + 1).filter(|cpu| (cpu - first) % size < used));
     }
 
     threads
 }
 
+struct CgroupMount {
+    path: PathBuf,
+    options: Vec<String>,
+}
+
+impl CgroupMount {
+    fn has_option(&self, name: &str) -> bool {
+        self.options.iter().any(|option| option == name)
+    }
+}
+
+// Every line of mountinfo is `id parent major:minor root mount-point options [optional fields] - fstype
+// source super-options`, cgroup v1 lists the mounted controllers among the super options
+fn parse_cgroup_mounts(mountinfo: &str) -> Vec<CgroupMount> {
+    let mut mounts = vec![];
+
+    for line in mountinfo.lines() {
+        let fields: Vec<&str> = line.split(' ').collect();
+        let separator = match fields.iter().skip(6).position(|field| *field == "-") {
+            Some(position) => position + 6,
+            None => continue,
+        };
+
+        if fields.len() < separator + 4 || fields[separator + 1] != "cgroup" {
+            continue;
+        }
+
+        mounts.push(CgroupMount {
+            path: PathBuf::from(unescape_mount_path(fields[4])),
+            options: fields[separator + 3]
+                .split(',')
+                .map(|option| option.to_owned())
+                .collect(),
+        });
+    }
+
+    mounts
+}
+
+// The kernel escapes spaces, tabs, new lines and backslashes in mount points as octal sequences
+fn unescape_mount_path(path: &str) -> String {
+    let mut unescaped = String::with_capacity(path.len());
+    let mut rest = path;
+
+    while let Some(position) = rest.find('\\') {
+        unescaped.push_str(&rest[..position]);
+        let code = rest
+            .get(position + 1..position + 4)
+            .and_then(|code| u8::from_str_radix(code, 8).ok());
+        match code {
+            Some(code) => {
+                unescaped.push(code as char);
+                rest = &rest[position + 4..];
+            }
+            None => {
+                unescaped.push('\\');
+                rest = &rest[position + 1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+
+    unescaped
+}
+
+fn lock_path<D: AsRef<Path>, P: AsRef<Path>>(directory: D, prefix: P) -> PathBuf {
+    path!(
+        directory.as_ref(),
+        format!("cpuset-{}.lock", prefix.as_ref().display())
+    )
+}
+
 fn read_cpus_from_file(file: &mut fs::File) -> Result<Vec<String>, Error> {
     let mut cpus = String::new();
     file.read_to_string(&mut cpus)?;
@@ -362,8 +961,27 @@ fn get_task_cpus(task: &str) -> Result<Vec<usize>, Error> {
     }
 }
 
-fn get_cpus_from_task_status<S: AsRef<str>>(status: S) -> Result<String, Error> {
-    for line in status.as_ref().lines() {
+fn is_affinity_fixed(task: &str) -> Result<bool, Error> {
+    let stat = fs::read_to_string(path!("/proc", task, "stat"))?;
+
+    // The command name may contain spaces and parentheses, fields are counted after it
+    let flags = stat
+        .rsplitn(2, ')')
+        .next()
+        .and_then(|fields| fields.split_whitespace().nth(6))
+        .and_then(|flags| flags.parse::<u64>().ok());
+
+    match flags {
+        Some(flags) => Ok(flags & PF_NO_SETAFFINITY != 0),
+        None => Err(Error::new(
+            ErrorKind::Other,
+            format!("Task ID {}: malformed process stat: {}", task, stat),
+        )),
+    }
+}
+
+fn get_cpus_from_task_status<S: AsRef<str>>(status: S) -> Result<String, Error> {
+    for line in status.as_ref().lines() {
         let fields = line.split(":\t").collect::<Vec<&str>>();
         if fields.len() != 2 {
             return Err(Error::new(
@@ -386,13 +1004,17 @@ fn get_cpus_from_task_status<S: AsRef<str>>(status: S) -> Result<String, Error>
 #[cfg(test)]
 mod test {
     use super::CpuSet;
-    use crate::{assert_error, expect, vec_deq, verify_expectations};
+    use crate::{
+        assert_error, clock::fake::FakeClock, expect, pinning::PinningBackend, vec_deq,
+        verify_expectations,
+    };
     use ::nix::{fcntl::FlockArg, mount::MsFlags};
     use ::std::{
         cell::RefCell,
         collections::VecDeque,
         io::{Error, ErrorKind, SeekFrom},
         os::unix::io::RawFd,
+        time::Duration,
     };
 
     macro_rules! error {
@@ -401,6 +1023,25 @@ mod test {
         }};
     }
 
+    fn mountinfo(cgroup_mounts: &[(&str, &str)]) -> String {
+        let mut mountinfo = String::from(
+            "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+            23 22 0:21 / /sys rw,nosuid,nodev,noexec,relatime shared:2 - sysfs sysfs rw\n",
+        );
+        for (index, (path, options)) in cgroup_mounts.iter().enumerate() {
+            mountinfo.push_str(&format!(
+                "{} 23 0:{} / {} rw,nosuid,nodev,noexec,relatime shared:{} - cgroup cgroup {}\n",
+                30 + index,
+                30 + index,
+                path,
+                10 + index,
+                options
+            ));
+        }
+
+        mountinfo
+    }
+
     struct TestExpectations {
         nix_fcntl_flock: VecDeque<((RawFd, ::nix::fcntl::FlockArg), ::nix::Result<()>)>,
         nix_mount_mount: VecDeque<(
@@ -413,10 +1054,10 @@ mod test {
             ),
             ::nix::Result<()>,
         )>,
-        proc_mounts_mount_iter_source_mounted_at:
-            VecDeque<((&'static str, &'static str), Result<bool, Error>)>,
+        nix_mount_umount: VecDeque<(&'static str, ::nix::Result<()>)>,
         std_fs_create_dir_all: VecDeque<(&'static str, Result<(), Error>)>,
         std_fs_file_as_raw_fd: VecDeque<((), RawFd)>,
+        std_fs_file_create: VecDeque<(&'static str, Result<std::fs::File, Error>)>,
         std_fs_file_open: VecDeque<(&'static str, Result<std::fs::File, Error>)>,
         std_fs_file_read: VecDeque<((), Result<String, Error>)>,
         std_fs_file_seek: VecDeque<(SeekFrom, Result<u64, Error>)>,
@@ -426,6 +1067,7 @@ mod test {
         std_fs_open_options_open: VecDeque<(&'static str, Result<std::fs::File, Error>)>,
         std_fs_open_options_read: VecDeque<(bool, ())>,
         std_fs_open_options_write: VecDeque<(bool, ())>,
+        std_fs_read_dir: VecDeque<(&'static str, Result<Vec<(&'static str, bool)>, Error>)>,
         std_fs_read_to_string: VecDeque<(&'static str, Result<String, Error>)>,
         std_fs_remove_dir: VecDeque<(&'static str, Result<(), Error>)>,
         std_fs_write: VecDeque<((&'static str, &'static str), Result<(), Error>)>,
@@ -436,9 +1078,10 @@ mod test {
             TestExpectations {
                 nix_fcntl_flock: vec_deq![],
                 nix_mount_mount: vec_deq![],
-                proc_mounts_mount_iter_source_mounted_at: vec_deq![],
+                nix_mount_umount: vec_deq![],
                 std_fs_create_dir_all: vec_deq![],
                 std_fs_file_as_raw_fd: vec_deq![],
+                std_fs_file_create: vec_deq![],
                 std_fs_file_open: vec_deq![],
                 std_fs_file_read: vec_deq![],
                 std_fs_file_seek: vec_deq![],
@@ -448,6 +1091,7 @@ mod test {
                 std_fs_open_options_open: vec_deq![],
                 std_fs_open_options_read: vec_deq![],
                 std_fs_open_options_write: vec_deq![],
+                std_fs_read_dir: vec_deq![],
                 std_fs_read_to_string: vec_deq![],
                 std_fs_remove_dir: vec_deq![],
                 std_fs_write: vec_deq![],
@@ -461,10 +1105,10 @@ mod test {
         verify_expectations!(
             nix::fcntl::flock => TEST_EXPECTATIONS::nix_fcntl_flock,
             nix::mount::mount => TEST_EXPECTATIONS::nix_mount_mount,
-            proc_mounts::MountIter<T>::source_mounted_at =>
-                TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at,
+            nix::mount::umount => TEST_EXPECTATIONS::nix_mount_umount,
             std::fs::create_dir_all => TEST_EXPECTATIONS::std_fs_create_dir_all,
             std::fs::File::as_raw_fd => TEST_EXPECTATIONS::std_fs_file_as_raw_fd,
+            std::fs::File::create => TEST_EXPECTATIONS::std_fs_file_create,
             std::fs::File::open => TEST_EXPECTATIONS::std_fs_file_open,
             std::fs::File::read => TEST_EXPECTATIONS::std_fs_file_read,
             std::fs::File::seek => TEST_EXPECTATIONS::std_fs_file_seek,
@@ -474,6 +1118,7 @@ mod test {
             std::fs::OpenOptions::open => TEST_EXPECTATIONS::std_fs_open_options_open,
             std::fs::OpenOptions::read => TEST_EXPECTATIONS::std_fs_open_options_read,
             std::fs::OpenOptions::write => TEST_EXPECTATIONS::std_fs_open_options_write,
+            std::fs::read_dir => TEST_EXPECTATIONS::std_fs_read_dir,
             std::fs::read_to_string => TEST_EXPECTATIONS::std_fs_read_to_string,
             std::fs::remove_dir => TEST_EXPECTATIONS::std_fs_remove_dir,
             std::fs::write => TEST_EXPECTATIONS::std_fs_write,
@@ -485,12 +1130,48 @@ mod test {
             use super::super::TEST_EXPECTATIONS;
             use crate::verify_expectation;
             use ::std::{
+                ffi::OsString,
                 io::{Read, Result, Seek, SeekFrom},
                 os::unix::io::{AsRawFd, RawFd},
                 path::Path,
                 str::from_utf8,
             };
 
+            pub struct DirEntry {
+                name: &'static str,
+                dir: bool,
+            }
+
+            impl DirEntry {
+                pub fn file_name(&self) -> OsString {
+                    OsString::from(self.name)
+                }
+
+                pub fn file_type(&self) -> Result<FileType> {
+                    Ok(FileType { dir: self.dir })
+                }
+            }
+
+            pub struct FileType {
+                dir: bool,
+            }
+
+            impl FileType {
+                pub fn is_dir(&self) -> bool {
+                    self.dir
+                }
+            }
+
+            pub fn read_dir<P: AsRef<Path>>(path: P) -> Result<Vec<Result<DirEntry>>> {
+                let path = path.as_ref().to_str().unwrap();
+                let entries: Vec<(&'static str, bool)> = verify_expectation!(TEST_EXPECTATIONS::std_fs_read_dir => std::fs::read_dir { path })?;
+
+                Ok(entries
+                    .into_iter()
+                    .map(|(name, dir)| Ok(DirEntry { name, dir }))
+                    .collect())
+            }
+
             pub struct OpenOptions {}
 
             impl OpenOptions {
@@ -553,6 +1234,12 @@ mod test {
             pub struct File {}
 
             impl File {
+                pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+                    let path = path.as_ref().to_str().unwrap();
+
+                    verify_expectation!(TEST_EXPECTATIONS::std_fs_file_create => std::fs::File::create { path })
+                }
+
                 pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
                     let path = path.as_ref().to_str().unwrap();
 
@@ -594,34 +1281,6 @@ mod test {
         }
     }
 
-    pub mod proc_mounts {
-        use super::TEST_EXPECTATIONS;
-        use crate::verify_expectation;
-        use std::{
-            io::{BufReader, Result},
-            path::Path,
-        };
-
-        pub struct MountIter<T> {
-            _inner: T,
-        }
-
-        impl MountIter<BufReader<super::std::fs::File>> {
-            pub fn source_mounted_at<D: AsRef<Path>, P: AsRef<Path>>(
-                source: D,
-                path: P,
-            ) -> Result<bool> {
-                let path = path.as_ref().to_str().unwrap();
-                let source = source.as_ref().to_str().unwrap();
-
-                verify_expectation!(
-                    TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at =>
-                        proc_mounts::MountIter<T>::source_mounted_at { source, path }
-                )
-            }
-        }
-    }
-
     pub mod nix {
         pub mod fcntl {
             use super::super::TEST_EXPECTATIONS;
@@ -668,9 +1327,23 @@ mod test {
                     })
                 })????
             }
+
+            pub fn umount<P: ?Sized + NixPath>(target: &P) -> Result<()> {
+                target.with_nix_path(|target| {
+                    let target = from_utf8(target.to_bytes()).unwrap();
+
+                    verify_expectation!(TEST_EXPECTATIONS::nix_mount_umount => nix::mount::umount { target })
+                })?
+            }
         }
     }
 
+    fn expect_pool_lock(path: &'static str, fd: RawFd) {
+        expect!(TEST_EXPECTATIONS::std_fs_file_create: { path => Ok(std::fs::File {}) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => fd });
+        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { fd, FlockArg::LockExclusive => Ok({}) });
+    }
+
     #[test]
     fn parse_cpus_list_handles_single_core_specification() {
         assert_eq!(vec![1], super::parse_cpus_list("1"));
@@ -726,6 +1399,28 @@ mod test {
         super::parse_cpus_list("0-1-2");
     }
 
+    #[test]
+    fn parse_cpus_list_handles_stride_specification() {
+        assert_eq!(
+            vec![0, 2, 4, 6, 9, 12],
+            super::parse_cpus_list("0-7:2,9-14:3")
+        );
+    }
+
+    #[test]
+    fn parse_cpus_list_handles_group_specification() {
+        assert_eq!(
+            vec![0, 1, 4, 5, 8, 9, 12],
+            super::parse_cpus_list("0-12:2/4")
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn parse_cpus_list_panics_if_group_is_malformed() {
+        super::parse_cpus_list("0-7:3/2");
+    }
+
     #[test]
     fn parse_cpus_list_handles_empty_specification() {
         assert_eq!(Vec::<usize>::new(), super::parse_cpus_list(""));
@@ -750,7 +1445,7 @@ mod test {
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_create_destination_mounting_directory() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_create_destination_mounting_directory() {
         let mut cpuset = CpuSet::new("/test1/cgroups/cpuset", "prefix1").unwrap();
 
         expect!(
@@ -761,40 +1456,40 @@ mod test {
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `1` - std::fs::create_dir_all(1)",
-            cpuset.pin_task(1, 32001)
+            cpuset.pin_tasks(&[(1, 32001)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_check_cpuset_cgroug_mount_status() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_check_cpuset_cgroug_mount_status() {
         let mut cpuset = CpuSet::new("/test2/cgroups/cpuset", "prefix2").unwrap();
 
         expect!(TEST_EXPECTATIONS::std_fs_create_dir_all: { "/test2/cgroups/cpuset" => Ok({}) });
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test2/cgroups/cpuset" => error!("source_mounted_at(2)") }
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => error!("std::fs::read_to_string(2)") },
         );
 
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `2` - An error \
-            occurred while reading mounts: source_mounted_at(2)",
-            cpuset.pin_task(2, 32002)
+            occurred while reading mounts: std::fs::read_to_string(2)",
+            cpuset.pin_tasks(&[(2, 32002)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_cpuset_cgroup_mount_fails() {
+    fn cpuset_pin_tasks_returns_error_if_cpuset_cgroup_mount_fails() {
         let mut cpuset = CpuSet::new("/test3/cgroups/cpuset", "prefix3").unwrap();
 
         expect!(TEST_EXPECTATIONS::std_fs_create_dir_all: { "/test3/cgroups/cpuset" => Ok({}) });
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test3/cgroups/cpuset" => Ok(false) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[])) },
         );
         expect!(TEST_EXPECTATIONS::nix_mount_mount: {
             "cgroup", "/test3/cgroups/cpuset", "cgroup", MsFlags::empty(), "cpuset" =>
@@ -805,24 +1500,151 @@ mod test {
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `3` - Failed to \
             mount cpuset to `/test3/cgroups/cpuset`: Invalid path",
-            cpuset.pin_task(3, 32003)
+            cpuset.pin_tasks(&[(3, 32003)]).pop().unwrap()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_pin_tasks_explains_missing_capability_if_mount_is_not_permitted() {
+        let mut cpuset = CpuSet::new("/test3/cgroups/cpuset", "prefix3").unwrap();
+
+        expect!(TEST_EXPECTATIONS::std_fs_create_dir_all: { "/test3/cgroups/cpuset" => Ok({}) });
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[])) },
+        );
+        expect!(TEST_EXPECTATIONS::nix_mount_mount: {
+            "cgroup", "/test3/cgroups/cpuset", "cgroup", MsFlags::empty(), "cpuset" =>
+                Err(::nix::Error::Sys(::nix::errno::Errno::EPERM))
+        });
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to isolate the host cpu thread `3` - Failed to \
+            mount cpuset to `/test3/cgroups/cpuset`: EPERM: Operation not permitted (mounting \
+            needs the `CAP_SYS_ADMIN` capability, otherwise the cpuset tree has to be mounted \
+            beforehand)",
+            cpuset.pin_tasks(&[(3, 32003)]).pop().unwrap()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_pin_tasks_uses_cpuset_tree_co_mounted_with_other_controllers() {
+        let mut cpuset = CpuSet::new("/test63/cgroups/cpuset", "prefix63").unwrap();
+
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix63.lock", 63);
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_create_dir_all:
+            { "/test63/cgroups/cpuset" => Ok({}) },
+            { "/test63/cgroups/cpuset/prefix63" => error!("std::fs::create_dir_all(63)") },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" =>
+                Ok(mountinfo(&[("/test63/cgroups/cpuset", "rw,cpu,cpuacct,cpuset")])) },
+        );
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to isolate the host cpu thread `63` - std::fs::create_dir_all(63)",
+            cpuset.pin_tasks(&[(63, 32063)]).pop().unwrap()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_pin_tasks_returns_error_if_mount_path_has_cgroup_tree_without_cpuset() {
+        let mut cpuset = CpuSet::new("/test64/cgroups/cpuset", "prefix64").unwrap();
+
+        expect!(TEST_EXPECTATIONS::std_fs_create_dir_all: { "/test64/cgroups/cpuset" => Ok({}) });
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" =>
+                Ok(mountinfo(&[("/test64/cgroups/cpuset", "rw,cpu,cpuacct")])) },
+        );
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to isolate the host cpu thread `64` - `/test64/cgroups/cpuset` is already \
+            mounted as a cgroup tree without the cpuset controller: rw,cpu,cpuacct",
+            cpuset.pin_tasks(&[(64, 32064)]).pop().unwrap()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_pin_tasks_mounts_co_mounted_hierarchy_with_extra_options() {
+        let mut cpuset = CpuSet::new("/test65/cgroups/cpuset", "prefix65")
+            .unwrap()
+            .with_mount_options(&[
+                String::from("nosuid"),
+                String::from("nodev"),
+                String::from("clone_children"),
+                String::from("cpuset"),
+            ]);
+
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix65.lock", 65);
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_create_dir_all:
+            { "/test65/cgroups/cpuset" => Ok({}) },
+            { "/test65/cgroups/cpuset/prefix65" => error!("std::fs::create_dir_all(65)") },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" =>
+                Ok(mountinfo(&[("/sys/fs/cgroup/cpu\\040cpuset", "rw,cpu,cpuset")])) },
+        );
+        expect!(TEST_EXPECTATIONS::nix_mount_mount: {
+            "cgroup", "/test65/cgroups/cpuset", "cgroup", MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
+                "cpu,cpuset,clone_children" => Ok({})
+        });
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to isolate the host cpu thread `65` - std::fs::create_dir_all(65)",
+            cpuset.pin_tasks(&[(65, 32065)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_create_cpuset_prefix_directory() {
+    fn parse_cgroup_mounts_unescapes_mount_paths() {
+        let mounts = super::parse_cgroup_mounts(&mountinfo(&[(
+            "/sys/fs/cgroup/cpu\\040cpuset",
+            "rw,cpuset",
+        )]));
+
+        assert_eq!(1, mounts.len());
+        assert_eq!(
+            ::std::path::PathBuf::from("/sys/fs/cgroup/cpu cpuset"),
+            mounts[0].path
+        );
+        assert_eq!(vec!["rw", "cpuset"], mounts[0].options);
+    }
+
+    #[test]
+    fn cpuset_pin_tasks_returns_error_if_unable_to_create_cpuset_prefix_directory() {
         let mut cpuset = CpuSet::new("/test4/cgroups/cpuset", "prefix4").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix4.lock", 4);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test4/cgroups/cpuset" => Ok({}) },
             { "/test4/cgroups/cpuset/prefix4" => error!("std::fs::create_dir_all(4)") },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test4/cgroups/cpuset" => Ok(false) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[])) },
         );
         expect!(TEST_EXPECTATIONS::nix_mount_mount:
             { "cgroup", "/test4/cgroups/cpuset", "cgroup", MsFlags::empty(), "cpuset" => Ok({}) }
@@ -831,24 +1653,26 @@ mod test {
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `4` - std::fs::create_dir_all(4)",
-            cpuset.pin_task(4, 32004)
+            cpuset.pin_tasks(&[(4, 32004)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_make_cpuset_cpu_exclusive() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_make_cpuset_cpu_exclusive() {
         let mut cpuset = CpuSet::new("/test5/cgroups/cpuset", "prefix5").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix5.lock", 5);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test5/cgroups/cpuset" => Ok({}) },
             { "/test5/cgroups/cpuset/prefix5" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test5/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test5/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -858,24 +1682,26 @@ mod test {
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `5` - std::fs::write(5)",
-            cpuset.pin_task(5, 32005)
+            cpuset.pin_tasks(&[(5, 32005)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_read_prefix_cpuset_mems() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_read_prefix_cpuset_mems() {
         let mut cpuset = CpuSet::new("/test6/cgroups/cpuset", "prefix6").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix6.lock", 6);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test6/cgroups/cpuset" => Ok({}) },
             { "/test6/cgroups/cpuset/prefix6" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test6/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test6/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -889,24 +1715,26 @@ mod test {
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `6` - std::fs::read_to_string(6)",
-            cpuset.pin_task(6, 32006)
+            cpuset.pin_tasks(&[(6, 32006)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_read_cpuset_mems() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_read_cpuset_mems() {
         let mut cpuset = CpuSet::new("/test7/cgroups/cpuset", "prefix7").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix7.lock", 7);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test7/cgroups/cpuset" => Ok({}) },
             { "/test7/cgroups/cpuset/prefix7" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test7/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test7/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -915,30 +1743,34 @@ mod test {
         expect!(
             TEST_EXPECTATIONS::std_fs_read_to_string:
             { "/test7/cgroups/cpuset/prefix7/cpuset.mems" => Ok(String::new()) },
+            { "/test7/cgroups/cpuset/cpuset.effective_mems" =>
+                Err(Error::new(ErrorKind::NotFound, "std::fs::read_to_string(7)")) },
             { "/test7/cgroups/cpuset/cpuset.mems" => error!("std::fs::read_to_string(7)") },
         );
 
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `7` - std::fs::read_to_string(7)",
-            cpuset.pin_task(7, 32007)
+            cpuset.pin_tasks(&[(7, 32007)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_write_prefix_cpuset_mems() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_write_prefix_cpuset_mems() {
         let mut cpuset = CpuSet::new("/test8/cgroups/cpuset", "prefix8").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix8.lock", 8);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test8/cgroups/cpuset" => Ok({}) },
             { "/test8/cgroups/cpuset/prefix8" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test8/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test8/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -948,30 +1780,32 @@ mod test {
         expect!(
             TEST_EXPECTATIONS::std_fs_read_to_string:
             { "/test8/cgroups/cpuset/prefix8/cpuset.mems" => Ok(String::new()) },
-            { "/test8/cgroups/cpuset/cpuset.mems" => Ok("8".to_string()) },
+            { "/test8/cgroups/cpuset/cpuset.effective_mems" => Ok("8".to_string()) },
         );
 
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `8` - std::fs::write(8)",
-            cpuset.pin_task(8, 32008)
+            cpuset.pin_tasks(&[(8, 32008)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_read_prefix_cpuset_cpus() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_read_prefix_cpuset_cpus() {
         let mut cpuset = CpuSet::new("/test9/cgroups/cpuset", "prefix9").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix9.lock", 9);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test9/cgroups/cpuset" => Ok({}) },
             { "/test9/cgroups/cpuset/prefix9" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test9/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test9/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -981,6 +1815,7 @@ mod test {
         expect!(
             TEST_EXPECTATIONS::std_fs_read_to_string:
             { "/test9/cgroups/cpuset/prefix9/cpuset.mems" => Ok(String::new()) },
+            { "/test9/cgroups/cpuset/cpuset.effective_mems" => Ok("\n".to_string()) },
             { "/test9/cgroups/cpuset/cpuset.mems" => Ok("9".to_string()) },
             { "/test9/cgroups/cpuset/prefix9/cpuset.cpus" => error!("std::fs::read_to_string(9)") },
         );
@@ -988,24 +1823,26 @@ mod test {
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `9` - std::fs::read_to_string(9)",
-            cpuset.pin_task(9, 32009)
+            cpuset.pin_tasks(&[(9, 32009)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_read_cpuset_cpus() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_read_cpuset_cpus() {
         let mut cpuset = CpuSet::new("/test10/cgroups/cpuset", "prefix10").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix10.lock", 10);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test10/cgroups/cpuset" => Ok({}) },
             { "/test10/cgroups/cpuset/prefix10" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test10/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test10/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1015,30 +1852,32 @@ mod test {
             TEST_EXPECTATIONS::std_fs_read_to_string:
             { "/test10/cgroups/cpuset/prefix10/cpuset.mems" => Ok("10".to_string()) },
             { "/test10/cgroups/cpuset/prefix10/cpuset.cpus" => Ok(String::new()) },
-            { "/test10/cgroups/cpuset/cpuset.cpus" => error!("std::fs::read_to_string(10)") },
+            { "/test10/cgroups/cpuset/cpuset.effective_cpus" => error!("std::fs::read_to_string(10)") },
         );
 
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `10` - std::fs::read_to_string(10)",
-            cpuset.pin_task(10, 32010)
+            cpuset.pin_tasks(&[(10, 32010)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_write_prefix_cpuset_cpus() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_write_prefix_cpuset_cpus() {
         let mut cpuset = CpuSet::new("/test11/cgroups/cpuset", "prefix11").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix11.lock", 11);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test11/cgroups/cpuset" => Ok({}) },
             { "/test11/cgroups/cpuset/prefix11" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test11/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test11/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1049,22 +1888,26 @@ mod test {
             TEST_EXPECTATIONS::std_fs_read_to_string:
             { "/test11/cgroups/cpuset/prefix11/cpuset.mems" => Ok("11".to_string()) },
             { "/test11/cgroups/cpuset/prefix11/cpuset.cpus" => Ok(String::new()) },
+            { "/test11/cgroups/cpuset/cpuset.effective_cpus" =>
+                Err(Error::new(ErrorKind::NotFound, "std::fs::read_to_string(11)")) },
             { "/test11/cgroups/cpuset/cpuset.cpus" => Ok("11".to_string()) },
         );
 
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `11` - std::fs::write(11)",
-            cpuset.pin_task(11, 32011)
+            cpuset.pin_tasks(&[(11, 32011)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_create_cpu_pool_directory() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_create_cpu_pool_directory() {
         let mut cpuset = CpuSet::new("/test12/cgroups/cpuset", "prefix12").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix12.lock", 12);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test12/cgroups/cpuset" => Ok({}) },
@@ -1072,8 +1915,8 @@ mod test {
             { "/test12/cgroups/cpuset/prefix12/pool" => error!("std::fs::create_dir_all(12)") },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test12/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test12/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1084,22 +1927,24 @@ mod test {
             TEST_EXPECTATIONS::std_fs_read_to_string:
             { "/test12/cgroups/cpuset/prefix12/cpuset.mems" => Ok("12".to_string()) },
             { "/test12/cgroups/cpuset/prefix12/cpuset.cpus" => Ok(String::new()) },
-            { "/test12/cgroups/cpuset/cpuset.cpus" => Ok("0-12".to_string()) },
+            { "/test12/cgroups/cpuset/cpuset.effective_cpus" => Ok("0-12".to_string()) },
         );
 
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `12` - std::fs::create_dir_all(12)",
-            cpuset.pin_task(12, 32012)
+            cpuset.pin_tasks(&[(12, 32012)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_make_cpuset_pool_cpu_exclusive() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_make_cpuset_pool_cpu_exclusive() {
         let mut cpuset = CpuSet::new("/test13/cgroups/cpuset", "prefix13").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix13.lock", 13);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test13/cgroups/cpuset" => Ok({}) },
@@ -1107,8 +1952,8 @@ mod test {
             { "/test13/cgroups/cpuset/prefix13/pool" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test13/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test13/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1124,16 +1969,18 @@ mod test {
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `13` - std::fs::write(13)",
-            cpuset.pin_task(13, 32013)
+            cpuset.pin_tasks(&[(13, 32013)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_read_cpuset_pool_mems() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_read_cpuset_pool_mems() {
         let mut cpuset = CpuSet::new("/test14/cgroups/cpuset", "prefix14").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix14.lock", 14);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test14/cgroups/cpuset" => Ok({}) },
@@ -1141,8 +1988,8 @@ mod test {
             { "/test14/cgroups/cpuset/prefix14/pool" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test14/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test14/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1159,16 +2006,18 @@ mod test {
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `14` - std::fs::read_to_string(14)",
-            cpuset.pin_task(14, 32014)
+            cpuset.pin_tasks(&[(14, 32014)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_write_cpuset_pool_mems() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_write_cpuset_pool_mems() {
         let mut cpuset = CpuSet::new("/test15/cgroups/cpuset", "prefix15").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix15.lock", 15);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test15/cgroups/cpuset" => Ok({}) },
@@ -1176,8 +2025,8 @@ mod test {
             { "/test15/cgroups/cpuset/prefix15/pool" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test15/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test15/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1195,16 +2044,18 @@ mod test {
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `15` - std::fs::write(15)",
-            cpuset.pin_task(15, 32015)
+            cpuset.pin_tasks(&[(15, 32015)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_read_cpuset_pool_cpus() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_read_cpuset_pool_cpus() {
         let mut cpuset = CpuSet::new("/test16/cgroups/cpuset", "prefix16").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix16.lock", 16);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test16/cgroups/cpuset" => Ok({}) },
@@ -1212,8 +2063,8 @@ mod test {
             { "/test16/cgroups/cpuset/prefix16/pool" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test16/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test16/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1232,16 +2083,18 @@ mod test {
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `16` - std::fs::read_to_string(16)",
-            cpuset.pin_task(16, 32016)
+            cpuset.pin_tasks(&[(16, 32016)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_write_cpuset_pool_cpus() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_write_cpuset_pool_cpus() {
         let mut cpuset = CpuSet::new("/test17/cgroups/cpuset", "prefix17").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix17.lock", 17);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test17/cgroups/cpuset" => Ok({}) },
@@ -1249,8 +2102,8 @@ mod test {
             { "/test17/cgroups/cpuset/prefix17/pool" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test17/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test17/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1269,16 +2122,18 @@ mod test {
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `17` - std::fs::write(17)",
-            cpuset.pin_task(17, 32017)
+            cpuset.pin_tasks(&[(17, 32017)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_read_cpuset_tasks() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_read_cpuset_tasks() {
         let mut cpuset = CpuSet::new("/test18/cgroups/cpuset", "prefix18").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix18.lock", 18);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test18/cgroups/cpuset" => Ok({}) },
@@ -1286,8 +2141,8 @@ mod test {
             { "/test18/cgroups/cpuset/prefix18/pool" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test18/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test18/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1308,16 +2163,18 @@ mod test {
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `18` - std::fs::read_to_string(18)",
-            cpuset.pin_task(18, 32018)
+            cpuset.pin_tasks(&[(18, 32018)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_open_thread_tasks_file() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_open_thread_tasks_file() {
         let mut cpuset = CpuSet::new("/test19/cgroups/cpuset", "prefix19").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix19.lock", 19);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test19/cgroups/cpuset" => Ok({}) },
@@ -1325,8 +2182,8 @@ mod test {
             { "/test19/cgroups/cpuset/prefix19/pool" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test19/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test19/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1358,16 +2215,18 @@ mod test {
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `19` - File::open(19)",
-            cpuset.pin_task(19, 32019)
+            cpuset.pin_tasks(&[(19, 32019)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_read_thread_tasks_file() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_read_thread_tasks_file() {
         let mut cpuset = CpuSet::new("/test40/cgroups/cpuset", "prefix40").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix40.lock", 40);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test40/cgroups/cpuset" => Ok({}) },
@@ -1375,8 +2234,8 @@ mod test {
             { "/test40/cgroups/cpuset/prefix40/pool" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test40/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test40/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1408,16 +2267,18 @@ mod test {
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `40` - std::fs::File::read(40)",
-            cpuset.pin_task(40, 32040)
+            cpuset.pin_tasks(&[(40, 32040)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_open_cpuset_pool_cpus_file_to_isolate_thread() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_open_cpuset_pool_cpus_file_to_isolate_thread() {
         let mut cpuset = CpuSet::new("/test20/cgroups/cpuset", "prefix20").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix20.lock", 20);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test20/cgroups/cpuset" => Ok({}) },
@@ -1425,8 +2286,8 @@ mod test {
             { "/test20/cgroups/cpuset/prefix20/pool" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test20/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test20/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1463,77 +2324,61 @@ mod test {
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `20` - std::fs::OpenOptions::new(20)",
-            cpuset.pin_task(20, 32020)
+            cpuset.pin_tasks(&[(20, 32020)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_lock_cpuset_pool_cpus_file_to_isolate_thread() {
-        let mut cpuset = CpuSet::new("/test21/cgroups/cpuset", "prefix21").unwrap();
+    fn cpuset_pin_tasks_returns_error_if_unable_to_lock_the_pool() {
+        let mut cpuset = CpuSet::new("/test21/cgroups/cpuset", "prefix21")
+            .unwrap()
+            .with_lock_directory("/var/lib/qemu-launcher");
 
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test21/cgroups/cpuset" => Ok({}) },
-            { "/test21/cgroups/cpuset/prefix21" => Ok({}) },
-            { "/test21/cgroups/cpuset/prefix21/pool" => Ok({}) },
-        );
-        expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test21/cgroups/cpuset" => Ok(true) },
-        );
-        expect!(
-            TEST_EXPECTATIONS::std_fs_write:
-            { "/test21/cgroups/cpuset/prefix21/cpuset.cpu_exclusive", "1" => Ok({}) },
-            { "/test21/cgroups/cpuset/prefix21/pool/cpuset.cpu_exclusive", "1" => Ok({}) },
-            { "/test21/cgroups/cpuset/prefix21/pool/tasks", "1121" => Ok({}) },
-            { "/test21/cgroups/cpuset/prefix21/pool/tasks", "2121" => Ok({}) },
-            { "/test21/cgroups/cpuset/prefix21/pool/tasks", "3121" => Ok({}) },
+            { "/test21/cgroups/cpuset" => Ok({}) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_read_to_string:
-            { "/test21/cgroups/cpuset/prefix21/cpuset.mems" => Ok("21\n".to_string()) },
-            { "/test21/cgroups/cpuset/prefix21/cpuset.cpus" => Ok("0-21\n".to_string()) },
-            { "/test21/cgroups/cpuset/prefix21/pool/cpuset.mems" => Ok("21\n".to_string()) },
-            { "/test21/cgroups/cpuset/prefix21/pool/cpuset.cpus" => Ok("0-21\n".to_string()) },
-            { "/test21/cgroups/cpuset/prefix21/pool/cpuset.cpus" => Ok("0-21\n".to_string()) },
-            { "/test21/cgroups/cpuset/tasks" => Ok("1121\n2121\n3121\n".to_string()) },
-            { "/proc/1121/status" => Ok("Cpus_allowed_list:\t0-21\n".to_string()) },
-            { "/proc/2121/status" => Ok("Cpus_allowed_list:\t0-21\n".to_string()) },
-            { "/proc/3121/status" => Ok("Cpus_allowed_list:\t0-21\n".to_string()) },
-        );
-        expect!(
-            TEST_EXPECTATIONS::std_fs_file_open:
-            { "/test21/cgroups/cpuset/prefix21/21/tasks" => Ok(std::fs::File {}) },
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test21/cgroups/cpuset", "rw,cpuset")])) },
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test21/cgroups/cpuset", "rw,cpuset")])) },
         );
-        expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok(String::new()) });
-        expect!(TEST_EXPECTATIONS::std_fs_open_options_new: { _ => std::fs::OpenOptions {} });
-        expect!(TEST_EXPECTATIONS::std_fs_open_options_read: { true => _ });
-        expect!(TEST_EXPECTATIONS::std_fs_open_options_write: { true => _ });
         expect!(
-            TEST_EXPECTATIONS::std_fs_open_options_open:
-            { "/test21/cgroups/cpuset/prefix21/pool/cpuset.cpus" => Ok(std::fs::File {}) },
+            TEST_EXPECTATIONS::std_fs_file_create:
+            { "/var/lib/qemu-launcher/cpuset-prefix21.lock" =>
+                Err(Error::new(ErrorKind::PermissionDenied, "std::fs::File::create(21)")) },
+            { "/var/lib/qemu-launcher/cpuset-prefix21.lock" => Ok(std::fs::File {}) },
         );
         expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 21 });
         expect!(
             TEST_EXPECTATIONS::nix_fcntl_flock: { 21, FlockArg::LockExclusive => Err(::nix::Error::InvalidPath) },
         );
 
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to isolate the host cpu thread `21` - Failed to open the \
+                `/var/lib/qemu-launcher/cpuset-prefix21.lock` lock file: std::fs::File::create(21)",
+            cpuset.pin_tasks(&[(21, 32021)]).pop().unwrap()
+        );
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `21` - Failed to lock \
-                `/test21/cgroups/cpuset/prefix21/pool/cpuset.cpus`: Invalid path",
-            cpuset.pin_task(21, 32021)
+                `/var/lib/qemu-launcher/cpuset-prefix21.lock`: Invalid path",
+            cpuset.pin_tasks(&[(21, 32021)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_read_cpuset_pool_cpus_file_to_isolate_thread() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_read_cpuset_pool_cpus_file_to_isolate_thread() {
         let mut cpuset = CpuSet::new("/test22/cgroups/cpuset", "prefix22").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix22.lock", 22);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test22/cgroups/cpuset" => Ok({}) },
@@ -1541,8 +2386,8 @@ mod test {
             { "/test22/cgroups/cpuset/prefix22/pool" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test22/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test22/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1576,22 +2421,22 @@ mod test {
             { "/test22/cgroups/cpuset/prefix22/pool/cpuset.cpus" => Ok(std::fs::File {}) },
         );
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => error!("std::fs::File::read(22)") });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 22 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 22, FlockArg::LockExclusive => Ok({}) });
 
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `22` - std::fs::File::read(22)",
-            cpuset.pin_task(22, 32022)
+            cpuset.pin_tasks(&[(22, 32022)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_seek_cpuset_pool_cpus_file_to_isolate_thread() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_seek_cpuset_pool_cpus_file_to_isolate_thread() {
         let mut cpuset = CpuSet::new("/test23/cgroups/cpuset", "prefix23").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix23.lock", 22);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test23/cgroups/cpuset" => Ok({}) },
@@ -1599,8 +2444,8 @@ mod test {
             { "/test23/cgroups/cpuset/prefix23/pool" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test23/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test23/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1636,23 +2481,79 @@ mod test {
         );
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("0-23".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => error!("std::fs::File::seek(23)") });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 22 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 22, FlockArg::LockExclusive => Ok({}) });
 
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `23` - std::fs::File::seek(23)",
-            cpuset.pin_task(23, 32023)
+            cpuset.pin_tasks(&[(23, 32023)]).pop().unwrap()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_pin_tasks_returns_error_if_host_cpu_is_not_in_the_pool() {
+        let mut cpuset = CpuSet::new("/test49/cgroups/cpuset", "prefix49").unwrap();
+
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix49.lock", 22);
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_create_dir_all:
+            { "/test49/cgroups/cpuset" => Ok({}) },
+            { "/test49/cgroups/cpuset/prefix49" => Ok({}) },
+            { "/test49/cgroups/cpuset/prefix49/pool" => Ok({}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test49/cgroups/cpuset", "rw,cpuset")])) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/test49/cgroups/cpuset/prefix49/cpuset.cpu_exclusive", "1" => Ok({}) },
+            { "/test49/cgroups/cpuset/prefix49/pool/cpuset.cpu_exclusive", "1" => Ok({}) },
+            { "/test49/cgroups/cpuset/prefix49/pool/tasks", "1349" => Ok({}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/test49/cgroups/cpuset/prefix49/cpuset.mems" => Ok("0\n".to_string()) },
+            { "/test49/cgroups/cpuset/prefix49/cpuset.cpus" => Ok("0-3\n".to_string()) },
+            { "/test49/cgroups/cpuset/prefix49/pool/cpuset.mems" => Ok("0\n".to_string()) },
+            { "/test49/cgroups/cpuset/prefix49/pool/cpuset.cpus" => Ok("0-3\n".to_string()) },
+            { "/test49/cgroups/cpuset/prefix49/pool/cpuset.cpus" => Ok("0-3\n".to_string()) },
+            { "/test49/cgroups/cpuset/tasks" => Ok("1349\n".to_string()) },
+            { "/proc/1349/status" => Ok("Cpus_allowed_list:\t0-3\n".to_string()) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_file_open:
+            { "/test49/cgroups/cpuset/prefix49/49/tasks" =>
+                Err(Error::new(ErrorKind::NotFound, "std::fs::File::open(49)")) },
+        );
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_new: { _ => std::fs::OpenOptions {} });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_read: { true => _ });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_write: { true => _ });
+        expect!(
+            TEST_EXPECTATIONS::std_fs_open_options_open:
+            { "/test49/cgroups/cpuset/prefix49/pool/cpuset.cpus" => Ok(std::fs::File {}) },
+        );
+        expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("0-3".to_string()) }, { _ => Ok(String::new()) });
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to isolate the host cpu thread `49` - The host CPU `49` is not in the CPU pool, \
+            it does not exist or is already pinned by another virtual machine. The pool contains CPUs: 0,1,2,3.",
+            cpuset.pin_tasks(&[(49, 32049)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_set_len_on_cpuset_pool_cpus_file_to_isolate_thread(
+    fn cpuset_pin_tasks_returns_error_if_unable_to_set_len_on_cpuset_pool_cpus_file_to_isolate_thread(
     ) {
         let mut cpuset = CpuSet::new("/test41/cgroups/cpuset", "prefix41").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix41.lock", 41);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test41/cgroups/cpuset" => Ok({}) },
@@ -1660,8 +2561,8 @@ mod test {
             { "/test41/cgroups/cpuset/prefix41/pool" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test41/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test41/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1697,24 +2598,24 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_fs_open_options_write: { true => _ });
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("0-41".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 41 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 41, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => error!("std::fs::File::set_len(41)") });
 
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `41` - std::fs::File::set_len(41)",
-            cpuset.pin_task(41, 32041)
+            cpuset.pin_tasks(&[(41, 32041)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_write_cpuset_pool_cpus_file_with_isolated_thread()
-    {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_write_cpuset_pool_cpus_file_with_isolated_thread(
+    ) {
         let mut cpuset = CpuSet::new("/test24/cgroups/cpuset", "prefix24").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix24.lock", 24);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test24/cgroups/cpuset" => Ok({}) },
@@ -1722,8 +2623,8 @@ mod test {
             { "/test24/cgroups/cpuset/prefix24/pool" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test24/cgroups/cpuset" => Ok(true) },
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test24/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1762,23 +2663,23 @@ mod test {
         expect!(
             TEST_EXPECTATIONS::std_fs_file_write: { "21,22,23" => error!("std::fs::File::write(24)") }
         );
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 24 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 24, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `24` - std::fs::File::write(24)",
-            cpuset.pin_task(24, 32024)
+            cpuset.pin_tasks(&[(24, 32024)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_create_a_cpuset_directory_for_isolated_thread() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_create_a_cpuset_directory_for_isolated_thread() {
         let mut cpuset = CpuSet::new("/test25/cgroups/cpuset", "prefix25").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix25.lock", 25);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test25/cgroups/cpuset" => Ok({}) },
@@ -1787,8 +2688,8 @@ mod test {
             { "/test25/cgroups/cpuset/prefix25/25" => error!("std::fs::create_dir_all(25)") },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test25/cgroups/cpuset" => Ok(true) }
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test25/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1825,23 +2726,23 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("21-25".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_write: { "21,22,23,24" => Ok(11) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 25 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 25, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `25` - std::fs::create_dir_all(25)",
-            cpuset.pin_task(25, 32025)
+            cpuset.pin_tasks(&[(25, 32025)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_write_cpuset_mems_for_isloated_thread() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_write_cpuset_mems_for_isloated_thread() {
         let mut cpuset = CpuSet::new("/test26/cgroups/cpuset", "prefix26").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix26.lock", 26);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test26/cgroups/cpuset" => Ok({}) },
@@ -1850,8 +2751,8 @@ mod test {
             { "/test26/cgroups/cpuset/prefix26/26" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test26/cgroups/cpuset" => Ok(true) }
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test26/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1890,23 +2791,24 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("23-26".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_write: { "23,24,25" => Ok(8) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 26 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 26, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `26` - std::fs::write(26)",
-            cpuset.pin_task(26, 32026)
+            cpuset.pin_tasks(&[(26, 32026)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_write_cpuset_cpu_exclusive_for_isloated_thread() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_write_cpuset_cpu_exclusive_for_isloated_thread()
+    {
         let mut cpuset = CpuSet::new("/test27/cgroups/cpuset", "prefix27").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix27.lock", 27);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test27/cgroups/cpuset" => Ok({}) },
@@ -1915,8 +2817,8 @@ mod test {
             { "/test27/cgroups/cpuset/prefix27/27" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test27/cgroups/cpuset" => Ok(true) }
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test27/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -1956,23 +2858,23 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("23-27".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_write: { "23,24,25,26" => Ok(11) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 27 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 27, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `27` - std::fs::write(27)",
-            cpuset.pin_task(27, 32027)
+            cpuset.pin_tasks(&[(27, 32027)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_write_cpuset_cpus_for_isolated_thread() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_write_cpuset_cpus_for_isolated_thread() {
         let mut cpuset = CpuSet::new("/test28/cgroups/cpuset", "prefix28").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix28.lock", 28);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test28/cgroups/cpuset" => Ok({}) },
@@ -1981,8 +2883,8 @@ mod test {
             { "/test28/cgroups/cpuset/prefix28/28" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test28/cgroups/cpuset" => Ok(true) }
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test28/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -2023,23 +2925,23 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("23-28".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_write: { "23,24,25,26,27" => Ok(14) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 28 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 28, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
         assert_error!(
             ErrorKind::Other,
             "Failed to isolate the host cpu thread `28` - std::fs::write(28)",
-            cpuset.pin_task(28, 32028)
+            cpuset.pin_tasks(&[(28, 32028)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_returns_error_if_unable_to_pin_task_to_isolated_thread() {
+    fn cpuset_pin_tasks_returns_error_if_unable_to_pin_task_to_isolated_thread() {
         let mut cpuset = CpuSet::new("/test29/cgroups/cpuset", "prefix29").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix29.lock", 29);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test29/cgroups/cpuset" => Ok({}) },
@@ -2048,8 +2950,8 @@ mod test {
             { "/test29/cgroups/cpuset/prefix29/29" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test29/cgroups/cpuset" => Ok(true) }
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test29/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -2093,23 +2995,23 @@ mod test {
         );
         expect!(TEST_EXPECTATIONS::std_fs_file_write: { "25,26,27,28" => Ok(11) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 29 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 29, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
         assert_error!(
             ErrorKind::Other,
             "Failed to pin the process id `32029` to the host cpu thread `29` - std::fs::write(29)",
-            cpuset.pin_task(29, 32029)
+            cpuset.pin_tasks(&[(29, 32029)]).pop().unwrap()
         );
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_pin_task_isolates_the_thread_and_pins_the_task_to_it() {
+    fn cpuset_pin_tasks_isolates_the_thread_and_pins_the_task_to_it() {
         let mut cpuset = CpuSet::new("/test30/cgroups/cpuset", "prefix30").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix30.lock", 30);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test30/cgroups/cpuset" => Ok({}) },
@@ -2118,8 +3020,8 @@ mod test {
             { "/test30/cgroups/cpuset/prefix30/30" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test30/cgroups/cpuset" => Ok(true) }
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test30/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -2161,33 +3063,113 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("25-30".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_write: { "25,26,27,28,29" => Ok(14) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 30 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 30, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
-        assert!(cpuset.pin_task(30, 3030).is_ok());
+        assert!(cpuset.pin_tasks(&[(30, 3030)]).pop().unwrap().is_ok());
 
         verify_expectations();
     }
 
     #[test]
-    fn cpuset_release_threads_returns_error_if_unable_to_open_pinned_thread_tasks_file() {
-        let mut cpuset = CpuSet::new("/test31/cgroups/cpuset", "prefix31").unwrap();
+    fn cpuset_pin_tasks_splits_all_host_cpus_from_the_pool_at_once() {
+        let mut cpuset = CpuSet::new("/test50/cgroups/cpuset", "prefix50").unwrap();
+
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix50.lock", 50);
 
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
-            { "/test31/cgroups/cpuset" => Ok({}) },
-            { "/test31/cgroups/cpuset/prefix31" => Ok({}) },
-            { "/test31/cgroups/cpuset/prefix31/pool" => Ok({}) },
-            { "/test31/cgroups/cpuset/prefix31/31" => Ok({}) },
+            { "/test50/cgroups/cpuset" => Ok({}) },
+            { "/test50/cgroups/cpuset/prefix50" => Ok({}) },
+            { "/test50/cgroups/cpuset/prefix50/pool" => Ok({}) },
+            { "/test50/cgroups/cpuset/prefix50/29" => Ok({}) },
+            { "/test50/cgroups/cpuset/prefix50/30" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test31/cgroups/cpuset" => Ok(true) }
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test50/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
-            { "/test31/cgroups/cpuset/prefix31/cpuset.cpu_exclusive", "1" => Ok({}) },
+            { "/test50/cgroups/cpuset/prefix50/cpuset.cpu_exclusive", "1" => Ok({}) },
+            { "/test50/cgroups/cpuset/prefix50/pool/cpuset.cpu_exclusive", "1" => Ok({}) },
+            { "/test50/cgroups/cpuset/prefix50/pool/tasks", "2050" => Ok({}) },
+            { "/test50/cgroups/cpuset/prefix50/29/cpuset.mems", "50" => Ok({}) },
+            { "/test50/cgroups/cpuset/prefix50/29/cpuset.cpu_exclusive", "1" => Ok({}) },
+            { "/test50/cgroups/cpuset/prefix50/29/cpuset.cpus", "29" => Ok({}) },
+            { "/test50/cgroups/cpuset/prefix50/30/cpuset.mems", "50" => Ok({}) },
+            { "/test50/cgroups/cpuset/prefix50/30/cpuset.cpu_exclusive", "1" => Ok({}) },
+            { "/test50/cgroups/cpuset/prefix50/30/cpuset.cpus", "30" => Ok({}) },
+            { "/test50/cgroups/cpuset/prefix50/29/tasks", "3029" => Ok({}) },
+            { "/test50/cgroups/cpuset/prefix50/30/tasks", "3030" => Ok({}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/test50/cgroups/cpuset/prefix50/cpuset.mems" => Ok("50".to_string()) },
+            { "/test50/cgroups/cpuset/prefix50/cpuset.cpus" => Ok("25-30".to_string()) },
+            { "/test50/cgroups/cpuset/prefix50/pool/cpuset.mems" => Ok("50".to_string()) },
+            { "/test50/cgroups/cpuset/prefix50/pool/cpuset.cpus" => Ok("25-30".to_string()) },
+            { "/test50/cgroups/cpuset/prefix50/pool/cpuset.cpus" => Ok("25-30".to_string()) },
+            { "/test50/cgroups/cpuset/tasks" => Ok("2050\n".to_string()) },
+            { "/proc/2050/status" => Ok("Cpus_allowed_list:\t25-30\n".to_string()) },
+            { "/test50/cgroups/cpuset/prefix50/cpuset.mems" => Ok("50".to_string()) },
+            { "/test50/cgroups/cpuset/prefix50/cpuset.mems" => Ok("50".to_string()) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_file_open:
+            { "/test50/cgroups/cpuset/prefix50/29/tasks" =>
+                Err(Error::new(ErrorKind::NotFound, "std::fs::File::open(29)")) },
+            { "/test50/cgroups/cpuset/prefix50/30/tasks" =>
+                Err(Error::new(ErrorKind::NotFound, "std::fs::File::open(30)")) },
+            { "/test50/cgroups/cpuset/prefix50/31/tasks" =>
+                Err(Error::new(ErrorKind::NotFound, "std::fs::File::open(31)")) },
+        );
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_new: { _ => std::fs::OpenOptions {} });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_read: { true => _ });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_write: { true => _ });
+        expect!(
+            TEST_EXPECTATIONS::std_fs_open_options_open:
+            { "/test50/cgroups/cpuset/prefix50/pool/cpuset.cpus" => Ok(std::fs::File {}) }
+        );
+        expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("25-30".to_string()) }, { _ => Ok(String::new()) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_write: { "25,26,27,28" => Ok(11) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
+
+        let mut results = cpuset.pin_tasks(&[(29, 3029), (30, 3030), (31, 3031)]);
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to isolate the host cpu thread `31` - The host CPU `31` is not in the CPU pool, \
+            it does not exist or is already pinned by another virtual machine. The pool contains CPUs: \
+            25,26,27,28,29,30.",
+            results.pop().unwrap()
+        );
+        assert!(results.pop().unwrap().is_ok());
+        assert!(results.pop().unwrap().is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_release_threads_returns_error_if_unable_to_open_pinned_thread_tasks_file() {
+        let mut cpuset = CpuSet::new("/test31/cgroups/cpuset", "prefix31").unwrap();
+
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix31.lock", 31);
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_create_dir_all:
+            { "/test31/cgroups/cpuset" => Ok({}) },
+            { "/test31/cgroups/cpuset/prefix31" => Ok({}) },
+            { "/test31/cgroups/cpuset/prefix31/pool" => Ok({}) },
+            { "/test31/cgroups/cpuset/prefix31/31" => Ok({}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test31/cgroups/cpuset", "rw,cpuset")])) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/test31/cgroups/cpuset/prefix31/cpuset.cpu_exclusive", "1" => Ok({}) },
             { "/test31/cgroups/cpuset/prefix31/pool/cpuset.cpu_exclusive", "1" => Ok({}) },
             { "/test31/cgroups/cpuset/prefix31/pool/tasks", "2031" => Ok({}) },
             { "/test31/cgroups/cpuset/prefix31/pool/tasks", "3031" => Ok({}) },
@@ -2225,22 +3207,19 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("30-31".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_write: { "30" => Ok(2) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 31 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 31, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
-        cpuset.pin_task(31, 3031).unwrap();
+        cpuset.pin_tasks(&[(31, 3031)]).pop().unwrap().unwrap();
 
         expect!(
             TEST_EXPECTATIONS::std_fs_file_open:
             { "/test31/cgroups/cpuset/prefix31/31/tasks" => error!("std::fs::File::open(31)") },
         );
 
-        assert_error!(
-            ErrorKind::Other,
-            "Failed to release some of the pinned threads.",
-            cpuset.release_threads()
-        );
+        let results = cpuset.release_threads();
+        assert_eq!(1, results.len());
+        assert_eq!(31, results[0].0);
+        assert_error!(ErrorKind::Other, "Failed to read the tasks of `/test31/cgroups/cpuset/prefix31/31`: std::fs::File::open(31)", &results[0].1);
 
         verify_expectations();
     }
@@ -2249,6 +3228,8 @@ mod test {
     fn cpuset_release_threads_returns_error_if_thread_still_busy_with_at_least_one_process() {
         let mut cpuset = CpuSet::new("/test32/cgroups/cpuset", "prefix32").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix32.lock", 32);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test32/cgroups/cpuset" => Ok({}) },
@@ -2257,8 +3238,8 @@ mod test {
             { "/test32/cgroups/cpuset/prefix32/32" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test32/cgroups/cpuset" => Ok(true) }
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test32/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -2300,11 +3281,9 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("30-32".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_write: { "30,31" => Ok(5) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 32 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 32, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
-        cpuset.pin_task(32, 2032).unwrap();
+        cpuset.pin_tasks(&[(32, 2032)]).pop().unwrap().unwrap();
 
         expect!(
             TEST_EXPECTATIONS::std_fs_file_open:
@@ -2312,11 +3291,15 @@ mod test {
         );
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("4032".to_string()) }, { _ => Ok(String::new()) });
 
+        let results = cpuset.release_threads();
+        assert_eq!(1, results.len());
+        assert_eq!(32, results[0].0);
         assert_error!(
             ErrorKind::Other,
-            "Failed to release some of the pinned threads.",
-            cpuset.release_threads()
+            "The task `4032` is still running on the CPU.",
+            &results[0].1
         );
+        assert_eq!(vec![32], cpuset.isolated_threads);
 
         verify_expectations();
     }
@@ -2324,6 +3307,8 @@ mod test {
     fn cpuset_release_threads_returns_error_if_unable_to_remove_thread_cpuset_cgroup_directory() {
         let mut cpuset = CpuSet::new("/test33/cgroups/cpuset", "prefix33").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix33.lock", 33);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test33/cgroups/cpuset" => Ok({}) },
@@ -2332,8 +3317,8 @@ mod test {
             { "/test33/cgroups/cpuset/prefix33/33" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test33/cgroups/cpuset" => Ok(true) }
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test33/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -2375,11 +3360,9 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("30-33".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_write: { "30,31,32" => Ok(8) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 33 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 33, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
-        cpuset.pin_task(33, 2033).unwrap();
+        cpuset.pin_tasks(&[(33, 2033)]).pop().unwrap().unwrap();
 
         expect!(
             TEST_EXPECTATIONS::std_fs_file_open:
@@ -2391,11 +3374,15 @@ mod test {
             { "/test33/cgroups/cpuset/prefix33/33" => error!("std::fs::remove_dir(33)") }
         );
 
+        let results = cpuset.release_threads();
+        assert_eq!(1, results.len());
+        assert_eq!(33, results[0].0);
         assert_error!(
             ErrorKind::Other,
-            "Failed to release some of the pinned threads.",
-            cpuset.release_threads()
+            "Failed to remove `/test33/cgroups/cpuset/prefix33/33`: std::fs::remove_dir(33)",
+            &results[0].1
         );
+        assert_eq!(vec![33], cpuset.isolated_threads);
 
         verify_expectations();
     }
@@ -2404,6 +3391,8 @@ mod test {
     fn cpuset_release_threads_returns_error_if_uanble_to_open_pool_cpuset_cpus_file() {
         let mut cpuset = CpuSet::new("/test34/cgroups/cpuset", "prefix34").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix34.lock", 34);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test34/cgroups/cpuset" => Ok({}) },
@@ -2412,8 +3401,8 @@ mod test {
             { "/test34/cgroups/cpuset/prefix34/34" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test34/cgroups/cpuset" => Ok(true) }
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test34/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -2455,12 +3444,11 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("30-34".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_write: { "30,31,32,33" => Ok(11) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 34 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 34, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
-        cpuset.pin_task(34, 2034).unwrap();
+        cpuset.pin_tasks(&[(34, 2034)]).pop().unwrap().unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix34.lock", 34);
         expect!(
             TEST_EXPECTATIONS::std_fs_file_open:
             { "/test34/cgroups/cpuset/prefix34/34/tasks" => Ok(std::fs::File {}) },
@@ -2475,11 +3463,15 @@ mod test {
             { "/test34/cgroups/cpuset/prefix34/pool/cpuset.cpus" => error!("File::open(34)") },
         );
 
+        let results = cpuset.release_threads();
+        assert_eq!(1, results.len());
+        assert_eq!(34, results[0].0);
         assert_error!(
             ErrorKind::Other,
-            "Failed to release some of the pinned threads.",
-            cpuset.release_threads()
+            "Failed to return the CPU to the pool: File::open(34)",
+            &results[0].1
         );
+        assert_eq!(Vec::<usize>::new(), cpuset.isolated_threads);
 
         verify_expectations();
     }
@@ -2488,6 +3480,8 @@ mod test {
     fn cpuset_release_threads_returns_error_if_uanble_to_lock_pool_cpuset_cpus_file() {
         let mut cpuset = CpuSet::new("/test35/cgroups/cpuset", "prefix35").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix35.lock", 35);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test35/cgroups/cpuset" => Ok({}) },
@@ -2496,8 +3490,8 @@ mod test {
             { "/test35/cgroups/cpuset/prefix35/35" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test35/cgroups/cpuset" => Ok(true) }
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test35/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -2539,11 +3533,9 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("30-35".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_write: { "30,31,32,33,34" => Ok(14) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 35 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 35, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
-        cpuset.pin_task(35, 2035).unwrap();
+        cpuset.pin_tasks(&[(35, 2035)]).pop().unwrap().unwrap();
 
         expect!(
             TEST_EXPECTATIONS::std_fs_file_open:
@@ -2551,12 +3543,9 @@ mod test {
         );
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_remove_dir: { "/test35/cgroups/cpuset/prefix35/35" => Ok({}) });
-        expect!(TEST_EXPECTATIONS::std_fs_open_options_new: { _ => std::fs::OpenOptions {} } );
-        expect!(TEST_EXPECTATIONS::std_fs_open_options_read: { true => _ } );
-        expect!(TEST_EXPECTATIONS::std_fs_open_options_write: { true => _ } );
         expect!(
-            TEST_EXPECTATIONS::std_fs_open_options_open:
-            { "/test35/cgroups/cpuset/prefix35/pool/cpuset.cpus" => Ok(std::fs::File {}) },
+            TEST_EXPECTATIONS::std_fs_file_create:
+            { "/run/qemu-launcher/cpuset-prefix35.lock" => Ok(std::fs::File {}) },
         );
         expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 3535 });
         expect!(
@@ -2564,11 +3553,10 @@ mod test {
             { 3535, FlockArg::LockExclusive => Err(::nix::Error::InvalidPath) },
         );
 
-        assert_error!(
-            ErrorKind::Other,
-            "Failed to release some of the pinned threads.",
-            cpuset.release_threads()
-        );
+        let results = cpuset.release_threads();
+        assert_eq!(1, results.len());
+        assert_eq!(35, results[0].0);
+        assert_error!(ErrorKind::Other, "Failed to return the CPU to the pool: Failed to lock `/run/qemu-launcher/cpuset-prefix35.lock`: Invalid path", &results[0].1);
 
         verify_expectations();
     }
@@ -2577,6 +3565,9 @@ mod test {
     fn cpuset_release_threads_returns_error_if_uanble_to_read_pool_cpuset_cpus_file() {
         let mut cpuset = CpuSet::new("/test36/cgroups/cpuset", "prefix36").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix36.lock", 36);
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix36.lock", 36);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test36/cgroups/cpuset" => Ok({}) },
@@ -2585,8 +3576,8 @@ mod test {
             { "/test36/cgroups/cpuset/prefix36/36" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test36/cgroups/cpuset" => Ok(true) }
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test36/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -2628,11 +3619,9 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("32-36".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_write: { "32,33,34,35" => Ok(11) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 36 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 36, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
-        cpuset.pin_task(36, 4036).unwrap();
+        cpuset.pin_tasks(&[(36, 4036)]).pop().unwrap().unwrap();
 
         expect!(
             TEST_EXPECTATIONS::std_fs_file_open:
@@ -2650,13 +3639,14 @@ mod test {
         expect!(
             TEST_EXPECTATIONS::std_fs_file_read: { _ => error!("std::fs::File::read(36)") },
         );
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 3636 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 3636, FlockArg::LockExclusive => Ok({}) });
 
+        let results = cpuset.release_threads();
+        assert_eq!(1, results.len());
+        assert_eq!(36, results[0].0);
         assert_error!(
             ErrorKind::Other,
-            "Failed to release some of the pinned threads.",
-            cpuset.release_threads()
+            "Failed to return the CPU to the pool: std::fs::File::read(36)",
+            &results[0].1
         );
 
         verify_expectations();
@@ -2666,6 +3656,9 @@ mod test {
     fn cpuset_release_threads_returns_error_if_uanble_to_seek_pool_cpuset_cpus_file() {
         let mut cpuset = CpuSet::new("/test37/cgroups/cpuset", "prefix37").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix37.lock", 37);
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix37.lock", 37);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test37/cgroups/cpuset" => Ok({}) },
@@ -2674,8 +3667,8 @@ mod test {
             { "/test37/cgroups/cpuset/prefix37/37" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test37/cgroups/cpuset" => Ok(true) }
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test37/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -2717,11 +3710,9 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("32-37".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_write: { "32,33,34,35,36" => Ok(14) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 37 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 37, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
-        cpuset.pin_task(37, 4037).unwrap();
+        cpuset.pin_tasks(&[(37, 4037)]).pop().unwrap().unwrap();
 
         expect!(
             TEST_EXPECTATIONS::std_fs_file_open:
@@ -2738,13 +3729,14 @@ mod test {
         );
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("32-36".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => error!("std::fs::File::seek(37)") });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 3737 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 3737, FlockArg::LockExclusive => Ok({}) });
 
+        let results = cpuset.release_threads();
+        assert_eq!(1, results.len());
+        assert_eq!(37, results[0].0);
         assert_error!(
             ErrorKind::Other,
-            "Failed to release some of the pinned threads.",
-            cpuset.release_threads()
+            "Failed to return the CPU to the pool: std::fs::File::seek(37)",
+            &results[0].1
         );
 
         verify_expectations();
@@ -2754,6 +3746,9 @@ mod test {
     fn cpuset_release_threads_returns_error_if_uanble_to_set_len_on_pool_cpuset_cpus_file() {
         let mut cpuset = CpuSet::new("/test42/cgroups/cpuset", "prefix42").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix42.lock", 42);
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix42.lock", 42);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test42/cgroups/cpuset" => Ok({}) },
@@ -2762,8 +3757,8 @@ mod test {
             { "/test42/cgroups/cpuset/prefix42/42" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test42/cgroups/cpuset" => Ok(true) }
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test42/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -2805,11 +3800,9 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("39-42".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_write: { "39,40,41" => Ok(8) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 42 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 42, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
-        cpuset.pin_task(42, 4042).unwrap();
+        cpuset.pin_tasks(&[(42, 4042)]).pop().unwrap().unwrap();
 
         expect!(
             TEST_EXPECTATIONS::std_fs_file_open:
@@ -2826,14 +3819,15 @@ mod test {
         );
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("39-41".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 4242 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 4242, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => error!("std::fs::File::set_len(42)") });
 
+        let results = cpuset.release_threads();
+        assert_eq!(1, results.len());
+        assert_eq!(42, results[0].0);
         assert_error!(
             ErrorKind::Other,
-            "Failed to release some of the pinned threads.",
-            cpuset.release_threads()
+            "Failed to return the CPU to the pool: std::fs::File::set_len(42)",
+            &results[0].1
         );
 
         verify_expectations();
@@ -2843,6 +3837,9 @@ mod test {
     fn cpuset_release_threads_returns_error_if_uanble_to_write_pool_cpuset_cpus_file() {
         let mut cpuset = CpuSet::new("/test38/cgroups/cpuset", "prefix38").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix38.lock", 38);
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix38.lock", 38);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test38/cgroups/cpuset" => Ok({}) },
@@ -2851,8 +3848,8 @@ mod test {
             { "/test38/cgroups/cpuset/prefix38/38" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test38/cgroups/cpuset" => Ok(true) }
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test38/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -2894,11 +3891,9 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("32-38".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_write: { "32,33,34,35,36,37" => Ok(17) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 38 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 38, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
-        cpuset.pin_task(38, 1038).unwrap();
+        cpuset.pin_tasks(&[(38, 1038)]).pop().unwrap().unwrap();
 
         expect!(
             TEST_EXPECTATIONS::std_fs_file_open:
@@ -2919,14 +3914,15 @@ mod test {
             { "32,33,34,35,36,37,38" => Err(Error::new(ErrorKind::Other, "std::fs::File::write(38)")) }
         );
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 3838 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 3838, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
+        let results = cpuset.release_threads();
+        assert_eq!(1, results.len());
+        assert_eq!(38, results[0].0);
         assert_error!(
             ErrorKind::Other,
-            "Failed to release some of the pinned threads.",
-            cpuset.release_threads()
+            "Failed to return the CPU to the pool: std::fs::File::write(38)",
+            &results[0].1
         );
 
         verify_expectations();
@@ -2936,6 +3932,9 @@ mod test {
     fn cpuset_release_threads_returns_all_pinned_threads_back_to_pool() {
         let mut cpuset = CpuSet::new("/test39/cgroups/cpuset", "prefix39").unwrap();
 
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix39.lock", 39);
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix39.lock", 39);
+
         expect!(
             TEST_EXPECTATIONS::std_fs_create_dir_all:
             { "/test39/cgroups/cpuset" => Ok({}) },
@@ -2944,8 +3943,8 @@ mod test {
             { "/test39/cgroups/cpuset/prefix39/39" => Ok({}) },
         );
         expect!(
-            TEST_EXPECTATIONS::proc_mounts_mount_iter_source_mounted_at:
-            { "cgroup", "/test39/cgroups/cpuset" => Ok(true) }
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test39/cgroups/cpuset", "rw,cpuset")])) },
         );
         expect!(
             TEST_EXPECTATIONS::std_fs_write:
@@ -2987,11 +3986,9 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("35-39".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_write: { "35,36,37,38" => Ok(11) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 39 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 39, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
-        cpuset.pin_task(39, 1039).unwrap();
+        cpuset.pin_tasks(&[(39, 1039)]).pop().unwrap().unwrap();
 
         expect!(
             TEST_EXPECTATIONS::std_fs_file_open:
@@ -3009,11 +4006,522 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("35-38".to_string()) }, { _ => Ok(String::new()) });
         expect!(TEST_EXPECTATIONS::std_fs_file_write: { "35,36,37,38,39" => Ok(14) });
         expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
-        expect!(TEST_EXPECTATIONS::std_fs_file_as_raw_fd: { _ => 3939 });
-        expect!(TEST_EXPECTATIONS::nix_fcntl_flock: { 3939, FlockArg::LockExclusive => Ok({}) });
         expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
 
-        assert!(cpuset.release_threads().is_ok());
+        assert!(cpuset
+            .release_threads()
+            .iter()
+            .all(|(_, result)| result.is_ok()));
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_collect_garbage_removes_empty_untracked_thread_directories() {
+        let cpuset = CpuSet::new("/test60/cgroups/cpuset", "prefix60").unwrap();
+
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix60.lock", 60);
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_dir:
+            { "/test60/cgroups/cpuset/prefix60" => Ok(vec![
+                ("pool", true),
+                ("cpuset.cpus", false),
+                ("my-vm-2", true),
+                ("old-vm-3", true),
+                ("busy-4", true),
+            ]) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/test60/cgroups/cpuset/prefix60/old-vm-3/tasks" => Ok(String::new()) },
+            { "/test60/cgroups/cpuset/prefix60/old-vm-3/cpuset.cpus" => Ok("3\n".to_string()) },
+            { "/test60/cgroups/cpuset/prefix60/busy-4/tasks" => Ok("1060\n".to_string()) },
+        );
+        expect!(TEST_EXPECTATIONS::std_fs_remove_dir: { "/test60/cgroups/cpuset/prefix60/old-vm-3" => Ok({}) });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_new: { _ => std::fs::OpenOptions {} });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_read: { true => _ });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_write: { true => _ });
+        expect!(
+            TEST_EXPECTATIONS::std_fs_open_options_open:
+            { "/test60/cgroups/cpuset/prefix60/pool/cpuset.cpus" => Ok(std::fs::File {}) },
+        );
+        expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("0-1".to_string()) }, { _ => Ok(String::new()) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_write: { "0,1,3" => Ok(5) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
+
+        assert_eq!(
+            vec![String::from("old-vm-3")],
+            cpuset
+                .collect_garbage(|| vec![String::from("my-vm-2")])
+                .unwrap()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_repair_pool_returns_cpus_back_online_to_the_pool_and_isolated_threads() {
+        let mut cpuset = CpuSet::new("/test62/cgroups/cpuset", "prefix62")
+            .unwrap()
+            .with_thread_name("my-vm-{cpu}");
+        cpuset.isolated_threads = vec![2, 3];
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/test62/cgroups/cpuset/prefix62/cpuset.cpus" => Ok("0-1,3-4\n".to_string()) },
+            { "/test62/cgroups/cpuset/prefix62/my-vm-2/cpuset.cpus" => Ok("\n".to_string()) },
+            { "/test62/cgroups/cpuset/prefix62/my-vm-3/cpuset.cpus" => Ok("3\n".to_string()) },
+        );
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix62.lock", 62);
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_new: { _ => std::fs::OpenOptions {} });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_read: { true => _ });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_write: { true => _ });
+        expect!(
+            TEST_EXPECTATIONS::std_fs_open_options_open:
+            { "/test62/cgroups/cpuset/prefix62/pool/cpuset.cpus" => Ok(std::fs::File {}) },
+        );
+        expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("0".to_string()) }, { _ => Ok(String::new()) });
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/test62/cgroups/cpuset/prefix62/cpuset.cpus", "0,1,2,3,4,5" => Ok({}) },
+            { "/test62/cgroups/cpuset/prefix62/my-vm-2/cpuset.cpus", "2" => Ok({}) },
+        );
+        expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_write: { "0,5" => Ok(3) });
+
+        // The CPU 1 is pinned by another machine, the CPU 4 is offline
+        assert_eq!(
+            vec![5],
+            cpuset.repair_pool(&[0, 1, 2, 3, 5], &[1, 2, 3]).unwrap()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_repair_pool_does_nothing_if_prefix_directory_does_not_exist() {
+        let cpuset = CpuSet::new("/test63/cgroups/cpuset", "prefix63").unwrap();
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/test63/cgroups/cpuset/prefix63/cpuset.cpus" =>
+                Err(Error::new(ErrorKind::NotFound, "std::fs::read_to_string()")) },
+        );
+
+        assert_eq!(0, cpuset.repair_pool(&[0, 1], &[]).unwrap().len());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_collect_garbage_does_nothing_if_prefix_directory_does_not_exist() {
+        let cpuset = CpuSet::new("/test61/cgroups/cpuset", "prefix61").unwrap();
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_dir:
+            { "/test61/cgroups/cpuset/prefix61" => Err(Error::new(ErrorKind::NotFound, "std::fs::read_dir()")) },
+        );
+
+        assert_eq!(0, cpuset.collect_garbage(|| vec![]).unwrap().len());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_release_threads_uses_thread_name_for_pinned_thread_directories() {
+        let mut cpuset = CpuSet::new("/test40/cgroups/cpuset", "prefix40")
+            .unwrap()
+            .with_thread_name("my-vm-{cpu}");
+
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix40.lock", 40);
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix40.lock", 40);
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_create_dir_all:
+            { "/test40/cgroups/cpuset" => Ok({}) },
+            { "/test40/cgroups/cpuset/prefix40" => Ok({}) },
+            { "/test40/cgroups/cpuset/prefix40/pool" => Ok({}) },
+            { "/test40/cgroups/cpuset/prefix40/my-vm-40" => Ok({}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mountinfo" => Ok(mountinfo(&[("/test40/cgroups/cpuset", "rw,cpuset")])) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/test40/cgroups/cpuset/prefix40/cpuset.cpu_exclusive", "1" => Ok({}) },
+            { "/test40/cgroups/cpuset/prefix40/pool/cpuset.cpu_exclusive", "1" => Ok({}) },
+            { "/test40/cgroups/cpuset/prefix40/pool/tasks", "2040" => Ok({}) },
+            { "/test40/cgroups/cpuset/prefix40/pool/tasks", "3040" => Ok({}) },
+            { "/test40/cgroups/cpuset/prefix40/pool/tasks", "4040" => Ok({}) },
+            { "/test40/cgroups/cpuset/prefix40/my-vm-40/cpuset.mems", "40" => Ok({}) },
+            { "/test40/cgroups/cpuset/prefix40/my-vm-40/cpuset.cpu_exclusive", "1" => Ok({}) },
+            { "/test40/cgroups/cpuset/prefix40/my-vm-40/cpuset.cpus", "40" => Ok({}) },
+            { "/test40/cgroups/cpuset/prefix40/my-vm-40/tasks", "1040" => Ok({}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/test40/cgroups/cpuset/prefix40/cpuset.mems" => Ok("40".to_string()) },
+            { "/test40/cgroups/cpuset/prefix40/cpuset.cpus" => Ok("36-40".to_string()) },
+            { "/test40/cgroups/cpuset/prefix40/pool/cpuset.mems" => Ok("40".to_string()) },
+            { "/test40/cgroups/cpuset/prefix40/pool/cpuset.cpus" => Ok("36-40".to_string()) },
+            { "/test40/cgroups/cpuset/prefix40/pool/cpuset.cpus" => Ok("36-40".to_string()) },
+            { "/test40/cgroups/cpuset/tasks" => Ok("2040\n3040\n4040\n".to_string()) },
+            { "/proc/2040/status" => Ok("Cpus_allowed_list:	36-40\n".to_string()) },
+            { "/proc/3040/status" => Ok("Cpus_allowed_list:	36-40\n".to_string()) },
+            { "/proc/4040/status" => Ok("Cpus_allowed_list:	36-40\n".to_string()) },
+            { "/test40/cgroups/cpuset/prefix40/cpuset.mems" => Ok("40".to_string()) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_file_open:
+            { "/test40/cgroups/cpuset/prefix40/my-vm-40/tasks" =>
+                Err(Error::new(ErrorKind::NotFound, "std::fs::File::open(40)")) },
+        );
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_new: { _ => std::fs::OpenOptions {} });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_read: { true => _ });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_write: { true => _ });
+        expect!(
+            TEST_EXPECTATIONS::std_fs_open_options_open:
+            { "/test40/cgroups/cpuset/prefix40/pool/cpuset.cpus" => Ok(std::fs::File {}) }
+        );
+        expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("36-40".to_string()) }, { _ => Ok(String::new()) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_write: { "36,37,38,39" => Ok(11) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
+
+        cpuset.pin_tasks(&[(40, 1040)]).pop().unwrap().unwrap();
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_file_open:
+            { "/test40/cgroups/cpuset/prefix40/my-vm-40/tasks" => Ok(std::fs::File {}) },
+        );
+        expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok(String::new()) });
+        expect!(TEST_EXPECTATIONS::std_fs_remove_dir: { "/test40/cgroups/cpuset/prefix40/my-vm-40" => Ok({}) });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_new: { _ => std::fs::OpenOptions {} });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_read: { true => _ });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_write: { true => _ });
+        expect!(
+            TEST_EXPECTATIONS::std_fs_open_options_open:
+            { "/test40/cgroups/cpuset/prefix40/pool/cpuset.cpus" => Ok(std::fs::File {} ) },
+        );
+        expect!(TEST_EXPECTATIONS::std_fs_file_read: { _ => Ok("36-39".to_string()) }, { _ => Ok(String::new()) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_write: { "36,37,38,39,40" => Ok(14) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
+
+        assert!(cpuset
+            .release_threads()
+            .iter()
+            .all(|(_, result)| result.is_ok()));
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_unmount_does_nothing_if_cpuset_was_mounted_before() {
+        let mut cpuset = CpuSet::new("/test41/cgroups/cpuset", "prefix41").unwrap();
+
+        assert!(cpuset.unmount().is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_unmount_does_nothing_if_other_threads_are_still_isolated() {
+        let mut cpuset = CpuSet::new("/test42/cgroups/cpuset", "prefix42").unwrap();
+        cpuset.mounted = true;
+
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix42.lock", 42);
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_dir:
+            { "/test42/cgroups/cpuset" => Ok(vec![("prefix42", true), ("tasks", false)]) },
+        );
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/test42/cgroups/cpuset/prefix42/cpuset.cpus" => Ok("0-42\n".to_string()) },
+            { "/test42/cgroups/cpuset/prefix42/pool/cpuset.cpus" => Ok("0-41\n".to_string()) },
+        );
+
+        assert!(cpuset.unmount().is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_unmount_leaves_hierarchy_with_foreign_cgroups_alone() {
+        let mut cpuset = CpuSet::new("/test66/cgroups/cpuset", "prefix66").unwrap();
+        cpuset.mounted = true;
+
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix66.lock", 66);
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_dir:
+            { "/test66/cgroups/cpuset" => Ok(vec![("prefix66", true), ("tasks", false), ("docker", true)]) },
+        );
+
+        assert!(cpuset.unmount().is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_unmount_returns_error_if_unable_to_list_hierarchy() {
+        let mut cpuset = CpuSet::new("/test67/cgroups/cpuset", "prefix67").unwrap();
+        cpuset.mounted = true;
+
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix67.lock", 67);
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_dir:
+            { "/test67/cgroups/cpuset" => error!("std::fs::read_dir(67)") },
+        );
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to list `/test67/cgroups/cpuset`: std::fs::read_dir(67)",
+            cpuset.unmount()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_unmount_returns_tasks_to_root_and_unmounts_the_hierarchy() {
+        let mut cpuset = CpuSet::new("/test43/cgroups/cpuset", "prefix43").unwrap();
+        cpuset.mounted = true;
+
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix43.lock", 43);
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_dir:
+            { "/test43/cgroups/cpuset" => Ok(vec![("prefix43", true), ("tasks", false)]) },
+        );
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/test43/cgroups/cpuset/prefix43/cpuset.cpus" => Ok("0-43\n".to_string()) },
+            { "/test43/cgroups/cpuset/prefix43/pool/cpuset.cpus" => Ok("0-43\n".to_string()) },
+            { "/test43/cgroups/cpuset/prefix43/pool/tasks" => Ok("1043\n2043\n".to_string()) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/test43/cgroups/cpuset/tasks", "1043" => Ok({}) },
+            { "/test43/cgroups/cpuset/tasks", "2043" => error!("std::fs::write(43)") },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_remove_dir:
+            { "/test43/cgroups/cpuset/prefix43/pool" => Ok({}) },
+            { "/test43/cgroups/cpuset/prefix43" => Ok({}) },
+        );
+        expect!(TEST_EXPECTATIONS::nix_mount_umount: { "/test43/cgroups/cpuset" => Ok({}) });
+
+        assert!(cpuset.unmount().is_ok());
+        assert!(cpuset.unmount().is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_unmount_returns_error_if_unmount_fails() {
+        let mut cpuset = CpuSet::new("/test44/cgroups/cpuset", "prefix44").unwrap();
+        cpuset.mounted = true;
+
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix44.lock", 44);
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_dir:
+            { "/test44/cgroups/cpuset" => Ok(vec![("prefix44", true), ("tasks", false)]) },
+        );
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/test44/cgroups/cpuset/prefix44/cpuset.cpus" => Ok("0-44".to_string()) },
+            { "/test44/cgroups/cpuset/prefix44/pool/cpuset.cpus" => Ok("0-44".to_string()) },
+            { "/test44/cgroups/cpuset/prefix44/pool/tasks" => Ok(String::new()) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_remove_dir:
+            { "/test44/cgroups/cpuset/prefix44/pool" => Ok({}) },
+            { "/test44/cgroups/cpuset/prefix44" => Ok({}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::nix_mount_umount: { "/test44/cgroups/cpuset" => Err(::nix::Error::InvalidPath) }
+        );
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to unmount cpuset from `/test44/cgroups/cpuset`: Invalid path",
+            cpuset.unmount()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_release_threads_retries_while_thread_is_still_busy() {
+        let clock = FakeClock::new();
+        let mut cpuset = CpuSet::new("/test45/cgroups/cpuset", "prefix45")
+            .unwrap()
+            .with_release_retries(2, Duration::from_millis(100))
+            .with_clock(clock.clone());
+
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix45.lock", 45);
+        cpuset.isolated_threads = vec![45];
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_file_open:
+            { "/test45/cgroups/cpuset/prefix45/45/tasks" => Ok(std::fs::File {}) },
+            { "/test45/cgroups/cpuset/prefix45/45/tasks" => Ok(std::fs::File {}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_file_read:
+            { _ => Ok("4045".to_string()) },
+            { _ => Ok(String::new()) },
+            { _ => Ok(String::new()) },
+            { _ => Ok("44".to_string()) },
+            { _ => Ok(String::new()) },
+        );
+        expect!(TEST_EXPECTATIONS::std_fs_remove_dir: { "/test45/cgroups/cpuset/prefix45/45" => Ok({}) });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_new: { _ => std::fs::OpenOptions {} });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_read: { true => _ });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_write: { true => _ });
+        expect!(
+            TEST_EXPECTATIONS::std_fs_open_options_open:
+            { "/test45/cgroups/cpuset/prefix45/pool/cpuset.cpus" => Ok(std::fs::File {}) },
+        );
+        expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_write: { "44,45" => Ok(5) });
+
+        assert!(cpuset.release_threads()[0].1.is_ok());
+        assert_eq!(vec![Duration::from_millis(100)], clock.get_sleeps());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_release_threads_returns_error_if_thread_is_busy_after_all_retries() {
+        let mut cpuset = CpuSet::new("/test46/cgroups/cpuset", "prefix46")
+            .unwrap()
+            .with_release_retries(1, Duration::from_millis(0));
+        cpuset.isolated_threads = vec![46];
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_file_open:
+            { "/test46/cgroups/cpuset/prefix46/46/tasks" => Ok(std::fs::File {}) },
+            { "/test46/cgroups/cpuset/prefix46/46/tasks" => Ok(std::fs::File {}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_file_read:
+            { _ => Ok("4046".to_string()) },
+            { _ => Ok(String::new()) },
+            { _ => Ok("4046".to_string()) },
+            { _ => Ok(String::new()) },
+        );
+
+        let results = cpuset.release_threads();
+        assert_eq!(1, results.len());
+        assert_eq!(46, results[0].0);
+        assert_error!(
+            ErrorKind::Other,
+            "The task `4046` is still running on the CPU.",
+            &results[0].1
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_evict_moves_remaining_tasks_to_the_pool_before_releasing_threads() {
+        let mut cpuset = CpuSet::new("/test62/cgroups/cpuset", "prefix62").unwrap();
+
+        expect_pool_lock("/run/qemu-launcher/cpuset-prefix62.lock", 62);
+        cpuset.isolated_threads = vec![62];
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/test62/cgroups/cpuset/prefix62/62/tasks" => Ok("1062\n2062\n".to_string()) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/test62/cgroups/cpuset/prefix62/pool/tasks", "1062" => Ok({}) },
+            { "/test62/cgroups/cpuset/prefix62/pool/tasks", "2062" => error!("std::fs::write(62)") },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_file_open:
+            { "/test62/cgroups/cpuset/prefix62/62/tasks" => Ok(std::fs::File {}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_file_read:
+            { _ => Ok(String::new()) },
+            { _ => Ok("61".to_string()) },
+            { _ => Ok(String::new()) },
+        );
+        expect!(TEST_EXPECTATIONS::std_fs_remove_dir: { "/test62/cgroups/cpuset/prefix62/62" => Ok({}) });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_new: { _ => std::fs::OpenOptions {} });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_read: { true => _ });
+        expect!(TEST_EXPECTATIONS::std_fs_open_options_write: { true => _ });
+        expect!(
+            TEST_EXPECTATIONS::std_fs_open_options_open:
+            { "/test62/cgroups/cpuset/prefix62/pool/cpuset.cpus" => Ok(std::fs::File {}) },
+        );
+        expect!(TEST_EXPECTATIONS::std_fs_file_seek: { SeekFrom::Start(0) => Ok(0) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_set_len: { 0 => Ok({}) });
+        expect!(TEST_EXPECTATIONS::std_fs_file_write: { "61,62" => Ok(5) });
+
+        assert!(cpuset.evict().is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_get_thread_tasks_lists_tasks_of_the_pinned_thread() {
+        let cpuset = CpuSet::new("/test48/cgroups/cpuset", "prefix48")
+            .unwrap()
+            .with_thread_name("vm-{cpu}");
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/test48/cgroups/cpuset/prefix48/vm-4/tasks" => Ok("1048\n2048\n".to_string()) },
+            { "/test48/cgroups/cpuset/prefix48/vm-5/tasks" => error!("std::fs::read_to_string(48)") },
+        );
+
+        assert_eq!(
+            vec!["1048".to_string(), "2048".to_string()],
+            cpuset.get_thread_tasks(4).unwrap()
+        );
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to read `/test48/cgroups/cpuset/prefix48/vm-5/tasks`: std::fs::read_to_string(48)",
+            cpuset.get_thread_tasks(5)
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn cpuset_migrate_tasks_moves_all_movable_tasks_and_reports_failures() {
+        let mut cpuset = CpuSet::new("/test47/cgroups/cpuset", "prefix47")
+            .unwrap()
+            .with_migrate_all(true);
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/test47/cgroups/cpuset/prefix47/pool/cpuset.cpus" => Ok("0-47".to_string()) },
+            { "/test47/cgroups/cpuset/tasks" => Ok("1047\n2047\n3047\n4047\n".to_string()) },
+            { "/proc/1047/stat" => Ok("1047 (my (app)) S 1 1047 1047 0 -1 4194560 0".to_string()) },
+            { "/proc/2047/stat" => Ok("2047 (kworker/0:1) I 2 0 0 0 -1 69238880 0".to_string()) },
+            { "/proc/3047/stat" => error!("std::fs::read_to_string(47)") },
+            { "/proc/4047/stat" => Ok("4047 (app) S 1 4047 4047 0 -1 4194560 0".to_string()) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/test47/cgroups/cpuset/prefix47/pool/tasks", "1047" => Ok({}) },
+            { "/test47/cgroups/cpuset/prefix47/pool/tasks", "4047" => error!("std::fs::write(47)") },
+        );
+
+        assert!(cpuset.migrate_tasks().is_ok());
+        assert_eq!(&vec!["4047".to_string()], cpuset.get_unmovable_tasks());
 
         verify_expectations();
     }
@@ -0,0 +1,142 @@
+// A qemu command line option which was deprecated and later removed, in major qemu versions
+struct DeprecatedOption {
+    name: &'static str,
+    deprecated: u32,
+    removed: u32,
+    replacement: &'static str,
+}
+
+const DEPRECATED_OPTIONS: &[DeprecatedOption] = &[
+    DeprecatedOption {
+        name: "enable-fips",
+        deprecated: 6,
+        removed: 7,
+        replacement: "the FIPS mode of the host kernel",
+    },
+    DeprecatedOption {
+        name: "no-kvm",
+        deprecated: 1,
+        removed: 5,
+        replacement: "`-machine accel=tcg`",
+    },
+    DeprecatedOption {
+        name: "realtime",
+        deprecated: 4,
+        removed: 6,
+        replacement: "`-overcommit mem-lock=on|off`",
+    },
+    DeprecatedOption {
+        name: "show-cursor",
+        deprecated: 5,
+        removed: 6,
+        replacement: "`-display sdl,show-cursor=on`",
+    },
+    DeprecatedOption {
+        name: "soundhw",
+        deprecated: 5,
+        removed: 7,
+        replacement: "`-audio` or `-device` with an `-audiodev`",
+    },
+    DeprecatedOption {
+        name: "tb-size",
+        deprecated: 5,
+        removed: 6,
+        replacement: "`-accel tcg,tb-size=...`",
+    },
+    DeprecatedOption {
+        name: "watchdog",
+        deprecated: 7,
+        removed: 9,
+        replacement: "`-device` with the watchdog model",
+    },
+];
+
+pub fn has_deprecated_options(options: &[&str]) -> bool {
+    options.iter().any(|option| find(option).is_some())
+}
+
+// Explains every option which the given qemu major version no longer supports or warns about
+pub fn lint(options: &[&str], major: u32) -> Vec<String> {
+    let mut warnings = vec![];
+
+    for option in options {
+        let deprecation = match find(option) {
+            Some(deprecation) if major >= deprecation.deprecated => deprecation,
+            _ => continue,
+        };
+
+        let warning = if major >= deprecation.removed {
+            format!(
+                "The `-{}` qemu option was removed in qemu {}, qemu {} will refuse to start, use {} instead.",
+                deprecation.name, deprecation.removed, major, deprecation.replacement
+            )
+        } else {
+            format!(
+                "The `-{}` qemu option is deprecated since qemu {} and is removed in qemu {}, use {} instead.",
+                deprecation.name, deprecation.deprecated, deprecation.removed, deprecation.replacement
+            )
+        };
+
+        if !warnings.contains(&warning) {
+            warnings.push(warning);
+        }
+    }
+
+    warnings
+}
+
+// Parses the major version out of the `--version` output, e.g. `QEMU emulator version 8.2.2 (...)`
+pub fn parse_major_version(output: &str) -> Option<u32> {
+    let line = output.lines().find(|line| line.contains(" version "))?;
+    let version = line.split(" version ").nth(1)?;
+
+    version
+        .split(|c: char| !c.is_ascii_digit())
+        .next()
+        .and_then(|major| major.parse::<u32>().ok())
+}
+
+fn find(option: &str) -> Option<&'static DeprecatedOption> {
+    DEPRECATED_OPTIONS
+        .iter()
+        .find(|deprecation| deprecation.name == option)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{has_deprecated_options, lint, parse_major_version};
+
+    #[test]
+    fn lint_warns_about_deprecated_and_removed_options_of_the_qemu_version() {
+        let options = ["enable-kvm", "soundhw", "realtime", "watchdog", "realtime"];
+
+        assert!(has_deprecated_options(&options));
+        assert!(!has_deprecated_options(&["enable-kvm", "m"]));
+        assert_eq!(0, lint(&options, 3).len());
+        assert_eq!(
+            vec![
+                "The `-soundhw` qemu option is deprecated since qemu 5 and is removed in qemu 7, \
+                use `-audio` or `-device` with an `-audiodev` instead.",
+                "The `-realtime` qemu option was removed in qemu 6, qemu 6 will refuse to start, \
+                use `-overcommit mem-lock=on|off` instead.",
+            ],
+            lint(&options, 6)
+        );
+    }
+
+    #[test]
+    fn parse_major_version_reads_the_version_output() {
+        assert_eq!(
+            Some(8),
+            parse_major_version(
+                "QEMU emulator version 8.2.2 (Debian 1:8.2.2+ds-0ubuntu1)\n\
+                Copyright (c) 2003-2023 Fabrice Bellard and the QEMU Project developers\n"
+            )
+        );
+        assert_eq!(
+            Some(10),
+            parse_major_version("QEMU emulator version 10.0.0\n")
+        );
+        assert_eq!(None, parse_major_version("qemu-kvm: unknown option\n"));
+    }
+}
@@ -0,0 +1,405 @@
+use crate::config::Config;
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConflictKind {
+    HostCpu,
+    MacAddress,
+    DiskImage,
+    SocketPath,
+}
+
+impl ConflictKind {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            ConflictKind::HostCpu => "host CPU",
+            ConflictKind::MacAddress => "MAC address",
+            ConflictKind::DiskImage => "disk image",
+            ConflictKind::SocketPath => "socket path",
+        }
+    }
+
+    fn code(&self) -> char {
+        match self {
+            ConflictKind::HostCpu => 'C',
+            ConflictKind::MacAddress => 'M',
+            ConflictKind::DiskImage => 'D',
+            ConflictKind::SocketPath => 'S',
+        }
+    }
+}
+
+// A host resource claimed by two machines, which can not both be running without stepping on each other
+pub struct Conflict {
+    kind: ConflictKind,
+    resource: String,
+    machines: (String, String),
+}
+
+impl Conflict {
+    pub fn get_kind(&self) -> ConflictKind {
+        self.kind
+    }
+
+    pub fn get_machines(&self) -> (&str, &str) {
+        (&self.machines.0, &self.machines.1)
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "The {} `{}` is claimed by both `{}` and `{}`.",
+            self.kind.describe(),
+            self.resource,
+            self.machines.0,
+            self.machines.1
+        )
+    }
+}
+
+// A resource used by a machine, along with whether the machine needs it exclusively
+type Claim = (ConflictKind, String, bool);
+
+pub fn find_conflicts(configs: &[Config]) -> Vec<Conflict> {
+    let claims: Vec<BTreeSet<Claim>> = configs.iter().map(claims).collect();
+    let mut conflicts = vec![];
+
+    for (first, first_claims) in claims.iter().enumerate() {
+        for (second, second_claims) in claims.iter().enumerate().skip(first + 1) {
+            // A disk image opened read-only by both machines is shared safely
+            let shared: BTreeSet<(ConflictKind, &str)> = first_claims
+                .iter()
+                .flat_map(|(kind, resource, exclusive)| {
+                    second_claims
+                        .iter()
+                        .filter(move |(other_kind, other_resource, other_exclusive)| {
+                            kind == other_kind
+                                && resource == other_resource
+                                && (*exclusive || *other_exclusive)
+                        })
+                        .map(move |_| (*kind, resource.as_str()))
+                })
+                .collect();
+
+            for (kind, resource) in shared {
+                conflicts.push(Conflict {
+                    kind,
+                    resource: resource.to_owned(),
+                    machines: (
+                        configs[first].get_machine_name().to_owned(),
+                        configs[second].get_machine_name().to_owned(),
+                    ),
+                });
+            }
+        }
+    }
+
+    conflicts.sort_by(|a, b| (a.kind, &a.resource).cmp(&(b.kind, &b.resource)));
+
+    conflicts
+}
+
+// Renders a table with a row and a column per machine, every cell lists the codes of the kinds of
+// resources the two machines conflict on
+pub fn conflict_matrix(machines: &[&str], conflicts: &[Conflict]) -> String {
+    let mut cells: HashMap<(&str, &str), BTreeSet<char>> = HashMap::new();
+    for conflict in conflicts {
+        let (first, second) = conflict.get_machines();
+        for pair in &[(first, second), (second, first)] {
+            cells
+                .entry(*pair)
+                .or_default()
+                .insert(conflict.get_kind().code());
+        }
+    }
+
+    let width = machines
+        .iter()
+        .map(|machine| machine.len())
+        .chain(Some(4))
+        .max()
+        .unwrap_or(4);
+
+    let mut matrix = format!("{:width$}", "", width = width);
+    for machine in machines {
+        matrix.push_str(&format!("  {:width$}", machine, width = width));
+    }
+    matrix = matrix.trim_end().to_owned();
+    matrix.push('\n');
+
+    for row in machines {
+        let mut line = format!("{:width$}", row, width = width);
+        for column in machines {
+            let cell = match cells.get(&(*row, *column)) {
+                _ if row == column => String::from("\\"),
+                Some(codes) => codes.iter().collect(),
+                None => String::from("-"),
+            };
+            line.push_str(&format!("  {:width$}", cell, width = width));
+        }
+        matrix.push_str(line.trim_end());
+        matrix.push('\n');
+    }
+
+    matrix.push_str("C - host CPU, M - MAC address, D - disk image, S - socket path\n");
+
+    matrix
+}
+
+fn claims(config: &Config) -> BTreeSet<Claim> {
+    let mut claims = BTreeSet::new();
+
+    for cpu in config.get_pinned_host_cpus() {
+        claims.insert((ConflictKind::HostCpu, cpu.to_string(), true));
+    }
+
+    for interface in config.get_sriov_interfaces() {
+        for function in interface.get_virtual_functions() {
+            if let Some(mac) = function.get_mac() {
+                claims.insert((ConflictKind::MacAddress, mac.to_lowercase(), true));
+            }
+        }
+    }
+
+    let sockets = config
+        .get_qmp_socket()
+        .into_iter()
+        .chain(config.get_qmp_proxy());
+    for socket in sockets {
+        claims.insert((ConflictKind::SocketPath, socket, true));
+    }
+
+    for daemon in config.get_storage_daemons() {
+        for cpu in daemon.get_host_cpus() {
+            claims.insert((ConflictKind::HostCpu, cpu.to_string(), true));
+        }
+
+        claims.insert((
+            ConflictKind::SocketPath,
+            config.get_storage_daemon_socket(daemon),
+            true,
+        ));
+
+        claim_storage_daemon_images(&config.get_storage_daemon_arguments(daemon), &mut claims);
+    }
+
+    let options = config.get_command_line_options();
+    for (name, value) in options.iter().zip(options.iter().skip(1)) {
+        if let Some(name) = name.strip_prefix('-') {
+            claim_option(name, value, &mut claims);
+        }
+    }
+
+    claims
+}
+
+fn claim_option(name: &str, value: &str, claims: &mut BTreeSet<Claim>) {
+    let properties = split_properties(value);
+    let property = |key: &str| {
+        properties
+            .iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| *value)
+    };
+
+    if let Some(mac) = property("mac") {
+        claims.insert((ConflictKind::MacAddress, mac.to_lowercase(), true));
+    }
+
+    if let Some(path) = value.strip_prefix("unix:") {
+        let path = path.split(',').next().unwrap_or(path);
+        claims.insert((ConflictKind::SocketPath, path.to_owned(), true));
+    }
+
+    let image = match name {
+        "chardev" if value.starts_with("socket,") => {
+            if let Some(path) = property("path") {
+                claims.insert((ConflictKind::SocketPath, path.to_owned(), true));
+            }
+            None
+        }
+        "drive" => property("file"),
+        // `file` of a block device is the name of another node, the image is its `filename`
+        "blockdev" => property("filename").or(property("file.filename")),
+        "hda" | "hdb" | "hdc" | "hdd" => Some(value),
+        _ => None,
+    };
+
+    if let Some(image) = image {
+        let read_only = ["readonly", "read-only", "snapshot"]
+            .iter()
+            .any(|key| property(key) == Some("on"));
+
+        claims.insert((ConflictKind::DiskImage, image.to_owned(), !read_only));
+    }
+}
+
+// Images of the nodes a storage daemon exports are written by the machine unless the export is
+// read-only, the daemon itself opens every one of them
+fn claim_storage_daemon_images(arguments: &[String], claims: &mut BTreeSet<Claim>) {
+    let mut writable = HashMap::new();
+    let mut images = vec![];
+
+    for (name, value) in arguments.iter().zip(arguments.iter().skip(1)) {
+        let properties = split_properties(value);
+        let property = |key: &str| {
+            properties
+                .iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| *value)
+        };
+
+        match (name.as_str(), property("node-name")) {
+            ("--blockdev", Some(node)) => {
+                if let Some(image) = property("filename").or(property("file.filename")) {
+                    images.push((node, image, property("read-only") == Some("on")));
+                }
+            }
+            ("--export", Some(node)) => {
+                writable.insert(node, property("writable") == Some("on"));
+            }
+            _ => {}
+        }
+    }
+
+    for (node, image, read_only) in images {
+        let exclusive = !read_only && writable.get(node).copied().unwrap_or(false);
+
+        claims.insert((ConflictKind::DiskImage, image.to_owned(), exclusive));
+    }
+}
+
+fn split_properties(value: &str) -> Vec<(&str, &str)> {
+    value
+        .split(',')
+        .filter_map(|property| property.split_once('='))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{conflict_matrix, find_conflicts, ConflictKind};
+    use crate::config::Config;
+
+    fn config(name: &str, yaml: &str) -> Config {
+        Config::new(yaml).unwrap().with_machine_name(name)
+    }
+
+    #[test]
+    fn find_conflicts_reports_resources_claimed_by_two_machines() {
+        let configs = vec![
+            config(
+                "bar",
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  qmp_socket: /run/vm/qmp.sock
+                  vcpu_pinning_by_index:
+                    0: 2
+                    1: 3
+
+                qemu:
+                - drive: file=/srv/images/shared.qcow2,if=virtio
+                - drive: file=/srv/images/install.iso,media=cdrom,readonly=on
+                - device: virtio-net-pci,mac=52:54:00:AA:BB:CC
+                ",
+            ),
+            config(
+                "foo",
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  qmp_socket: /run/vm/qmp.sock
+                  vcpu_pinning_by_index:
+                    0: 3
+                    1: 4
+
+                qemu:
+                - blockdev: driver=file,node-name=disk0,filename=/srv/images/shared.qcow2
+                - drive: file=/srv/images/install.iso,media=cdrom,readonly=on
+                - device: virtio-net-pci,mac=52:54:00:aa:bb:cc
+                ",
+            ),
+            config(
+                "baz",
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning_by_index:
+                    0: 5
+
+                qemu:
+                - drive: file=/srv/images/shared.qcow2,snapshot=on
+                - chardev: socket,id=serial0,path=/tmp/serial.sock,server=on
+                ",
+            ),
+        ];
+
+        let conflicts = find_conflicts(&configs);
+
+        assert_eq!(
+            vec![
+                "The host CPU `3` is claimed by both `bar` and `foo`.",
+                "The MAC address `52:54:00:aa:bb:cc` is claimed by both `bar` and `foo`.",
+                "The disk image `/srv/images/shared.qcow2` is claimed by both `bar` and `foo`.",
+                "The disk image `/srv/images/shared.qcow2` is claimed by both `bar` and `baz`.",
+                "The disk image `/srv/images/shared.qcow2` is claimed by both `foo` and `baz`.",
+                "The socket path `/run/vm/qmp.sock` is claimed by both `bar` and `foo`.",
+            ],
+            conflicts
+                .iter()
+                .map(|conflict| conflict.describe())
+                .collect::<Vec<String>>()
+        );
+        assert_eq!(ConflictKind::DiskImage, conflicts[2].get_kind());
+        assert_eq!(("bar", "foo"), conflicts[2].get_machines());
+    }
+
+    #[test]
+    fn conflict_matrix_lists_conflict_kinds_per_pair_of_machines() {
+        let configs = vec![
+            config(
+                "bar",
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning_by_index:
+                    0: 2
+
+                qemu:
+                - device: virtio-net-pci,mac=52:54:00:aa:bb:cc
+                ",
+            ),
+            config(
+                "foo",
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+                  vcpu_pinning_by_index:
+                    0: 2
+
+                qemu:
+                - device: virtio-net-pci,mac=52:54:00:aa:bb:cc
+                ",
+            ),
+            config(
+                "web",
+                "
+                launcher:
+                  binary: /usr/bin/qemu-kvm
+
+                qemu:
+                - enable-kvm
+                ",
+            ),
+        ];
+
+        assert_eq!(
+            "      bar   foo   web\n\
+            bar   \\     CM    -\n\
+            foo   CM    \\     -\n\
+            web   -     -     \\\n\
+            C - host CPU, M - MAC address, D - disk image, S - socket path\n",
+            conflict_matrix(&["bar", "foo", "web"], &find_conflicts(&configs))
+        );
+    }
+}
@@ -1,39 +1,409 @@
-use std::io::{Error, ErrorKind};
+use json::{object, JsonValue};
+use std::{
+    io::{Error, ErrorKind},
+    path::Path,
+};
+
+struct VariableSpec {
+    name: &'static str,
+    default: Option<&'static str>,
+    description: &'static str,
+}
+
+const CHECK_MITIGATIONS: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_CHECK_MITIGATIONS",
+    default: Some("false"),
+    description:
+        "set to `true` to warn about pinned host CPUs sharing a core with host tasks or other machines \
+        while the host CPU is exposed to a vulnerability exploitable across SMT siblings.",
+};
+
+const COLOR: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_COLOR",
+    default: Some("auto"),
+    description: "whether errors and warnings are colored: `auto` colors them when printed to a terminal and \
+        `NO_COLOR` is not set, `always` or `never`",
+};
+
+const CONFIG_DIR: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_CONFIG_DIR",
+    default: Some("/usr/local/etc/qemu-launcher"),
+    description: "a path to the directory where virtual machine configuration files are stored.",
+};
+
+const CONFIG_PERMISSIONS: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_CONFIG_PERMISSIONS",
+    default: Some("strict"),
+    description: "what to do with configuration files that are not owned by root or the launcher user, or are \
+        writable by the group or other users: `strict` refuses to load them, `warn` prints a warning, `ignore` \
+        skips the check",
+};
+
+const CPUSET_MIGRATE: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_CPUSET_MIGRATE",
+    default: Some("matching"),
+    description: "which tasks to move into the pool of non-pinned CPUs: `matching` moves only tasks allowed to \
+        run on all CPUs of the pool, `all` moves every task except per-CPU kernel threads",
+};
+
+const CPUSET_MOUNT_OPTIONS: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_CPUSET_MOUNT_OPTIONS",
+    default: None,
+    description: "a comma separated list of extra options, e.g. `nosuid,nodev,noexec`, used when the launcher \
+        mounts the cpuset cgroup tree itself",
+};
+
+const CPUSET_MOUNT_PATH: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_CPUSET_MOUNT_PATH",
+    default: Some("/sys/fs/cgroup/cpuset"),
+    description: "a path to the directory where a cpuset cgroup tree will be mounted.",
+};
+
+const CPUSET_PREFIX: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_CPUSET_PREFIX",
+    default: Some("qemu"),
+    description: "a prefix (directory) under the mount path where qemu cpusets will be created",
+};
+
+const CPUSET_RELEASE_RETRIES: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_CPUSET_RELEASE_RETRIES",
+    default: Some("10"),
+    description: "how many times to retry releasing a pinned host CPU, waiting 100ms in between, while exited \
+        vCPU threads are still being reaped",
+};
+
+const CPUSET_THREAD_NAME: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_CPUSET_THREAD_NAME",
+    default: Some("{vm}-{cpu}"),
+    description: "a name of the pinned host CPU cpuset directory, `{vm}` and `{cpu}` placeholders are replaced \
+        with the machine name and the host CPU ID",
+};
+
+const CPUSET_UNMOUNT: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_CPUSET_UNMOUNT",
+    default: Some("false"),
+    description: "set to `true` to unmount the cpuset cgroup tree on exit, if it was mounted by the launcher \
+        and no other pinned threads remain",
+};
+
+const DEPENDENCY_TIMEOUT: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_DEPENDENCY_TIMEOUT",
+    default: Some("300"),
+    description: "how many seconds to wait for machines listed in `launcher.depends_on` to become ready before \
+        giving up",
+};
+
+const HELPER_INTERVAL: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_HELPER_INTERVAL",
+    default: Some("5"),
+    description:
+        "how many milliseconds to wait at least between starting two helper commands, e.g. `chrt`",
+};
+
+const HELPER_WORKERS: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_HELPER_WORKERS",
+    default: Some("4"),
+    description:
+        "how many helper commands, e.g. `chrt` for every vCPU thread, are allowed to run at once",
+};
+
+const LOG_DIR: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_LOG_DIR",
+    default: Some("/var/log/qemu-launcher"),
+    description: "a path to the directory where per-machine log files are stored.",
+};
+
+const MAX_RT_PRIORITY: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_MAX_RT_PRIORITY",
+    default: None,
+    description:
+        "the highest `fifo` or `rr` vCPU priority any machine is allowed to use, from 1 to 99",
+};
+
+const OUTPUT: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_OUTPUT",
+    default: Some("auto"),
+    description:
+        "how errors and warnings are rendered: `terse` prints each on a single line, `pretty` puts \
+        every underlying cause on a separate line and `auto` uses `pretty` only in a terminal",
+};
+
+const PINNING_FALLBACK: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_PINNING_FALLBACK",
+    default: Some("none"),
+    description:
+        "what to do when vCPU pinning is configured, but the launcher can not manage the cpuset cgroup tree: \
+        `none` refuses to start the machine, `affinity` only sets the CPU affinity of the pinned tasks, \
+        without reserving the host CPUs for them",
+};
+
+const RESCTRL_PATH: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_RESCTRL_PATH",
+    default: Some("/sys/fs/resctrl"),
+    description:
+        "a path where the resctrl filesystem is mounted, used for `launcher.cache_allocation`.",
+};
+
+const RUNTIME_DIR: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_RUNTIME_DIR",
+    default: Some("/run/qemu-launcher"),
+    description: "a path to the directory where per-machine runtime state is stored.",
+};
+
+const STATE_DIR: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_STATE_DIR",
+    default: None,
+    description: "a path to the directory where per-machine state, such as the pid file, is stored, the runtime \
+        directory is used unless provided.",
+};
+
+const SUDO_CHOWN: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_SUDO_CHOWN",
+    default: Some("true"),
+    description:
+        "set to `false` to keep the machine files owned by root, when the launcher is started with \
+        sudo, instead of handing them over to the `SUDO_UID` and `SUDO_GID` user.",
+};
+
+const VALIDATE_HOST: VariableSpec = VariableSpec {
+    name: "QEMU_LAUNCHER_VALIDATE_HOST",
+    default: Some("true"),
+    description:
+        "set to `false` to skip checking the configuration against the host, e.g. that pinned host \
+        CPUs are present, when configurations are prepared on another machine.",
+};
+
+// Every variable the launcher understands, the defaults are parsed the same way as provided values
+const VARIABLES: &[&VariableSpec] = &[
+    &CHECK_MITIGATIONS,
+    &COLOR,
+    &CONFIG_DIR,
+    &CONFIG_PERMISSIONS,
+    &CPUSET_MIGRATE,
+    &CPUSET_MOUNT_OPTIONS,
+    &CPUSET_MOUNT_PATH,
+    &CPUSET_PREFIX,
+    &CPUSET_RELEASE_RETRIES,
+    &CPUSET_THREAD_NAME,
+    &CPUSET_UNMOUNT,
+    &DEPENDENCY_TIMEOUT,
+    &HELPER_INTERVAL,
+    &HELPER_WORKERS,
+    &LOG_DIR,
+    &MAX_RT_PRIORITY,
+    &OUTPUT,
+    &PINNING_FALLBACK,
+    &RESCTRL_PATH,
+    &RUNTIME_DIR,
+    &STATE_DIR,
+    &SUDO_CHOWN,
+    &VALIDATE_HOST,
+];
+
+#[derive(Debug, PartialEq)]
+pub enum Color {
+    Always,
+    Auto,
+    Never,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigPermissions {
+    Ignore,
+    Strict,
+    Warn,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OutputStyle {
+    Auto,
+    Pretty,
+    Terse,
+}
 
 pub struct Environment {
+    check_mitigations: bool,
+    color: Color,
     config_directory: String,
+    config_permissions: ConfigPermissions,
+    cpuset_migrate_all: bool,
+    cpuset_mount_options: Vec<String>,
     cpuset_mount_path: String,
     cpuset_prefix: String,
+    cpuset_release_retries: usize,
+    cpuset_thread_name: String,
+    cpuset_unmount: bool,
+    dependency_timeout: u64,
+    helper_interval: u64,
+    helper_workers: usize,
+    log_directory: String,
+    max_rt_priority: Option<u8>,
+    output_style: OutputStyle,
+    pinning_fallback: bool,
+    resctrl_path: String,
+    runtime_directory: String,
+    state_directory: String,
+    sudo_chown: bool,
+    sudo_gid: Option<u32>,
+    sudo_uid: Option<u32>,
+    validate_host: bool,
 }
 
 impl Environment {
     pub fn new(vars: impl Iterator<Item = (String, String)>) -> Result<Self, Error> {
-        let mut config_directory = String::from("/usr/local/etc/qemu-launcher");
-        let mut cpuset_mount_path = String::from("/sys/fs/cgroup/cpuset");
-        let mut cpuset_prefix = String::from("qemu");
+        let mut check_mitigations = parse_check_mitigations(&default_value(&CHECK_MITIGATIONS))?;
+        let mut color = parse_color(&default_value(&COLOR))?;
+        let mut config_directory = default_value(&CONFIG_DIR);
+        let mut config_permissions = parse_config_permissions(&default_value(&CONFIG_PERMISSIONS))?;
+        let mut cpuset_mount_options =
+            parse_cpuset_mount_options(&default_value(&CPUSET_MOUNT_OPTIONS))?;
+        let mut cpuset_mount_path = default_value(&CPUSET_MOUNT_PATH);
+        let mut cpuset_prefix = default_value(&CPUSET_PREFIX);
+        let mut cpuset_migrate_all = parse_cpuset_migrate(&default_value(&CPUSET_MIGRATE))?;
+        let mut cpuset_release_retries =
+            parse_cpuset_release_retries(&default_value(&CPUSET_RELEASE_RETRIES))?;
+        let mut cpuset_thread_name = default_value(&CPUSET_THREAD_NAME);
+        let mut cpuset_unmount = parse_cpuset_unmount(&default_value(&CPUSET_UNMOUNT))?;
+        let mut dependency_timeout = parse_dependency_timeout(&default_value(&DEPENDENCY_TIMEOUT))?;
+        let mut helper_interval = parse_helper_interval(&default_value(&HELPER_INTERVAL))?;
+        let mut helper_workers = parse_helper_workers(&default_value(&HELPER_WORKERS))?;
+        let mut log_directory = default_value(&LOG_DIR);
+        let mut max_rt_priority = None;
+        let mut no_color = false;
+        let mut output_style = parse_output_style(&default_value(&OUTPUT))?;
+        let mut pinning_fallback = parse_pinning_fallback(&default_value(&PINNING_FALLBACK))?;
+        let mut resctrl_path = default_value(&RESCTRL_PATH);
+        let mut runtime_directory = default_value(&RUNTIME_DIR);
+        let mut state_directory = None;
+        let mut sudo_chown = parse_sudo_chown(&default_value(&SUDO_CHOWN))?;
+        let mut sudo_gid = None;
+        let mut sudo_uid = None;
+        let mut validate_host = parse_validate_host(&default_value(&VALIDATE_HOST))?;
 
         for (name, value) in vars {
             match name.as_str() {
-                "QEMU_LAUNCHER_CONFIG_DIR" => config_directory = value,
-                "QEMU_LAUNCHER_CPUSET_MOUNT_PATH" => cpuset_mount_path = value,
-                "QEMU_LAUNCHER_CPUSET_PREFIX" => cpuset_prefix = value,
+                "NO_COLOR" => no_color = value.len() > 0,
+                name if name == CHECK_MITIGATIONS.name => {
+                    check_mitigations = parse_check_mitigations(&value)?
+                }
+                name if name == COLOR.name => color = parse_color(&value)?,
+                name if name == CONFIG_DIR.name => config_directory = value,
+                name if name == CONFIG_PERMISSIONS.name => {
+                    config_permissions = parse_config_permissions(&value)?
+                }
+                name if name == CPUSET_MIGRATE.name => {
+                    cpuset_migrate_all = parse_cpuset_migrate(&value)?
+                }
+                name if name == CPUSET_MOUNT_OPTIONS.name => {
+                    cpuset_mount_options = parse_cpuset_mount_options(&value)?
+                }
+                name if name == CPUSET_MOUNT_PATH.name => cpuset_mount_path = value,
+                name if name == CPUSET_PREFIX.name => cpuset_prefix = value,
+                name if name == CPUSET_RELEASE_RETRIES.name => {
+                    cpuset_release_retries = parse_cpuset_release_retries(&value)?
+                }
+                name if name == CPUSET_THREAD_NAME.name => cpuset_thread_name = value,
+                name if name == CPUSET_UNMOUNT.name => {
+                    cpuset_unmount = parse_cpuset_unmount(&value)?
+                }
+                name if name == DEPENDENCY_TIMEOUT.name => {
+                    dependency_timeout = parse_dependency_timeout(&value)?
+                }
+                name if name == HELPER_INTERVAL.name => {
+                    helper_interval = parse_helper_interval(&value)?
+                }
+                name if name == HELPER_WORKERS.name => {
+                    helper_workers = parse_helper_workers(&value)?
+                }
+                name if name == LOG_DIR.name => log_directory = value,
+                name if name == MAX_RT_PRIORITY.name => {
+                    max_rt_priority = Some(parse_max_rt_priority(&value)?)
+                }
+                name if name == OUTPUT.name => output_style = parse_output_style(&value)?,
+                name if name == PINNING_FALLBACK.name => {
+                    pinning_fallback = parse_pinning_fallback(&value)?
+                }
+                name if name == RESCTRL_PATH.name => resctrl_path = value,
+                name if name == RUNTIME_DIR.name => runtime_directory = value,
+                name if name == STATE_DIR.name => state_directory = Some(value),
+                name if name == SUDO_CHOWN.name => sudo_chown = parse_sudo_chown(&value)?,
+                name if name == VALIDATE_HOST.name => validate_host = parse_validate_host(&value)?,
+                "SUDO_GID" => sudo_gid = Some(parse_sudo_id("SUDO_GID", &value)?),
+                "SUDO_UID" => sudo_uid = Some(parse_sudo_id("SUDO_UID", &value)?),
                 _ => {}
             }
         }
 
+        // See https://no-color.org, an explicitly requested color mode still takes precedence
+        if no_color && color == Color::Auto {
+            color = Color::Never;
+        }
+
+        // Per-machine state is kept next to the runtime files, unless explicitly separated
+        let state_directory = state_directory.unwrap_or_else(|| runtime_directory.clone());
+
+        validate_absolute_path(CONFIG_DIR.name, &config_directory)?;
+        validate_absolute_path(CPUSET_MOUNT_PATH.name, &cpuset_mount_path)?;
+        validate_absolute_path(LOG_DIR.name, &log_directory)?;
+        validate_absolute_path(RESCTRL_PATH.name, &resctrl_path)?;
+        validate_absolute_path(RUNTIME_DIR.name, &runtime_directory)?;
+        validate_absolute_path(STATE_DIR.name, &state_directory)?;
         validate_cpuset_prefix(&cpuset_prefix)?;
+        validate_cpuset_thread_name(&cpuset_thread_name)?;
 
         Ok(Environment {
+            check_mitigations: check_mitigations,
+            color: color,
             config_directory: config_directory,
+            config_permissions: config_permissions,
+            cpuset_migrate_all: cpuset_migrate_all,
+            cpuset_mount_options: cpuset_mount_options,
             cpuset_mount_path: cpuset_mount_path,
             cpuset_prefix: cpuset_prefix,
+            cpuset_release_retries: cpuset_release_retries,
+            cpuset_thread_name: cpuset_thread_name,
+            cpuset_unmount: cpuset_unmount,
+            dependency_timeout: dependency_timeout,
+            helper_interval: helper_interval,
+            helper_workers: helper_workers,
+            log_directory: log_directory,
+            max_rt_priority: max_rt_priority,
+            output_style: output_style,
+            pinning_fallback: pinning_fallback,
+            resctrl_path: resctrl_path,
+            runtime_directory: runtime_directory,
+            state_directory: state_directory,
+            sudo_chown: sudo_chown,
+            sudo_gid: sudo_gid,
+            sudo_uid: sudo_uid,
+            validate_host: validate_host,
         })
     }
 
+    pub fn should_check_mitigations(&self) -> bool {
+        self.check_mitigations
+    }
+
+    pub fn get_color(&self) -> &Color {
+        &self.color
+    }
+
     pub fn get_config_directory(&self) -> &String {
         &self.config_directory
     }
 
+    pub fn get_config_permissions(&self) -> &ConfigPermissions {
+        &self.config_permissions
+    }
+
+    pub fn should_migrate_all_tasks(&self) -> bool {
+        self.cpuset_migrate_all
+    }
+
+    pub fn get_cpuset_mount_options(&self) -> &Vec<String> {
+        &self.cpuset_mount_options
+    }
+
     pub fn get_cpuset_mount_path(&self) -> &String {
         &self.cpuset_mount_path
     }
@@ -41,6 +411,122 @@ impl Environment {
     pub fn get_cpuset_prefix(&self) -> &String {
         &self.cpuset_prefix
     }
+
+    pub fn get_cpuset_release_retries(&self) -> usize {
+        self.cpuset_release_retries
+    }
+
+    pub fn get_cpuset_thread_name(&self) -> &String {
+        &self.cpuset_thread_name
+    }
+
+    pub fn should_unmount_cpuset(&self) -> bool {
+        self.cpuset_unmount
+    }
+
+    pub fn get_dependency_timeout(&self) -> u64 {
+        self.dependency_timeout
+    }
+
+    pub fn get_helper_interval(&self) -> u64 {
+        self.helper_interval
+    }
+
+    pub fn get_helper_workers(&self) -> usize {
+        self.helper_workers
+    }
+
+    pub fn get_log_directory(&self) -> &String {
+        &self.log_directory
+    }
+
+    pub fn get_max_rt_priority(&self) -> Option<u8> {
+        self.max_rt_priority
+    }
+
+    pub fn get_output_style(&self) -> &OutputStyle {
+        &self.output_style
+    }
+
+    pub fn should_fallback_to_affinity(&self) -> bool {
+        self.pinning_fallback
+    }
+
+    pub fn get_resctrl_path(&self) -> &String {
+        &self.resctrl_path
+    }
+
+    pub fn get_runtime_directory(&self) -> &String {
+        &self.runtime_directory
+    }
+
+    pub fn get_state_directory(&self) -> &String {
+        &self.state_directory
+    }
+
+    pub fn should_validate_host(&self) -> bool {
+        self.validate_host
+    }
+
+    // The user who invoked the launcher through sudo, unless it is root itself or the ownership
+    // hand over is disabled
+    pub fn get_sudo_owner(&self) -> Option<(u32, Option<u32>)> {
+        match self.sudo_uid {
+            Some(uid) if self.sudo_chown && uid != 0 => Some((uid, self.sudo_gid)),
+            _ => None,
+        }
+    }
+}
+
+pub fn usage() -> String {
+    let mut usage = String::new();
+
+    for variable in VARIABLES {
+        usage.push_str(&format!("- {} - {}\n", variable.name, variable.description));
+        if let Some(default) = variable.default {
+            usage.push_str(&format!(
+                "{:indent$}default: {}\n",
+                "",
+                default,
+                indent = variable.name.len() + 5
+            ));
+        }
+    }
+
+    usage
+}
+
+pub fn describe() -> JsonValue {
+    let variables: Vec<JsonValue> = VARIABLES
+        .iter()
+        .map(|variable| {
+            object! {
+                "name": variable.name,
+                "default": variable.default,
+                "description": variable.description,
+            }
+        })
+        .collect();
+
+    variables.into()
+}
+
+fn default_value(variable: &VariableSpec) -> String {
+    variable.default.unwrap_or_default().to_owned()
+}
+
+fn validate_absolute_path(variable: &str, path: &String) -> Result<(), Error> {
+    if !Path::new(path).is_absolute() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "`{}` environment variable must be an absolute path",
+                variable
+            ),
+        ));
+    }
+
+    Ok({})
 }
 
 fn validate_cpuset_prefix(prefix: &String) -> Result<(), Error> {
@@ -54,18 +540,301 @@ fn validate_cpuset_prefix(prefix: &String) -> Result<(), Error> {
     Ok({})
 }
 
+fn parse_color(value: &String) -> Result<Color, Error> {
+    match value.as_str() {
+        "always" => Ok(Color::Always),
+        "auto" => Ok(Color::Auto),
+        "never" => Ok(Color::Never),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`QEMU_LAUNCHER_COLOR` environment variable must be one of `auto`, `always` or `never`",
+        )),
+    }
+}
+
+fn parse_output_style(value: &String) -> Result<OutputStyle, Error> {
+    match value.as_str() {
+        "auto" => Ok(OutputStyle::Auto),
+        "pretty" => Ok(OutputStyle::Pretty),
+        "terse" => Ok(OutputStyle::Terse),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`QEMU_LAUNCHER_OUTPUT` environment variable must be one of `auto`, `pretty` or `terse`",
+        )),
+    }
+}
+
+fn parse_config_permissions(value: &String) -> Result<ConfigPermissions, Error> {
+    match value.as_str() {
+        "ignore" => Ok(ConfigPermissions::Ignore),
+        "strict" => Ok(ConfigPermissions::Strict),
+        "warn" => Ok(ConfigPermissions::Warn),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`QEMU_LAUNCHER_CONFIG_PERMISSIONS` environment variable must be one of `strict`, `warn` or `ignore`",
+        )),
+    }
+}
+
+fn parse_cpuset_migrate(value: &String) -> Result<bool, Error> {
+    match value.as_str() {
+        "all" => Ok(true),
+        "matching" => Ok(false),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`QEMU_LAUNCHER_CPUSET_MIGRATE` environment variable must be either `all` or `matching`",
+        )),
+    }
+}
+
+fn parse_cpuset_mount_options(value: &String) -> Result<Vec<String>, Error> {
+    let options: Vec<String> = value
+        .split(',')
+        .map(|option| option.trim())
+        .filter(|option| option.len() > 0)
+        .map(|option| option.to_owned())
+        .collect();
+
+    // The launcher relies on the `cpuset.` prefix of the control file names
+    if options.iter().any(|option| option == "noprefix") {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`QEMU_LAUNCHER_CPUSET_MOUNT_OPTIONS` environment variable can not contain the `noprefix` option",
+        ));
+    }
+
+    Ok(options)
+}
+
+fn parse_cpuset_release_retries(value: &String) -> Result<usize, Error> {
+    match value.parse::<usize>() {
+        Ok(retries) => Ok(retries),
+        Err(_) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`QEMU_LAUNCHER_CPUSET_RELEASE_RETRIES` environment variable must be a non-negative integer",
+        )),
+    }
+}
+
+fn parse_cpuset_unmount(value: &String) -> Result<bool, Error> {
+    match value.as_str() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`QEMU_LAUNCHER_CPUSET_UNMOUNT` environment variable must be either `true` or `false`",
+        )),
+    }
+}
+
+fn parse_pinning_fallback(value: &String) -> Result<bool, Error> {
+    match value.as_str() {
+        "affinity" => Ok(true),
+        "none" => Ok(false),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`QEMU_LAUNCHER_PINNING_FALLBACK` environment variable must be either `affinity` or `none`",
+        )),
+    }
+}
+
+fn parse_sudo_chown(value: &String) -> Result<bool, Error> {
+    match value.as_str() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`QEMU_LAUNCHER_SUDO_CHOWN` environment variable must be either `true` or `false`",
+        )),
+    }
+}
+
+fn parse_check_mitigations(value: &String) -> Result<bool, Error> {
+    match value.as_str() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`QEMU_LAUNCHER_CHECK_MITIGATIONS` environment variable must be either `true` or `false`",
+        )),
+    }
+}
+
+fn parse_validate_host(value: &String) -> Result<bool, Error> {
+    match value.as_str() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`QEMU_LAUNCHER_VALIDATE_HOST` environment variable must be either `true` or `false`",
+        )),
+    }
+}
+
+fn parse_sudo_id(variable: &str, value: &String) -> Result<u32, Error> {
+    match value.parse::<u32>() {
+        Ok(id) => Ok(id),
+        Err(_) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("`{}` environment variable must be a numeric ID", variable),
+        )),
+    }
+}
+
+fn parse_dependency_timeout(value: &String) -> Result<u64, Error> {
+    match value.parse::<u64>() {
+        Ok(timeout) => Ok(timeout),
+        Err(_) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`QEMU_LAUNCHER_DEPENDENCY_TIMEOUT` environment variable must be a non-negative integer",
+        )),
+    }
+}
+
+fn parse_helper_interval(value: &String) -> Result<u64, Error> {
+    match value.parse::<u64>() {
+        Ok(interval) => Ok(interval),
+        Err(_) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`QEMU_LAUNCHER_HELPER_INTERVAL` environment variable must be a non-negative integer",
+        )),
+    }
+}
+
+fn parse_helper_workers(value: &String) -> Result<usize, Error> {
+    match value.parse::<usize>() {
+        Ok(workers) if workers > 0 => Ok(workers),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`QEMU_LAUNCHER_HELPER_WORKERS` environment variable must be a positive integer",
+        )),
+    }
+}
+
+fn parse_max_rt_priority(value: &String) -> Result<u8, Error> {
+    match value.parse::<u8>() {
+        Ok(priority) if priority >= 1 && priority <= 99 => Ok(priority),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`QEMU_LAUNCHER_MAX_RT_PRIORITY` environment variable must be an integer from 1 to 99",
+        )),
+    }
+}
+
+fn validate_cpuset_thread_name(name: &String) -> Result<(), Error> {
+    if name.contains("\0") || name.contains("/") {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`QEMU_LAUNCHER_CPUSET_THREAD_NAME` environment variable has invalid characters",
+        ));
+    }
+
+    if !name.contains("{cpu}") {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`QEMU_LAUNCHER_CPUSET_THREAD_NAME` environment variable must contain the `{cpu}` placeholder",
+        ));
+    }
+
+    Ok({})
+}
+
 #[cfg(test)]
 mod test {
-    use super::Environment;
+    use super::{Color, ConfigPermissions, Environment, OutputStyle};
     use std::io::ErrorKind;
 
+    #[test]
+    fn usage_lists_variables_with_their_defaults() {
+        let usage = super::usage();
+
+        assert!(usage.starts_with("- QEMU_LAUNCHER_CHECK_MITIGATIONS - set to `true` to warn"));
+        assert!(usage.contains(
+            "\n- QEMU_LAUNCHER_COLOR - whether errors and warnings are colored: `auto` colors them"
+        ));
+        assert!(usage.contains("\n                        default: auto\n"));
+        assert!(usage.contains(
+            "\n- QEMU_LAUNCHER_MAX_RT_PRIORITY - the highest `fifo` or `rr` vCPU priority any machine is \
+            allowed to use, from 1 to 99\n- QEMU_LAUNCHER_OUTPUT - "
+        ));
+    }
+
+    #[test]
+    fn describe_lists_variables_with_their_defaults() {
+        let description = super::describe();
+
+        assert_eq!("QEMU_LAUNCHER_CHECK_MITIGATIONS", description[0]["name"]);
+        assert_eq!("false", description[0]["default"]);
+        assert_eq!("QEMU_LAUNCHER_COLOR", description[1]["name"]);
+        assert_eq!("auto", description[1]["default"]);
+        assert!(description.members().any(|variable| variable["name"]
+            == "QEMU_LAUNCHER_MAX_RT_PRIORITY"
+            && variable["default"].is_null()));
+    }
+
     #[test]
     fn environment_uses_default_values_if_not_provided() {
         let env = Environment::new(vec![].into_iter()).unwrap();
 
+        assert_eq!(&Color::Auto, env.get_color());
+        assert_eq!(&OutputStyle::Auto, env.get_output_style());
         assert_eq!("/usr/local/etc/qemu-launcher", env.get_config_directory());
+        assert_eq!(&ConfigPermissions::Strict, env.get_config_permissions());
         assert_eq!("/sys/fs/cgroup/cpuset", env.get_cpuset_mount_path());
         assert_eq!("qemu", env.get_cpuset_prefix());
+        assert_eq!("{vm}-{cpu}", env.get_cpuset_thread_name());
+        assert!(!env.should_unmount_cpuset());
+        assert_eq!(10, env.get_cpuset_release_retries());
+        assert!(!env.should_migrate_all_tasks());
+        assert_eq!(300, env.get_dependency_timeout());
+        assert_eq!(5, env.get_helper_interval());
+        assert_eq!(4, env.get_helper_workers());
+        assert_eq!("/var/log/qemu-launcher", env.get_log_directory());
+        assert_eq!(None, env.get_max_rt_priority());
+        assert!(!env.should_fallback_to_affinity());
+        assert_eq!("/run/qemu-launcher", env.get_runtime_directory());
+        assert_eq!("/run/qemu-launcher", env.get_state_directory());
+        assert!(env.should_validate_host());
+        assert!(!env.should_check_mitigations());
+    }
+
+    #[test]
+    fn environment_uses_color_and_output_style_if_provided() {
+        let vars = vec![
+            ("QEMU_LAUNCHER_COLOR".to_owned(), "always".to_owned()),
+            ("QEMU_LAUNCHER_OUTPUT".to_owned(), "terse".to_owned()),
+            ("NO_COLOR".to_owned(), "1".to_owned()),
+        ]
+        .into_iter();
+
+        let env = Environment::new(vars).unwrap();
+
+        assert_eq!(&Color::Always, env.get_color());
+        assert_eq!(&OutputStyle::Terse, env.get_output_style());
+    }
+
+    #[test]
+    fn environment_disables_automatic_color_if_no_color_is_set() {
+        let vars = vec![("NO_COLOR".to_owned(), "1".to_owned())].into_iter();
+
+        let env = Environment::new(vars).unwrap();
+
+        assert_eq!(&Color::Never, env.get_color());
+    }
+
+    #[test]
+    fn environment_returns_error_if_output_style_is_invalid() {
+        let vars = vec![("QEMU_LAUNCHER_OUTPUT".to_owned(), "fancy".to_owned())].into_iter();
+
+        match Environment::new(vars) {
+            Ok(_) => panic!(
+                "Environment::new() returned no error for invalid `QEMU_LAUNCHER_OUTPUT` variable"
+            ),
+            Err(e) => {
+                assert!(format!("{}", e).contains("QEMU_LAUNCHER_OUTPUT"));
+                assert_eq!(ErrorKind::InvalidInput, e.kind());
+            }
+        }
     }
 
     #[test]
@@ -107,6 +876,273 @@ mod test {
         assert_eq!("foobar", env.get_cpuset_prefix());
     }
 
+    #[test]
+    fn environment_uses_log_dir_if_provided() {
+        let vars = vec![("QEMU_LAUNCHER_LOG_DIR".to_owned(), "/logs".to_owned())].into_iter();
+
+        let env = Environment::new(vars).unwrap();
+
+        assert_eq!("/logs", env.get_log_directory());
+    }
+
+    #[test]
+    fn environment_uses_runtime_dir_if_provided() {
+        let vars = vec![(
+            "QEMU_LAUNCHER_RUNTIME_DIR".to_owned(),
+            "/tmp/run".to_owned(),
+        )]
+        .into_iter();
+
+        let env = Environment::new(vars).unwrap();
+
+        assert_eq!("/tmp/run", env.get_runtime_directory());
+    }
+
+    #[test]
+    fn environment_uses_cpuset_release_retries_if_provided() {
+        let vars = vec![(
+            "QEMU_LAUNCHER_CPUSET_RELEASE_RETRIES".to_owned(),
+            "3".to_owned(),
+        )]
+        .into_iter();
+
+        let env = Environment::new(vars).unwrap();
+
+        assert_eq!(3, env.get_cpuset_release_retries());
+    }
+
+    #[test]
+    fn environment_uses_config_permissions_if_provided() {
+        let vars = vec![(
+            "QEMU_LAUNCHER_CONFIG_PERMISSIONS".to_owned(),
+            "warn".to_owned(),
+        )]
+        .into_iter();
+
+        let env = Environment::new(vars).unwrap();
+
+        assert_eq!(&ConfigPermissions::Warn, env.get_config_permissions());
+    }
+
+    #[test]
+    fn environment_returns_error_if_config_permissions_is_invalid() {
+        let vars = vec![(
+            "QEMU_LAUNCHER_CONFIG_PERMISSIONS".to_owned(),
+            "off".to_owned(),
+        )]
+        .into_iter();
+
+        match Environment::new(vars) {
+            Ok(_) => panic!(
+                "Environment::new() returned no error for invalid `QEMU_LAUNCHER_CONFIG_PERMISSIONS` variable"
+            ),
+            Err(e) => {
+                assert!(format!("{}", e).contains("QEMU_LAUNCHER_CONFIG_PERMISSIONS"));
+                assert_eq!(ErrorKind::InvalidInput, e.kind());
+            }
+        }
+    }
+
+    #[test]
+    fn environment_uses_cpuset_migrate_if_provided() {
+        let vars = vec![("QEMU_LAUNCHER_CPUSET_MIGRATE".to_owned(), "all".to_owned())].into_iter();
+
+        let env = Environment::new(vars).unwrap();
+
+        assert!(env.should_migrate_all_tasks());
+    }
+
+    #[test]
+    fn environment_uses_pinning_fallback_if_provided() {
+        let vars = vec![(
+            "QEMU_LAUNCHER_PINNING_FALLBACK".to_owned(),
+            "affinity".to_owned(),
+        )]
+        .into_iter();
+
+        let env = Environment::new(vars).unwrap();
+
+        assert!(env.should_fallback_to_affinity());
+    }
+
+    #[test]
+    fn environment_returns_error_if_helper_workers_is_zero() {
+        let vars = vec![("QEMU_LAUNCHER_HELPER_WORKERS".to_owned(), "0".to_owned())].into_iter();
+
+        match Environment::new(vars) {
+            Ok(_) => panic!(
+                "Environment::new() returned no error for zero `QEMU_LAUNCHER_HELPER_WORKERS` variable"
+            ),
+            Err(e) => {
+                assert_eq!(
+                    "`QEMU_LAUNCHER_HELPER_WORKERS` environment variable must be a positive integer",
+                    format!("{}", e)
+                );
+                assert_eq!(ErrorKind::InvalidInput, e.kind());
+            }
+        }
+    }
+
+    #[test]
+    fn environment_uses_dependency_timeout_if_provided() {
+        let vars = vec![(
+            "QEMU_LAUNCHER_DEPENDENCY_TIMEOUT".to_owned(),
+            "30".to_owned(),
+        )]
+        .into_iter();
+
+        let env = Environment::new(vars).unwrap();
+
+        assert_eq!(30, env.get_dependency_timeout());
+    }
+
+    #[test]
+    fn environment_uses_cpuset_unmount_if_provided() {
+        let vars = vec![("QEMU_LAUNCHER_CPUSET_UNMOUNT".to_owned(), "true".to_owned())].into_iter();
+
+        let env = Environment::new(vars).unwrap();
+
+        assert!(env.should_unmount_cpuset());
+    }
+
+    #[test]
+    fn environment_returns_error_if_cpuset_unmount_is_invalid() {
+        let vars = vec![("QEMU_LAUNCHER_CPUSET_UNMOUNT".to_owned(), "yes".to_owned())].into_iter();
+
+        match Environment::new(vars) {
+            Ok(_) => panic!(
+                "Environment::new() returned no error for invalid `QEMU_LAUNCHER_CPUSET_UNMOUNT` variable"
+            ),
+            Err(e) => {
+                assert!(format!("{}", e).contains("QEMU_LAUNCHER_CPUSET_UNMOUNT"));
+                assert_eq!(ErrorKind::InvalidInput, e.kind());
+            }
+        }
+    }
+
+    #[test]
+    fn environment_uses_check_mitigations_if_provided() {
+        let vars = vec![(
+            "QEMU_LAUNCHER_CHECK_MITIGATIONS".to_owned(),
+            "true".to_owned(),
+        )]
+        .into_iter();
+
+        let env = Environment::new(vars).unwrap();
+
+        assert!(env.should_check_mitigations());
+    }
+
+    #[test]
+    fn environment_uses_state_dir_if_provided() {
+        let vars = vec![
+            (
+                "QEMU_LAUNCHER_RUNTIME_DIR".to_owned(),
+                "/tmp/run".to_owned(),
+            ),
+            (
+                "QEMU_LAUNCHER_STATE_DIR".to_owned(),
+                "/var/lib/qemu-launcher".to_owned(),
+            ),
+        ]
+        .into_iter();
+
+        let env = Environment::new(vars).unwrap();
+
+        assert_eq!("/tmp/run", env.get_runtime_directory());
+        assert_eq!("/var/lib/qemu-launcher", env.get_state_directory());
+    }
+
+    #[test]
+    fn environment_uses_the_last_value_of_repeated_variables() {
+        let vars = vec![
+            ("QEMU_LAUNCHER_LOG_DIR".to_owned(), "/logs".to_owned()),
+            ("QEMU_LAUNCHER_LOG_DIR".to_owned(), "/override".to_owned()),
+        ]
+        .into_iter();
+
+        let env = Environment::new(vars).unwrap();
+
+        assert_eq!("/override", env.get_log_directory());
+    }
+
+    #[test]
+    fn environment_returns_error_if_directory_is_relative() {
+        let vars = vec![("QEMU_LAUNCHER_LOG_DIR".to_owned(), "logs".to_owned())].into_iter();
+
+        match Environment::new(vars) {
+            Ok(_) => panic!(
+                "Environment::new() returned no error for relative `QEMU_LAUNCHER_LOG_DIR` variable"
+            ),
+            Err(e) => {
+                assert_eq!(
+                    "`QEMU_LAUNCHER_LOG_DIR` environment variable must be an absolute path",
+                    format!("{}", e)
+                );
+                assert_eq!(ErrorKind::InvalidInput, e.kind());
+            }
+        }
+    }
+
+    #[test]
+    fn environment_uses_max_rt_priority_if_provided() {
+        let vars = vec![("QEMU_LAUNCHER_MAX_RT_PRIORITY".to_owned(), "50".to_owned())].into_iter();
+
+        let env = Environment::new(vars).unwrap();
+
+        assert_eq!(Some(50), env.get_max_rt_priority());
+    }
+
+    #[test]
+    fn environment_returns_error_if_max_rt_priority_is_invalid() {
+        let vars = vec![("QEMU_LAUNCHER_MAX_RT_PRIORITY".to_owned(), "100".to_owned())].into_iter();
+
+        match Environment::new(vars) {
+            Ok(_) => panic!(
+                "Environment::new() returned no error for invalid `QEMU_LAUNCHER_MAX_RT_PRIORITY` variable"
+            ),
+            Err(e) => {
+                assert!(format!("{}", e).contains("QEMU_LAUNCHER_MAX_RT_PRIORITY"));
+                assert_eq!(ErrorKind::InvalidInput, e.kind());
+            }
+        }
+    }
+
+    #[test]
+    fn environment_splits_cpuset_mount_options() {
+        let vars = vec![(
+            "QEMU_LAUNCHER_CPUSET_MOUNT_OPTIONS".to_owned(),
+            "nosuid, nodev,,clone_children".to_owned(),
+        )]
+        .into_iter();
+
+        let env = Environment::new(vars).unwrap();
+
+        assert_eq!(
+            &vec!["nosuid", "nodev", "clone_children"],
+            env.get_cpuset_mount_options()
+        );
+    }
+
+    #[test]
+    fn environment_returns_error_if_cpuset_mount_options_contain_noprefix() {
+        let vars = vec![(
+            "QEMU_LAUNCHER_CPUSET_MOUNT_OPTIONS".to_owned(),
+            "nosuid,noprefix".to_owned(),
+        )]
+        .into_iter();
+
+        match Environment::new(vars) {
+            Ok(_) => panic!(
+                "Environment::new() returned no error for `noprefix` in `QEMU_LAUNCHER_CPUSET_MOUNT_OPTIONS`"
+            ),
+            Err(e) => {
+                assert!(format!("{}", e).contains("`noprefix`"));
+                assert_eq!(ErrorKind::InvalidInput, e.kind());
+            }
+        }
+    }
+
     #[test]
     fn environment_returns_error_if_prefix_is_invalid() {
         let vars = vec![(
@@ -127,4 +1163,56 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn environment_returns_error_if_thread_name_has_no_cpu_placeholder() {
+        let vars = vec![(
+            "QEMU_LAUNCHER_CPUSET_THREAD_NAME".to_owned(),
+            "{vm}".to_owned(),
+        )]
+        .into_iter();
+
+        match Environment::new(vars) {
+            Ok(_) => panic!(
+                "Environment::new() returned no error for `QEMU_LAUNCHER_CPUSET_THREAD_NAME` without `{cpu}`"
+            ),
+            Err(e) => {
+                assert!(format!("{}", e).contains("{cpu}"));
+                assert_eq!(ErrorKind::InvalidInput, e.kind());
+            }
+        }
+    }
+
+    #[test]
+    fn environment_detects_sudo_invoking_user() {
+        let vars = vec![
+            ("SUDO_UID".to_owned(), "1000".to_owned()),
+            ("SUDO_GID".to_owned(), "100".to_owned()),
+        ];
+
+        let env = Environment::new(vars.clone().into_iter()).unwrap();
+        assert_eq!(Some((1000, Some(100))), env.get_sudo_owner());
+
+        let disabled = vars.into_iter().chain(vec![(
+            "QEMU_LAUNCHER_SUDO_CHOWN".to_owned(),
+            "false".to_owned(),
+        )]);
+        assert_eq!(None, Environment::new(disabled).unwrap().get_sudo_owner());
+
+        let root = vec![("SUDO_UID".to_owned(), "0".to_owned())].into_iter();
+        assert_eq!(None, Environment::new(root).unwrap().get_sudo_owner());
+    }
+
+    #[test]
+    fn environment_returns_error_if_sudo_uid_is_invalid() {
+        let vars = vec![("SUDO_UID".to_owned(), "alice".to_owned())].into_iter();
+
+        match Environment::new(vars) {
+            Ok(_) => panic!("Environment::new() returned no error for invalid `SUDO_UID` variable"),
+            Err(e) => {
+                assert!(format!("{}", e).contains("SUDO_UID"));
+                assert_eq!(ErrorKind::InvalidInput, e.kind());
+            }
+        }
+    }
 }
@@ -0,0 +1,127 @@
+use crate::{config::EventSink, output};
+use json::{object, JsonValue};
+use nix::{errno::Errno, sys::stat::Mode, unistd::mkfifo};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Error, ErrorKind, Write},
+    os::unix::fs::{FileTypeExt, OpenOptionsExt},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Mirrors the QMP events received from qemu as JSON lines, so scripts can react to them without
+// speaking QMP
+pub struct QmpEventSink {
+    file: Option<File>,
+    overflowing: bool,
+    path: String,
+}
+
+impl QmpEventSink {
+    pub fn open(sink: &EventSink) -> Result<Self, Error> {
+        let (path, file) = match sink {
+            EventSink::Fifo(path) => (path, open_fifo(path)),
+            EventSink::File(path) => (
+                path,
+                OpenOptions::new().create(true).append(true).open(path),
+            ),
+        };
+
+        match file {
+            Ok(file) => Ok(QmpEventSink {
+                file: Some(file),
+                overflowing: false,
+                path: path.to_owned(),
+            }),
+            Err(e) => Err(Error::new(
+                e.kind(),
+                format!("Failed to open the `{}` event sink: {}", path, e),
+            )),
+        }
+    }
+
+    pub fn write(&mut self, event: &str, data: &JsonValue) {
+        let file = match self.file.as_mut() {
+            Some(file) => file,
+            None => return,
+        };
+
+        let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_millis() as u64,
+            Err(_) => 0,
+        };
+
+        match file.write_all(format!("{}\n", render(event, timestamp, data)).as_bytes()) {
+            Ok(_) => self.overflowing = false,
+            // Nobody reads the FIFO, events are dropped until the reader catches up
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                if !self.overflowing {
+                    output::warning(format!(
+                        "The `{}` event sink is full, QMP events are dropped until it is read.",
+                        self.path
+                    ));
+                }
+                self.overflowing = true;
+            }
+            Err(e) => {
+                output::warning(format!(
+                    "Failed to write the `{}` QMP event into `{}`, no further events will be written: {}",
+                    event, self.path, e
+                ));
+                self.file = None;
+            }
+        }
+    }
+}
+
+fn open_fifo(path: &str) -> Result<File, Error> {
+    match mkfifo(path, Mode::S_IRUSR | Mode::S_IWUSR) {
+        Ok(_) | Err(nix::Error::Sys(Errno::EEXIST)) => {}
+        Err(e) => return Err(Error::new(ErrorKind::Other, e.to_string())),
+    }
+
+    if !fs::metadata(path)?.file_type().is_fifo() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "the path exists, but is not a FIFO",
+        ));
+    }
+
+    // Opening for reading as well never blocks waiting for a reader, and the non-blocking writes
+    // never stall the QMP connection when the reader falls behind
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(nix::libc::O_NONBLOCK)
+        .open(path)
+}
+
+fn render(event: &str, timestamp: u64, data: &JsonValue) -> String {
+    object! {
+        "event": event,
+        "data": data.clone(),
+        "timestamp_ms": timestamp,
+    }
+    .dump()
+}
+
+#[cfg(test)]
+mod test {
+    use super::render;
+    use json::{object, JsonValue};
+
+    #[test]
+    fn render_prints_qmp_event_on_a_single_line() {
+        assert_eq!(
+            r#"{"event":"BLOCK_JOB_COMPLETED","data":{"device":"drive0","len":1024},"timestamp_ms":1000}"#,
+            render(
+                "BLOCK_JOB_COMPLETED",
+                1000,
+                &object! { "device": "drive0", "len": 1024 }
+            )
+        );
+        assert_eq!(
+            r#"{"event":"STOP","data":null,"timestamp_ms":1000}"#,
+            render("STOP", 1000, &JsonValue::Null)
+        );
+    }
+}
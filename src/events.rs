@@ -1,67 +1,87 @@
-use std::{any::TypeId, collections::HashMap, mem::transmute};
+use crate::output;
+use json::{object, JsonValue};
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::Write,
+    os::unix::io::FromRawFd,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-pub trait Event<EventData: ?Sized>: 'static {}
-
-pub trait EventEmitter {
-    fn trigger<E: Event<X>, X: ?Sized>(&mut self, event: &E);
-}
-
-pub struct EventDispatcher {
-    listeners: HashMap<TypeId, Vec<Box<dyn FnMut(&())>>>,
+pub struct Events {
+    machine_name: String,
+    sink: RefCell<Option<File>>,
 }
 
-impl EventDispatcher {
-    pub fn new() -> Self {
-        Self {
-            listeners: HashMap::new(),
+impl Events {
+    pub fn new(fd: Option<i32>, machine_name: &str) -> Self {
+        Events {
+            machine_name: machine_name.to_owned(),
+            // Safe, since the descriptor is handed over to the launcher and not used anywhere else
+            sink: RefCell::new(fd.map(|fd| unsafe { File::from_raw_fd(fd) })),
         }
     }
 
-    pub fn add_listener<E: Event<X>, X: ?Sized, F: FnMut(&E)>(&mut self, listener: F) {
-        let callback: Box<dyn FnMut(&E)> = Box::new(listener);
-        let callback: Box<dyn FnMut(&())> = unsafe { transmute(callback) };
+    pub fn emit(&self, event: &str, data: JsonValue) {
+        let mut sink = self.sink.borrow_mut();
+        let file = match sink.as_mut() {
+            Some(file) => file,
+            None => return,
+        };
+
+        let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_millis() as u64,
+            Err(_) => 0,
+        };
 
-        self.listeners
-            .entry(TypeId::of::<E>())
-            .or_insert(vec![])
-            .push(callback);
+        if let Err(e) = writeln!(
+            file,
+            "{}",
+            render(event, &self.machine_name, timestamp, data)
+        ) {
+            // The consumer went away, there is no point in failing the machine because of it
+            output::warning(format!(
+                "Failed to write the `{}` launch event, no further events will be written: {}",
+                event, e
+            ));
+            *sink = None;
+        }
     }
 }
 
-impl EventEmitter for EventDispatcher {
-    fn trigger<E: Event<X>, X: ?Sized>(&mut self, event: &E) {
-        if let Some(listeners) = self.listeners.remove(&TypeId::of::<E>()) {
-            for listener in listeners {
-                let mut listener: Box<dyn FnMut(&E)> = unsafe { transmute(listener) };
-                listener(event);
-                self.add_listener(listener);
-            }
-        }
+fn render(event: &str, machine_name: &str, timestamp: u64, data: JsonValue) -> String {
+    let mut record = object! {
+        "event": event,
+        "machine": machine_name,
+        "timestamp_ms": timestamp,
+    };
+
+    for (key, value) in data.entries() {
+        record[key] = value.clone();
     }
+
+    record.dump()
 }
 
 #[cfg(test)]
 mod test {
-    use super::{Event, EventDispatcher, EventEmitter};
-
-    struct TestEvent {}
-    impl Event<TestEvent> for TestEvent {}
-
-    struct EventWithNoListeners {}
-    impl Event<EventWithNoListeners> for EventWithNoListeners {}
+    use super::render;
+    use json::object;
 
     #[test]
-    fn event_dispatcher_notifies_appropriate_event_listeners() {
-        let mut event_dispatcher = EventDispatcher::new();
-        let mut test_event_count = 0;
-
-        event_dispatcher.add_listener(|_event: &TestEvent| {
-            test_event_count += 1;
-        });
-
-        event_dispatcher.trigger(&TestEvent {});
-        event_dispatcher.trigger(&EventWithNoListeners {});
-
-        assert_eq!(1, test_event_count);
+    fn render_prints_event_on_a_single_line_with_data() {
+        assert_eq!(
+            r#"{"event":"spawned","machine":"my-vm","timestamp_ms":1000,"pid":123,"adopted":false}"#,
+            render(
+                "spawned",
+                "my-vm",
+                1000,
+                object! { "pid": 123, "adopted": false }
+            )
+        );
+        assert_eq!(
+            r#"{"event":"ready","machine":"my-vm","timestamp_ms":1000}"#,
+            render("ready", "my-vm", 1000, object! {})
+        );
     }
 }
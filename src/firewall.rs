@@ -0,0 +1,215 @@
+use crate::config::Firewall;
+#[cfg(not(test))]
+use crate::process::Process;
+use std::io::Error;
+#[cfg(test)]
+use test::process::Process;
+
+const NFT: &str = "nft";
+const TABLE_PREFIX: &str = "qemu_launcher_";
+
+pub fn table_name(machine_name: &str) -> String {
+    // nftables identifiers only allow a limited set of characters without quoting
+    let name: String = machine_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    format!("{}{}", TABLE_PREFIX, name)
+}
+
+pub fn apply(table: &str, firewall: &Firewall) -> Result<(), Error> {
+    for command in commands(table, firewall) {
+        if let Err(e) = Process::oneshot(NFT, &[&command]) {
+            return Err(Error::new(
+                e.kind(),
+                format!("Failed to apply the `{}` firewall rule: {}", command, e),
+            ));
+        }
+    }
+
+    Ok({})
+}
+
+pub fn remove(table: &str) -> Result<(), Error> {
+    if let Err(e) = Process::oneshot(NFT, &[format!("delete table ip {}", table)]) {
+        return Err(Error::new(
+            e.kind(),
+            format!("Failed to remove the `{}` firewall table: {}", table, e),
+        ));
+    }
+
+    Ok({})
+}
+
+fn commands(table: &str, firewall: &Firewall) -> Vec<String> {
+    // Adding an existing table or chain is a no-op, so leftovers of a crashed launcher are reused
+    let mut commands = vec![
+        format!("add table ip {}", table),
+        format!("flush table ip {}", table),
+        format!(
+            "add chain ip {} prerouting {{ type nat hook prerouting priority -100; }}",
+            table
+        ),
+        format!(
+            "add chain ip {} forward {{ type filter hook forward priority 0; }}",
+            table
+        ),
+    ];
+
+    let source = match firewall.get_allow_from().len() {
+        0 => String::new(),
+        _ => format!("ip saddr {{ {} }} ", firewall.get_allow_from().join(", ")),
+    };
+
+    for forward in firewall.get_forwards() {
+        commands.push(format!(
+            "add rule ip {} prerouting {}{} dport {} dnat to {}:{}",
+            table,
+            source,
+            forward.get_protocol(),
+            forward.get_host_port(),
+            firewall.get_guest_address(),
+            forward.get_guest_port()
+        ));
+        commands.push(format!(
+            "add rule ip {} forward ip daddr {} {} dport {} ct status dnat accept",
+            table,
+            firewall.get_guest_address(),
+            forward.get_protocol(),
+            forward.get_guest_port()
+        ));
+    }
+
+    commands
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{assert_error, config::Config, expect, vec_deq, verify_expectations};
+    use ::std::{
+        cell::RefCell,
+        collections::VecDeque,
+        io::{Error, ErrorKind},
+    };
+
+    struct TestExpectations {
+        process_oneshot: VecDeque<(&'static str, Result<(), Error>)>,
+    }
+
+    impl TestExpectations {
+        fn new() -> Self {
+            TestExpectations {
+                process_oneshot: vec_deq![],
+            }
+        }
+    }
+
+    thread_local! { static TEST_EXPECTATIONS: RefCell<TestExpectations> = RefCell::new(TestExpectations::new()) }
+
+    fn verify_expectations() {
+        verify_expectations!(
+            crate::process::Process::oneshot => TEST_EXPECTATIONS::process_oneshot,
+        );
+    }
+
+    pub mod process {
+        use super::TEST_EXPECTATIONS;
+        use crate::verify_expectation;
+        use ::std::{ffi::OsStr, io::Result};
+
+        pub struct Process {}
+
+        impl Process {
+            pub fn oneshot<C: AsRef<OsStr>, I: IntoIterator<Item = S>, S: AsRef<OsStr>>(
+                command: C,
+                arguments: I,
+            ) -> Result<()> {
+                let mut command = command.as_ref().to_string_lossy().to_string();
+                for argument in arguments {
+                    command.push(' ');
+                    command.push_str(&argument.as_ref().to_string_lossy());
+                }
+
+                verify_expectation!(TEST_EXPECTATIONS::process_oneshot => crate::process::Process::oneshot { command })
+            }
+        }
+    }
+
+    macro_rules! error {
+        ($msg:expr) => {{
+            Err(Error::new(ErrorKind::Other, format!("{}", $msg)))
+        }};
+    }
+
+    fn create_config() -> Config {
+        Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              firewall:
+                guest_address: 192.168.122.10
+                allow_from: [ 10.0.0.0/8, 192.168.1.5 ]
+                forward:
+                - host_port: 2222
+                  guest_port: 22
+
+            qemu:
+            - enable-kvm
+            ",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn table_name_replaces_characters_not_allowed_in_identifiers() {
+        assert_eq!("qemu_launcher_my_vm_1", super::table_name("my-vm.1"));
+    }
+
+    #[test]
+    fn apply_translates_forwards_into_nft_rules() {
+        expect!(
+            TEST_EXPECTATIONS::process_oneshot:
+            { "nft add table ip qemu_launcher_vm" => Ok({}) },
+            { "nft flush table ip qemu_launcher_vm" => Ok({}) },
+            { "nft add chain ip qemu_launcher_vm prerouting { type nat hook prerouting priority -100; }" => Ok({}) },
+            { "nft add chain ip qemu_launcher_vm forward { type filter hook forward priority 0; }" => Ok({}) },
+            { "nft add rule ip qemu_launcher_vm prerouting ip saddr { 10.0.0.0/8, 192.168.1.5 } \
+                tcp dport 2222 dnat to 192.168.122.10:22" => Ok({}) },
+            { "nft add rule ip qemu_launcher_vm forward ip daddr 192.168.122.10 \
+                tcp dport 22 ct status dnat accept" => Ok({}) },
+        );
+
+        assert!(super::apply("qemu_launcher_vm", create_config().get_firewall().unwrap()).is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn apply_returns_error_if_nft_command_fails() {
+        expect!(
+            TEST_EXPECTATIONS::process_oneshot:
+            { "nft add table ip qemu_launcher_vm" => error!("Process::oneshot()") },
+        );
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to apply the `add table ip qemu_launcher_vm` firewall rule: Process::oneshot()",
+            super::apply("qemu_launcher_vm", create_config().get_firewall().unwrap())
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn remove_deletes_the_machine_table() {
+        expect!(
+            TEST_EXPECTATIONS::process_oneshot:
+            { "nft delete table ip qemu_launcher_vm" => Ok({}) },
+        );
+
+        assert!(super::remove("qemu_launcher_vm").is_ok());
+
+        verify_expectations();
+    }
+}
@@ -0,0 +1,1185 @@
+use crate::cpuset::parse_cpus_list;
+#[cfg(not(test))]
+use std::fs;
+use std::{
+    io::{Error, ErrorKind},
+    time::Duration,
+};
+#[cfg(test)]
+use test::std::fs;
+
+const CGROUP2_PATH: &str = "/sys/fs/cgroup";
+const CGROUPS_PATH: &str = "/proc/cgroups";
+const CPU_PATH: &str = "/sys/devices/system/cpu";
+const CPU_VULNERABILITIES_PATH: &str = "/sys/devices/system/cpu/vulnerabilities";
+const HALT_POLL_NS_PATH: &str = "/sys/module/kvm/parameters/halt_poll_ns";
+const IO_URING_DISABLED_PATH: &str = "/proc/sys/kernel/io_uring_disabled";
+const IO_URING_GROUP_PATH: &str = "/proc/sys/kernel/io_uring_group";
+const KSM_RUN_PATH: &str = "/sys/kernel/mm/ksm/run";
+const OSRELEASE_PATH: &str = "/proc/sys/kernel/osrelease";
+const ISOLATED_CPUS_PATH: &str = "/sys/devices/system/cpu/isolated";
+const MOUNTS_PATH: &str = "/proc/self/mounts";
+const NOHZ_FULL_CPUS_PATH: &str = "/sys/devices/system/cpu/nohz_full";
+const ONLINE_CPUS_PATH: &str = "/sys/devices/system/cpu/online";
+const PRESENT_CPUS_PATH: &str = "/sys/devices/system/cpu/present";
+const PROC_PATH: &str = "/proc";
+const PROC_SELF_STATUS_PATH: &str = "/proc/self/status";
+const RT_PERIOD_PATH: &str = "/proc/sys/kernel/sched_rt_period_us";
+const RT_RUNTIME_PATH: &str = "/proc/sys/kernel/sched_rt_runtime_us";
+const THERMAL_PATH: &str = "/sys/class/thermal";
+const UPTIME_PATH: &str = "/proc/uptime";
+const THP_ENABLED_PATH: &str = "/sys/kernel/mm/transparent_hugepage/enabled";
+// Numbers of the capabilities the launcher might need, as in `linux/capability.h`
+const CAPABILITIES: &[(&str, u32)] = &[
+    ("CAP_CHOWN", 0),
+    ("CAP_DAC_OVERRIDE", 1),
+    ("CAP_SETGID", 6),
+    ("CAP_SETUID", 7),
+    ("CAP_NET_BIND_SERVICE", 10),
+    ("CAP_NET_ADMIN", 12),
+    ("CAP_SYS_ADMIN", 21),
+    ("CAP_SYS_NICE", 23),
+    ("CAP_SYS_RESOURCE", 24),
+];
+
+#[derive(Debug, PartialEq)]
+pub enum IoUring {
+    Available,
+    // Only the members of the group, if any, and `CAP_SYS_ADMIN` holders may create rings
+    Restricted(Option<u32>),
+    Disabled,
+    // The kernel predates io_uring, which appeared in 5.1
+    Unsupported(String),
+}
+
+pub struct KernelThread {
+    id: usize,
+    name: String,
+    policy: i32,
+    priority: i32,
+}
+
+impl KernelThread {
+    pub fn get_id(&self) -> usize {
+        self.id
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_policy(&self) -> i32 {
+        self.policy
+    }
+
+    pub fn get_priority(&self) -> i32 {
+        self.priority
+    }
+}
+
+pub fn get_online_cpus() -> Result<Vec<usize>, Error> {
+    match fs::read_to_string(ONLINE_CPUS_PATH) {
+        Ok(cpus) => Ok(parse_cpus_list(cpus.trim())),
+        Err(e) => Err(Error::new(
+            e.kind(),
+            format!(
+                "Failed to read the list of online host CPUs from `{}`: {}",
+                ONLINE_CPUS_PATH, e
+            ),
+        )),
+    }
+}
+
+pub fn get_present_cpus() -> Result<Vec<usize>, Error> {
+    match fs::read_to_string(PRESENT_CPUS_PATH) {
+        Ok(cpus) => Ok(parse_cpus_list(cpus.trim())),
+        Err(e) => Err(Error::new(
+            e.kind(),
+            format!(
+                "Failed to read the list of present host CPUs from `{}`: {}",
+                PRESENT_CPUS_PATH, e
+            ),
+        )),
+    }
+}
+
+// Logical CPUs sharing a core with the given one, including itself
+pub fn get_thread_siblings(cpu: usize) -> Result<Vec<usize>, Error> {
+    let path = format!("{}/cpu{}/topology/thread_siblings_list", CPU_PATH, cpu);
+
+    match fs::read_to_string(&path) {
+        Ok(siblings) => Ok(parse_cpus_list(siblings.trim())),
+        Err(e) => Err(Error::new(
+            e.kind(),
+            format!(
+                "Failed to read the SMT siblings of the host CPU `{}` from `{}`: {}",
+                cpu, path, e
+            ),
+        )),
+    }
+}
+
+// The state of every CPU vulnerability known to the kernel, sorted by its name
+pub fn get_cpu_vulnerabilities() -> Result<Vec<(String, String)>, Error> {
+    let entries = match fs::read_dir(CPU_VULNERABILITIES_PATH) {
+        Ok(entries) => entries,
+        Err(e) => {
+            return Err(Error::new(
+                e.kind(),
+                format!("Failed to list `{}`: {}", CPU_VULNERABILITIES_PATH, e),
+            ))
+        }
+    };
+
+    let mut vulnerabilities = vec![];
+    for entry in entries {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        let path = format!("{}/{}", CPU_VULNERABILITIES_PATH, name);
+
+        match fs::read_to_string(&path) {
+            Ok(state) => vulnerabilities.push((name, state.trim().to_owned())),
+            Err(e) => {
+                return Err(Error::new(
+                    e.kind(),
+                    format!(
+                        "Failed to read the state of the CPU vulnerability from `{}`: {}",
+                        path, e
+                    ),
+                ))
+            }
+        }
+    }
+
+    vulnerabilities.sort();
+
+    Ok(vulnerabilities)
+}
+
+pub fn is_ksm_running() -> Result<bool, Error> {
+    match fs::read_to_string(KSM_RUN_PATH) {
+        Ok(run) => Ok(run.trim() == "1"),
+        Err(e) => Err(Error::new(
+            e.kind(),
+            format!(
+                "Failed to read the KSM state from `{}`: {}",
+                KSM_RUN_PATH, e
+            ),
+        )),
+    }
+}
+
+pub fn get_thp_policy() -> Result<String, Error> {
+    let policies = match fs::read_to_string(THP_ENABLED_PATH) {
+        Ok(policies) => policies,
+        Err(e) => {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to read the transparent hugepage policy from `{}`: {}",
+                    THP_ENABLED_PATH, e
+                ),
+            ))
+        }
+    };
+
+    // The active policy is enclosed in brackets, e.g. `always [madvise] never`
+    match policies
+        .split_whitespace()
+        .find(|policy| policy.starts_with('[') && policy.ends_with(']'))
+    {
+        Some(policy) => Ok(policy[1..policy.len() - 1].to_string()),
+        None => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse the transparent hugepage policy from `{}`: `{}`",
+                THP_ENABLED_PATH,
+                policies.trim()
+            ),
+        )),
+    }
+}
+
+// Unlike the monotonic clock, the uptime keeps counting while the host is suspended
+pub fn get_uptime() -> Result<Duration, Error> {
+    let uptime = match fs::read_to_string(UPTIME_PATH) {
+        Ok(uptime) => uptime,
+        Err(e) => {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to read the host uptime from `{}`: {}",
+                    UPTIME_PATH, e
+                ),
+            ))
+        }
+    };
+
+    match uptime
+        .split_whitespace()
+        .next()
+        .and_then(|seconds| seconds.parse::<f64>().ok())
+    {
+        Some(seconds) if seconds >= 0.0 => Ok(Duration::from_secs_f64(seconds)),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse the host uptime from `{}`: `{}`",
+                UPTIME_PATH,
+                uptime.trim()
+            ),
+        )),
+    }
+}
+
+// The temperature of the thermal zone in millidegrees Celsius
+pub fn get_thermal_zone_temperature(zone: &str) -> Result<i64, Error> {
+    let path = format!("{}/{}/temp", THERMAL_PATH, zone);
+
+    let temperature = match fs::read_to_string(&path) {
+        Ok(temperature) => temperature,
+        Err(e) => {
+            return Err(Error::new(
+                e.kind(),
+                format!("Failed to read the temperature from `{}`: {}", path, e),
+            ))
+        }
+    };
+
+    match temperature.trim().parse::<i64>() {
+        Ok(temperature) => Ok(temperature),
+        Err(_) => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse the temperature from `{}`: `{}`",
+                path,
+                temperature.trim()
+            ),
+        )),
+    }
+}
+
+// CPU time consumed by the tasks of the cgroup v2 the process belongs to, in microseconds
+pub fn get_cgroup_cpu_usage(pid: i32) -> Result<u64, Error> {
+    let path = format!("{}/{}/cgroup", PROC_PATH, pid);
+
+    let cgroups = match fs::read_to_string(&path) {
+        Ok(cgroups) => cgroups,
+        Err(e) => {
+            return Err(Error::new(
+                e.kind(),
+                format!("Failed to read the cgroups from `{}`: {}", path, e),
+            ))
+        }
+    };
+
+    // The unified hierarchy is the one with the `0` ID and no controllers listed
+    let cgroup = match cgroups.lines().find_map(|line| line.strip_prefix("0::")) {
+        Some(cgroup) => cgroup.trim_end_matches('/'),
+        None => {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "The process `{}` does not belong to a cgroup v2 hierarchy.",
+                    pid
+                ),
+            ))
+        }
+    };
+
+    let path = format!("{}{}/cpu.stat", CGROUP2_PATH, cgroup);
+
+    let stat = match fs::read_to_string(&path) {
+        Ok(stat) => stat,
+        Err(e) => {
+            return Err(Error::new(
+                e.kind(),
+                format!("Failed to read the CPU usage from `{}`: {}", path, e),
+            ))
+        }
+    };
+
+    match stat
+        .lines()
+        .find_map(|line| line.strip_prefix("usage_usec "))
+        .and_then(|usage| usage.trim().parse::<u64>().ok())
+    {
+        Some(usage) => Ok(usage),
+        None => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Failed to parse the CPU usage from `{}`.", path),
+        )),
+    }
+}
+
+// vhost workers are named after the owning process, they are threads of the owner since Linux 6.4
+// and kernel threads before that. The IDs are sorted, i.e. in the order the workers were created.
+pub fn get_vhost_workers(pid: i32) -> Result<Vec<usize>, Error> {
+    let name = format!("vhost-{}", pid);
+
+    let mut workers = find_tasks_by_name(&format!("{}/{}/task", PROC_PATH, pid), &name)?;
+    if workers.len() < 1 {
+        workers = find_tasks_by_name(PROC_PATH, &name)?;
+    }
+
+    workers.sort();
+
+    Ok(workers)
+}
+
+// Per-CPU kernel threads are named after the CPU they are bound to, e.g. `ksoftirqd/3`. Their
+// scheduling policy and real-time priority are read from the `stat` file, sorted by their IDs.
+pub fn get_per_cpu_kernel_threads(
+    names: &[&str],
+    cpus: &[usize],
+) -> Result<Vec<KernelThread>, Error> {
+    let entries = match fs::read_dir(PROC_PATH) {
+        Ok(entries) => entries,
+        Err(e) => {
+            return Err(Error::new(
+                e.kind(),
+                format!("Failed to list `{}`: {}", PROC_PATH, e),
+            ))
+        }
+    };
+
+    let mut threads = vec![];
+    for entry in entries {
+        let id = match entry?.file_name().to_string_lossy().parse::<usize>() {
+            Ok(id) => id,
+            Err(_) => continue,
+        };
+
+        // The task might have exited since the directory was listed
+        let name = match fs::read_to_string(format!("{}/{}/comm", PROC_PATH, id)) {
+            Ok(comm) => comm.trim().to_owned(),
+            Err(_) => continue,
+        };
+
+        let bound = match name.split_once('/') {
+            Some((prefix, cpu)) => {
+                names.contains(&prefix) && cpu.parse().map_or(false, |cpu| cpus.contains(&cpu))
+            }
+            None => false,
+        };
+
+        if !bound {
+            continue;
+        }
+
+        let stat = match fs::read_to_string(format!("{}/{}/stat", PROC_PATH, id)) {
+            Ok(stat) => stat,
+            Err(_) => continue,
+        };
+
+        // The fields following the parenthesized name start with the third one, `state`, so the
+        // 40th and the 41st, `rt_priority` and `policy`, are the 37th and the 38th
+        let fields: Vec<&str> = match stat.rfind(')') {
+            Some(end) => stat[end + 1..].split_whitespace().collect(),
+            None => vec![],
+        };
+
+        match (
+            fields.get(37).and_then(|priority| priority.parse().ok()),
+            fields.get(38).and_then(|policy| policy.parse().ok()),
+        ) {
+            (Some(priority), Some(policy)) => threads.push(KernelThread {
+                id,
+                name,
+                policy,
+                priority,
+            }),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Failed to parse `{}/{}/stat`: `{}`",
+                        PROC_PATH,
+                        id,
+                        stat.trim()
+                    ),
+                ))
+            }
+        }
+    }
+
+    threads.sort_by_key(|thread| thread.id);
+
+    Ok(threads)
+}
+
+// Threads of the process, sorted by their IDs
+pub fn get_tasks(pid: i32) -> Result<Vec<usize>, Error> {
+    let directory = format!("{}/{}/task", PROC_PATH, pid);
+
+    let entries = match fs::read_dir(&directory) {
+        Ok(entries) => entries,
+        Err(e) => {
+            return Err(Error::new(
+                e.kind(),
+                format!("Failed to list `{}`: {}", directory, e),
+            ))
+        }
+    };
+
+    let mut tasks = vec![];
+    for entry in entries {
+        if let Ok(id) = entry?.file_name().to_string_lossy().parse::<usize>() {
+            tasks.push(id);
+        }
+    }
+
+    tasks.sort();
+
+    Ok(tasks)
+}
+
+fn find_tasks_by_name(directory: &str, name: &str) -> Result<Vec<usize>, Error> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) => {
+            return Err(Error::new(
+                e.kind(),
+                format!("Failed to list `{}`: {}", directory, e),
+            ))
+        }
+    };
+
+    let mut tasks = vec![];
+    for entry in entries {
+        let id = match entry?.file_name().to_string_lossy().parse::<usize>() {
+            Ok(id) => id,
+            Err(_) => continue,
+        };
+
+        // The task might have exited since the directory was listed
+        if let Ok(comm) = fs::read_to_string(format!("{}/{}/comm", directory, id)) {
+            if comm.trim() == name {
+                tasks.push(id);
+            }
+        }
+    }
+
+    Ok(tasks)
+}
+
+// Capabilities are numbered as in `linux/capability.h`, e.g. 21 for `CAP_SYS_ADMIN`
+pub fn has_effective_capability(capability: u32) -> Result<bool, Error> {
+    Ok(get_effective_capabilities()? & (1 << capability) != 0)
+}
+
+// Names the capabilities, e.g. `CAP_SYS_NICE`, which are not in the effective set of the launcher
+pub fn get_missing_capabilities<'a>(names: &[&'a str]) -> Result<Vec<&'a str>, Error> {
+    let mask = get_effective_capabilities()?;
+    let mut missing = vec![];
+
+    for name in names {
+        let capability = match CAPABILITIES.iter().find(|(known, _)| known == name) {
+            Some((_, capability)) => capability,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Unknown capability `{}`.", name),
+                ))
+            }
+        };
+
+        if mask & (1 << capability) == 0 && !missing.contains(name) {
+            missing.push(*name);
+        }
+    }
+
+    Ok(missing)
+}
+
+fn get_effective_capabilities() -> Result<u64, Error> {
+    let status = match fs::read_to_string(PROC_SELF_STATUS_PATH) {
+        Ok(status) => status,
+        Err(e) => {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to read the launcher capabilities from `{}`: {}",
+                    PROC_SELF_STATUS_PATH, e
+                ),
+            ))
+        }
+    };
+
+    let mask = status
+        .lines()
+        .find(|line| line.starts_with("CapEff:"))
+        .and_then(|line| u64::from_str_radix(line["CapEff:".len()..].trim(), 16).ok());
+
+    match mask {
+        Some(mask) => Ok(mask),
+        None => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Failed to parse the effective capabilities from `{}`.",
+                PROC_SELF_STATUS_PATH
+            ),
+        )),
+    }
+}
+
+pub fn get_io_uring_support() -> Result<IoUring, Error> {
+    let release = read_sysctl(OSRELEASE_PATH, "the host kernel release")?;
+    let version: Vec<u32> = release
+        .split(|c: char| !c.is_ascii_digit())
+        .take(2)
+        .filter_map(|number| number.parse().ok())
+        .collect();
+
+    if version.len() < 2 || (version[0], version[1]) < (5, 1) {
+        return Ok(IoUring::Unsupported(release));
+    }
+
+    // The knob only exists since 6.6, io_uring is always available on older kernels
+    let disabled = match read_sysctl(IO_URING_DISABLED_PATH, "the io_uring state") {
+        Ok(disabled) => disabled,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(IoUring::Available),
+        Err(e) => return Err(e),
+    };
+
+    match disabled.as_str() {
+        "0" => Ok(IoUring::Available),
+        "1" => {
+            let group = read_sysctl(IO_URING_GROUP_PATH, "the io_uring group")?;
+
+            Ok(IoUring::Restricted(group.parse().ok()))
+        }
+        _ => Ok(IoUring::Disabled),
+    }
+}
+
+fn read_sysctl(path: &str, description: &str) -> Result<String, Error> {
+    match fs::read_to_string(path) {
+        Ok(value) => Ok(value.trim().to_string()),
+        Err(e) => Err(Error::new(
+            e.kind(),
+            format!("Failed to read {} from `{}`: {}", description, path, e),
+        )),
+    }
+}
+
+// Describes the host kernel facilities the launcher relies on, a facility which can not be
+// inspected is described by the error instead
+pub fn describe_kernel_facilities() -> Vec<(&'static str, String)> {
+    let describe = |facility: Result<String, Error>| match facility {
+        Ok(description) => description,
+        Err(e) => format!("unknown, {}", e),
+    };
+
+    vec![
+        ("cgroup hierarchy", describe(describe_cgroup_hierarchy())),
+        ("cpuset controller", describe(describe_cpuset_controller())),
+        ("KVM halt_poll_ns", describe(describe_halt_poll_ns())),
+        ("transparent hugepages", describe(get_thp_policy())),
+        (
+            "isolated CPUs",
+            describe(describe_cpu_list(
+                ISOLATED_CPUS_PATH,
+                "the isolated host CPUs",
+            )),
+        ),
+        (
+            "nohz_full CPUs",
+            describe(describe_cpu_list(
+                NOHZ_FULL_CPUS_PATH,
+                "the nohz_full host CPUs",
+            )),
+        ),
+        ("real-time throttling", describe(describe_rt_throttling())),
+    ]
+}
+
+fn describe_cgroup_hierarchy() -> Result<String, Error> {
+    let mounts = read_sysctl(MOUNTS_PATH, "the mounted filesystems")?;
+    let types: Vec<&str> = mounts
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(2))
+        .collect();
+
+    Ok(String::from(
+        match (types.contains(&"cgroup"), types.contains(&"cgroup2")) {
+            (true, true) => "hybrid, v1 controllers alongside v2",
+            (true, false) => "v1",
+            (false, true) => "v2, unified",
+            (false, false) => "not mounted",
+        },
+    ))
+}
+
+fn describe_cpuset_controller() -> Result<String, Error> {
+    let cgroups = read_sysctl(CGROUPS_PATH, "the cgroup controllers")?;
+
+    // `#subsys_name hierarchy num_cgroups enabled`
+    let enabled = cgroups
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+        .find(|fields| fields.first() == Some(&"cpuset"))
+        .map(|fields| fields.get(3) == Some(&"1"));
+
+    Ok(String::from(match enabled {
+        Some(true) => "available",
+        Some(false) => "disabled",
+        None => "not built into the kernel",
+    }))
+}
+
+fn describe_halt_poll_ns() -> Result<String, Error> {
+    match read_sysctl(HALT_POLL_NS_PATH, "the KVM halt polling interval") {
+        Ok(interval) => Ok(format!("{} ns", interval)),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            Ok(String::from("the KVM module is not loaded"))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn describe_cpu_list(path: &str, description: &str) -> Result<String, Error> {
+    match read_sysctl(path, description)?.as_str() {
+        "" | "(null)" => Ok(String::from("none")),
+        cpus => Ok(cpus.to_string()),
+    }
+}
+
+fn describe_rt_throttling() -> Result<String, Error> {
+    let runtime = read_sysctl(RT_RUNTIME_PATH, "the real-time runtime")?;
+    if runtime == "-1" {
+        return Ok(String::from("disabled"));
+    }
+
+    let period = read_sysctl(RT_PERIOD_PATH, "the real-time period")?;
+
+    Ok(format!("{} us of every {} us", runtime, period))
+}
+
+pub fn get_offline_cpus(cpus: &[usize]) -> Result<Vec<usize>, Error> {
+    let online = get_online_cpus()?;
+
+    Ok(cpus
+        .iter()
+        .filter(|cpu| !online.contains(cpu))
+        .cloned()
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        describe_kernel_facilities, get_cgroup_cpu_usage, get_cpu_vulnerabilities,
+        get_io_uring_support, get_missing_capabilities, get_offline_cpus, get_online_cpus,
+        get_per_cpu_kernel_threads, get_present_cpus, get_tasks, get_thermal_zone_temperature,
+        get_thp_policy, get_thread_siblings, get_uptime, get_vhost_workers,
+        has_effective_capability, is_ksm_running, IoUring,
+    };
+    use crate::{assert_error, expect, vec_deq, verify_expectations};
+    use ::std::{
+        cell::RefCell,
+        collections::VecDeque,
+        io::{Error, ErrorKind},
+    };
+
+    struct TestExpectations {
+        std_fs_read_dir: VecDeque<(&'static str, Result<Vec<&'static str>, Error>)>,
+        std_fs_read_to_string: VecDeque<(&'static str, Result<String, Error>)>,
+    }
+
+    impl TestExpectations {
+        fn new() -> Self {
+            TestExpectations {
+                std_fs_read_dir: vec_deq![],
+                std_fs_read_to_string: vec_deq![],
+            }
+        }
+    }
+
+    thread_local! { static TEST_EXPECTATIONS: RefCell<TestExpectations> = RefCell::new(TestExpectations::new()) }
+
+    fn verify_expectations() {
+        verify_expectations!(
+            std::fs::read_dir => TEST_EXPECTATIONS::std_fs_read_dir,
+            std::fs::read_to_string => TEST_EXPECTATIONS::std_fs_read_to_string,
+        );
+    }
+
+    pub mod std {
+        pub mod fs {
+            use super::super::TEST_EXPECTATIONS;
+            use crate::verify_expectation;
+            use ::std::{ffi::OsString, io::Result, path::Path};
+
+            pub struct DirEntry {
+                name: &'static str,
+            }
+
+            impl DirEntry {
+                pub fn file_name(&self) -> OsString {
+                    OsString::from(self.name)
+                }
+            }
+
+            pub fn read_dir<P: AsRef<Path>>(path: P) -> Result<Vec<Result<DirEntry>>> {
+                let path = path.as_ref().to_str().unwrap();
+                let entries: Vec<&'static str> = verify_expectation!(TEST_EXPECTATIONS::std_fs_read_dir => std::fs::read_dir { path })?;
+
+                Ok(entries
+                    .into_iter()
+                    .map(|name| Ok(DirEntry { name }))
+                    .collect())
+            }
+
+            pub fn read_to_string<P: AsRef<Path>>(path: P) -> Result<String> {
+                let path = path.as_ref().to_str().unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_read_to_string => std::fs::read_to_string { path })
+            }
+        }
+    }
+
+    #[test]
+    fn get_online_cpus_parses_the_sysfs_cpu_list() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/sys/devices/system/cpu/online" => Ok("0-2,5\n".to_string()) },
+        );
+
+        assert_eq!(vec![0, 1, 2, 5], get_online_cpus().unwrap());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn get_thread_siblings_parses_the_sysfs_cpu_list() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/sys/devices/system/cpu/cpu2/topology/thread_siblings_list" => Ok("2,6\n".to_string()) },
+            { "/sys/devices/system/cpu/cpu3/topology/thread_siblings_list" =>
+                Err(Error::new(ErrorKind::NotFound, "std::fs::read_to_string()")) },
+        );
+
+        assert_eq!(vec![2, 6], get_thread_siblings(2).unwrap());
+        assert_error!(
+            ErrorKind::NotFound,
+            "Failed to read the SMT siblings of the host CPU `3` from \
+            `/sys/devices/system/cpu/cpu3/topology/thread_siblings_list`: std::fs::read_to_string()",
+            get_thread_siblings(3)
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn get_cpu_vulnerabilities_reads_the_state_of_every_vulnerability() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_dir:
+            { "/sys/devices/system/cpu/vulnerabilities" => Ok(vec!["mds", "l1tf"]) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/sys/devices/system/cpu/vulnerabilities/mds" =>
+                Ok("Mitigation: Clear CPU buffers; SMT vulnerable\n".to_string()) },
+            { "/sys/devices/system/cpu/vulnerabilities/l1tf" => Ok("Not affected\n".to_string()) },
+        );
+
+        assert_eq!(
+            vec![
+                (String::from("l1tf"), String::from("Not affected")),
+                (
+                    String::from("mds"),
+                    String::from("Mitigation: Clear CPU buffers; SMT vulnerable")
+                ),
+            ],
+            get_cpu_vulnerabilities().unwrap()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn get_online_cpus_returns_error_if_unable_to_read_sysfs() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/sys/devices/system/cpu/online" =>
+                Err(Error::new(ErrorKind::NotFound, "std::fs::read_to_string()")) },
+        );
+
+        assert_error!(
+            ErrorKind::NotFound,
+            "Failed to read the list of online host CPUs from \
+            `/sys/devices/system/cpu/online`: std::fs::read_to_string()",
+            get_online_cpus()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn get_uptime_parses_the_first_proc_uptime_field() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/uptime" => Ok("350735.47 234388.90\n".to_string()) },
+            { "/proc/uptime" => Ok("\n".to_string()) },
+        );
+
+        assert_eq!(
+            ::std::time::Duration::from_millis(350735470),
+            get_uptime().unwrap()
+        );
+        assert_error!(
+            ErrorKind::InvalidData,
+            "Failed to parse the host uptime from `/proc/uptime`: ``",
+            get_uptime()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn get_tasks_lists_sorted_threads_of_the_process() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_dir:
+            { "/proc/300/task" => Ok(vec!["300", "312", "301"]) },
+            { "/proc/301/task" => Err(Error::new(ErrorKind::NotFound, "std::fs::read_dir()")) },
+        );
+
+        assert_eq!(vec![300, 301, 312], get_tasks(300).unwrap());
+        assert_error!(
+            ErrorKind::NotFound,
+            "Failed to list `/proc/301/task`: std::fs::read_dir()",
+            get_tasks(301)
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn get_thermal_zone_temperature_reads_millidegrees() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/sys/class/thermal/thermal_zone0/temp" => Ok("87500\n".to_string()) },
+            { "/sys/class/thermal/thermal_zone1/temp" => Ok("N/A\n".to_string()) },
+        );
+
+        assert_eq!(
+            87500,
+            get_thermal_zone_temperature("thermal_zone0").unwrap()
+        );
+        assert_error!(
+            ErrorKind::InvalidData,
+            "Failed to parse the temperature from `/sys/class/thermal/thermal_zone1/temp`: `N/A`",
+            get_thermal_zone_temperature("thermal_zone1")
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn get_cgroup_cpu_usage_reads_the_unified_hierarchy_usage() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/300/cgroup" =>
+                Ok("1:name=systemd:/machine.slice/machine-foo.scope\n0::/machine.slice/machine-foo.scope\n".to_string()) },
+            { "/sys/fs/cgroup/machine.slice/machine-foo.scope/cpu.stat" =>
+                Ok("usage_usec 81234567\nuser_usec 61234567\nsystem_usec 20000000\n".to_string()) },
+            { "/proc/301/cgroup" => Ok("0::/\n".to_string()) },
+            { "/sys/fs/cgroup/cpu.stat" => Ok("user_usec 100\n".to_string()) },
+            { "/proc/302/cgroup" => Ok("4:cpuset:/\n".to_string()) },
+        );
+
+        assert_eq!(81234567, get_cgroup_cpu_usage(300).unwrap());
+        assert_error!(
+            ErrorKind::InvalidData,
+            "Failed to parse the CPU usage from `/sys/fs/cgroup/cpu.stat`.",
+            get_cgroup_cpu_usage(301)
+        );
+        assert_error!(
+            ErrorKind::NotFound,
+            "The process `302` does not belong to a cgroup v2 hierarchy.",
+            get_cgroup_cpu_usage(302)
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn has_effective_capability_checks_the_effective_set() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/status" =>
+                Ok("Name:\tqemu-launcher\nCapPrm:\t000001ffffffffff\nCapEff:\t0000000000200000\n".to_string()) },
+            { "/proc/self/status" => Ok("Name:\tqemu-launcher\nCapEff:\t0000000000000000\n".to_string()) },
+            { "/proc/self/status" => Ok("Name:\tqemu-launcher\n".to_string()) },
+        );
+
+        assert!(has_effective_capability(21).unwrap());
+        assert!(!has_effective_capability(21).unwrap());
+        assert_error!(
+            ErrorKind::InvalidData,
+            "Failed to parse the effective capabilities from `/proc/self/status`.",
+            has_effective_capability(21)
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn get_missing_capabilities_names_capabilities_outside_the_effective_set() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/status" => Ok("Name:\tqemu-launcher\nCapEff:\t0000000000a00000\n".to_string()) },
+            { "/proc/self/status" => Ok("Name:\tqemu-launcher\nCapEff:\t0000000000000000\n".to_string()) },
+        );
+
+        assert_eq!(
+            vec!["CAP_SYS_RESOURCE"],
+            get_missing_capabilities(&["CAP_SYS_ADMIN", "CAP_SYS_NICE", "CAP_SYS_RESOURCE"])
+                .unwrap()
+        );
+        assert_error!(
+            ErrorKind::InvalidInput,
+            "Unknown capability `CAP_BPF`.",
+            get_missing_capabilities(&["CAP_BPF"])
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn get_present_cpus_parses_the_sysfs_cpu_list() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/sys/devices/system/cpu/present" => Ok("0-7\n".to_string()) },
+        );
+
+        assert_eq!(vec![0, 1, 2, 3, 4, 5, 6, 7], get_present_cpus().unwrap());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn is_ksm_running_checks_the_sysfs_run_state() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/sys/kernel/mm/ksm/run" => Ok("1\n".to_string()) },
+            { "/sys/kernel/mm/ksm/run" => Ok("0\n".to_string()) },
+            { "/sys/kernel/mm/ksm/run" =>
+                Err(Error::new(ErrorKind::NotFound, "std::fs::read_to_string()")) },
+        );
+
+        assert!(is_ksm_running().unwrap());
+        assert!(!is_ksm_running().unwrap());
+        assert_error!(
+            ErrorKind::NotFound,
+            "Failed to read the KSM state from `/sys/kernel/mm/ksm/run`: std::fs::read_to_string()",
+            is_ksm_running()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn get_thp_policy_returns_the_active_policy() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/sys/kernel/mm/transparent_hugepage/enabled" =>
+                Ok("always [madvise] never\n".to_string()) },
+            { "/sys/kernel/mm/transparent_hugepage/enabled" => Ok("always madvise never\n".to_string()) },
+        );
+
+        assert_eq!("madvise", get_thp_policy().unwrap());
+        assert_error!(
+            ErrorKind::InvalidData,
+            "Failed to parse the transparent hugepage policy from \
+            `/sys/kernel/mm/transparent_hugepage/enabled`: `always madvise never`",
+            get_thp_policy()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn describe_kernel_facilities_reports_every_facility() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/self/mounts" => Ok("sysfs /sys sysfs rw 0 0\n\
+                cgroup2 /sys/fs/cgroup cgroup2 rw,nosuid 0 0\n".to_string()) },
+            { "/proc/cgroups" => Ok("#subsys_name\thierarchy\tnum_cgroups\tenabled\n\
+                cpuset\t0\t92\t1\ncpu\t0\t92\t1\n".to_string()) },
+            { "/sys/module/kvm/parameters/halt_poll_ns" =>
+                Err(Error::new(ErrorKind::NotFound, "std::fs::read_to_string()")) },
+            { "/sys/kernel/mm/transparent_hugepage/enabled" =>
+                Err(Error::new(ErrorKind::PermissionDenied, "std::fs::read_to_string()")) },
+            { "/sys/devices/system/cpu/isolated" => Ok("2-5\n".to_string()) },
+            { "/sys/devices/system/cpu/nohz_full" => Ok("(null)\n".to_string()) },
+            { "/proc/sys/kernel/sched_rt_runtime_us" => Ok("950000\n".to_string()) },
+            { "/proc/sys/kernel/sched_rt_period_us" => Ok("1000000\n".to_string()) },
+        );
+
+        assert_eq!(
+            vec![
+                ("cgroup hierarchy", String::from("v2, unified")),
+                ("cpuset controller", String::from("available")),
+                (
+                    "KVM halt_poll_ns",
+                    String::from("the KVM module is not loaded")
+                ),
+                (
+                    "transparent hugepages",
+                    String::from(
+                        "unknown, Failed to read the transparent hugepage policy from \
+                        `/sys/kernel/mm/transparent_hugepage/enabled`: std::fs::read_to_string()"
+                    )
+                ),
+                ("isolated CPUs", String::from("2-5")),
+                ("nohz_full CPUs", String::from("none")),
+                (
+                    "real-time throttling",
+                    String::from("950000 us of every 1000000 us")
+                ),
+            ],
+            describe_kernel_facilities()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn get_io_uring_support_checks_the_kernel_release_and_sysctls() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/sys/kernel/osrelease" => Ok("4.19.0-27-amd64\n".to_string()) },
+            { "/proc/sys/kernel/osrelease" => Ok("5.15.0-91-generic\n".to_string()) },
+            { "/proc/sys/kernel/io_uring_disabled" =>
+                Err(Error::new(ErrorKind::NotFound, "std::fs::read_to_string()")) },
+            { "/proc/sys/kernel/osrelease" => Ok("6.8.0-45-generic\n".to_string()) },
+            { "/proc/sys/kernel/io_uring_disabled" => Ok("1\n".to_string()) },
+            { "/proc/sys/kernel/io_uring_group" => Ok("-1\n".to_string()) },
+            { "/proc/sys/kernel/osrelease" => Ok("6.8.0-45-generic\n".to_string()) },
+            { "/proc/sys/kernel/io_uring_disabled" => Ok("1\n".to_string()) },
+            { "/proc/sys/kernel/io_uring_group" => Ok("36\n".to_string()) },
+            { "/proc/sys/kernel/osrelease" => Ok("6.8.0-45-generic\n".to_string()) },
+            { "/proc/sys/kernel/io_uring_disabled" => Ok("2\n".to_string()) },
+        );
+
+        assert_eq!(
+            IoUring::Unsupported("4.19.0-27-amd64".to_string()),
+            get_io_uring_support().unwrap()
+        );
+        assert_eq!(IoUring::Available, get_io_uring_support().unwrap());
+        assert_eq!(IoUring::Restricted(None), get_io_uring_support().unwrap());
+        assert_eq!(
+            IoUring::Restricted(Some(36)),
+            get_io_uring_support().unwrap()
+        );
+        assert_eq!(IoUring::Disabled, get_io_uring_support().unwrap());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn get_offline_cpus_returns_cpus_missing_from_the_online_list() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/sys/devices/system/cpu/online" => Ok("0-3,6-7".to_string()) },
+        );
+
+        assert_eq!(vec![4, 5], get_offline_cpus(&[1, 4, 5, 7]).unwrap());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn get_vhost_workers_finds_worker_threads_of_the_process() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_dir:
+            { "/proc/100/task" => Ok(vec!["100", "131", "102", "103"]) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/100/task/100/comm" => Ok("qemu-kvm\n".to_string()) },
+            { "/proc/100/task/131/comm" => Ok("vhost-100\n".to_string()) },
+            { "/proc/100/task/102/comm" => Err(Error::new(ErrorKind::NotFound, "std::fs::read_to_string()")) },
+            { "/proc/100/task/103/comm" => Ok("vhost-100\n".to_string()) },
+        );
+
+        assert_eq!(vec![103, 131], get_vhost_workers(100).unwrap());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn get_per_cpu_kernel_threads_finds_threads_bound_to_the_cpus() {
+        let stat = |name: &str, priority: u32, policy: u32| {
+            let mut fields = vec!["0"; 37];
+            fields[0] = "S";
+            Ok(format!(
+                "16 ({}) {} {} {} 0 0 0 0\n",
+                name,
+                fields.join(" "),
+                priority,
+                policy
+            ))
+        };
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_dir:
+            { "/proc" => Ok(vec!["self", "31", "16", "17", "40", "41"]) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/31/comm" => Ok("ksoftirqd/3\n".to_string()) },
+            { "/proc/31/stat" => stat("ksoftirqd/3", 0, 0) },
+            { "/proc/16/comm" => Ok("rcuc/2\n".to_string()) },
+            { "/proc/16/stat" => stat("rcuc/2", 1, 1) },
+            { "/proc/17/comm" => Ok("rcuc/1\n".to_string()) },
+            { "/proc/40/comm" => Ok("migration/2\n".to_string()) },
+            { "/proc/41/comm" => Err(Error::new(ErrorKind::NotFound, "std::fs::read_to_string()")) },
+        );
+
+        let threads: Vec<(usize, String, i32, i32)> =
+            get_per_cpu_kernel_threads(&["ksoftirqd", "rcuc"], &[2, 3])
+                .unwrap()
+                .iter()
+                .map(|t| {
+                    (
+                        t.get_id(),
+                        t.get_name().to_owned(),
+                        t.get_policy(),
+                        t.get_priority(),
+                    )
+                })
+                .collect();
+
+        assert_eq!(
+            vec![
+                (16, String::from("rcuc/2"), 1, 1),
+                (31, String::from("ksoftirqd/3"), 0, 0)
+            ],
+            threads
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn get_vhost_workers_falls_back_to_kernel_threads() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_dir:
+            { "/proc/200/task" => Ok(vec!["200"]) },
+            { "/proc" => Ok(vec!["self", "2", "201", "202"]) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/200/task/200/comm" => Ok("qemu-kvm\n".to_string()) },
+            { "/proc/2/comm" => Ok("kthreadd\n".to_string()) },
+            { "/proc/201/comm" => Ok("vhost-200\n".to_string()) },
+            { "/proc/202/comm" => Ok("vhost-2000\n".to_string()) },
+        );
+
+        assert_eq!(vec![201], get_vhost_workers(200).unwrap());
+
+        verify_expectations();
+    }
+}
@@ -0,0 +1,236 @@
+use crate::process::Process;
+use std::{
+    env,
+    ffi::OsStr,
+    fs::{self, File},
+    io::{Error, ErrorKind, Read},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+const ROUND_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const INITIAL_STATE: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+// A plain FIPS 180-4 SHA-256, the launcher only needs it to fingerprint the qemu binary
+struct Sha256 {
+    state: [u32; 8],
+    block: Vec<u8>,
+    length: u64,
+}
+
+impl Sha256 {
+    fn new() -> Self {
+        Sha256 {
+            state: INITIAL_STATE,
+            block: Vec::with_capacity(64),
+            length: 0,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.length += data.len() as u64;
+
+        for byte in data {
+            self.block.push(*byte);
+            if self.block.len() == 64 {
+                self.compress();
+                self.block.clear();
+            }
+        }
+    }
+
+    fn finish(mut self) -> String {
+        let bits = self.length * 8;
+
+        self.block.push(0x80);
+        if self.block.len() > 56 {
+            self.block.resize(64, 0);
+            self.compress();
+            self.block.clear();
+        }
+        self.block.resize(56, 0);
+        self.block.extend_from_slice(&bits.to_be_bytes());
+        self.compress();
+
+        self.state
+            .iter()
+            .map(|word| format!("{:08x}", word))
+            .collect()
+    }
+
+    fn compress(&mut self) {
+        let mut schedule = [0u32; 64];
+        for (index, chunk) in self.block.chunks(4).enumerate() {
+            schedule[index] = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        for index in 16..64 {
+            let s0 = schedule[index - 15].rotate_right(7)
+                ^ schedule[index - 15].rotate_right(18)
+                ^ (schedule[index - 15] >> 3);
+            let s1 = schedule[index - 2].rotate_right(17)
+                ^ schedule[index - 2].rotate_right(19)
+                ^ (schedule[index - 2] >> 10);
+            schedule[index] = schedule[index - 16]
+                .wrapping_add(s0)
+                .wrapping_add(schedule[index - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for index in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let temp1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(ROUND_CONSTANTS[index])
+                .wrapping_add(schedule[index]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(majority);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+
+        for (word, value) in self.state.iter_mut().zip(&[a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(*value);
+        }
+    }
+}
+
+pub fn resolve_binary<P: AsRef<Path>>(binary: P) -> PathBuf {
+    let binary = binary.as_ref();
+    if binary.components().count() > 1 {
+        return binary.to_path_buf();
+    }
+
+    // Bare names are looked up the same way the child process is executed
+    env::var_os("PATH")
+        .and_then(|paths| {
+            env::split_paths(&paths)
+                .map(|directory| directory.join(binary))
+                .find(|candidate| candidate.is_file())
+        })
+        .unwrap_or_else(|| binary.to_path_buf())
+}
+
+pub fn sha256_file<P: AsRef<Path>>(path: P) -> Result<String, Error> {
+    let path = path.as_ref();
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => {
+            return Err(Error::new(
+                e.kind(),
+                format!("Failed to open `{}`: {}", path.display(), e),
+            ))
+        }
+    };
+
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => hasher.update(&buffer[..read]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => {
+                return Err(Error::new(
+                    e.kind(),
+                    format!("Failed to read `{}`: {}", path.display(), e),
+                ))
+            }
+        }
+    }
+
+    Ok(hasher.finish())
+}
+
+pub fn get_modification_time<P: AsRef<Path>>(path: P) -> Option<u64> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+}
+
+// Asks the package managers which package installed the binary, if any of them knows about it
+pub fn get_package_origin<P: AsRef<Path>>(path: P) -> Option<String> {
+    let path = path.as_ref().as_os_str();
+    let queries: [(&str, &[&str]); 2] = [
+        ("rpm", &["--query", "--file"]),
+        ("dpkg-query", &["--search"]),
+    ];
+
+    for (command, arguments) in queries.iter() {
+        let mut arguments: Vec<&OsStr> = arguments.iter().map(|a| a.as_ref()).collect();
+        arguments.push(path);
+
+        if let Ok(stdout) = Process::oneshot(command, arguments) {
+            if let Some(line) = stdout.lines().find(|line| line.trim().len() > 0) {
+                return Some(format!("{} ({})", line.trim(), command));
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::Sha256;
+
+    fn sha256(data: &[u8]) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    #[test]
+    fn sha256_matches_reference_digests() {
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            sha256(b"")
+        );
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            sha256(b"abc")
+        );
+        assert_eq!(
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")
+        );
+    }
+
+    #[test]
+    fn sha256_produces_the_same_digest_for_chunked_input() {
+        let data = vec![b'a'; 1000];
+        let mut hasher = Sha256::new();
+        for chunk in data.chunks(7) {
+            hasher.update(chunk);
+        }
+
+        assert_eq!(
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3",
+            hasher.finish()
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use crate::clock::Clock;
+#[cfg(not(test))]
+use std::fs;
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+    time::Duration,
+};
+#[cfg(test)]
+use test::std::fs;
+
+const INTERRUPTS_PATH: &str = "/proc/interrupts";
+
+pub struct CpuReport {
+    cpu: usize,
+    foreign_tasks: Vec<String>,
+    interrupts: u64,
+    involuntary_switches: Vec<(String, u64)>,
+}
+
+impl CpuReport {
+    pub fn get_cpu(&self) -> usize {
+        self.cpu
+    }
+
+    pub fn get_foreign_tasks(&self) -> &Vec<String> {
+        &self.foreign_tasks
+    }
+
+    pub fn get_interrupts(&self) -> u64 {
+        self.interrupts
+    }
+
+    pub fn get_involuntary_switches(&self) -> &Vec<(String, u64)> {
+        &self.involuntary_switches
+    }
+}
+
+pub fn check_isolation(
+    pid: i32,
+    threads: &[(usize, Vec<String>)],
+    interval: Duration,
+    clock: &dyn Clock,
+) -> Result<Vec<CpuReport>, Error> {
+    let interrupts_before = read_interrupts()?;
+    let switches_before = read_switches(pid, threads);
+
+    clock.sleep(interval);
+
+    let interrupts_after = read_interrupts()?;
+    let switches_after = read_switches(pid, threads);
+
+    let mut reports = vec![];
+
+    for (cpu, tasks) in threads {
+        let mut foreign_tasks = vec![];
+        let mut involuntary_switches = vec![];
+
+        for task in tasks {
+            match (switches_before.get(task), switches_after.get(task)) {
+                (Some(before), Some(after)) => {
+                    involuntary_switches.push((task.to_owned(), after.saturating_sub(*before)))
+                }
+                // Tasks that are not qemu threads have no business on the isolated CPU
+                _ => foreign_tasks.push(task.to_owned()),
+            }
+        }
+
+        let before = interrupts_before.get(*cpu).cloned().unwrap_or(0);
+        let after = interrupts_after.get(*cpu).cloned().unwrap_or(0);
+
+        reports.push(CpuReport {
+            cpu: *cpu,
+            foreign_tasks,
+            interrupts: after.saturating_sub(before),
+            involuntary_switches,
+        });
+    }
+
+    Ok(reports)
+}
+
+fn read_interrupts() -> Result<Vec<u64>, Error> {
+    match fs::read_to_string(INTERRUPTS_PATH) {
+        Ok(interrupts) => Ok(parse_interrupts(&interrupts)),
+        Err(e) => Err(Error::new(
+            e.kind(),
+            format!(
+                "Failed to read interrupt counters from `{}`: {}",
+                INTERRUPTS_PATH, e
+            ),
+        )),
+    }
+}
+
+fn parse_interrupts(interrupts: &str) -> Vec<u64> {
+    let mut lines = interrupts.lines();
+    let cpus = match lines.next() {
+        Some(header) => header.split_whitespace().count(),
+        None => return vec![],
+    };
+
+    let mut totals = vec![0; cpus];
+
+    for line in lines {
+        // Every line starts with the IRQ name followed by per-CPU counters and a description
+        let counters = line.split_whitespace().skip(1).take(cpus);
+        for (cpu, counter) in counters.enumerate() {
+            match counter.parse::<u64>() {
+                Ok(count) => totals[cpu] += count,
+                Err(_) => break,
+            }
+        }
+    }
+
+    totals
+}
+
+fn read_switches(pid: i32, threads: &[(usize, Vec<String>)]) -> HashMap<String, u64> {
+    let mut switches = HashMap::new();
+
+    for (_, tasks) in threads {
+        for task in tasks {
+            if let Ok(count) = read_involuntary_switches(pid, task) {
+                switches.insert(task.to_owned(), count);
+            }
+        }
+    }
+
+    switches
+}
+
+fn read_involuntary_switches(pid: i32, task: &str) -> Result<u64, Error> {
+    // Only threads of the qemu process are listed in its task directory
+    let status = fs::read_to_string(format!("/proc/{}/task/{}/status", pid, task))?;
+
+    for line in status.lines() {
+        if let Some(count) = line.strip_prefix("nonvoluntary_ctxt_switches:") {
+            if let Ok(count) = count.trim().parse::<u64>() {
+                return Ok(count);
+            }
+        }
+    }
+
+    Err(Error::new(
+        ErrorKind::InvalidData,
+        format!(
+            "Task ID {}: process status does not contain the `nonvoluntary_ctxt_switches` field.",
+            task
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_isolation, parse_interrupts};
+    use crate::{assert_error, clock::fake::FakeClock, expect, vec_deq, verify_expectations};
+    use ::std::{
+        cell::RefCell,
+        collections::VecDeque,
+        io::{Error, ErrorKind},
+        time::Duration,
+    };
+
+    struct TestExpectations {
+        std_fs_read_to_string: VecDeque<(&'static str, Result<String, Error>)>,
+    }
+
+    impl TestExpectations {
+        fn new() -> Self {
+            TestExpectations {
+                std_fs_read_to_string: vec_deq![],
+            }
+        }
+    }
+
+    thread_local! { static TEST_EXPECTATIONS: RefCell<TestExpectations> = RefCell::new(TestExpectations::new()) }
+
+    fn verify_expectations() {
+        verify_expectations!(
+            std::fs::read_to_string => TEST_EXPECTATIONS::std_fs_read_to_string,
+        );
+    }
+
+    pub mod std {
+        pub mod fs {
+            use super::super::TEST_EXPECTATIONS;
+            use crate::verify_expectation;
+            use ::std::{io::Result, path::Path};
+
+            pub fn read_to_string<P: AsRef<Path>>(path: P) -> Result<String> {
+                let path = path.as_ref().to_str().unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_read_to_string => std::fs::read_to_string { path })
+            }
+        }
+    }
+
+    const INTERRUPTS_BEFORE: &str = "           CPU0       CPU1       CPU2
+  0:         10          0          0  IR-IO-APIC    2-edge      timer
+  8:          0          1          0  IR-IO-APIC    8-edge      rtc0
+LOC:       1000        500         20   Local timer interrupts
+ERR:          0
+";
+
+    const INTERRUPTS_AFTER: &str = "           CPU0       CPU1       CPU2
+  0:         15          0          0  IR-IO-APIC    2-edge      timer
+  8:          0          1          3  IR-IO-APIC    8-edge      rtc0
+LOC:       1100        501         24   Local timer interrupts
+ERR:          0
+";
+
+    #[test]
+    fn parse_interrupts_sums_counters_per_cpu() {
+        assert_eq!(vec![1010, 501, 20], parse_interrupts(INTERRUPTS_BEFORE));
+    }
+
+    #[test]
+    fn check_isolation_reports_foreign_tasks_interrupts_and_switches() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/interrupts" => Ok(INTERRUPTS_BEFORE.to_owned()) },
+            { "/proc/100/task/101/status" =>
+                Ok("Name:\tCPU 0/KVM\nnonvoluntary_ctxt_switches:\t7\n".to_owned()) },
+            { "/proc/100/task/555/status" => Err(Error::new(ErrorKind::NotFound, "not found")) },
+            { "/proc/100/task/102/status" =>
+                Ok("Name:\tCPU 1/KVM\nnonvoluntary_ctxt_switches:\t3\n".to_owned()) },
+            { "/proc/interrupts" => Ok(INTERRUPTS_AFTER.to_owned()) },
+            { "/proc/100/task/101/status" =>
+                Ok("Name:\tCPU 0/KVM\nnonvoluntary_ctxt_switches:\t9\n".to_owned()) },
+            { "/proc/100/task/555/status" => Err(Error::new(ErrorKind::NotFound, "not found")) },
+            { "/proc/100/task/102/status" =>
+                Ok("Name:\tCPU 1/KVM\nnonvoluntary_ctxt_switches:\t3\n".to_owned()) },
+        );
+
+        let clock = FakeClock::new();
+        let reports = check_isolation(
+            100,
+            &[
+                (1, vec!["101".to_owned(), "555".to_owned()]),
+                (2, vec!["102".to_owned()]),
+            ],
+            Duration::from_secs(5),
+            &*clock,
+        )
+        .unwrap();
+
+        assert_eq!(vec![Duration::from_secs(5)], clock.get_sleeps());
+        assert_eq!(2, reports.len());
+        assert_eq!(1, reports[0].get_cpu());
+        assert_eq!(&vec!["555".to_owned()], reports[0].get_foreign_tasks());
+        assert_eq!(1, reports[0].get_interrupts());
+        assert_eq!(
+            &vec![("101".to_owned(), 2)],
+            reports[0].get_involuntary_switches()
+        );
+        assert_eq!(2, reports[1].get_cpu());
+        assert_eq!(0, reports[1].get_foreign_tasks().len());
+        assert_eq!(7, reports[1].get_interrupts());
+        assert_eq!(
+            &vec![("102".to_owned(), 0)],
+            reports[1].get_involuntary_switches()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn check_isolation_returns_error_if_unable_to_read_interrupts() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/interrupts" =>
+                Err(Error::new(ErrorKind::PermissionDenied, "std::fs::read_to_string()")) },
+        );
+
+        assert_error!(
+            ErrorKind::PermissionDenied,
+            "Failed to read interrupt counters from `/proc/interrupts`: std::fs::read_to_string()",
+            check_isolation(100, &[], Duration::from_secs(5), &*FakeClock::new())
+        );
+
+        verify_expectations();
+    }
+}
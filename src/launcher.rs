@@ -0,0 +1,2263 @@
+use crate::{
+    clock::{self, Clock},
+    config::{self, Config},
+    cpuset::{self, CpuSet},
+    environment::Environment,
+    event_sink,
+    events::Events,
+    firewall, host, integrity, is_process_alive, load_config,
+    netboot::NetbootServer,
+    output, permissions,
+    pinning::{AffinityBackend, PinningBackend},
+    process::{self, ChildProcess, Process},
+    provision, qemu_log,
+    qmp::{self, QmpClient, QmpPipe},
+    qmp_proxy, readconfig, remove_firewall_rules,
+    resctrl::{self, ResctrlGroup},
+    serial_log,
+    shutdown::{Action, ShutdownSchedule},
+    sriov::Sriov,
+    state::StateDirectory,
+    storage::StorageDaemons,
+    systemd,
+    throttle::Throttle,
+    tuning::HostTuning,
+};
+use json::{object, JsonValue};
+use nix::{
+    libc::c_int,
+    sys::{
+        mman::{mlockall, munlockall, MlockAllFlags},
+        signal::{kill, signal, SigHandler, Signal},
+    },
+    unistd::{access, AccessFlags, Pid},
+};
+use rlimit::{getrlimit, setrlimit, Resource, Rlim};
+use std::{
+    fs,
+    io::{self, Error, ErrorKind, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+const CAP_DAC_OVERRIDE: u32 = 1;
+const CAP_SYS_ADMIN: u32 = 21;
+const SRIOV_TIMEOUT: u64 = 10;
+const STORAGE_DAEMON_TIMEOUT: u64 = 10;
+// Per-CPU kernel threads which run real-time on `PREEMPT_RT` kernels and preempt the vCPUs
+const LOWERED_KERNEL_THREADS: &[&str] = &["ksoftirqd", "rcuc"];
+// Hooks are waited for before the machine is reported ready, so a hung one must not hold it forever
+const POST_START_HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+// The time the guest is given to power down before qemu is stopped, and qemu to quit before it is
+// killed
+const POWERDOWN_TIMEOUT: Duration = Duration::from_secs(60);
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+// How often the CPU usage is sampled for `idle_shutdown`
+const IDLE_SAMPLE_PERIOD: Duration = Duration::from_secs(10);
+// Shorter gaps between the uptime and the monotonic clock are scheduling noise rather than a suspend
+const SUSPEND_THRESHOLD: Duration = Duration::from_secs(5);
+
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_reload(_: c_int) {
+    RELOAD_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+// The outcome of pinning a single vCPU, named by its `vcpu_pinning` path or `vcpu_pinning_by_index` index
+pub struct PinStatus {
+    error: Option<String>,
+    host_cpu: usize,
+    task_id: Option<usize>,
+    vcpu: String,
+}
+
+impl PinStatus {
+    pub fn get_vcpu(&self) -> &str {
+        &self.vcpu
+    }
+
+    pub fn get_task_id(&self) -> Option<usize> {
+        self.task_id
+    }
+
+    pub fn get_host_cpu(&self) -> usize {
+        self.host_cpu
+    }
+
+    pub fn is_pinned(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+// Starts a machine, or adopts an already running one, and hands it over to the caller as a `VmHandle`
+pub struct Launcher<'a> {
+    adopt: bool,
+    clock: Arc<dyn Clock>,
+    debug: bool,
+    env: &'a Environment,
+    events: Events,
+    mlock_self: bool,
+}
+
+impl<'a> Launcher<'a> {
+    pub fn new(env: &'a Environment, events: Events) -> Self {
+        Launcher {
+            adopt: false,
+            clock: clock::system(),
+            debug: false,
+            env,
+            events,
+            mlock_self: false,
+        }
+    }
+
+    pub fn should_adopt(mut self, should_adopt: bool) -> Self {
+        self.adopt = should_adopt;
+
+        self
+    }
+
+    pub fn should_debug(mut self, should_debug: bool) -> Self {
+        self.debug = should_debug;
+
+        self
+    }
+
+    pub fn should_mlock_self(mut self, should_mlock: bool) -> Self {
+        self.mlock_self = should_mlock;
+
+        self
+    }
+
+    pub fn start(self, config: Config) -> Result<VmHandle<'a>, Error> {
+        let env = self.env;
+
+        let mut affinity = None;
+        let (mount_path, prefix) = cpuset_location(env, &config);
+
+        if config.has_cpu_pinning() && !can_manage_cpusets(mount_path, prefix) {
+            let delegated = Path::new(mount_path).join(prefix);
+
+            if !env.should_fallback_to_affinity() {
+                return Err(Error::new(
+                    ErrorKind::PermissionDenied,
+                    format!(
+                        "vCPU pinning requires managing the cpuset cgroup tree, but the launcher lacks the \
+                        `CAP_SYS_ADMIN` and `CAP_DAC_OVERRIDE` capabilities and can not write into `{}`: \
+                        grant the capabilities to the launcher, run the launcher as root, \
+                        delegate the `{}` directory to the launcher user, or set \
+                        `QEMU_LAUNCHER_PINNING_FALLBACK=affinity` to only set the CPU affinity of pinned tasks.",
+                        delegated.display(),
+                        delegated.display()
+                    ),
+                ));
+            }
+
+            output::warning(format!(
+                "The launcher can not manage the cpuset cgroup tree at `{}`, pinned tasks only get their \
+                CPU affinity set and the host CPUs are not reserved for them.",
+                delegated.display()
+            ));
+            affinity = Some(AffinityBackend::new());
+        }
+
+        let cpuset = CpuSet::new(mount_path, prefix)?
+            .with_lock_directory(env.get_state_directory())
+            .with_migrate_all(env.should_migrate_all_tasks())
+            .with_mount_options(env.get_cpuset_mount_options())
+            .with_release_retries(env.get_cpuset_release_retries(), Duration::from_millis(100))
+            .with_clock(self.clock.clone())
+            .with_thread_name(
+                env.get_cpuset_thread_name()
+                    .replace("{vm}", config.get_machine_name()),
+            );
+
+        let mut vm = VmHandle {
+            affinity,
+            child: None,
+            clock: self.clock.clone(),
+            cpuset,
+            env,
+            events: self.events,
+            exited: false,
+            guest_events: None,
+            helpers: process::OneshotPool::new(
+                env.get_helper_workers(),
+                Duration::from_millis(env.get_helper_interval()),
+            )
+            .with_clock(self.clock.clone()),
+            netboot: NetbootServer::new().with_clock(self.clock.clone()),
+            owned: false,
+            pid: None,
+            pin_status: vec![],
+            powerdown_deadline: None,
+            proxy: None,
+            qemu_log: None,
+            qmp_stdio: None,
+            resctrl: ResctrlGroup::new(env.get_resctrl_path()),
+            serial_log_rotatable: true,
+            shutdown: ShutdownSchedule::new(
+                config.get_max_runtime(),
+                config.get_idle_shutdown().map(|policy| policy.get_after()),
+            ),
+            sriov: Sriov::new(Duration::from_secs(SRIOV_TIMEOUT)).with_clock(self.clock.clone()),
+            started: self.clock.now(),
+            state: StateDirectory::new(env.get_state_directory(), config.get_machine_name()),
+            storage: StorageDaemons::new(Duration::from_secs(STORAGE_DAEMON_TIMEOUT))
+                .with_clock(self.clock.clone()),
+            thermal_readable: true,
+            throttle: None,
+            throttle_percent: 0,
+            tuning: HostTuning::new(),
+            usage_sample: None,
+            usage_readable: true,
+            vcpu_info: None,
+            config,
+        };
+
+        if vm.config.has_cpu_pinning() {
+            if vm.affinity.is_none() {
+                collect_stale_cpusets(env, &vm.cpuset);
+            }
+
+            let cpus: Vec<usize> = vm.config.get_pinned_host_cpus();
+
+            let offline = host::get_offline_cpus(&cpus)?;
+            if offline.len() > 0 {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "Unable to pin vCPUs to the offline host CPU(s): {}.",
+                        offline
+                            .iter()
+                            .map(|cpu| format!("`{}`", cpu))
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ),
+                ));
+            }
+        }
+
+        if vm.config.get_ksm() == Some(true) {
+            match host::is_ksm_running() {
+                Ok(true) => {}
+                Ok(false) => output::warning("Kernel samepage merging is not running on the host, `launcher.ksm: on` has no effect."),
+                Err(e) => output::error(e),
+            }
+        }
+
+        if let Some(thp) = vm.config.get_thp() {
+            check_thp_policy(thp);
+        }
+
+        if vm.config.uses_io_uring() {
+            check_io_uring(&vm.config)?;
+        }
+
+        let running = vm
+            .state
+            .read_pid()
+            .ok()
+            .filter(|pid| is_process_alive(*pid));
+
+        match (running, self.adopt) {
+            (None, true) => {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!(
+                        "The `{}` machine is not running, there is nothing to adopt.",
+                        vm.config.get_machine_name()
+                    ),
+                ))
+            }
+            (Some(pid), false) => {
+                return Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!(
+                        "The `{}` machine is already running with the process ID `{}`, \
+                        use `--adopt` to resume its supervision.",
+                        vm.config.get_machine_name(),
+                        pid
+                    ),
+                ))
+            }
+            _ => {}
+        }
+
+        if self.mlock_self {
+            // Memory locks are not inherited by the child process, so qemu is not affected
+            if let Err(e) = mlockall(MlockAllFlags::MCL_CURRENT | MlockAllFlags::MCL_FUTURE) {
+                output::error(format!("Failed to lock the launcher memory: {}", e));
+            }
+        }
+
+        // From here on the handle undoes the host changes, including the ones of a failed launch
+        vm.owned = true;
+
+        match running {
+            Some(pid) => {
+                vm.events
+                    .emit("spawned", object! { "pid": pid, "adopted": true });
+                vm.pid = Some(pid);
+            }
+            None => {
+                // A request left behind by a previous launcher does not apply to the new machine
+                if let Err(e) = vm.state.clear_throttle() {
+                    output::error(e);
+                }
+
+                let (child, qemu_log) = launch(
+                    env,
+                    &*vm.clock,
+                    &vm.config,
+                    &vm.state,
+                    &mut vm.sriov,
+                    &mut vm.storage,
+                    &mut vm.netboot,
+                    &vm.helpers,
+                    self.debug,
+                )?;
+                let pid = child.id() as i32;
+                vm.events
+                    .emit("spawned", object! { "pid": pid, "adopted": false });
+                vm.child = Some(child);
+                vm.pid = Some(pid);
+                vm.qemu_log = qemu_log;
+            }
+        }
+
+        vm.connect_qmp(self.debug);
+
+        if self.mlock_self {
+            if let Err(e) = munlockall() {
+                output::error(format!("Failed to unlock the launcher memory: {}", e));
+            }
+        }
+
+        vm.tune_host();
+        vm.run_post_start_hooks();
+
+        if let Err(e) = vm.state.mark_ready() {
+            output::error(e);
+        }
+
+        if let Some((uid, gid)) = env.get_sudo_owner() {
+            hand_over_artifacts(env, &vm.config, &vm.state, uid, gid);
+        }
+
+        vm.events.emit("ready", object! {});
+
+        // Safe, since the handler only stores into an atomic flag
+        if let Err(e) = unsafe { signal(Signal::SIGHUP, SigHandler::Handler(request_reload)) } {
+            output::error(format!(
+                "Failed to install the configuration reload handler: {}",
+                e
+            ));
+        }
+
+        Ok(vm)
+    }
+}
+
+// A launched or adopted machine. The host changes made for the machine are undone when the handle is
+// dropped after qemu has exited, while a machine outliving its handle only gets its pinned host CPUs
+// released, so they are not leaked.
+pub struct VmHandle<'a> {
+    affinity: Option<AffinityBackend>,
+    child: Option<ChildProcess>,
+    clock: Arc<dyn Clock>,
+    config: Config,
+    cpuset: CpuSet,
+    env: &'a Environment,
+    events: Events,
+    exited: bool,
+    guest_events: Option<Receiver<(String, JsonValue)>>,
+    helpers: process::OneshotPool,
+    netboot: NetbootServer,
+    owned: bool,
+    pid: Option<i32>,
+    pin_status: Vec<PinStatus>,
+    powerdown_deadline: Option<Instant>,
+    proxy: Option<String>,
+    qemu_log: Option<JoinHandle<()>>,
+    // The qemu stdio stays open for as long as qemu runs, even when nothing is watching it
+    qmp_stdio: Option<process::ChildStdio>,
+    resctrl: ResctrlGroup,
+    serial_log_rotatable: bool,
+    shutdown: ShutdownSchedule,
+    sriov: Sriov,
+    started: Instant,
+    state: StateDirectory,
+    storage: StorageDaemons,
+    thermal_readable: bool,
+    throttle: Option<Throttle>,
+    throttle_percent: u8,
+    tuning: HostTuning,
+    // The last CPU usage sample of the machine cgroup, if it could be read
+    usage_sample: Option<(Instant, Option<u64>)>,
+    usage_readable: bool,
+    vcpu_info: Option<qmp::Topology>,
+}
+
+impl VmHandle<'_> {
+    // Opens a new QMP connection to the machine: through the proxy socket when the launcher serves one,
+    // since the private channel is taken by the launcher, or through `launcher.qmp_socket` otherwise
+    pub fn qmp(&self) -> Result<QmpClient<'static>, Error> {
+        let clock = self.clock.clone();
+
+        self.open_qmp()
+            .map(|stream| QmpClient::new(stream).with_clock(clock))
+    }
+
+    fn open_qmp(&self) -> Result<UnixStream, Error> {
+        let path = match (&self.proxy, self.config.get_qmp_socket()) {
+            (Some(path), _) => path.clone(),
+            (None, Some(path)) => path,
+            (None, None) => {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    "Neither `launcher.qmp_proxy` nor `launcher.qmp_socket` is available.",
+                ))
+            }
+        };
+
+        match UnixStream::connect(&path) {
+            Ok(stream) => Ok(stream),
+            Err(e) => Err(Error::new(
+                e.kind(),
+                format!("Failed to connect to the QMP socket `{}`: {}", path, e),
+            )),
+        }
+    }
+
+    // Asks qemu to quit over QMP, or with `SIGTERM` when no QMP socket is available, and kills qemu
+    // if it is still running after the timeout. `wait()` still has to be called to reap it.
+    pub fn stop(&mut self, timeout: Duration) -> Result<(), Error> {
+        if !self.is_running() {
+            return Ok({});
+        }
+
+        if self.qmp().and_then(|mut qmp| qmp.quit()).is_err() {
+            if let Some(pid) = self.pid {
+                if let Err(e) = kill(Pid::from_raw(pid), Signal::SIGTERM) {
+                    return Err(Error::new(
+                        ErrorKind::Other,
+                        format!("Failed to terminate the process `{}`: {}", pid, e),
+                    ));
+                }
+            }
+        }
+
+        let clock = self.clock.clone();
+        let deadline = clock.now() + timeout;
+        if !clock::wait_until(&*clock, deadline, Duration::from_millis(100), || {
+            !self.is_running()
+        }) {
+            output::warning(format!(
+                "The `{}` machine did not stop in {} seconds, killing qemu.",
+                self.config.get_machine_name(),
+                timeout.as_secs()
+            ));
+            return self.kill();
+        }
+
+        Ok({})
+    }
+
+    pub fn kill(&mut self) -> Result<(), Error> {
+        if self.exited {
+            return Ok({});
+        }
+
+        match (self.child.as_mut(), self.pid) {
+            (Some(child), _) => child.kill(),
+            (None, Some(pid)) => match kill(Pid::from_raw(pid), Signal::SIGKILL) {
+                Ok(_) => Ok({}),
+                Err(e) => Err(Error::new(
+                    ErrorKind::Other,
+                    format!("Failed to kill the process `{}`: {}", pid, e),
+                )),
+            },
+            (None, None) => Ok({}),
+        }
+    }
+
+    // Supervises the machine until qemu exits: reports the guest events, applies the configuration
+    // reloads requested with `SIGHUP` and re-applies the pinning after the host resumes from suspend
+    pub fn wait(&mut self) -> Result<(), Error> {
+        if self.exited {
+            return Ok({});
+        }
+
+        let mut clock = match host::get_uptime() {
+            Ok(uptime) => Some((Instant::now(), uptime)),
+            Err(e) => {
+                output::warning(format!(
+                    "Host suspend can not be detected, the pinning is not re-applied on resume: {}",
+                    e
+                ));
+                None
+            }
+        };
+
+        let mut online = host::get_online_cpus().ok();
+
+        loop {
+            if let Some(receiver) = &self.guest_events {
+                for (event, data) in receiver.try_iter() {
+                    report_guest_event(&self.events, &event, &data, &self.config);
+                }
+            }
+
+            if !self.is_running() {
+                break;
+            }
+
+            if RELOAD_REQUESTED.swap(false, Ordering::Relaxed) {
+                if let Some(reloaded) = reload_config(
+                    self.env,
+                    &self.config,
+                    self.vcpu_info.as_ref(),
+                    pinning_backend(&mut self.cpuset, &mut self.affinity),
+                    &self.helpers,
+                    &mut self.pin_status,
+                ) {
+                    self.config = reloaded;
+                }
+            }
+
+            if let Some(suspended) = get_suspended_time(&mut clock) {
+                self.recover_from_suspend(suspended);
+            }
+
+            self.check_online_cpus(&mut online);
+
+            self.update_throttle();
+            self.rotate_serial_log();
+            self.apply_shutdown_policies();
+
+            for id in self.storage.take_exited() {
+                output::error(format!(
+                    "The `{}` storage daemon exited, the block nodes it exports are no longer available.",
+                    id
+                ));
+                self.events
+                    .emit("storage-daemon-exited", object! { "id": id });
+            }
+
+            self.clock.sleep(Duration::from_secs(1));
+        }
+
+        self.exited = true;
+        self.throttle = None;
+
+        // The exit status of an adopted qemu process is not known to the launcher
+        let (status, result) = match self.child.take() {
+            Some(child) => match child.wait() {
+                Ok(_) => (object! { "success": true }, Ok({})),
+                Err(e) => (
+                    object! { "success": false, "error": e.to_string() },
+                    Err(Error::new(
+                        e.kind(),
+                        format!(
+                            "The child process `{}` was terminated preliminarly: {}",
+                            self.config.get_qemu_binary_path(),
+                            e
+                        ),
+                    )),
+                ),
+            },
+            None => (object! { "success": JsonValue::Null }, Ok({})),
+        };
+
+        self.events.emit("exited", status);
+
+        if let Some(qemu_log) = self.qemu_log.take() {
+            let _ = qemu_log.join();
+        }
+
+        result
+    }
+
+    fn is_running(&mut self) -> bool {
+        if self.exited {
+            return false;
+        }
+
+        // An adopted qemu process is not a child of the launcher, so it can not be waited for
+        match (self.child.as_mut(), self.pid) {
+            (Some(child), _) => !child.has_exited().unwrap_or_else(|e| {
+                output::error(e);
+                true
+            }),
+            (None, Some(pid)) => is_process_alive(pid),
+            (None, None) => false,
+        }
+    }
+
+    fn connect_qmp(&mut self, debug: bool) {
+        let env = self.env;
+        let config = &self.config;
+
+        // Startup commands are only sent to a freshly spawned qemu, an adopted one has already received them
+        let startup_commands = match self.child {
+            Some(_) => config.get_qmp_startup_commands().clone(),
+            None => vec![],
+        };
+
+        // The private QMP channel of an adopted qemu went away with the launcher which started it
+        if self.child.is_none() && config.get_qmp_proxy().is_some() {
+            output::warning(
+                "The `launcher.qmp_proxy` socket is not available for an adopted machine, use `launcher.qmp_socket` instead.",
+            );
+        }
+
+        // Devices of an adopted qemu were added by the launcher which started it
+        let deferred_devices = match self.child {
+            Some(_) => config.get_deferred_devices(),
+            None => vec![],
+        };
+
+        if !(config.has_cpu_pinning()
+            || startup_commands.len() > 0
+            || deferred_devices.len() > 0
+            || config.should_watch_qmp_events()
+            || (self.child.is_some() && config.get_qmp_proxy().is_some()))
+        {
+            return;
+        }
+
+        let qmp_log = open_qmp_log(env, config, debug);
+
+        match self.child.as_mut() {
+            Some(child) => match child.take_stdio() {
+                Ok(mut stdio) => {
+                    if let Some((vcpu_info, pin_status)) = handle_vcpu_pinning(
+                        &mut stdio,
+                        pinning_backend(&mut self.cpuset, &mut self.affinity),
+                        config,
+                        &startup_commands,
+                        qmp_log,
+                        &self.events,
+                        &self.helpers,
+                        self.clock.clone(),
+                    ) {
+                        self.vcpu_info = Some(vcpu_info);
+                        self.pin_status = pin_status;
+                    }
+
+                    // Added even when the pinning failed, so the machine never ends up without them
+                    if deferred_devices.len() > 0 {
+                        let qmp_log =
+                            open_qmp_log(env, config, debug).map(|log| log as Box<dyn Write>);
+                        for e in qmp::add_devices(&mut stdio, qmp_log, &deferred_devices) {
+                            output::error(e);
+                        }
+                    }
+
+                    let proxy =
+                        config
+                            .get_qmp_proxy()
+                            .and_then(|path| match qmp_proxy::bind(&path) {
+                                Ok(listener) => Some((path, listener)),
+                                Err(e) => {
+                                    output::error(e);
+                                    None
+                                }
+                            });
+
+                    match proxy {
+                        Some((path, listener)) => {
+                            let qmp_log = open_qmp_log(env, config, debug);
+                            self.proxy = Some(path.clone());
+                            self.guest_events =
+                                Some(serve_qmp_proxy(stdio, path, listener, config, qmp_log));
+                        }
+                        None if config.should_watch_qmp_events() => {
+                            let qmp_log = open_qmp_log(env, config, debug);
+                            self.guest_events = Some(watch_guest_events(
+                                stdio,
+                                config,
+                                qmp_log,
+                                self.clock.clone(),
+                            ));
+                        }
+                        None => self.qmp_stdio = Some(stdio),
+                    }
+                }
+                Err(e) => output::error(format!(
+                    "Unable to obtain qemu process stdio descriptors: {}",
+                    e
+                )),
+            },
+            None => match connect_qmp_socket(config) {
+                Ok(mut stream) => {
+                    if let Some((vcpu_info, pin_status)) = handle_vcpu_pinning(
+                        &mut stream,
+                        pinning_backend(&mut self.cpuset, &mut self.affinity),
+                        config,
+                        &startup_commands,
+                        qmp_log,
+                        &self.events,
+                        &self.helpers,
+                        self.clock.clone(),
+                    ) {
+                        self.vcpu_info = Some(vcpu_info);
+                        self.pin_status = pin_status;
+                    }
+
+                    if config.should_watch_qmp_events() {
+                        let qmp_log = open_qmp_log(env, config, debug);
+                        self.guest_events = Some(watch_guest_events(
+                            stream,
+                            config,
+                            qmp_log,
+                            self.clock.clone(),
+                        ));
+                    }
+                }
+                Err(e) => output::error(e),
+            },
+        }
+
+        if config.has_queue_pinning() {
+            if let (Some(vcpu_info), Some(pid)) = (&self.vcpu_info, self.pid) {
+                apply_queue_pinning(
+                    pid,
+                    vcpu_info,
+                    pinning_backend(&mut self.cpuset, &mut self.affinity),
+                    config,
+                );
+            }
+        }
+
+        if self.cpuset.get_unmovable_tasks().len() > 0 {
+            output::warning(format!(
+                "Failed to move some tasks into the CPU pool, they may still run on pinned CPUs: {}.",
+                self.cpuset.get_unmovable_tasks().join(", ")
+            ));
+        }
+    }
+
+    // The vCPUs are throttled by the larger of the `throttle` command request and the thermal policy
+    fn update_throttle(&mut self) {
+        let requested = self.state.read_throttle().unwrap_or(0);
+
+        let thermal = match self.config.get_thermal_throttle() {
+            Some(policy) => match host::get_thermal_zone_temperature(policy.get_zone()) {
+                Ok(temperature) => {
+                    self.thermal_readable = true;
+                    policy.get_throttle(temperature)
+                }
+                Err(e) => {
+                    if self.thermal_readable {
+                        output::error(format!(
+                            "Unable to apply the thermal throttle policy: {}",
+                            e
+                        ));
+                    }
+                    self.thermal_readable = false;
+                    0
+                }
+            },
+            None => 0,
+        };
+
+        let percent = requested.max(thermal);
+        if percent == self.throttle_percent {
+            return;
+        }
+
+        self.throttle_percent = percent;
+
+        match (&self.throttle, percent) {
+            (Some(throttle), _) => throttle.set_percent(percent),
+            (None, 0) => {}
+            (None, _) => match self.open_qmp() {
+                Ok(stream) => {
+                    self.throttle = Some(Throttle::start(stream, percent, self.clock.clone()))
+                }
+                Err(e) => {
+                    output::error(format!("Unable to throttle the vCPUs: {}", e));
+                    return;
+                }
+            },
+        }
+
+        eprintln!(
+            "Throttling the vCPUs of the `{}` machine by {}%.",
+            self.config.get_machine_name(),
+            percent
+        );
+
+        self.events.emit(
+            "throttled",
+            object! { "percent": percent, "requested": requested, "thermal": thermal },
+        );
+    }
+
+    fn rotate_serial_log(&mut self) {
+        let log = match self.config.get_serial_log() {
+            Some(log) => log,
+            None => return,
+        };
+
+        let path = self.config.get_serial_log_path(log);
+        match serial_log::rotate(&path, log.get_max_size(), log.get_keep()) {
+            Ok(_) => self.serial_log_rotatable = true,
+            Err(e) => {
+                if self.serial_log_rotatable {
+                    output::error(format!("Unable to rotate the serial console log: {}", e));
+                }
+                self.serial_log_rotatable = false;
+            }
+        }
+    }
+
+    // Powers the machine down once `max_runtime` is reached or it stayed idle for `idle_shutdown`,
+    // warning ahead of it
+    fn apply_shutdown_policies(&mut self) {
+        if let Some(deadline) = self.powerdown_deadline {
+            if self.clock.now() >= deadline {
+                self.powerdown_deadline = None;
+                output::warning(format!(
+                    "The `{}` machine did not power down in {} seconds, stopping qemu.",
+                    self.config.get_machine_name(),
+                    POWERDOWN_TIMEOUT.as_secs()
+                ));
+                if let Err(e) = self.stop(STOP_TIMEOUT) {
+                    output::error(e);
+                }
+            }
+            return;
+        }
+
+        if !self.shutdown.is_enabled() {
+            return;
+        }
+
+        let idle = self.sample_idle();
+        let runtime = self.clock.now().duration_since(self.started);
+        match self.shutdown.update(runtime, idle) {
+            Action::None => {}
+            Action::Warn(reason, remaining) => {
+                output::warning(format!(
+                    "The `{}` machine will be powered down in {} seconds, as {}.",
+                    self.config.get_machine_name(),
+                    remaining.as_secs(),
+                    reason.describe()
+                ));
+                self.events.emit(
+                    "shutdown-warning",
+                    object! { "reason": reason.get_name(), "seconds": remaining.as_secs() },
+                );
+            }
+            Action::Enforce(reason) => {
+                output::warning(format!(
+                    "Powering down the `{}` machine, as {}.",
+                    self.config.get_machine_name(),
+                    reason.describe()
+                ));
+                self.events
+                    .emit("shutdown-enforced", object! { "reason": reason.get_name() });
+                self.power_down();
+            }
+        }
+    }
+
+    // The guest is given the chance to shut down cleanly when it can be asked to over QMP
+    fn power_down(&mut self) {
+        match self
+            .qmp()
+            .and_then(|mut qmp| qmp.execute(object! {"execute": "system_powerdown"}))
+        {
+            Ok(_) => self.powerdown_deadline = Some(self.clock.now() + POWERDOWN_TIMEOUT),
+            Err(_) => {
+                if let Err(e) = self.stop(STOP_TIMEOUT) {
+                    output::error(e);
+                }
+            }
+        }
+    }
+
+    // Whether the machine stayed idle since the previous sample, along with the time passed since then.
+    // Nothing is returned until the next sample is due, or when neither the QMP status nor the cgroup
+    // CPU usage is available.
+    fn sample_idle(&mut self) -> Option<(bool, Duration)> {
+        let cpu_percent = self.config.get_idle_shutdown()?.get_cpu_percent();
+        let pid = self.pid?;
+
+        let now = self.clock.now();
+        let (sampled_at, previous) = match self.usage_sample {
+            Some((at, _)) if now.duration_since(at) < IDLE_SAMPLE_PERIOD => return None,
+            Some((at, usage)) => (Some(at), usage),
+            None => (None, None),
+        };
+
+        let usage = match host::get_cgroup_cpu_usage(pid) {
+            Ok(usage) => {
+                self.usage_readable = true;
+                Some(usage)
+            }
+            Err(e) => {
+                if self.usage_readable {
+                    output::warning(format!(
+                        "Unable to measure the CPU usage of the `{}` machine: {}",
+                        self.config.get_machine_name(),
+                        e
+                    ));
+                }
+                self.usage_readable = false;
+                None
+            }
+        };
+
+        self.usage_sample = Some((now, usage));
+        let period = now.duration_since(sampled_at?);
+
+        let paused = match self.proxy.is_some() || self.config.get_qmp_socket().is_some() {
+            true => self
+                .qmp()
+                .and_then(|mut qmp| qmp.execute(object! {"execute": "query-status"}))
+                .ok()
+                .map(|status| status["running"] == false),
+            false => None,
+        };
+
+        let below = match (previous, usage) {
+            (Some(previous), Some(usage)) => Some(
+                usage.saturating_sub(previous) * 100
+                    < cpu_percent as u64 * period.as_micros() as u64,
+            ),
+            _ => None,
+        };
+
+        match (paused, below) {
+            (None, None) => None,
+            _ => Some((paused == Some(true) || below == Some(true), period)),
+        }
+    }
+
+    // A resume may reset the affinities of the vCPU threads and the host settings tuned for them
+    fn recover_from_suspend(&mut self, suspended: Duration) {
+        eprintln!(
+            "The host resumed after {} seconds of suspend, re-applying the vCPU pinning and host tuning.",
+            suspended.as_secs()
+        );
+
+        self.events.emit(
+            "host-resumed",
+            object! { "suspended_seconds": suspended.as_secs() },
+        );
+
+        self.reapply_pinning();
+
+        if let Err(e) = self.tuning.reapply() {
+            output::error(e);
+        }
+    }
+
+    // A host CPU going offline is dropped from every cpuset and breaks the affinity of the threads
+    // pinned to it, so once host CPUs are back online the pool is repaired and the pinning re-applied
+    fn check_online_cpus(&mut self, online: &mut Option<Vec<usize>>) {
+        let current = match host::get_online_cpus() {
+            Ok(current) => current,
+            Err(_) => return,
+        };
+
+        let previous = match online.replace(current.clone()) {
+            Some(previous) if previous != current => previous,
+            _ => return,
+        };
+
+        let pinned = self.config.get_pinned_host_cpus();
+        for cpu in previous.iter().filter(|cpu| !current.contains(cpu)) {
+            if pinned.contains(cpu) {
+                output::warning(format!(
+                    "The pinned host CPU `{}` went offline, the vCPU pinned to it is moved to other host CPUs \
+                    until it is back online.",
+                    cpu
+                ));
+            }
+        }
+
+        self.events
+            .emit("host-cpus-changed", object! { "online": current.clone() });
+
+        if !current.iter().any(|cpu| !previous.contains(cpu)) {
+            return;
+        }
+
+        if self.config.has_cpu_pinning() && self.affinity.is_none() {
+            let running: Vec<usize> = get_running_pinned_host_cpus(self.env)
+                .into_iter()
+                .flat_map(|(_, cpus)| cpus)
+                .collect();
+
+            match self.cpuset.repair_pool(&current, &running) {
+                Ok(returned) if returned.len() > 0 => eprintln!(
+                    "Returned the host CPUs back online to the CPU pool: {}.",
+                    returned
+                        .iter()
+                        .map(|cpu| format!("`{}`", cpu))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ),
+                Ok(_) => {}
+                Err(e) => output::error(format!(
+                    "Failed to repair the CPU pool after host CPUs came back online: {}",
+                    e
+                )),
+            }
+        }
+
+        if current
+            .iter()
+            .any(|cpu| !previous.contains(cpu) && pinned.contains(cpu))
+        {
+            eprintln!("Pinned host CPUs are back online, re-applying the vCPU pinning.");
+            self.reapply_pinning();
+        }
+    }
+
+    fn reapply_pinning(&mut self) {
+        if let Some(vcpu_info) = &self.vcpu_info {
+            if self.config.has_cpu_pinning() {
+                self.pin_status = apply_vcpu_pinning(
+                    vcpu_info,
+                    pinning_backend(&mut self.cpuset, &mut self.affinity),
+                    &self.config,
+                );
+                apply_vcpu_scheduling(vcpu_info, &self.config, false, &self.helpers);
+            }
+
+            if let (true, Some(pid)) = (self.config.has_queue_pinning(), self.pid) {
+                apply_queue_pinning(
+                    pid,
+                    vcpu_info,
+                    pinning_backend(&mut self.cpuset, &mut self.affinity),
+                    &self.config,
+                );
+            }
+        }
+    }
+
+    fn tune_host(&mut self) {
+        if self.config.should_isolate_workqueues() {
+            let cpus: Vec<usize> = self.config.get_pinned_host_cpus();
+
+            if let Err(e) = self.tuning.isolate_workqueues(&cpus) {
+                output::error(format!(
+                    "Failed to isolate kernel workqueues from pinned CPUs: {}",
+                    e
+                ));
+            }
+        }
+
+        if self.config.should_disable_timer_migration() {
+            if let Err(e) = self.tuning.disable_timer_migration() {
+                output::error(format!("Failed to disable kernel timer migration: {}", e));
+            }
+        }
+
+        for (name, value) in self.config.get_kvm_parameters() {
+            if let Err(e) = self.tuning.set_kvm_parameter(name, *value) {
+                output::error(format!("Failed to tune the KVM module: {}", e));
+            }
+        }
+
+        if self.config.should_lower_kernel_thread_priority() {
+            let cpus = self.config.get_pinned_host_cpus();
+
+            match host::get_per_cpu_kernel_threads(LOWERED_KERNEL_THREADS, &cpus) {
+                Ok(threads) => {
+                    for thread in threads {
+                        if let Err(e) = self.tuning.lower_kernel_thread(
+                            thread.get_id(),
+                            thread.get_name(),
+                            thread.get_policy(),
+                            thread.get_priority(),
+                        ) {
+                            output::warning(e);
+                        }
+                    }
+                }
+                Err(e) => output::error(format!(
+                    "Failed to find kernel threads of pinned CPUs: {}",
+                    e
+                )),
+            }
+        }
+
+        if let Some(allocation) = self.config.get_cache_allocation() {
+            if let Err(e) = self.resctrl.create(
+                &resctrl::group_name(self.config.get_machine_name()),
+                &self.config.get_pinned_host_cpus(),
+                &allocation.get_schemata(),
+            ) {
+                output::error(format!(
+                    "Failed to allocate the cache for pinned CPUs: {}",
+                    e
+                ));
+                if let Err(e) = self.resctrl.remove() {
+                    output::error(e);
+                }
+            }
+        }
+    }
+
+    // Hooks get the outcome of the vCPU pinning, so site-specific tuning does not have to query QMP
+    fn run_post_start_hooks(&self) {
+        let pid = match self.pid {
+            Some(pid) => pid.to_string(),
+            None => return,
+        };
+        let pinning_map = pinning_map(&self.pin_status);
+
+        for hook in self.config.get_post_start_hooks() {
+            let result = Process::new(hook)
+                .set_environment_variables(vec![
+                    ("QEMU_PID", pid.as_str()),
+                    ("VM_NAME", self.config.get_machine_name()),
+                    ("PINNING_MAP", pinning_map.as_str()),
+                ])
+                .set_clock(self.clock.clone())
+                .run(POST_START_HOOK_TIMEOUT);
+
+            if let Err(e) = result {
+                output::error(format!("The `{}` post-start hook failed: {}", hook, e));
+            }
+        }
+    }
+
+    fn cleanup(&mut self) {
+        if let Err(e) = self.state.clear_ready() {
+            output::error(e);
+        }
+
+        if let Err(e) = self.storage.stop() {
+            output::error(e);
+        }
+
+        if let Err(e) = self.netboot.stop() {
+            output::error(e);
+        }
+
+        if let Err(e) = self.tuning.restore() {
+            output::error(e);
+        }
+
+        if let Err(e) = self.resctrl.remove() {
+            output::error(e);
+        }
+
+        if let Err(e) = self.sriov.restore() {
+            output::error(e);
+        }
+
+        remove_firewall_rules(&self.state);
+
+        if let Err(e) = self.state.clear_throttle() {
+            output::error(e);
+        }
+
+        for (id, result) in self.cpuset.release_threads() {
+            if let Err(e) = result {
+                output::error(format!(
+                    "Failed to release the pinned host CPU `{}`: {}",
+                    id, e
+                ));
+            }
+        }
+
+        if self.env.should_unmount_cpuset() {
+            if let Err(e) = self.cpuset.unmount() {
+                output::error(e);
+            }
+        }
+
+        // A launch which failed before qemu was spawned has nothing to report
+        if self.pid.is_some() {
+            self.events.emit("cleanup-done", object! {});
+        }
+    }
+}
+
+impl Drop for VmHandle<'_> {
+    fn drop(&mut self) {
+        if !self.owned {
+            return;
+        }
+
+        // Also covers the launcher crashing while qemu keeps running
+        if self.is_running() {
+            if let Err(e) = pinning_backend(&mut self.cpuset, &mut self.affinity).evict() {
+                output::error(format!(
+                    "Failed to release the pinned host CPUs of the still running machine: {}",
+                    e
+                ));
+            }
+            return;
+        }
+
+        self.cleanup();
+    }
+}
+
+// The uptime advances ahead of the monotonic clock by the time the host spent suspended since the
+// previous check
+fn get_suspended_time(clock: &mut Option<(Instant, Duration)>) -> Option<Duration> {
+    let (checked_at, uptime) = (*clock)?;
+    let now = Instant::now();
+    let current = host::get_uptime().ok()?;
+
+    *clock = Some((now, current));
+
+    current
+        .checked_sub(uptime)?
+        .checked_sub(now - checked_at)
+        .filter(|suspended| *suspended >= SUSPEND_THRESHOLD)
+}
+
+fn open_qmp_log(
+    env: &Environment,
+    config: &config::Config,
+    debug: bool,
+) -> Option<Box<dyn Write + Send>> {
+    match config.get_qmp_log() {
+        Some(config::QmpLog::File) => {
+            let directory = format!("{}/{}", env.get_log_directory(), config.get_machine_name());
+            let path = format!("{}/qmp.log", directory);
+
+            match fs::create_dir_all(&directory)
+                .and_then(|_| fs::OpenOptions::new().create(true).append(true).open(&path))
+            {
+                Ok(file) => Some(Box::new(file)),
+                Err(e) => {
+                    output::error(format!("Failed to open the QMP log file `{}`: {}", path, e));
+                    None
+                }
+            }
+        }
+        Some(config::QmpLog::Main) => Some(Box::new(io::stderr())),
+        None if debug => Some(Box::new(io::stderr())),
+        None => None,
+    }
+}
+
+// Gives the user who started the launcher with sudo access to the machine files, so the machine
+// can be managed without elevated privileges
+fn hand_over_artifacts(
+    env: &Environment,
+    config: &config::Config,
+    state: &StateDirectory,
+    uid: u32,
+    gid: Option<u32>,
+) {
+    // qemu writes into the state directory of relative paths, so it stays owned by the qemu user
+    if let Err(e) = state.hand_over(uid, gid, !config.is_cwd_relative()) {
+        output::error(e);
+    }
+
+    let log_directory = format!("{}/{}", env.get_log_directory(), config.get_machine_name());
+    let mut paths = vec![format!("{}/qmp.log", log_directory), log_directory];
+    paths.extend(config.get_qmp_socket());
+    paths.extend(config.get_qmp_proxy());
+
+    for path in paths
+        .iter()
+        .filter(|path| std::path::Path::new(path).exists())
+    {
+        if let Err(e) = nix::unistd::chown(
+            path.as_str(),
+            Some(nix::unistd::Uid::from_raw(uid)),
+            gid.map(nix::unistd::Gid::from_raw),
+        ) {
+            output::error(format!("Failed to change the owner of `{}`: {}", path, e));
+        }
+    }
+}
+
+fn check_thp_policy(thp: config::Thp) {
+    let host = match host::get_thp_policy() {
+        Ok(policy) => policy,
+        Err(e) => {
+            output::error(e);
+            return;
+        }
+    };
+
+    let (requested, conflicts) = match thp {
+        config::Thp::Always => ("always", host != "always"),
+        config::Thp::Madvise => ("madvise", host == "never"),
+        config::Thp::Never => ("never", false),
+    };
+
+    if conflicts {
+        output::warning(format!(
+            "The host transparent hugepage policy `{}` conflicts with the requested `{}` policy.",
+            host, requested
+        ));
+    }
+}
+
+// qemu only fails on an inaccessible image once it runs with the dropped privileges, with a message
+// naming a single path, so every path is checked upfront
+fn check_qemu_access(config: &Config) -> Result<(), Error> {
+    let uid = config
+        .get_user()
+        .map_or_else(|| nix::unistd::geteuid().as_raw(), u32::from);
+    let gid = config
+        .get_group()
+        .map_or_else(|| nix::unistd::getegid().as_raw(), u32::from);
+
+    let required = permissions::required_access(config);
+    let mut denials = permissions::find_denials(&required, uid, gid)?;
+
+    if config.should_fix_permissions() && denials.len() > 0 {
+        permissions::fix_ownership(&denials, config.get_user(), config.get_group())?;
+        denials = permissions::find_denials(&required, uid, gid)?;
+    }
+
+    if denials.len() == 0 {
+        return Ok(());
+    }
+
+    Err(Error::new(
+        ErrorKind::PermissionDenied,
+        format!(
+            "The qemu user `{}` and group `{}` can not access: {}.",
+            uid,
+            gid,
+            denials
+                .iter()
+                .map(|denial| denial.describe())
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+    ))
+}
+
+// Kernels without io_uring fail every request of the disks, so the machine is refused instead
+fn check_io_uring(config: &Config) -> Result<(), Error> {
+    let message = match host::get_io_uring_support() {
+        Ok(host::IoUring::Available) => return Ok(()),
+        Ok(host::IoUring::Restricted(group)) => {
+            if group.is_none() || group != config.get_group().map(u32::from) {
+                output::warning(
+                    "io_uring is restricted by `kernel.io_uring_disabled` on the host, qemu needs to \
+                    run within the `kernel.io_uring_group` group to use `aio=io_uring`.",
+                );
+            }
+
+            return Ok(());
+        }
+        Ok(host::IoUring::Disabled) => String::from(
+            "io_uring is disabled by `kernel.io_uring_disabled` on the host, `aio=io_uring` can not \
+            be used.",
+        ),
+        Ok(host::IoUring::Unsupported(release)) => format!(
+            "The host kernel `{}` does not support io_uring, `aio=io_uring` requires 5.1 or newer.",
+            release
+        ),
+        Err(e) => {
+            output::error(e);
+            return Ok(());
+        }
+    };
+
+    Err(Error::new(ErrorKind::InvalidInput, message))
+}
+
+// A higher inherited limit is kept, the hard limit is only raised when it is in the way
+fn raise_open_files_limit(limit: u64) -> Result<(), Error> {
+    let (soft, hard) = getrlimit(Resource::NOFILE)?;
+    let limit = Rlim::from_raw(limit);
+
+    if soft >= limit {
+        return Ok(());
+    }
+
+    if let Err(e) = setrlimit(Resource::NOFILE, limit, hard.max(limit)) {
+        return Err(Error::new(
+            e.kind(),
+            format!(
+                "Failed to raise the open files limit to `{}`, raising the hard limit requires \
+                `CAP_SYS_RESOURCE`: {}",
+                limit.as_raw(),
+                e
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+fn report_env_vars(qemu_binary: &str, env_vars: &[config::EnvVar]) {
+    eprintln!("Environment of the `{}` child process:", qemu_binary);
+
+    for var in env_vars {
+        let mut sources = var.get_source().describe().to_owned();
+        if var.get_overrides().len() > 0 {
+            let overrides: Vec<&str> = var.get_overrides().iter().map(|s| s.describe()).collect();
+            sources.push_str(&format!(", overrides {}", overrides.join(", ")));
+        }
+
+        eprintln!("  {}={} ({})", var.get_name(), var.get_value(), sources);
+    }
+}
+
+fn verify_binary(qemu_binary: &str, expected: &str) -> Result<(), String> {
+    let path = integrity::resolve_binary(qemu_binary);
+
+    let modified = match integrity::get_modification_time(&path) {
+        Some(timestamp) => format!("{} (unix time)", timestamp),
+        None => String::from("unknown"),
+    };
+    let origin = integrity::get_package_origin(&path).unwrap_or_else(|| String::from("unknown"));
+
+    eprintln!(
+        "The qemu binary `{}` was modified at {}, package: {}",
+        path.display(),
+        modified,
+        origin
+    );
+
+    let actual = match integrity::sha256_file(&path) {
+        Ok(digest) => digest,
+        Err(e) => return Err(format!("Failed to verify the qemu binary: {}", e)),
+    };
+
+    if actual != expected {
+        return Err(format!(
+            "The SHA-256 digest `{}` of the qemu binary `{}` does not match the `launcher.binary_sha256` \
+            value `{}`, refusing to launch.",
+            actual,
+            path.display(),
+            expected
+        ));
+    }
+
+    Ok({})
+}
+
+fn wait_for_dependencies(
+    env: &Environment,
+    clock: &dyn Clock,
+    config: &config::Config,
+) -> Result<(), Error> {
+    let deadline = clock.now() + Duration::from_secs(env.get_dependency_timeout());
+
+    for machine in config.get_dependencies() {
+        let state = StateDirectory::new(env.get_state_directory(), machine);
+
+        if !clock::wait_until(clock, deadline, Duration::from_secs(1), || state.is_ready()) {
+            return Err(Error::new(
+                ErrorKind::TimedOut,
+                format!(
+                    "The `{}` machine did not become ready in {} seconds.",
+                    machine,
+                    env.get_dependency_timeout()
+                ),
+            ));
+        }
+    }
+
+    Ok({})
+}
+
+// The host CPUs pinned by every running machine, as recorded in their state directories
+fn get_running_pinned_host_cpus(env: &Environment) -> Vec<(String, Vec<usize>)> {
+    let machines = match fs::read_dir(env.get_state_directory()) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect(),
+        Err(_) => vec![],
+    };
+
+    let mut pinned = vec![];
+    for machine in machines {
+        let state = StateDirectory::new(env.get_state_directory(), &machine);
+        if !state.read_pid().map_or(false, is_process_alive) {
+            continue;
+        }
+
+        let cpus = state.read_pinned_host_cpus();
+        pinned.push((machine, cpus));
+    }
+
+    pinned
+}
+
+fn collect_stale_cpusets(env: &Environment, cpuset: &cpuset::CpuSet) {
+    let tracked = || {
+        let mut tracked = vec![];
+        for (machine, cpus) in get_running_pinned_host_cpus(env) {
+            let thread_name = env.get_cpuset_thread_name().replace("{vm}", &machine);
+            for cpu in cpus {
+                tracked.push(thread_name.replace("{cpu}", &cpu.to_string()));
+            }
+        }
+
+        tracked
+    };
+
+    match cpuset.collect_garbage(tracked) {
+        Ok(removed) if removed.len() > 0 => output::warning(format!(
+            "Removed stale cpuset directories not used by any running machine: {}.",
+            removed
+                .iter()
+                .map(|name| format!("`{}`", name))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )),
+        Ok(_) => {}
+        Err(e) => output::error(format!("Failed to remove stale cpuset directories: {}", e)),
+    }
+}
+
+// The machine configuration can move its cpusets away from the ones of other machines, e.g. to
+// dedicate a prefix to every tenant
+pub fn cpuset_location<'e>(env: &'e Environment, config: &'e Config) -> (&'e str, &'e str) {
+    (
+        config
+            .get_cpuset_mount_path()
+            .unwrap_or(env.get_cpuset_mount_path()),
+        config
+            .get_cpuset_prefix()
+            .unwrap_or(env.get_cpuset_prefix()),
+    )
+}
+
+// Mounting the cpuset tree needs `CAP_SYS_ADMIN`, while an already mounted tree can be delegated
+// to an unprivileged launcher by handing over the prefix directory
+fn can_manage_cpusets(mount_path: &str, prefix: &str) -> bool {
+    // Unreadable capabilities are not a reason to refuse pinning, cpuset errors explain the rest
+    let has_capability = |capability| host::has_effective_capability(capability).unwrap_or(true);
+
+    // The cgroup files are owned by root even when a service account mounts the tree
+    if has_capability(CAP_SYS_ADMIN) && has_capability(CAP_DAC_OVERRIDE) {
+        return true;
+    }
+
+    let delegated = Path::new(mount_path).join(prefix);
+
+    access(&delegated, AccessFlags::W_OK).is_ok()
+}
+
+fn pinning_backend<'a>(
+    cpuset: &'a mut cpuset::CpuSet,
+    affinity: &'a mut Option<AffinityBackend>,
+) -> &'a mut dyn PinningBackend {
+    match affinity {
+        Some(affinity) => affinity,
+        None => cpuset,
+    }
+}
+
+fn reload_config(
+    env: &Environment,
+    config: &config::Config,
+    vcpu_info: Option<&qmp::Topology>,
+    backend: &mut dyn PinningBackend,
+    helpers: &process::OneshotPool,
+    pin_status: &mut Vec<PinStatus>,
+) -> Option<config::Config> {
+    // A configuration failing to load or validate is reported and the current one is kept
+    let reloaded = load_config(env, config.get_machine_name())?;
+    let (live, restart) = config.get_changes(&reloaded);
+
+    for key in &restart {
+        output::warning(format!(
+            "The `{}` change requires restarting the machine, it is not applied.",
+            key
+        ));
+    }
+
+    if live.len() < 1 {
+        return None;
+    }
+
+    let changed = |keys: &[&str]| live.iter().any(|key| keys.contains(&key.as_str()));
+    let pinning_changed = changed(&[
+        "launcher.uclamp",
+        "launcher.vcpu_pinning",
+        "launcher.vcpu_pinning_by_index",
+    ]);
+    let scheduling_changed = changed(&[
+        "launcher.deadline",
+        "launcher.priority",
+        "launcher.scheduler",
+    ]);
+
+    // The thermal throttle policy is picked up by the main loop, the rest is applied to the vCPU threads
+    if vcpu_info.is_none() && (pinning_changed || scheduling_changed) {
+        output::warning(format!(
+            "The vCPU threads are unknown, since vCPU pinning was not done at launch, \
+            these changes require restarting the machine: `{}`.",
+            live.join("`, `")
+        ));
+        return None;
+    }
+
+    let updated = match config.with_live_changes(&reloaded) {
+        Ok(updated) => updated,
+        Err(e) => {
+            output::error(format!(
+                "Failed to apply the reloaded configuration, keeping the current one: {}",
+                e
+            ));
+            return None;
+        }
+    };
+
+    if let Some(vcpu_info) = vcpu_info {
+        if pinning_changed {
+            *pin_status = apply_vcpu_pinning(vcpu_info, backend, &updated);
+        }
+
+        if scheduling_changed {
+            apply_vcpu_scheduling(vcpu_info, &updated, true, helpers);
+        }
+    }
+
+    eprintln!(
+        "Applied the reloaded configuration changes: `{}`.",
+        live.join("`, `")
+    );
+
+    Some(updated)
+}
+
+fn guest_action_commands(action: Option<config::GuestShutdown>) -> Vec<JsonValue> {
+    match action {
+        None | Some(config::GuestShutdown::Ignore) => vec![],
+        Some(config::GuestShutdown::Quit) => vec![object! { "execute": "quit" }],
+        Some(config::GuestShutdown::Restart) => vec![
+            object! { "execute": "system_reset" },
+            object! { "execute": "cont" },
+        ],
+    }
+}
+
+// Reports guest resets, shutdowns and watchdog expiries, answering shutdowns and expiries with the
+// commands of the configured actions. Every event is mirrored into the event sink, if configured.
+fn guest_event_handler(
+    config: &config::Config,
+    sender: Sender<(String, JsonValue)>,
+) -> impl FnMut(&str, &JsonValue) -> Vec<JsonValue> {
+    let shutdown_commands = guest_action_commands(config.get_on_guest_shutdown());
+    let watchdog = config.get_watchdog_action();
+    let watchdog_commands = guest_action_commands(watchdog);
+    let mut sink =
+        config
+            .get_event_sink()
+            .and_then(|sink| match event_sink::QmpEventSink::open(&sink) {
+                Ok(sink) => Some(sink),
+                Err(e) => {
+                    output::error(e);
+                    None
+                }
+            });
+
+    move |event, data| {
+        if let Some(sink) = sink.as_mut() {
+            sink.write(event, data);
+        }
+
+        match event {
+            // Resets and shutdowns requested by the launcher itself are not guest actions
+            "RESET" | "SHUTDOWN" if data["guest"] == true => {}
+            "WATCHDOG" if watchdog.is_some() => {}
+            _ => return vec![],
+        }
+
+        let _ = sender.send((event.to_owned(), data.clone()));
+
+        match event {
+            "SHUTDOWN" => shutdown_commands.clone(),
+            "WATCHDOG" => watchdog_commands.clone(),
+            _ => vec![],
+        }
+    }
+}
+
+// Serves the QMP events on a separate thread, so guest actions are handled while the launcher
+// sleeps. The guest initiated ones are passed back to the main loop to be reported.
+fn watch_guest_events(
+    pipe: impl QmpPipe + Send + 'static,
+    config: &config::Config,
+    qmp_log: Option<Box<dyn Write + Send>>,
+    clock: Arc<dyn Clock>,
+) -> Receiver<(String, JsonValue)> {
+    let (sender, receiver) = mpsc::channel();
+    let handler = guest_event_handler(config, sender);
+
+    thread::spawn(move || {
+        let qmp_log = qmp_log.map(|log| log as Box<dyn Write>);
+        let result = qmp::watch_events(pipe, qmp_log, clock, handler);
+
+        if let Err(e) = result {
+            output::error(format!("Stopped watching the guest events: {}", e));
+        }
+    });
+
+    receiver
+}
+
+// Shares the private QMP channel with the clients of the proxy socket, while still watching the guest
+// events for the launcher
+fn serve_qmp_proxy(
+    stdio: process::ChildStdio,
+    path: String,
+    listener: UnixListener,
+    config: &config::Config,
+    qmp_log: Option<Box<dyn Write + Send>>,
+) -> Receiver<(String, JsonValue)> {
+    let (sender, receiver) = mpsc::channel();
+    let handler = guest_event_handler(config, sender);
+
+    thread::spawn(move || {
+        let (qemu_output, qemu_input) = stdio.into_split();
+        let qmp_log = qmp_log.map(|log| log as Box<dyn Write>);
+
+        if let Err(e) = qmp_proxy::serve(&path, listener, qemu_output, qemu_input, qmp_log, handler)
+        {
+            output::error(format!("Stopped serving the QMP proxy socket: {}", e));
+        }
+    });
+
+    receiver
+}
+
+fn report_guest_event(events: &Events, event: &str, data: &JsonValue, config: &config::Config) {
+    let reason = data["reason"].as_str().unwrap_or("unknown");
+
+    match (event, config.get_on_guest_shutdown()) {
+        ("SHUTDOWN", Some(action)) => {
+            let (name, outcome) = match action {
+                config::GuestShutdown::Ignore => ("ignore", "leaving the machine stopped"),
+                config::GuestShutdown::Quit => ("quit", "quitting qemu"),
+                config::GuestShutdown::Restart => ("restart", "restarting the machine"),
+            };
+
+            eprintln!("The guest has shut down (`{}`), {}.", reason, outcome);
+            events.emit(
+                "guest-shutdown",
+                object! { "reason": reason, "action": name },
+            );
+        }
+        ("RESET", _) => {
+            eprintln!("The guest has reset (`{}`).", reason);
+            events.emit("guest-reset", object! { "reason": reason });
+        }
+        ("WATCHDOG", _) => {
+            if let Some(action) = config.get_watchdog_action() {
+                let (name, outcome) = match action {
+                    config::GuestShutdown::Ignore => ("ignore", "leaving the machine running"),
+                    config::GuestShutdown::Quit => ("quit", "quitting qemu"),
+                    config::GuestShutdown::Restart => ("restart", "restarting the machine"),
+                };
+
+                eprintln!("The guest watchdog has expired, {}.", outcome);
+                events.emit("guest-watchdog", object! { "action": name });
+            }
+        }
+        _ => {}
+    }
+}
+
+fn connect_qmp_socket(config: &config::Config) -> Result<UnixStream, io::Error> {
+    let path = match config.get_qmp_socket() {
+        Some(path) => path,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Unable to adopt the running machine: `launcher.qmp_socket` is not configured.",
+            ))
+        }
+    };
+
+    match UnixStream::connect(&path) {
+        Ok(stream) => Ok(stream),
+        Err(e) => Err(io::Error::new(
+            e.kind(),
+            format!("Failed to connect to the QMP socket `{}`: {}", path, e),
+        )),
+    }
+}
+
+fn handle_vcpu_pinning(
+    qmp_socket: impl QmpPipe,
+    backend: &mut dyn PinningBackend,
+    config: &config::Config,
+    startup_commands: &[JsonValue],
+    qmp_log: Option<Box<dyn Write + Send>>,
+    events: &Events,
+    helpers: &process::OneshotPool,
+    clock: Arc<dyn Clock>,
+) -> Option<(qmp::Topology, Vec<PinStatus>)> {
+    let qmp_log = qmp_log.map(|log| log as Box<dyn Write>);
+    let vcpu_info = match qmp::run_startup_commands(qmp_socket, qmp_log, clock, startup_commands) {
+        Ok((vcpu_info, failures)) => {
+            for failure in failures {
+                // Commands missing from older qemu binaries are expected when configurations are shared
+                match qmp::as_qmp_error(&failure).map(|e| e.get_class()) {
+                    Some(qmp::QmpErrorClass::CommandNotFound) => output::warning(failure),
+                    _ => output::error(failure),
+                }
+            }
+            vcpu_info
+        }
+        Err(e) => {
+            output::error(format!(
+                "Failed to obtain vCPU mapping info from QEMU: {}",
+                e
+            ));
+            return None;
+        }
+    };
+
+    events.emit(
+        "qmp-negotiated",
+        object! { "vcpu_threads": vcpu_info.get_task_ids().len() },
+    );
+
+    if !config.has_cpu_pinning() {
+        return Some((vcpu_info, vec![]));
+    }
+
+    let pin_status = apply_vcpu_pinning(&vcpu_info, backend, config);
+    apply_vcpu_scheduling(&vcpu_info, config, false, helpers);
+
+    events.emit(
+        "pinned",
+        object! {
+            "host_cpus": config.get_pinned_host_cpus(),
+            "scheduler": config.get_scheduler().clone(),
+            "priority": config.get_priority(),
+        },
+    );
+
+    Some((vcpu_info, pin_status))
+}
+
+// Pinned vCPUs as space separated `<vCPU>:<host CPU>:<thread ID>` triples, e.g. `0.0.0:2:4242 0.0.1:3:4243`
+fn pinning_map(pin_status: &[PinStatus]) -> String {
+    pin_status
+        .iter()
+        .filter(|status| status.is_pinned())
+        .filter_map(|status| {
+            let task_id = status.get_task_id()?;
+
+            Some(format!(
+                "{}:{}:{}",
+                status.get_vcpu(),
+                status.get_host_cpu(),
+                task_id
+            ))
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// Guests without dies or clusters have every vCPU in die and cluster 0
+fn get_pinned_thread_id(vcpu_info: &qmp::Topology, pin: &config::VcpuPin) -> Option<usize> {
+    vcpu_info.get_thread_id(
+        pin.get_socket(),
+        pin.get_die().unwrap_or(0),
+        pin.get_cluster().unwrap_or(0),
+        pin.get_core(),
+        pin.get_thread(),
+    )
+}
+
+fn apply_vcpu_pinning(
+    vcpu_info: &qmp::Topology,
+    backend: &mut dyn PinningBackend,
+    config: &config::Config,
+) -> Vec<PinStatus> {
+    let mut pins = vec![];
+    for pin in config.get_cpu_pinning() {
+        pins.push((
+            pin.get_path(),
+            get_pinned_thread_id(vcpu_info, pin),
+            pin.get_host_cpu(),
+        ));
+    }
+    for (index, host_id) in config.get_cpu_pinning_by_index() {
+        pins.push((
+            index.to_string(),
+            vcpu_info.get_thread_id_by_index(*index),
+            *host_id,
+        ));
+    }
+
+    let mut status = vec![];
+    let mut resolved = vec![];
+    for (vcpu, task_id, host_id) in pins {
+        match task_id {
+            Some(task_id) => resolved.push((vcpu, task_id, host_id)),
+            None => {
+                output::error(format!(
+                    "The vCPU core `{}` does not exist, unable to pin.",
+                    vcpu
+                ));
+                status.push(PinStatus {
+                    error: Some(String::from("the vCPU does not exist")),
+                    host_cpu: host_id,
+                    task_id: None,
+                    vcpu,
+                });
+            }
+        }
+    }
+
+    let tasks: Vec<(usize, usize)> = resolved
+        .iter()
+        .map(|(_, task_id, host_id)| (*host_id, *task_id))
+        .collect();
+    let results = backend.pin_tasks(&tasks);
+
+    for ((vcpu, task_id, host_id), result) in resolved.into_iter().zip(results) {
+        if let Err(e) = &result {
+            output::error(format!(
+                "Failed to pin the vCPU `{}` core task ID `{}` to the host CPU `{}`: {}",
+                vcpu, task_id, host_id, e
+            ));
+        }
+
+        if let Some(clamp) = config.get_util_clamp(host_id) {
+            if let Err(e) = process::set_util_clamp(task_id as i32, &clamp) {
+                output::error(format!(
+                    "Failed to set utilization clamps of the vCPU `{}` core task ID `{}`: {}",
+                    vcpu, task_id, e
+                ));
+            }
+        }
+
+        status.push(PinStatus {
+            error: result.err().map(|e| e.to_string()),
+            host_cpu: host_id,
+            task_id: Some(task_id),
+            vcpu,
+        });
+    }
+
+    status
+}
+
+// Pins the vhost worker of every queue to the host CPU of the vCPU the guest handles the queue on,
+// queues are spread over the pinned vCPUs in order, wrapping around when there are more queues
+fn apply_queue_pinning(
+    pid: i32,
+    vcpu_info: &qmp::Topology,
+    backend: &mut dyn PinningBackend,
+    config: &config::Config,
+) {
+    let workers = match host::get_vhost_workers(pid) {
+        Ok(workers) => workers,
+        Err(e) => {
+            output::error(format!(
+                "Failed to discover the vhost workers of qemu, unable to pin network queues: {}",
+                e
+            ));
+            return;
+        }
+    };
+
+    // Workers can only be told apart by their creation order, which is lost if the count differs
+    let interfaces = config.get_vhost_queues();
+    let queues: usize = interfaces.iter().map(|(_, queues, _)| queues).sum();
+    if workers.len() != queues {
+        output::error(format!(
+            "Unable to pin network queues: found {} vhost workers, while {} vhost queues are configured.",
+            workers.len(),
+            queues
+        ));
+        return;
+    }
+
+    let mut host_cpus = vec![];
+    let mut index = 0;
+    while let Some(task_id) = vcpu_info.get_thread_id_by_index(index) {
+        let by_index = config
+            .get_cpu_pinning_by_index()
+            .iter()
+            .find(|(vcpu, _)| *vcpu == index)
+            .map(|(_, host_id)| *host_id);
+        let by_topology = config
+            .get_cpu_pinning()
+            .iter()
+            .find(|pin| get_pinned_thread_id(vcpu_info, pin) == Some(task_id))
+            .map(|pin| pin.get_host_cpu());
+
+        host_cpus.extend(by_index.or(by_topology));
+        index += 1;
+    }
+
+    if host_cpus.len() < 1 {
+        output::error("Unable to pin network queues: none of the vCPUs is pinned.");
+        return;
+    }
+
+    let mut workers = workers.into_iter();
+    for (id, queues, pin) in interfaces {
+        for queue in 0..queues {
+            let worker = workers.next().unwrap();
+            if !pin {
+                continue;
+            }
+
+            let host_id = host_cpus[queue % host_cpus.len()];
+            if let Err(e) = backend.pin(host_id, worker) {
+                output::error(format!(
+                    "Failed to pin the vhost worker `{}` of the `{}` network queue {} to the host CPU `{}`: {}",
+                    worker, id, queue, host_id, e
+                ));
+            }
+        }
+    }
+}
+
+fn apply_vcpu_scheduling(
+    vcpu_info: &qmp::Topology,
+    config: &config::Config,
+    reset: bool,
+    helpers: &process::OneshotPool,
+) {
+    let task_ids = vcpu_info.get_task_ids();
+
+    if config.has_scheduling() {
+        let scheduler = config.get_scheduler().clone().unwrap();
+        let priority = config.get_priority().unwrap().to_string();
+
+        let commands = task_ids
+            .iter()
+            .map(|task_id| {
+                (
+                    String::from("chrt"),
+                    vec![
+                        format!("--{}", scheduler),
+                        String::from("--pid"),
+                        priority.clone(),
+                        task_id.to_string(),
+                    ],
+                )
+            })
+            .collect();
+
+        for (task_id, result) in task_ids.iter().zip(helpers.oneshot_all(commands)) {
+            match result {
+                Ok(_) => {} // TODO: debug
+                Err(e) => output::error(format!(
+                    "Failed to change vCPU thread `{}` priority: {}",
+                    task_id, e
+                )),
+            }
+        }
+    }
+
+    if let Some(deadline) = config.get_deadline() {
+        for task_id in &task_ids {
+            if let Err(e) = process::set_deadline_scheduler(*task_id as i32, &deadline) {
+                output::error(format!(
+                    "Failed to set the deadline scheduler for vCPU thread `{}`: {}",
+                    task_id, e
+                ));
+            }
+        }
+    }
+
+    // Threads are moved back to the default policy, when the scheduling was removed on reload
+    if reset && !config.has_scheduling() && config.get_deadline().is_none() {
+        let commands = task_ids
+            .iter()
+            .map(|task_id| {
+                (
+                    String::from("chrt"),
+                    vec![
+                        String::from("--other"),
+                        String::from("--pid"),
+                        String::from("0"),
+                        task_id.to_string(),
+                    ],
+                )
+            })
+            .collect();
+
+        for (task_id, result) in task_ids.iter().zip(helpers.oneshot_all(commands)) {
+            if let Err(e) = result {
+                output::error(format!(
+                    "Failed to reset vCPU thread `{}` scheduling policy: {}",
+                    task_id, e
+                ));
+            }
+        }
+    }
+}
+
+fn launch(
+    env: &Environment,
+    clock: &dyn Clock,
+    config: &config::Config,
+    state: &StateDirectory,
+    sriov: &mut Sriov,
+    storage: &mut StorageDaemons,
+    netboot: &mut NetbootServer,
+    helpers: &process::OneshotPool,
+    debug: bool,
+) -> Result<(ChildProcess, Option<JoinHandle<()>>), Error> {
+    wait_for_dependencies(env, clock, config)?;
+
+    if let Some(expected) = config.get_binary_sha256() {
+        if let Err(e) = verify_binary(config.get_qemu_binary_path(), expected) {
+            return Err(Error::new(ErrorKind::InvalidData, e));
+        }
+    }
+
+    for path in config.get_paths() {
+        provision::provision(path)?;
+    }
+
+    storage.start(config, helpers)?;
+    netboot.start(config)?;
+
+    // The log left behind by the previous run is appended to, unless it is already too big
+    if let Some(log) = config.get_serial_log() {
+        let path = config.get_serial_log_path(log);
+        if let Err(e) = serial_log::rotate(&path, log.get_max_size(), log.get_keep()) {
+            output::warning(e);
+        }
+    }
+
+    let mut arguments = config.get_command_line_options();
+
+    for interface in config.get_sriov_interfaces() {
+        match sriov.create_virtual_functions(interface) {
+            Ok(addresses) => {
+                for address in addresses {
+                    arguments.push(String::from("-device"));
+                    arguments.push(format!("vfio-pci,host={}", address));
+                }
+            }
+            Err(e) => {
+                return Err(Error::new(
+                    e.kind(),
+                    format!(
+                        "Failed to prepare SR-IOV virtual functions of `{}`: {}",
+                        interface.get_interface(),
+                        e
+                    ),
+                ))
+            }
+        }
+    }
+
+    if config.rlimit_memlock() {
+        setrlimit(Resource::MEMLOCK, Rlim::INFINITY, Rlim::INFINITY)?;
+    }
+
+    if let Some(limit) = config.get_open_files_limit() {
+        raise_open_files_limit(limit)?;
+    }
+
+    if let Err(e) = state.write_launch_record(config) {
+        output::error(e);
+    }
+
+    // The launch record keeps the full command line, only qemu gets the options through the file
+    if config.should_use_readconfig() {
+        let path = state.get_readconfig_path().display().to_string();
+        let (contents, remaining) =
+            readconfig::render(config.get_machine_name(), &arguments, &path);
+
+        if let Some(contents) = contents {
+            state.write_readconfig(&contents)?;
+            arguments = remaining;
+        }
+    }
+
+    if config.is_cwd_relative() {
+        if let Err(e) = state.set_owner(config.get_user(), config.get_group()) {
+            output::error(e);
+        }
+    }
+
+    if config.get_user().is_some() || config.get_group().is_some() {
+        check_qemu_access(config)?;
+    }
+
+    if let Some(rules) = config.get_firewall() {
+        let table = firewall::table_name(config.get_machine_name());
+
+        // The table is recorded first, so the `cleanup` command finds partially applied rules
+        if let Err(e) = state.write_firewall_table(&table) {
+            output::error(e);
+        }
+
+        firewall::apply(&table, rules)?;
+    }
+
+    let env_vars = config.resolve_env_vars(std::env::vars());
+
+    if debug {
+        report_env_vars(config.get_qemu_binary_path(), &env_vars);
+    }
+
+    let mut child = match Process::new(config.get_qemu_binary_path())
+        .set_args(arguments)
+        .set_effective_group_id(&config.get_group())
+        .set_effective_user_id(&config.get_user())
+        .should_capture_stderr(config.should_merge_qemu_log())
+        .should_disable_thp(config.get_thp() == Some(config::Thp::Never))
+        .should_clear_ambient_capabilities(true)
+        .should_clear_env(true)
+        .set_environment_variables(env_vars.iter().map(|var| (var.get_name(), var.get_value())))
+        .spawn()
+    {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to execute the `{}` child process: {}",
+                    config.get_qemu_binary_path(),
+                    e
+                ),
+            ))
+        }
+    };
+
+    // The reader starts right away, so a chatty qemu never stalls on a full stderr pipe
+    let qemu_log = child.take_stderr().map(qemu_log::drain);
+
+    if let Some(scope) = config.get_systemd_scope() {
+        if let Err(e) = systemd::start_scope(scope, config.get_machine_name(), child.id()) {
+            // The machine must not keep running outside of the requested scope
+            if let Err(e) = child.kill() {
+                output::error(e);
+            }
+            let _ = child.wait();
+            if let Some(qemu_log) = qemu_log {
+                let _ = qemu_log.join();
+            }
+
+            return Err(e);
+        }
+    }
+
+    if let Err(e) = state.write_pid(child.id()) {
+        output::error(e);
+    }
+
+    Ok((child, qemu_log))
+}
@@ -0,0 +1,506 @@
+use crate::xml::Element;
+use std::io::{Error, ErrorKind};
+use yaml_rust::{yaml::Hash, Yaml, YamlEmitter};
+
+pub struct Conversion {
+    yaml: String,
+    warnings: Vec<String>,
+}
+
+impl Conversion {
+    pub fn get_yaml(&self) -> &str {
+        &self.yaml
+    }
+
+    pub fn get_warnings(&self) -> &Vec<String> {
+        &self.warnings
+    }
+}
+
+pub fn convert_domain<S: AsRef<str>>(xml: S) -> Result<Conversion, Error> {
+    let domain = Element::parse(xml)?;
+
+    if domain.get_name() != "domain" {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Expected a libvirt `<domain>` definition, got `<{}>`.",
+                domain.get_name()
+            ),
+        ));
+    }
+
+    let mut warnings = vec![];
+    let mut launcher = Hash::new();
+    let mut qemu = vec![];
+
+    let binary = match domain
+        .get_child("devices")
+        .and_then(|devices| devices.get_child("emulator"))
+    {
+        Some(emulator) => emulator.get_text().to_owned(),
+        None => String::from("qemu-system-x86_64"),
+    };
+    launcher.insert(string("binary"), string(&binary));
+
+    if let Some(name) = domain.get_child("name") {
+        qemu.push(option("name", name.get_text()));
+    }
+
+    if let Some(machine) = domain
+        .get_child("os")
+        .and_then(|os| os.get_child("type"))
+        .and_then(|os_type| os_type.get_attribute("machine"))
+    {
+        match domain.get_attribute("type") {
+            Some("kvm") => qemu.push(option("machine", &format!("{},accel=kvm", machine))),
+            _ => qemu.push(option("machine", machine)),
+        }
+    }
+
+    if let Some(cpu) = domain.get_child("cpu") {
+        match cpu.get_attribute("mode") {
+            Some("host-passthrough") | Some("host-model") => qemu.push(option("cpu", "host")),
+            _ => {
+                if let Some(model) = cpu.get_child("model") {
+                    qemu.push(option("cpu", model.get_text()));
+                }
+            }
+        }
+    }
+
+    if let Some(memory) = domain.get_child("memory") {
+        let mebibytes = convert_memory(memory.get_text(), memory.get_attribute("unit"))?;
+        qemu.push(option("m", &format!("{}M", mebibytes)));
+    }
+
+    let topology = convert_topology(&domain)?;
+    if let Some((vcpus, sockets, cores, threads)) = topology {
+        qemu.push(option(
+            "smp",
+            &format!(
+                "{},sockets={},cores={},threads={}",
+                vcpus, sockets, cores, threads
+            ),
+        ));
+    }
+
+    if let Some(pinning) = convert_pinning(&domain, topology, &mut warnings)? {
+        launcher.insert(string("vcpu_pinning"), pinning);
+    }
+
+    if let Some(devices) = domain.get_child("devices") {
+        for disk in devices.get_children("disk") {
+            match convert_disk(disk) {
+                Some(drive) => qemu.push(option("drive", &drive)),
+                None => warnings.push(String::from(
+                    "Skipped a disk without a file or block device source.",
+                )),
+            }
+        }
+
+        let mut network = vec![];
+        for interface in devices.get_children("interface") {
+            match convert_interface(interface) {
+                Some(nic) => network.push(nic),
+                None => warnings.push(format!(
+                    "Skipped an unsupported `{}` network interface.",
+                    interface.get_attribute("type").unwrap_or("unknown")
+                )),
+            }
+        }
+
+        if network.len() > 0 {
+            launcher.insert(string("network"), Yaml::Array(network));
+        }
+    }
+
+    let mut document = Hash::new();
+    document.insert(string("launcher"), Yaml::Hash(launcher));
+    document.insert(string("qemu"), Yaml::Array(qemu));
+
+    let mut yaml = String::new();
+    if let Err(e) = YamlEmitter::new(&mut yaml).dump(&Yaml::Hash(document)) {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!("Failed to produce the YAML configuration: {:?}", e),
+        ));
+    }
+    yaml.push('\n');
+
+    Ok(Conversion { yaml, warnings })
+}
+
+fn string(value: &str) -> Yaml {
+    Yaml::String(value.to_owned())
+}
+
+fn option(name: &str, value: &str) -> Yaml {
+    let mut option = Hash::new();
+    option.insert(string(name), string(value));
+
+    Yaml::Hash(option)
+}
+
+fn parse_number(value: &str, what: &str) -> Result<usize, Error> {
+    value.trim().parse::<usize>().map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Invalid {} value: a positive integer expected, got `{}`.",
+                what, value
+            ),
+        )
+    })
+}
+
+fn convert_memory(amount: &str, unit: Option<&str>) -> Result<usize, Error> {
+    let amount = parse_number(amount, "`<memory>`")?;
+
+    match unit.unwrap_or("KiB") {
+        "b" | "bytes" => Ok(amount / 1024 / 1024),
+        "k" | "KiB" => Ok(amount / 1024),
+        "M" | "MiB" => Ok(amount),
+        "G" | "GiB" => Ok(amount * 1024),
+        "T" | "TiB" => Ok(amount * 1024 * 1024),
+        unit => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Unsupported `<memory>` unit: `{}`.", unit),
+        )),
+    }
+}
+
+fn convert_topology(domain: &Element) -> Result<Option<(usize, usize, usize, usize)>, Error> {
+    let vcpus = match domain.get_child("vcpu") {
+        Some(vcpu) => parse_number(vcpu.get_text(), "`<vcpu>`")?,
+        None => return Ok(None),
+    };
+
+    match domain
+        .get_child("cpu")
+        .and_then(|cpu| cpu.get_child("topology"))
+    {
+        Some(topology) => {
+            // The pinning divides vCPU indexes by the cores and threads, none of them can be zero
+            let get = |name: &str| match topology.get_attribute(name) {
+                Some(value) => match parse_number(value, &format!("`<topology {}>`", name))? {
+                    0 => Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "Invalid `<topology {}>` value: a positive integer expected, got `{}`.",
+                            name, value
+                        ),
+                    )),
+                    number => Ok(number),
+                },
+                None => Ok(1),
+            };
+
+            Ok(Some((
+                vcpus,
+                get("sockets")?,
+                get("cores")?,
+                get("threads")?,
+            )))
+        }
+        None => Ok(Some((vcpus, vcpus, 1, 1))),
+    }
+}
+
+fn convert_pinning(
+    domain: &Element,
+    topology: Option<(usize, usize, usize, usize)>,
+    warnings: &mut Vec<String>,
+) -> Result<Option<Yaml>, Error> {
+    let cputune = match domain.get_child("cputune") {
+        Some(cputune) => cputune,
+        None => return Ok(None),
+    };
+
+    let (_, _, cores, threads) = topology.unwrap_or((1, 1, 1, 1));
+    let mut sockets = Hash::new();
+
+    for pin in cputune.get_children("vcpupin") {
+        let vcpu = parse_number(pin.get_attribute("vcpu").unwrap_or(""), "`<vcpupin vcpu>`")?;
+        let cpuset = pin.get_attribute("cpuset").unwrap_or("");
+        let host = match cpuset.trim().parse::<usize>() {
+            Ok(host) => host,
+            Err(_) => {
+                warnings.push(format!(
+                    "Skipped pinning of vCPU {}: only a single host CPU is supported, got `{}`.",
+                    vcpu, cpuset
+                ));
+                continue;
+            }
+        };
+
+        let socket = Yaml::Integer((vcpu / (cores * threads)) as i64);
+        let core = Yaml::Integer(((vcpu / threads) % cores) as i64);
+        let thread = Yaml::Integer((vcpu % threads) as i64);
+
+        let cores = sockets
+            .entry(socket)
+            .or_insert_with(|| Yaml::Hash(Hash::new()));
+        if let Yaml::Hash(cores) = cores {
+            let threads = cores.entry(core).or_insert_with(|| Yaml::Hash(Hash::new()));
+            if let Yaml::Hash(threads) = threads {
+                threads.insert(thread, Yaml::Integer(host as i64));
+            }
+        }
+    }
+
+    if sockets.len() == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(Yaml::Hash(sockets)))
+}
+
+fn convert_disk(disk: &Element) -> Option<String> {
+    let source = disk.get_child("source")?;
+    let path = source
+        .get_attribute("file")
+        .or_else(|| source.get_attribute("dev"))?;
+
+    let mut parts = vec![format!("file={}", path)];
+
+    if let Some(format) = disk
+        .get_child("driver")
+        .and_then(|driver| driver.get_attribute("type"))
+    {
+        parts.push(format!("format={}", format));
+    }
+
+    match disk
+        .get_child("target")
+        .and_then(|target| target.get_attribute("bus"))
+    {
+        Some("virtio") => parts.push(String::from("if=virtio")),
+        Some("scsi") => parts.push(String::from("if=scsi")),
+        Some("ide") | Some("sata") => parts.push(String::from("if=ide")),
+        _ => {}
+    }
+
+    if let Some("cdrom") = disk.get_attribute("device") {
+        parts.push(String::from("media=cdrom"));
+    }
+
+    if disk.get_child("readonly").is_some() {
+        parts.push(String::from("readonly=on"));
+    }
+
+    Some(parts.join(","))
+}
+
+fn convert_interface(interface: &Element) -> Option<Yaml> {
+    let source = interface.get_child("source");
+
+    let netdev = match interface.get_attribute("type") {
+        Some("bridge") => format!("bridge,br={}", source?.get_attribute("bridge")?),
+        Some("ethernet") => format!(
+            "tap,ifname={},script=no,downscript=no",
+            interface.get_child("target")?.get_attribute("dev")?
+        ),
+        Some("user") => String::from("user"),
+        _ => return None,
+    };
+
+    let mut nic = Hash::new();
+    nic.insert(string("netdev"), string(&netdev));
+
+    if let Some(model) = interface
+        .get_child("model")
+        .and_then(|model| model.get_attribute("type"))
+    {
+        match model {
+            "virtio" => nic.insert(string("device"), string("virtio-net-pci")),
+            model => nic.insert(string("device"), string(model)),
+        };
+    }
+
+    if let Some(mac) = interface
+        .get_child("mac")
+        .and_then(|mac| mac.get_attribute("address"))
+    {
+        nic.insert(string("mac"), string(mac));
+    }
+
+    Some(Yaml::Hash(nic))
+}
+
+#[cfg(test)]
+mod test {
+    use super::convert_domain;
+    use crate::{assert_error, config::Config};
+    use std::io::ErrorKind;
+
+    #[test]
+    fn convert_domain_translates_common_libvirt_subset() {
+        let conversion = convert_domain(
+            "<domain type='kvm'>
+              <name>win10</name>
+              <memory unit='GiB'>8</memory>
+              <vcpu placement='static'>4</vcpu>
+              <cputune>
+                <vcpupin vcpu='0' cpuset='2'/>
+                <vcpupin vcpu='1' cpuset='6'/>
+                <vcpupin vcpu='2' cpuset='3'/>
+                <vcpupin vcpu='3' cpuset='4-5'/>
+              </cputune>
+              <os><type arch='x86_64' machine='pc-q35-5.0'>hvm</type></os>
+              <cpu mode='host-passthrough'>
+                <topology sockets='1' cores='2' threads='2'/>
+              </cpu>
+              <devices>
+                <emulator>/usr/bin/qemu-system-x86_64</emulator>
+                <disk type='file' device='disk'>
+                  <driver name='qemu' type='qcow2'/>
+                  <source file='/var/lib/vms/win10.qcow2'/>
+                  <target dev='vda' bus='virtio'/>
+                </disk>
+                <disk type='file' device='cdrom'>
+                  <source file='/iso/virtio.iso'/>
+                  <target dev='sda' bus='sata'/>
+                  <readonly/>
+                </disk>
+                <interface type='bridge'>
+                  <mac address='52:54:00:12:34:56'/>
+                  <source bridge='br0'/>
+                  <model type='virtio'/>
+                </interface>
+                <interface type='network'>
+                  <source network='default'/>
+                </interface>
+              </devices>
+            </domain>",
+        )
+        .unwrap();
+
+        assert_eq!(
+            &vec![
+                String::from(
+                    "Skipped pinning of vCPU 3: only a single host CPU is supported, got `4-5`."
+                ),
+                String::from("Skipped an unsupported `network` network interface."),
+            ],
+            conversion.get_warnings()
+        );
+
+        let config = Config::new(conversion.get_yaml()).unwrap();
+
+        assert_eq!("/usr/bin/qemu-system-x86_64", config.get_qemu_binary_path());
+        assert_eq!(
+            vec![
+                (String::from("0.0.0"), 2),
+                (String::from("0.0.1"), 6),
+                (String::from("0.1.0"), 3)
+            ],
+            config
+                .get_cpu_pinning()
+                .iter()
+                .map(|pin| (pin.get_path(), pin.get_host_cpu()))
+                .collect::<Vec<(String, usize)>>()
+        );
+        assert_eq!(
+            vec![
+                "-name",
+                "win10",
+                "-machine",
+                "pc-q35-5.0,accel=kvm",
+                "-cpu",
+                "host",
+                "-m",
+                "8192M",
+                "-smp",
+                "4,sockets=1,cores=2,threads=2",
+                "-drive",
+                "file=/var/lib/vms/win10.qcow2,format=qcow2,if=virtio",
+                "-drive",
+                "file=/iso/virtio.iso,if=ide,media=cdrom,readonly=on",
+                "-netdev",
+                "bridge,br=br0,id=net0",
+                "-device",
+                "virtio-net-pci,netdev=net0,mac=52:54:00:12:34:56",
+                "-qmp",
+                "stdio",
+            ],
+            config.get_command_line_options()
+        );
+    }
+
+    #[test]
+    fn convert_domain_returns_error_if_root_element_is_not_a_domain() {
+        assert_error!(
+            ErrorKind::InvalidData,
+            "Expected a libvirt `<domain>` definition, got `<network>`.",
+            convert_domain("<network/>")
+        );
+    }
+
+    #[test]
+    fn convert_domain_returns_error_on_zero_topology_values() {
+        assert_error!(
+            ErrorKind::InvalidData,
+            "Invalid `<topology cores>` value: a positive integer expected, got `0`.",
+            convert_domain(
+                "<domain>
+                    <vcpu>2</vcpu>
+                    <cpu><topology sockets='1' cores='0' threads='2'/></cpu>
+                    <cputune><vcpupin vcpu='1' cpuset='3'/></cputune>
+                </domain>"
+            )
+        );
+        assert_error!(
+            ErrorKind::InvalidData,
+            "Invalid `<topology threads>` value: a positive integer expected, got `0`.",
+            convert_domain(
+                "<domain>
+                    <vcpu>2</vcpu>
+                    <cpu><topology sockets='1' cores='2' threads='0'/></cpu>
+                    <cputune><vcpupin vcpu='1' cpuset='3'/></cputune>
+                </domain>"
+            )
+        );
+    }
+
+    #[test]
+    fn convert_domain_skips_pinning_to_negative_host_cpu() {
+        let conversion = convert_domain(
+            "<domain>
+                <vcpu>2</vcpu>
+                <cputune>
+                    <vcpupin vcpu='0' cpuset='-1'/>
+                    <vcpupin vcpu='1' cpuset='1'/>
+                </cputune>
+            </domain>",
+        )
+        .unwrap();
+
+        assert_eq!(
+            &vec![String::from(
+                "Skipped pinning of vCPU 0: only a single host CPU is supported, got `-1`."
+            )],
+            conversion.get_warnings()
+        );
+
+        let config = Config::new(conversion.get_yaml()).unwrap();
+
+        assert_eq!(
+            vec![(String::from("1.0.0"), 1)],
+            config
+                .get_cpu_pinning()
+                .iter()
+                .map(|pin| (pin.get_path(), pin.get_host_cpu()))
+                .collect::<Vec<(String, usize)>>()
+        );
+    }
+
+    #[test]
+    fn convert_domain_returns_error_on_unsupported_memory_unit() {
+        assert_error!(
+            ErrorKind::InvalidData,
+            "Unsupported `<memory>` unit: `PiB`.",
+            convert_domain("<domain><memory unit='PiB'>1</memory></domain>")
+        );
+    }
+}
@@ -1,200 +1,723 @@
+#![recursion_limit = "256"]
+
 mod arguments;
+mod clock;
 mod config;
 mod cpuset;
+mod deprecation;
+mod doctor;
 mod environment;
+mod event_sink;
+mod events;
+mod firewall;
+mod host;
+mod integrity;
+mod isolation;
+mod launcher;
+mod libvirt;
+mod mitigations;
+mod netboot;
+mod output;
+mod permissions;
+mod pinning;
 mod process;
+mod provision;
+mod qemu_log;
 mod qmp;
+mod qmp_proxy;
+mod readconfig;
+mod resctrl;
+mod schema;
+mod serial_log;
+mod shutdown;
+mod sriov;
+mod state;
+mod storage;
+mod systemd;
 #[cfg(test)]
 mod test;
+mod throttle;
+mod tuning;
+mod xml;
 
 use arguments::Arguments;
-use environment::Environment;
-use process::{ChildProcess, Process};
-use rlimit::{setrlimit, Resource, Rlim};
-use std::{env, fs};
+use environment::{ConfigPermissions, Environment};
+use events::Events;
+use json::{object, JsonValue};
+use nix::{sys::signal::kill, unistd::Pid};
+use process::Process;
+use state::StateDirectory;
+use std::{
+    collections::BTreeMap, env, fs, io, os::unix::fs::MetadataExt, path::Path, time::Duration,
+};
+
+const ISOLATION_CHECK_INTERVAL: u64 = 5;
 
 fn usage(name: &str) {
-    eprintln!("Usage: {} [-v] [-d] [-h] <vm-name>", name);
-    eprintln!("");
-    eprintln!("-h  display this help message");
-    eprintln!("-v  enable verbose mode. In this mode additional information about program execution flow will be \
-        printed.");
-    eprintln!("-d  enable debugging mode. In this mode a lot of information about pretty much every step taken by \
-        the application will be printed.");
+    eprint!("{}", arguments::usage(name));
     eprintln!("");
     eprintln!("Supported environment variables:");
-    eprintln!("- QEMU_LAUNCHER_CONFIG_DIR - a path to the directory where virtual machine configuration files are \
-        stored.");
-    eprintln!("- QEMU_LAUNCHER_CPUSET_MOUNT_PATH - a path to the directory where a cpuset cgroup tree will be \
-        mounted.");
-    eprintln!("                                    default: /sys/fs/cgroup/cpuset");
-    eprintln!("- QEMU_LAUNCHER_CPUSET_PREFIX - a prefix (directory) under the mount path where qemu cpusets will \
-        be created");
-    eprintln!("                                default: qemu");
+    eprint!("{}", environment::usage());
+    eprintln!("");
+    eprintln!("All directories must be absolute paths, the `--<name>-dir=<path>` options take precedence over the \
+        environment variables.");
     eprintln!("");
 }
 
-fn handle_vcpu_pinning(
-    child: &mut ChildProcess,
-    cpuset: &mut cpuset::CpuSet,
-    config: &config::Config,
-) {
-    let qmp_socket = match child.get_stdio() {
-        Ok(io) => io,
+// Describes the knobs the launcher accepts, for machine provisioning tools
+fn introspect() -> JsonValue {
+    object! {
+        "version": env!("CARGO_PKG_VERSION"),
+        "schema_version": schema::SCHEMA_VERSION,
+        "arguments": arguments::describe(),
+        "environment": environment::describe(),
+    }
+}
+
+// A machine is defined either by `<name>.yml` or by a machine generated `<name>.json`, never both
+fn find_config_file(env: &Environment, machine_name: &str) -> Option<String> {
+    let yaml_path = format!("{}/{}.yml", env.get_config_directory(), machine_name);
+    let json_path = format!("{}/{}.json", env.get_config_directory(), machine_name);
+
+    match (
+        Path::new(&yaml_path).exists(),
+        Path::new(&json_path).exists(),
+    ) {
+        (true, true) => {
+            output::error(format!(
+                "Both `{}` and `{}` define the `{}` machine, remove one of them.",
+                yaml_path, json_path, machine_name
+            ));
+            None
+        }
+        (false, true) => Some(json_path),
+        _ => Some(yaml_path),
+    }
+}
+
+fn load_config(env: &Environment, machine_name: &str) -> Option<config::Config> {
+    let config_file_path = find_config_file(env, machine_name)?;
+    let check = |path: &str| check_config_permissions(env.get_config_permissions(), path);
+
+    match config::Config::from_file(&config_file_path, &check) {
+        Ok(c) => {
+            if let (Some(priority), Some(max)) = (c.get_rt_priority(), env.get_max_rt_priority()) {
+                if priority > max {
+                    output::error(format!(
+                        "The real-time priority `{}` of the `{}` machine exceeds the host limit `{}` \
+                        set by the `QEMU_LAUNCHER_MAX_RT_PRIORITY` environment variable.",
+                        priority, machine_name, max
+                    ));
+                    return None;
+                }
+            }
+
+            if env.should_validate_host() && c.has_cpu_pinning() {
+                match host::get_present_cpus().and_then(|present| c.validate_host_cpus(&present)) {
+                    Ok(_) => {}
+                    Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                        output::error(format!(
+                            "Configuration load error for `{}` machine: {}",
+                            machine_name, e
+                        ));
+                        return None;
+                    }
+                    Err(e) => output::warning(e),
+                }
+            }
+
+            Some(
+                c.with_machine_name(machine_name)
+                    .with_runtime_directory(env.get_runtime_directory()),
+            )
+        }
+        Err(e) => {
+            output::error(format!(
+                "Configuration load error for `{}` machine: {}",
+                machine_name, e
+            ));
+            None
+        }
+    }
+}
+
+fn check_isolation(env: &Environment, machine_name: &str) {
+    let config = match load_config(env, machine_name) {
+        Some(config) => config,
+        None => return,
+    };
+
+    let (mount_path, prefix) = launcher::cpuset_location(env, &config);
+    let cpuset = match cpuset::CpuSet::new(mount_path, prefix) {
+        Ok(cpuset) => cpuset.with_thread_name(
+            env.get_cpuset_thread_name()
+                .replace("{vm}", config.get_machine_name()),
+        ),
         Err(e) => {
-            eprintln!("Unable to obtain qemu process stdio descriptors: {}", e);
+            output::error(e);
             return;
         }
     };
 
-    let vcpu_info = match qmp::read_vcpu_info_from_qmp_socket(qmp_socket) {
-        Ok(vcpu_info) => vcpu_info,
+    let pid = match StateDirectory::new(env.get_state_directory(), machine_name).read_pid() {
+        Ok(pid) if is_process_alive(pid) => pid,
+        Ok(_) => {
+            output::error(format!("The `{}` machine is not running.", machine_name));
+            return;
+        }
         Err(e) => {
-            eprintln!("Failed to obtain vCPU mapping info from QEMU: {}", e);
+            output::error(e);
             return;
         }
     };
 
-    for pin in config.get_cpu_pinning() {
-        let task_id = match vcpu_info.get_thread_id(pin.0, pin.1, pin.2) {
-            Some(tid) => tid,
-            None => {
-                eprintln!(
-                    "The vCPU core `{}.{}.{}` does not exist, unable to pin.",
-                    pin.0, pin.1, pin.2
-                );
-                continue;
-            }
-        };
+    let mut threads = vec![];
+    for cpu in config.get_pinned_host_cpus() {
+        match cpuset.get_thread_tasks(cpu) {
+            Ok(tasks) => threads.push((cpu, tasks)),
+            Err(e) => output::error(e),
+        }
+    }
+
+    let interval = Duration::from_secs(ISOLATION_CHECK_INTERVAL);
+    let reports = match isolation::check_isolation(pid, &threads, interval, &clock::SystemClock {})
+    {
+        Ok(reports) => reports,
+        Err(e) => {
+            output::error(e);
+            return;
+        }
+    };
 
-        if let Err(e) = cpuset.pin_task(pin.3, task_id) {
-            eprintln!(
-                "Failed to pin the vCPU `{}.{}.{}` core task ID `{}` to the host CPU `{}`: {}",
-                pin.0, pin.1, pin.2, pin.3, task_id, e
+    println!(
+        "Isolation of the `{}` machine over {} seconds:",
+        machine_name, ISOLATION_CHECK_INTERVAL
+    );
+
+    for report in reports {
+        println!("CPU {}:", report.get_cpu());
+        println!(
+            "  foreign tasks: {} {:?}",
+            report.get_foreign_tasks().len(),
+            report.get_foreign_tasks()
+        );
+        println!("  interrupts: {}", report.get_interrupts());
+        for (task, switches) in report.get_involuntary_switches() {
+            println!(
+                "  involuntary context switches of task {}: {}",
+                task, switches
             );
         }
     }
+}
 
-    if config.has_scheduling() {
-        let scheduler = config.get_scheduler().clone().unwrap();
-        let priority = config.get_priority().unwrap().to_string();
+fn cleanup(env: &Environment, machine_name: &str) {
+    let state = StateDirectory::new(env.get_state_directory(), machine_name);
 
-        for task_id in vcpu_info.get_task_ids() {
-            match process::Process::oneshot(
-                "chrt",
-                &[
-                    format!("--{}", scheduler).as_str(),
-                    "--pid",
-                    &priority,
-                    task_id.to_string().as_str(),
-                ],
-            ) {
-                Ok(_) => {} // TODO: debug
-                Err(e) => eprintln!("Failed to change vCPU thread `{}` priority: {}", task_id, e),
-            }
-        }
+    if let Some(pid) = state.read_pid().ok().filter(|pid| is_process_alive(*pid)) {
+        output::error(format!(
+            "The `{}` machine is still running with the process ID `{}`, nothing to clean up.",
+            machine_name, pid
+        ));
+        return;
+    }
+
+    remove_firewall_rules(&state);
+
+    if let Err(e) = state.remove_sockets() {
+        output::error(e);
+    }
+
+    if let Err(e) = state.clear_ready() {
+        output::error(e);
+    }
+
+    if let Err(e) = state.clear_throttle() {
+        output::error(e);
     }
 }
 
-fn main() {
-    let env = match Environment::new(env::vars()) {
-        Ok(e) => e,
+// The record of the last launch is kept after the machine exits, until it is launched again
+fn status(env: &Environment, machine_name: &str) {
+    let state = StateDirectory::new(env.get_state_directory(), machine_name);
+
+    match state.read_pid().ok().filter(|pid| is_process_alive(*pid)) {
+        Some(pid) => println!(
+            "The `{}` machine is running with the process ID `{}`.",
+            machine_name, pid
+        ),
+        None => println!("The `{}` machine is not running.", machine_name),
+    }
+
+    match state.load_launch_record() {
+        Ok(record) => println!("{}", record.pretty(2)),
+        Err(e) => output::error(e),
+    }
+}
+
+// The request is applied by the launcher supervising the machine, which owns a QMP connection to it
+fn throttle(env: &Environment, machine_name: &str, percent: u8) {
+    let config = match load_config(env, machine_name) {
+        Some(config) => config,
+        None => return,
+    };
+
+    if config.get_qmp_socket().is_none() && config.get_qmp_proxy().is_none() {
+        output::error(format!(
+            "The `{}` machine can not be throttled without `launcher.qmp_socket` or `launcher.qmp_proxy`.",
+            machine_name
+        ));
+        return;
+    }
+
+    let state = StateDirectory::new(env.get_state_directory(), machine_name);
+
+    if !state.read_pid().map_or(false, is_process_alive) {
+        output::error(format!("The `{}` machine is not running.", machine_name));
+        return;
+    }
+
+    match state.write_throttle(percent) {
+        Ok(_) if percent == 0 => println!(
+            "Requested lifting the throttle of the `{}` machine.",
+            machine_name
+        ),
+        Ok(_) => println!(
+            "Requested throttling the vCPUs of the `{}` machine by {}%.",
+            machine_name, percent
+        ),
+        Err(e) => output::error(e),
+    }
+}
+
+fn dump_config(env: &Environment, machine_name: &str) {
+    let config = match load_config(env, machine_name) {
+        Some(config) => config,
+        None => return,
+    };
+
+    match config.dump() {
+        Ok(yaml) => print!("{}", yaml),
+        Err(e) => output::error(format!(
+            "Failed to dump the configuration of the `{}` machine: {}",
+            machine_name, e
+        )),
+    }
+}
+
+fn print_kernel_facilities() {
+    println!("Host kernel facilities:");
+    for (facility, description) in host::describe_kernel_facilities() {
+        println!("  {}: {}", facility, description);
+    }
+}
+
+// Machines are not expected to run all at once, so conflicts are reported rather than refused
+fn run_doctor(env: &Environment) {
+    print_kernel_facilities();
+    println!("");
+
+    let entries = match fs::read_dir(env.get_config_directory()) {
+        Ok(entries) => entries,
         Err(e) => {
-            eprintln!("Unable to parse environment variables: {}", e);
+            output::error(format!(
+                "Failed to read the configuration directory `{}`: {}",
+                env.get_config_directory(),
+                e
+            ));
             return;
         }
     };
 
-    let args = match Arguments::new(&env::args().collect()) {
-        Arguments::Empty => panic!("Could not parse arguments. Aborting."),
-        Arguments::Usage(u) => {
-            usage(&u.get_program_name());
-            return;
+    let mut machine_names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .map_or(false, |ext| ext == "yml" || ext == "json")
+        })
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_owned()))
+        .collect();
+    machine_names.sort();
+    machine_names.dedup();
+
+    let mut configs = vec![];
+    for machine_name in &machine_names {
+        if let Some(config) = load_config(env, machine_name) {
+            configs.push(config);
         }
-        Arguments::Invalid(i) => {
-            eprintln!("Error parsing arguments: {}", i.get_error());
-            eprintln!("");
-            usage(&i.get_program_name());
-            return;
+    }
+
+    if configs.len() < machine_names.len() {
+        output::warning(format!(
+            "Skipped {} of {} machines which failed to load.",
+            machine_names.len() - configs.len(),
+            machine_names.len()
+        ));
+    }
+
+    if env.should_check_mitigations() {
+        warn_smt_exposures(&configs);
+    }
+
+    let conflicts = doctor::find_conflicts(&configs);
+    if conflicts.len() == 0 {
+        println!("No conflicts found between {} machines.", configs.len());
+        return;
+    }
+
+    for conflict in &conflicts {
+        println!("{}", conflict.describe());
+    }
+
+    let machines: Vec<&str> = configs.iter().map(|c| c.get_machine_name()).collect();
+    println!("");
+    print!("{}", doctor::conflict_matrix(&machines, &conflicts));
+}
+
+fn remove_firewall_rules(state: &StateDirectory) {
+    if let Some(table) = state.read_firewall_table() {
+        match firewall::remove(&table) {
+            Ok(_) => {
+                if let Err(e) = state.clear_firewall_table() {
+                    output::error(e);
+                }
+            }
+            Err(e) => output::error(e),
+        }
+    }
+}
+
+fn check_config_permissions(policy: &ConfigPermissions, path: &str) -> io::Result<()> {
+    if *policy == ConfigPermissions::Ignore {
+        return Ok({});
+    }
+
+    let result = match fs::metadata(path) {
+        Ok(metadata) => config::check_file_permissions(
+            path,
+            metadata.mode(),
+            metadata.uid(),
+            nix::unistd::geteuid().as_raw(),
+        ),
+        Err(e) => {
+            return Err(io::Error::new(
+                e.kind(),
+                format!("Failed to read configuration file `{}`: {}", path, e),
+            ))
         }
-        Arguments::Valid(v) => v,
     };
 
-    let config_file_path = format!(
-        "{}/{}.yml",
-        env.get_config_directory(),
-        &args.get_machine_name()
-    );
-    let config_file = match fs::read_to_string(&config_file_path) {
-        Ok(s) => s,
+    match result {
+        Ok(_) => Ok({}),
+        Err(e) if *policy == ConfigPermissions::Warn => {
+            output::warning(e);
+            Ok({})
+        }
+        Err(e) => Err(io::Error::new(
+            e.kind(),
+            format!(
+                "{} Refusing to load it, set `QEMU_LAUNCHER_CONFIG_PERMISSIONS` to `warn` or `ignore` to \
+                override.",
+                e
+            ),
+        )),
+    }
+}
+
+// Options removed by a qemu upgrade only fail once qemu starts, so they are pointed out up front
+fn warn_deprecated_options(config: &config::Config) {
+    let options = config.get_qemu_option_names();
+    if !deprecation::has_deprecated_options(&options) {
+        return;
+    }
+
+    let major = Process::oneshot(config.get_qemu_binary_path(), &["--version"])
+        .ok()
+        .and_then(|output| deprecation::parse_major_version(&output));
+
+    match major {
+        Some(major) => {
+            for warning in deprecation::lint(&options, major) {
+                output::warning(warning);
+            }
+        }
+        None => output::warning(format!(
+            "Unable to detect the version of the `{}` qemu binary, deprecated qemu options are not checked.",
+            config.get_qemu_binary_path()
+        )),
+    }
+}
+
+// Opt-in, since most hosts knowingly keep SMT enabled for the performance, and warned about rather
+// than refused, as the sibling sharing is a policy decision of the host owner
+fn warn_smt_exposures(configs: &[config::Config]) {
+    let vulnerabilities = match host::get_cpu_vulnerabilities() {
+        Ok(vulnerabilities) => vulnerabilities,
         Err(e) => {
-            eprintln!(
-                "Failed to read configuration file `{}`: {}",
-                config_file_path, e
-            );
+            output::warning(e);
             return;
         }
     };
-    let config = match config::Config::new(&config_file) {
-        Ok(c) => c,
+
+    let machines: Vec<(&str, Vec<usize>)> = configs
+        .iter()
+        .map(|c| (c.get_machine_name(), c.get_pinned_host_cpus()))
+        .collect();
+
+    let mut siblings = BTreeMap::new();
+    for (_, cpus) in &machines {
+        for cpu in cpus {
+            if siblings.contains_key(cpu) {
+                continue;
+            }
+
+            match host::get_thread_siblings(*cpu) {
+                Ok(cpu_siblings) => siblings.insert(*cpu, cpu_siblings),
+                Err(e) => {
+                    output::warning(e);
+                    return;
+                }
+            };
+        }
+    }
+
+    for exposure in mitigations::find_smt_exposures(&machines, &siblings, &vulnerabilities) {
+        output::warning(exposure);
+    }
+}
+
+// A launcher running as a service account only has the capabilities granted to it, e.g. by the
+// `AmbientCapabilities=` of its systemd unit, so the missing ones are reported before anything is changed
+fn check_capabilities(config: &config::Config) -> bool {
+    if nix::unistd::geteuid().is_root() {
+        return true;
+    }
+
+    let required = config.get_required_capabilities();
+    let names: Vec<&str> = required.iter().map(|(_, capability)| *capability).collect();
+    let missing = match host::get_missing_capabilities(&names) {
+        Ok(missing) => missing,
         Err(e) => {
-            eprintln!(
-                "Configuration load error for `{}` machine: {}",
-                args.get_machine_name(),
-                e
-            );
-            return;
+            output::warning(e);
+            return true;
         }
     };
 
-    let mut cpuset = match cpuset::CpuSet::new(env.get_cpuset_mount_path(), env.get_cpuset_prefix())
+    for (option, capability) in required
+        .iter()
+        .filter(|(_, capability)| missing.contains(capability))
     {
-        Ok(cpuset) => cpuset,
+        output::error(format!(
+            "`{}` requires the `{}` capability: grant it to the launcher, e.g. with \
+            `AmbientCapabilities={}` in its systemd unit, or run the launcher as root.",
+            option, capability, capability
+        ));
+    }
+
+    missing.len() < 1
+}
+
+fn is_process_alive(pid: i32) -> bool {
+    kill(Pid::from_raw(pid), None).is_ok()
+}
+
+fn import_libvirt_domain(path: &str) {
+    let xml = match fs::read_to_string(path) {
+        Ok(xml) => xml,
         Err(e) => {
-            eprintln!("{}", e);
+            output::error(format!(
+                "Failed to read libvirt domain definition `{}`: {}",
+                path, e
+            ));
             return;
         }
     };
 
-    if config.rlimit_memlock() {
-        if let Err(e) = setrlimit(Resource::MEMLOCK, Rlim::INFINITY, Rlim::INFINITY) {
-            eprintln!("{}", e);
-            return;
+    match libvirt::convert_domain(&xml) {
+        Ok(conversion) => {
+            for warning in conversion.get_warnings() {
+                output::warning(warning);
+            }
+
+            print!("{}", conversion.get_yaml());
         }
+        Err(e) => output::error(format!(
+            "Failed to convert libvirt domain `{}`: {}",
+            path, e
+        )),
     }
+}
 
-    let mut child = match Process::new(config.get_qemu_binary_path())
-        .set_args(config.get_command_line_options())
-        .set_effective_group_id(&config.get_group())
-        .set_effective_user_id(&config.get_user())
-        .should_clear_env(config.should_clear_env())
-        .set_environment_variables(config.get_env_vars())
+fn run_self_test(qemu_binary: &str) {
+    let mut child = match Process::new(qemu_binary)
+        .set_args(&[
+            "-machine",
+            "none",
+            "-nodefaults",
+            "-display",
+            "none",
+            "-qmp",
+            "stdio",
+        ])
         .spawn()
     {
         Ok(c) => c,
         Err(e) => {
-            eprintln!(
-                "Failed to execute the `{}` child process: {}",
-                config.get_qemu_binary_path(),
-                e
-            );
+            output::error(format!(
+                "Self-test failed to execute `{}`: {}",
+                qemu_binary, e
+            ));
             return;
         }
     };
 
-    if config.has_cpu_pinning() {
-        handle_vcpu_pinning(&mut child, &mut cpuset, &config);
+    let result = match child.get_stdio() {
+        Ok(io) => qmp::run_self_test(io, None),
+        Err(e) => Err(e),
+    };
+
+    // Qemu keeps running unless it was told to quit over QMP
+    if result.is_err() {
+        if let Err(e) = child.kill() {
+            output::error(e);
+        }
     }
 
     if let Err(e) = child.wait() {
-        eprintln!(
-            "The child process `{}` was terminated preliminarly: {}",
-            config.get_qemu_binary_path(),
-            e
-        );
+        output::error(format!(
+            "Self-test qemu process `{}` failed: {}",
+            qemu_binary, e
+        ));
+        return;
     }
 
-    if let Err(e) = cpuset.release_threads() {
-        eprintln!("Failed to release some pinned CPU threads: {}", e);
+    match result {
+        Ok(topology) => println!(
+            "Self-test passed: `{}` responded over QMP and reported {} vCPU thread(s).",
+            qemu_binary,
+            topology.get_task_ids().len()
+        ),
+        Err(e) => output::error(format!(
+            "Self-test failed to communicate with `{}`: {}",
+            qemu_binary, e
+        )),
+    }
+}
+
+fn main() {
+    let arguments: Vec<String> = env::args().collect();
+
+    let env =
+        match Environment::new(env::vars().chain(arguments::environment_overrides(&arguments))) {
+            Ok(e) => e,
+            Err(e) => {
+                output::error(format!("Unable to parse environment variables: {}", e));
+                return;
+            }
+        };
+
+    output::init(env.get_color(), env.get_output_style());
+
+    let args = match Arguments::new(&arguments) {
+        Arguments::Empty => panic!("Could not parse arguments. Aborting."),
+        Arguments::Usage(u) => {
+            usage(&u.get_program_name());
+            return;
+        }
+        Arguments::Invalid(i) => {
+            output::error(format!("Failed to parse arguments: {}", i.get_error()));
+            eprintln!("");
+            usage(&i.get_program_name());
+            return;
+        }
+        Arguments::Import(i) => {
+            import_libvirt_domain(i.get_domain_path());
+            return;
+        }
+        Arguments::CheckIsolation(c) => {
+            check_isolation(&env, c.get_machine_name());
+            return;
+        }
+        Arguments::Cleanup(c) => {
+            cleanup(&env, c.get_machine_name());
+            return;
+        }
+        Arguments::ConfigDump(c) => {
+            dump_config(&env, c.get_machine_name());
+            return;
+        }
+        Arguments::Doctor => {
+            run_doctor(&env);
+            return;
+        }
+        Arguments::Introspect => {
+            println!("{}", introspect().pretty(2));
+            return;
+        }
+        Arguments::Schema => {
+            println!("{}", schema::config_schema().pretty(2));
+            return;
+        }
+        Arguments::SelfTest(t) => {
+            run_self_test(t.get_qemu_binary());
+            return;
+        }
+        Arguments::Status(s) => {
+            status(&env, s.get_machine_name());
+            return;
+        }
+        Arguments::Throttle(t) => {
+            throttle(&env, t.get_machine_name(), t.get_percent());
+            return;
+        }
+        Arguments::Valid(v) => v,
+    };
+
+    let events = Events::new(args.get_events_fd(), args.get_machine_name());
+
+    let config = match load_config(&env, args.get_machine_name()) {
+        Some(config) => config.with_qemu_arguments(args.get_qemu_arguments()),
+        None => return,
+    };
+
+    events.emit(
+        "config-loaded",
+        object! {
+            "profile": config.get_profile(),
+        },
+    );
+
+    if !check_capabilities(&config) {
+        return;
+    }
+
+    warn_deprecated_options(&config);
+
+    if env.should_check_mitigations() && config.has_cpu_pinning() {
+        warn_smt_exposures(std::slice::from_ref(&config));
+    }
+
+    if args.is_verbose_mode() {
+        print_kernel_facilities();
+    }
+
+    let launcher = launcher::Launcher::new(&env, events)
+        .should_adopt(args.is_adopt_mode())
+        .should_debug(args.is_debug_enabled())
+        .should_mlock_self(args.should_mlock_self());
+
+    let mut vm = match launcher.start(config) {
+        Ok(vm) => vm,
+        Err(e) => {
+            output::error(e);
+            return;
+        }
+    };
+
+    if let Err(e) = vm.wait() {
+        output::error(e);
     }
 }
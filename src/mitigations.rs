@@ -0,0 +1,136 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+// Vulnerabilities which leak data between the sibling threads of a core, the kernel reports them as
+// `SMT vulnerable` once mitigated for everything but SMT, or as plain `Vulnerable` when not mitigated
+const SMT_VULNERABILITIES: &[&str] = &["l1tf", "mds", "mmio_stale_data", "tsx_async_abort"];
+const SMT_VULNERABLE_MARKERS: &[&str] = &["SMT vulnerable", "SMT Host state unknown"];
+
+// Names of the vulnerabilities which can be exploited across the sibling threads of a core on this host
+pub fn find_smt_vulnerabilities(vulnerabilities: &[(String, String)]) -> Vec<&str> {
+    vulnerabilities
+        .iter()
+        .filter(|(name, state)| {
+            SMT_VULNERABLE_MARKERS
+                .iter()
+                .any(|marker| state.contains(marker))
+                || (SMT_VULNERABILITIES.contains(&name.as_str()) && state.starts_with("Vulnerable"))
+        })
+        .map(|(name, _)| name.as_str())
+        .collect()
+}
+
+// Every pinned host CPU sharing its core with a sibling which runs anything but the vCPUs of the same
+// machine, i.e. host tasks or the vCPUs of another machine. A pair of machines is reported once.
+pub fn find_smt_exposures(
+    machines: &[(&str, Vec<usize>)],
+    siblings: &BTreeMap<usize, Vec<usize>>,
+    vulnerabilities: &[(String, String)],
+) -> Vec<String> {
+    let vulnerable = find_smt_vulnerabilities(vulnerabilities);
+    if vulnerable.len() == 0 {
+        return vec![];
+    }
+
+    let vulnerable = format!("`{}`", vulnerable.join("`, `"));
+    let mut exposures = vec![];
+
+    for (index, (machine, cpus)) in machines.iter().enumerate() {
+        let pinned: BTreeSet<&usize> = cpus.iter().collect();
+
+        for cpu in &pinned {
+            let cpu_siblings = siblings.get(cpu).map(|s| s.as_slice()).unwrap_or_default();
+
+            for sibling in cpu_siblings.iter().filter(|s| !pinned.contains(s)) {
+                let owner = machines
+                    .iter()
+                    .position(|(_, other)| other.contains(sibling));
+
+                let exposure = match owner {
+                    Some(owner) if owner < index => continue,
+                    Some(owner) => format!("pinned by `{}`", machines[owner].0),
+                    None => String::from("left to host tasks"),
+                };
+
+                exposures.push(format!(
+                    "The host CPU `{}` pinned by `{}` shares its core with the host CPU `{}` {}, which \
+                    is exposed to {} while SMT is enabled.",
+                    cpu, machine, sibling, exposure, vulnerable
+                ));
+            }
+        }
+    }
+
+    exposures
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_smt_exposures, find_smt_vulnerabilities};
+    use std::collections::BTreeMap;
+
+    fn vulnerabilities(states: &[(&str, &str)]) -> Vec<(String, String)> {
+        states
+            .iter()
+            .map(|(name, state)| (name.to_string(), state.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn find_smt_vulnerabilities_picks_vulnerabilities_exploitable_across_siblings() {
+        let vulnerabilities = vulnerabilities(&[
+            (
+                "l1tf",
+                "Mitigation: PTE Inversion; VMX: conditional cache flushes, SMT vulnerable",
+            ),
+            ("mds", "Mitigation: Clear CPU buffers; SMT disabled"),
+            ("meltdown", "Mitigation: PTI"),
+            (
+                "spectre_v1",
+                "Vulnerable: __user pointer sanitization and usercopy barriers only",
+            ),
+            ("tsx_async_abort", "Vulnerable"),
+        ]);
+
+        assert_eq!(
+            vec!["l1tf", "tsx_async_abort"],
+            find_smt_vulnerabilities(&vulnerabilities)
+        );
+    }
+
+    #[test]
+    fn find_smt_exposures_reports_siblings_shared_with_host_tasks_and_other_machines() {
+        let machines = vec![("bar", vec![2, 6, 3]), ("foo", vec![7, 4]), ("web", vec![])];
+        let siblings: BTreeMap<usize, Vec<usize>> = vec![
+            (2, vec![2, 6]),
+            (3, vec![3, 7]),
+            (4, vec![0, 4]),
+            (6, vec![2, 6]),
+            (7, vec![3, 7]),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            vec![
+                "The host CPU `3` pinned by `bar` shares its core with the host CPU `7` pinned by \
+                `foo`, which is exposed to `mds` while SMT is enabled.",
+                "The host CPU `4` pinned by `foo` shares its core with the host CPU `0` left to host \
+                tasks, which is exposed to `mds` while SMT is enabled.",
+            ],
+            find_smt_exposures(
+                &machines,
+                &siblings,
+                &vulnerabilities(&[("mds", "Mitigation: Clear CPU buffers; SMT vulnerable")])
+            )
+        );
+
+        assert_eq!(
+            Vec::<String>::new(),
+            find_smt_exposures(
+                &machines,
+                &siblings,
+                &vulnerabilities(&[("mds", "Mitigation: Clear CPU buffers; SMT disabled")])
+            )
+        );
+    }
+}
@@ -0,0 +1,469 @@
+use crate::{
+    clock::{self, Clock},
+    config::Config,
+    output,
+};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Error, ErrorKind, Read, Write},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener, TcpStream, UdpSocket},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+const TFTP_PORT: u16 = 69;
+const TFTP_BLOCK_SIZE: usize = 512;
+const TFTP_RETRIES: u32 = 5;
+const TFTP_TIMEOUT: Duration = Duration::from_secs(1);
+const OPCODE_READ: u16 = 1;
+const OPCODE_WRITE: u16 = 2;
+const OPCODE_DATA: u16 = 3;
+const OPCODE_ACK: u16 = 4;
+const OPCODE_ERROR: u16 = 5;
+const ERROR_NOT_FOUND: u16 = 1;
+const ERROR_ACCESS_VIOLATION: u16 = 2;
+const ERROR_ILLEGAL_OPERATION: u16 = 4;
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_HTTP_REQUEST_SIZE: u64 = 8192;
+// Listeners wake up this often to notice the server being stopped
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// Serves the `launcher.netboot` directory over TFTP, and optionally HTTP, on the host address the
+// machine reaches over its tap or bridge interface. Files are only ever read, and only for as long as
+// the machine is running.
+pub struct NetbootServer {
+    clock: Arc<dyn Clock>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl NetbootServer {
+    pub fn new() -> Self {
+        Self {
+            clock: clock::system(),
+            stop: Arc::new(AtomicBool::new(false)),
+            threads: vec![],
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+
+        self
+    }
+
+    pub fn start(&mut self, config: &Config) -> Result<(), Error> {
+        let netboot = match config.get_netboot() {
+            Some(netboot) => netboot,
+            None => return Ok({}),
+        };
+
+        // Over the `user` networking qemu serves the files itself
+        let listen = match netboot.get_listen() {
+            Some(listen) => listen,
+            None => return Ok({}),
+        };
+
+        let directory = PathBuf::from(netboot.get_directory());
+
+        let address = SocketAddrV4::new(listen, TFTP_PORT);
+        let socket = match UdpSocket::bind(address) {
+            Ok(socket) => socket,
+            Err(e) => {
+                return Err(Error::new(
+                    e.kind(),
+                    format!("Failed to bind the TFTP server to `{}`: {}", address, e),
+                ))
+            }
+        };
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+
+        let (stop, root) = (self.stop.clone(), directory.clone());
+        self.threads.push(thread::spawn(move || {
+            serve_tftp(socket, listen, &root, &stop)
+        }));
+
+        if let Some(port) = netboot.get_http_port() {
+            let address = SocketAddrV4::new(listen, port);
+            let listener = match TcpListener::bind(address) {
+                Ok(listener) => listener,
+                Err(e) => {
+                    return Err(Error::new(
+                        e.kind(),
+                        format!("Failed to bind the HTTP server to `{}`: {}", address, e),
+                    ))
+                }
+            };
+            listener.set_nonblocking(true)?;
+
+            let (clock, stop) = (self.clock.clone(), self.stop.clone());
+            self.threads.push(thread::spawn(move || {
+                serve_http(listener, &directory, &*clock, &stop)
+            }));
+        }
+
+        Ok({})
+    }
+
+    pub fn stop(&mut self) -> Result<(), Error> {
+        self.stop.store(true, Ordering::SeqCst);
+
+        let mut failed = false;
+        for thread in self.threads.drain(..) {
+            failed |= thread.join().is_err();
+        }
+
+        if failed {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "The netboot server stopped unexpectedly.",
+            ));
+        }
+
+        Ok({})
+    }
+}
+
+fn serve_tftp(socket: UdpSocket, listen: Ipv4Addr, directory: &Path, stop: &AtomicBool) {
+    let mut packet = [0; TFTP_BLOCK_SIZE + 4];
+
+    while !stop.load(Ordering::SeqCst) {
+        let (length, client) = match socket.recv_from(&mut packet) {
+            Ok(received) => received,
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                continue
+            }
+            Err(e) => {
+                output::warning(format!("Failed to receive a TFTP request: {}", e));
+                continue;
+            }
+        };
+
+        let request = &packet[..length];
+        let result = match parse_opcode(request) {
+            Some(OPCODE_READ) => match parse_read_request(request) {
+                Some(file) => {
+                    let path = resolve_path(directory, &file);
+
+                    // Every transfer gets its own port, as RFC 1350 requires
+                    thread::spawn(move || {
+                        if let Err(e) = send_file(listen, client, path) {
+                            output::warning(format!(
+                                "Failed to send `{}` over TFTP to `{}`: {}",
+                                file, client, e
+                            ));
+                        }
+                    });
+                    Ok({})
+                }
+                None => send_error(
+                    &socket,
+                    client,
+                    ERROR_ILLEGAL_OPERATION,
+                    "Malformed request",
+                ),
+            },
+            Some(OPCODE_WRITE) => {
+                send_error(&socket, client, ERROR_ACCESS_VIOLATION, "Read-only server")
+            }
+            _ => send_error(
+                &socket,
+                client,
+                ERROR_ILLEGAL_OPERATION,
+                "Unexpected packet",
+            ),
+        };
+
+        if let Err(e) = result {
+            output::warning(format!("Failed to answer `{}` over TFTP: {}", client, e));
+        }
+    }
+}
+
+fn send_file(listen: Ipv4Addr, client: SocketAddr, path: Option<PathBuf>) -> Result<(), Error> {
+    let socket = UdpSocket::bind(SocketAddrV4::new(listen, 0))?;
+    socket.connect(client)?;
+    socket.set_read_timeout(Some(TFTP_TIMEOUT))?;
+
+    let mut file = match path.map(File::open) {
+        Some(Ok(file)) if file.metadata()?.is_file() => file,
+        _ => return send_error(&socket, client, ERROR_NOT_FOUND, "File not found"),
+    };
+
+    let mut block: u16 = 1;
+    loop {
+        let mut data = [0; TFTP_BLOCK_SIZE];
+        let length = read_block(&mut file, &mut data)?;
+
+        let mut packet = encode_u16(OPCODE_DATA).to_vec();
+        packet.extend_from_slice(&encode_u16(block));
+        packet.extend_from_slice(&data[..length]);
+
+        wait_for_ack(&socket, &packet, block)?;
+
+        // A short block, including an empty one, completes the transfer
+        if length < TFTP_BLOCK_SIZE {
+            return Ok({});
+        }
+
+        // Files over 32 MiB roll the block number over, which the firmware of the guests expects
+        block = block.wrapping_add(1);
+    }
+}
+
+fn wait_for_ack(socket: &UdpSocket, packet: &[u8], block: u16) -> Result<(), Error> {
+    let mut ack = [0; 4];
+
+    for _ in 0..TFTP_RETRIES {
+        socket.send(packet)?;
+
+        loop {
+            match socket.recv(&mut ack) {
+                Ok(4)
+                    if parse_opcode(&ack) == Some(OPCODE_ACK) && ack[2..] == encode_u16(block) =>
+                {
+                    return Ok({})
+                }
+                Ok(_) if parse_opcode(&ack) == Some(OPCODE_ERROR) => {
+                    return Err(Error::new(
+                        ErrorKind::ConnectionAborted,
+                        "the transfer was aborted by the client",
+                    ))
+                }
+                // Duplicate acknowledgements of the previous block are ignored
+                Ok(_) => continue,
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                    break
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    Err(Error::new(
+        ErrorKind::TimedOut,
+        format!("block {} was not acknowledged", block),
+    ))
+}
+
+fn send_error(
+    socket: &UdpSocket,
+    client: SocketAddr,
+    code: u16,
+    message: &str,
+) -> Result<(), Error> {
+    let mut packet = encode_u16(OPCODE_ERROR).to_vec();
+    packet.extend_from_slice(&encode_u16(code));
+    packet.extend_from_slice(message.as_bytes());
+    packet.push(0);
+
+    socket.send_to(&packet, client)?;
+
+    Ok({})
+}
+
+fn read_block(file: &mut File, block: &mut [u8]) -> Result<usize, Error> {
+    let mut length = 0;
+
+    while length < block.len() {
+        match file.read(&mut block[length..])? {
+            0 => break,
+            read => length += read,
+        }
+    }
+
+    Ok(length)
+}
+
+fn encode_u16(value: u16) -> [u8; 2] {
+    value.to_be_bytes()
+}
+
+fn parse_opcode(packet: &[u8]) -> Option<u16> {
+    match packet {
+        [high, low, ..] => Some(u16::from_be_bytes([*high, *low])),
+        _ => None,
+    }
+}
+
+// The file name of a read request, transfer options (RFC 2347) are ignored and both modes are served
+// as raw bytes, which is what the boot firmware asks for
+fn parse_read_request(packet: &[u8]) -> Option<String> {
+    if parse_opcode(packet) != Some(OPCODE_READ) {
+        return None;
+    }
+
+    let mut fields = packet[2..].split(|byte| *byte == 0);
+    let file = std::str::from_utf8(fields.next()?).ok()?;
+    let mode = std::str::from_utf8(fields.next()?).ok()?.to_lowercase();
+
+    match (file.len() > 0, mode.as_str()) {
+        (true, "octet") | (true, "netascii") => Some(file.to_owned()),
+        _ => None,
+    }
+}
+
+// Requested paths are relative to the served directory, whether they start with a slash or not, and
+// can never leave it. Backslashes sent by some firmware are taken for slashes.
+fn resolve_path(directory: &Path, requested: &str) -> Option<PathBuf> {
+    let requested = requested.replace('\\', "/");
+    let mut path = directory.to_path_buf();
+
+    for component in Path::new(requested.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    Some(path)
+}
+
+fn serve_http(listener: TcpListener, directory: &Path, clock: &dyn Clock, stop: &AtomicBool) {
+    while !stop.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, client)) => {
+                let directory = directory.to_path_buf();
+
+                thread::spawn(move || {
+                    if let Err(e) = answer_http(stream, &directory) {
+                        output::warning(format!("Failed to answer `{}` over HTTP: {}", client, e));
+                    }
+                });
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => clock.sleep(POLL_INTERVAL),
+            Err(e) => {
+                output::warning(format!("Failed to accept an HTTP connection: {}", e));
+                clock.sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+fn answer_http(stream: TcpStream, directory: &Path) -> Result<(), Error> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+
+    let mut reader = BufReader::new((&stream).take(MAX_HTTP_REQUEST_SIZE));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Headers are not needed, but are read so the client is not reset while still sending them
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim().len() > 0 {
+        header.clear();
+    }
+
+    let mut stream = &stream;
+    let (method, path) = match parse_http_request_line(&request_line) {
+        Some(request) => request,
+        None => return write_http_status(&mut stream, "400 Bad Request"),
+    };
+
+    if method != "GET" && method != "HEAD" {
+        return write_http_status(&mut stream, "405 Method Not Allowed");
+    }
+
+    let mut file = match resolve_path(directory, path).map(File::open) {
+        Some(Ok(file)) if file.metadata()?.is_file() => file,
+        _ => return write_http_status(&mut stream, "404 Not Found"),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.0 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\n\
+        Connection: close\r\n\r\n",
+        file.metadata()?.len()
+    )?;
+
+    if method == "GET" {
+        io::copy(&mut file, &mut stream)?;
+    }
+
+    Ok({})
+}
+
+fn write_http_status(stream: &mut impl Write, status: &str) -> Result<(), Error> {
+    write!(
+        stream,
+        "HTTP/1.0 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status
+    )
+}
+
+// The method and the path of the request line, without the query
+fn parse_http_request_line(line: &str) -> Option<(&str, &str)> {
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?;
+    let target = parts.next()?;
+
+    if !parts.next()?.starts_with("HTTP/") || !target.starts_with('/') {
+        return None;
+    }
+
+    Some((method, target.split('?').next()?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_http_request_line, parse_read_request, resolve_path};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn parse_read_request_returns_the_requested_file() {
+        assert_eq!(
+            Some(String::from("pxelinux.0")),
+            parse_read_request(b"\x00\x01pxelinux.0\x00octet\x00")
+        );
+        assert_eq!(
+            Some(String::from("/boot/grub/grub.cfg")),
+            parse_read_request(b"\x00\x01/boot/grub/grub.cfg\x00NETASCII\x00tsize\x000\x00")
+        );
+        assert_eq!(None, parse_read_request(b"\x00\x01pxelinux.0\x00mail\x00"));
+        assert_eq!(None, parse_read_request(b"\x00\x01\x00octet\x00"));
+        assert_eq!(None, parse_read_request(b"\x00\x02pxelinux.0\x00octet\x00"));
+        assert_eq!(None, parse_read_request(b"\x00"));
+    }
+
+    #[test]
+    fn resolve_path_keeps_requests_inside_of_the_directory() {
+        let directory = Path::new("/srv/netboot");
+
+        assert_eq!(
+            Some(PathBuf::from("/srv/netboot/pxelinux.0")),
+            resolve_path(directory, "pxelinux.0")
+        );
+        assert_eq!(
+            Some(PathBuf::from("/srv/netboot/boot/grub/grub.cfg")),
+            resolve_path(directory, "/boot/./grub/grub.cfg")
+        );
+        assert_eq!(
+            Some(PathBuf::from("/srv/netboot/efi/boot/bootx64.efi")),
+            resolve_path(directory, "\\efi\\boot\\bootx64.efi")
+        );
+        assert_eq!(None, resolve_path(directory, "../etc/shadow"));
+        assert_eq!(None, resolve_path(directory, "/boot/../../etc/shadow"));
+    }
+
+    #[test]
+    fn parse_http_request_line_returns_the_method_and_the_path() {
+        assert_eq!(
+            Some(("GET", "/vmlinuz")),
+            parse_http_request_line("GET /vmlinuz?arch=x86_64 HTTP/1.1\r\n")
+        );
+        assert_eq!(
+            Some(("HEAD", "/initrd.img")),
+            parse_http_request_line("HEAD /initrd.img HTTP/1.0\r\n")
+        );
+        assert_eq!(None, parse_http_request_line("GET vmlinuz HTTP/1.1\r\n"));
+        assert_eq!(None, parse_http_request_line("GET /vmlinuz\r\n"));
+        assert_eq!(None, parse_http_request_line(""));
+    }
+}
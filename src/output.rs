@@ -0,0 +1,151 @@
+use crate::environment::{Color, OutputStyle};
+use nix::{libc::STDERR_FILENO, unistd::isatty};
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+const COLOR_ERROR: &str = "\x1b[1;31m";
+const COLOR_WARNING: &str = "\x1b[1;33m";
+const COLOR_RESET: &str = "\x1b[0m";
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+static PRETTY: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy)]
+enum Level {
+    Error,
+    Warning,
+}
+
+pub fn init(color: &Color, style: &OutputStyle) {
+    let is_terminal = isatty(STDERR_FILENO).unwrap_or(false);
+
+    COLOR.store(
+        match color {
+            Color::Always => true,
+            Color::Auto => is_terminal,
+            Color::Never => false,
+        },
+        Ordering::Relaxed,
+    );
+    PRETTY.store(
+        match style {
+            OutputStyle::Auto => is_terminal,
+            OutputStyle::Pretty => true,
+            OutputStyle::Terse => false,
+        },
+        Ordering::Relaxed,
+    );
+}
+
+pub fn error<M: Display>(message: M) {
+    print(Level::Error, message);
+}
+
+pub fn warning<M: Display>(message: M) {
+    print(Level::Warning, message);
+}
+
+fn print<M: Display>(level: Level, message: M) {
+    eprintln!(
+        "{}",
+        render(
+            level,
+            &message.to_string(),
+            COLOR.load(Ordering::Relaxed),
+            PRETTY.load(Ordering::Relaxed)
+        )
+    );
+}
+
+fn render(level: Level, message: &str, color: bool, pretty: bool) -> String {
+    let (prefix, code) = match level {
+        Level::Error => ("error:", COLOR_ERROR),
+        Level::Warning => ("warning:", COLOR_WARNING),
+    };
+
+    let prefix = match color {
+        true => format!("{}{}{}", code, prefix, COLOR_RESET),
+        false => prefix.to_owned(),
+    };
+
+    if !pretty {
+        return format!("{} {}", prefix, message);
+    }
+
+    let causes = split_causes(message);
+    let mut rendered = format!("{} {}", prefix, causes[0]);
+    for cause in &causes[1..] {
+        rendered.push_str(&format!("\n  caused by: {}", cause));
+    }
+
+    rendered
+}
+
+fn split_causes(message: &str) -> Vec<&str> {
+    let mut causes = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+
+    for (index, character) in message.char_indices() {
+        if character == '`' {
+            quoted = !quoted;
+        }
+
+        if quoted || index < start {
+            continue;
+        }
+
+        // Nested errors are appended after a colon or a dash, while names are always quoted
+        for separator in &[": ", " - "] {
+            if message[index..].starts_with(separator) {
+                causes.push(&message[start..index]);
+                start = index + separator.len();
+            }
+        }
+    }
+
+    causes.push(&message[start..]);
+
+    causes
+}
+
+#[cfg(test)]
+mod test {
+    use super::{render, Level};
+
+    const MESSAGE: &str = "Failed to pin the vCPU `0.0.0` core task ID `123` to the host CPU `2`: \
+        Failed to isolate the host cpu thread `2` - Permission denied (os error 13)";
+
+    #[test]
+    fn render_prints_terse_message_on_a_single_line() {
+        assert_eq!(
+            format!("error: {}", MESSAGE),
+            render(Level::Error, MESSAGE, false, false)
+        );
+        assert_eq!(
+            "\x1b[1;33mwarning:\x1b[0m Kernel samepage merging is not running.",
+            render(
+                Level::Warning,
+                "Kernel samepage merging is not running.",
+                true,
+                false
+            )
+        );
+    }
+
+    #[test]
+    fn render_prints_every_cause_of_pretty_message_on_a_separate_line() {
+        assert_eq!(
+            "error: Failed to pin the vCPU `0.0.0` core task ID `123` to the host CPU `2`\n  \
+            caused by: Failed to isolate the host cpu thread `2`\n  \
+            caused by: Permission denied (os error 13)",
+            render(Level::Error, MESSAGE, false, true)
+        );
+        assert_eq!(
+            "error: Failed to read `/tmp/a: b - c`",
+            render(Level::Error, "Failed to read `/tmp/a: b - c`", false, true)
+        );
+    }
+}
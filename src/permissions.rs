@@ -0,0 +1,408 @@
+use crate::config::Config;
+#[cfg(not(test))]
+use nix::unistd::chown;
+use nix::unistd::{Gid, Uid};
+#[cfg(not(test))]
+use std::{fs, os::unix::fs::MetadataExt};
+use std::{
+    io::{Error, ErrorKind},
+    path::Path,
+};
+#[cfg(test)]
+use test::{nix::unistd::chown, std::fs};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    ReadWrite,
+    // Sockets qemu listens on are created in their directory
+    Create,
+    // Every directory leading to a path needs to be searchable
+    Search,
+}
+
+impl Access {
+    fn bits(&self) -> u32 {
+        match self {
+            Access::Read => 0o4,
+            Access::ReadWrite => 0o6,
+            Access::Create => 0o3,
+            Access::Search => 0o1,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::ReadWrite => "read and write",
+            Access::Create => "create files in",
+            Access::Search => "search",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Denial {
+    path: String,
+    access: Access,
+}
+
+impl Denial {
+    // Directories are never taken over, it is up to the administrator to open them up
+    fn is_fixable(&self) -> bool {
+        self.access == Access::Read || self.access == Access::ReadWrite
+    }
+
+    pub fn describe(&self) -> String {
+        format!("`{}` ({})", self.path, self.access.describe())
+    }
+}
+
+// Disk images and sockets qemu opens by itself, i.e. with the configured user and group. Paths
+// using a protocol, e.g. `nbd:...`, are left to qemu.
+pub fn required_access(config: &Config) -> Vec<(String, Access)> {
+    let options = config.get_command_line_options();
+    let mut required = vec![];
+
+    for (name, value) in options.iter().zip(options.iter().skip(1)) {
+        let properties = split_properties(value);
+        let property = |key: &str| {
+            properties
+                .iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| *value)
+        };
+        let read_only = ["readonly", "read-only", "snapshot"]
+            .iter()
+            .any(|key| property(key) == Some("on"));
+        let image_access = if read_only {
+            Access::Read
+        } else {
+            Access::ReadWrite
+        };
+        let socket_access = if property("server") == Some("on") || value.contains(",server,") {
+            Access::Create
+        } else {
+            Access::ReadWrite
+        };
+
+        let path = match name.as_str() {
+            "-drive" => property("file").map(|file| (file, image_access)),
+            "-blockdev" => property("filename")
+                .or(property("file.filename"))
+                .map(|file| (file, image_access)),
+            "-hda" | "-hdb" | "-hdc" | "-hdd" => Some((value.as_str(), Access::ReadWrite)),
+            "-cdrom" | "-kernel" | "-initrd" | "-bios" => Some((value.as_str(), Access::Read)),
+            "-chardev" if value.starts_with("socket,") => {
+                property("path").map(|path| (path, socket_access))
+            }
+            _ => value
+                .strip_prefix("unix:")
+                .and_then(|path| path.split(',').next())
+                .map(|path| (path, socket_access)),
+        };
+
+        match path {
+            Some((path, access)) if path.len() > 0 && !path.contains(':') => {
+                required.push((path.to_owned(), access))
+            }
+            _ => {}
+        }
+    }
+
+    required
+}
+
+// Mirrors the kernel permission check for the owner, the group and others. Supplementary groups
+// are not taken into account, so a path shared through one of them is reported as well.
+pub fn find_denials(
+    required: &[(String, Access)],
+    uid: u32,
+    gid: u32,
+) -> Result<Vec<Denial>, Error> {
+    let mut denials = vec![];
+
+    if uid == 0 {
+        return Ok(denials);
+    }
+
+    let mut deny = |path: &Path, access| {
+        let denial = Denial {
+            path: path.display().to_string(),
+            access,
+        };
+        if !denials.contains(&denial) {
+            denials.push(denial);
+        }
+    };
+
+    for (path, access) in required {
+        let target = match access {
+            Access::Create => Path::new(path).parent().unwrap_or(Path::new("/")),
+            _ => Path::new(path),
+        };
+
+        for directory in target.ancestors().skip(1) {
+            if let Some(false) = is_allowed(directory, Access::Search, uid, gid)? {
+                deny(directory, Access::Search);
+            }
+        }
+
+        if let Some(false) = is_allowed(target, *access, uid, gid)? {
+            deny(target, *access);
+        }
+    }
+
+    Ok(denials)
+}
+
+// Hands the offending files over to the configured user and group
+pub fn fix_ownership(
+    denials: &[Denial],
+    user: Option<u16>,
+    group: Option<u16>,
+) -> Result<(), Error> {
+    let user = user.map(|id| Uid::from_raw(id as u32));
+    let group = group.map(|id| Gid::from_raw(id as u32));
+
+    for denial in denials.iter().filter(|denial| denial.is_fixable()) {
+        if let Err(e) = chown(denial.path.as_str(), user, group) {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("Failed to change the owner of `{}`: {}", denial.path, e),
+            ));
+        }
+    }
+
+    Ok({})
+}
+
+// Missing paths are not reported, qemu fails on them with a clearer message
+fn is_allowed(path: &Path, access: Access, uid: u32, gid: u32) -> Result<Option<bool>, Error> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to check the permissions of `{}`: {}",
+                    path.display(),
+                    e
+                ),
+            ))
+        }
+    };
+
+    let mode = match (metadata.uid(), metadata.gid()) {
+        (owner, _) if owner == uid => metadata.mode() >> 6,
+        (_, group) if group == gid => metadata.mode() >> 3,
+        _ => metadata.mode(),
+    };
+
+    Ok(Some(mode & access.bits() == access.bits()))
+}
+
+fn split_properties(value: &str) -> Vec<(&str, &str)> {
+    value
+        .split(',')
+        .filter_map(|property| property.split_once('='))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_denials, fix_ownership, required_access, Access};
+    use crate::{config::Config, expect, vec_deq, verify_expectations};
+    use ::std::{
+        cell::RefCell,
+        collections::VecDeque,
+        io::{Error, ErrorKind},
+    };
+
+    struct TestExpectations {
+        nix_unistd_chown: VecDeque<((&'static str, Option<u32>, Option<u32>), nix::Result<()>)>,
+        std_fs_metadata: VecDeque<(&'static str, Result<(u32, u32, u32), Error>)>,
+    }
+
+    impl TestExpectations {
+        fn new() -> Self {
+            TestExpectations {
+                nix_unistd_chown: vec_deq![],
+                std_fs_metadata: vec_deq![],
+            }
+        }
+    }
+
+    thread_local! { static TEST_EXPECTATIONS: RefCell<TestExpectations> = RefCell::new(TestExpectations::new()) }
+
+    fn verify_expectations() {
+        verify_expectations!(
+            nix::unistd::chown => TEST_EXPECTATIONS::nix_unistd_chown,
+            std::fs::metadata => TEST_EXPECTATIONS::std_fs_metadata,
+        );
+    }
+
+    pub mod nix {
+        pub use ::nix::Result;
+
+        pub mod unistd {
+            use super::super::TEST_EXPECTATIONS;
+            use crate::verify_expectation;
+            use ::nix::{
+                unistd::{Gid, Uid},
+                Result,
+            };
+
+            pub fn chown(path: &str, owner: Option<Uid>, group: Option<Gid>) -> Result<()> {
+                let owner = owner.map(|uid| uid.as_raw());
+                let group = group.map(|gid| gid.as_raw());
+
+                verify_expectation!(TEST_EXPECTATIONS::nix_unistd_chown => nix::unistd::chown { path, owner, group })
+            }
+        }
+    }
+
+    pub mod std {
+        pub mod fs {
+            use super::super::TEST_EXPECTATIONS;
+            use crate::verify_expectation;
+            use ::std::{io::Result, path::Path};
+
+            pub struct Metadata {
+                mode: u32,
+                uid: u32,
+                gid: u32,
+            }
+
+            impl Metadata {
+                pub fn mode(&self) -> u32 {
+                    self.mode
+                }
+
+                pub fn uid(&self) -> u32 {
+                    self.uid
+                }
+
+                pub fn gid(&self) -> u32 {
+                    self.gid
+                }
+            }
+
+            pub fn metadata<P: AsRef<Path>>(path: P) -> Result<Metadata> {
+                let path = path.as_ref().to_str().unwrap();
+                let (mode, uid, gid) = verify_expectation!(TEST_EXPECTATIONS::std_fs_metadata => std::fs::metadata { path })?;
+
+                Ok(Metadata { mode, uid, gid })
+            }
+        }
+    }
+
+    #[test]
+    fn required_access_lists_images_and_sockets() {
+        let config = Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              qmp_socket: /run/vm/qmp.sock
+            qemu:
+            - drive: file=/srv/images/disk.qcow2,if=virtio
+            - drive: file=/srv/images/install.iso,media=cdrom,readonly=on
+            - drive: file=nbd:unix:/run/nbd.sock,if=virtio
+            - blockdev: driver=file,node-name=proto0,filename=/srv/images/data.raw,read-only=on
+            - kernel: /boot/vmlinuz
+            - chardev: socket,id=console0,path=/run/vm/console.sock,server=on,wait=off
+            - chardev: socket,id=agent0,path=/run/agent.sock
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                (String::from("/srv/images/disk.qcow2"), Access::ReadWrite),
+                (String::from("/srv/images/install.iso"), Access::Read),
+                (String::from("/srv/images/data.raw"), Access::Read),
+                (String::from("/boot/vmlinuz"), Access::Read),
+                (String::from("/run/vm/console.sock"), Access::Create),
+                (String::from("/run/agent.sock"), Access::ReadWrite),
+                (String::from("/run/vm/qmp.sock"), Access::Create),
+            ],
+            required_access(&config)
+        );
+    }
+
+    #[test]
+    fn find_denials_reports_paths_the_user_can_not_access_and_fixes_files() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_metadata:
+            { "/srv/images" => Ok((0o40750, 0, 0)) },
+            { "/srv" => Ok((0o40755, 0, 0)) },
+            { "/" => Ok((0o40755, 0, 0)) },
+            { "/srv/images/disk.qcow2" => Ok((0o100644, 1000, 1000)) },
+            { "/srv/images" => Ok((0o40750, 0, 0)) },
+            { "/srv" => Ok((0o40755, 0, 0)) },
+            { "/" => Ok((0o40755, 0, 0)) },
+            { "/srv/images/base.qcow2" => Ok((0o100640, 0, 107)) },
+            { "/run" => Ok((0o40755, 0, 0)) },
+            { "/" => Ok((0o40755, 0, 0)) },
+            { "/run/vm" => Ok((0o40755, 0, 0)) },
+            { "/srv/images" => Ok((0o40755, 0, 0)) },
+            { "/srv" => Ok((0o40755, 0, 0)) },
+            { "/" => Ok((0o40755, 0, 0)) },
+            { "/srv/images/missing.qcow2" => Err(Error::new(ErrorKind::NotFound, "std::fs::metadata()")) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::nix_unistd_chown:
+            { "/srv/images/base.qcow2", Some(1000), None => Ok({}) },
+        );
+
+        let denials = find_denials(
+            &[
+                (String::from("/srv/images/disk.qcow2"), Access::ReadWrite),
+                (String::from("/srv/images/base.qcow2"), Access::Read),
+                (String::from("/run/vm/qmp.sock"), Access::Create),
+            ],
+            1000,
+            1000,
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "`/srv/images` (search)",
+                "`/srv/images/base.qcow2` (read)",
+                "`/run/vm` (create files in)",
+            ],
+            denials
+                .iter()
+                .map(|denial| denial.describe())
+                .collect::<Vec<String>>()
+        );
+        assert_eq!(
+            Vec::<String>::new(),
+            find_denials(
+                &[(String::from("/srv/images/missing.qcow2"), Access::ReadWrite)],
+                1000,
+                1000
+            )
+            .unwrap()
+            .iter()
+            .map(|denial| denial.describe())
+            .collect::<Vec<String>>()
+        );
+        assert_eq!(
+            0,
+            find_denials(
+                &[(String::from("/root/disk.qcow2"), Access::ReadWrite)],
+                0,
+                0
+            )
+            .unwrap()
+            .len()
+        );
+
+        fix_ownership(&denials, Some(1000), None).unwrap();
+
+        verify_expectations();
+    }
+}
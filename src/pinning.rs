@@ -0,0 +1,205 @@
+use nix::{
+    sched::{sched_setaffinity, CpuSet as AffinityMask},
+    unistd::Pid,
+};
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+};
+
+pub trait PinningBackend {
+    // Reserves the host CPUs for exclusive use, returning the reason for every CPU which could not be reserved
+    fn isolate(&mut self, host_ids: &[usize]) -> HashMap<usize, String>;
+
+    fn pin(&mut self, host_id: usize, task_id: usize) -> Result<(), Error>;
+
+    fn release(&mut self) -> Result<(), Error>;
+
+    // Releases the host CPUs even though pinned tasks might still run on them, e.g. when the launcher
+    // is going away while qemu outlives it
+    fn evict(&mut self) -> Result<(), Error> {
+        self.release()
+    }
+
+    fn pin_tasks(&mut self, pins: &[(usize, usize)]) -> Vec<Result<(), Error>> {
+        let mut host_ids = vec![];
+        for (host_id, _) in pins {
+            if !host_ids.contains(host_id) {
+                host_ids.push(*host_id);
+            }
+        }
+
+        let failures = self.isolate(&host_ids);
+
+        pins.iter()
+            .map(|(host_id, task_id)| {
+                if let Some(e) = failures.get(host_id) {
+                    return Err(Error::new(
+                        ErrorKind::Other,
+                        format!(
+                            "Failed to isolate the host cpu thread `{}` - {}",
+                            host_id, e
+                        ),
+                    ));
+                }
+
+                if let Err(e) = self.pin(*host_id, *task_id) {
+                    return Err(Error::new(
+                        ErrorKind::Other,
+                        format!(
+                            "Failed to pin the process id `{}` to the host cpu thread `{}` - {}",
+                            task_id, host_id, e
+                        ),
+                    ));
+                }
+
+                Ok({})
+            })
+            .collect()
+    }
+}
+
+// Only restricts the pinned tasks to their host CPUs, without reserving the CPUs for them. Used when
+// the launcher is not allowed to manage the cpuset cgroup tree, other tasks may still run on the CPUs.
+pub struct AffinityBackend {}
+
+impl AffinityBackend {
+    pub fn new() -> Self {
+        AffinityBackend {}
+    }
+}
+
+impl PinningBackend for AffinityBackend {
+    fn isolate(&mut self, _host_ids: &[usize]) -> HashMap<usize, String> {
+        HashMap::new()
+    }
+
+    fn pin(&mut self, host_id: usize, task_id: usize) -> Result<(), Error> {
+        set_affinity(task_id, &[host_id])
+    }
+
+    // The affinity of qemu tasks goes away with them, there is nothing to release
+    fn release(&mut self) -> Result<(), Error> {
+        Ok({})
+    }
+}
+
+pub fn set_affinity(task_id: usize, host_ids: &[usize]) -> Result<(), Error> {
+    let mut mask = AffinityMask::new();
+    for host_id in host_ids {
+        if let Err(e) = mask.set(*host_id) {
+            return Err(Error::new(ErrorKind::InvalidInput, e.to_string()));
+        }
+    }
+
+    if let Err(e) = sched_setaffinity(Pid::from_raw(task_id as i32), &mask) {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!("Failed to set the CPU affinity: {}", e),
+        ));
+    }
+
+    Ok({})
+}
+
+#[cfg(test)]
+pub mod mock {
+    use super::PinningBackend;
+    use std::{
+        collections::HashMap,
+        io::{Error, ErrorKind},
+    };
+
+    // Records pinning requests instead of touching the host, for tests of code driving a backend
+    pub struct MockBackend {
+        pub isolation_failures: HashMap<usize, String>,
+        pub pin_failures: Vec<usize>,
+        pub isolated: Vec<usize>,
+        pub pinned: Vec<(usize, usize)>,
+        pub released: bool,
+    }
+
+    impl MockBackend {
+        pub fn new() -> Self {
+            MockBackend {
+                isolation_failures: HashMap::new(),
+                pin_failures: vec![],
+                isolated: vec![],
+                pinned: vec![],
+                released: false,
+            }
+        }
+    }
+
+    impl PinningBackend for MockBackend {
+        fn isolate(&mut self, host_ids: &[usize]) -> HashMap<usize, String> {
+            for host_id in host_ids {
+                if !self.isolation_failures.contains_key(host_id) {
+                    self.isolated.push(*host_id);
+                }
+            }
+
+            self.isolation_failures.clone()
+        }
+
+        fn pin(&mut self, host_id: usize, task_id: usize) -> Result<(), Error> {
+            if self.pin_failures.contains(&task_id) {
+                return Err(Error::new(ErrorKind::Other, "MockBackend::pin()"));
+            }
+
+            self.pinned.push((host_id, task_id));
+
+            Ok({})
+        }
+
+        fn release(&mut self) -> Result<(), Error> {
+            self.released = true;
+
+            Ok({})
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{mock::MockBackend, PinningBackend};
+
+    #[test]
+    fn pin_tasks_isolates_every_host_cpu_once_and_pins_tasks() {
+        let mut backend = MockBackend::new();
+
+        let results = backend.pin_tasks(&[(2, 1001), (3, 1002), (2, 1003)]);
+
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert_eq!(vec![2, 3], backend.isolated);
+        assert_eq!(vec![(2, 1001), (3, 1002), (2, 1003)], backend.pinned);
+    }
+
+    #[test]
+    fn pin_tasks_reports_isolation_and_pinning_failures_per_task() {
+        let mut backend = MockBackend::new();
+        backend
+            .isolation_failures
+            .insert(2, String::from("CPU is busy"));
+        backend.pin_failures.push(1002);
+
+        let results: Vec<String> = backend
+            .pin_tasks(&[(2, 1001), (3, 1002), (4, 1003)])
+            .into_iter()
+            .map(|result| match result {
+                Ok(_) => String::from("ok"),
+                Err(e) => e.to_string(),
+            })
+            .collect();
+
+        assert_eq!(
+            vec![
+                "Failed to isolate the host cpu thread `2` - CPU is busy",
+                "Failed to pin the process id `1002` to the host cpu thread `3` - MockBackend::pin()",
+                "ok",
+            ],
+            results
+        );
+        assert_eq!(vec![(4, 1003)], backend.pinned);
+    }
+}
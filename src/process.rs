@@ -1,16 +1,28 @@
-use crate::qmp::QmpPipe;
+use crate::{
+    clock::{self, Clock},
+    config::{Deadline, UtilClamp},
+    qmp::QmpPipe,
+};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     ffi::{OsStr, OsString},
     io::{Error, ErrorKind, Read, Result, Write},
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 #[cfg(not(test))]
 use std::{
     os::unix::process::CommandExt,
-    process::{Child, Command, Stdio},
+    process::{Child, ChildStderr, ChildStdin, ChildStdout, Command, Stdio},
 };
 #[cfg(test)]
-use test::std::process::{Child, Command, Stdio};
+use test::std::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command, Stdio};
+
+const ONESHOT_TIMEOUT: Duration = Duration::from_secs(30);
+const ONESHOT_POLL_INTERVAL: Duration = Duration::from_millis(10);
 
 pub struct StdioReadWrite<'a> {
     stdin: &'a mut dyn Write,
@@ -44,6 +56,37 @@ impl Write for StdioReadWrite<'_> {
 
 impl QmpPipe for StdioReadWrite<'_> {}
 
+// The child process stdio taken over from it, so QMP can be served from another thread
+pub struct ChildStdio {
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+}
+
+impl Read for ChildStdio {
+    fn read(&mut self, message: &mut [u8]) -> Result<usize> {
+        self.stdout.read(message)
+    }
+}
+
+impl Write for ChildStdio {
+    fn write(&mut self, message: &[u8]) -> Result<usize> {
+        self.stdin.write(message)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.stdin.flush()
+    }
+}
+
+impl QmpPipe for ChildStdio {}
+
+impl ChildStdio {
+    // Allows reading and writing from separate threads, e.g. to share the QMP channel
+    pub fn into_split(self) -> (ChildStdout, ChildStdin) {
+        (self.stdout, self.stdin)
+    }
+}
+
 pub struct ChildProcess {
     child: Child,
 }
@@ -71,6 +114,34 @@ impl ChildProcess {
         }
     }
 
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    pub fn has_exited(&mut self) -> Result<bool> {
+        match self.child.try_wait() {
+            Ok(status) => Ok(status.is_some()),
+            Err(e) => Err(Error::new(
+                e.kind(),
+                format!("Failed to check the child process state: {}", e),
+            )),
+        }
+    }
+
+    pub fn kill(&mut self) -> Result<()> {
+        match self.child.kill() {
+            Ok(_) => Ok({}),
+            Err(e) => Err(Error::new(
+                e.kind(),
+                format!("Failed to kill the child process: {}", e),
+            )),
+        }
+    }
+
+    pub fn take_stderr(&mut self) -> Option<ChildStderr> {
+        self.child.stderr.take()
+    }
+
     pub fn get_stdio(&mut self) -> Result<StdioReadWrite> {
         let stdin = match self.child.stdin.as_mut() {
             Some(stdin) => stdin,
@@ -93,26 +164,44 @@ impl ChildProcess {
 
         Ok(StdioReadWrite::new(stdin, stdout))
     }
+
+    pub fn take_stdio(&mut self) -> Result<ChildStdio> {
+        match (self.child.stdin.take(), self.child.stdout.take()) {
+            (Some(stdin), Some(stdout)) => Ok(ChildStdio { stdin, stdout }),
+            _ => Err(Error::new(
+                ErrorKind::Other,
+                "Unable to take over the child process stdio",
+            )),
+        }
+    }
 }
 
 pub struct Process {
     command: OsString,
+    ambient_clear: bool,
     arguments: Vec<OsString>,
+    capture_stderr: bool,
     env_clear: bool,
+    thp_disable: bool,
     uid: Option<u32>,
     gid: Option<u32>,
     envs: HashMap<OsString, OsString>,
+    clock: Arc<dyn Clock>,
 }
 
 impl Process {
     pub fn new<C: AsRef<OsStr>>(command: C) -> Self {
         Self {
             command: command.as_ref().to_owned(),
+            ambient_clear: false,
             arguments: vec![],
+            capture_stderr: false,
             env_clear: false,
+            thp_disable: false,
             uid: None,
             gid: None,
             envs: HashMap::new(),
+            clock: clock::system(),
         }
     }
 
@@ -126,12 +215,30 @@ impl Process {
         self
     }
 
+    pub fn should_capture_stderr(mut self, should_capture: bool) -> Self {
+        self.capture_stderr = should_capture;
+
+        self
+    }
+
     pub fn should_clear_env(mut self, should_clear: bool) -> Self {
         self.env_clear = should_clear;
 
         self
     }
 
+    pub fn should_disable_thp(mut self, should_disable: bool) -> Self {
+        self.thp_disable = should_disable;
+
+        self
+    }
+
+    pub fn should_clear_ambient_capabilities(mut self, should_clear: bool) -> Self {
+        self.ambient_clear = should_clear;
+
+        self
+    }
+
     pub fn set_effective_user_id(mut self, uid: &Option<u16>) -> Self {
         if let Some(uid) = uid {
             self.uid = Some(*uid as u32);
@@ -166,27 +273,18 @@ impl Process {
         self
     }
 
-    pub fn spawn(self) -> Result<ChildProcess> {
-        let mut command = Command::new(self.command.as_os_str());
-        command
-            .args(self.arguments)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped());
-
-        if self.env_clear {
-            command.env_clear();
-        }
+    pub fn set_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
 
-        if let Some(uid) = self.uid {
-            command.uid(uid);
-        }
+        self
+    }
 
-        if let Some(gid) = self.gid {
-            command.gid(gid);
-        }
+    pub fn spawn(self) -> Result<ChildProcess> {
+        let mut command = self.build_command();
+        command.stdin(Stdio::piped()).stdout(Stdio::piped());
 
-        if self.envs.len() > 0 {
-            command.envs(self.envs);
+        if self.capture_stderr {
+            command.stderr(Stdio::piped());
         }
 
         let child = match command.spawn() {
@@ -202,63 +300,393 @@ impl Process {
         Ok(ChildProcess { child })
     }
 
-    pub fn oneshot<C: AsRef<OsStr>, I: IntoIterator<Item = S>, S: AsRef<OsStr>>(
-        command: C,
-        arguments: I,
-    ) -> Result<()> {
-        let result = match Command::new(command.as_ref()).args(arguments).output() {
-            Ok(r) => r,
+    // Runs the command to completion and returns its stdout, the command is killed once it runs
+    // longer than the timeout, so a hung helper never blocks the launcher
+    pub fn run(self, timeout: Duration) -> Result<String> {
+        let name = self.command.to_string_lossy().to_string();
+
+        let mut command = self.build_command();
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let mut child = match command.spawn() {
+            Ok(c) => c,
             Err(e) => {
                 return Err(Error::new(
                     ErrorKind::Other,
-                    format!(
-                        "Unable to execute the `{}` command: {}",
-                        command.as_ref().to_string_lossy(),
-                        e
-                    ),
+                    format!("Unable to execute the `{}` command: {}", name, e),
                 ))
             }
         };
 
-        if !result.status.success() {
-            let stderr = String::from_utf8_lossy(&result.stderr);
-            let stdout = String::from_utf8_lossy(&result.stdout);
+        // Pipes are drained concurrently, so a chatty command can not stall on a full pipe buffer
+        let stdout = child.stdout.take().map(drain_pipe);
+        let stderr = child.stderr.take().map(drain_pipe);
+
+        let deadline = self.clock.now() + timeout;
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if self.clock.now() >= deadline => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+
+                    return Err(Error::new(
+                        ErrorKind::TimedOut,
+                        format!(
+                            "The `{}` command did not finish within {} ms and was killed.",
+                            name,
+                            timeout.as_millis()
+                        ),
+                    ));
+                }
+                Ok(None) => self.clock.sleep(ONESHOT_POLL_INTERVAL),
+                Err(e) => {
+                    return Err(Error::new(
+                        e.kind(),
+                        format!("Failed to wait for the `{}` command: {}", name, e),
+                    ))
+                }
+            }
+        };
+
+        let stdout = collect_pipe(stdout);
+        let stderr = collect_pipe(stderr);
 
+        if !status.success() {
             return Err(Error::new(
                 ErrorKind::Other,
                 format!(
                     "The `{}` command failed with:\nstdout:\n{}\n\nstderr:\n{}\n",
-                    command.as_ref().to_string_lossy(),
-                    stdout,
-                    stderr,
+                    name, stdout, stderr,
                 ),
             ));
         }
 
-        Ok({})
+        Ok(stdout)
+    }
+
+    pub fn oneshot<C: AsRef<OsStr>, I: IntoIterator<Item = S>, S: AsRef<OsStr>>(
+        command: C,
+        arguments: I,
+    ) -> Result<String> {
+        Process::new(command)
+            .set_args(arguments)
+            .run(ONESHOT_TIMEOUT)
+    }
+
+    fn build_command(&self) -> Command {
+        let mut command = Command::new(self.command.as_os_str());
+        command.args(&self.arguments);
+
+        if self.env_clear {
+            command.env_clear();
+        }
+
+        if self.thp_disable {
+            // Safe, since only the async-signal-safe prctl() is called in the child
+            unsafe {
+                command.pre_exec(disable_thp);
+            }
+        }
+
+        if self.ambient_clear {
+            // Safe, since only the async-signal-safe prctl() is called in the child
+            unsafe {
+                command.pre_exec(clear_ambient_capabilities);
+            }
+        }
+
+        if let Some(uid) = self.uid {
+            command.uid(uid);
+        }
+
+        if let Some(gid) = self.gid {
+            command.gid(gid);
+        }
+
+        if self.envs.len() > 0 {
+            command.envs(&self.envs);
+        }
+
+        command
+    }
+}
+
+// Runs helper commands on a bounded number of threads and starts them no more often than the
+// interval allows, so scheduling dozens of vCPU threads does not spawn dozens of processes at once
+pub struct OneshotPool {
+    clock: Arc<dyn Clock>,
+    interval: Duration,
+    workers: usize,
+}
+
+impl OneshotPool {
+    pub fn new(workers: usize, interval: Duration) -> Self {
+        OneshotPool {
+            clock: clock::system(),
+            interval: interval,
+            workers: workers.max(1),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+
+        self
+    }
+
+    // Returns the result of every command in the order the commands were given
+    pub fn oneshot_all(&self, commands: Vec<(String, Vec<String>)>) -> Vec<Result<String>> {
+        self.run(commands, |(command, arguments)| {
+            Process::oneshot(command, arguments)
+        })
+        .into_iter()
+        .map(|result| match result {
+            Ok(result) => result,
+            Err(message) => Err(Error::new(
+                ErrorKind::Other,
+                format!("The helper command crashed: {}", message),
+            )),
+        })
+        .collect()
+    }
+
+    // A panicking job is reported with its panic message, the worker carries on with the rest
+    fn run<J, R, F>(&self, jobs: Vec<J>, job: F) -> Vec<std::result::Result<R, String>>
+    where
+        J: Send + 'static,
+        R: Send + 'static,
+        F: Fn(J) -> R + Send + Sync + 'static,
+    {
+        let count = jobs.len();
+        let queue = Arc::new(Mutex::new(
+            jobs.into_iter().enumerate().collect::<VecDeque<_>>(),
+        ));
+        let next_start = Arc::new(Mutex::new(self.clock.now()));
+        let job = Arc::new(job);
+        let (sender, receiver) = mpsc::channel();
+
+        let workers: Vec<JoinHandle<()>> = (0..self.workers.min(count))
+            .map(|_| {
+                let clock = self.clock.clone();
+                let queue = queue.clone();
+                let next_start = next_start.clone();
+                let job = job.clone();
+                let sender = sender.clone();
+                let interval = self.interval;
+
+                thread::spawn(move || loop {
+                    let (index, item) = match queue.lock().unwrap().pop_front() {
+                        Some(entry) => entry,
+                        None => break,
+                    };
+
+                    wait_for_start(&*clock, &next_start, interval);
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| job(item)))
+                        .map_err(|payload| get_panic_message(&*payload));
+                    if sender.send((index, result)).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        let mut results: Vec<Option<std::result::Result<R, String>>> =
+            (0..count).map(|_| None).collect();
+        for (index, result) in receiver {
+            results[index] = Some(result);
+        }
+
+        for worker in workers {
+            let _ = worker.join();
+        }
+
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err("the worker exited".to_string())))
+            .collect()
+    }
+}
+
+// The lock is held while waiting, so the workers take turns starting their commands
+fn wait_for_start(clock: &dyn Clock, next_start: &Mutex<Instant>, interval: Duration) {
+    let mut next_start = next_start.lock().unwrap();
+
+    let now = clock.now();
+    if *next_start > now {
+        clock.sleep(*next_start - now);
+    }
+
+    *next_start = clock.now() + interval;
+}
+
+fn get_panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+fn drain_pipe<R: Read + Send + 'static>(mut pipe: R) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = vec![];
+        let _ = pipe.read_to_end(&mut buffer);
+
+        buffer
+    })
+}
+
+fn collect_pipe(reader: Option<JoinHandle<Vec<u8>>>) -> String {
+    match reader.map(|reader| reader.join()) {
+        Some(Ok(buffer)) => String::from_utf8_lossy(&buffer).to_string(),
+        _ => String::new(),
+    }
+}
+
+const SCHED_DEADLINE: u32 = 6;
+const SCHED_FLAG_KEEP_ALL: u64 = 0x08 | 0x10;
+const SCHED_FLAG_UTIL_CLAMP_MIN: u64 = 0x20;
+const SCHED_FLAG_UTIL_CLAMP_MAX: u64 = 0x40;
+const SCHED_CAPACITY_SCALE: u32 = 1024;
+
+// The kernel structure is not exposed by libc, see `man sched_setattr(2)`
+#[repr(C)]
+struct SchedAttr {
+    size: u32,
+    sched_policy: u32,
+    sched_flags: u64,
+    sched_nice: i32,
+    sched_priority: u32,
+    sched_runtime: u64,
+    sched_deadline: u64,
+    sched_period: u64,
+    sched_util_min: u32,
+    sched_util_max: u32,
+}
+
+pub fn set_deadline_scheduler(task_id: i32, deadline: &Deadline) -> Result<()> {
+    let attr = SchedAttr {
+        size: mem::size_of::<SchedAttr>() as u32,
+        sched_policy: SCHED_DEADLINE,
+        sched_flags: 0,
+        sched_nice: 0,
+        sched_priority: 0,
+        sched_runtime: deadline.get_runtime_us() * 1000,
+        sched_deadline: deadline.get_deadline_us() * 1000,
+        sched_period: deadline.get_period_us() * 1000,
+        sched_util_min: 0,
+        sched_util_max: 0,
+    };
+
+    sched_setattr(task_id, &attr)
+}
+
+pub fn set_util_clamp(task_id: i32, clamp: &UtilClamp) -> Result<()> {
+    // Only the clamps are changed, the scheduling policy and parameters of the thread are kept intact
+    let mut flags = SCHED_FLAG_KEEP_ALL;
+    if clamp.get_min().is_some() {
+        flags |= SCHED_FLAG_UTIL_CLAMP_MIN;
+    }
+    if clamp.get_max().is_some() {
+        flags |= SCHED_FLAG_UTIL_CLAMP_MAX;
+    }
+
+    let attr = SchedAttr {
+        size: mem::size_of::<SchedAttr>() as u32,
+        sched_policy: 0,
+        sched_flags: flags,
+        sched_nice: 0,
+        sched_priority: 0,
+        sched_runtime: 0,
+        sched_deadline: 0,
+        sched_period: 0,
+        sched_util_min: percent_to_capacity(clamp.get_min()),
+        sched_util_max: percent_to_capacity(clamp.get_max()),
+    };
+
+    sched_setattr(task_id, &attr)
+}
+
+fn percent_to_capacity(percent: Option<u8>) -> u32 {
+    percent.unwrap_or(0) as u32 * SCHED_CAPACITY_SCALE / 100
+}
+
+fn sched_setattr(task_id: i32, attr: &SchedAttr) -> Result<()> {
+    match unsafe {
+        nix::libc::syscall(
+            nix::libc::SYS_sched_setattr,
+            task_id,
+            attr as *const SchedAttr,
+            0,
+        )
+    } {
+        0 => Ok({}),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+fn disable_thp() -> Result<()> {
+    // The flag is inherited by the child across execve() and disables THP for all of its memory
+    match unsafe { nix::libc::prctl(nix::libc::PR_SET_THP_DISABLE, 1, 0, 0, 0) } {
+        0 => Ok({}),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+fn clear_ambient_capabilities() -> Result<()> {
+    // Ambient capabilities survive execve(), qemu must not inherit the ones granted to the launcher
+    match unsafe {
+        nix::libc::prctl(
+            nix::libc::PR_CAP_AMBIENT,
+            nix::libc::PR_CAP_AMBIENT_CLEAR_ALL,
+            0,
+            0,
+            0,
+        )
+    } {
+        0 => Ok({}),
+        _ => Err(Error::last_os_error()),
     }
 }
 
 #[cfg(test)]
 mod test {
-    use self::std::process::{Child, ChildStdin, ChildStdout, ExitStatus, Stdio};
-    use super::{ChildProcess, Process};
-    use crate::{assert_error, expect, vec_deq, verify_expectations};
+    use self::std::process::{Child, ChildStderr, ChildStdin, ChildStdout, ExitStatus, Stdio};
+    use super::{ChildProcess, OneshotPool, Process};
+    use crate::{assert_error, clock::fake::FakeClock, expect, vec_deq, verify_expectations};
     use ::std::{
         cell::RefCell,
         collections::VecDeque,
         io::{Error, ErrorKind, Result},
     };
+    use ::std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
 
     struct TestExpectations {
+        std_process_child_id: VecDeque<((), u32)>,
+        std_process_child_kill: VecDeque<((), Result<()>)>,
+        std_process_child_try_wait: VecDeque<((), Result<Option<ExitStatus>>)>,
         std_process_child_wait: VecDeque<((), Result<ExitStatus>)>,
         std_process_command_args: VecDeque<(Vec<&'static str>, ())>,
         std_process_command_env_clear: VecDeque<((), ())>,
         std_process_command_envs: VecDeque<(Vec<(String, String)>, ())>,
         std_process_command_gid: VecDeque<(u32, ())>,
         std_process_command_new: VecDeque<(&'static str, ())>,
-        std_process_command_output: VecDeque<((), Result<std::process::Output>)>,
+        std_process_command_pre_exec: VecDeque<((), ())>,
         std_process_command_spawn: VecDeque<((), Result<Child>)>,
+        std_process_command_stderr: VecDeque<(std::process::Stdio, ())>,
         std_process_command_stdin: VecDeque<(std::process::Stdio, ())>,
         std_process_command_stdout: VecDeque<(std::process::Stdio, ())>,
         std_process_command_uid: VecDeque<(u32, ())>,
@@ -269,14 +697,18 @@ mod test {
     impl TestExpectations {
         fn new() -> Self {
             TestExpectations {
+                std_process_child_id: vec_deq![],
+                std_process_child_kill: vec_deq![],
+                std_process_child_try_wait: vec_deq![],
                 std_process_child_wait: vec_deq![],
                 std_process_command_args: vec_deq![],
                 std_process_command_env_clear: vec_deq![],
                 std_process_command_envs: vec_deq![],
                 std_process_command_gid: vec_deq![],
                 std_process_command_new: vec_deq![],
-                std_process_command_output: vec_deq![],
+                std_process_command_pre_exec: vec_deq![],
                 std_process_command_spawn: vec_deq![],
+                std_process_command_stderr: vec_deq![],
                 std_process_command_stdin: vec_deq![],
                 std_process_command_stdout: vec_deq![],
                 std_process_command_uid: vec_deq![],
@@ -295,7 +727,7 @@ mod test {
             use std::{
                 cmp::PartialEq,
                 ffi::OsStr,
-                io::{Read, Result, Write},
+                io::{Cursor, Read, Result, Write},
             };
 
             pub struct ExitStatus {}
@@ -316,12 +748,6 @@ mod test {
                 }
             }
 
-            pub struct Output {
-                pub status: ExitStatus,
-                pub stdout: Vec<u8>,
-                pub stderr: Vec<u8>,
-            }
-
             #[derive(Debug)]
             pub struct Stdio {
                 t: &'static str,
@@ -331,6 +757,10 @@ mod test {
                 pub fn piped() -> Self {
                     Self { t: "piped" }
                 }
+
+                pub fn null() -> Self {
+                    Self { t: "null" }
+                }
             }
 
             impl PartialEq for Stdio {
@@ -351,20 +781,69 @@ mod test {
                 }
             }
 
-            pub struct ChildStdout {}
+            // Pipes are read from the reader threads, so they carry their contents instead of
+            // consulting the thread local expectations
+            pub struct ChildStdout {
+                data: Cursor<Vec<u8>>,
+            }
+
+            impl ChildStdout {
+                pub fn new(data: &str) -> Self {
+                    Self {
+                        data: Cursor::new(data.as_bytes().to_vec()),
+                    }
+                }
+            }
 
             impl Read for ChildStdout {
-                fn read(&mut self, _: &mut [u8]) -> Result<usize> {
-                    panic!("Unexpected call to std::process::ChildStdout::read() method.")
+                fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+                    self.data.read(buffer)
+                }
+            }
+
+            pub struct ChildStderr {
+                data: Cursor<Vec<u8>>,
+            }
+
+            impl ChildStderr {
+                pub fn new(data: &str) -> Self {
+                    Self {
+                        data: Cursor::new(data.as_bytes().to_vec()),
+                    }
+                }
+            }
+
+            impl Read for ChildStderr {
+                fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+                    self.data.read(buffer)
                 }
             }
 
             pub struct Child {
                 pub stdin: Option<ChildStdin>,
                 pub stdout: Option<ChildStdout>,
+                pub stderr: Option<ChildStderr>,
             }
 
             impl Child {
+                pub fn id(&self) -> u32 {
+                    verify_expectation!(
+                        TEST_EXPECTATIONS::std_process_child_id => std::process::Child::id { _ }
+                    )
+                }
+
+                pub fn kill(&mut self) -> Result<()> {
+                    verify_expectation!(
+                        TEST_EXPECTATIONS::std_process_child_kill => std::process::Child::kill { _ }
+                    )
+                }
+
+                pub fn try_wait(&mut self) -> Result<Option<ExitStatus>> {
+                    verify_expectation!(
+                        TEST_EXPECTATIONS::std_process_child_try_wait => std::process::Child::try_wait { _ }
+                    )
+                }
+
                 pub fn wait(&mut self) -> Result<ExitStatus> {
                     verify_expectation!(
                         TEST_EXPECTATIONS::std_process_child_wait => std::process::Child::wait { _ }
@@ -398,12 +877,6 @@ mod test {
                     self
                 }
 
-                pub fn output(&mut self) -> Result<Output> {
-                    verify_expectation!(
-                        TEST_EXPECTATIONS::std_process_command_output => std::process::Command::output { _ }
-                    )
-                }
-
                 pub fn stdin<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
                     let cfg: Stdio = cfg.into();
                     verify_expectation!(
@@ -422,6 +895,15 @@ mod test {
                     self
                 }
 
+                pub fn stderr<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+                    let cfg: Stdio = cfg.into();
+                    verify_expectation!(
+                        TEST_EXPECTATIONS::std_process_command_stderr => std::process::Command::stderr { cfg }
+                    );
+
+                    self
+                }
+
                 pub fn env_clear(&mut self) -> &mut Self {
                     verify_expectation!(
                         TEST_EXPECTATIONS::std_process_command_env_clear =>
@@ -456,6 +938,18 @@ mod test {
                     )
                 }
 
+                pub unsafe fn pre_exec<F: FnMut() -> Result<()> + Send + Sync + 'static>(
+                    &mut self,
+                    _: F,
+                ) -> &mut Self {
+                    verify_expectation!(
+                        TEST_EXPECTATIONS::std_process_command_pre_exec =>
+                            std::process::Command::pre_exec { _ }
+                    );
+
+                    self
+                }
+
                 pub fn uid(&mut self, id: u32) -> &mut Self {
                     verify_expectation!(
                         TEST_EXPECTATIONS::std_process_command_uid => std::process::Command::uid { id }
@@ -483,14 +977,18 @@ mod test {
 
     fn verify_expectations() {
         verify_expectations!(
+            std::process::Child::id => TEST_EXPECTATIONS::std_process_child_id,
+            std::process::Child::kill => TEST_EXPECTATIONS::std_process_child_kill,
+            std::process::Child::try_wait => TEST_EXPECTATIONS::std_process_child_try_wait,
             std::process::Child::wait => TEST_EXPECTATIONS::std_process_child_wait,
             std::process::Command::args => TEST_EXPECTATIONS::std_process_command_args,
             std::process::Command::env_clear => TEST_EXPECTATIONS::std_process_command_env_clear,
             std::process::Command::envs => TEST_EXPECTATIONS::std_process_command_envs,
             std::process::Command::gid => TEST_EXPECTATIONS::std_process_command_gid,
             std::process::Command::new => TEST_EXPECTATIONS::std_process_command_new,
-            std::process::Command::output => TEST_EXPECTATIONS::std_process_command_output,
+            std::process::Command::pre_exec => TEST_EXPECTATIONS::std_process_command_pre_exec,
             std::process::Command::spawn => TEST_EXPECTATIONS::std_process_command_spawn,
+            std::process::Command::stderr => TEST_EXPECTATIONS::std_process_command_stderr,
             std::process::Command::stdin => TEST_EXPECTATIONS::std_process_command_stdin,
             std::process::Command::stdout => TEST_EXPECTATIONS::std_process_command_stdout,
             std::process::Command::uid => TEST_EXPECTATIONS::std_process_command_uid,
@@ -499,21 +997,33 @@ mod test {
         );
     }
 
+    fn expect_oneshot_pipes() {
+        expect!(TEST_EXPECTATIONS::std_process_command_stdin: { Stdio::null() => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_stdout: { Stdio::piped() => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_stderr: { Stdio::piped() => _ });
+    }
+
     #[test]
     fn process_oneshot_command_executes_process_successfully() {
         expect!(TEST_EXPECTATIONS::std_process_command_new: { "ls" => _ });
         expect!(TEST_EXPECTATIONS::std_process_command_args: { vec!["-la"] => _ });
-        expect!(TEST_EXPECTATIONS::std_process_command_output: { _ => Ok(std::process::Output {
-            status: std::process::ExitStatus {},
-            stdout: "total 47\n\
+        expect_oneshot_pipes();
+        expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
+            stdin: None,
+            stdout: Some(ChildStdout::new("total 47\n\
                 drwxr-xr-x 5 root root  3488 Oct 26 16:54 .\n\
-                drwxr-xr-x 4 root root  3488 Jun  7 16:09 ..\n\
-                ".as_bytes().to_vec(),
-            stderr: vec![],
+                drwxr-xr-x 4 root root  3488 Jun  7 16:09 ..\n")),
+            stderr: Some(ChildStderr::new("")),
         }) });
+        expect!(TEST_EXPECTATIONS::std_process_child_try_wait: { _ => Ok(Some(ExitStatus {})) });
         expect!(TEST_EXPECTATIONS::std_process_exit_status_success: { _ => true });
 
-        assert!(Process::oneshot("ls", &["-la"]).is_ok());
+        assert_eq!(
+            "total 47\n\
+            drwxr-xr-x 5 root root  3488 Oct 26 16:54 .\n\
+            drwxr-xr-x 4 root root  3488 Jun  7 16:09 ..\n",
+            Process::oneshot("ls", &["-la"]).unwrap()
+        );
 
         verify_expectations();
     }
@@ -522,8 +1032,9 @@ mod test {
     fn process_oneshot_command_returns_error_if_process_execution_fails() {
         expect!(TEST_EXPECTATIONS::std_process_command_new: { "lsl" => _ });
         expect!(TEST_EXPECTATIONS::std_process_command_args: { vec!["-la"] => _ });
+        expect_oneshot_pipes();
         expect!(
-            TEST_EXPECTATIONS::std_process_command_output:
+            TEST_EXPECTATIONS::std_process_command_spawn:
             { _ => Err(Error::new(ErrorKind::NotFound, format!("lsl: command not found\n"))) },
         );
 
@@ -540,11 +1051,15 @@ mod test {
     fn process_oneshot_command_returns_error_if_process_returns_non_zero_status() {
         expect!(TEST_EXPECTATIONS::std_process_command_new: { "ls" => _ });
         expect!(TEST_EXPECTATIONS::std_process_command_args: { vec!["/not_existent"] => _ });
-        expect!(TEST_EXPECTATIONS::std_process_command_output: { _ => Ok(std::process::Output {
-            status: std::process::ExitStatus {},
-            stdout: vec![],
-            stderr: "ls: cannot open directory '/non_existent': Permission denied\n".as_bytes().to_vec(),
+        expect_oneshot_pipes();
+        expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
+            stdin: None,
+            stdout: Some(ChildStdout::new("")),
+            stderr: Some(ChildStderr::new(
+                "ls: cannot open directory '/non_existent': Permission denied\n"
+            )),
         }) });
+        expect!(TEST_EXPECTATIONS::std_process_child_try_wait: { _ => Ok(Some(ExitStatus {})) });
         expect!(TEST_EXPECTATIONS::std_process_exit_status_success: { _ => false });
 
         assert_error!(
@@ -557,6 +1072,117 @@ mod test {
         verify_expectations();
     }
 
+    #[test]
+    fn process_run_kills_command_exceeding_timeout() {
+        expect!(TEST_EXPECTATIONS::std_process_command_new: { "nft" => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_args: { vec!["list", "ruleset"] => _ });
+        expect_oneshot_pipes();
+        expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
+            stdin: None,
+            stdout: Some(ChildStdout::new("")),
+            stderr: Some(ChildStderr::new("")),
+        }) });
+        expect!(
+            TEST_EXPECTATIONS::std_process_child_try_wait:
+            { _ => Ok(None) },
+            { _ => Ok(None) },
+            { _ => Ok(None) },
+            { _ => Ok(None) },
+        );
+        expect!(TEST_EXPECTATIONS::std_process_child_kill: { _ => Ok({}) });
+        expect!(TEST_EXPECTATIONS::std_process_child_wait: { _ => Ok(ExitStatus {}) });
+
+        let clock = FakeClock::new();
+
+        assert_error!(
+            ErrorKind::TimedOut,
+            "The `nft` command did not finish within 30 ms and was killed.",
+            Process::new("nft")
+                .set_args(&["list", "ruleset"])
+                .set_clock(clock.clone())
+                .run(Duration::from_millis(30))
+        );
+        assert_eq!(vec![Duration::from_millis(10); 3], clock.get_sleeps());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn process_run_clears_environment_of_command() {
+        expect!(TEST_EXPECTATIONS::std_process_command_new: { "ip" => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_args: { vec!["link"] => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_env_clear: { _ => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_envs: {
+            vec![(String::from("LC_ALL"), String::from("C"))] => _
+        });
+        expect_oneshot_pipes();
+        expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
+            stdin: None,
+            stdout: Some(ChildStdout::new("1: lo: <LOOPBACK,UP,LOWER_UP>\n")),
+            stderr: Some(ChildStderr::new("")),
+        }) });
+        expect!(TEST_EXPECTATIONS::std_process_child_try_wait: { _ => Ok(Some(ExitStatus {})) });
+        expect!(TEST_EXPECTATIONS::std_process_exit_status_success: { _ => true });
+
+        assert_eq!(
+            "1: lo: <LOOPBACK,UP,LOWER_UP>\n",
+            Process::new("ip")
+                .set_args(&["link"])
+                .should_clear_env(true)
+                .set_environment_variables(vec![("LC_ALL", "C")])
+                .run(Duration::from_secs(1))
+                .unwrap()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn oneshot_pool_runs_jobs_on_bounded_number_of_workers() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let pool = OneshotPool::new(3, Duration::from_millis(0));
+
+        let (job_running, job_peak) = (running.clone(), peak.clone());
+        let results = pool.run((0..12).collect(), move |job: usize| {
+            let now = job_running.fetch_add(1, Ordering::SeqCst) + 1;
+            job_peak.fetch_max(now, Ordering::SeqCst);
+            ::std::thread::sleep(Duration::from_millis(5));
+            job_running.fetch_sub(1, Ordering::SeqCst);
+
+            job * 2
+        });
+
+        assert_eq!((0..12).map(|job| Ok(job * 2)).collect::<Vec<_>>(), results);
+        assert!(peak.load(Ordering::SeqCst) <= 3);
+    }
+
+    #[test]
+    fn oneshot_pool_spaces_job_starts_by_interval() {
+        let clock = FakeClock::new();
+        let pool = OneshotPool::new(4, Duration::from_millis(20)).with_clock(clock.clone());
+
+        let results = pool.run(vec![1, 2, 3, 4], |job: usize| job);
+
+        assert_eq!(vec![Ok(1), Ok(2), Ok(3), Ok(4)], results);
+        assert_eq!(vec![Duration::from_millis(20); 3], clock.get_sleeps());
+    }
+
+    #[test]
+    fn oneshot_pool_reports_crashed_jobs_and_runs_the_remaining_ones() {
+        let pool = OneshotPool::new(1, Duration::from_millis(0));
+
+        let results = pool.run(vec![1, 2], |job: usize| {
+            if job == 1 {
+                panic!("chrt crashed");
+            }
+
+            job
+        });
+
+        assert_eq!(vec![Err("chrt crashed".to_string()), Ok(2)], results);
+    }
+
     #[test]
     fn process_new_returns_process_instance() {
         expect!(TEST_EXPECTATIONS::std_process_command_new: { "test" => _ });
@@ -565,7 +1191,8 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_process_command_stdout: { Stdio::piped() => _ });
         expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
             stdin: Some(ChildStdin {}),
-            stdout: Some(ChildStdout {}),
+            stdout: Some(ChildStdout::new("")),
+            stderr: None,
         }) });
 
         let subject = Process::new("test");
@@ -583,7 +1210,8 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_process_command_stdout: { Stdio::piped() => _ });
         expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
             stdin: Some(ChildStdin {}),
-            stdout: Some(ChildStdout {}),
+            stdout: Some(ChildStdout::new("")),
+            stderr: None,
         }) });
 
         let subject = Process::new("test").set_args(&["-c", "test.yml"]);
@@ -601,7 +1229,8 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_process_command_stdout: { Stdio::piped() => _ });
         expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
             stdin: Some(ChildStdin {}),
-            stdout: Some(ChildStdout {}),
+            stdout: Some(ChildStdout::new("")),
+            stderr: None,
         }) });
 
         let subject = Process::new("test")
@@ -622,7 +1251,8 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_process_command_stdout: { Stdio::piped() => _ });
         expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
             stdin: Some(ChildStdin {}),
-            stdout: Some(ChildStdout {}),
+            stdout: Some(ChildStdout::new("")),
+            stderr: None,
         }) });
 
         let subject = Process::new("test").should_clear_env(true);
@@ -632,6 +1262,30 @@ mod test {
         verify_expectations();
     }
 
+    #[test]
+    fn process_should_capture_stderr_pipes_child_process_stderr() {
+        expect!(TEST_EXPECTATIONS::std_process_command_new: { "test" => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_args: { vec![] => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_stdin: { Stdio::piped() => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_stdout: { Stdio::piped() => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_stderr: { Stdio::piped() => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
+            stdin: Some(ChildStdin {}),
+            stdout: Some(ChildStdout::new("")),
+            stderr: Some(ChildStderr::new("")),
+        }) });
+
+        let mut child = Process::new("test")
+            .should_capture_stderr(true)
+            .spawn()
+            .unwrap();
+
+        assert!(child.take_stderr().is_some());
+        assert!(child.take_stderr().is_none());
+
+        verify_expectations();
+    }
+
     #[test]
     fn process_should_clear_env_respects_last_configured_value() {
         expect!(TEST_EXPECTATIONS::std_process_command_new: { "test" => _ });
@@ -640,7 +1294,8 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_process_command_stdout: { Stdio::piped() => _ });
         expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
             stdin: Some(ChildStdin {}),
-            stdout: Some(ChildStdout {}),
+            stdout: Some(ChildStdout::new("")),
+            stderr: None,
         }) });
 
         let subject = Process::new("test")
@@ -652,6 +1307,46 @@ mod test {
         verify_expectations();
     }
 
+    #[test]
+    fn process_should_disable_thp_installs_pre_exec_hook() {
+        expect!(TEST_EXPECTATIONS::std_process_command_new: { "test" => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_args: { vec![] => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_stdin: { Stdio::piped() => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_stdout: { Stdio::piped() => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_pre_exec: { _ => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
+            stdin: Some(ChildStdin {}),
+            stdout: Some(ChildStdout::new("")),
+            stderr: None,
+        }) });
+
+        let subject = Process::new("test").should_disable_thp(true);
+
+        assert!(subject.spawn().is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn process_should_clear_ambient_capabilities_installs_pre_exec_hook() {
+        expect!(TEST_EXPECTATIONS::std_process_command_new: { "test" => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_args: { vec![] => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_stdin: { Stdio::piped() => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_stdout: { Stdio::piped() => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_pre_exec: { _ => _ });
+        expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
+            stdin: Some(ChildStdin {}),
+            stdout: Some(ChildStdout::new("")),
+            stderr: None,
+        }) });
+
+        let subject = Process::new("test").should_clear_ambient_capabilities(true);
+
+        assert!(subject.spawn().is_ok());
+
+        verify_expectations();
+    }
+
     #[test]
     fn process_set_effective_user_id_sets_effective_user_id_for_child_process_if_some_is_given() {
         expect!(TEST_EXPECTATIONS::std_process_command_new: { "test-user" => _ });
@@ -661,7 +1356,8 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_process_command_uid: { 123 => _ });
         expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
             stdin: Some(ChildStdin {}),
-            stdout: Some(ChildStdout {}),
+            stdout: Some(ChildStdout::new("")),
+            stderr: None,
         }) });
 
         let subject = Process::new("test-user").set_effective_user_id(&Some(123));
@@ -679,7 +1375,8 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_process_command_stdout: { Stdio::piped() => _ });
         expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
             stdin: Some(ChildStdin {}),
-            stdout: Some(ChildStdout {}),
+            stdout: Some(ChildStdout::new("")),
+            stderr: None,
         }) });
 
         let subject = Process::new("test-user").set_effective_user_id(&None);
@@ -698,7 +1395,8 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_process_command_gid: { 321 => _ });
         expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
             stdin: Some(ChildStdin {}),
-            stdout: Some(ChildStdout {}),
+            stdout: Some(ChildStdout::new("")),
+            stderr: None,
         }) });
 
         let subject = Process::new("test-group").set_effective_group_id(&Some(321));
@@ -716,7 +1414,8 @@ mod test {
         expect!(TEST_EXPECTATIONS::std_process_command_stdout: { Stdio::piped() => _ });
         expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
             stdin: Some(ChildStdin {}),
-            stdout: Some(ChildStdout {}),
+            stdout: Some(ChildStdout::new("")),
+            stderr: None,
         }) });
 
         let subject = Process::new("test-group").set_effective_group_id(&None);
@@ -738,7 +1437,8 @@ mod test {
         );
         expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
             stdin: Some(ChildStdin {}),
-            stdout: Some(ChildStdout {}),
+            stdout: Some(ChildStdout::new("")),
+            stderr: None,
         }) });
 
         let subject = Process::new("test-group")
@@ -760,7 +1460,8 @@ mod test {
         );
         expect!(TEST_EXPECTATIONS::std_process_command_spawn: { _ => Ok(Child {
             stdin: Some(ChildStdin {}),
-            stdout: Some(ChildStdout {}),
+            stdout: Some(ChildStdout::new("")),
+            stderr: None,
         }) });
 
         let subject = Process::new("test-group")
@@ -802,7 +1503,8 @@ mod test {
         let subject = ChildProcess {
             child: Child {
                 stdin: Some(ChildStdin {}),
-                stdout: Some(ChildStdout {}),
+                stdout: Some(ChildStdout::new("")),
+                stderr: None,
             },
         };
 
@@ -811,6 +1513,66 @@ mod test {
         verify_expectations();
     }
 
+    #[test]
+    fn child_process_has_exited_checks_child_state_without_blocking() {
+        expect!(
+            TEST_EXPECTATIONS::std_process_child_try_wait:
+            { _ => Ok(None) },
+            { _ => Ok(Some(ExitStatus {})) },
+        );
+
+        let mut subject = ChildProcess {
+            child: Child {
+                stdin: Some(ChildStdin {}),
+                stdout: Some(ChildStdout::new("")),
+                stderr: None,
+            },
+        };
+
+        assert_eq!(false, subject.has_exited().unwrap());
+        assert_eq!(true, subject.has_exited().unwrap());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn child_process_id_returns_child_process_id() {
+        expect!(TEST_EXPECTATIONS::std_process_child_id: { _ => 4321 });
+
+        let subject = ChildProcess {
+            child: Child {
+                stdin: Some(ChildStdin {}),
+                stdout: Some(ChildStdout::new("")),
+                stderr: None,
+            },
+        };
+
+        assert_eq!(4321, subject.id());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn child_process_kill_returns_error_if_child_kill_fails() {
+        expect!(TEST_EXPECTATIONS::std_process_child_kill: { _ => error!("test error") });
+
+        let mut subject = ChildProcess {
+            child: Child {
+                stdin: Some(ChildStdin {}),
+                stdout: Some(ChildStdout::new("")),
+                stderr: None,
+            },
+        };
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to kill the child process: test error",
+            subject.kill()
+        );
+
+        verify_expectations();
+    }
+
     #[test]
     fn child_process_wait_returns_error_if_child_wait_fails() {
         expect!(TEST_EXPECTATIONS::std_process_child_wait: { _ => error!("test error") });
@@ -818,7 +1580,8 @@ mod test {
         let subject = ChildProcess {
             child: Child {
                 stdin: Some(ChildStdin {}),
-                stdout: Some(ChildStdout {}),
+                stdout: Some(ChildStdout::new("")),
+                stderr: None,
             },
         };
 
@@ -840,7 +1603,8 @@ mod test {
         let subject = ChildProcess {
             child: Child {
                 stdin: Some(ChildStdin {}),
-                stdout: Some(ChildStdout {}),
+                stdout: Some(ChildStdout::new("")),
+                stderr: None,
             },
         };
 
@@ -862,7 +1626,8 @@ mod test {
         let subject = ChildProcess {
             child: Child {
                 stdin: Some(ChildStdin {}),
-                stdout: Some(ChildStdout {}),
+                stdout: Some(ChildStdout::new("")),
+                stderr: None,
             },
         };
 
@@ -0,0 +1,288 @@
+use crate::config::{ManagedPath, PathKind};
+#[cfg(not(test))]
+use nix::{
+    fcntl::open,
+    unistd::{chown, close},
+};
+use nix::{
+    fcntl::OFlag,
+    sys::stat::Mode,
+    unistd::{Gid, Uid},
+};
+#[cfg(not(test))]
+use std::fs;
+use std::{
+    fs::Permissions,
+    io::{Error, ErrorKind},
+    os::unix::fs::PermissionsExt,
+};
+#[cfg(test)]
+use test::{
+    nix::{
+        fcntl::open,
+        unistd::{chown, close},
+    },
+    std::fs,
+};
+
+// Creates the declared path if it is missing, then applies its mode and ownership the way
+// systemd-tmpfiles does for `d` and `f` entries: existing content is never touched
+pub fn provision(declared: &ManagedPath) -> Result<(), Error> {
+    let path = declared.get_path();
+
+    match declared.get_kind() {
+        PathKind::Directory => {
+            if let Err(e) = fs::create_dir_all(path) {
+                return Err(Error::new(
+                    e.kind(),
+                    format!("Failed to create the `{}` directory: {}", path, e),
+                ));
+            }
+        }
+        PathKind::File => {
+            // Without `O_TRUNC` an existing file is opened as is, while a directory fails with `EISDIR`
+            let flags = OFlag::O_CREAT | OFlag::O_WRONLY | OFlag::O_CLOEXEC;
+            let fd = match open(path, flags, Mode::from_bits_truncate(0o644)) {
+                Ok(fd) => fd,
+                Err(e) => {
+                    return Err(Error::new(
+                        ErrorKind::Other,
+                        format!("Failed to create the `{}` file: {}", path, e),
+                    ))
+                }
+            };
+
+            if let Err(e) = close(fd) {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("Failed to close the `{}` file: {}", path, e),
+                ));
+            }
+        }
+    }
+
+    if let Some(mode) = declared.get_mode() {
+        if let Err(e) = fs::set_permissions(path, Permissions::from_mode(mode)) {
+            return Err(Error::new(
+                e.kind(),
+                format!("Failed to change the mode of `{}`: {}", path, e),
+            ));
+        }
+    }
+
+    let user = declared.get_user().map(|id| Uid::from_raw(id as u32));
+    let group = declared.get_group().map(|id| Gid::from_raw(id as u32));
+
+    if user.is_some() || group.is_some() {
+        if let Err(e) = chown(path, user, group) {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("Failed to change the owner of `{}`: {}", path, e),
+            ));
+        }
+    }
+
+    Ok({})
+}
+
+#[cfg(test)]
+mod test {
+    use super::provision;
+    use crate::{assert_error, config::Config, expect, vec_deq, verify_expectations};
+    use ::std::{
+        cell::RefCell,
+        collections::VecDeque,
+        io::{Error, ErrorKind},
+    };
+
+    struct TestExpectations {
+        nix_fcntl_open: VecDeque<((&'static str, i32, u32), nix::Result<i32>)>,
+        nix_unistd_chown: VecDeque<((&'static str, Option<u32>, Option<u32>), nix::Result<()>)>,
+        nix_unistd_close: VecDeque<(i32, nix::Result<()>)>,
+        std_fs_create_dir_all: VecDeque<(&'static str, Result<(), Error>)>,
+        std_fs_set_permissions: VecDeque<((&'static str, u32), Result<(), Error>)>,
+    }
+
+    impl TestExpectations {
+        fn new() -> Self {
+            TestExpectations {
+                nix_fcntl_open: vec_deq![],
+                nix_unistd_chown: vec_deq![],
+                nix_unistd_close: vec_deq![],
+                std_fs_create_dir_all: vec_deq![],
+                std_fs_set_permissions: vec_deq![],
+            }
+        }
+    }
+
+    thread_local! { static TEST_EXPECTATIONS: RefCell<TestExpectations> = RefCell::new(TestExpectations::new()) }
+
+    fn verify_expectations() {
+        verify_expectations!(
+            nix::fcntl::open => TEST_EXPECTATIONS::nix_fcntl_open,
+            nix::unistd::chown => TEST_EXPECTATIONS::nix_unistd_chown,
+            nix::unistd::close => TEST_EXPECTATIONS::nix_unistd_close,
+            std::fs::create_dir_all => TEST_EXPECTATIONS::std_fs_create_dir_all,
+            std::fs::set_permissions => TEST_EXPECTATIONS::std_fs_set_permissions,
+        );
+    }
+
+    pub mod nix {
+        pub use ::nix::Result;
+
+        pub mod fcntl {
+            use super::super::TEST_EXPECTATIONS;
+            use crate::verify_expectation;
+            use ::nix::{fcntl::OFlag, sys::stat::Mode, Result};
+            use ::std::os::unix::io::RawFd;
+
+            pub fn open(path: &str, flags: OFlag, mode: Mode) -> Result<RawFd> {
+                let flags = flags.bits();
+                let mode = mode.bits();
+
+                verify_expectation!(TEST_EXPECTATIONS::nix_fcntl_open => nix::fcntl::open { path, flags, mode })
+            }
+        }
+
+        pub mod unistd {
+            use super::super::TEST_EXPECTATIONS;
+            use crate::verify_expectation;
+            use ::nix::{
+                unistd::{Gid, Uid},
+                Result,
+            };
+            use ::std::os::unix::io::RawFd;
+
+            pub fn chown(path: &str, owner: Option<Uid>, group: Option<Gid>) -> Result<()> {
+                let owner = owner.map(|uid| uid.as_raw());
+                let group = group.map(|gid| gid.as_raw());
+
+                verify_expectation!(TEST_EXPECTATIONS::nix_unistd_chown => nix::unistd::chown { path, owner, group })
+            }
+
+            pub fn close(fd: RawFd) -> Result<()> {
+                verify_expectation!(TEST_EXPECTATIONS::nix_unistd_close => nix::unistd::close { fd })
+            }
+        }
+    }
+
+    pub mod std {
+        pub mod fs {
+            use super::super::TEST_EXPECTATIONS;
+            use crate::verify_expectation;
+            use ::std::{fs::Permissions, io::Result, os::unix::fs::PermissionsExt, path::Path};
+
+            pub fn create_dir_all<P: AsRef<Path>>(path: P) -> Result<()> {
+                let path = path.as_ref().to_str().unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_create_dir_all => std::fs::create_dir_all { path })
+            }
+
+            pub fn set_permissions<P: AsRef<Path>>(
+                path: P,
+                permissions: Permissions,
+            ) -> Result<()> {
+                let path = path.as_ref().to_str().unwrap();
+                let mode = permissions.mode();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_set_permissions => std::fs::set_permissions { path, mode })
+            }
+        }
+    }
+
+    fn config(paths: &str) -> Config {
+        Config::new(format!(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              paths:
+{}
+
+            qemu:
+            - enable-kvm
+            ",
+            paths
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn provision_creates_missing_paths_and_applies_mode_and_ownership() {
+        let config = config(
+            "
+              - path: /var/lib/vm-foo/images
+                user: 107
+                group: 36
+                mode: \"0750\"
+              - path: /var/lib/vm-foo/OVMF_VARS.fd
+                type: file
+                mode: \"0600\"
+              - path: /var/lib/vm-foo/logs
+            ",
+        );
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_create_dir_all:
+            { "/var/lib/vm-foo/images" => Ok({}) },
+            { "/var/lib/vm-foo/logs" => Ok({}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::nix_fcntl_open:
+            { "/var/lib/vm-foo/OVMF_VARS.fd", 0o2000101, 0o644 => Ok(7) },
+        );
+        expect!(TEST_EXPECTATIONS::nix_unistd_close: { 7 => Ok({}) });
+        expect!(
+            TEST_EXPECTATIONS::std_fs_set_permissions:
+            { "/var/lib/vm-foo/images", 0o750 => Ok({}) },
+            { "/var/lib/vm-foo/OVMF_VARS.fd", 0o600 => Ok({}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::nix_unistd_chown:
+            { "/var/lib/vm-foo/images", Some(107), Some(36) => Ok({}) },
+        );
+
+        for path in config.get_paths() {
+            assert!(provision(path).is_ok());
+        }
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn provision_returns_error_if_path_cannot_be_created() {
+        let directory = config(
+            "
+              - path: /var/lib/vm-foo/images
+                mode: \"0750\"
+            ",
+        );
+        let file = config(
+            "
+              - path: /var/lib/vm-foo/images
+                type: file
+            ",
+        );
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_create_dir_all:
+            { "/var/lib/vm-foo/images" => Err(Error::new(ErrorKind::AlreadyExists, "File exists")) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::nix_fcntl_open:
+            { "/var/lib/vm-foo/images", 0o2000101, 0o644 => Err(::nix::Error::Sys(::nix::errno::Errno::EISDIR)) },
+        );
+
+        assert_error!(
+            ErrorKind::AlreadyExists,
+            "Failed to create the `/var/lib/vm-foo/images` directory: File exists",
+            provision(&directory.get_paths()[0])
+        );
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to create the `/var/lib/vm-foo/images` file: EISDIR: Is a directory",
+            provision(&file.get_paths()[0])
+        );
+
+        verify_expectations();
+    }
+}
@@ -0,0 +1,131 @@
+use crate::output;
+use std::{
+    io::{self, BufRead, BufReader, Error, Read, Write},
+    thread::{self, JoinHandle},
+};
+
+const LOG_ERROR: u8 = 3;
+const LOG_WARNING: u8 = 4;
+const LOG_INFO: u8 = 6;
+
+pub fn drain<R: Read + Send + 'static>(stderr: R) -> JoinHandle<()> {
+    thread::spawn(move || {
+        if let Err(e) = forward(BufReader::new(stderr), &mut io::stderr()) {
+            output::error(format!("Failed to forward qemu messages: {}", e));
+        }
+    })
+}
+
+pub fn forward<R: BufRead, W: Write>(mut input: R, output: &mut W) -> Result<(), Error> {
+    let mut buffer = vec![];
+    let mut failure = None;
+
+    loop {
+        buffer.clear();
+        if input.read_until(b'\n', &mut buffer)? == 0 {
+            break;
+        }
+
+        // Keep draining the pipe once the output fails, otherwise qemu blocks writing into a full pipe
+        if failure.is_some() {
+            continue;
+        }
+
+        let line = String::from_utf8_lossy(&buffer);
+        let line = line.trim_end_matches(&['\r', '\n'][..]);
+        if line.trim().len() < 1 {
+            continue;
+        }
+
+        if let Err(e) = writeln!(output, "<{}>qemu: {}", infer_level(line), line) {
+            failure = Some(e);
+        }
+    }
+
+    match failure {
+        Some(e) => Err(e),
+        None => Ok({}),
+    }
+}
+
+fn infer_level(line: &str) -> u8 {
+    let line = line.to_lowercase();
+
+    if line.contains("warning:") {
+        return LOG_WARNING;
+    }
+
+    if line.contains("info:") {
+        return LOG_INFO;
+    }
+
+    // Qemu reports errors without a prefix, so fall back on the common wording
+    for marker in &["error", "fail", "could not", "cannot", "unable", "invalid"] {
+        if line.contains(marker) {
+            return LOG_ERROR;
+        }
+    }
+
+    LOG_INFO
+}
+
+#[cfg(test)]
+mod test {
+    use super::forward;
+    use std::io::{BufRead, Cursor, Error, ErrorKind, Write};
+
+    struct FailingWriter {}
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _: &[u8]) -> Result<usize, Error> {
+            Err(Error::new(ErrorKind::BrokenPipe, "test error"))
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok({})
+        }
+    }
+
+    #[test]
+    fn forward_prefixes_qemu_messages_with_inferred_level() {
+        let input = Cursor::new(
+            "2021-03-01T10:00:00.000000Z qemu-system-x86_64: warning: host doesn't support requested feature\n\
+            \n\
+            2021-03-01T10:00:00.100000Z qemu-system-x86_64: -drive file=vm.qcow2: Could not open 'vm.qcow2'\n\
+            2021-03-01T10:00:00.200000Z qemu-system-x86_64: terminating on signal 15 from pid 1\n",
+        );
+        let mut output = vec![];
+
+        forward(input, &mut output).unwrap();
+
+        assert_eq!(
+            "<4>qemu: 2021-03-01T10:00:00.000000Z qemu-system-x86_64: warning: host doesn't support requested feature\n\
+            <3>qemu: 2021-03-01T10:00:00.100000Z qemu-system-x86_64: -drive file=vm.qcow2: Could not open 'vm.qcow2'\n\
+            <6>qemu: 2021-03-01T10:00:00.200000Z qemu-system-x86_64: terminating on signal 15 from pid 1\n",
+            String::from_utf8(output).unwrap()
+        );
+    }
+
+    #[test]
+    fn forward_replaces_invalid_utf8_instead_of_stopping() {
+        let input = Cursor::new(&b"bad \xff byte\r\nwarning: next line\n"[..]);
+        let mut output = vec![];
+
+        forward(input, &mut output).unwrap();
+
+        assert_eq!(
+            "<6>qemu: bad \u{fffd} byte\n<4>qemu: warning: next line\n",
+            String::from_utf8(output).unwrap()
+        );
+    }
+
+    #[test]
+    fn forward_keeps_draining_input_after_output_fails() {
+        let mut input = Cursor::new("first line\nsecond line\nthird line\n");
+
+        let result = forward(&mut input, &mut FailingWriter {});
+
+        assert_eq!(ErrorKind::BrokenPipe, result.unwrap_err().kind());
+        assert!(input.fill_buf().unwrap().is_empty());
+    }
+}
@@ -1,24 +1,149 @@
+use crate::clock::{self, Clock};
 use json::{object, JsonValue};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
+    fmt,
     io::{BufRead, BufReader, Error, ErrorKind, Read, Write},
+    os::unix::net::UnixStream,
+    sync::Arc,
+    time::Duration,
 };
 
 pub trait QmpPipe: Read + Write {}
 
-struct QmpClient<'a> {
-    io: Box<dyn QmpPipe + 'a>,
+impl QmpPipe for UnixStream {}
+
+impl<P: QmpPipe + ?Sized> QmpPipe for &mut P {}
+
+// Responses listing every block node of a large machine easily span megabytes, the limit only
+// guards against a runaway peer
+const MAX_MESSAGE_SIZE: u64 = 64 * 1024 * 1024;
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+const REDACTED_KEYS: [&str; 2] = ["password", "secret"];
+const RETRY_ATTEMPTS: u32 = 5;
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+
+// The `error.class` of QMP error responses, classes unknown to the launcher are treated as generic
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QmpErrorClass {
+    CommandNotFound,
+    DeviceNotActive,
+    DeviceNotFound,
+    GenericError,
+    KvmMissingCap,
+}
+
+impl QmpErrorClass {
+    fn parse(class: &str) -> Self {
+        match class {
+            "CommandNotFound" => QmpErrorClass::CommandNotFound,
+            "DeviceNotActive" => QmpErrorClass::DeviceNotActive,
+            "DeviceNotFound" => QmpErrorClass::DeviceNotFound,
+            "KVMMissingCap" => QmpErrorClass::KvmMissingCap,
+            _ => QmpErrorClass::GenericError,
+        }
+    }
+}
+
+// Carried by the `std::io::Error` of a failed command, see `as_qmp_error()`
+#[derive(Debug)]
+pub struct QmpError {
+    class: QmpErrorClass,
+    description: String,
+    message: String,
+}
+
+impl QmpError {
+    fn new(class: QmpErrorClass, description: &str) -> Self {
+        QmpError {
+            class,
+            description: description.to_owned(),
+            message: format!("Received error QMP response: `{}`.", description),
+        }
+    }
+
+    pub fn get_class(&self) -> QmpErrorClass {
+        self.class
+    }
+
+    // A device which is not active yet, e.g. before the guest driver is loaded, or a busy one, e.g.
+    // taken by a block job, is likely to accept the same command a bit later
+    pub fn is_retryable(&self) -> bool {
+        match self.class {
+            QmpErrorClass::DeviceNotActive => true,
+            QmpErrorClass::GenericError => self.description.to_lowercase().contains("busy"),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for QmpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for QmpError {}
+
+pub fn as_qmp_error(e: &Error) -> Option<&QmpError> {
+    e.get_ref()?.downcast_ref::<QmpError>()
+}
+
+// Prefixes the message, while keeping the QMP error class of the original error
+fn with_context(e: Error, context: String) -> Error {
+    match as_qmp_error(&e) {
+        Some(qmp) => Error::new(
+            e.kind(),
+            QmpError {
+                class: qmp.class,
+                description: qmp.description.clone(),
+                message: format!("{}: {}", context, qmp.message),
+            },
+        ),
+        None => Error::new(e.kind(), format!("{}: {}", context, e)),
+    }
+}
+
+pub struct QmpClient<'a> {
+    clock: Arc<dyn Clock>,
+    events: VecDeque<JsonValue>,
+    // Kept for the lifetime of the client, a single read may carry more than one message
+    io: BufReader<Box<dyn QmpPipe + 'a>>,
+    log: Option<Box<dyn Write + 'a>>,
     negotiated: bool,
 }
 
 impl<'a> QmpClient<'a> {
     pub fn new(io: impl QmpPipe + 'a) -> QmpClient<'a> {
         QmpClient {
-            io: Box::new(io),
+            clock: clock::system(),
+            events: VecDeque::new(),
+            io: BufReader::with_capacity(READ_BUFFER_SIZE, Box::new(io)),
+            log: None,
             negotiated: false,
         }
     }
 
+    pub fn with_log(mut self, log: Option<Box<dyn Write + 'a>>) -> Self {
+        self.log = log;
+
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+
+        self
+    }
+
+    fn log_message(&mut self, direction: &str, message: &JsonValue) {
+        if let Some(log) = &mut self.log {
+            // Logging is a debugging aid, failing to write it must not break the QMP exchange
+            let _ = writeln!(log, "QMP {} {}", direction, redact(message).pretty(2));
+            let _ = log.flush();
+        }
+    }
+
     fn negotiate_capabilities(&mut self) -> Result<(), Error> {
         if self.negotiated {
             return Ok({});
@@ -44,8 +169,26 @@ impl<'a> QmpClient<'a> {
     }
 
     fn read_message(&mut self) -> Result<JsonValue, Error> {
-        let mut qmp_response = String::new();
-        match BufReader::new(&mut (self.io)).read_line(&mut qmp_response) {
+        let mut message = vec![];
+        match (&mut self.io)
+            .take(MAX_MESSAGE_SIZE)
+            .read_until(b'\n', &mut message)
+        {
+            Ok(0) => {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "The QMP connection was closed.",
+                ))
+            }
+            Ok(size) if size as u64 == MAX_MESSAGE_SIZE && !message.ends_with(b"\n") => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "The QMP message exceeds the maximum size of {} bytes.",
+                        MAX_MESSAGE_SIZE
+                    ),
+                ))
+            }
             Ok(_) => {}
             Err(e) => {
                 return Err(Error::new(
@@ -55,8 +198,12 @@ impl<'a> QmpClient<'a> {
             }
         }
 
+        let qmp_response = String::from_utf8_lossy(&message);
         match json::parse(&qmp_response) {
-            Ok(r) => Ok(r),
+            Ok(r) => {
+                self.log_message("<-", &r);
+                Ok(r)
+            }
             Err(e) => Err(Error::new(
                 ErrorKind::Other,
                 format!(
@@ -68,16 +215,17 @@ impl<'a> QmpClient<'a> {
     }
 
     fn send_command(&mut self, json: JsonValue) -> Result<JsonValue, Error> {
-        self.io.write_all(json.dump().as_bytes())?;
-        self.io.flush()?;
-        let mut response = self.read_message()?;
+        self.log_message("->", &json);
+        self.io.get_mut().write_all(json.dump().as_bytes())?;
+        self.io.get_mut().flush()?;
+        let mut response = self.read_response()?;
 
         if !response["error"].is_null() {
             return Err(Error::new(
                 ErrorKind::Other,
-                format!(
-                    "Received error QMP response: `{}`.",
-                    response["error"]["desc"]
+                QmpError::new(
+                    QmpErrorClass::parse(response["error"]["class"].as_str().unwrap_or_default()),
+                    &response["error"]["desc"].to_string(),
                 ),
             ));
         }
@@ -92,6 +240,65 @@ impl<'a> QmpClient<'a> {
         Ok(response["return"].take())
     }
 
+    // Events may arrive before the response to a command, they are kept for `read_event()`
+    fn read_response(&mut self) -> Result<JsonValue, Error> {
+        loop {
+            let message = self.read_message()?;
+            if message["event"].is_null() {
+                return Ok(message);
+            }
+
+            self.events.push_back(message);
+        }
+    }
+
+    fn read_event(&mut self) -> Result<JsonValue, Error> {
+        if let Some(event) = self.events.pop_front() {
+            return Ok(event);
+        }
+
+        loop {
+            let message = self.read_message()?;
+            if !message["event"].is_null() {
+                return Ok(message);
+            }
+        }
+    }
+
+    pub fn execute(&mut self, command: JsonValue) -> Result<JsonValue, Error> {
+        self.negotiate_capabilities()?;
+        self.send_command(command)
+    }
+
+    pub fn execute_retrying(&mut self, command: JsonValue) -> Result<JsonValue, Error> {
+        let mut attempt = 1;
+
+        loop {
+            match self.execute(command.clone()) {
+                Err(e)
+                    if attempt < RETRY_ATTEMPTS
+                        && as_qmp_error(&e).map_or(false, QmpError::is_retryable) =>
+                {
+                    attempt += 1;
+                    self.clock.sleep(RETRY_DELAY);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // Drops the events received so far, for long-lived connections which are not interested in them
+    pub fn discard_events(&mut self) {
+        self.events.clear();
+    }
+
+    pub fn quit(&mut self) -> Result<(), Error> {
+        self.negotiate_capabilities()?;
+        self.send_command(object! {"execute": "quit"})?;
+
+        Ok({})
+    }
+
     pub fn query_cpus_fast(&mut self) -> Result<std::vec::Vec<JsonValue>, Error> {
         self.negotiate_capabilities()?;
 
@@ -111,119 +318,310 @@ impl<'a> QmpClient<'a> {
     }
 }
 
+pub fn redact(message: &JsonValue) -> JsonValue {
+    match message {
+        JsonValue::Object(object) => {
+            let mut redacted = JsonValue::new_object();
+
+            for (key, value) in object.iter() {
+                let name = key.to_lowercase();
+
+                if REDACTED_KEYS.iter().any(|secret| name.contains(secret)) {
+                    redacted[key] = "<redacted>".into();
+                } else {
+                    redacted[key] = redact(value);
+                }
+            }
+
+            // The key material of a `secret` object is passed inline in `data`, unless read from a
+            // `file`, either among the arguments or under `props` for older qemu versions
+            if message["qom-type"] == "secret" {
+                if message.has_key("data") {
+                    redacted["data"] = "<redacted>".into();
+                }
+                if message["props"].has_key("data") {
+                    redacted["props"]["data"] = "<redacted>".into();
+                }
+            }
+
+            redacted
+        }
+        JsonValue::Array(values) => JsonValue::Array(values.iter().map(redact).collect()),
+        _ => message.clone(),
+    }
+}
+
+// Task IDs of vCPUs keyed by their socket, die, cluster, core and thread IDs
 pub struct Topology {
-    topology: HashMap<usize, HashMap<usize, HashMap<usize, usize>>>,
+    indices: HashMap<usize, usize>,
+    topology: HashMap<(usize, usize, usize, usize, usize), usize>,
 }
 
 impl Topology {
     pub fn get_thread_id(
         &self,
         socket_id: usize,
+        die_id: usize,
+        cluster_id: usize,
         core_id: usize,
         thread_id: usize,
     ) -> Option<usize> {
-        match self.topology.get(&socket_id) {
-            Some(socket) => match socket.get(&core_id) {
-                Some(core) => core.get(&thread_id).cloned(),
-                None => None,
-            },
-            None => None,
-        }
+        self.topology
+            .get(&(socket_id, die_id, cluster_id, core_id, thread_id))
+            .cloned()
     }
 
-    pub fn get_task_ids(&self) -> Vec<usize> {
-        let mut task_ids = vec![];
-
-        for (_, cores) in &self.topology {
-            for (_, threads) in cores {
-                for (_, thread) in threads {
-                    task_ids.push(*thread);
-                }
-            }
-        }
+    pub fn get_thread_id_by_index(&self, index: usize) -> Option<usize> {
+        self.indices.get(&index).cloned()
+    }
 
-        task_ids
+    pub fn get_task_ids(&self) -> Vec<usize> {
+        self.topology.values().cloned().collect()
     }
 }
 
-fn transform_vcpu_info(json_response: &Vec<JsonValue>) -> Result<Topology, Error> {
-    let mut topology = HashMap::new();
+// A vCPU as reported by `query-cpus-fast`. Only the topology levels the target and the machine know
+// about are reported, e.g. s390x only reports cores, microvm might omit sockets, and aarch64 `virt`
+// machines without an explicit topology might report nothing but the NUMA `node-id`.
+struct VcpuInfo {
+    cpu_index: Option<usize>,
+    task_id: usize,
+    socket_id: Option<usize>,
+    die_id: Option<usize>,
+    cluster_id: Option<usize>,
+    core_id: Option<usize>,
+    thread_id: Option<usize>,
+}
 
-    for (id, cpu) in json_response.iter().enumerate() {
-        let task_id = cpu["thread-id"].as_usize().ok_or_else(|| {
+impl VcpuInfo {
+    fn parse(position: usize, cpu: &JsonValue) -> Result<Self, Error> {
+        let invalid = |name: &str, value: &JsonValue| {
             Error::new(
                 ErrorKind::Other,
                 format!(
-                    "`return.{}.thread-id` is invalid, a \
+                    "`return.{}.{}` is invalid, a \
                     positive number is expected, but got: `{}`.",
-                    id, cpu["thread-id"]
+                    position, name, value
                 ),
             )
-        })?;
-        match &cpu["props"] {
-            JsonValue::Object(props) => {
-                let core_id = props["core-id"].as_usize().ok_or_else(|| {
-                    Error::new(
-                        ErrorKind::Other,
-                        format!(
-                            "`return.{}.props.core-id` is invalid, \
-                            a positive number is expected, but got: `{}`.",
-                            id, props["core-id"]
-                        ),
-                    )
-                })?;
-                let thread_id = props["thread-id"].as_usize().ok_or_else(|| {
-                    Error::new(
-                        ErrorKind::Other,
-                        format!(
-                            "`return.{}.props.thread-id` is invalid, a \
-                            positive number is expected, but got: `{}`.",
-                            id, props["thread-id"]
-                        ),
-                    )
-                })?;
-                let socket_id = props["socket-id"].as_usize().ok_or_else(|| {
-                    Error::new(
-                        ErrorKind::Other,
-                        format!(
-                            "`return.{}.props.socket-id` is invalid, a \
-                            positive number is expected, but got: `{}`.",
-                            id, props["socket-id"]
-                        ),
-                    )
-                })?;
-
-                topology
-                    .entry(socket_id)
-                    .or_insert(HashMap::new())
-                    .entry(core_id)
-                    .or_insert(HashMap::new())
-                    .insert(thread_id, task_id);
-            }
-            _ => {
+        };
+
+        let task_id = cpu["thread-id"]
+            .as_usize()
+            .ok_or_else(|| invalid("thread-id", &cpu["thread-id"]))?;
+
+        let props = match &cpu["props"] {
+            JsonValue::Object(_) | JsonValue::Null => &cpu["props"],
+            props => {
                 return Err(Error::new(
                     ErrorKind::Other,
                     format!(
                         "Invalid vCPU info, expected `props` to be an object, but got: `{}`.",
-                        cpu["props"]
+                        props
                     ),
                 ))
             }
+        };
+
+        let level = |name: &str| match &props[name] {
+            JsonValue::Null => Ok(None),
+            value => match value.as_usize() {
+                Some(id) => Ok(Some(id)),
+                None => Err(invalid(&format!("props.{}", name), value)),
+            },
+        };
+
+        // The NUMA node is not a part of the topology, but a malformed one points at a bad payload
+        level("node-id")?;
+
+        Ok(Self {
+            cpu_index: cpu["cpu-index"].as_usize(),
+            task_id,
+            socket_id: level("socket-id")?,
+            die_id: level("die-id")?,
+            cluster_id: level("cluster-id")?,
+            core_id: level("core-id")?,
+            thread_id: level("thread-id")?,
+        })
+    }
+
+    // Absent levels default to zero, except for the core: without it every vCPU is a core of its own
+    fn get_topology(&self, position: usize) -> (usize, usize, usize, usize, usize) {
+        (
+            self.socket_id.unwrap_or(0),
+            self.die_id.unwrap_or(0),
+            self.cluster_id.unwrap_or(0),
+            self.core_id.or(self.cpu_index).unwrap_or(position),
+            self.thread_id.unwrap_or(0),
+        )
+    }
+}
+
+fn transform_vcpu_info(json_response: &Vec<JsonValue>) -> Result<Topology, Error> {
+    let mut indices = HashMap::new();
+    let mut topology = HashMap::new();
+
+    for (position, cpu) in json_response.iter().enumerate() {
+        let vcpu = VcpuInfo::parse(position, cpu)?;
+
+        if let Some(index) = vcpu.cpu_index {
+            indices.insert(index, vcpu.task_id);
+        }
+
+        let key = vcpu.get_topology(position);
+        if topology.insert(key, vcpu.task_id).is_some() {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "`return.{}.props` is invalid, another vCPU is already placed at socket {}, \
+                    die {}, cluster {}, core {} and thread {}.",
+                    position, key.0, key.1, key.2, key.3, key.4
+                ),
+            ));
         }
     }
 
-    Ok(Topology { topology: topology })
+    Ok(Topology {
+        indices: indices,
+        topology: topology,
+    })
 }
 
-pub fn read_vcpu_info_from_qmp_socket(io: impl QmpPipe) -> Result<Topology, Error> {
-    transform_vcpu_info(&QmpClient::new(io).query_cpus_fast()?)
+pub fn run_startup_commands<'a>(
+    io: impl QmpPipe + 'a,
+    log: Option<Box<dyn Write + 'a>>,
+    clock: Arc<dyn Clock>,
+    commands: &[JsonValue],
+) -> Result<(Topology, Vec<Error>), Error> {
+    let mut client = QmpClient::new(io).with_log(log).with_clock(clock);
+    let mut failures = vec![];
+
+    // A rejected command is reported, but must not prevent the vCPU pinning that follows
+    for command in commands {
+        if let Err(e) = client.execute_retrying(command.clone()) {
+            failures.push(with_context(
+                e,
+                format!(
+                    "Failed to execute the `{}` QMP startup command",
+                    command["execute"]
+                ),
+            ));
+        }
+    }
+
+    Ok((transform_vcpu_info(&client.query_cpus_fast()?)?, failures))
+}
+
+// Serves the events of an already negotiated connection until it is closed, executing the commands
+// the handler answers every event with
+pub fn watch_events<'a, F>(
+    io: impl QmpPipe + 'a,
+    log: Option<Box<dyn Write + 'a>>,
+    clock: Arc<dyn Clock>,
+    mut handler: F,
+) -> Result<(), Error>
+where
+    F: FnMut(&str, &JsonValue) -> Vec<JsonValue>,
+{
+    let mut client = QmpClient::new(io).with_log(log).with_clock(clock);
+    client.negotiated = true;
+
+    loop {
+        let event = match client.read_event() {
+            Ok(event) => event,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok({}),
+            Err(e) => return Err(e),
+        };
+
+        for command in handler(event["event"].as_str().unwrap_or_default(), &event["data"]) {
+            if let Err(e) = client.execute_retrying(command.clone()) {
+                return Err(with_context(
+                    e,
+                    format!("Failed to execute the `{}` QMP command", command["execute"]),
+                ));
+            }
+        }
+    }
+}
+
+// Hot-adds devices described with the `-device` syntax over an already negotiated connection. The
+// human monitor parses the description exactly as the command line would, a device which can not be
+// added is reported without preventing the others.
+pub fn add_devices<'a>(
+    io: impl QmpPipe + 'a,
+    log: Option<Box<dyn Write + 'a>>,
+    devices: &[String],
+) -> Vec<Error> {
+    let mut client = QmpClient::new(io).with_log(log);
+    client.negotiated = true;
+
+    let mut failures = vec![];
+    for device in devices {
+        let command = object! {
+            "execute": "human-monitor-command",
+            "arguments": { "command-line": format!("device_add {}", device) },
+        };
+
+        // The human monitor reports a failure as its output rather than as an error response
+        match client.execute(command) {
+            Ok(output)
+                if output
+                    .as_str()
+                    .map_or(true, |output| output.trim().len() < 1) => {}
+            Ok(output) => failures.push(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "Failed to add the `{}` device: {}",
+                    device,
+                    output.as_str().unwrap_or_default().trim()
+                ),
+            )),
+            Err(e) => failures.push(with_context(
+                e,
+                format!("Failed to add the `{}` device", device),
+            )),
+        }
+    }
+
+    failures
+}
+
+pub fn run_self_test<'a>(
+    io: impl QmpPipe + 'a,
+    log: Option<Box<dyn Write + 'a>>,
+) -> Result<Topology, Error> {
+    let mut client = QmpClient::new(io).with_log(log);
+    let topology = transform_vcpu_info(&client.query_cpus_fast()?)?;
+    client.quit()?;
+
+    Ok(topology)
 }
 
 #[cfg(test)]
 mod test {
-    use super::{read_vcpu_info_from_qmp_socket, QmpPipe, Topology};
+    use super::{
+        add_devices, as_qmp_error, redact, run_self_test, run_startup_commands, watch_events,
+        QmpErrorClass, QmpPipe, Topology, RETRY_DELAY,
+    };
+    use crate::clock::fake::FakeClock;
     use json::{object, JsonValue};
-    use std::io::{Error, ErrorKind, Read, Write};
+    use std::{
+        cell::RefCell,
+        io::{Error, ErrorKind, Read, Write},
+        rc::Rc,
+    };
+
+    struct SharedLog(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedLog {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok({})
+        }
+    }
 
     struct MockQmpPipe {
         reads: Vec<Option<String>>,
@@ -368,8 +766,19 @@ mod test {
         )
     }
 
+    // Without startup commands only the vCPUs are queried
+    fn query_vcpus<'a>(
+        io: impl QmpPipe + 'a,
+        log: Option<Box<dyn Write + 'a>>,
+    ) -> Result<Topology, Error> {
+        let (topology, failures) = run_startup_commands(io, log, FakeClock::new(), &[])?;
+        assert_eq!(0, failures.len());
+
+        Ok(topology)
+    }
+
     #[test]
-    fn read_vcpu_info_returns_json_information() {
+    fn run_startup_commands_returns_json_information() {
         let io = create_successful_mock_qmp_pipe(object! { "return": [
                 {
                     "thread-id": 25627,
@@ -390,25 +799,192 @@ mod test {
             ]
         });
 
-        let topology = read_vcpu_info_from_qmp_socket(io).unwrap();
+        let topology = query_vcpus(io, None).unwrap();
 
         let mut task_ids = topology.get_task_ids();
         task_ids.sort();
 
         assert_eq!(vec![25627, 25628], task_ids);
 
-        assert_eq!(Some(25627), topology.get_thread_id(0, 0, 0));
-        assert_eq!(Some(25628), topology.get_thread_id(0, 0, 1));
+        assert_eq!(Some(25627), topology.get_thread_id(0, 0, 0, 0, 0));
+        assert_eq!(Some(25628), topology.get_thread_id(0, 0, 0, 0, 1));
+        assert_eq!(Some(25627), topology.get_thread_id_by_index(0));
+        assert_eq!(Some(25628), topology.get_thread_id_by_index(1));
+        assert_eq!(None, topology.get_thread_id_by_index(2));
+
+        assert_eq!(None, topology.get_thread_id(1, 0, 0, 0, 0));
+        assert_eq!(None, topology.get_thread_id(0, 0, 0, 1, 0));
+        assert_eq!(None, topology.get_thread_id(0, 0, 0, 0, 2));
+    }
+
+    #[test]
+    fn run_startup_commands_keys_vcpus_by_die_and_cluster() {
+        let io = create_successful_mock_qmp_pipe(object! { "return": [
+                {
+                    "thread-id": 4101,
+                    "props": { "core-id": 0, "thread-id": 0, "die-id": 0, "socket-id": 0 },
+                    "cpu-index": 0
+                },
+                {
+                    "thread-id": 4102,
+                    "props": {
+                        "core-id": 0, "thread-id": 0, "die-id": 1, "cluster-id": 1, "socket-id": 0
+                    },
+                    "cpu-index": 1
+                }
+            ]
+        });
+
+        let topology = query_vcpus(io, None).unwrap();
+
+        assert_eq!(Some(4101), topology.get_thread_id(0, 0, 0, 0, 0));
+        assert_eq!(Some(4102), topology.get_thread_id(0, 1, 1, 0, 0));
+        assert_eq!(None, topology.get_thread_id(0, 1, 0, 0, 0));
+    }
+
+    #[test]
+    fn run_startup_commands_treats_missing_topology_props_as_zero() {
+        let io = create_successful_mock_qmp_pipe(object! { "return": [
+                {
+                    "thread-id": 3301,
+                    "props": { "core-id": 0 },
+                    "qom-path": "/machine/unattached/device[0]",
+                    "target": "s390x",
+                    "cpu-index": 0,
+                    "cpu-state": "operating"
+                },
+                {
+                    "thread-id": 3302,
+                    "props": { "core-id": 1 },
+                    "qom-path": "/machine/unattached/device[1]",
+                    "target": "s390x",
+                    "cpu-index": 1,
+                    "cpu-state": "stopped"
+                }
+            ]
+        });
+
+        let topology = query_vcpus(io, None).unwrap();
 
-        assert_eq!(None, topology.get_thread_id(1, 0, 0));
-        assert_eq!(None, topology.get_thread_id(0, 1, 0));
-        assert_eq!(None, topology.get_thread_id(0, 0, 2));
+        assert_eq!(Some(3301), topology.get_thread_id(0, 0, 0, 0, 0));
+        assert_eq!(Some(3302), topology.get_thread_id(0, 0, 0, 1, 0));
+        assert_eq!(Some(3302), topology.get_thread_id_by_index(1));
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_negotiation_read_fails() {
+    fn run_startup_commands_accepts_aarch64_virt_payloads() {
+        let io = create_successful_mock_qmp_pipe(object! { "return": [
+                {
+                    "thread-id": 5201,
+                    "props": {
+                        "core-id": 0, "thread-id": 0, "cluster-id": 0, "socket-id": 0, "node-id": 0
+                    },
+                    "qom-path": "/machine/unattached/device[0]",
+                    "target": "aarch64",
+                    "cpu-index": 0
+                },
+                {
+                    "thread-id": 5202,
+                    "props": {
+                        "core-id": 1, "thread-id": 0, "cluster-id": 0, "socket-id": 0, "node-id": 1
+                    },
+                    "qom-path": "/machine/unattached/device[1]",
+                    "target": "aarch64",
+                    "cpu-index": 1
+                }
+            ]
+        });
+
+        let topology = query_vcpus(io, None).unwrap();
+
+        assert_eq!(Some(5201), topology.get_thread_id(0, 0, 0, 0, 0));
+        assert_eq!(Some(5202), topology.get_thread_id(0, 0, 0, 1, 0));
+    }
+
+    #[test]
+    fn run_startup_commands_places_vcpus_without_core_id_on_cores_of_their_own() {
+        let io = create_successful_mock_qmp_pipe(object! { "return": [
+                {
+                    "thread-id": 5301,
+                    "props": { "node-id": 0 },
+                    "qom-path": "/machine/unattached/device[0]",
+                    "target": "aarch64",
+                    "cpu-index": 0
+                },
+                {
+                    "thread-id": 5302,
+                    "props": {},
+                    "qom-path": "/machine/unattached/device[1]",
+                    "target": "aarch64",
+                    "cpu-index": 1
+                },
+                {
+                    "thread-id": 5303,
+                    "qom-path": "/machine/unattached/device[2]",
+                    "target": "aarch64"
+                }
+            ]
+        });
+
+        let topology = query_vcpus(io, None).unwrap();
+
+        assert_eq!(Some(5301), topology.get_thread_id(0, 0, 0, 0, 0));
+        assert_eq!(Some(5302), topology.get_thread_id(0, 0, 0, 1, 0));
+        assert_eq!(Some(5303), topology.get_thread_id(0, 0, 0, 2, 0));
+        assert_eq!(Some(5302), topology.get_thread_id_by_index(1));
+        assert_eq!(None, topology.get_thread_id_by_index(2));
+    }
+
+    #[test]
+    fn run_startup_commands_returns_error_if_node_id_is_invalid_or_vcpus_share_the_topology() {
+        let io = create_successful_mock_qmp_pipe(object! { "return": [
+                { "thread-id": 5401, "props": { "socket-id": 0, "node-id": 0 }, "cpu-index": 0 },
+                { "thread-id": 5402, "props": { "socket-id": 0, "node-id": "x" }, "cpu-index": 1 }
+            ]
+        });
+
+        assert_error(
+            query_vcpus(io, None),
+            ErrorKind::Other,
+            "`return.1.props.node-id` is invalid, a positive number is expected, but got: `x`.",
+        );
+
+        let io = create_successful_mock_qmp_pipe(object! { "return": [
+                { "thread-id": 5401, "props": { "core-id": 0 }, "cpu-index": 0 },
+                { "thread-id": 5402, "props": { "core-id": 0 }, "cpu-index": 1 }
+            ]
+        });
+
+        assert_error(
+            query_vcpus(io, None),
+            ErrorKind::Other,
+            "`return.1.props` is invalid, another vCPU is already placed at socket 0, \
+            die 0, cluster 0, core 0 and thread 0.",
+        );
+    }
+
+    #[test]
+    fn run_startup_commands_returns_error_if_die_id_is_invalid() {
+        let io = create_successful_mock_qmp_pipe(object! { "return": [
+                {
+                    "thread-id": 4101,
+                    "props": { "core-id": 0, "thread-id": 0, "die-id": -1, "socket-id": 0 },
+                    "cpu-index": 0
+                }
+            ]
+        });
+
+        assert_error(
+            query_vcpus(io, None),
+            ErrorKind::Other,
+            "`return.0.props.die-id` is invalid, a positive number is expected, but got: `-1`.",
+        );
+    }
+
+    #[test]
+    fn run_startup_commands_returns_error_if_negotiation_read_fails() {
         let io = MockQmpPipe::new(vec![None], vec![], vec![]);
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -418,13 +994,13 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_negotiation_json_parsing_fails() {
+    fn run_startup_commands_returns_error_if_negotiation_json_parsing_fails() {
         let io = MockQmpPipe::new(
             vec![Some(String::from("this is not a json\n"))],
             vec![],
             vec![],
         );
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -435,9 +1011,9 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_negotiation_json_is_invalid() {
+    fn run_startup_commands_returns_error_if_negotiation_json_is_invalid() {
         let io = MockQmpPipe::new(vec![Some(String::from("{}\n"))], vec![], vec![]);
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -447,7 +1023,7 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_negotiation_write_fails() {
+    fn run_startup_commands_returns_error_if_negotiation_write_fails() {
         let io = MockQmpPipe::new(
             vec![Some(
                 (object! {
@@ -471,13 +1047,13 @@ mod test {
             )],
             vec![],
         );
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(result, ErrorKind::Other, "MockQmpPipe::write()");
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_negotiation_flush_fails() {
+    fn run_startup_commands_returns_error_if_negotiation_flush_fails() {
         let io = MockQmpPipe::new(
             vec![Some(
                 (object! {
@@ -495,7 +1071,7 @@ mod test {
             vec![((object! { "execute": "qmp_capabilities" }).dump(), true)],
             vec![false],
         );
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(result, ErrorKind::Other, "MockQmpPipe::flush()");
     }
@@ -522,7 +1098,7 @@ mod test {
             vec![((object! { "execute": "qmp_capabilities" }).dump(), true)],
             vec![true],
         );
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -532,7 +1108,7 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_json_response_contains_error_message() {
+    fn run_startup_commands_returns_error_if_json_response_contains_error_message() {
         let io = MockQmpPipe::new(
             vec![
                 Some(
@@ -560,7 +1136,7 @@ mod test {
             vec![((object! { "execute": "qmp_capabilities" }).dump(), true)],
             vec![true],
         );
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -570,7 +1146,7 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_json_response_contains_no_return_field() {
+    fn run_startup_commands_returns_error_if_json_response_contains_no_return_field() {
         let io = MockQmpPipe::new(
             vec![
                 Some(
@@ -591,7 +1167,7 @@ mod test {
             vec![((object! { "execute": "qmp_capabilities" }).dump(), true)],
             vec![true],
         );
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -601,7 +1177,7 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_sending_command_write_fails() {
+    fn run_startup_commands_returns_error_if_sending_command_write_fails() {
         let io = MockQmpPipe::new(
             vec![
                 Some(
@@ -625,13 +1201,13 @@ mod test {
             ],
             vec![true],
         );
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(result, ErrorKind::Other, "MockQmpPipe::write()");
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_sending_command_flush_fails() {
+    fn run_startup_commands_returns_error_if_sending_command_flush_fails() {
         let io = MockQmpPipe::new(
             vec![
                 Some(
@@ -655,13 +1231,13 @@ mod test {
             ],
             vec![true, false],
         );
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(result, ErrorKind::Other, "MockQmpPipe::flush()");
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_reading_fails_after_sending_command() {
+    fn run_startup_commands_returns_error_if_reading_fails_after_sending_command() {
         let io = MockQmpPipe::new(
             vec![
                 Some(
@@ -686,7 +1262,7 @@ mod test {
             ],
             vec![true, true],
         );
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -696,7 +1272,7 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_parsing_json_response_fails() {
+    fn run_startup_commands_returns_error_if_parsing_json_response_fails() {
         let io = MockQmpPipe::new(
             vec![
                 Some(
@@ -721,7 +1297,7 @@ mod test {
             ],
             vec![true, true],
         );
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -732,7 +1308,7 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_qemu_returns_error_response() {
+    fn run_startup_commands_returns_error_if_qemu_returns_error_response() {
         let io = MockQmpPipe::new(
             vec![
                 Some(
@@ -764,7 +1340,7 @@ mod test {
             ],
             vec![true, true],
         );
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -774,7 +1350,7 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_return_field_is_missing_from_qemu_response() {
+    fn run_startup_commands_returns_error_if_return_field_is_missing_from_qemu_response() {
         let io = MockQmpPipe::new(
             vec![
                 Some(
@@ -799,7 +1375,7 @@ mod test {
             ],
             vec![true, true],
         );
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -809,7 +1385,7 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_return_field_is_not_an_array() {
+    fn run_startup_commands_returns_error_if_return_field_is_not_an_array() {
         let io = MockQmpPipe::new(
             vec![
                 Some(
@@ -834,7 +1410,7 @@ mod test {
             ],
             vec![true, true],
         );
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -845,7 +1421,7 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_process_id_is_not_an_integer() {
+    fn run_startup_commands_returns_error_if_process_id_is_not_an_integer() {
         let io = create_successful_mock_qmp_pipe(object! { "return": [
                 {
                     "thread-id": "string",
@@ -858,7 +1434,7 @@ mod test {
             ]
         });
 
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -869,7 +1445,7 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_process_id_is_negative() {
+    fn run_startup_commands_returns_error_if_process_id_is_negative() {
         let io = create_successful_mock_qmp_pipe(object! { "return": [
                 {
                     "thread-id": -33,
@@ -882,7 +1458,7 @@ mod test {
             ]
         });
 
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -892,7 +1468,7 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_vcpu_properties_are_invalid() {
+    fn run_startup_commands_returns_error_if_vcpu_properties_are_invalid() {
         let io = create_successful_mock_qmp_pipe(object! { "return": [
                 {
                     "thread-id": 2418,
@@ -905,7 +1481,7 @@ mod test {
             ]
         });
 
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -915,7 +1491,7 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_vcpu_core_id_is_not_an_integer() {
+    fn run_startup_commands_returns_error_if_vcpu_core_id_is_not_an_integer() {
         let io = create_successful_mock_qmp_pipe(object! { "return": [
                 {
                     "thread-id": 5974,
@@ -928,7 +1504,7 @@ mod test {
             ]
         });
 
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -939,7 +1515,7 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_vcpu_core_id_is_negative() {
+    fn run_startup_commands_returns_error_if_vcpu_core_id_is_negative() {
         let io = create_successful_mock_qmp_pipe(object! { "return": [
                 {
                     "thread-id": 6894,
@@ -952,7 +1528,7 @@ mod test {
             ]
         });
 
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -963,7 +1539,7 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_vcpu_thread_id_is_not_an_integer() {
+    fn run_startup_commands_returns_error_if_vcpu_thread_id_is_not_an_integer() {
         let io = create_successful_mock_qmp_pipe(object! { "return": [
                 {
                     "thread-id": 24789,
@@ -976,7 +1552,7 @@ mod test {
             ]
         });
 
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -987,7 +1563,7 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_vcpu_thread_id_is_negative() {
+    fn run_startup_commands_returns_error_if_vcpu_thread_id_is_negative() {
         let io = create_successful_mock_qmp_pipe(object! { "return": [
                 {
                     "thread-id": 9514,
@@ -1000,7 +1576,7 @@ mod test {
             ]
         });
 
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -1011,7 +1587,7 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_vcpu_socket_id_is_not_an_integer() {
+    fn run_startup_commands_returns_error_if_vcpu_socket_id_is_not_an_integer() {
         let io = create_successful_mock_qmp_pipe(object! { "return": [
                 {
                     "thread-id": 8711,
@@ -1024,7 +1600,7 @@ mod test {
             ]
         });
 
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -1035,7 +1611,7 @@ mod test {
     }
 
     #[test]
-    fn read_vcpu_info_returns_error_if_vcpu_socket_id_is_negative() {
+    fn run_startup_commands_returns_error_if_vcpu_socket_id_is_negative() {
         let io = create_successful_mock_qmp_pipe(object! { "return": [
                 {
                     "thread-id": 6152,
@@ -1048,7 +1624,7 @@ mod test {
             ]
         });
 
-        let result = read_vcpu_info_from_qmp_socket(io);
+        let result = query_vcpus(io, None);
 
         assert_error(
             result,
@@ -1057,4 +1633,362 @@ mod test {
             positive number is expected, but got: `-2`.",
         );
     }
+
+    #[test]
+    fn run_startup_commands_reads_messages_split_across_reads_and_sharing_a_read() {
+        let welcome = (object! { "QMP": { "version": {}, "capabilities": [] } }).dump();
+        let mut vcpus = JsonValue::new_array();
+        for index in 0..512 {
+            vcpus
+                .push(object! {
+                    "thread-id": 4000 + index,
+                    "props": { "core-id": index, "thread-id": 0, "socket-id": 0 },
+                    "qom-path": format!("/machine/unattached/device[{}]", index),
+                    "cpu-index": index
+                })
+                .unwrap();
+        }
+        let payload = (object! { "return": vcpus }).dump() + "\n";
+
+        let mut reads = vec![
+            Some(welcome[..10].to_owned()),
+            Some(welcome[10..].to_owned() + "\n"),
+            Some((object! { "return": {} }).dump() + "\n" + &payload[..1000]),
+        ];
+        for chunk in payload.as_bytes()[1000..].chunks(4096) {
+            reads.push(Some(String::from_utf8(chunk.to_vec()).unwrap()));
+        }
+
+        let io = MockQmpPipe::new(
+            reads,
+            vec![
+                ((object! { "execute": "qmp_capabilities" }).dump(), true),
+                ((object! { "execute": "query-cpus-fast" }).dump(), true),
+            ],
+            vec![true, true],
+        );
+
+        let topology = query_vcpus(io, None).unwrap();
+
+        assert_eq!(512, topology.get_task_ids().len());
+        assert_eq!(Some(4511), topology.get_thread_id_by_index(511));
+    }
+
+    #[test]
+    fn run_startup_commands_logs_every_qmp_message() {
+        let io = create_successful_mock_qmp_pipe(object! { "return": [] });
+        let buffer = Rc::new(RefCell::new(vec![]));
+
+        query_vcpus(io, Some(Box::new(SharedLog(buffer.clone())))).unwrap();
+
+        let log = String::from_utf8(buffer.borrow().clone()).unwrap();
+        let lines: Vec<&str> = log
+            .lines()
+            .filter(|line| line.starts_with("QMP "))
+            .collect();
+
+        assert_eq!(
+            vec!["QMP <- {", "QMP -> {", "QMP <- {", "QMP -> {", "QMP <- {",],
+            lines
+        );
+        assert!(log.contains("\"execute\": \"query-cpus-fast\""));
+    }
+
+    #[test]
+    fn run_startup_commands_executes_commands_before_querying_vcpus() {
+        let welcome = object! { "QMP": { "version": {}, "capabilities": [] } };
+        let io = MockQmpPipe::new(
+            vec![
+                Some(welcome.dump() + "\n"),
+                Some((object! { "return": {} }).dump() + "\n"),
+                Some((object! { "error": { "class": "GenericError", "desc": "Unknown capability" } }).dump() + "\n"),
+                Some((object! { "error": { "class": "DeviceNotActive", "desc": "No medium" } }).dump() + "\n"),
+                Some((object! { "return": {} }).dump() + "\n"),
+                Some((object! { "return": [] }).dump() + "\n"),
+            ],
+            vec![
+                ((object! { "execute": "qmp_capabilities" }).dump(), true),
+                ((object! {
+                    "execute": "migrate-set-capabilities",
+                    "arguments": { "capabilities": [{ "capability": "foo", "state": true }] }
+                })
+                .dump(), true),
+                ((object! { "execute": "block_set_io_throttle", "arguments": { "id": "disk0", "iops": 100 } })
+                    .dump(), true),
+                ((object! { "execute": "block_set_io_throttle", "arguments": { "id": "disk0", "iops": 100 } })
+                    .dump(), true),
+                ((object! { "execute": "query-cpus-fast" }).dump(), true),
+            ],
+            vec![true, true, true, true, true],
+        );
+
+        let clock = FakeClock::new();
+        let (_, failures) = run_startup_commands(
+            io,
+            None,
+            clock.clone(),
+            &[
+                object! {
+                    "execute": "migrate-set-capabilities",
+                    "arguments": { "capabilities": [{ "capability": "foo", "state": true }] }
+                },
+                object! { "execute": "block_set_io_throttle", "arguments": { "id": "disk0", "iops": 100 } },
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(vec![RETRY_DELAY], clock.get_sleeps());
+        assert_eq!(1, failures.len());
+        assert_eq!(
+            "Failed to execute the `migrate-set-capabilities` QMP startup command: \
+            Received error QMP response: `Unknown capability`.",
+            format!("{}", failures[0])
+        );
+
+        let error = as_qmp_error(&failures[0]).unwrap();
+        assert_eq!(QmpErrorClass::GenericError, error.get_class());
+        assert_eq!(false, error.is_retryable());
+    }
+
+    #[test]
+    fn add_devices_hot_adds_every_device_reporting_failures() {
+        let add = |device: &str| {
+            (object! {
+                "execute": "human-monitor-command",
+                "arguments": { "command-line": format!("device_add {}", device) },
+            })
+            .dump()
+        };
+        let io = MockQmpPipe::new(
+            vec![
+                Some((object! { "return": "" }).dump() + "\n"),
+                Some(
+                    (object! { "return": "Error: failed to find host usb device 1:9\r\n" }).dump()
+                        + "\n",
+                ),
+                Some((object! { "return": "" }).dump() + "\n"),
+            ],
+            vec![
+                (add("usb-host,hostbus=1,hostaddr=5,id=kbd"), true),
+                (add("usb-host,hostbus=1,hostaddr=9,id=mouse"), true),
+                (add("usb-host,vendorid=0x046d,productid=0xc52b"), true),
+            ],
+            vec![true, true, true],
+        );
+
+        let failures = add_devices(
+            io,
+            None,
+            &[
+                String::from("usb-host,hostbus=1,hostaddr=5,id=kbd"),
+                String::from("usb-host,hostbus=1,hostaddr=9,id=mouse"),
+                String::from("usb-host,vendorid=0x046d,productid=0xc52b"),
+            ],
+        );
+
+        assert_eq!(1, failures.len());
+        assert_eq!(
+            "Failed to add the `usb-host,hostbus=1,hostaddr=9,id=mouse` device: \
+            Error: failed to find host usb device 1:9",
+            format!("{}", failures[0])
+        );
+    }
+
+    #[test]
+    fn redact_hides_password_and_secret_values() {
+        assert_eq!(
+            object! {
+                "execute": "change-vnc-password",
+                "arguments": {
+                    "password": "<redacted>",
+                    "nested": [{ "key-secret": "<redacted>", "id": "sec0" }]
+                }
+            },
+            redact(&object! {
+                "execute": "change-vnc-password",
+                "arguments": {
+                    "password": "hunter2",
+                    "nested": [{ "key-secret": "sec1", "id": "sec0" }]
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn redact_hides_inline_data_of_secret_objects() {
+        assert_eq!(
+            object! {
+                "execute": "object-add",
+                "arguments": {
+                    "qom-type": "secret",
+                    "id": "sec0",
+                    "data": "<redacted>",
+                    "format": "base64"
+                }
+            },
+            redact(&object! {
+                "execute": "object-add",
+                "arguments": {
+                    "qom-type": "secret",
+                    "id": "sec0",
+                    "data": "aHVudGVyMg==",
+                    "format": "base64"
+                }
+            })
+        );
+        assert_eq!(
+            object! {
+                "execute": "object-add",
+                "arguments": {
+                    "qom-type": "secret",
+                    "id": "sec1",
+                    "props": { "data": "<redacted>" }
+                }
+            },
+            redact(&object! {
+                "execute": "object-add",
+                "arguments": {
+                    "qom-type": "secret",
+                    "id": "sec1",
+                    "props": { "data": "hunter2" }
+                }
+            })
+        );
+        assert_eq!(
+            object! {
+                "execute": "object-add",
+                "arguments": { "qom-type": "memory-backend-file", "id": "mem0", "data": "foo" }
+            },
+            redact(&object! {
+                "execute": "object-add",
+                "arguments": { "qom-type": "memory-backend-file", "id": "mem0", "data": "foo" }
+            })
+        );
+    }
+
+    #[test]
+    fn run_self_test_queries_vcpus_and_quits() {
+        let io = MockQmpPipe::new(
+            vec![
+                Some((object! { "QMP": { "capabilities": [] } }).dump() + "\n"),
+                Some((object! { "return": {} }).dump() + "\n"),
+                Some((object! { "return": [] }).dump() + "\n"),
+                Some((object! { "return": {} }).dump() + "\n"),
+            ],
+            vec![
+                ((object! { "execute": "qmp_capabilities" }).dump(), true),
+                ((object! { "execute": "query-cpus-fast" }).dump(), true),
+                ((object! { "execute": "quit" }).dump(), true),
+            ],
+            vec![true, true, true],
+        );
+
+        let topology = run_self_test(io, None).unwrap();
+
+        assert_eq!(Vec::<usize>::new(), topology.get_task_ids());
+    }
+
+    #[test]
+    fn watch_events_answers_events_with_handler_commands_until_connection_is_closed() {
+        let io = MockQmpPipe::new(
+            vec![
+                Some((object! { "event": "SHUTDOWN", "data": { "guest": true } }).dump() + "\n"),
+                Some((object! { "event": "RESET", "data": { "guest": false } }).dump() + "\n"),
+                Some((object! { "return": {} }).dump() + "\n"),
+                Some((object! { "return": {} }).dump() + "\n"),
+                Some(String::new()),
+            ],
+            vec![
+                ((object! { "execute": "system_reset" }).dump(), true),
+                ((object! { "execute": "cont" }).dump(), true),
+            ],
+            vec![true, true],
+        );
+        let mut events = vec![];
+
+        watch_events(io, None, FakeClock::new(), |event, data| {
+            events.push(format!("{} {}", event, data["guest"]));
+            match event {
+                "SHUTDOWN" => vec![
+                    object! { "execute": "system_reset" },
+                    object! { "execute": "cont" },
+                ],
+                _ => vec![],
+            }
+        })
+        .unwrap();
+
+        assert_eq!(vec!["SHUTDOWN true", "RESET false"], events);
+    }
+
+    #[test]
+    fn watch_events_retries_commands_failing_with_retryable_errors() {
+        let busy = object! { "error": {
+            "class": "GenericError",
+            "desc": "Device 'drive0' is busy: block device is in use by block job: mirror"
+        } };
+        let io = MockQmpPipe::new(
+            vec![
+                Some((object! { "event": "SHUTDOWN", "data": { "guest": true } }).dump() + "\n"),
+                Some(busy.dump() + "\n"),
+                Some((object! { "return": {} }).dump() + "\n"),
+                Some((object! { "event": "SHUTDOWN", "data": { "guest": true } }).dump() + "\n"),
+                Some(
+                    (object! { "error": { "class": "CommandNotFound", "desc": "nope" } }).dump()
+                        + "\n",
+                ),
+            ],
+            vec![
+                ((object! { "execute": "system_reset" }).dump(), true),
+                ((object! { "execute": "system_reset" }).dump(), true),
+                ((object! { "execute": "system_reset" }).dump(), true),
+            ],
+            vec![true, true, true],
+        );
+
+        let clock = FakeClock::new();
+        let result = watch_events(io, None, clock.clone(), |_, _| {
+            vec![object! { "execute": "system_reset" }]
+        });
+
+        match result {
+            Err(e) => {
+                assert_eq!(
+                    "Failed to execute the `system_reset` QMP command: \
+                    Received error QMP response: `nope`.",
+                    e.to_string()
+                );
+                assert_eq!(
+                    QmpErrorClass::CommandNotFound,
+                    as_qmp_error(&e).unwrap().get_class()
+                );
+                assert_eq!(vec![RETRY_DELAY], clock.get_sleeps());
+            }
+            Ok(_) => panic!("Expected an error"),
+        }
+    }
+
+    #[test]
+    fn watch_events_returns_error_if_command_fails() {
+        let io = MockQmpPipe::new(
+            vec![
+                Some((object! { "event": "SHUTDOWN", "data": { "guest": true } }).dump() + "\n"),
+                Some((object! { "error": { "desc": "not now" } }).dump() + "\n"),
+            ],
+            vec![((object! { "execute": "quit" }).dump(), true)],
+            vec![true],
+        );
+
+        let result = watch_events(io, None, FakeClock::new(), |_, _| {
+            vec![object! { "execute": "quit" }]
+        });
+
+        match result {
+            Err(e) => assert_eq!(
+                "Failed to execute the `quit` QMP command: Received error QMP response: `not now`.",
+                e.to_string()
+            ),
+            Ok(_) => panic!("Expected an error"),
+        }
+    }
 }
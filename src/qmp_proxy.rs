@@ -0,0 +1,519 @@
+use crate::{output, qmp::redact};
+use json::{object, JsonValue};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    io::{BufRead, BufReader, Error, ErrorKind, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    sync::mpsc::{self, Sender},
+    thread,
+};
+
+const TAG_PREFIX: &str = "qemu-launcher-";
+
+enum Message {
+    Qemu(JsonValue),
+    QemuClosed,
+    Connected(usize, UnixStream),
+    Command(usize, Result<JsonValue, String>),
+    Disconnected(usize),
+}
+
+#[derive(Debug, PartialEq)]
+enum Owner {
+    Client(usize),
+    Launcher(String),
+    Version,
+}
+
+// Replaces the ids of commands sharing the single qemu channel with unique tags, so every response
+// finds its way back, and only lets one command be in flight at a time
+struct Router {
+    in_flight: bool,
+    next_tag: usize,
+    pending: HashMap<String, (Owner, JsonValue)>,
+    queue: VecDeque<JsonValue>,
+}
+
+impl Router {
+    fn new() -> Self {
+        Router {
+            in_flight: false,
+            next_tag: 0,
+            pending: HashMap::new(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    // Queues the command, returning the command to send next if the channel is idle
+    fn submit(&mut self, owner: Owner, mut command: JsonValue) -> Option<JsonValue> {
+        let tag = format!("{}{}", TAG_PREFIX, self.next_tag);
+        self.next_tag += 1;
+
+        let id = command.remove("id");
+        command["id"] = tag.clone().into();
+
+        self.pending.insert(tag, (owner, id));
+        self.queue.push_back(command);
+
+        self.next()
+    }
+
+    fn next(&mut self) -> Option<JsonValue> {
+        if self.in_flight {
+            return None;
+        }
+
+        let command = self.queue.pop_front()?;
+        self.in_flight = true;
+
+        Some(command)
+    }
+
+    // Commands are executed in order, so any response completes the command in flight
+    fn complete(&mut self, mut response: JsonValue) -> Option<(Owner, JsonValue)> {
+        self.in_flight = false;
+
+        let tag = response.remove("id");
+        let (owner, id) = self.pending.remove(tag.as_str()?)?;
+        if !id.is_null() {
+            response["id"] = id;
+        }
+
+        Some((owner, response))
+    }
+
+    // Drops the queued commands of a client gone away, the response to one in flight is discarded
+    fn forget(&mut self, client: usize) {
+        let pending = &mut self.pending;
+
+        self.queue.retain(|command| {
+            let tag = command["id"].as_str().unwrap_or_default();
+            if pending.get(tag).map(|(owner, _)| owner) != Some(&Owner::Client(client)) {
+                return true;
+            }
+
+            pending.remove(tag);
+            false
+        });
+    }
+}
+
+struct Client {
+    greeted: bool,
+    negotiated: bool,
+    stream: UnixStream,
+}
+
+impl Client {
+    fn send(&mut self, message: &JsonValue) -> Result<(), Error> {
+        self.stream
+            .write_all(format!("{}\r\n", message.dump()).as_bytes())
+    }
+}
+
+// Answers the commands the proxy handles itself, qemu only sees commands of negotiated clients
+fn negotiate(negotiated: &mut bool, command: &JsonValue) -> Option<JsonValue> {
+    let error = |class: &str, desc: &str| {
+        let mut response = object! { "error": { "class": class, "desc": desc } };
+        if !command["id"].is_null() {
+            response["id"] = command["id"].clone();
+        }
+
+        Some(response)
+    };
+
+    if !command.is_object() || !command["execute"].is_string() {
+        return error(
+            "GenericError",
+            "QMP input must be an object with the `execute` command name",
+        );
+    }
+
+    match (*negotiated, command["execute"].as_str()) {
+        (false, Some("qmp_capabilities")) => {
+            *negotiated = true;
+
+            let mut response = object! { "return": {} };
+            if !command["id"].is_null() {
+                response["id"] = command["id"].clone();
+            }
+
+            Some(response)
+        }
+        (false, _) => error(
+            "CommandNotFound",
+            "Expecting capabilities negotiation with 'qmp_capabilities'",
+        ),
+        (true, Some("qmp_capabilities")) => error(
+            "CommandNotFound",
+            "Capabilities negotiation is already complete, command ignored",
+        ),
+        (true, _) => None,
+    }
+}
+
+pub fn bind(path: &str) -> Result<UnixListener, Error> {
+    // A socket left behind by a crashed launcher would prevent binding
+    match fs::remove_file(path) {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to remove the stale QMP proxy socket `{}`: {}",
+                    path, e
+                ),
+            ))
+        }
+    }
+
+    match UnixListener::bind(path) {
+        Ok(listener) => Ok(listener),
+        Err(e) => Err(Error::new(
+            e.kind(),
+            format!("Failed to create the QMP proxy socket `{}`: {}", path, e),
+        )),
+    }
+}
+
+// Shares an already negotiated qemu channel with the clients of the listener, until qemu closes it.
+// Events are passed to every negotiated client and to the handler, whose commands are executed too.
+pub fn serve<R, W, F>(
+    path: &str,
+    listener: UnixListener,
+    qemu_output: R,
+    mut qemu_input: W,
+    mut log: Option<Box<dyn Write>>,
+    mut handler: F,
+) -> Result<(), Error>
+where
+    R: Read + Send + 'static,
+    W: Write,
+    F: FnMut(&str, &JsonValue) -> Vec<JsonValue>,
+{
+    let (sender, receiver) = mpsc::channel();
+
+    let qemu_sender = sender.clone();
+    thread::spawn(move || read_qemu(qemu_output, qemu_sender));
+    thread::spawn(move || accept_clients(listener, sender));
+
+    let mut send = |command: JsonValue, log: &mut Option<Box<dyn Write>>| {
+        if let Some(log) = log {
+            let _ = writeln!(log, "QMP -> {}", redact(&command).pretty(2));
+            let _ = log.flush();
+        }
+
+        qemu_input
+            .write_all(command.dump().as_bytes())
+            .and_then(|_| qemu_input.flush())
+    };
+
+    let mut router = Router::new();
+    let mut clients: HashMap<usize, Client> = HashMap::new();
+    // The qemu greeting was consumed by the launcher, clients get one with the same version
+    let mut version = None;
+
+    if let Some(command) = router.submit(Owner::Version, object! { "execute": "query-version" }) {
+        send(command, &mut log)?;
+    }
+
+    for message in receiver {
+        match message {
+            Message::Qemu(message) => {
+                if let Some(log) = &mut log {
+                    let _ = writeln!(log, "QMP <- {}", redact(&message).pretty(2));
+                    let _ = log.flush();
+                }
+
+                if !message["event"].is_null() {
+                    clients.retain(|_, client| !client.negotiated || client.send(&message).is_ok());
+
+                    let event = message["event"].as_str().unwrap_or_default();
+                    for command in handler(event, &message["data"]) {
+                        let name = command["execute"].to_string();
+                        if let Some(command) = router.submit(Owner::Launcher(name), command) {
+                            send(command, &mut log)?;
+                        }
+                    }
+
+                    continue;
+                }
+
+                match router.complete(message) {
+                    Some((Owner::Client(id), response)) => {
+                        if let Some(client) = clients.get_mut(&id) {
+                            if client.send(&response).is_err() {
+                                clients.remove(&id);
+                            }
+                        }
+                    }
+                    Some((Owner::Launcher(name), response)) => {
+                        if !response["error"].is_null() {
+                            output::error(format!(
+                                "Failed to execute the `{}` QMP command: Received error QMP response: `{}`.",
+                                name, response["error"]["desc"]
+                            ));
+                        }
+                    }
+                    Some((Owner::Version, response)) => {
+                        version = Some(response["return"].clone());
+                    }
+                    None => {}
+                }
+
+                if let Some(command) = router.next() {
+                    send(command, &mut log)?;
+                }
+            }
+            Message::QemuClosed => break,
+            Message::Connected(id, stream) => {
+                clients.insert(
+                    id,
+                    Client {
+                        greeted: false,
+                        negotiated: false,
+                        stream: stream,
+                    },
+                );
+            }
+            Message::Command(id, command) => {
+                let client = match clients.get_mut(&id) {
+                    Some(client) => client,
+                    None => continue,
+                };
+
+                let command = match command {
+                    Ok(command) => command,
+                    Err(e) => {
+                        let response = object! { "error": { "class": "GenericError", "desc": e } };
+                        if client.send(&response).is_err() {
+                            clients.remove(&id);
+                        }
+                        continue;
+                    }
+                };
+
+                if let Some(response) = negotiate(&mut client.negotiated, &command) {
+                    if client.send(&response).is_err() {
+                        clients.remove(&id);
+                    }
+                    continue;
+                }
+
+                if let Some(command) = router.submit(Owner::Client(id), command) {
+                    send(command, &mut log)?;
+                }
+            }
+            Message::Disconnected(id) => {
+                clients.remove(&id);
+                router.forget(id);
+            }
+        }
+
+        if let Some(version) = &version {
+            let greeting = object! {
+                "QMP": { "version": version.clone(), "capabilities": [] }
+            };
+
+            clients.retain(|_, client| {
+                if client.greeted {
+                    return true;
+                }
+
+                client.greeted = true;
+                client.send(&greeting).is_ok()
+            });
+        }
+    }
+
+    let _ = fs::remove_file(path);
+
+    Ok({})
+}
+
+fn read_qemu(qemu_output: impl Read, sender: Sender<Message>) {
+    let mut reader = BufReader::new(qemu_output);
+
+    loop {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+
+        match json::parse(&line) {
+            Ok(message) => {
+                if sender.send(Message::Qemu(message)).is_err() {
+                    return;
+                }
+            }
+            Err(e) => output::error(format!(
+                "Failed to parse QMP response `{}`, error: `{}`.",
+                line.trim(),
+                e
+            )),
+        }
+    }
+
+    let _ = sender.send(Message::QemuClosed);
+}
+
+fn accept_clients(listener: UnixListener, sender: Sender<Message>) {
+    for (id, stream) in listener.incoming().enumerate() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+
+        let reader = match stream.try_clone() {
+            Ok(reader) => reader,
+            Err(_) => continue,
+        };
+
+        if sender.send(Message::Connected(id, stream)).is_err() {
+            return;
+        }
+
+        let sender = sender.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+
+                if line.trim().len() < 1 {
+                    continue;
+                }
+
+                let command = json::parse(&line).map_err(|e| format!("JSON parse error, {}", e));
+                if sender.send(Message::Command(id, command)).is_err() {
+                    return;
+                }
+            }
+
+            let _ = sender.send(Message::Disconnected(id));
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{negotiate, Owner, Router};
+    use json::object;
+
+    #[test]
+    fn router_tags_commands_and_sends_one_at_a_time() {
+        let mut router = Router::new();
+
+        assert_eq!(
+            Some(object! { "execute": "query-status", "id": "qemu-launcher-0" }),
+            router.submit(
+                Owner::Client(1),
+                object! { "execute": "query-status", "id": "user-1" }
+            )
+        );
+        assert_eq!(
+            None,
+            router.submit(
+                Owner::Launcher(String::from("quit")),
+                object! { "execute": "quit" }
+            )
+        );
+
+        assert_eq!(
+            Some((
+                Owner::Client(1),
+                object! { "return": { "running": true }, "id": "user-1" }
+            )),
+            router.complete(object! { "return": { "running": true }, "id": "qemu-launcher-0" })
+        );
+        assert_eq!(
+            Some(object! { "execute": "quit", "id": "qemu-launcher-1" }),
+            router.next()
+        );
+        assert_eq!(
+            Some((
+                Owner::Launcher(String::from("quit")),
+                object! { "return": {} }
+            )),
+            router.complete(object! { "return": {}, "id": "qemu-launcher-1" })
+        );
+        assert_eq!(None, router.next());
+    }
+
+    #[test]
+    fn router_forgets_queued_commands_of_disconnected_client() {
+        let mut router = Router::new();
+
+        router.submit(Owner::Client(1), object! { "execute": "stop" });
+        router.submit(Owner::Client(1), object! { "execute": "cont" });
+        router.submit(Owner::Client(2), object! { "execute": "query-status" });
+
+        router.forget(1);
+
+        assert_eq!(
+            Some((Owner::Client(1), object! { "return": {} })),
+            router.complete(object! { "return": {}, "id": "qemu-launcher-0" })
+        );
+        assert_eq!(
+            Some(object! { "execute": "query-status", "id": "qemu-launcher-2" }),
+            router.next()
+        );
+        assert_eq!(
+            None,
+            router.complete(object! { "return": {}, "id": "qemu-launcher-1" })
+        );
+    }
+
+    #[test]
+    fn negotiate_requires_capabilities_negotiation_before_forwarding() {
+        let mut negotiated = false;
+
+        assert_eq!(
+            Some(object! {
+                "error": {
+                    "class": "CommandNotFound",
+                    "desc": "Expecting capabilities negotiation with 'qmp_capabilities'"
+                },
+                "id": 1
+            }),
+            negotiate(
+                &mut negotiated,
+                &object! { "execute": "query-status", "id": 1 }
+            )
+        );
+        assert_eq!(
+            Some(object! { "return": {}, "id": 2 }),
+            negotiate(
+                &mut negotiated,
+                &object! { "execute": "qmp_capabilities", "id": 2 }
+            )
+        );
+        assert!(negotiated);
+        assert_eq!(
+            None,
+            negotiate(&mut negotiated, &object! { "execute": "query-status" })
+        );
+        assert_eq!(
+            Some(object! {
+                "error": {
+                    "class": "CommandNotFound",
+                    "desc": "Capabilities negotiation is already complete, command ignored"
+                }
+            }),
+            negotiate(&mut negotiated, &object! { "execute": "qmp_capabilities" })
+        );
+        assert_eq!(
+            Some(object! {
+                "error": {
+                    "class": "GenericError",
+                    "desc": "QMP input must be an object with the `execute` command name"
+                }
+            }),
+            negotiate(&mut negotiated, &object! { "arguments": {} })
+        );
+    }
+}
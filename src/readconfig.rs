@@ -0,0 +1,232 @@
+use std::collections::HashSet;
+
+// Option groups `-readconfig` accepts, along with the property a leading value without a name is
+// implied to be, as qemu parses them on the command line
+const GROUPS: &[(&str, Option<&str>)] = &[
+    ("chardev", Some("backend")),
+    ("device", Some("driver")),
+    ("drive", None),
+];
+
+// Limits of the qemu configuration file parser
+const MAX_NAME_LENGTH: usize = 127;
+const MAX_VALUE_LENGTH: usize = 1023;
+
+// Moves the options qemu can read from the configuration file at `path` out of the command line,
+// returning the file contents, unless nothing was moved, and the remaining arguments. Once an option
+// of a group can not be moved, the following ones of the same group stay on the command line as
+// well, so devices are still created in the configured order.
+pub fn render(
+    machine_name: &str,
+    arguments: &[String],
+    path: &str,
+) -> (Option<String>, Vec<String>) {
+    let mut contents = format!("# Generated by qemu-launcher for `{}`\n", machine_name);
+    let mut remaining = vec![];
+    let mut stuck = HashSet::new();
+    let mut moved = false;
+
+    let mut iter = arguments.iter();
+    while let Some(argument) = iter.next() {
+        let group = GROUPS
+            .iter()
+            .find(|(name, _)| argument.strip_prefix('-') == Some(name));
+
+        let (group, implied) = match group {
+            Some(group) if !stuck.contains(group.0) => *group,
+            _ => {
+                remaining.push(argument.to_owned());
+                continue;
+            }
+        };
+
+        let value = match iter.next() {
+            Some(value) => value,
+            None => {
+                remaining.push(argument.to_owned());
+                continue;
+            }
+        };
+
+        match render_section(group, implied, value) {
+            Some(section) => {
+                contents.push_str(&section);
+                if !moved {
+                    moved = true;
+                    remaining.push(String::from("-readconfig"));
+                    remaining.push(path.to_owned());
+                }
+            }
+            None => {
+                stuck.insert(group);
+                remaining.push(argument.to_owned());
+                remaining.push(value.to_owned());
+            }
+        }
+    }
+
+    match moved {
+        true => (Some(contents), remaining),
+        false => (None, remaining),
+    }
+}
+
+fn render_section(group: &str, implied: Option<&str>, value: &str) -> Option<String> {
+    let mut id = None;
+    let mut properties = vec![];
+
+    for (index, property) in split_properties(value).into_iter().enumerate() {
+        let (name, value) = match (property.split_once('='), implied) {
+            (Some((name, value)), _) => (name.to_owned(), value.to_owned()),
+            (None, Some(implied)) if index == 0 => (implied.to_owned(), property),
+            // Bare flags, e.g. `server` or `nowait`, have no equivalent in the file
+            (None, _) => return None,
+        };
+
+        if name.len() == 0
+            || name.len() > MAX_NAME_LENGTH
+            || value.len() > MAX_VALUE_LENGTH
+            || name.contains(|c: char| c == '=' || c == '"' || c.is_whitespace())
+            || value.contains(|c: char| c == '"' || c == '\n')
+        {
+            return None;
+        }
+
+        match name.as_str() {
+            "id" => id = Some(value),
+            _ => properties.push((name, value)),
+        }
+    }
+
+    let mut section = match id {
+        Some(id) => format!("\n[{} \"{}\"]\n", group, id),
+        None => format!("\n[{}]\n", group),
+    };
+    for (name, value) in properties {
+        section.push_str(&format!("  {} = \"{}\"\n", name, value));
+    }
+
+    Some(section)
+}
+
+// Properties are separated by commas, a doubled comma is a literal one
+fn split_properties(value: &str) -> Vec<String> {
+    let mut properties = vec![String::new()];
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ',' if chars.peek() == Some(&',') => {
+                chars.next();
+                properties.last_mut().unwrap().push(',');
+            }
+            ',' => properties.push(String::new()),
+            c => properties.last_mut().unwrap().push(c),
+        }
+    }
+
+    properties
+}
+
+#[cfg(test)]
+mod test {
+    use super::render;
+
+    fn arguments(arguments: &[&str]) -> Vec<String> {
+        arguments
+            .iter()
+            .map(|argument| argument.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn render_moves_devices_drives_and_chardevs_into_the_file() {
+        let (contents, remaining) = render(
+            "foo",
+            &arguments(&[
+                "-enable-kvm",
+                "-drive",
+                "file=/srv/foo,,bar.qcow2,if=virtio",
+                "-chardev",
+                "pty,id=serial0",
+                "-device",
+                "virtio-net-pci,mac=52:54:00:aa:bb:cc,id=net0",
+                "-qmp",
+                "stdio",
+            ]),
+            "/run/qemu-launcher/foo/qemu.cfg",
+        );
+
+        assert_eq!(
+            Some(String::from(
+                "# Generated by qemu-launcher for `foo`\n\
+            \n\
+            [drive]\n  file = \"/srv/foo,bar.qcow2\"\n  if = \"virtio\"\n\
+            \n\
+            [chardev \"serial0\"]\n  backend = \"pty\"\n\
+            \n\
+            [device \"net0\"]\n  driver = \"virtio-net-pci\"\n  mac = \"52:54:00:aa:bb:cc\"\n"
+            )),
+            contents
+        );
+        assert_eq!(
+            arguments(&[
+                "-enable-kvm",
+                "-readconfig",
+                "/run/qemu-launcher/foo/qemu.cfg",
+                "-qmp",
+                "stdio"
+            ]),
+            remaining
+        );
+    }
+
+    #[test]
+    fn render_keeps_options_after_one_which_can_not_be_moved_on_the_command_line() {
+        let (contents, remaining) = render(
+            "foo",
+            &arguments(&[
+                "-device",
+                "qemu-xhci,id=xhci",
+                "-chardev",
+                "socket,id=mon0,path=/tmp/mon.sock,server,nowait",
+                "-device",
+                "usb-tablet",
+                "-chardev",
+                "pty,id=serial0",
+            ]),
+            "/run/qemu-launcher/foo/qemu.cfg",
+        );
+
+        assert_eq!(
+            Some(String::from(
+                "# Generated by qemu-launcher for `foo`\n\
+            \n\
+            [device \"xhci\"]\n  driver = \"qemu-xhci\"\n\
+            \n\
+            [device]\n  driver = \"usb-tablet\"\n"
+            )),
+            contents
+        );
+        assert_eq!(
+            arguments(&[
+                "-readconfig",
+                "/run/qemu-launcher/foo/qemu.cfg",
+                "-chardev",
+                "socket,id=mon0,path=/tmp/mon.sock,server,nowait",
+                "-chardev",
+                "pty,id=serial0",
+            ]),
+            remaining
+        );
+
+        assert_eq!(
+            (None, arguments(&["-device", "usb-tablet,nofoo"])),
+            render(
+                "foo",
+                &arguments(&["-device", "usb-tablet,nofoo"]),
+                "/run/qemu-launcher/foo/qemu.cfg"
+            )
+        );
+    }
+}
@@ -0,0 +1,265 @@
+#[cfg(not(test))]
+use std::fs;
+use std::{
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+#[cfg(test)]
+use test::std::fs;
+
+pub fn group_name(machine_name: &str) -> String {
+    format!("qemu-launcher-{}", machine_name)
+}
+
+// A resource control group of the kernel `resctrl` filesystem (Intel RDT, AMD PQoS), which limits the
+// cache and memory bandwidth used by the CPUs assigned to it
+pub struct ResctrlGroup {
+    mount_path: PathBuf,
+    path: Option<PathBuf>,
+}
+
+impl ResctrlGroup {
+    pub fn new<P: AsRef<Path>>(mount_path: P) -> Self {
+        ResctrlGroup {
+            mount_path: PathBuf::from(mount_path.as_ref()),
+            path: None,
+        }
+    }
+
+    pub fn create(&mut self, name: &str, cpus: &[usize], schemata: &[String]) -> Result<(), Error> {
+        let path = self.mount_path.join(name);
+
+        // A group left behind by a crashed launcher is reused, it is fully reconfigured below
+        match fs::create_dir(&path) {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+            Err(e) => {
+                return Err(Error::new(
+                    e.kind(),
+                    format!(
+                        "Failed to create the `{}` resctrl group, is the resctrl filesystem mounted at `{}`? {}",
+                        name,
+                        self.mount_path.display(),
+                        e
+                    ),
+                ))
+            }
+        }
+        self.path = Some(path.clone());
+
+        let cpus: Vec<String> = cpus.iter().map(|cpu| cpu.to_string()).collect();
+        self.write(&path.join("cpus_list"), &cpus.join(","))?;
+
+        for line in schemata {
+            self.write(&path.join("schemata"), line)?;
+        }
+
+        Ok({})
+    }
+
+    fn write(&self, path: &Path, value: &str) -> Result<(), Error> {
+        if let Err(e) = fs::write(path, value) {
+            // The kernel explains rejected values in a separate file, the write error is just `EINVAL`
+            let status = fs::read_to_string(self.mount_path.join("info").join("last_cmd_status"))
+                .map(|status| status.trim().to_owned())
+                .unwrap_or_default();
+
+            return Err(Error::new(
+                e.kind(),
+                match status.len() {
+                    0 => format!(
+                        "Failed to write `{}` into `{}`: {}",
+                        value,
+                        path.display(),
+                        e
+                    ),
+                    _ => format!(
+                        "Failed to write `{}` into `{}`: {} ({})",
+                        value,
+                        path.display(),
+                        e,
+                        status
+                    ),
+                },
+            ));
+        }
+
+        Ok({})
+    }
+
+    // The CPUs of the removed group are returned to the default group by the kernel
+    pub fn remove(&mut self) -> Result<(), Error> {
+        let path = match self.path.take() {
+            Some(path) => path,
+            None => return Ok({}),
+        };
+
+        if let Err(e) = fs::remove_dir(&path) {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to remove the `{}` resctrl group: {}",
+                    path.display(),
+                    e
+                ),
+            ));
+        }
+
+        Ok({})
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ResctrlGroup;
+    use crate::{assert_error, expect, vec_deq, verify_expectations};
+    use ::std::{
+        cell::RefCell,
+        collections::VecDeque,
+        io::{Error, ErrorKind},
+    };
+
+    struct TestExpectations {
+        std_fs_create_dir: VecDeque<(&'static str, Result<(), Error>)>,
+        std_fs_read_to_string: VecDeque<(&'static str, Result<String, Error>)>,
+        std_fs_remove_dir: VecDeque<(&'static str, Result<(), Error>)>,
+        std_fs_write: VecDeque<((&'static str, &'static str), Result<(), Error>)>,
+    }
+
+    impl TestExpectations {
+        fn new() -> Self {
+            TestExpectations {
+                std_fs_create_dir: vec_deq![],
+                std_fs_read_to_string: vec_deq![],
+                std_fs_remove_dir: vec_deq![],
+                std_fs_write: vec_deq![],
+            }
+        }
+    }
+
+    thread_local! { static TEST_EXPECTATIONS: RefCell<TestExpectations> = RefCell::new(TestExpectations::new()) }
+
+    fn verify_expectations() {
+        verify_expectations!(
+            std::fs::create_dir => TEST_EXPECTATIONS::std_fs_create_dir,
+            std::fs::read_to_string => TEST_EXPECTATIONS::std_fs_read_to_string,
+            std::fs::remove_dir => TEST_EXPECTATIONS::std_fs_remove_dir,
+            std::fs::write => TEST_EXPECTATIONS::std_fs_write,
+        );
+    }
+
+    pub mod std {
+        pub mod fs {
+            use super::super::TEST_EXPECTATIONS;
+            use crate::verify_expectation;
+            use ::std::{io::Result, path::Path, str::from_utf8};
+
+            pub fn create_dir<P: AsRef<Path>>(path: P) -> Result<()> {
+                let path = path.as_ref().to_str().unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_create_dir => std::fs::create_dir { path })
+            }
+
+            pub fn read_to_string<P: AsRef<Path>>(path: P) -> Result<String> {
+                let path = path.as_ref().to_str().unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_read_to_string => std::fs::read_to_string { path })
+            }
+
+            pub fn remove_dir<P: AsRef<Path>>(path: P) -> Result<()> {
+                let path = path.as_ref().to_str().unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_remove_dir => std::fs::remove_dir { path })
+            }
+
+            pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, data: C) -> Result<()> {
+                let path = path.as_ref().to_str().unwrap();
+                let data = from_utf8(data.as_ref()).unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_write => std::fs::write { path, data })
+            }
+        }
+    }
+
+    macro_rules! error {
+        ($msg:expr) => {{
+            Err(Error::new(ErrorKind::Other, format!("{}", $msg)))
+        }};
+    }
+
+    #[test]
+    fn resctrl_group_create_assigns_cpus_and_schemata_and_remove_deletes_group() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_create_dir:
+            { "/sys/fs/resctrl/qemu-launcher-vm" =>
+                Err(Error::new(ErrorKind::AlreadyExists, "std::fs::create_dir()")) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/sys/fs/resctrl/qemu-launcher-vm/cpus_list", "2,3" => Ok({}) },
+            { "/sys/fs/resctrl/qemu-launcher-vm/schemata", "L3:0=ff0;1=ff0" => Ok({}) },
+            { "/sys/fs/resctrl/qemu-launcher-vm/schemata", "MB:0=50" => Ok({}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_remove_dir:
+            { "/sys/fs/resctrl/qemu-launcher-vm" => Ok({}) },
+        );
+
+        let mut group = ResctrlGroup::new("/sys/fs/resctrl");
+
+        assert!(group
+            .create(
+                &super::group_name("vm"),
+                &[2, 3],
+                &[String::from("L3:0=ff0;1=ff0"), String::from("MB:0=50")]
+            )
+            .is_ok());
+        assert!(group.remove().is_ok());
+        assert!(group.remove().is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn resctrl_group_create_reports_kernel_status_of_rejected_schemata() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_create_dir:
+            { "/sys/fs/resctrl/vm" => Ok({}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/sys/fs/resctrl/vm/cpus_list", "4" => Ok({}) },
+            { "/sys/fs/resctrl/vm/schemata", "L3:0=f0f" => error!("std::fs::write()") },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/sys/fs/resctrl/info/last_cmd_status" => Ok(String::from("Mask not contiguous\n")) },
+        );
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to write `L3:0=f0f` into `/sys/fs/resctrl/vm/schemata`: std::fs::write() \
+            (Mask not contiguous)",
+            ResctrlGroup::new("/sys/fs/resctrl").create("vm", &[4], &[String::from("L3:0=f0f")])
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn resctrl_group_create_returns_error_if_filesystem_is_not_mounted() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_create_dir:
+            { "/sys/fs/resctrl/vm" => error!("std::fs::create_dir()") },
+        );
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to create the `vm` resctrl group, is the resctrl filesystem mounted at \
+            `/sys/fs/resctrl`? std::fs::create_dir()",
+            ResctrlGroup::new("/sys/fs/resctrl").create("vm", &[4], &[])
+        );
+
+        verify_expectations();
+    }
+}
@@ -0,0 +1,562 @@
+use json::{object, JsonValue};
+
+// Bumped whenever a configuration valid for the previous schema is no longer accepted
+pub const SCHEMA_VERSION: u32 = 1;
+
+pub fn config_schema() -> JsonValue {
+    object! {
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "qemu-launcher virtual machine configuration",
+        "type": "object",
+        "required": ["launcher", "qemu"],
+        "properties": {
+            "launcher": launcher_schema(),
+            "qemu": {
+                "description": "Qemu command line options, in the order they are passed to qemu.",
+                "type": "array",
+                "items": { "$ref": "#/definitions/option" },
+            },
+        },
+        "definitions": {
+            "cpu_id": { "type": "integer", "minimum": 0 },
+            "id": { "type": "integer", "minimum": 0, "maximum": 65535 },
+            "option": {
+                "oneOf": [
+                    { "description": "A flag, e.g. `enable-kvm`.", "type": "string" },
+                    {
+                        "description": "A parameter with a value, e.g. `m: 4G`.",
+                        "type": "object",
+                        "minProperties": 1,
+                        "maxProperties": 1,
+                        "additionalProperties": { "$ref": "#/definitions/value" },
+                    },
+                ],
+            },
+            "smbios_string": {
+                "type": "string",
+                "minLength": 1,
+                "maxLength": 64,
+                "pattern": "^[ -~]+$",
+            },
+            "properties": {
+                "description": "A list of values joined with commas, hashes are turned into `name=value` pairs.",
+                "type": "array",
+                "minItems": 1,
+                "items": {
+                    "oneOf": [
+                        { "type": ["string", "number"] },
+                        {
+                            "type": "object",
+                            "minProperties": 1,
+                            "maxProperties": 1,
+                            "additionalProperties": { "type": ["string", "number"] },
+                        },
+                    ],
+                },
+            },
+            "vcpu_pinning_level": {
+                "type": "object",
+                "additionalProperties": false,
+                "patternProperties": {
+                    "^[0-9]+$": {
+                        "oneOf": [
+                            { "$ref": "#/definitions/cpu_id" },
+                            { "$ref": "#/definitions/vcpu_pinning_level" },
+                        ],
+                    },
+                },
+            },
+            "value": {
+                "oneOf": [
+                    { "type": ["string", "number"] },
+                    { "$ref": "#/definitions/properties" },
+                ],
+            },
+        },
+    }
+}
+
+fn launcher_schema() -> JsonValue {
+    object! {
+        "type": "object",
+        "required": ["binary"],
+        "properties": {
+            "audio": audio_schema(),
+            "binary": { "type": "string" },
+            "binary_sha256": { "type": "string", "pattern": "^[0-9a-fA-F]{64}$" },
+            "cache_allocation": {
+                "description": "Resctrl cache and memory bandwidth allocation of pinned host CPUs, keyed by cache IDs.",
+                "type": "object",
+                "minProperties": 1,
+                "additionalProperties": false,
+                "properties": {
+                    "l3": {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "patternProperties": {
+                            "^[0-9]+$": { "type": "string", "pattern": "^[0-9a-fA-F]+$" },
+                        },
+                    },
+                    "mb": {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "patternProperties": {
+                            "^[0-9]+$": { "type": "integer", "minimum": 1, "maximum": 100 },
+                        },
+                    },
+                },
+            },
+            "clear_env": { "type": "boolean", "default": false },
+            "clock": {
+                "oneOf": [
+                    { "enum": ["utc", "localtime"] },
+                    {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "properties": {
+                            "base": { "enum": ["utc", "localtime"], "default": "utc" },
+                            "tsc_frequency": { "type": "integer", "minimum": 1 },
+                        },
+                    },
+                ],
+            },
+            "cpuset": {
+                "description": "The cpuset cgroup tree location overriding the environment variables.",
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "mount_path": { "type": "string", "pattern": "^/" },
+                    "prefix": { "type": "string", "pattern": "^[^/]+$" },
+                },
+            },
+            "cwd_relative": { "type": "boolean", "default": false },
+            "deadline": {
+                "description": "SCHED_DEADLINE parameters of vCPU threads, can not be combined with `priority`.",
+                "type": "object",
+                "required": ["runtime_us", "deadline_us", "period_us"],
+                "properties": {
+                    "deadline_us": { "type": "integer", "minimum": 1 },
+                    "period_us": { "type": "integer", "minimum": 1 },
+                    "runtime_us": { "type": "integer", "minimum": 1 },
+                },
+            },
+            "defer_usb_host": { "type": "boolean", "default": false },
+            "depends_on": {
+                "type": "array",
+                "items": { "type": "string", "minLength": 1, "pattern": "^[^/]+$" },
+            },
+            "disable_timer_migration": { "type": "boolean", "default": false },
+            "display": display_schema(),
+            "env": {
+                "type": "object",
+                "additionalProperties": { "type": ["boolean", "number", "string"] },
+            },
+            "env_file": { "type": "string", "minLength": 1 },
+            "env_keep": {
+                "description": "Launcher environment variables passed to qemu when `clear_env` is set.",
+                "type": "array",
+                "items": { "type": "string", "minLength": 1, "pattern": "^[^=]+$" },
+            },
+            "event_sink": {
+                "description": "Where every QMP event is written as a JSON line, `fifo:<path>` or `file:<path>`.",
+                "type": "string",
+                "pattern": "^(fifo|file):.+",
+            },
+            "firewall": firewall_schema(),
+            "fix_permissions": { "type": "boolean", "default": false },
+            "group": { "$ref": "#/definitions/id" },
+            "idle_shutdown": {
+                "description": "Powers the machine down once it stayed paused or below the CPU usage for `after` seconds.",
+                "type": "object",
+                "required": ["after"],
+                "additionalProperties": false,
+                "properties": {
+                    "after": { "type": "integer", "minimum": 1 },
+                    "cpu_percent": { "type": "integer", "minimum": 1, "maximum": 100, "default": 5 },
+                },
+            },
+            "io": {
+                "description": "Open files limit and iothreads for machines with many or large disks.",
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "iothreads": { "type": "boolean", "default": false },
+                    "nofile": {
+                        "oneOf": [
+                            { "enum": ["auto"] },
+                            { "type": "integer", "minimum": 1 },
+                        ],
+                        "default": "auto",
+                    },
+                },
+            },
+            "isolate_workqueues": { "type": "boolean", "default": false },
+            "ksm": { "enum": ["on", "off", true, false] },
+            "kvm": {
+                "description": "Halt polling parameters of the kvm module applied while the machine is running.",
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "halt_poll_ns": { "type": "integer", "minimum": 0, "maximum": u32::MAX },
+                    "halt_poll_ns_grow": { "type": "integer", "minimum": 0, "maximum": u32::MAX },
+                    "halt_poll_ns_grow_start": { "type": "integer", "minimum": 0, "maximum": u32::MAX },
+                    "halt_poll_ns_shrink": { "type": "integer", "minimum": 0, "maximum": u32::MAX },
+                },
+            },
+            "lower_kernel_thread_priority": {
+                "description": "Moves real-time `ksoftirqd/N` and `rcuc/N` threads of the pinned host CPUs to `SCHED_OTHER`.",
+                "type": "boolean",
+                "default": false,
+            },
+            "max_runtime": {
+                "description": "Seconds after which the machine is powered down.",
+                "type": "integer",
+                "minimum": 1,
+            },
+            "merge_qemu_log": { "type": "boolean", "default": false },
+            "netboot": {
+                "description": "Boot files served to the guest, by qemu over `user` interfaces or by the launcher over TFTP and HTTP on `listen`.",
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["directory"],
+                "properties": {
+                    "bootfile": { "type": "string", "minLength": 1, "pattern": "^[^,]+$" },
+                    "directory": { "type": "string", "pattern": "^/[^,]*$" },
+                    "http_port": { "type": "integer", "minimum": 1, "maximum": 65535 },
+                    "listen": { "type": "string", "format": "ipv4" },
+                },
+            },
+            "network": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["netdev"],
+                    "properties": {
+                        "device": {
+                            "oneOf": [{ "type": "string" }, { "$ref": "#/definitions/properties" }],
+                            "default": "virtio-net-pci",
+                        },
+                        "id": { "type": "string" },
+                        "mac": {
+                            "type": "string",
+                            "pattern": "^[0-9A-Fa-f]{2}(:[0-9A-Fa-f]{2}){5}$",
+                        },
+                        "netdev": {
+                            "oneOf": [{ "type": "string" }, { "$ref": "#/definitions/properties" }],
+                        },
+                        "pin_queues": { "type": "boolean", "default": false },
+                    },
+                },
+            },
+            "on_guest_shutdown": { "enum": ["quit", "restart", "ignore"] },
+            "paths": {
+                "description": "Directories and files created with the given ownership and mode before qemu starts.",
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["path"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "group": { "$ref": "#/definitions/id" },
+                        "mode": { "type": "string", "pattern": "^[0-7]{1,4}$" },
+                        "path": { "type": "string", "pattern": "^/" },
+                        "type": { "enum": ["directory", "file"], "default": "directory" },
+                        "user": { "$ref": "#/definitions/id" },
+                    },
+                },
+            },
+            "post_start_hooks": {
+                "description": "Executables run once the machine is started, with `QEMU_PID`, `VM_NAME` and `PINNING_MAP` set.",
+                "type": "array",
+                "items": { "type": "string", "pattern": "^/" },
+            },
+            "priority": { "type": "integer", "minimum": 0, "maximum": 255 },
+            "profile": { "type": "string", "minLength": 1, "pattern": "^[^/]+$" },
+            "qmp_format": { "enum": ["chardev", "qmp"], "default": "qmp" },
+            "qmp_log": { "enum": ["file", "main"] },
+            "qmp_position": { "enum": ["first", "last"], "default": "last" },
+            "qmp_proxy": { "type": "string", "minLength": 1 },
+            "qmp_socket": { "type": "string", "minLength": 1, "pattern": "^[^,]+$" },
+            "qmp_startup_commands": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["execute"],
+                    "properties": { "execute": { "type": "string", "minLength": 1 } },
+                },
+            },
+            "readconfig": { "type": "boolean", "default": false },
+            "rlimit_memlock": { "type": "boolean", "default": false },
+            "scheduler": { "enum": ["batch", "deadline", "fifo", "idle", "other", "rr"] },
+            "serial_log": {
+                "description": "A file the guest serial console is logged into, rotated once it outgrows the maximum size.",
+                "oneOf": [
+                    { "type": "string", "minLength": 1, "pattern": "^[^,]+$" },
+                    {
+                        "type": "object",
+                        "required": ["path"],
+                        "additionalProperties": false,
+                        "properties": {
+                            "keep": { "type": "integer", "minimum": 0, "maximum": 100, "default": 5 },
+                            "max_size_mb": { "type": "integer", "minimum": 1, "default": 10 },
+                            "path": { "type": "string", "minLength": 1, "pattern": "^[^,]+$" },
+                            "socket": { "type": "string", "minLength": 1, "pattern": "^[^,]+$" },
+                        },
+                    },
+                ],
+            },
+            "smbios": {
+                "description": "SMBIOS system information (type 1) presented to the guest.",
+                "type": "object",
+                "minProperties": 1,
+                "additionalProperties": false,
+                "properties": {
+                    "manufacturer": { "$ref": "#/definitions/smbios_string" },
+                    "product": { "$ref": "#/definitions/smbios_string" },
+                    "serial": { "$ref": "#/definitions/smbios_string" },
+                    "uuid": {
+                        "oneOf": [
+                            { "enum": ["auto"] },
+                            {
+                                "type": "string",
+                                "pattern": "^[0-9a-fA-F]{8}(-[0-9a-fA-F]{4}){3}-[0-9a-fA-F]{12}$",
+                            },
+                        ],
+                    },
+                    "version": { "$ref": "#/definitions/smbios_string" },
+                },
+            },
+            "sriov": sriov_schema(),
+            "storage_daemons": {
+                "description": "qemu-storage-daemon instances exporting block nodes to the machine over NBD.",
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["id", "socket", "exports"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "binary": { "type": "string", "minLength": 1, "default": "qemu-storage-daemon" },
+                        "exports": {
+                            "type": "array",
+                            "minItems": 1,
+                            "items": {
+                                "type": "object",
+                                "required": ["node", "blockdev"],
+                                "additionalProperties": false,
+                                "properties": {
+                                    "blockdev": {
+                                        "oneOf": [{ "type": "string" }, { "$ref": "#/definitions/properties" }],
+                                    },
+                                    "node": { "type": "string", "minLength": 1, "pattern": "^[^,=]+$" },
+                                    "writable": { "type": "boolean", "default": true },
+                                },
+                            },
+                        },
+                        "host_cpus": {
+                            "type": "array",
+                            "minItems": 1,
+                            "items": { "$ref": "#/definitions/cpu_id" },
+                        },
+                        "id": { "type": "string", "pattern": "^[A-Za-z0-9_-]+$" },
+                        "priority": { "type": "integer", "minimum": 1, "maximum": 99 },
+                        "scheduler": { "enum": ["batch", "fifo", "idle", "other", "rr"] },
+                        "socket": { "type": "string", "minLength": 1, "pattern": "^[^,]+$" },
+                    },
+                },
+            },
+            "systemd_scope": { "type": "string", "pattern": "^[A-Za-z0-9:_.\\\\-]+\\.scope$", "maxLength": 255 },
+            "thermal_throttle": {
+                "description": "vCPU throttling applied when the host thermal zone reaches the temperatures.",
+                "type": "object",
+                "required": ["zone", "thresholds"],
+                "additionalProperties": false,
+                "properties": {
+                    "thresholds": {
+                        "type": "array",
+                        "minItems": 1,
+                        "items": {
+                            "type": "object",
+                            "required": ["temperature", "throttle"],
+                            "additionalProperties": false,
+                            "properties": {
+                                "temperature": { "type": "integer", "minimum": 1, "maximum": 150 },
+                                "throttle": { "type": "integer", "minimum": 1, "maximum": 100 },
+                            },
+                        },
+                    },
+                    "zone": { "type": "string", "minLength": 1, "pattern": "^[^/]+$" },
+                },
+            },
+            "thp": { "enum": ["always", "madvise", "never"] },
+            "uclamp": {
+                "description": "Utilization clamps in percent of vCPU threads, keyed by pinned host CPU IDs.",
+                "type": "object",
+                "additionalProperties": false,
+                "patternProperties": {
+                    "^[0-9]+$": {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "properties": {
+                            "max": { "type": "integer", "minimum": 0, "maximum": 100 },
+                            "min": { "type": "integer", "minimum": 0, "maximum": 100 },
+                        },
+                    },
+                },
+            },
+            "user": { "$ref": "#/definitions/id" },
+            "vcpu_pinning_by_index": {
+                "description": "vCPU indices (`cpu-index`) mapped to host CPU IDs, can not be combined with `vcpu_pinning`.",
+                "type": "object",
+                "additionalProperties": false,
+                "patternProperties": {
+                    "^[0-9]+$": { "$ref": "#/definitions/cpu_id" },
+                },
+            },
+            "vcpu_pinning": {
+                "description": "vCPU socket, core and thread IDs mapped to host CPU IDs, with die and cluster IDs in between when `-smp` has more than one of them.",
+                "$ref": "#/definitions/vcpu_pinning_level",
+            },
+            "vcpu_pinning_file": {
+                "description": "A host specific YAML file with a `launcher` hash replacing the machine settings, `$(hostname)` is replaced with the host name.",
+                "type": "string",
+                "minLength": 1,
+            },
+            "watchdog": {
+                "description": "A watchdog device, its expiry is handled by the launcher.",
+                "oneOf": [
+                    { "enum": ["i6300esb", "ib700"] },
+                    {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "properties": {
+                            "action": { "enum": ["quit", "restart", "ignore"], "default": "restart" },
+                            "model": { "enum": ["i6300esb", "ib700"], "default": "i6300esb" },
+                        },
+                    },
+                ],
+            },
+        },
+    }
+}
+
+fn audio_schema() -> JsonValue {
+    object! {
+        "oneOf": [
+            { "enum": ["alsa", "pipewire", "pulseaudio"] },
+            {
+                "type": "object",
+                "required": ["backend"],
+                "additionalProperties": false,
+                "properties": {
+                    "backend": { "enum": ["alsa", "pipewire", "pulseaudio"] },
+                    "id": { "type": "string", "minLength": 1, "default": "audio0" },
+                    "runtime_dir": { "type": "string", "pattern": "^/" },
+                    "server": { "type": "string", "minLength": 1 },
+                },
+            },
+        ],
+    }
+}
+
+fn display_schema() -> JsonValue {
+    object! {
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {
+            "env": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "display": { "type": "string", "minLength": 1 },
+                    "wayland_display": { "type": "string", "minLength": 1 },
+                    "xauthority": { "type": "string", "minLength": 1 },
+                },
+            },
+        },
+    }
+}
+
+fn firewall_schema() -> JsonValue {
+    object! {
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["guest_address", "forward"],
+        "properties": {
+            "allow_from": {
+                "type": "array",
+                "items": { "type": "string", "pattern": "^[0-9.]+(/[0-9]{1,2})?$" },
+            },
+            "forward": {
+                "type": "array",
+                "minItems": 1,
+                "items": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "required": ["host_port"],
+                    "properties": {
+                        "guest_port": { "type": "integer", "minimum": 1, "maximum": 65535 },
+                        "host_port": { "type": "integer", "minimum": 1, "maximum": 65535 },
+                        "protocol": { "enum": ["tcp", "udp"], "default": "tcp" },
+                    },
+                },
+            },
+            "guest_address": { "type": "string", "format": "ipv4" },
+        },
+    }
+}
+
+fn sriov_schema() -> JsonValue {
+    object! {
+        "type": "array",
+        "items": {
+            "type": "object",
+            "additionalProperties": false,
+            "required": ["interface", "vfs"],
+            "properties": {
+                "interface": { "type": "string", "minLength": 1, "pattern": "^[^/]+$" },
+                "vfs": {
+                    "oneOf": [
+                        { "type": "integer", "minimum": 1 },
+                        {
+                            "type": "array",
+                            "minItems": 1,
+                            "items": {
+                                "type": "object",
+                                "additionalProperties": false,
+                                "properties": {
+                                    "mac": {
+                                        "type": "string",
+                                        "pattern": "^[0-9A-Fa-f]{2}(:[0-9A-Fa-f]{2}){5}$",
+                                    },
+                                    "vlan": { "type": "integer", "minimum": 1, "maximum": 4094 },
+                                },
+                            },
+                        },
+                    ],
+                },
+            },
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::config_schema;
+
+    #[test]
+    fn config_schema_describes_launcher_and_qemu_sections() {
+        let schema = config_schema();
+
+        assert_eq!(
+            "string",
+            schema["properties"]["launcher"]["properties"]["binary"]["type"]
+        );
+        assert_eq!("binary", schema["properties"]["launcher"]["required"][0]);
+        assert_eq!(
+            "#/definitions/option",
+            schema["properties"]["qemu"]["items"]["$ref"]
+        );
+        assert!(schema["definitions"]["option"]["oneOf"].is_array());
+        assert!(json::parse(&schema.pretty(2)).is_ok());
+    }
+}
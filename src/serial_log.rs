@@ -0,0 +1,197 @@
+#[cfg(not(test))]
+use std::fs;
+use std::io::{Error, ErrorKind};
+#[cfg(test)]
+use test::std::fs;
+
+// Rotates the log once it outgrows the maximum size, `<path>.1` being the most recent one. qemu
+// keeps the log open in the append mode, so it is copied and truncated in place rather than renamed.
+pub fn rotate(path: &str, max_size: u64, keep: u32) -> Result<bool, Error> {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.len() > max_size => {}
+        Ok(_) => return Ok(false),
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => {
+            return Err(Error::new(
+                e.kind(),
+                format!("Failed to check the size of `{}`: {}", path, e),
+            ))
+        }
+    }
+
+    for index in (1..keep).rev() {
+        let (from, to) = (
+            format!("{}.{}", path, index),
+            format!("{}.{}", path, index + 1),
+        );
+
+        match fs::rename(&from, &to) {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(Error::new(
+                    e.kind(),
+                    format!("Failed to rename `{}` to `{}`: {}", from, to, e),
+                ))
+            }
+        }
+    }
+
+    if keep > 0 {
+        let rotated = format!("{}.1", path);
+
+        if let Err(e) = fs::copy(path, &rotated) {
+            return Err(Error::new(
+                e.kind(),
+                format!("Failed to copy `{}` to `{}`: {}", path, rotated, e),
+            ));
+        }
+    }
+
+    // Output written by qemu between the copy and the truncation is lost, which is the price of
+    // rotating a file held open by another process
+    if let Err(e) = fs::write(path, "") {
+        return Err(Error::new(
+            e.kind(),
+            format!("Failed to truncate `{}`: {}", path, e),
+        ));
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::rotate;
+    use crate::{assert_error, expect, vec_deq, verify_expectations};
+    use ::std::{
+        cell::RefCell,
+        collections::VecDeque,
+        io::{Error, ErrorKind},
+    };
+
+    struct TestExpectations {
+        std_fs_copy: VecDeque<((&'static str, &'static str), Result<u64, Error>)>,
+        std_fs_metadata: VecDeque<(&'static str, Result<u64, Error>)>,
+        std_fs_rename: VecDeque<((&'static str, &'static str), Result<(), Error>)>,
+        std_fs_write: VecDeque<((&'static str, &'static str), Result<(), Error>)>,
+    }
+
+    impl TestExpectations {
+        fn new() -> Self {
+            TestExpectations {
+                std_fs_copy: vec_deq![],
+                std_fs_metadata: vec_deq![],
+                std_fs_rename: vec_deq![],
+                std_fs_write: vec_deq![],
+            }
+        }
+    }
+
+    thread_local! { static TEST_EXPECTATIONS: RefCell<TestExpectations> = RefCell::new(TestExpectations::new()) }
+
+    fn verify_expectations() {
+        verify_expectations!(
+            std::fs::copy => TEST_EXPECTATIONS::std_fs_copy,
+            std::fs::metadata => TEST_EXPECTATIONS::std_fs_metadata,
+            std::fs::rename => TEST_EXPECTATIONS::std_fs_rename,
+            std::fs::write => TEST_EXPECTATIONS::std_fs_write,
+        );
+    }
+
+    pub mod std {
+        pub mod fs {
+            use super::super::TEST_EXPECTATIONS;
+            use crate::verify_expectation;
+            use ::std::{io::Result, path::Path, str::from_utf8};
+
+            pub struct Metadata {
+                len: u64,
+            }
+
+            impl Metadata {
+                pub fn len(&self) -> u64 {
+                    self.len
+                }
+            }
+
+            pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<u64> {
+                let from = from.as_ref().to_str().unwrap();
+                let to = to.as_ref().to_str().unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_copy => std::fs::copy { from, to })
+            }
+
+            pub fn metadata<P: AsRef<Path>>(path: P) -> Result<Metadata> {
+                let path = path.as_ref().to_str().unwrap();
+                let len: u64 = verify_expectation!(TEST_EXPECTATIONS::std_fs_metadata => std::fs::metadata { path })?;
+
+                Ok(Metadata { len })
+            }
+
+            pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<()> {
+                let from = from.as_ref().to_str().unwrap();
+                let to = to.as_ref().to_str().unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_rename => std::fs::rename { from, to })
+            }
+
+            pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, data: C) -> Result<()> {
+                let path = path.as_ref().to_str().unwrap();
+                let data = from_utf8(data.as_ref()).unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_write => std::fs::write { path, data })
+            }
+        }
+    }
+
+    #[test]
+    fn rotate_shifts_rotated_logs_and_truncates_the_log_once_it_is_too_big() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_metadata:
+            { "/var/log/serial.log" => Ok(100) },
+            { "/var/log/serial.log" => Ok(101) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_rename:
+            { "/var/log/serial.log.2", "/var/log/serial.log.3" => Err(Error::new(ErrorKind::NotFound, "std::fs::rename()")) },
+            { "/var/log/serial.log.1", "/var/log/serial.log.2" => Ok({}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_copy:
+            { "/var/log/serial.log", "/var/log/serial.log.1" => Ok(101) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/var/log/serial.log", "" => Ok({}) },
+        );
+
+        assert_eq!(false, rotate("/var/log/serial.log", 100, 3).unwrap());
+        assert_eq!(true, rotate("/var/log/serial.log", 100, 3).unwrap());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn rotate_returns_error_if_the_log_can_not_be_copied() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_metadata:
+            { "/var/log/serial.log" => Err(Error::new(ErrorKind::NotFound, "std::fs::metadata()")) },
+            { "/var/log/serial.log" => Ok(101) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_copy:
+            { "/var/log/serial.log", "/var/log/serial.log.1" =>
+                Err(Error::new(ErrorKind::PermissionDenied, "std::fs::copy()")) },
+        );
+
+        assert_eq!(false, rotate("/var/log/serial.log", 100, 1).unwrap());
+        assert_error!(
+            ErrorKind::PermissionDenied,
+            "Failed to copy `/var/log/serial.log` to `/var/log/serial.log.1`: std::fs::copy()",
+            rotate("/var/log/serial.log", 100, 1)
+        );
+
+        verify_expectations();
+    }
+}
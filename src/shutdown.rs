@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+// How long ahead of powering the machine down the warning is given
+pub const WARNING_LEAD: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reason {
+    Idle,
+    MaxRuntime,
+}
+
+impl Reason {
+    // The configuration key the reason originates from
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Reason::Idle => "idle_shutdown",
+            Reason::MaxRuntime => "max_runtime",
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Reason::Idle => "it stayed idle for too long",
+            Reason::MaxRuntime => "its maximum runtime is reached",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    None,
+    Warn(Reason, Duration),
+    Enforce(Reason),
+}
+
+// Tracks the time budget of the machine, the warning is given once per reason, unless the machine
+// becomes busy again, and the shutdown is only enforced once
+pub struct ShutdownSchedule {
+    enforced: bool,
+    idle_after: Option<Duration>,
+    idle_for: Duration,
+    max_runtime: Option<Duration>,
+    warned: Option<Reason>,
+}
+
+impl ShutdownSchedule {
+    pub fn new(max_runtime: Option<Duration>, idle_after: Option<Duration>) -> Self {
+        ShutdownSchedule {
+            enforced: false,
+            idle_after,
+            idle_for: Duration::from_secs(0),
+            max_runtime,
+            warned: None,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.enforced && (self.max_runtime.is_some() || self.idle_after.is_some())
+    }
+
+    // Takes the time the machine has been running for and, when a new usage sample is available,
+    // whether the machine was idle over the period the sample covers
+    pub fn update(&mut self, runtime: Duration, idle: Option<(bool, Duration)>) -> Action {
+        if !self.is_enabled() {
+            return Action::None;
+        }
+
+        match idle {
+            Some((true, period)) => self.idle_for += period,
+            Some((false, _)) => {
+                self.idle_for = Duration::from_secs(0);
+                if self.warned == Some(Reason::Idle) {
+                    self.warned = None;
+                }
+            }
+            None => {}
+        }
+
+        let remaining = [
+            (
+                Reason::MaxRuntime,
+                self.max_runtime.map(|max| max.saturating_sub(runtime)),
+            ),
+            (
+                Reason::Idle,
+                self.idle_after
+                    .map(|after| after.saturating_sub(self.idle_for)),
+            ),
+        ]
+        .iter()
+        .filter_map(|(reason, remaining)| remaining.map(|remaining| (*reason, remaining)))
+        .min_by_key(|(_, remaining)| *remaining);
+
+        match remaining {
+            Some((reason, remaining)) if remaining == Duration::from_secs(0) => {
+                self.enforced = true;
+                Action::Enforce(reason)
+            }
+            Some((reason, remaining))
+                if remaining <= WARNING_LEAD && self.warned != Some(reason) =>
+            {
+                self.warned = Some(reason);
+                Action::Warn(reason, remaining)
+            }
+            _ => Action::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Action, Reason, ShutdownSchedule};
+    use std::time::Duration;
+
+    fn seconds(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn update_warns_ahead_of_the_maximum_runtime_and_enforces_it_once() {
+        let mut schedule = ShutdownSchedule::new(Some(seconds(3600)), None);
+
+        assert_eq!(Action::None, schedule.update(seconds(3539), None));
+        assert_eq!(
+            Action::Warn(Reason::MaxRuntime, seconds(60)),
+            schedule.update(seconds(3540), Some((true, seconds(10))))
+        );
+        assert_eq!(Action::None, schedule.update(seconds(3541), None));
+        assert_eq!(
+            Action::Enforce(Reason::MaxRuntime),
+            schedule.update(seconds(3600), None)
+        );
+        assert_eq!(Action::None, schedule.update(seconds(3601), None));
+        assert_eq!(false, schedule.is_enabled());
+
+        assert_eq!(false, ShutdownSchedule::new(None, None).is_enabled());
+    }
+
+    #[test]
+    fn update_restarts_the_idle_budget_once_the_machine_is_busy() {
+        let mut schedule = ShutdownSchedule::new(Some(seconds(3600)), Some(seconds(120)));
+
+        assert_eq!(
+            Action::None,
+            schedule.update(seconds(10), Some((true, seconds(50))))
+        );
+        assert_eq!(
+            Action::Warn(Reason::Idle, seconds(60)),
+            schedule.update(seconds(20), Some((true, seconds(10))))
+        );
+        assert_eq!(
+            Action::None,
+            schedule.update(seconds(30), Some((false, seconds(10))))
+        );
+        assert_eq!(
+            Action::None,
+            schedule.update(seconds(40), Some((true, seconds(50))))
+        );
+        assert_eq!(
+            Action::Warn(Reason::Idle, seconds(20)),
+            schedule.update(seconds(50), Some((true, seconds(50))))
+        );
+        assert_eq!(Action::None, schedule.update(seconds(55), None));
+        assert_eq!(
+            Action::Enforce(Reason::Idle),
+            schedule.update(seconds(60), Some((true, seconds(20))))
+        );
+    }
+}
@@ -0,0 +1,474 @@
+#[cfg(not(test))]
+use crate::process::Process;
+use crate::{
+    clock::{self, Clock},
+    config::SriovInterface,
+};
+#[cfg(not(test))]
+use std::fs;
+use std::{
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+#[cfg(test)]
+use test::{process::Process, std::fs};
+
+const SYSFS_NET_PATH: &str = "/sys/class/net";
+const SYSFS_PCI_DEVICES_PATH: &str = "/sys/bus/pci/devices";
+const PCI_DRIVERS_PROBE_PATH: &str = "/sys/bus/pci/drivers_probe";
+const VFIO_PCI_DRIVER: &str = "vfio-pci";
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct Sriov {
+    clock: Arc<dyn Clock>,
+    saved: Vec<(PathBuf, String)>,
+    timeout: Duration,
+}
+
+impl Sriov {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            clock: clock::system(),
+            saved: vec![],
+            timeout,
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+
+        self
+    }
+
+    pub fn create_virtual_functions(
+        &mut self,
+        interface: &SriovInterface,
+    ) -> Result<Vec<String>, Error> {
+        let name = interface.get_interface();
+        let vfs = interface.get_virtual_functions();
+        let numvfs_path = format!("{}/{}/device/sriov_numvfs", SYSFS_NET_PATH, name);
+        let previous = read_value(&numvfs_path)?;
+
+        // The kernel refuses to change the number of existing virtual functions directly
+        if previous != "0" {
+            write_value(&numvfs_path, "0")?;
+            self.saved.push((PathBuf::from(&numvfs_path), previous));
+            write_value(&numvfs_path, vfs.len().to_string())?;
+        } else {
+            write_value(&numvfs_path, vfs.len().to_string())?;
+            self.saved.push((PathBuf::from(&numvfs_path), previous));
+        }
+
+        for (index, vf) in vfs.iter().enumerate() {
+            let mut arguments = vec![
+                String::from("link"),
+                String::from("set"),
+                String::from("dev"),
+                name.to_owned(),
+                String::from("vf"),
+                index.to_string(),
+            ];
+
+            if let Some(mac) = vf.get_mac() {
+                arguments.push(String::from("mac"));
+                arguments.push(mac.to_owned());
+            }
+
+            if let Some(vlan) = vf.get_vlan() {
+                arguments.push(String::from("vlan"));
+                arguments.push(vlan.to_string());
+            }
+
+            if arguments.len() > 6 {
+                if let Err(e) = Process::oneshot("ip", &arguments) {
+                    return Err(Error::new(
+                        e.kind(),
+                        format!(
+                            "Failed to configure the virtual function `{}` of `{}`: {}",
+                            index, name, e
+                        ),
+                    ));
+                }
+            }
+        }
+
+        let mut addresses = vec![];
+
+        for index in 0..vfs.len() {
+            let link = format!("{}/{}/device/virtfn{}", SYSFS_NET_PATH, name, index);
+
+            match self.wait_for(|| link_name(&link)) {
+                Some(address) => addresses.push(address),
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::TimedOut,
+                        format!(
+                            "The virtual function `{}` of `{}` did not appear in {} seconds.",
+                            index,
+                            name,
+                            self.timeout.as_secs()
+                        ),
+                    ))
+                }
+            }
+        }
+
+        for address in &addresses {
+            self.bind_to_vfio(address)?;
+        }
+
+        Ok(addresses)
+    }
+
+    fn bind_to_vfio(&self, address: &str) -> Result<(), Error> {
+        let device_path = format!("{}/{}", SYSFS_PCI_DEVICES_PATH, address);
+        let driver_path = format!("{}/driver", device_path);
+
+        write_value(format!("{}/driver_override", device_path), VFIO_PCI_DRIVER)?;
+
+        match link_name(&driver_path) {
+            Some(driver) if driver == VFIO_PCI_DRIVER => return Ok({}),
+            Some(_) => write_value(format!("{}/unbind", driver_path), address)?,
+            None => {}
+        }
+
+        write_value(PCI_DRIVERS_PROBE_PATH, address)?;
+
+        match self.wait_for(|| link_name(&driver_path).filter(|d| d == VFIO_PCI_DRIVER)) {
+            Some(_) => Ok({}),
+            None => Err(Error::new(
+                ErrorKind::TimedOut,
+                format!(
+                    "The virtual function `{}` was not bound to the `{}` driver in {} seconds.",
+                    address,
+                    VFIO_PCI_DRIVER,
+                    self.timeout.as_secs()
+                ),
+            )),
+        }
+    }
+
+    fn wait_for<T, F: FnMut() -> Option<T>>(&self, mut probe: F) -> Option<T> {
+        let deadline = self.clock.now() + self.timeout;
+
+        loop {
+            if let Some(value) = probe() {
+                return Some(value);
+            }
+
+            if self.clock.now() >= deadline {
+                return None;
+            }
+
+            self.clock.sleep(POLL_INTERVAL);
+        }
+    }
+
+    pub fn restore(&mut self) -> Result<(), Error> {
+        let mut failed = vec![];
+
+        while let Some((path, value)) = self.saved.pop() {
+            if let Err(e) = fs::write(&path, "0") {
+                failed.push(format!("`{}` ({})", path.display(), e));
+                continue;
+            }
+
+            if value != "0" {
+                if let Err(e) = fs::write(&path, &value) {
+                    failed.push(format!("`{}` ({})", path.display(), e));
+                }
+            }
+        }
+
+        if failed.len() > 0 {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "Failed to restore the number of virtual functions: {}.",
+                    failed.join(", ")
+                ),
+            ));
+        }
+
+        Ok({})
+    }
+}
+
+fn read_value<P: AsRef<Path>>(path: P) -> Result<String, Error> {
+    match fs::read_to_string(path.as_ref()) {
+        Ok(value) => Ok(value.trim().to_owned()),
+        Err(e) => Err(Error::new(
+            e.kind(),
+            format!("Failed to read `{}`: {}", path.as_ref().display(), e),
+        )),
+    }
+}
+
+fn write_value<P: AsRef<Path>, V: AsRef<str>>(path: P, value: V) -> Result<(), Error> {
+    match fs::write(path.as_ref(), value.as_ref()) {
+        Ok(_) => Ok({}),
+        Err(e) => Err(Error::new(
+            e.kind(),
+            format!(
+                "Failed to write `{}` into `{}`: {}",
+                value.as_ref(),
+                path.as_ref().display(),
+                e
+            ),
+        )),
+    }
+}
+
+fn link_name<P: AsRef<Path>>(path: P) -> Option<String> {
+    fs::read_link(path.as_ref())
+        .ok()
+        .and_then(|target| target.file_name().map(|n| n.to_string_lossy().to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::Sriov;
+    use crate::{
+        assert_error, clock::fake::FakeClock, config::Config, expect, vec_deq, verify_expectations,
+    };
+    use ::std::{
+        cell::RefCell,
+        collections::VecDeque,
+        io::{Error, ErrorKind},
+        path::PathBuf,
+        time::Duration,
+    };
+
+    struct TestExpectations {
+        process_oneshot: VecDeque<(&'static str, Result<(), Error>)>,
+        std_fs_read_link: VecDeque<(&'static str, Result<PathBuf, Error>)>,
+        std_fs_read_to_string: VecDeque<(&'static str, Result<String, Error>)>,
+        std_fs_write: VecDeque<((&'static str, &'static str), Result<(), Error>)>,
+    }
+
+    impl TestExpectations {
+        fn new() -> Self {
+            TestExpectations {
+                process_oneshot: vec_deq![],
+                std_fs_read_link: vec_deq![],
+                std_fs_read_to_string: vec_deq![],
+                std_fs_write: vec_deq![],
+            }
+        }
+    }
+
+    thread_local! { static TEST_EXPECTATIONS: RefCell<TestExpectations> = RefCell::new(TestExpectations::new()) }
+
+    fn verify_expectations() {
+        verify_expectations!(
+            crate::process::Process::oneshot => TEST_EXPECTATIONS::process_oneshot,
+            std::fs::read_link => TEST_EXPECTATIONS::std_fs_read_link,
+            std::fs::read_to_string => TEST_EXPECTATIONS::std_fs_read_to_string,
+            std::fs::write => TEST_EXPECTATIONS::std_fs_write,
+        );
+    }
+
+    pub mod process {
+        use super::TEST_EXPECTATIONS;
+        use crate::verify_expectation;
+        use ::std::{ffi::OsStr, io::Result};
+
+        pub struct Process {}
+
+        impl Process {
+            pub fn oneshot<C: AsRef<OsStr>, I: IntoIterator<Item = S>, S: AsRef<OsStr>>(
+                command: C,
+                arguments: I,
+            ) -> Result<()> {
+                let mut command = command.as_ref().to_string_lossy().to_string();
+                for argument in arguments {
+                    command.push(' ');
+                    command.push_str(&argument.as_ref().to_string_lossy());
+                }
+
+                verify_expectation!(TEST_EXPECTATIONS::process_oneshot => crate::process::Process::oneshot { command })
+            }
+        }
+    }
+
+    pub mod std {
+        pub mod fs {
+            use super::super::TEST_EXPECTATIONS;
+            use crate::verify_expectation;
+            use ::std::{
+                io::Result,
+                path::{Path, PathBuf},
+                str::from_utf8,
+            };
+
+            pub fn read_link<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
+                let path = path.as_ref().to_str().unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_read_link => std::fs::read_link { path })
+            }
+
+            pub fn read_to_string<P: AsRef<Path>>(path: P) -> Result<String> {
+                let path = path.as_ref().to_str().unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_read_to_string => std::fs::read_to_string { path })
+            }
+
+            pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, data: C) -> Result<()> {
+                let path = path.as_ref().to_str().unwrap();
+                let data = from_utf8(data.as_ref()).unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_write => std::fs::write { path, data })
+            }
+        }
+    }
+
+    macro_rules! error {
+        ($msg:expr) => {{
+            Err(Error::new(ErrorKind::Other, format!("{}", $msg)))
+        }};
+    }
+
+    fn config(sriov: &str) -> Config {
+        Config::new(format!(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              sriov:
+{}
+
+            qemu:
+            - enable-kvm
+            ",
+            sriov
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn sriov_creates_configures_and_binds_virtual_functions_then_restores_their_count() {
+        let config = config(
+            "
+              - interface: enp3s0f0
+                vfs:
+                - mac: 52:54:00:ab:cd:ef
+                  vlan: 100
+                - {}
+            ",
+        );
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/sys/class/net/enp3s0f0/device/sriov_numvfs" => Ok("1\n".to_string()) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::process_oneshot:
+            { "ip link set dev enp3s0f0 vf 0 mac 52:54:00:ab:cd:ef vlan 100" => Ok({}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_link:
+            { "/sys/class/net/enp3s0f0/device/virtfn0" => Ok(PathBuf::from("../0000:03:02.0")) },
+            { "/sys/class/net/enp3s0f0/device/virtfn1" => error!("std::fs::read_link()") },
+            { "/sys/class/net/enp3s0f0/device/virtfn1" => Ok(PathBuf::from("../0000:03:02.1")) },
+            { "/sys/bus/pci/devices/0000:03:02.0/driver" => Ok(PathBuf::from("../../../bus/pci/drivers/iavf")) },
+            { "/sys/bus/pci/devices/0000:03:02.0/driver" => Ok(PathBuf::from("../../../bus/pci/drivers/vfio-pci")) },
+            { "/sys/bus/pci/devices/0000:03:02.1/driver" => Ok(PathBuf::from("../../../bus/pci/drivers/vfio-pci")) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/sys/class/net/enp3s0f0/device/sriov_numvfs", "0" => Ok({}) },
+            { "/sys/class/net/enp3s0f0/device/sriov_numvfs", "2" => Ok({}) },
+            { "/sys/bus/pci/devices/0000:03:02.0/driver_override", "vfio-pci" => Ok({}) },
+            { "/sys/bus/pci/devices/0000:03:02.0/driver/unbind", "0000:03:02.0" => Ok({}) },
+            { "/sys/bus/pci/drivers_probe", "0000:03:02.0" => Ok({}) },
+            { "/sys/bus/pci/devices/0000:03:02.1/driver_override", "vfio-pci" => Ok({}) },
+            { "/sys/class/net/enp3s0f0/device/sriov_numvfs", "0" => Ok({}) },
+            { "/sys/class/net/enp3s0f0/device/sriov_numvfs", "1" => Ok({}) },
+        );
+
+        let clock = FakeClock::new();
+        let mut sriov = Sriov::new(Duration::from_secs(1)).with_clock(clock.clone());
+
+        assert_eq!(
+            vec!["0000:03:02.0", "0000:03:02.1"],
+            sriov
+                .create_virtual_functions(&config.get_sriov_interfaces()[0])
+                .unwrap()
+        );
+        assert!(sriov.restore().is_ok());
+        assert_eq!(vec![Duration::from_millis(100)], clock.get_sleeps());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn sriov_returns_error_if_virtual_function_does_not_appear() {
+        let config = config(
+            "
+              - interface: enp3s0f0
+                vfs: 1
+            ",
+        );
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/sys/class/net/enp3s0f0/device/sriov_numvfs" => Ok("0\n".to_string()) },
+        );
+        // Polled every 100ms for a second, including both ends
+        for _ in 0..11 {
+            expect!(
+                TEST_EXPECTATIONS::std_fs_read_link:
+                { "/sys/class/net/enp3s0f0/device/virtfn0" => error!("std::fs::read_link()") },
+            );
+        }
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/sys/class/net/enp3s0f0/device/sriov_numvfs", "1" => Ok({}) },
+            { "/sys/class/net/enp3s0f0/device/sriov_numvfs", "0" => Ok({}) },
+        );
+
+        let clock = FakeClock::new();
+        let mut sriov = Sriov::new(Duration::from_secs(1)).with_clock(clock.clone());
+
+        assert_error!(
+            ErrorKind::TimedOut,
+            "The virtual function `0` of `enp3s0f0` did not appear in 1 seconds.",
+            sriov.create_virtual_functions(&config.get_sriov_interfaces()[0])
+        );
+        assert!(sriov.restore().is_ok());
+        assert_eq!(vec![Duration::from_millis(100); 10], clock.get_sleeps());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn sriov_returns_error_if_unable_to_change_number_of_virtual_functions() {
+        let config = config(
+            "
+              - interface: enp3s0f0
+                vfs: 1
+            ",
+        );
+
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/sys/class/net/enp3s0f0/device/sriov_numvfs" => Ok("0\n".to_string()) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/sys/class/net/enp3s0f0/device/sriov_numvfs", "1" => error!("std::fs::write()") },
+        );
+
+        let mut sriov = Sriov::new(Duration::from_secs(0));
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to write `1` into `/sys/class/net/enp3s0f0/device/sriov_numvfs`: std::fs::write()",
+            sriov.create_virtual_functions(&config.get_sriov_interfaces()[0])
+        );
+        assert!(sriov.restore().is_ok());
+
+        verify_expectations();
+    }
+}
@@ -0,0 +1,1269 @@
+use crate::{config::Config, output};
+use json::{object, JsonValue};
+use nix::{
+    errno::Errno,
+    fcntl::OFlag,
+    sys::stat::Mode,
+    unistd::{Gid, Uid},
+};
+use std::{
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+#[cfg(test)]
+use test::{
+    nix::{
+        fcntl::open,
+        unistd::{chown, close},
+    },
+    std::fs,
+};
+#[cfg(not(test))]
+use {
+    nix::{
+        fcntl::open,
+        unistd::{chown, close},
+    },
+    std::{fs, os::unix::fs::FileTypeExt},
+};
+
+// Records of older versions are migrated when read and fields unknown to this version are left
+// alone, so machines started by another version of the launcher are still recognized after an upgrade.
+// The record only covers what is fixed at launch: the firewall table and readiness change while the
+// machine runs, the throttle request is written by other users and the helpers are reaped by the
+// launcher itself, so those keep their own files.
+const LAUNCH_RECORD_VERSION: u32 = 1;
+
+pub struct StateDirectory {
+    path: PathBuf,
+}
+
+impl StateDirectory {
+    pub fn new<P: AsRef<Path>, N: AsRef<str>>(runtime_directory: P, machine_name: N) -> Self {
+        let mut path = PathBuf::from(runtime_directory.as_ref());
+        path.push(machine_name.as_ref());
+
+        StateDirectory { path }
+    }
+
+    pub fn write_pid(&self, pid: u32) -> Result<(), Error> {
+        let path = self.path.join("qemu.pid");
+
+        if let Err(e) = fs::write(&path, format!("{}\n", pid)) {
+            return Err(Error::new(
+                e.kind(),
+                format!("Failed to write the pid file `{}`: {}", path.display(), e),
+            ));
+        }
+
+        Ok({})
+    }
+
+    pub fn read_pid(&self) -> Result<i32, Error> {
+        let path = self.path.join("qemu.pid");
+
+        let pid = match fs::read_to_string(&path) {
+            Ok(pid) => pid,
+            Err(e) => {
+                return Err(Error::new(
+                    e.kind(),
+                    format!("Failed to read the pid file `{}`: {}", path.display(), e),
+                ))
+            }
+        };
+
+        match pid.trim().parse::<i32>() {
+            Ok(pid) if pid > 0 => Ok(pid),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "The pid file `{}` contains an invalid process ID: `{}`",
+                    path.display(),
+                    pid.trim()
+                ),
+            )),
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        fs::read_to_string(self.path.join("ready")).is_ok()
+    }
+
+    pub fn mark_ready(&self) -> Result<(), Error> {
+        let path = self.path.join("ready");
+
+        if let Err(e) = fs::write(&path, "") {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to write the readiness marker `{}`: {}",
+                    path.display(),
+                    e
+                ),
+            ));
+        }
+
+        Ok({})
+    }
+
+    pub fn clear_ready(&self) -> Result<(), Error> {
+        let path = self.path.join("ready");
+
+        match fs::remove_file(&path) {
+            Ok(_) => Ok({}),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok({}),
+            Err(e) => Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to remove the readiness marker `{}`: {}",
+                    path.display(),
+                    e
+                ),
+            )),
+        }
+    }
+
+    pub fn write_firewall_table(&self, table: &str) -> Result<(), Error> {
+        let path = self.path.join("firewall");
+
+        if let Err(e) = fs::write(&path, format!("{}\n", table)) {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to record the firewall table in `{}`: {}",
+                    path.display(),
+                    e
+                ),
+            ));
+        }
+
+        Ok({})
+    }
+
+    pub fn read_firewall_table(&self) -> Option<String> {
+        fs::read_to_string(self.path.join("firewall"))
+            .ok()
+            .map(|table| table.trim().to_owned())
+            .filter(|table| table.len() > 0)
+    }
+
+    pub fn clear_firewall_table(&self) -> Result<(), Error> {
+        let path = self.path.join("firewall");
+
+        match fs::remove_file(&path) {
+            Ok(_) => Ok({}),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok({}),
+            Err(e) => Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to remove the firewall table record `{}`: {}",
+                    path.display(),
+                    e
+                ),
+            )),
+        }
+    }
+
+    // The throttle requested with the `throttle` command, picked up by the supervising launcher
+    pub fn write_throttle(&self, percent: u8) -> Result<(), Error> {
+        let path = self.path.join("throttle");
+
+        if let Err(e) = fs::write(&path, format!("{}\n", percent)) {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to record the throttle request in `{}`: {}",
+                    path.display(),
+                    e
+                ),
+            ));
+        }
+
+        Ok({})
+    }
+
+    pub fn read_throttle(&self) -> Option<u8> {
+        fs::read_to_string(self.path.join("throttle"))
+            .ok()
+            .and_then(|percent| percent.trim().parse::<u8>().ok())
+            .filter(|percent| *percent <= 100)
+    }
+
+    pub fn clear_throttle(&self) -> Result<(), Error> {
+        let path = self.path.join("throttle");
+
+        match fs::remove_file(&path) {
+            Ok(_) => Ok({}),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok({}),
+            Err(e) => Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to remove the throttle request `{}`: {}",
+                    path.display(),
+                    e
+                ),
+            )),
+        }
+    }
+
+    pub fn set_owner(&self, uid: Option<u16>, gid: Option<u16>) -> Result<(), Error> {
+        if let Err(e) = chown(
+            &self.path,
+            uid.map(|uid| Uid::from_raw(uid.into())),
+            gid.map(|gid| Gid::from_raw(gid.into())),
+        ) {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "Failed to change the owner of the state directory `{}`: {}",
+                    self.path.display(),
+                    e
+                ),
+            ));
+        }
+
+        Ok({})
+    }
+
+    // Hands the launcher written files over to another user, the directory itself is only included
+    // when qemu does not need to write into it
+    pub fn hand_over(&self, uid: u32, gid: Option<u32>, with_directory: bool) -> Result<(), Error> {
+        let mut paths = vec![];
+        if with_directory {
+            paths.push(self.path.clone());
+        }
+        for name in &["firewall", "launch.json", "qemu.pid", "ready"] {
+            paths.push(self.path.join(name));
+        }
+
+        for path in paths {
+            match chown(
+                &path,
+                Some(Uid::from_raw(uid)),
+                gid.map(|gid| Gid::from_raw(gid)),
+            ) {
+                Ok(_) | Err(::nix::Error::Sys(Errno::ENOENT)) => {}
+                Err(e) => {
+                    return Err(Error::new(
+                        ErrorKind::Other,
+                        format!("Failed to change the owner of `{}`: {}", path.display(), e),
+                    ))
+                }
+            }
+        }
+
+        Ok({})
+    }
+
+    pub fn read_pinned_host_cpus(&self) -> Vec<usize> {
+        let record = match self.read_launch_record() {
+            Some(record) => record,
+            None => return vec![],
+        };
+
+        record["vcpu_pinning"]
+            .members()
+            .filter_map(|pin| pin["host_cpu"].as_usize())
+            .collect()
+    }
+
+    pub fn read_sockets(&self) -> Vec<String> {
+        let record = match self.read_launch_record() {
+            Some(record) => record,
+            None => return vec![],
+        };
+
+        record["sockets"]
+            .members()
+            .filter_map(|socket| socket.as_str())
+            .map(|socket| socket.to_owned())
+            .collect()
+    }
+
+    // qemu and its helpers leave their sockets behind when they are killed. The record is handed over
+    // to other users, who can rewrite it, so only the paths which still are sockets are removed.
+    pub fn remove_sockets(&self) -> Result<(), Error> {
+        for socket in self.read_sockets() {
+            match fs::symlink_metadata(&socket) {
+                Ok(metadata) if metadata.file_type().is_socket() => {}
+                Ok(_) => {
+                    output::warning(format!(
+                        "The `{}` path of the launch record is not a socket, leaving it alone.",
+                        socket
+                    ));
+                    continue;
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(Error::new(
+                        e.kind(),
+                        format!("Failed to inspect the socket `{}`: {}", socket, e),
+                    ))
+                }
+            }
+
+            match fs::remove_file(&socket) {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(Error::new(
+                        e.kind(),
+                        format!("Failed to remove the socket `{}`: {}", socket, e),
+                    ))
+                }
+            }
+        }
+
+        Ok({})
+    }
+
+    fn read_launch_record(&self) -> Option<JsonValue> {
+        self.load_launch_record().ok()
+    }
+
+    pub fn load_launch_record(&self) -> Result<JsonValue, Error> {
+        let path = self.path.join("launch.json");
+        let record = match fs::read_to_string(&path) {
+            Ok(record) => record,
+            Err(e) => {
+                return Err(Error::new(
+                    e.kind(),
+                    format!(
+                        "Failed to read the launch record `{}`: {}",
+                        path.display(),
+                        e
+                    ),
+                ))
+            }
+        };
+
+        match json::parse(&record) {
+            Ok(record) if record.is_object() => Ok(migrate_launch_record(record)),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "The launch record `{}` is not a JSON object.",
+                    path.display()
+                ),
+            )),
+        }
+    }
+
+    pub fn write_launch_record(&self, config: &Config) -> Result<(), Error> {
+        if let Err(e) = fs::create_dir_all(&self.path) {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to create the state directory `{}`: {}",
+                    self.path.display(),
+                    e
+                ),
+            ));
+        }
+
+        let path = self.path.join("launch.json");
+
+        if let Err(e) = create_private_file(&path) {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to create the launch record `{}`: {}",
+                    path.display(),
+                    e
+                ),
+            ));
+        }
+
+        if let Err(e) = fs::write(&path, launch_record(config).pretty(2)) {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to write the launch record `{}`: {}",
+                    path.display(),
+                    e
+                ),
+            ));
+        }
+
+        Ok({})
+    }
+
+    pub fn get_readconfig_path(&self) -> PathBuf {
+        self.path.join("qemu.cfg")
+    }
+
+    pub fn write_readconfig(&self, contents: &str) -> Result<(), Error> {
+        if let Err(e) = fs::create_dir_all(&self.path) {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to create the state directory `{}`: {}",
+                    self.path.display(),
+                    e
+                ),
+            ));
+        }
+
+        let path = self.get_readconfig_path();
+
+        if let Err(e) = fs::write(&path, contents) {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to write the qemu configuration file `{}`: {}",
+                    path.display(),
+                    e
+                ),
+            ));
+        }
+
+        Ok({})
+    }
+}
+
+// A file left behind is replaced rather than reused, it may have been opened by anybody while its
+// mode allowed it, so only the owner can ever read what gets written into the new one
+fn create_private_file(path: &Path) -> Result<(), Error> {
+    match fs::remove_file(path) {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    let flags = OFlag::O_CREAT | OFlag::O_EXCL | OFlag::O_WRONLY | OFlag::O_CLOEXEC;
+    let fd = match open(path, flags, Mode::S_IRUSR | Mode::S_IWUSR) {
+        Ok(fd) => fd,
+        Err(e) => return Err(Error::new(ErrorKind::Other, e.to_string())),
+    };
+
+    match close(fd) {
+        Ok(_) => Ok({}),
+        Err(e) => Err(Error::new(ErrorKind::Other, e.to_string())),
+    }
+}
+
+// Brings a record written by an older launcher up to the current version, a record of a newer version
+// is returned as is, its fields known to this version keep their meaning
+fn migrate_launch_record(mut record: JsonValue) -> JsonValue {
+    // Records written before versioning did not list the sockets
+    if record["version"].is_null() {
+        record["version"] = 1.into();
+        record["sockets"] = JsonValue::new_array();
+    }
+
+    if let Some(version) = record["version"].as_u32() {
+        if version > LAUNCH_RECORD_VERSION {
+            output::warning(format!(
+                "The launch record of version {} was written by a newer launcher, only the fields of version {} are used.",
+                version, LAUNCH_RECORD_VERSION
+            ));
+        }
+    }
+
+    record
+}
+
+fn launch_record(config: &Config) -> JsonValue {
+    let mut argv = JsonValue::new_array();
+    argv.push(config.get_qemu_binary_path().as_str()).unwrap();
+    for argument in config.get_command_line_options() {
+        argv.push(argument).unwrap();
+    }
+
+    // The record is handed over to other users, the secrets of `launcher.env_file` stay masked
+    let mut env = JsonValue::new_object();
+    for (name, value) in config.get_masked_env_vars() {
+        env[name] = value.into();
+    }
+
+    let mut pinning = JsonValue::new_array();
+    for pin in config.get_cpu_pinning() {
+        let mut entry = object! { "socket": pin.get_socket() };
+        if let Some(die) = pin.get_die() {
+            entry["die"] = die.into();
+        }
+        if let Some(cluster) = pin.get_cluster() {
+            entry["cluster"] = cluster.into();
+        }
+        entry["core"] = pin.get_core().into();
+        entry["thread"] = pin.get_thread().into();
+        entry["host_cpu"] = pin.get_host_cpu().into();
+
+        pinning.push(entry).unwrap();
+    }
+    for pin in config.get_cpu_pinning_by_index() {
+        pinning
+            .push(object! {
+                "index": pin.0,
+                "host_cpu": pin.1,
+            })
+            .unwrap();
+    }
+
+    let mut sockets = JsonValue::new_array();
+    for socket in config
+        .get_qmp_socket()
+        .into_iter()
+        .chain(config.get_qmp_proxy())
+    {
+        sockets.push(socket).unwrap();
+    }
+    for daemon in config.get_storage_daemons() {
+        sockets
+            .push(config.get_storage_daemon_socket(daemon))
+            .unwrap();
+    }
+
+    object! {
+        "version": LAUNCH_RECORD_VERSION,
+        "machine": config.get_machine_name(),
+        "argv": argv,
+        "clear_env": config.should_clear_env(),
+        "env": env,
+        "uid": config.get_user(),
+        "gid": config.get_group(),
+        "rlimits": {
+            "memlock": if config.rlimit_memlock() { "unlimited" } else { "inherited" },
+            "nofile": match config.get_open_files_limit() {
+                Some(limit) => limit.into(),
+                None => JsonValue::from("inherited"),
+            },
+        },
+        "vcpu_pinning": pinning,
+        "scheduler": config.get_scheduler().clone(),
+        "priority": config.get_priority(),
+        "deadline": config.get_deadline().map(|deadline| object! {
+            "runtime_us": deadline.get_runtime_us(),
+            "deadline_us": deadline.get_deadline_us(),
+            "period_us": deadline.get_period_us(),
+        }),
+        "sockets": sockets,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StateDirectory;
+    use crate::{assert_error, config::Config, expect, vec_deq, verify_expectations};
+    use ::nix::errno::Errno;
+    use ::std::{
+        cell::RefCell,
+        collections::VecDeque,
+        io::{Error, ErrorKind},
+    };
+    use json::object;
+
+    struct TestExpectations {
+        nix_fcntl_open: VecDeque<((&'static str, i32, u32), ::nix::Result<i32>)>,
+        nix_unistd_chown: VecDeque<((&'static str, Option<u32>, Option<u32>), ::nix::Result<()>)>,
+        nix_unistd_close: VecDeque<(i32, ::nix::Result<()>)>,
+        std_fs_create_dir_all: VecDeque<(&'static str, Result<(), Error>)>,
+        std_fs_read_to_string: VecDeque<(&'static str, Result<String, Error>)>,
+        std_fs_remove_file: VecDeque<(&'static str, Result<(), Error>)>,
+        std_fs_symlink_metadata: VecDeque<(&'static str, Result<bool, Error>)>,
+        std_fs_write: VecDeque<((&'static str, &'static str), Result<(), Error>)>,
+    }
+
+    impl TestExpectations {
+        fn new() -> Self {
+            TestExpectations {
+                nix_fcntl_open: vec_deq![],
+                nix_unistd_chown: vec_deq![],
+                nix_unistd_close: vec_deq![],
+                std_fs_create_dir_all: vec_deq![],
+                std_fs_read_to_string: vec_deq![],
+                std_fs_remove_file: vec_deq![],
+                std_fs_symlink_metadata: vec_deq![],
+                std_fs_write: vec_deq![],
+            }
+        }
+    }
+
+    thread_local! { static TEST_EXPECTATIONS: RefCell<TestExpectations> = RefCell::new(TestExpectations::new()) }
+
+    fn verify_expectations() {
+        verify_expectations!(
+            nix::fcntl::open => TEST_EXPECTATIONS::nix_fcntl_open,
+            nix::unistd::chown => TEST_EXPECTATIONS::nix_unistd_chown,
+            nix::unistd::close => TEST_EXPECTATIONS::nix_unistd_close,
+            std::fs::create_dir_all => TEST_EXPECTATIONS::std_fs_create_dir_all,
+            std::fs::read_to_string => TEST_EXPECTATIONS::std_fs_read_to_string,
+            std::fs::remove_file => TEST_EXPECTATIONS::std_fs_remove_file,
+            std::fs::symlink_metadata => TEST_EXPECTATIONS::std_fs_symlink_metadata,
+            std::fs::write => TEST_EXPECTATIONS::std_fs_write,
+        );
+    }
+
+    pub mod nix {
+        pub mod fcntl {
+            use super::super::TEST_EXPECTATIONS;
+            use crate::verify_expectation;
+            use ::nix::{fcntl::OFlag, sys::stat::Mode, NixPath, Result};
+            use ::std::{os::unix::io::RawFd, path::Path};
+
+            pub fn open<P: ?Sized + NixPath + AsRef<Path>>(
+                path: &P,
+                flags: OFlag,
+                mode: Mode,
+            ) -> Result<RawFd> {
+                let path = path.as_ref().to_str().unwrap();
+                let flags = flags.bits();
+                let mode = mode.bits();
+
+                verify_expectation!(TEST_EXPECTATIONS::nix_fcntl_open => nix::fcntl::open { path, flags, mode })
+            }
+        }
+
+        pub mod unistd {
+            use super::super::TEST_EXPECTATIONS;
+            use crate::verify_expectation;
+            use ::nix::{
+                unistd::{Gid, Uid},
+                NixPath, Result,
+            };
+            use ::std::{os::unix::io::RawFd, path::Path};
+
+            pub fn chown<P: ?Sized + NixPath + AsRef<Path>>(
+                path: &P,
+                owner: Option<Uid>,
+                group: Option<Gid>,
+            ) -> Result<()> {
+                let path = path.as_ref().to_str().unwrap();
+                let owner = owner.map(|uid| uid.as_raw());
+                let group = group.map(|gid| gid.as_raw());
+
+                verify_expectation!(TEST_EXPECTATIONS::nix_unistd_chown => nix::unistd::chown { path, owner, group })
+            }
+
+            pub fn close(fd: RawFd) -> Result<()> {
+                verify_expectation!(TEST_EXPECTATIONS::nix_unistd_close => nix::unistd::close { fd })
+            }
+        }
+    }
+
+    pub mod std {
+        pub mod fs {
+            use super::super::TEST_EXPECTATIONS;
+            use crate::verify_expectation;
+            use ::std::{io::Result, path::Path, str::from_utf8};
+
+            pub fn create_dir_all<P: AsRef<Path>>(path: P) -> Result<()> {
+                let path = path.as_ref().to_str().unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_create_dir_all => std::fs::create_dir_all { path })
+            }
+
+            pub fn read_to_string<P: AsRef<Path>>(path: P) -> Result<String> {
+                let path = path.as_ref().to_str().unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_read_to_string => std::fs::read_to_string { path })
+            }
+
+            pub fn remove_file<P: AsRef<Path>>(path: P) -> Result<()> {
+                let path = path.as_ref().to_str().unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_remove_file => std::fs::remove_file { path })
+            }
+
+            pub struct FileType {
+                socket: bool,
+            }
+
+            impl FileType {
+                pub fn is_socket(&self) -> bool {
+                    self.socket
+                }
+            }
+
+            pub struct Metadata {
+                socket: bool,
+            }
+
+            impl Metadata {
+                pub fn file_type(&self) -> FileType {
+                    FileType {
+                        socket: self.socket,
+                    }
+                }
+            }
+
+            pub fn symlink_metadata<P: AsRef<Path>>(path: P) -> Result<Metadata> {
+                let path = path.as_ref().to_str().unwrap();
+                let socket = verify_expectation!(TEST_EXPECTATIONS::std_fs_symlink_metadata => std::fs::symlink_metadata { path })?;
+
+                Ok(Metadata { socket })
+            }
+
+            pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, data: C) -> Result<()> {
+                let path = path.as_ref().to_str().unwrap();
+                let data = from_utf8(data.as_ref()).unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_write => std::fs::write { path, data })
+            }
+        }
+    }
+
+    macro_rules! error {
+        ($msg:expr) => {{
+            Err(Error::new(ErrorKind::Other, format!("{}", $msg)))
+        }};
+    }
+
+    fn create_config() -> Config {
+        Config::new(
+            "
+            launcher:
+              binary: /usr/bin/qemu-kvm
+              user: 1000
+              rlimit_memlock: true
+              qmp_socket: /run/vms/my-vm.qmp
+              env:
+                SPICE_DEBUG: 1
+              vcpu_pinning:
+                0:
+                  0:
+                    0: 3
+
+            qemu:
+            - enable-kvm
+            ",
+        )
+        .unwrap()
+        .with_machine_name("my-vm")
+    }
+
+    #[test]
+    fn state_directory_write_launch_record_writes_resolved_launch_parameters() {
+        let record = r#"{
+  "version": 1,
+  "machine": "my-vm",
+  "argv": [
+    "/usr/bin/qemu-kvm",
+    "-enable-kvm",
+    "-qmp",
+    "unix:/run/vms/my-vm.qmp,server=on,wait=off",
+    "-qmp",
+    "stdio"
+  ],
+  "clear_env": false,
+  "env": {
+    "SPICE_DEBUG": "1"
+  },
+  "uid": 1000,
+  "gid": null,
+  "rlimits": {
+    "memlock": "unlimited",
+    "nofile": "inherited"
+  },
+  "vcpu_pinning": [
+    {
+      "socket": 0,
+      "core": 0,
+      "thread": 0,
+      "host_cpu": 3
+    }
+  ],
+  "scheduler": null,
+  "priority": null,
+  "deadline": null,
+  "sockets": [
+    "/run/vms/my-vm.qmp"
+  ]
+}"#;
+
+        expect!(TEST_EXPECTATIONS::std_fs_create_dir_all: { "/run/qemu-launcher/my-vm" => Ok({}) });
+        expect_private_file("/run/qemu-launcher/my-vm/launch.json");
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/run/qemu-launcher/my-vm/launch.json", record => Ok({}) },
+        );
+
+        let state = StateDirectory::new("/run/qemu-launcher", "my-vm");
+
+        assert!(state.write_launch_record(&create_config()).is_ok());
+
+        verify_expectations();
+    }
+
+    fn expect_private_file(path: &'static str) {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_remove_file:
+            { path => Err(Error::new(ErrorKind::NotFound, "std::fs::remove_file()")) },
+        );
+        expect!(TEST_EXPECTATIONS::nix_fcntl_open: { path, 0o2000301, 0o600 => Ok(7) });
+        expect!(TEST_EXPECTATIONS::nix_unistd_close: { 7 => Ok({}) });
+    }
+
+    #[test]
+    fn state_directory_write_launch_record_never_writes_env_file_values() {
+        let record = r#"{
+  "version": 1,
+  "machine": "my-vm",
+  "argv": [
+    "/usr/bin/qemu-kvm",
+    "-enable-kvm",
+    "-qmp",
+    "unix:/run/vms/my-vm.qmp,server=on,wait=off",
+    "-qmp",
+    "stdio"
+  ],
+  "clear_env": false,
+  "env": {
+    "SPICE_DEBUG": "1",
+    "SPICE_TICKET": "********",
+    "TZ": "********"
+  },
+  "uid": 1000,
+  "gid": null,
+  "rlimits": {
+    "memlock": "unlimited",
+    "nofile": "inherited"
+  },
+  "vcpu_pinning": [
+    {
+      "socket": 0,
+      "core": 0,
+      "thread": 0,
+      "host_cpu": 3
+    }
+  ],
+  "scheduler": null,
+  "priority": null,
+  "deadline": null,
+  "sockets": [
+    "/run/vms/my-vm.qmp"
+  ]
+}"#;
+
+        expect!(TEST_EXPECTATIONS::std_fs_create_dir_all: { "/run/qemu-launcher/my-vm" => Ok({}) });
+        expect_private_file("/run/qemu-launcher/my-vm/launch.json");
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/run/qemu-launcher/my-vm/launch.json", record => Ok({}) },
+        );
+
+        let config = create_config().with_env_file(&[
+            ("SPICE_DEBUG", "2"),
+            ("SPICE_TICKET", "hunter2"),
+            ("TZ", "UTC"),
+        ]);
+
+        assert!(StateDirectory::new("/run/qemu-launcher", "my-vm")
+            .write_launch_record(&config)
+            .is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_write_launch_record_returns_error_if_unable_to_replace_record() {
+        expect!(TEST_EXPECTATIONS::std_fs_create_dir_all: { "/run/qemu-launcher/my-vm" => Ok({}) });
+        expect!(
+            TEST_EXPECTATIONS::std_fs_remove_file:
+            { "/run/qemu-launcher/my-vm/launch.json" => Ok({}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::nix_fcntl_open:
+            { "/run/qemu-launcher/my-vm/launch.json", 0o2000301, 0o600 => Err(::nix::Error::Sys(Errno::EEXIST)) },
+        );
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to create the launch record `/run/qemu-launcher/my-vm/launch.json`: EEXIST: File exists",
+            StateDirectory::new("/run/qemu-launcher", "my-vm").write_launch_record(&create_config())
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_write_launch_record_returns_error_if_unable_to_create_directory() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_create_dir_all:
+            { "/run/qemu-launcher/my-vm" => error!("std::fs::create_dir_all()") },
+        );
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to create the state directory `/run/qemu-launcher/my-vm`: std::fs::create_dir_all()",
+            StateDirectory::new("/run/qemu-launcher", "my-vm").write_launch_record(&create_config())
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_write_readconfig_writes_qemu_configuration_file() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_create_dir_all:
+            { "/run/qemu-launcher/my-vm" => Ok({}) },
+            { "/run/qemu-launcher/my-vm" => Ok({}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/run/qemu-launcher/my-vm/qemu.cfg", "[device]\n  driver = \"virtio-rng-pci\"\n" => Ok({}) },
+            { "/run/qemu-launcher/my-vm/qemu.cfg", "" => error!("std::fs::write()") },
+        );
+
+        let state = StateDirectory::new("/run/qemu-launcher", "my-vm");
+
+        assert_eq!(
+            "/run/qemu-launcher/my-vm/qemu.cfg",
+            state.get_readconfig_path().to_str().unwrap()
+        );
+        assert!(state
+            .write_readconfig("[device]\n  driver = \"virtio-rng-pci\"\n")
+            .is_ok());
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to write the qemu configuration file `/run/qemu-launcher/my-vm/qemu.cfg`: std::fs::write()",
+            state.write_readconfig("")
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_read_throttle_ignores_missing_and_invalid_requests() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/run/qemu-launcher/my-vm/throttle" => Ok("40\n".to_owned()) },
+            { "/run/qemu-launcher/my-vm/throttle" => Ok("140\n".to_owned()) },
+            { "/run/qemu-launcher/my-vm/throttle" => error!("std::fs::read_to_string()") },
+        );
+
+        let state = StateDirectory::new("/run/qemu-launcher", "my-vm");
+
+        assert_eq!(Some(40), state.read_throttle());
+        assert_eq!(None, state.read_throttle());
+        assert_eq!(None, state.read_throttle());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_read_pinned_host_cpus_returns_host_cpus_of_launch_record() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/run/qemu-launcher/my-vm/launch.json" => Ok(
+                r#"{"vcpu_pinning": [{"socket": 0, "core": 0, "thread": 0, "host_cpu": 3}, {"index": 1, "host_cpu": 5}]}"#
+                    .to_owned()) },
+            { "/run/qemu-launcher/my-vm/launch.json" => error!("std::fs::read_to_string()") },
+        );
+
+        let state = StateDirectory::new("/run/qemu-launcher", "my-vm");
+
+        assert_eq!(vec![3, 5], state.read_pinned_host_cpus());
+        assert_eq!(Vec::<usize>::new(), state.read_pinned_host_cpus());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_read_sockets_migrates_older_launch_records() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/run/qemu-launcher/my-vm/launch.json" => Ok(
+                r#"{"vcpu_pinning": [{"index": 0, "host_cpu": 3}]}"#.to_owned()) },
+            { "/run/qemu-launcher/my-vm/launch.json" => Ok(
+                r#"{"vcpu_pinning": [{"index": 0, "host_cpu": 3}]}"#.to_owned()) },
+            { "/run/qemu-launcher/my-vm/launch.json" => Ok(
+                r#"{"version": 2, "sockets": ["/run/vms/qmp.sock"], "helpers": {"nbd": 42}}"#.to_owned()) },
+        );
+
+        let state = StateDirectory::new("/run/qemu-launcher", "my-vm");
+
+        assert_eq!(vec![3], state.read_pinned_host_cpus());
+        assert_eq!(Vec::<String>::new(), state.read_sockets());
+        assert_eq!(vec!["/run/vms/qmp.sock"], state.read_sockets());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_load_launch_record_returns_migrated_record() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/run/qemu-launcher/my-vm/launch.json" => Ok(r#"{"argv": ["qemu"]}"#.to_owned()) },
+        );
+
+        let state = StateDirectory::new("/run/qemu-launcher", "my-vm");
+
+        assert_eq!(
+            object! { "argv": ["qemu"], "version": 1, "sockets": [] },
+            state.load_launch_record().unwrap()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_load_launch_record_returns_error_if_record_is_unreadable_or_invalid() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/run/qemu-launcher/my-vm/launch.json" => Err(Error::new(ErrorKind::NotFound, "std::fs::read_to_string()")) },
+            { "/run/qemu-launcher/my-vm/launch.json" => Ok(r#"["qemu"]"#.to_owned()) },
+        );
+
+        let state = StateDirectory::new("/run/qemu-launcher", "my-vm");
+
+        assert_error!(
+            ErrorKind::NotFound,
+            "Failed to read the launch record `/run/qemu-launcher/my-vm/launch.json`: std::fs::read_to_string()",
+            state.load_launch_record()
+        );
+        assert_error!(
+            ErrorKind::InvalidData,
+            "The launch record `/run/qemu-launcher/my-vm/launch.json` is not a JSON object.",
+            state.load_launch_record()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_remove_sockets_removes_sockets_of_launch_record() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/run/qemu-launcher/my-vm/launch.json" => Ok(
+                r#"{"version": 1, "sockets": ["/run/vms/qmp.sock", "/run/vms/nbd.sock", "/run/vms/gone.sock"]}"#
+                    .to_owned()) },
+            { "/run/qemu-launcher/my-vm/launch.json" => Ok(
+                r#"{"version": 1, "sockets": ["/run/vms/qmp.sock"]}"#.to_owned()) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_symlink_metadata:
+            { "/run/vms/qmp.sock" => Ok(true) },
+            { "/run/vms/nbd.sock" => Ok(true) },
+            { "/run/vms/gone.sock" => Err(Error::new(ErrorKind::NotFound, "std::fs::symlink_metadata()")) },
+            { "/run/vms/qmp.sock" => Ok(true) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_remove_file:
+            { "/run/vms/qmp.sock" => Err(Error::new(ErrorKind::NotFound, "std::fs::remove_file()")) },
+            { "/run/vms/nbd.sock" => Ok({}) },
+            { "/run/vms/qmp.sock" => error!("std::fs::remove_file()") },
+        );
+
+        let state = StateDirectory::new("/run/qemu-launcher", "my-vm");
+
+        assert!(state.remove_sockets().is_ok());
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to remove the socket `/run/vms/qmp.sock`: std::fs::remove_file()",
+            state.remove_sockets()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_remove_sockets_leaves_paths_which_are_not_sockets_alone() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/run/qemu-launcher/my-vm/launch.json" => Ok(
+                r#"{"version": 1, "sockets": ["/etc/shadow", "/run/vms/qmp.sock"]}"#.to_owned()) },
+            { "/run/qemu-launcher/my-vm/launch.json" => Ok(
+                r#"{"version": 1, "sockets": ["/root/.ssh"]}"#.to_owned()) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_symlink_metadata:
+            { "/etc/shadow" => Ok(false) },
+            { "/run/vms/qmp.sock" => Ok(true) },
+            { "/root/.ssh" => error!("std::fs::symlink_metadata()") },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_remove_file:
+            { "/run/vms/qmp.sock" => Ok({}) },
+        );
+
+        let state = StateDirectory::new("/run/qemu-launcher", "my-vm");
+
+        assert!(state.remove_sockets().is_ok());
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to inspect the socket `/root/.ssh`: std::fs::symlink_metadata()",
+            state.remove_sockets()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_write_pid_writes_qemu_process_id() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/run/qemu-launcher/my-vm/qemu.pid", "1234\n" => Ok({}) },
+        );
+
+        assert!(StateDirectory::new("/run/qemu-launcher", "my-vm")
+            .write_pid(1234)
+            .is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_read_pid_parses_qemu_process_id() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/run/qemu-launcher/my-vm/qemu.pid" => Ok("1234\n".to_owned()) },
+            { "/run/qemu-launcher/my-vm/qemu.pid" => Ok("garbage\n".to_owned()) },
+        );
+
+        let state = StateDirectory::new("/run/qemu-launcher", "my-vm");
+
+        assert_eq!(1234, state.read_pid().unwrap());
+        assert_error!(
+            ErrorKind::InvalidData,
+            "The pid file `/run/qemu-launcher/my-vm/qemu.pid` contains an invalid process ID: `garbage`",
+            state.read_pid()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_is_ready_checks_readiness_marker() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/run/qemu-launcher/storage/ready" => Ok(String::new()) },
+            { "/run/qemu-launcher/storage/ready" =>
+                Err(Error::new(ErrorKind::NotFound, "std::fs::read_to_string()")) },
+        );
+
+        let state = StateDirectory::new("/run/qemu-launcher", "storage");
+
+        assert!(state.is_ready());
+        assert!(!state.is_ready());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_mark_ready_writes_readiness_marker() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/run/qemu-launcher/my-vm/ready", "" => Ok({}) },
+        );
+
+        assert!(StateDirectory::new("/run/qemu-launcher", "my-vm")
+            .mark_ready()
+            .is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_clear_ready_ignores_missing_readiness_marker() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_remove_file:
+            { "/run/qemu-launcher/my-vm/ready" =>
+                Err(Error::new(ErrorKind::NotFound, "std::fs::remove_file()")) },
+            { "/run/qemu-launcher/my-vm/ready" => error!("std::fs::remove_file()") },
+        );
+
+        let state = StateDirectory::new("/run/qemu-launcher", "my-vm");
+
+        assert!(state.clear_ready().is_ok());
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to remove the readiness marker `/run/qemu-launcher/my-vm/ready`: std::fs::remove_file()",
+            state.clear_ready()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_records_and_reads_firewall_table() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/run/qemu-launcher/my-vm/firewall", "qemu_launcher_my_vm\n" => Ok({}) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/run/qemu-launcher/my-vm/firewall" => Ok("qemu_launcher_my_vm\n".to_string()) },
+            { "/run/qemu-launcher/my-vm/firewall" => error!("std::fs::read_to_string()") },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_remove_file:
+            { "/run/qemu-launcher/my-vm/firewall" => Ok({}) },
+        );
+
+        let state = StateDirectory::new("/run/qemu-launcher", "my-vm");
+
+        assert!(state.write_firewall_table("qemu_launcher_my_vm").is_ok());
+        assert_eq!(
+            Some(String::from("qemu_launcher_my_vm")),
+            state.read_firewall_table()
+        );
+        assert!(state.clear_firewall_table().is_ok());
+        assert_eq!(None, state.read_firewall_table());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_set_owner_changes_directory_owner() {
+        expect!(
+            TEST_EXPECTATIONS::nix_unistd_chown:
+            { "/run/qemu-launcher/my-vm", Some(1000), None => Ok({}) },
+        );
+
+        assert!(StateDirectory::new("/run/qemu-launcher", "my-vm")
+            .set_owner(Some(1000), None)
+            .is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_hand_over_changes_owner_of_existing_files() {
+        expect!(
+            TEST_EXPECTATIONS::nix_unistd_chown:
+            { "/run/qemu-launcher/my-vm/firewall", Some(1000), Some(100) => Err(::nix::Error::Sys(Errno::ENOENT)) },
+            { "/run/qemu-launcher/my-vm/launch.json", Some(1000), Some(100) => Ok({}) },
+            { "/run/qemu-launcher/my-vm/qemu.pid", Some(1000), Some(100) => Ok({}) },
+            { "/run/qemu-launcher/my-vm/ready", Some(1000), Some(100) => Ok({}) },
+        );
+
+        assert!(StateDirectory::new("/run/qemu-launcher", "my-vm")
+            .hand_over(1000, Some(100), false)
+            .is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_hand_over_returns_error_if_chown_fails() {
+        expect!(
+            TEST_EXPECTATIONS::nix_unistd_chown:
+            { "/run/qemu-launcher/my-vm", Some(1000), None => Err(::nix::Error::Sys(Errno::EPERM)) },
+        );
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to change the owner of `/run/qemu-launcher/my-vm`: EPERM: Operation not permitted",
+            StateDirectory::new("/run/qemu-launcher", "my-vm").hand_over(1000, None, true)
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn state_directory_set_owner_returns_error_if_chown_fails() {
+        expect!(
+            TEST_EXPECTATIONS::nix_unistd_chown:
+            { "/run/qemu-launcher/my-vm", None, Some(100) => Err(::nix::Error::InvalidPath) },
+        );
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to change the owner of the state directory `/run/qemu-launcher/my-vm`: Invalid path",
+            StateDirectory::new("/run/qemu-launcher", "my-vm").set_owner(None, Some(100))
+        );
+
+        verify_expectations();
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{
+    clock::{self, Clock},
+    config::{Config, StorageDaemon},
+    host, output, pinning,
+    process::{ChildProcess, OneshotPool, Process},
+};
+use nix::{
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+};
+use std::{
+    fs,
+    io::{Error, ErrorKind},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+// The `qemu-storage-daemon` processes serving block nodes to the machine. They are started before
+// qemu, which connects to their NBD sockets on startup, and stopped once qemu is gone.
+pub struct StorageDaemons {
+    clock: Arc<dyn Clock>,
+    running: Vec<(String, ChildProcess)>,
+    timeout: Duration,
+}
+
+impl StorageDaemons {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            clock: clock::system(),
+            running: vec![],
+            timeout,
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+
+        self
+    }
+
+    pub fn start(&mut self, config: &Config, helpers: &OneshotPool) -> Result<(), Error> {
+        for daemon in config.get_storage_daemons() {
+            let socket = config.get_storage_daemon_socket(daemon);
+
+            // A socket left behind by a previous daemon would be taken for the new one listening
+            match fs::remove_file(&socket) {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(Error::new(
+                        e.kind(),
+                        format!("Failed to remove the stale socket `{}`: {}", socket, e),
+                    ))
+                }
+            }
+
+            let child = match Process::new(daemon.get_binary())
+                .set_args(config.get_storage_daemon_arguments(daemon))
+                .set_effective_group_id(&config.get_group())
+                .set_effective_user_id(&config.get_user())
+                .should_clear_ambient_capabilities(true)
+                .should_clear_env(true)
+                .spawn()
+            {
+                Ok(child) => child,
+                Err(e) => {
+                    return Err(Error::new(
+                        e.kind(),
+                        format!(
+                            "Failed to execute the `{}` storage daemon: {}",
+                            daemon.get_id(),
+                            e
+                        ),
+                    ))
+                }
+            };
+
+            let pid = child.id() as i32;
+            self.running.push((daemon.get_id().to_owned(), child));
+            self.wait_for_socket(daemon.get_id(), &socket)?;
+
+            tune(daemon, pid, helpers);
+        }
+
+        Ok({})
+    }
+
+    fn wait_for_socket(&mut self, id: &str, socket: &str) -> Result<(), Error> {
+        let deadline = self.clock.now() + self.timeout;
+
+        while !Path::new(socket).exists() {
+            if let Some((_, child)) = self.running.last_mut() {
+                if child.has_exited()? {
+                    return Err(Error::new(
+                        ErrorKind::Other,
+                        format!(
+                            "The `{}` storage daemon exited before listening on `{}`.",
+                            id, socket
+                        ),
+                    ));
+                }
+            }
+
+            if self.clock.now() >= deadline {
+                return Err(Error::new(
+                    ErrorKind::TimedOut,
+                    format!(
+                        "The `{}` storage daemon did not listen on `{}` in {} seconds.",
+                        id,
+                        socket,
+                        self.timeout.as_secs()
+                    ),
+                ));
+            }
+
+            self.clock.sleep(Duration::from_millis(100));
+        }
+
+        Ok({})
+    }
+
+    // Names the daemons which exited since the previous call, so each one is reported once
+    pub fn take_exited(&mut self) -> Vec<String> {
+        let mut exited = vec![];
+        let mut index = 0;
+
+        while index < self.running.len() {
+            match self.running[index].1.has_exited() {
+                Ok(false) => index += 1,
+                Ok(true) | Err(_) => exited.push(self.running.remove(index).0),
+            }
+        }
+
+        exited
+    }
+
+    // Asks every daemon to terminate and kills the ones still running after the timeout
+    pub fn stop(&mut self) -> Result<(), Error> {
+        for (_, child) in &self.running {
+            let _ = kill(Pid::from_raw(child.id() as i32), Signal::SIGTERM);
+        }
+
+        let deadline = self.clock.now() + self.timeout;
+        let mut failed = vec![];
+
+        while let Some((id, mut child)) = self.running.pop() {
+            while !child.has_exited().unwrap_or(true) && self.clock.now() < deadline {
+                self.clock.sleep(Duration::from_millis(100));
+            }
+
+            if !child.has_exited().unwrap_or(true) {
+                output::warning(format!(
+                    "The `{}` storage daemon did not stop in {} seconds, killing it.",
+                    id,
+                    self.timeout.as_secs()
+                ));
+                if let Err(e) = child.kill() {
+                    failed.push(format!("`{}` ({})", id, e));
+                }
+            }
+
+            // Reaps the daemon, a daemon killed by the launcher does not exit successfully
+            let _ = child.wait();
+        }
+
+        if failed.len() > 0 {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("Failed to stop storage daemons: {}.", failed.join(", ")),
+            ));
+        }
+
+        Ok({})
+    }
+}
+
+// Threads started later inherit the affinity and the scheduling policy of the ones tuned here
+fn tune(daemon: &StorageDaemon, pid: i32, helpers: &OneshotPool) {
+    if daemon.get_host_cpus().len() > 0 {
+        match host::get_tasks(pid) {
+            Ok(tasks) => {
+                for task in tasks {
+                    if let Err(e) = pinning::set_affinity(task, daemon.get_host_cpus()) {
+                        output::error(format!(
+                            "Failed to pin the `{}` storage daemon thread `{}`: {}",
+                            daemon.get_id(),
+                            task,
+                            e
+                        ));
+                    }
+                }
+            }
+            Err(e) => output::error(e),
+        }
+    }
+
+    if let Some(scheduler) = daemon.get_scheduler() {
+        let command = (
+            String::from("chrt"),
+            vec![
+                String::from("--all-tasks"),
+                format!("--{}", scheduler),
+                String::from("--pid"),
+                daemon.get_priority().unwrap_or(0).to_string(),
+                pid.to_string(),
+            ],
+        );
+
+        for result in helpers.oneshot_all(vec![command]) {
+            if let Err(e) = result {
+                output::error(format!(
+                    "Failed to change the `{}` storage daemon scheduling policy: {}",
+                    daemon.get_id(),
+                    e
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,136 @@
+#[cfg(not(test))]
+use crate::process::Process;
+use std::io::Error;
+#[cfg(test)]
+use test::process::Process;
+
+const BUSCTL: &str = "busctl";
+const MACHINE_SLICE: &str = "machine.slice";
+
+// Registers the running qemu process as a transient scope unit under `machine.slice`, so the
+// machine shows up in `machinectl` and `systemd-cgls`. The cgroup is delegated, since the launcher
+// keeps managing the placement of the qemu threads on its own.
+pub fn start_scope(scope: &str, machine_name: &str, pid: u32) -> Result<(), Error> {
+    let description = format!("qemu-launcher virtual machine {}", machine_name);
+    let pid = pid.to_string();
+
+    let arguments = [
+        "call",
+        "org.freedesktop.systemd1",
+        "/org/freedesktop/systemd1",
+        "org.freedesktop.systemd1.Manager",
+        "StartTransientUnit",
+        "ssa(sv)a(sa(sv))",
+        scope,
+        "fail",
+        "5",
+        "Description",
+        "s",
+        &description,
+        "PIDs",
+        "au",
+        "1",
+        &pid,
+        "Slice",
+        "s",
+        MACHINE_SLICE,
+        "Delegate",
+        "b",
+        "true",
+        // A scope of a crashed machine is garbage collected, rather than blocking the next launch
+        "CollectMode",
+        "s",
+        "inactive-or-failed",
+        "0",
+    ];
+
+    if let Err(e) = Process::oneshot(BUSCTL, arguments) {
+        return Err(Error::new(
+            e.kind(),
+            format!("Failed to start the `{}` systemd scope: {}", scope, e),
+        ));
+    }
+
+    Ok({})
+}
+
+#[cfg(test)]
+mod test {
+    use super::start_scope;
+    use crate::{assert_error, expect, vec_deq, verify_expectations};
+    use ::std::{
+        cell::RefCell,
+        collections::VecDeque,
+        io::{Error, ErrorKind},
+    };
+
+    struct TestExpectations {
+        process_oneshot: VecDeque<(&'static str, Result<(), Error>)>,
+    }
+
+    impl TestExpectations {
+        fn new() -> Self {
+            TestExpectations {
+                process_oneshot: vec_deq![],
+            }
+        }
+    }
+
+    thread_local! { static TEST_EXPECTATIONS: RefCell<TestExpectations> = RefCell::new(TestExpectations::new()) }
+
+    fn verify_expectations() {
+        verify_expectations!(
+            crate::process::Process::oneshot => TEST_EXPECTATIONS::process_oneshot,
+        );
+    }
+
+    pub mod process {
+        use super::TEST_EXPECTATIONS;
+        use crate::verify_expectation;
+        use ::std::{ffi::OsStr, io::Result};
+
+        pub struct Process {}
+
+        impl Process {
+            pub fn oneshot<C: AsRef<OsStr>, I: IntoIterator<Item = S>, S: AsRef<OsStr>>(
+                command: C,
+                arguments: I,
+            ) -> Result<()> {
+                let mut command = command.as_ref().to_string_lossy().to_string();
+                for argument in arguments {
+                    command.push(' ');
+                    command.push_str(&argument.as_ref().to_string_lossy());
+                }
+
+                verify_expectation!(TEST_EXPECTATIONS::process_oneshot => crate::process::Process::oneshot { command })
+            }
+        }
+    }
+
+    #[test]
+    fn start_scope_registers_the_process_under_the_machine_slice() {
+        expect!(
+            TEST_EXPECTATIONS::process_oneshot:
+            { "busctl call org.freedesktop.systemd1 /org/freedesktop/systemd1 \
+                org.freedesktop.systemd1.Manager StartTransientUnit ssa(sv)a(sa(sv)) \
+                machine-foo.scope fail 5 Description s qemu-launcher virtual machine foo \
+                PIDs au 1 4242 Slice s machine.slice Delegate b true \
+                CollectMode s inactive-or-failed 0" => Ok({}) },
+            { "busctl call org.freedesktop.systemd1 /org/freedesktop/systemd1 \
+                org.freedesktop.systemd1.Manager StartTransientUnit ssa(sv)a(sa(sv)) \
+                machine-foo.scope fail 5 Description s qemu-launcher virtual machine foo \
+                PIDs au 1 4243 Slice s machine.slice Delegate b true \
+                CollectMode s inactive-or-failed 0" =>
+                Err(Error::new(ErrorKind::Other, "Unit machine-foo.scope already exists.")) },
+        );
+
+        start_scope("machine-foo.scope", "foo", 4242).unwrap();
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to start the `machine-foo.scope` systemd scope: Unit machine-foo.scope already exists.",
+            start_scope("machine-foo.scope", "foo", 4243)
+        );
+
+        verify_expectations();
+    }
+}
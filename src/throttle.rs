@@ -0,0 +1,139 @@
+use crate::{
+    clock::Clock,
+    output,
+    qmp::{QmpClient, QmpPipe},
+};
+use json::object;
+use std::{
+    io::{Error, ErrorKind},
+    sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+// Short enough for the guest to stay responsive, while not flooding qemu with QMP commands
+const THROTTLE_PERIOD: Duration = Duration::from_millis(100);
+
+// Throttles the vCPUs by stopping the whole machine over QMP for the given share of every period,
+// the same duty cycle qemu applies to the vCPUs of an auto-converging migration
+pub struct Throttle {
+    percent: Arc<AtomicU8>,
+    stopping: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Throttle {
+    pub fn start(qmp: impl QmpPipe + Send + 'static, percent: u8, clock: Arc<dyn Clock>) -> Self {
+        let percent = Arc::new(AtomicU8::new(percent));
+        let stopping = Arc::new(AtomicBool::new(false));
+
+        let worker = {
+            let (percent, stopping) = (percent.clone(), stopping.clone());
+
+            thread::spawn(move || {
+                match run(
+                    QmpClient::new(qmp).with_clock(clock.clone()),
+                    &*clock,
+                    &percent,
+                    &stopping,
+                ) {
+                    Ok(_) => {}
+                    // qemu went away, its exit is reported by the supervisor
+                    Err(e)
+                        if e.kind() == ErrorKind::UnexpectedEof
+                            || e.kind() == ErrorKind::BrokenPipe => {}
+                    Err(e) => output::error(format!("Stopped throttling the vCPUs: {}", e)),
+                }
+            })
+        };
+
+        Self {
+            percent,
+            stopping,
+            worker: Some(worker),
+        }
+    }
+
+    pub fn set_percent(&self, percent: u8) {
+        self.percent.store(percent, Ordering::Relaxed);
+    }
+}
+
+// The machine is resumed, if it is stopped by the throttle, before the worker goes away
+impl Drop for Throttle {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::Relaxed);
+
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run(
+    mut qmp: QmpClient,
+    clock: &dyn Clock,
+    percent: &AtomicU8,
+    stopping: &AtomicBool,
+) -> Result<(), Error> {
+    let mut stopped = false;
+
+    loop {
+        let is_stopping = stopping.load(Ordering::Relaxed);
+        let (stop, run) = match is_stopping {
+            true => (Duration::from_secs(0), Duration::from_secs(0)),
+            false => duty_cycle(percent.load(Ordering::Relaxed)),
+        };
+
+        // A machine paused by somebody else is left alone, so it is not resumed by the throttle
+        if stop > Duration::from_secs(0) && !stopped {
+            let status = qmp.execute(object! { "execute": "query-status" })?;
+            if status["status"] == "running" {
+                qmp.execute(object! { "execute": "stop" })?;
+                stopped = true;
+            }
+        }
+
+        clock.sleep(stop);
+
+        if stopped && (run > Duration::from_secs(0) || is_stopping) {
+            qmp.execute(object! { "execute": "cont" })?;
+            stopped = false;
+        }
+
+        if is_stopping {
+            return Ok({});
+        }
+
+        // Every cycle brings `STOP` and `RESUME` events, which nobody reads on this connection
+        qmp.discard_events();
+
+        clock.sleep(run);
+    }
+}
+
+// Splits the throttle period into the time the machine is stopped and the time it runs
+fn duty_cycle(percent: u8) -> (Duration, Duration) {
+    let stop = THROTTLE_PERIOD * percent.min(100) as u32 / 100;
+
+    (stop, THROTTLE_PERIOD - stop)
+}
+
+#[cfg(test)]
+mod test {
+    use super::duty_cycle;
+    use std::time::Duration;
+
+    #[test]
+    fn duty_cycle_splits_the_period_by_the_percentage() {
+        let ms = Duration::from_millis;
+
+        assert_eq!((ms(0), ms(100)), duty_cycle(0));
+        assert_eq!((ms(35), ms(65)), duty_cycle(35));
+        assert_eq!((ms(100), ms(0)), duty_cycle(100));
+        assert_eq!((ms(100), ms(0)), duty_cycle(250));
+    }
+}
@@ -0,0 +1,508 @@
+#[cfg(not(test))]
+use nix::libc::sched_setscheduler;
+use nix::libc::{sched_param, SCHED_FIFO, SCHED_OTHER, SCHED_RR};
+#[cfg(not(test))]
+use std::fs;
+use std::{
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+#[cfg(test)]
+use test::{nix::libc::sched_setscheduler, std::fs};
+
+const KVM_PARAMETERS_PATH: &str = "/sys/module/kvm/parameters";
+const WORKQUEUE_CPUMASK_PATH: &str = "/sys/devices/virtual/workqueue/cpumask";
+const TIMER_MIGRATION_PATH: &str = "/proc/sys/kernel/timer_migration";
+
+pub struct HostTuning {
+    // The path along with its value before and after tuning
+    saved: Vec<(PathBuf, String, String)>,
+    // The kernel thread ID and name along with its scheduling policy and priority before tuning
+    threads: Vec<(usize, String, i32, i32)>,
+}
+
+impl HostTuning {
+    pub fn new() -> Self {
+        Self {
+            saved: vec![],
+            threads: vec![],
+        }
+    }
+
+    pub fn isolate_workqueues(&mut self, cpus: &[usize]) -> Result<(), Error> {
+        let current = read_value(WORKQUEUE_CPUMASK_PATH)?;
+        let mut mask = parse_cpumask(&current)?;
+
+        for cpu in cpus {
+            if let Some(word) = mask.get_mut(cpu / 32) {
+                *word &= !(1 << (cpu % 32));
+            }
+        }
+
+        if mask.iter().all(|word| *word == 0) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Unable to isolate workqueues: no housekeeping CPUs would remain in the `{}` mask.",
+                    current
+                ),
+            ));
+        }
+
+        self.set(WORKQUEUE_CPUMASK_PATH, current, format_cpumask(&mask))
+    }
+
+    pub fn disable_timer_migration(&mut self) -> Result<(), Error> {
+        let current = read_value(TIMER_MIGRATION_PATH)?;
+
+        self.set(TIMER_MIGRATION_PATH, current, String::from("0"))
+    }
+
+    // The module parameters are shared by every machine on the host, they are restored to the
+    // values found at start once the machine exits
+    pub fn set_kvm_parameter(&mut self, name: &str, value: u64) -> Result<(), Error> {
+        let path = Path::new(KVM_PARAMETERS_PATH).join(name);
+        let current = match read_value(&path) {
+            Ok(current) => current,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Err(Error::new(
+                    e.kind(),
+                    format!(
+                        "The `{}` KVM module parameter is not available, the module is either not \
+                        loaded or too old.",
+                        name
+                    ),
+                ))
+            }
+            Err(e) => return Err(e),
+        };
+
+        self.set(path, current, value.to_string())
+    }
+
+    // Moves a real-time kernel thread to `SCHED_OTHER`, so it no longer preempts real-time vCPU
+    // threads sharing its CPU. Threads which are not real-time are left alone.
+    pub fn lower_kernel_thread(
+        &mut self,
+        id: usize,
+        name: &str,
+        policy: i32,
+        priority: i32,
+    ) -> Result<(), Error> {
+        if policy != SCHED_FIFO && policy != SCHED_RR {
+            return Ok({});
+        }
+
+        if let Err(e) = set_scheduler(id, SCHED_OTHER, 0) {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to lower the priority of the `{}` kernel thread: {}",
+                    name, e
+                ),
+            ));
+        }
+
+        self.threads.push((id, name.to_owned(), policy, priority));
+
+        Ok({})
+    }
+
+    fn set<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        previous: String,
+        value: String,
+    ) -> Result<(), Error> {
+        if let Err(e) = fs::write(path.as_ref(), &value) {
+            return Err(Error::new(
+                e.kind(),
+                format!(
+                    "Failed to write `{}` into `{}`: {}",
+                    value,
+                    path.as_ref().display(),
+                    e
+                ),
+            ));
+        }
+
+        self.saved
+            .push((PathBuf::from(path.as_ref()), previous, value));
+
+        Ok({})
+    }
+
+    // Writes the tuned values again, e.g. after a host resume might have reset them, while keeping
+    // the values to restore
+    pub fn reapply(&self) -> Result<(), Error> {
+        let mut failed = vec![];
+
+        for (path, _, value) in &self.saved {
+            if let Err(e) = fs::write(path, value) {
+                failed.push(format!("`{}` ({})", path.display(), e));
+            }
+        }
+
+        for (id, name, _, _) in &self.threads {
+            if let Err(e) = set_scheduler(*id, SCHED_OTHER, 0) {
+                failed.push(format!("`{}` ({})", name, e));
+            }
+        }
+
+        if failed.len() > 0 {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "Failed to reapply host tuning settings: {}.",
+                    failed.join(", ")
+                ),
+            ));
+        }
+
+        Ok({})
+    }
+
+    pub fn restore(&mut self) -> Result<(), Error> {
+        let mut failed = vec![];
+
+        while let Some((path, value, _)) = self.saved.pop() {
+            if let Err(e) = fs::write(&path, &value) {
+                failed.push(format!("`{}` ({})", path.display(), e));
+            }
+        }
+
+        while let Some((id, name, policy, priority)) = self.threads.pop() {
+            if let Err(e) = set_scheduler(id, policy, priority) {
+                failed.push(format!("`{}` ({})", name, e));
+            }
+        }
+
+        if failed.len() > 0 {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "Failed to restore host tuning settings: {}.",
+                    failed.join(", ")
+                ),
+            ));
+        }
+
+        Ok({})
+    }
+}
+
+fn set_scheduler(id: usize, policy: i32, priority: i32) -> Result<(), Error> {
+    let param = sched_param {
+        sched_priority: priority,
+    };
+
+    match unsafe { sched_setscheduler(id as i32, policy, &param) } {
+        0 => Ok({}),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+fn read_value<P: AsRef<Path>>(path: P) -> Result<String, Error> {
+    match fs::read_to_string(path.as_ref()) {
+        Ok(value) => Ok(value.trim().to_owned()),
+        Err(e) => Err(Error::new(
+            e.kind(),
+            format!("Failed to read `{}`: {}", path.as_ref().display(), e),
+        )),
+    }
+}
+
+fn parse_cpumask<S: AsRef<str>>(mask: S) -> Result<Vec<u32>, Error> {
+    let mut words = vec![];
+
+    for group in mask.as_ref().rsplit(',') {
+        match u32::from_str_radix(group, 16) {
+            Ok(word) => words.push(word),
+            Err(_) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Malformed CPU mask: `{}`.", mask.as_ref()),
+                ))
+            }
+        }
+    }
+
+    Ok(words)
+}
+
+fn format_cpumask(mask: &[u32]) -> String {
+    let mut groups = vec![];
+
+    for (position, word) in mask.iter().rev().enumerate() {
+        match position {
+            0 => groups.push(format!("{:x}", word)),
+            _ => groups.push(format!("{:08x}", word)),
+        }
+    }
+
+    groups.join(",")
+}
+
+#[cfg(test)]
+mod test {
+    use super::HostTuning;
+    use crate::{assert_error, expect, vec_deq, verify_expectations};
+    use ::std::{
+        cell::RefCell,
+        collections::VecDeque,
+        io::{Error, ErrorKind},
+    };
+
+    struct TestExpectations {
+        nix_libc_sched_setscheduler: VecDeque<((i32, i32, i32), i32)>,
+        std_fs_read_to_string: VecDeque<(&'static str, Result<String, Error>)>,
+        std_fs_write: VecDeque<((&'static str, &'static str), Result<(), Error>)>,
+    }
+
+    impl TestExpectations {
+        fn new() -> Self {
+            TestExpectations {
+                nix_libc_sched_setscheduler: vec_deq![],
+                std_fs_read_to_string: vec_deq![],
+                std_fs_write: vec_deq![],
+            }
+        }
+    }
+
+    thread_local! { static TEST_EXPECTATIONS: RefCell<TestExpectations> = RefCell::new(TestExpectations::new()) }
+
+    fn verify_expectations() {
+        verify_expectations!(
+            nix::libc::sched_setscheduler => TEST_EXPECTATIONS::nix_libc_sched_setscheduler,
+            std::fs::read_to_string => TEST_EXPECTATIONS::std_fs_read_to_string,
+            std::fs::write => TEST_EXPECTATIONS::std_fs_write,
+        );
+    }
+
+    pub mod nix {
+        pub mod libc {
+            use super::super::TEST_EXPECTATIONS;
+            use crate::verify_expectation;
+            use ::nix::libc::{c_int, pid_t, sched_param};
+
+            pub unsafe fn sched_setscheduler(
+                pid: pid_t,
+                policy: c_int,
+                param: *const sched_param,
+            ) -> c_int {
+                let priority = (*param).sched_priority;
+
+                verify_expectation!(TEST_EXPECTATIONS::nix_libc_sched_setscheduler => nix::libc::sched_setscheduler { pid, policy, priority })
+            }
+        }
+    }
+
+    pub mod std {
+        pub mod fs {
+            use super::super::TEST_EXPECTATIONS;
+            use crate::verify_expectation;
+            use ::std::{io::Result, path::Path, str::from_utf8};
+
+            pub fn read_to_string<P: AsRef<Path>>(path: P) -> Result<String> {
+                let path = path.as_ref().to_str().unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_read_to_string => std::fs::read_to_string { path })
+            }
+
+            pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, data: C) -> Result<()> {
+                let path = path.as_ref().to_str().unwrap();
+                let data = from_utf8(data.as_ref()).unwrap();
+
+                verify_expectation!(TEST_EXPECTATIONS::std_fs_write => std::fs::write { path, data })
+            }
+        }
+    }
+
+    macro_rules! error {
+        ($msg:expr) => {{
+            Err(Error::new(ErrorKind::Other, format!("{}", $msg)))
+        }};
+    }
+
+    #[test]
+    fn parse_cpumask_handles_multiple_groups() {
+        assert_eq!(
+            vec![0xffffffff, 0xff],
+            super::parse_cpumask("ff,ffffffff").unwrap()
+        );
+    }
+
+    #[test]
+    fn format_cpumask_pads_all_but_the_most_significant_group() {
+        assert_eq!("f,0000ff00", super::format_cpumask(&[0xff00, 0xf]));
+    }
+
+    #[test]
+    fn host_tuning_isolate_workqueues_removes_cpus_from_the_mask_and_restores_it() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/sys/devices/virtual/workqueue/cpumask" => Ok("ff,ffffffff\n".to_string()) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/sys/devices/virtual/workqueue/cpumask", "7f,fffffff3" => Ok({}) },
+            { "/sys/devices/virtual/workqueue/cpumask", "ff,ffffffff" => Ok({}) },
+        );
+
+        let mut tuning = HostTuning::new();
+
+        assert!(tuning.isolate_workqueues(&[2, 3, 39]).is_ok());
+        assert!(tuning.restore().is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn host_tuning_reapply_writes_tuned_values_and_keeps_the_originals() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/sys/kernel/timer_migration" => Ok("1\n".to_string()) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/proc/sys/kernel/timer_migration", "0" => Ok({}) },
+            { "/proc/sys/kernel/timer_migration", "0" => error!("std::fs::write()") },
+            { "/proc/sys/kernel/timer_migration", "0" => Ok({}) },
+            { "/proc/sys/kernel/timer_migration", "1" => Ok({}) },
+        );
+
+        let mut tuning = HostTuning::new();
+
+        assert!(tuning.disable_timer_migration().is_ok());
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to reapply host tuning settings: \
+            `/proc/sys/kernel/timer_migration` (std::fs::write()).",
+            tuning.reapply()
+        );
+        assert!(tuning.reapply().is_ok());
+        assert!(tuning.restore().is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn host_tuning_set_kvm_parameter_writes_the_module_parameter_and_restores_it() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/sys/module/kvm/parameters/halt_poll_ns" => Ok("200000\n".to_string()) },
+            { "/sys/module/kvm/parameters/halt_poll_ns_shrink" =>
+                Err(Error::new(ErrorKind::NotFound, "std::fs::read_to_string()")) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/sys/module/kvm/parameters/halt_poll_ns", "0" => Ok({}) },
+            { "/sys/module/kvm/parameters/halt_poll_ns", "200000" => Ok({}) },
+        );
+
+        let mut tuning = HostTuning::new();
+
+        assert!(tuning.set_kvm_parameter("halt_poll_ns", 0).is_ok());
+        assert_error!(
+            ErrorKind::NotFound,
+            "The `halt_poll_ns_shrink` KVM module parameter is not available, the module is either \
+            not loaded or too old.",
+            tuning.set_kvm_parameter("halt_poll_ns_shrink", 2)
+        );
+        assert!(tuning.restore().is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn host_tuning_lower_kernel_thread_moves_real_time_threads_to_other_and_restores_them() {
+        expect!(
+            TEST_EXPECTATIONS::nix_libc_sched_setscheduler:
+            { 16, 0, 0 => 0 },
+            { 16, 0, 0 => 0 },
+            { 16, 1, 1 => 0 },
+        );
+
+        let mut tuning = HostTuning::new();
+
+        assert!(tuning.lower_kernel_thread(16, "rcuc/2", 1, 1).is_ok());
+        assert!(tuning.lower_kernel_thread(31, "ksoftirqd/2", 0, 0).is_ok());
+        assert!(tuning.reapply().is_ok());
+        assert!(tuning.restore().is_ok());
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn host_tuning_isolate_workqueues_returns_error_if_no_housekeeping_cpus_remain() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/sys/devices/virtual/workqueue/cpumask" => Ok("3".to_string()) },
+        );
+
+        assert_error!(
+            ErrorKind::InvalidInput,
+            "Unable to isolate workqueues: no housekeeping CPUs would remain in the `3` mask.",
+            HostTuning::new().isolate_workqueues(&[0, 1])
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn host_tuning_isolate_workqueues_returns_error_if_mask_is_malformed() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/sys/devices/virtual/workqueue/cpumask" => Ok("xyz".to_string()) },
+        );
+
+        assert_error!(
+            ErrorKind::InvalidData,
+            "Malformed CPU mask: `xyz`.",
+            HostTuning::new().isolate_workqueues(&[0])
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn host_tuning_disable_timer_migration_returns_error_if_unable_to_read_current_value() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/sys/kernel/timer_migration" => error!("std::fs::read_to_string()") },
+        );
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to read `/proc/sys/kernel/timer_migration`: std::fs::read_to_string()",
+            HostTuning::new().disable_timer_migration()
+        );
+
+        verify_expectations();
+    }
+
+    #[test]
+    fn host_tuning_restore_reports_all_failed_settings() {
+        expect!(
+            TEST_EXPECTATIONS::std_fs_read_to_string:
+            { "/proc/sys/kernel/timer_migration" => Ok("1\n".to_string()) },
+        );
+        expect!(
+            TEST_EXPECTATIONS::std_fs_write:
+            { "/proc/sys/kernel/timer_migration", "0" => Ok({}) },
+            { "/proc/sys/kernel/timer_migration", "1" => error!("std::fs::write()") },
+        );
+
+        let mut tuning = HostTuning::new();
+        tuning.disable_timer_migration().unwrap();
+
+        assert_error!(
+            ErrorKind::Other,
+            "Failed to restore host tuning settings: \
+            `/proc/sys/kernel/timer_migration` (std::fs::write()).",
+            tuning.restore()
+        );
+
+        verify_expectations();
+    }
+}
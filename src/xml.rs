@@ -0,0 +1,303 @@
+use std::io::{Error, ErrorKind};
+
+pub struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    pub fn parse<S: AsRef<str>>(document: S) -> Result<Element, Error> {
+        let mut parser = Parser {
+            input: document.as_ref().as_bytes(),
+            position: 0,
+        };
+
+        parser.skip_prolog()?;
+        let root = parser.parse_element()?;
+        parser.skip_misc()?;
+
+        if parser.position < parser.input.len() {
+            return Err(parser.error("unexpected content after the root element"));
+        }
+
+        Ok(root)
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_text(&self) -> &str {
+        self.text.trim()
+    }
+
+    pub fn get_child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    pub fn get_children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> Error {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Malformed XML at byte {}: {}.", self.position, message),
+        )
+    }
+
+    fn starts_with(&self, prefix: &str) -> bool {
+        self.input[self.position..].starts_with(prefix.as_bytes())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.position < self.input.len() && self.input[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+    }
+
+    fn skip_until(&mut self, terminator: &str) -> Result<(), Error> {
+        while self.position < self.input.len() {
+            if self.starts_with(terminator) {
+                self.position += terminator.len();
+                return Ok({});
+            }
+
+            self.position += 1;
+        }
+
+        Err(self.error(&format!("missing `{}`", terminator)))
+    }
+
+    fn skip_misc(&mut self) -> Result<(), Error> {
+        loop {
+            self.skip_whitespace();
+
+            if self.starts_with("<!--") {
+                self.skip_until("-->")?;
+            } else if self.starts_with("<?") {
+                self.skip_until("?>")?;
+            } else {
+                return Ok({});
+            }
+        }
+    }
+
+    fn skip_prolog(&mut self) -> Result<(), Error> {
+        self.skip_misc()?;
+
+        if self.starts_with("<!DOCTYPE") {
+            self.skip_until(">")?;
+            self.skip_misc()?;
+        }
+
+        Ok({})
+    }
+
+    fn parse_name(&mut self) -> Result<String, Error> {
+        let start = self.position;
+
+        while self.position < self.input.len() {
+            match self.input[self.position] {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' | b'.' | b':' => {
+                    self.position += 1
+                }
+                _ => break,
+            }
+        }
+
+        if start == self.position {
+            return Err(self.error("a name expected"));
+        }
+
+        Ok(String::from_utf8_lossy(&self.input[start..self.position]).to_string())
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), Error> {
+        if !self.starts_with(token) {
+            return Err(self.error(&format!("`{}` expected", token)));
+        }
+
+        self.position += token.len();
+
+        Ok({})
+    }
+
+    fn parse_element(&mut self) -> Result<Element, Error> {
+        self.expect("<")?;
+        let name = self.parse_name()?;
+        let mut attributes = vec![];
+
+        loop {
+            self.skip_whitespace();
+
+            if self.starts_with("/>") {
+                self.position += 2;
+
+                return Ok(Element {
+                    name,
+                    attributes,
+                    children: vec![],
+                    text: String::new(),
+                });
+            }
+
+            if self.starts_with(">") {
+                self.position += 1;
+                break;
+            }
+
+            let attribute = self.parse_name()?;
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            attributes.push((attribute, self.parse_quoted()?));
+        }
+
+        let mut children = vec![];
+        let mut text = String::new();
+
+        loop {
+            if self.position >= self.input.len() {
+                return Err(self.error(&format!("unterminated `{}` element", name)));
+            }
+
+            if self.starts_with("</") {
+                self.position += 2;
+                let closing = self.parse_name()?;
+                if closing != name {
+                    return Err(self.error(&format!(
+                        "`</{}>` found while `</{}>` expected",
+                        closing, name
+                    )));
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+
+                return Ok(Element {
+                    name,
+                    attributes,
+                    children,
+                    text,
+                });
+            }
+
+            if self.starts_with("<!--") {
+                self.skip_until("-->")?;
+            } else if self.starts_with("<![CDATA[") {
+                self.position += 9;
+                let start = self.position;
+                self.skip_until("]]>")?;
+                text.push_str(&String::from_utf8_lossy(
+                    &self.input[start..self.position - 3],
+                ));
+            } else if self.starts_with("<") {
+                children.push(self.parse_element()?);
+            } else {
+                let start = self.position;
+                while self.position < self.input.len() && self.input[self.position] != b'<' {
+                    self.position += 1;
+                }
+                text.push_str(&unescape(&String::from_utf8_lossy(
+                    &self.input[start..self.position],
+                )));
+            }
+        }
+    }
+
+    fn parse_quoted(&mut self) -> Result<String, Error> {
+        let quote = match self.input.get(self.position) {
+            Some(b'"') => "\"",
+            Some(b'\'') => "'",
+            _ => return Err(self.error("a quoted attribute value expected")),
+        };
+
+        self.position += 1;
+        let start = self.position;
+        self.skip_until(quote)?;
+
+        Ok(unescape(&String::from_utf8_lossy(
+            &self.input[start..self.position - 1],
+        )))
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod test {
+    use super::Element;
+    use crate::assert_error;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn element_parse_handles_attributes_children_and_text() {
+        let root = Element::parse(
+            "<?xml version='1.0'?>
+            <!-- a comment -->
+            <domain type='kvm'>
+              <name>my &amp; vm</name>
+              <vcpu placement=\"static\">4</vcpu>
+              <devices><disk device='disk'/><disk device='cdrom'/></devices>
+            </domain>",
+        )
+        .unwrap();
+
+        assert_eq!("domain", root.get_name());
+        assert_eq!(Some("kvm"), root.get_attribute("type"));
+        assert_eq!(None, root.get_attribute("id"));
+        assert_eq!("my & vm", root.get_child("name").unwrap().get_text());
+        assert_eq!(
+            Some("static"),
+            root.get_child("vcpu").unwrap().get_attribute("placement")
+        );
+        assert_eq!(
+            vec![Some("disk"), Some("cdrom")],
+            root.get_child("devices")
+                .unwrap()
+                .get_children("disk")
+                .map(|disk| disk.get_attribute("device"))
+                .collect::<Vec<Option<&str>>>()
+        );
+    }
+
+    #[test]
+    fn element_parse_returns_error_on_mismatched_closing_tag() {
+        assert_error!(
+            ErrorKind::InvalidData,
+            "Malformed XML at byte 14: `</b>` found while `</a>` expected.",
+            Element::parse("<domain><a></b></domain>")
+        );
+    }
+
+    #[test]
+    fn element_parse_returns_error_on_unterminated_element() {
+        assert_error!(
+            ErrorKind::InvalidData,
+            "Malformed XML at byte 14: unterminated `domain` element.",
+            Element::parse("<domain>text  ")
+        );
+    }
+}